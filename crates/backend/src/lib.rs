@@ -85,7 +85,7 @@ pub use crate::rest::RestBackend;
 // rustic_backend Public API
 pub use crate::{
     choose::{BackendOptions, SupportedBackend},
-    local::LocalBackend,
+    local::{LayoutIssue, LocalBackend},
 };
 
 // re-export for error handling
@@ -8,6 +8,7 @@ use std::{
 
 use aho_corasick::AhoCorasick;
 use bytes::Bytes;
+use jiff::Timestamp;
 use log::{debug, error, trace, warn};
 use walkdir::WalkDir;
 
@@ -16,6 +17,28 @@ use rustic_core::{
     WriteBackend,
 };
 
+/// A difference between a local repository and the expected repository layout.
+///
+/// Such differences may occur for repositories which were created or copied by other tools,
+/// see [`LocalBackend::check_layout`].
+#[derive(Clone, Debug, PartialEq, Eq, displaydoc::Display)]
+#[non_exhaustive]
+pub enum LayoutIssue {
+    /// the repository is nested within the sub-directory {0:?}
+    NestedRepository(PathBuf),
+    /// the directory {0:?} is missing
+    MissingDirectory(PathBuf),
+    /// the {tpe} file {from:?} should be located at {to:?}
+    MisplacedFile {
+        /// The type of the file
+        tpe: FileType,
+        /// The current location of the file
+        from: PathBuf,
+        /// The expected location of the file
+        to: PathBuf,
+    },
+}
+
 /// A local backend.
 #[derive(Clone, Debug)]
 pub struct LocalBackend {
@@ -113,6 +136,185 @@ impl LocalBackend {
         self.base_path(tpe, id).join(Self::filename(tpe, id))
     }
 
+    /// Check the repository for differences to the expected layout.
+    ///
+    /// This detects
+    /// * a repository which is nested within a single sub-directory,
+    /// * missing directories, e.g. the fan-out directories within `data/`,
+    /// * files which are not located at their expected path, e.g. because of uppercase ids,
+    ///   missing fan-out directories or extra directory nesting.
+    ///
+    /// If a nested repository is found, no further checks are done. Use [`LocalBackend::fix_layout`]
+    /// to fix all issues.
+    ///
+    /// # Errors
+    ///
+    /// * If the repository directory could not be read.
+    ///
+    /// # Returns
+    ///
+    /// The found layout issues.
+    pub fn check_layout(&self) -> RusticResult<Vec<LayoutIssue>> {
+        let mut issues = Vec::new();
+
+        if !self.path.join("config").exists()
+            && let Some(nested) = self.nested_repository()?
+        {
+            issues.push(LayoutIssue::NestedRepository(nested));
+            return Ok(issues);
+        }
+
+        let dirs = ALL_FILE_TYPES
+            .into_iter()
+            .filter(|tpe| *tpe != FileType::Config)
+            .map(|tpe| self.path.join(tpe.dirname()))
+            .chain((0u8..=255).map(|i| self.path.join("data").join(hex::encode([i]))));
+        issues.extend(
+            dirs.filter(|dir| !dir.is_dir())
+                .map(LayoutIssue::MissingDirectory),
+        );
+
+        for tpe in ALL_FILE_TYPES {
+            if tpe == FileType::Config {
+                continue;
+            }
+            for entry in WalkDir::new(self.path.join(tpe.dirname()))
+                .into_iter()
+                .filter_map(|r| {
+                    r.inspect_err(|err| error!("error listing {tpe}: {err}"))
+                        .ok()
+                })
+                .filter(|entry| entry.file_type().is_file())
+            {
+                let name = entry.file_name().to_string_lossy();
+                let Some(id) = Id::parse_some(&name, tpe) else {
+                    continue;
+                };
+                let expected = self.path(tpe, &id);
+                if entry.path() != expected {
+                    issues.push(LayoutIssue::MisplacedFile {
+                        tpe,
+                        from: entry.into_path(),
+                        to: expected,
+                    });
+                }
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Fix the layout of the repository by moving files and creating missing directories.
+    ///
+    /// Files are only moved if no file exists at the expected location.
+    ///
+    /// # Errors
+    ///
+    /// * If the repository directory could not be read.
+    /// * If a file or directory could not be moved or created.
+    ///
+    /// # Returns
+    ///
+    /// The fixed layout issues.
+    pub fn fix_layout(&self) -> RusticResult<Vec<LayoutIssue>> {
+        let mut fixed = Vec::new();
+        let mut issues = self.check_layout()?;
+        if let [LayoutIssue::NestedRepository(nested)] = issues.as_slice() {
+            self.unnest_repository(nested)?;
+            fixed.append(&mut issues);
+            issues = self.check_layout()?;
+        }
+
+        for issue in issues {
+            match &issue {
+                LayoutIssue::NestedRepository(nested) => self.unnest_repository(nested)?,
+                LayoutIssue::MissingDirectory(dir) => create_dir(dir)?,
+                LayoutIssue::MisplacedFile { from, to, .. } => {
+                    if to.exists() {
+                        warn!(
+                            "not moving {} as {} already exists.",
+                            from.display(),
+                            to.display()
+                        );
+                        continue;
+                    }
+                    if let Some(parent) = to.parent() {
+                        create_dir(parent)?;
+                    }
+                    rename(from, to)?;
+                }
+            }
+            fixed.push(issue);
+        }
+        Ok(fixed)
+    }
+
+    /// Find a repository within a single sub-directory of the backend path.
+    ///
+    /// # Errors
+    ///
+    /// * If the repository directory could not be read.
+    fn nested_repository(&self) -> RusticResult<Option<PathBuf>> {
+        let entries = fs::read_dir(&self.path).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InputOutput,
+                "Failed to read the directory `{path}`. Please check the path and try again.",
+                err,
+            )
+            .attach_context("path", self.path.display().to_string())
+        })?;
+        let dirs: Vec<_> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect();
+        Ok(match dirs.as_slice() {
+            [dir] if dir.join("config").is_file() => Some(dir.clone()),
+            _ => None,
+        })
+    }
+
+    /// Move all entries of the nested repository in `nested` to the backend path.
+    ///
+    /// # Errors
+    ///
+    /// * If the nested directory could not be read.
+    /// * If an entry already exists in the backend path.
+    /// * If an entry could not be moved.
+    fn unnest_repository(&self, nested: &Path) -> RusticResult<()> {
+        // move the nested dir first to avoid conflicts with entries having the same name
+        let tmp = self.path.join(".rustic-relayout-tmp");
+        rename(nested, &tmp)?;
+        let entries = fs::read_dir(&tmp).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InputOutput,
+                "Failed to read the directory `{path}`. Please check the path and try again.",
+                err,
+            )
+            .attach_context("path", tmp.display().to_string())
+        })?;
+        for entry in entries.filter_map(Result::ok) {
+            let to = self.path.join(entry.file_name());
+            if to.exists() {
+                return Err(RusticError::new(
+                    ErrorKind::InputOutput,
+                    "Cannot move `{from}` to `{path}` as it already exists. Please move the repository manually.",
+                )
+                .attach_context("from", entry.path().display().to_string())
+                .attach_context("path", to.display().to_string()));
+            }
+            rename(&entry.path(), &to)?;
+        }
+        fs::remove_dir(&tmp).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InputOutput,
+                "Failed to remove the directory `{path}`. Please check the path and try again.",
+                err,
+            )
+            .attach_context("path", tmp.display().to_string())
+        })
+    }
+
     /// Call the given command.
     ///
     /// # Arguments
@@ -193,6 +395,39 @@ impl LocalBackend {
     }
 }
 
+/// Create the given directory including all parent directories.
+///
+/// # Errors
+///
+/// * If the directory could not be created.
+fn create_dir(path: &Path) -> RusticResult<()> {
+    fs::create_dir_all(path).map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::InputOutput,
+            "Failed to create the directory `{path}`. Please check the path and try again.",
+            err,
+        )
+        .attach_context("path", path.display().to_string())
+    })
+}
+
+/// Move `from` to `to`.
+///
+/// # Errors
+///
+/// * If `from` could not be moved.
+fn rename(from: &Path, to: &Path) -> RusticResult<()> {
+    fs::rename(from, to).map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::InputOutput,
+            "Failed to move `{from}` to `{path}`. Please check the path and try again.",
+            err,
+        )
+        .attach_context("from", from.display().to_string())
+        .attach_context("path", to.display().to_string())
+    })
+}
+
 impl ReadBackend for LocalBackend {
     /// Returns the location of the backend.
     ///
@@ -300,6 +535,31 @@ impl ReadBackend for LocalBackend {
         Ok(walker.collect())
     }
 
+    /// Returns the modification time of the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the metadata of the file could not be queried.
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        let path = self.path(tpe, id);
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::Backend,
+                    "Failed to query the modification time of file `{path}`.",
+                    err,
+                )
+                .attach_context("path", path.to_string_lossy())
+            })?;
+        Ok(Timestamp::try_from(modified).ok())
+    }
+
     /// Reads full data of the given file.
     ///
     /// # Arguments
@@ -400,6 +660,11 @@ impl ReadBackend for LocalBackend {
         // though warmup is not typically needed for local storage
         self.path(tpe, id).to_string_lossy().to_string()
     }
+
+    fn supports_file_type(&self, _tpe: FileType) -> bool {
+        // every file type is stored in its own directory
+        true
+    }
 }
 
 impl WriteBackend for LocalBackend {
@@ -603,3 +868,60 @@ impl WriteBackend for LocalBackend {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::tempdir;
+
+    #[test]
+    fn fix_layout_moves_misplaced_files() -> RusticResult<()> {
+        let dir = tempdir().unwrap();
+        let be = LocalBackend::new(dir.path().to_str().unwrap(), None)?;
+        be.create()?;
+        assert!(be.check_layout()?.is_empty());
+
+        let key = Id::random();
+        let pack = Id::random();
+        // uppercase id, pack file without fan-out dir and missing fan-out dir
+        fs::write(
+            be.path.join("keys").join(key.to_hex().to_uppercase()),
+            "key",
+        )
+        .unwrap();
+        fs::write(be.path.join("data").join(pack.to_hex().as_str()), "pack").unwrap();
+        fs::remove_dir(be.base_path(FileType::Pack, &pack)).unwrap();
+
+        let issues = be.check_layout()?;
+        assert_eq!(issues.len(), 3);
+        assert!(issues.contains(&LayoutIssue::MissingDirectory(
+            be.base_path(FileType::Pack, &pack)
+        )));
+
+        assert_eq!(be.fix_layout()?, issues);
+        assert!(be.check_layout()?.is_empty());
+        assert_eq!(be.read_full(FileType::Key, &key)?, "key");
+        assert_eq!(be.read_full(FileType::Pack, &pack)?, "pack");
+        Ok(())
+    }
+
+    #[test]
+    fn fix_layout_unnests_repository() -> RusticResult<()> {
+        let dir = tempdir().unwrap();
+        let nested = LocalBackend::new(dir.path().join("repo").to_str().unwrap(), None)?;
+        nested.create()?;
+        fs::write(nested.path.join("config"), "config").unwrap();
+
+        let be = LocalBackend::new(dir.path().to_str().unwrap(), None)?;
+        assert_eq!(
+            be.check_layout()?,
+            vec![LayoutIssue::NestedRepository(dir.path().join("repo"))]
+        );
+        _ = be.fix_layout()?;
+        assert!(be.check_layout()?.is_empty());
+        assert_eq!(be.read_full(FileType::Config, &Id::default())?, "config");
+        assert!(!dir.path().join("repo").exists());
+        Ok(())
+    }
+}
@@ -9,6 +9,7 @@ use std::{
 
 use bytes::Bytes;
 use bytesize::ByteSize;
+use jiff::Timestamp;
 use log::{error, trace, warn};
 use opendal::{
     Entry, Metadata,
@@ -26,6 +27,10 @@ use rustic_core::{
     repofile::{Node, NodeType},
 };
 
+use object_lock::{ObjectLockMode, S3ObjectLock};
+
+mod object_lock;
+
 mod constants {
     /// Default number of retries
     pub(super) const DEFAULT_RETRY: usize = 5;
@@ -35,6 +40,8 @@ mod constants {
 #[derive(Clone, Debug)]
 pub struct OpenDALBackend {
     operator: Operator,
+    /// Client to write locked files, if S3 object locks are used
+    object_lock: Option<S3ObjectLock>,
 }
 
 fn runtime() -> &'static Runtime {
@@ -112,7 +119,7 @@ impl OpenDALBackend {
     /// # Returns
     ///
     /// A new `OpenDAL` backend.
-    pub fn new(path: impl AsRef<str>, options: BTreeMap<String, String>) -> RusticResult<Self> {
+    pub fn new(path: impl AsRef<str>, mut options: BTreeMap<String, String>) -> RusticResult<Self> {
         let max_retries = match options.get("retry").map(String::as_str) {
             Some("false" | "off") => 0,
             None | Some("default") => constants::DEFAULT_RETRY,
@@ -150,6 +157,29 @@ impl OpenDALBackend {
             .next()
             .unwrap_or_else(|| path.as_ref());
 
+        // `object-lock` is no option of the `OpenDAL` services, so don't pass it to the operator
+        let object_lock = match options.remove("object-lock").as_deref() {
+            None | Some("false" | "off") => None,
+            Some(value) => {
+                if scheme != "s3" {
+                    return Err(RusticError::new(
+                        ErrorKind::Unsupported,
+                        "Object locks are only supported for the `s3` service, not for `{schema}`.",
+                    )
+                    .attach_context("schema", scheme.to_string()));
+                }
+                let mode = ObjectLockMode::from_str(value).map_err(|err| {
+                    RusticError::with_source(
+                        ErrorKind::InvalidInput,
+                        "Parsing object-lock value `{value}` failed, the value must be `compliance` or `governance`.",
+                        err,
+                    )
+                    .attach_context("value", value.to_string())
+                })?;
+                Some(S3ObjectLock::new(mode, &options)?)
+            }
+        };
+
         let mut operator = opendal::Operator::via_iter(scheme, options)
             .map_err(|err| {
                 RusticError::with_source(
@@ -180,7 +210,10 @@ impl OpenDALBackend {
             .attach_context("path", path.as_ref().to_string())
         })?;
 
-        Ok(Self { operator })
+        Ok(Self {
+            operator,
+            object_lock,
+        })
     }
 
     /// Return a path for the given file type and id.
@@ -369,6 +402,23 @@ impl ReadBackend for OpenDALBackend {
         Ok(entries)
     }
 
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        let path = self.path(tpe, id);
+        let metadata = self.operator.stat(&path).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::Backend,
+                "Getting Metadata of file `{path}` failed in the backend.",
+                err,
+            )
+            .attach_context("path", path)
+            .attach_context("type", tpe.to_string())
+            .attach_context("id", id.to_string())
+        })?;
+        Ok(metadata
+            .last_modified()
+            .map(opendal::raw::Timestamp::into_inner))
+    }
+
     fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
         trace!("reading tpe: {tpe:?}, id: {id}");
 
@@ -439,6 +489,11 @@ impl ReadBackend for OpenDALBackend {
             format!("{root}/{relative_path}")
         }
     }
+
+    fn supports_file_type(&self, _tpe: FileType) -> bool {
+        // every file type is stored in its own directory
+        true
+    }
 }
 
 impl WriteBackend for OpenDALBackend {
@@ -538,6 +593,57 @@ impl WriteBackend for OpenDALBackend {
         })?;
         Ok(())
     }
+
+    /// Object locks are supported for the `s3` service if the `object-lock` option is given.
+    fn supports_object_lock(&self) -> bool {
+        self.object_lock.is_some()
+    }
+
+    /// Write the given bytes to the given file and lock it until the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    /// * `buf` - The bytes to write.
+    /// * `until` - The end of the retention period.
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        _cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        trace!(
+            "writing tpe: {:?}, id: {}, locked until: {until}",
+            &tpe, &id
+        );
+        let Some(object_lock) = &self.object_lock else {
+            return Err(RusticError::new(
+                ErrorKind::Unsupported,
+                "Backend `{location}` does not support locking file `{id}`. Please set the `object-lock` option.",
+            )
+            .attach_context("location", self.location())
+            .attach_context("id", id.to_string()));
+        };
+        object_lock
+            .put_locked(&self.path(tpe, id), buf, until)
+            .map_err(|err| err.attach_context("type", tpe.to_string()))
+    }
+
+    /// Returns the end of the retention period of the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.object_lock.as_ref().map_or(Ok(None), |object_lock| {
+            object_lock.retain_until(&self.path(tpe, id))
+        })
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,458 @@
+//! S3 object lock support for the `OpenDAL` backend.
+//!
+//! `OpenDAL` does not allow to set the object lock headers of a `PutObject` request, so locked files
+//! are uploaded using a request to the S3 API which is signed by `reqsign`, the signer also used by
+//! `OpenDAL`. This ensures that the retention period is set atomically with the upload.
+use std::{collections::BTreeMap, env, fmt::Write as _, sync::Arc};
+
+use bytes::Bytes;
+use jiff::Timestamp;
+use log::trace;
+use reqsign_aws_v4::{
+    Credential, DefaultCredentialProvider, RequestSigner, StaticCredentialProvider,
+};
+use reqsign_core::{
+    Context, HttpSend, OsEnv, Signer,
+    hash::{base64_encode, hex_sha256, sha256},
+};
+use reqsign_file_read_tokio::TokioFileRead;
+use reqwest::{StatusCode, Url, blocking::Client};
+use serde::Deserialize;
+use strum::{Display, EnumString};
+
+use rustic_core::{ErrorKind, RusticError, RusticResult};
+
+use super::runtime;
+
+/// The object lock mode used for newly written locked files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "UPPERCASE", ascii_case_insensitive)]
+pub(super) enum ObjectLockMode {
+    /// Nobody, including the root user of the account, can remove locked files.
+    Compliance,
+    /// Users with special permissions can remove locked files.
+    Governance,
+}
+
+/// Sends the requests of the credential providers, e.g. to the instance metadata service.
+#[derive(Debug, Clone, Default)]
+struct ReqwestHttpSend(reqwest::Client);
+
+impl HttpSend for ReqwestHttpSend {
+    async fn http_send(
+        &self,
+        req: http::Request<Bytes>,
+    ) -> reqsign_core::Result<http::Response<Bytes>> {
+        let error = |err: reqwest::Error| {
+            reqsign_core::Error::unexpected("sending credential request failed").with_source(err)
+        };
+        let req = reqwest::Request::try_from(req).map_err(error)?;
+        let resp = self.0.execute(req).await.map_err(error)?;
+        let mut builder = http::Response::builder().status(resp.status());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = resp.headers().clone();
+        }
+        let body = resp.bytes().await.map_err(error)?;
+        builder.body(body).map_err(|err| {
+            reqsign_core::Error::unexpected("building credential response failed").with_source(err)
+        })
+    }
+}
+
+/// The `Retention` element of a `GetObjectRetention` response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Retention {
+    retain_until_date: Option<String>,
+}
+
+/// The `Error` element of an S3 error response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct S3Error {
+    code: String,
+}
+
+/// Client for the S3 object lock API of the bucket used by the `OpenDAL` backend.
+#[derive(Clone)]
+pub(super) struct S3ObjectLock {
+    client: Client,
+    signer: Arc<Signer<Credential>>,
+    /// The URL of the bucket, ending with `/`
+    bucket_url: Url,
+    /// The root within the bucket, without leading and trailing `/`
+    root: String,
+    mode: ObjectLockMode,
+}
+
+impl std::fmt::Debug for S3ObjectLock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3ObjectLock")
+            .field("bucket_url", &self.bucket_url)
+            .field("root", &self.root)
+            .field("mode", &self.mode)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Get an option given for the `OpenDAL` S3 service or fall back to the given environment variable.
+fn option_or_env(options: &BTreeMap<String, String>, key: &str, var: &str) -> Option<String> {
+    options
+        .get(key)
+        .cloned()
+        .or_else(|| env::var(var).ok())
+        .filter(|value| !value.is_empty())
+}
+
+fn missing_option(key: &str) -> Box<RusticError> {
+    RusticError::new(
+        ErrorKind::MissingInput,
+        "Option `{option}` is needed to use S3 object locks.",
+    )
+    .attach_context("option", key)
+}
+
+impl S3ObjectLock {
+    /// Create a new object lock client from the options of the `OpenDAL` S3 service.
+    ///
+    /// If no `access_key_id` and `secret_access_key` are given, the credentials are loaded like the
+    /// AWS SDKs do, i.e. from the environment, the AWS config files, web identity tokens or the
+    /// instance metadata service.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The object lock mode to use
+    /// * `options` - The options of the `OpenDAL` S3 service
+    ///
+    /// # Errors
+    ///
+    /// * If the bucket or region are not given.
+    /// * If the endpoint is not a valid URL.
+    pub(super) fn new(
+        mode: ObjectLockMode,
+        options: &BTreeMap<String, String>,
+    ) -> RusticResult<Self> {
+        let bucket = options
+            .get("bucket")
+            .ok_or_else(|| missing_option("bucket"))?;
+        let region = option_or_env(options, "region", "AWS_REGION")
+            .ok_or_else(|| missing_option("region"))?;
+        let endpoint = options
+            .get("endpoint")
+            .cloned()
+            .unwrap_or_else(|| format!("https://s3.{region}.amazonaws.com"));
+        let virtual_host_style = options
+            .get("enable_virtual_host_style")
+            .is_some_and(|value| value == "true");
+
+        let mut bucket_url = Url::parse(&endpoint).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InvalidInput,
+                "Parsing endpoint `{endpoint}` failed.",
+                err,
+            )
+            .attach_context("endpoint", endpoint.clone())
+        })?;
+        if virtual_host_style {
+            let host = format!("{bucket}.{}", bucket_url.host_str().unwrap_or_default());
+            bucket_url.set_host(Some(&host)).map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::InvalidInput,
+                    "Setting host `{host}` of endpoint `{endpoint}` failed.",
+                    err,
+                )
+                .attach_context("host", host)
+                .attach_context("endpoint", endpoint.clone())
+            })?;
+        } else {
+            _ = bucket_url
+                .path_segments_mut()
+                .map_err(|()| {
+                    RusticError::new(
+                        ErrorKind::InvalidInput,
+                        "Endpoint `{endpoint}` cannot be used as base URL.",
+                    )
+                    .attach_context("endpoint", endpoint.clone())
+                })?
+                .pop_if_empty()
+                .push(bucket)
+                .push("");
+        }
+
+        let root = options
+            .get("root")
+            .map(|root| root.trim_matches('/').to_string())
+            .unwrap_or_default();
+
+        let ctx = Context::new()
+            .with_file_read(TokioFileRead)
+            .with_http_send(ReqwestHttpSend::default())
+            .with_env(OsEnv);
+        let request_signer = RequestSigner::new("s3", &region);
+        let signer = match (
+            options.get("access_key_id"),
+            options.get("secret_access_key"),
+        ) {
+            (Some(access_key_id), Some(secret_access_key)) => {
+                let mut provider = StaticCredentialProvider::new(access_key_id, secret_access_key);
+                if let Some(token) = options.get("session_token") {
+                    provider = provider.with_session_token(token);
+                }
+                Signer::new(ctx, provider, request_signer)
+            }
+            _ => Signer::new(ctx, DefaultCredentialProvider::new(), request_signer),
+        };
+
+        Ok(Self {
+            client: Client::new(),
+            signer: Arc::new(signer),
+            bucket_url,
+            root,
+            mode,
+        })
+    }
+
+    /// The URL of the given path relative to the root
+    fn url(&self, path: &str) -> Url {
+        let key = if self.root.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{path}", self.root)
+        };
+        let mut url = self.bucket_url.clone();
+        url.set_path(&format!("{}{}", url.path(), uri_encode(&key)));
+        url
+    }
+
+    /// Create a request to the given URL with the given headers and sign it.
+    fn signed_request(
+        &self,
+        method: http::Method,
+        url: &Url,
+        headers: &[(&'static str, String)],
+    ) -> RusticResult<http::request::Parts> {
+        let mut builder = http::Request::builder().method(method).uri(url.as_str());
+        for (name, value) in headers {
+            builder = builder.header(*name, value);
+        }
+        let (mut parts, ()) = builder
+            .body(())
+            .map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::Internal,
+                    "Creating request to `{url}` failed.",
+                    err,
+                )
+                .attach_context("url", url.to_string())
+            })?
+            .into_parts();
+        runtime()
+            .block_on(self.signer.sign(&mut parts, None))
+            .map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::Backend,
+                    "Signing request to `{url}` failed. Please check the S3 credentials.",
+                    err,
+                )
+                .attach_context("url", url.to_string())
+            })?;
+        Ok(parts)
+    }
+
+    /// Upload the given data and lock it until the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file relative to the root
+    /// * `buf` - The data to write
+    /// * `until` - The end of the retention period
+    ///
+    /// # Errors
+    ///
+    /// * If the upload failed.
+    pub(super) fn put_locked(&self, path: &str, buf: Bytes, until: Timestamp) -> RusticResult<()> {
+        trace!("writing {path} locked until {until}");
+        let url = self.url(path);
+        let headers = [
+            ("x-amz-object-lock-mode", self.mode.to_string()),
+            (
+                "x-amz-object-lock-retain-until-date",
+                until.strftime("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            ),
+            ("x-amz-content-sha256", hex_sha256(&buf)),
+            ("x-amz-sdk-checksum-algorithm", "SHA256".to_string()),
+            ("x-amz-checksum-sha256", base64_encode(&sha256(&buf))),
+        ];
+        let parts = self.signed_request(http::Method::PUT, &url, &headers)?;
+        let response = self
+            .client
+            .put(url)
+            .headers(parts.headers)
+            .body(buf)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::Backend,
+                    "Writing locked file `{path}` failed in the backend.",
+                    err,
+                )
+                .attach_context("path", path)
+                .attach_context("until", until.to_string())
+            })?;
+        trace!("locked upload returned {}", response.status());
+        Ok(())
+    }
+
+    /// Get the end of the retention period of the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file relative to the root
+    ///
+    /// # Errors
+    ///
+    /// * If the retention could not be requested or parsed.
+    pub(super) fn retain_until(&self, path: &str) -> RusticResult<Option<Timestamp>> {
+        let mut url = self.url(path);
+        url.set_query(Some("retention="));
+        let headers = [("x-amz-content-sha256", hex_sha256(b""))];
+        let parts = self.signed_request(http::Method::GET, &url, &headers)?;
+        let error = |err: reqwest::Error| {
+            RusticError::with_source(
+                ErrorKind::Backend,
+                "Getting the retention of file `{path}` failed in the backend.",
+                err,
+            )
+            .attach_context("path", path)
+        };
+        let response = self
+            .client
+            .get(url)
+            .headers(parts.headers)
+            .send()
+            .map_err(error)?;
+        let status = response.status();
+        let body = response.text().map_err(error)?;
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            if quick_xml::de::from_str::<S3Error>(&body)
+                .is_ok_and(|err| err.code == "NoSuchObjectLockConfiguration")
+            {
+                return Ok(None);
+            }
+            return Err(RusticError::new(
+                ErrorKind::Backend,
+                "Getting the retention of file `{path}` failed with status `{status}`: {body}",
+            )
+            .attach_context("path", path)
+            .attach_context("status", status.to_string())
+            .attach_context("body", body));
+        }
+        parse_retain_until(&body)
+    }
+}
+
+/// Parse the `RetainUntilDate` of a `GetObjectRetention` response.
+fn parse_retain_until(body: &str) -> RusticResult<Option<Timestamp>> {
+    let retention: Retention = quick_xml::de::from_str(body).map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::Backend,
+            "Parsing the retention response failed.",
+            err,
+        )
+        .attach_context("body", body)
+    })?;
+    retention
+        .retain_until_date
+        .map(|date| {
+            date.trim().parse().map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::Backend,
+                    "Parsing retain until date `{date}` failed.",
+                    err,
+                )
+                .attach_context("date", date.clone())
+            })
+        })
+        .transpose()
+}
+
+/// URI-encode the given object key, keeping the `/` separators.
+fn uri_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(char::from(byte));
+            }
+            _ => {
+                _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+
+    #[test]
+    fn test_url() -> Result<()> {
+        let options = BTreeMap::from([
+            ("bucket".to_string(), "bucket_name".to_string()),
+            ("region".to_string(), "eu-west-1".to_string()),
+            ("access_key_id".to_string(), "xxx".to_string()),
+            ("secret_access_key".to_string(), "xxx".to_string()),
+            ("root".to_string(), "/path/to repo/".to_string()),
+        ]);
+        let lock = S3ObjectLock::new(ObjectLockMode::Compliance, &options)?;
+        assert_eq!(
+            lock.url("data/03/03dc").as_str(),
+            "https://s3.eu-west-1.amazonaws.com/bucket_name/path/to%20repo/data/03/03dc"
+        );
+
+        let mut options = options;
+        _ = options.insert("enable_virtual_host_style".to_string(), "true".to_string());
+        _ = options.insert("endpoint".to_string(), "http://localhost:9000".to_string());
+        let lock = S3ObjectLock::new(ObjectLockMode::Governance, &options)?;
+        assert_eq!(
+            lock.url("config").as_str(),
+            "http://bucket_name.localhost:9000/path/to%20repo/config"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_retain_until() -> Result<()> {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Retention xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><Mode>COMPLIANCE</Mode><RetainUntilDate>2030-01-05T00:00:00.000Z</RetainUntilDate></Retention>"#;
+        assert_eq!(
+            parse_retain_until(body)?,
+            Some("2030-01-05T00:00:00Z".parse()?)
+        );
+        assert_eq!(parse_retain_until("<Retention></Retention>")?, None);
+        assert!(parse_retain_until("<Retention><RetainUntilDate>").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_s3_error() -> Result<()> {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Error><Code>NoSuchObjectLockConfiguration</Code><Message>The specified object does not have a ObjectLock configuration</Message></Error>"#;
+        let error: S3Error = quick_xml::de::from_str(body)?;
+        assert_eq!(error.code, "NoSuchObjectLockConfiguration");
+        Ok(())
+    }
+
+    #[test]
+    fn test_object_lock_mode() {
+        assert_eq!("compliance".parse(), Ok(ObjectLockMode::Compliance));
+        assert_eq!("GOVERNANCE".parse(), Ok(ObjectLockMode::Governance));
+        assert_eq!(ObjectLockMode::Compliance.to_string(), "COMPLIANCE");
+    }
+}
@@ -360,6 +360,10 @@ impl ReadBackend for RcloneBackend {
         // Delegate to the underlying REST backend
         self.rest.warmup_path(tpe, id)
     }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.rest.supports_file_type(tpe)
+    }
 }
 
 impl WriteBackend for RcloneBackend {
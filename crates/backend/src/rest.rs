@@ -407,6 +407,11 @@ impl ReadBackend for RestBackend {
             .unwrap()
             .to_string()
     }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        // the REST protocol only knows the restic file types
+        !tpe.is_extension()
+    }
 }
 
 fn construct_join_url_error(
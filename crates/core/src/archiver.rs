@@ -1,4 +1,5 @@
 pub(crate) mod file_archiver;
+pub(crate) mod incremental;
 pub(crate) mod parent;
 pub(crate) mod tree;
 pub(crate) mod tree_archiver;
@@ -7,7 +8,7 @@ use std::path::{Path, PathBuf};
 use std::thread::scope;
 
 use jiff::Zoned;
-use log::warn;
+use log::{info, warn};
 use pariter::IteratorExt;
 
 use crate::{
@@ -18,6 +19,7 @@ use crate::{
     },
     backend::{ReadSource, ReadSourceEntry, decrypt::DecryptFullBackend},
     blob::BlobType,
+    cancel::{CancellationToken, PauseHandle},
     error::RusticResult,
     index::{
         ReadGlobalIndex,
@@ -87,9 +89,11 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> Archiver<'a, BE, I> {
         let indexer = Indexer::new(be.clone()).into_shared();
         let mut summary = snap.summary.take().unwrap_or_default();
         summary.backup_start = Zoned::now();
+        let roots = std::mem::take(&mut snap.roots);
 
         let file_archiver = FileArchiver::new(be.clone(), index, indexer.clone(), config)?;
-        let tree_archiver = TreeArchiver::new(be.clone(), index, indexer.clone(), config, summary)?;
+        let tree_archiver =
+            TreeArchiver::new(be.clone(), index, indexer.clone(), config, summary, roots)?;
 
         Ok(Self {
             file_archiver,
@@ -117,10 +121,14 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> Archiver<'a, BE, I> {
     /// * `backup_path` - The path to the backup.
     /// * `as_path` - The path to archive the backup as.
     /// * `skip_identical_parent` - skip saving of snapshot if tree is identical to parent tree.
+    /// * `identical_candidates` - existing snapshots to return instead of saving a new one if their content digest is identical.
     /// * `p` - The progress bar.
+    /// * `cancel` - The token to cancel the backup.
+    /// * `pause` - The handle to pause the backup; it is paused before processing the next file.
     ///
     /// # Errors
     ///
+    /// * If the backup has been cancelled. All data which has been processed so far is saved and indexed.
     /// * If sending the message to the raw packer fails.
     /// * If the index file could not be serialized.
     /// * If the time is not in the range of `Local::now()`.
@@ -130,8 +138,11 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> Archiver<'a, BE, I> {
         backup_path: &Path,
         as_path: Option<&PathBuf>,
         skip_identical_parent: bool,
+        identical_candidates: &[SnapshotFile],
         no_scan: bool,
         p: &Progress,
+        cancel: &CancellationToken,
+        pause: &PauseHandle,
     ) -> RusticResult<SnapshotFile>
     where
         R: ReadSource + 'static,
@@ -150,8 +161,13 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> Archiver<'a, BE, I> {
                 }
             });
 
+            // stop reading entries if cancelled; the trees processed so far are still saved
+            let entries = src.entries().take_while(|_| {
+                pause.wait(cancel);
+                !cancel.is_cancelled()
+            });
             // filter out errors and handle as_path
-            let iter = src.entries().filter_map(|item| match item {
+            let iter = entries.filter_map(|item| match item {
                 Err(err) => {
                     warn!("ignoring error: {}", err.display_log());
                     None
@@ -192,7 +208,10 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> Archiver<'a, BE, I> {
                 },
             )
             // archive files in parallel
-            .parallel_map_scoped(s, |item| self.file_archiver.process(item, p))
+            .parallel_map_scoped(s, |item| {
+                pause.wait(cancel);
+                self.file_archiver.process(item, p)
+            })
             .readahead_scoped(s)
             .filter_map(|item| match item {
                 Ok(item) => Some(item),
@@ -211,15 +230,31 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> Archiver<'a, BE, I> {
         })?;
 
         let stats = self.file_archiver.finalize()?;
-        let (id, mut summary) = self.tree_archiver.finalize(self.parent.tree_id())?;
+        let (id, mut summary, roots) = self.tree_archiver.finalize(self.parent.tree_id())?;
         stats.apply(&mut summary, BlobType::Data);
         self.snap.tree = id;
+        self.snap.roots = roots;
 
         self.indexer.write().unwrap().finalize()?;
+        cancel.check("backup")?;
 
+        summary.change_detection = Some(self.parent.change_detection());
         summary.finalize(&self.snap.time);
         self.snap.summary = Some(summary);
 
+        let digest = self.snap.content_digest();
+        if let Some(existing) = identical_candidates
+            .iter()
+            .find(|sn| sn.content_digest() == digest)
+        {
+            info!(
+                "identical snapshot {} already exists, not saving a new snapshot.",
+                existing.id
+            );
+            p.finish();
+            return Ok(existing.clone());
+        }
+
         if !skip_identical_parent || Some(self.snap.tree) != self.parent.tree_id() {
             let id = self.be.save_file(&self.snap)?;
             self.snap.id = id.into();
@@ -0,0 +1,298 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+};
+
+use jiff::Zoned;
+use log::warn;
+use rayon::prelude::*;
+
+use crate::{
+    Progress,
+    archiver::{file_archiver::FileArchiver, parent::ParentResult, tree::TreeType},
+    backend::{
+        ReadSourceEntry,
+        decrypt::DecryptFullBackend,
+        ignore::OpenFile,
+        node::{Metadata, Node, NodeType},
+    },
+    blob::{
+        BlobType,
+        tree::{Tree, TreeId, comp_to_osstr, modify::TreeModifier},
+    },
+    error::{ErrorKind, RusticError, RusticResult},
+    index::ReadGlobalIndex,
+    repofile::{
+        configfile::ConfigFile,
+        snapshotfile::{SnapshotFile, SnapshotSummary},
+    },
+};
+
+/// A change to apply to the parent tree.
+#[derive(Debug, Clone)]
+pub(crate) enum TreeChange {
+    /// The node has been created or modified; files are already archived
+    Modified(Node),
+    /// The node and everything below has been removed
+    Removed,
+}
+
+/// Converts a path of the backup source into the path within the snapshot tree.
+///
+/// # Errors
+///
+/// * If the path contains `.` or `..` components.
+pub(crate) fn snapshot_path(path: &Path) -> RusticResult<PathBuf> {
+    let mut snapshot_path = PathBuf::new();
+    for comp in path.components() {
+        if let Some(comp) = comp_to_osstr(comp).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InvalidInput,
+                "Failed to convert changed path `{path}`. Please use absolute or normalized paths.",
+                err,
+            )
+            .attach_context("path", path.display().to_string())
+        })? {
+            snapshot_path.push(comp);
+        }
+    }
+    Ok(snapshot_path)
+}
+
+/// The `IncrementalArchiver` applies a list of changed paths to the tree of a parent snapshot.
+///
+/// Only the changed files are read and chunked. Trees which don't contain any change are reused
+/// from the parent without reading them; all other trees are read from the parent and saved with the
+/// changes applied.
+///
+/// # Type Parameters
+///
+/// * `BE` - The backend type.
+/// * `I` - The index to read from.
+#[allow(missing_debug_implementations)]
+pub(crate) struct IncrementalArchiver<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> {
+    /// The `FileArchiver` is responsible for archiving the changed files.
+    file_archiver: FileArchiver<'a, BE, I>,
+
+    /// The `TreeModifier` is used to save the modified trees.
+    modifier: TreeModifier<'a, BE, I>,
+
+    /// The backend to write to.
+    be: &'a BE,
+
+    /// The index to read from.
+    index: &'a I,
+
+    /// The summary of the changes.
+    summary: SnapshotSummary,
+}
+
+impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> IncrementalArchiver<'a, BE, I> {
+    /// Creates a new `IncrementalArchiver`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to write to.
+    /// * `index` - The index to read from.
+    /// * `config` - The config file.
+    ///
+    /// # Errors
+    ///
+    /// * If sending the message to the raw packer fails.
+    pub(crate) fn new(be: &'a BE, index: &'a I, config: &ConfigFile) -> RusticResult<Self> {
+        let modifier = TreeModifier::new(be, index, config, false)?;
+        let file_archiver =
+            FileArchiver::new(be.clone(), index, modifier.indexer().clone(), config)?;
+        let summary = SnapshotSummary {
+            backup_start: Zoned::now(),
+            ..Default::default()
+        };
+
+        Ok(Self {
+            file_archiver,
+            modifier,
+            be,
+            index,
+            summary,
+        })
+    }
+
+    /// Archives the given entries, i.e. reads and chunks the contents of files.
+    ///
+    /// Entries which fail to be archived are ignored with a warning and are kept unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The entries to archive.
+    /// * `p` - The progress bar.
+    ///
+    /// # Returns
+    ///
+    /// The archived nodes together with their path within the snapshot.
+    pub(crate) fn archive_entries(
+        &mut self,
+        entries: Vec<ReadSourceEntry<OpenFile>>,
+        p: &Progress,
+    ) -> Vec<(PathBuf, Node)> {
+        let items: Vec<_> = entries
+            .into_par_iter()
+            .filter_map(|ReadSourceEntry { path, node, open }| {
+                let item = TreeType::Other((path.clone(), node, (open, ParentResult::NotFound)));
+                match self.file_archiver.process(item, p) {
+                    Ok(TreeType::Other((_, node, (_, size)))) => Some((path, node, size)),
+                    Ok(_) => None,
+                    Err(err) => {
+                        warn!("ignoring error: {}", err.display_log());
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        items
+            .into_iter()
+            .filter_map(|(path, node, size)| {
+                if node.is_dir() {
+                    self.summary.total_dirs_processed += 1;
+                } else {
+                    self.summary.total_files_processed += 1;
+                    self.summary.total_bytes_processed += size;
+                }
+                match snapshot_path(&path) {
+                    Ok(path) => Some((path, node)),
+                    Err(err) => {
+                        warn!("ignoring error: {}", err.display_log());
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Applies the changes to the given tree and saves all modified trees.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the tree to modify, `None` for a new (empty) tree.
+    /// * `depth` - The depth of the tree; the paths of `changes` have to be below the tree.
+    /// * `changes` - The changes to apply, sorted by path. Paths are relative to the snapshot root.
+    ///
+    /// # Errors
+    ///
+    /// * If a tree could not be read or saved.
+    ///
+    /// # Returns
+    ///
+    /// The id of the modified tree.
+    pub(crate) fn update_tree(
+        &mut self,
+        id: Option<TreeId>,
+        depth: usize,
+        changes: &[(PathBuf, TreeChange)],
+    ) -> RusticResult<TreeId> {
+        let tree = id.map_or_else(
+            || Ok(Tree::default()),
+            |id| Tree::from_backend(self.be, self.index, id),
+        )?;
+        let mut nodes: BTreeMap<OsString, Node> = tree
+            .nodes
+            .into_iter()
+            .map(|node| (node.name().into_owned(), node))
+            .collect();
+
+        // changes below the same child are adjacent, as paths are ordered by their components
+        for group in changes
+            .chunk_by(|(p1, _), (p2, _)| p1.components().nth(depth) == p2.components().nth(depth))
+        {
+            let Some(Component::Normal(name)) = group[0].0.components().nth(depth) else {
+                continue;
+            };
+            let name = name.to_os_string();
+            // the child itself sorts before all paths below it
+            let (own, below) = match group.split_first() {
+                Some(((path, change), below)) if path.components().count() == depth + 1 => {
+                    (Some(change), below)
+                }
+                _ => (None, group),
+            };
+
+            let old = nodes.remove(&name);
+            let (old, new) = match own {
+                Some(TreeChange::Removed) => (None, None),
+                Some(TreeChange::Modified(node)) => (old, Some(node.clone())),
+                None => (old, None),
+            };
+            if new.is_none() && below.is_empty() {
+                // removed
+                continue;
+            }
+
+            let mut node = new.or_else(|| old.clone()).unwrap_or_else(|| {
+                // Use mode 755 for missing dirs, so they can be accessed
+                let meta = Metadata {
+                    mode: Some(0o755),
+                    ..Default::default()
+                };
+                Node::new_node(&name, NodeType::Dir, meta)
+            });
+            let old_subtree = old
+                .as_ref()
+                .filter(|old| old.is_dir())
+                .and_then(|old| old.subtree);
+            if node.is_dir() {
+                node.subtree = Some(if below.is_empty() {
+                    match old_subtree {
+                        Some(subtree) => subtree,
+                        None => self.update_tree(None, depth + 1, below)?,
+                    }
+                } else {
+                    self.update_tree(old_subtree, depth + 1, below)?
+                });
+            } else if !below.is_empty() {
+                warn!(
+                    "{}: ignoring changes below a node which is not a directory.",
+                    group[0].0.display()
+                );
+            }
+
+            match (&old, node.is_dir()) {
+                (None, true) => self.summary.dirs_new += 1,
+                (None, false) => self.summary.files_new += 1,
+                (Some(old), true) if *old != node => self.summary.dirs_changed += 1,
+                (Some(old), false) if *old != node => self.summary.files_changed += 1,
+                _ => {}
+            }
+            _ = nodes.insert(name, node);
+        }
+
+        let tree = Tree {
+            nodes: nodes.into_values().collect(),
+        };
+        self.modifier.save_tree(&tree)
+    }
+
+    /// Finalizes the archiver and sets tree and summary of the snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The (modified) root tree.
+    /// * `snap` - The snapshot to modify.
+    ///
+    /// # Errors
+    ///
+    /// * If the packs or the index could not be written.
+    pub(crate) fn finalize(mut self, tree: TreeId, snap: &mut SnapshotFile) -> RusticResult<()> {
+        let stats = self.file_archiver.finalize()?;
+        stats.apply(&mut self.summary, BlobType::Data);
+        let tree_bytes = self.modifier.saved_bytes();
+        self.summary.data_added += tree_bytes;
+        self.summary.data_added_trees += tree_bytes;
+        self.modifier.finalize()?;
+
+        self.summary.finalize(&snap.time);
+        snap.tree = tree;
+        snap.summary = Some(self.summary);
+        Ok(())
+    }
+}
@@ -10,6 +10,7 @@ use crate::{
     backend::{decrypt::DecryptReadBackend, node::Node},
     blob::tree::{Tree, TreeId},
     index::ReadGlobalIndex,
+    repofile::snapshotfile::ChangeDetection,
 };
 
 /// The `ItemWithParent` is a `TreeType` wrapping the result of a parent search and a type `O`.
@@ -28,6 +29,8 @@ pub struct Parent {
     trees: Vec<(Tree, usize)>,
     /// The stack of parent trees.
     stack: Vec<Vec<(Tree, usize)>>,
+    /// The level of checks used when comparing nodes.
+    change_detection: ChangeDetection,
     /// Ignore ctime when comparing nodes.
     ignore_ctime: bool,
     /// Ignore inode number when comparing nodes.
@@ -83,12 +86,14 @@ impl Parent {
     ///
     /// * `be` - The backend to read from.
     /// * `tree_id` - The tree id of the parent tree.
-    /// * `ignore_ctime` - Ignore ctime when comparing nodes.
-    /// * `ignore_inode` - Ignore inode number when comparing nodes.
+    /// * `change_detection` - The level of checks used when comparing nodes.
+    /// * `ignore_ctime` - Ignore ctime when comparing nodes, regardless of `change_detection`.
+    /// * `ignore_inode` - Ignore inode number when comparing nodes, regardless of `change_detection`.
     pub(crate) fn new(
         be: &impl DecryptReadBackend,
         index: &impl ReadGlobalIndex,
         tree_id: impl IntoIterator<Item = TreeId>,
+        change_detection: ChangeDetection,
         ignore_ctime: bool,
         ignore_inode: bool,
     ) -> Self {
@@ -111,11 +116,17 @@ impl Parent {
             tree_ids,
             trees,
             stack: Vec::new(),
+            change_detection,
             ignore_ctime,
             ignore_inode,
         }
     }
 
+    /// Returns the level of checks used when comparing nodes.
+    pub(crate) const fn change_detection(&self) -> ChangeDetection {
+        self.change_detection
+    }
+
     /// Returns the parent node with the given name.
     ///
     /// # Arguments
@@ -161,8 +172,8 @@ impl Parent {
     /// TODO: This function does not check whether the given node is a directory.
     fn is_parent(&mut self, node: &Node, name: &OsStr) -> ParentResult<&Node> {
         // use new variables as the mutable borrow is used later
-        let ignore_ctime = self.ignore_ctime;
-        let ignore_inode = self.ignore_inode;
+        let check_ctime = self.change_detection >= ChangeDetection::Ctime && !self.ignore_ctime;
+        let check_inode = self.change_detection >= ChangeDetection::Inode && !self.ignore_inode;
 
         let mut p_node = self.p_node(name).peekable();
         if p_node.peek().is_none() {
@@ -175,8 +186,8 @@ impl Parent {
                 let meta = &node.meta;
 
                 let match_ctime =
-                    ignore_ctime || p_meta.ctime.zip(meta.ctime).is_none_or(|(x, y)| x == y);
-                let match_inode = !ignore_inode
+                    !check_ctime || p_meta.ctime.zip(meta.ctime).is_none_or(|(x, y)| x == y);
+                let match_inode = !check_inode
                     || p_meta.inode == 0
                     || meta.inode == 0
                     || p_meta.inode == meta.inode;
@@ -288,8 +299,12 @@ impl Parent {
                 TreeType::EndTree
             }
             TreeType::Other((path, mut node, open)) => {
+                let read_content = self.change_detection == ChangeDetection::Content;
                 let parent = self.is_parent(&node, &node.name());
                 let parent = match parent {
+                    ParentResult::Matched(_) if read_content && node.is_file() => {
+                        ParentResult::NotMatched
+                    }
                     ParentResult::Matched(p_node) => {
                         if p_node.content.iter().flatten().all(|id| index.has_data(id)) {
                             node.content.clone_from(&p_node.content);
@@ -13,7 +13,10 @@ use crate::{
     },
     error::{ErrorKind, RusticError, RusticResult},
     index::{ReadGlobalIndex, indexer::SharedIndexer},
-    repofile::{configfile::ConfigFile, snapshotfile::SnapshotSummary},
+    repofile::{
+        configfile::ConfigFile,
+        snapshotfile::{SnapshotRoot, SnapshotSummary},
+    },
 };
 
 pub(crate) type TreeItem = TreeType<(ParentResult<()>, u64), ParentResult<TreeId>>;
@@ -37,6 +40,8 @@ pub(crate) struct TreeArchiver<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex>
     tree_packer: Packer<BE>,
     /// The summary of the snapshot.
     summary: SnapshotSummary,
+    /// The backup paths of the snapshot.
+    roots: Vec<SnapshotRoot>,
 }
 
 impl<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex> TreeArchiver<'a, BE, I> {
@@ -54,6 +59,7 @@ impl<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex> TreeArchiver<'a, BE, I> {
     /// * `indexer` - The indexer to write to.
     /// * `config` - The config file.
     /// * `summary` - The summary of the snapshot.
+    /// * `roots` - The backup paths of the snapshot.
     ///
     /// # Errors
     ///
@@ -65,6 +71,7 @@ impl<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex> TreeArchiver<'a, BE, I> {
         indexer: SharedIndexer<BE>,
         config: &ConfigFile,
         summary: SnapshotSummary,
+        roots: Vec<SnapshotRoot>,
     ) -> RusticResult<Self> {
         let pack_sizer =
             PackSizer::from_config(config, BlobType::Tree, index.total_size(BlobType::Tree));
@@ -76,6 +83,7 @@ impl<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex> TreeArchiver<'a, BE, I> {
             index,
             tree_packer,
             summary,
+            roots,
         })
     }
 
@@ -143,6 +151,15 @@ impl<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex> TreeArchiver<'a, BE, I> {
         }
         self.summary.total_files_processed += 1;
         self.summary.total_bytes_processed += size;
+        if let Some(root) = self
+            .roots
+            .iter_mut()
+            .filter(|root| root.contains(&filename))
+            .max_by_key(|root| root.path.len())
+        {
+            root.files_processed += 1;
+            root.bytes_processed += size;
+        }
         self.tree.add(node);
     }
 
@@ -218,12 +235,12 @@ impl<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex> TreeArchiver<'a, BE, I> {
     pub(crate) fn finalize(
         mut self,
         parent_tree: Option<TreeId>,
-    ) -> RusticResult<(TreeId, SnapshotSummary)> {
+    ) -> RusticResult<(TreeId, SnapshotSummary, Vec<SnapshotRoot>)> {
         let parent = parent_tree.map_or(ParentResult::NotFound, ParentResult::Matched);
         let id = self.backup_tree(&PathBuf::new(), &parent)?;
         let stats = self.tree_packer.finalize()?;
         stats.apply(&mut self.summary, BlobType::Tree);
 
-        Ok((id, self.summary))
+        Ok((id, self.summary, self.roots))
     }
 }
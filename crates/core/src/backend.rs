@@ -1,12 +1,26 @@
 //! Module for backend related functionality.
+#[cfg(feature = "async")]
+pub(crate) mod async_backend;
+pub(crate) mod bandwidth;
 pub(crate) mod cache;
 pub(crate) mod childstdout;
 pub(crate) mod decrypt;
 pub(crate) mod dry_run;
+pub(crate) mod filter;
 pub(crate) mod hotcold;
 pub(crate) mod ignore;
 pub(crate) mod local_destination;
+pub(crate) mod manifest;
+pub(crate) mod memory;
+pub(crate) mod metrics;
+pub(crate) mod mirror;
 pub(crate) mod node;
+pub(crate) mod object_lock;
+pub(crate) mod profile;
+pub(crate) mod reader;
+pub(crate) mod remap;
+pub(crate) mod remote_source;
+pub(crate) mod retry;
 pub(crate) mod stdin;
 pub(crate) mod warm_up;
 
@@ -14,6 +28,7 @@ use std::{io::Read, ops::Deref, path::PathBuf, sync::Arc};
 
 use bytes::Bytes;
 use enum_map::Enum;
+use jiff::Timestamp;
 use log::trace;
 
 #[cfg(test)]
@@ -22,8 +37,11 @@ use mockall::mock;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::{
-    backend::node::{Metadata, Node, NodeType},
-    error::RusticResult,
+    backend::{
+        metrics::BackendCounters,
+        node::{Metadata, Node, NodeType},
+    },
+    error::{ErrorKind, RusticError, RusticResult},
     id::Id,
 };
 
@@ -38,15 +56,19 @@ pub enum BackendErrorKind {
 pub(crate) type BackendResult<T> = Result<T, BackendErrorKind>;
 
 /// All [`FileType`]s which are located in separated directories
-pub const ALL_FILE_TYPES: [FileType; 4] = [
+pub const ALL_FILE_TYPES: [FileType; 7] = [
     FileType::Key,
     FileType::Snapshot,
     FileType::Index,
     FileType::Pack,
+    FileType::Scrub,
+    FileType::Lock,
+    FileType::Journal,
 ];
 
 /// Type for describing the kind of a file that can occur.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum, derive_more::Display)]
+#[non_exhaustive]
 pub enum FileType {
     /// Config file
     #[serde(rename = "config")]
@@ -63,6 +85,15 @@ pub enum FileType {
     /// Data
     #[serde(rename = "pack")]
     Pack,
+    /// Scrub state
+    #[serde(rename = "scrub")]
+    Scrub,
+    /// Locks
+    #[serde(rename = "lock")]
+    Lock,
+    /// Deletion journals
+    #[serde(rename = "journal")]
+    Journal,
 }
 
 impl FileType {
@@ -75,13 +106,27 @@ impl FileType {
             Self::Index => "index",
             Self::Key => "keys",
             Self::Pack => "data",
+            Self::Scrub => "scrub",
+            Self::Lock => "locks",
+            Self::Journal => "journal",
         }
     }
 
+    /// Returns if the file type is a rustic extension which is no part of the restic repository layout.
+    ///
+    /// Files of these types are only stored if the backend supports them, see
+    /// [`ReadBackend::supports_file_type`].
+    #[must_use]
+    pub const fn is_extension(self) -> bool {
+        matches!(self, Self::Scrub | Self::Journal)
+    }
+
     /// Returns if the file type is cacheable.
     const fn is_cacheable(self) -> bool {
         match self {
-            Self::Config | Self::Key | Self::Pack => false,
+            Self::Config | Self::Key | Self::Pack | Self::Scrub | Self::Lock | Self::Journal => {
+                false
+            }
             Self::Snapshot | Self::Index => true,
         }
     }
@@ -122,6 +167,21 @@ pub trait ReadBackend: Send + Sync + 'static {
             .collect())
     }
 
+    /// Returns the time the given file has been modified last, if the backend supports it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the metadata of the file could not be read.
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        _ = (tpe, id);
+        Ok(None)
+    }
+
     /// Reads full data of the given file.
     ///
     /// # Arguments
@@ -182,6 +242,19 @@ pub trait ReadBackend: Send + Sync + 'static {
         false
     }
 
+    /// Returns whether the backend can store files of the given type.
+    ///
+    /// Backends which only implement the restic repository layout, e.g. the REST backend, reject file
+    /// types which are no part of it, see [`FileType::is_extension`]. By default, only the restic file
+    /// types are supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files.
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        !tpe.is_extension()
+    }
+
     /// Warm-up the given file.
     ///
     /// # Arguments
@@ -322,6 +395,64 @@ pub trait WriteBackend: ReadBackend {
     ///
     /// The result of the removal.
     fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()>;
+
+    /// Returns whether the backend supports object locks, e.g. S3 object lock in compliance mode.
+    ///
+    /// Locked files cannot be removed or overwritten until their retention period expired.
+    fn supports_object_lock(&self) -> bool {
+        false
+    }
+
+    /// Writes bytes to the given file and locks it until the given time.
+    ///
+    /// The retention period is set within the same request which writes the file, e.g. using the
+    /// object lock headers of a S3 `PutObject` request, so there is no time span where the file
+    /// exists unlocked.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the data should be cached.
+    /// * `buf` - The data to write.
+    /// * `until` - The end of the retention period.
+    ///
+    /// # Errors
+    ///
+    /// * If the backend does not support object locks.
+    /// * If the data could not be written.
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        _ = (cacheable, buf, until);
+        Err(RusticError::new(
+            ErrorKind::Unsupported,
+            "Backend `{location}` does not support locking file `{id}`.",
+        )
+        .attach_context("location", self.location())
+        .attach_context("tpe", tpe.to_string())
+        .attach_context("id", id.to_string()))
+    }
+
+    /// Returns the end of the retention period of the given file, if it is locked.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the retention period could not be determined.
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        _ = (tpe, id);
+        Ok(None)
+    }
 }
 
 #[cfg(test)]
@@ -331,6 +462,7 @@ mock! {
     impl ReadBackend for Backend{
         fn location(&self) -> String;
         fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>>;
+        fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>>;
         fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes>;
         fn read_partial(
             &self,
@@ -347,6 +479,16 @@ mock! {
         fn create(&self) -> RusticResult<()>;
         fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()>;
         fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()>;
+        fn supports_object_lock(&self) -> bool;
+        fn write_bytes_locked(
+            &self,
+            tpe: FileType,
+            id: &Id,
+            cacheable: bool,
+            buf: Bytes,
+            until: Timestamp,
+        ) -> RusticResult<()>;
+        fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>>;
     }
 }
 
@@ -360,6 +502,23 @@ impl WriteBackend for Arc<dyn WriteBackend> {
     fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
         self.deref().remove(tpe, id, cacheable)
     }
+    fn supports_object_lock(&self) -> bool {
+        self.deref().supports_object_lock()
+    }
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        self.deref()
+            .write_bytes_locked(tpe, id, cacheable, buf, until)
+    }
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.deref().locked_until(tpe, id)
+    }
 }
 
 impl ReadBackend for Arc<dyn WriteBackend> {
@@ -369,6 +528,10 @@ impl ReadBackend for Arc<dyn WriteBackend> {
     fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
         self.deref().list_with_size(tpe)
     }
+
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.deref().modified(tpe, id)
+    }
     fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
         self.deref().list(tpe)
     }
@@ -393,6 +556,9 @@ impl ReadBackend for Arc<dyn WriteBackend> {
     fn needs_warm_up(&self) -> bool {
         self.deref().needs_warm_up()
     }
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.deref().supports_file_type(tpe)
+    }
     fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
         self.deref().warm_up(tpe, id)
     }
@@ -432,6 +598,35 @@ impl<O> ReadSourceEntry<O> {
         Ok(Self { path, node, open })
     }
 
+    /// Create a new entry; the name of the node is the last component of `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the entry
+    /// * `node_type` - The type of the entry
+    /// * `meta` - The metadata of the entry
+    /// * `open` - Information how to open the entry; needed for files
+    ///
+    /// # Errors
+    ///
+    /// * If `path` has no last component, e.g. is `/` or ends with `..`.
+    pub fn new(
+        path: PathBuf,
+        node_type: NodeType,
+        meta: Metadata,
+        open: Option<O>,
+    ) -> RusticResult<Self> {
+        let Some(name) = path.file_name() else {
+            return Err(RusticError::new(
+                ErrorKind::InvalidInput,
+                "Failed to create an entry for `{path}` as it has no file name.",
+            )
+            .attach_context("path", path.display().to_string()));
+        };
+        let node = Node::new_node(name, node_type, meta);
+        Ok(Self { path, node, open })
+    }
+
     /// Turn this into a tree entry
     pub fn as_tree_entry(self) -> (PathBuf, Node) {
         (self.path, self.node)
@@ -466,7 +661,17 @@ impl<T: Read + Send + 'static> ReadSourceOpen for T {
 
 /// Trait for backends that can read from a source.
 ///
-/// This trait is implemented by all backends that can read data from a source.
+/// This trait is implemented by all backends that can read data from a source. Implement it to back up
+/// data which is not stored in a local filesystem, e.g. database dumps, object store listings or data
+/// generated in memory, directly into a snapshot using [`Repository::archive`](crate::Repository::archive).
+/// See [`MemorySource`](crate::MemorySource) for a simple implementation.
+///
+/// # Requirements for implementors
+///
+/// * The entries must be returned in the order of a sorted directory walk, i.e. sorted by path.
+/// * Directories don't need to be returned; missing parent directories are added automatically.
+/// * File entries must contain the information to open them; their contents are read in parallel.
+/// * Errors returned by the iterator are logged and the corresponding entries are skipped.
 pub trait ReadSource: Sync + Send {
     /// The type used to handle open source files
     type Open: ReadSourceOpen;
@@ -502,6 +707,9 @@ pub struct RepositoryBackends {
 
     /// The hot repository of this [`RepositoryBackends`].
     repo_hot: Option<Arc<dyn WriteBackend>>,
+
+    /// The metrics collected for the operations on the repository backends.
+    metrics: Arc<BackendCounters>,
 }
 
 impl RepositoryBackends {
@@ -515,9 +723,24 @@ impl RepositoryBackends {
         Self {
             repository,
             repo_hot,
+            metrics: Arc::default(),
         }
     }
 
+    /// Use the given counters to collect the metrics of the backend operations.
+    ///
+    /// This allows to also pass the counters to backends wrapped by the repository backends, e.g. to
+    /// [`RetryBackend::with_metrics`](crate::RetryBackend::with_metrics).
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - The counters to use
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<BackendCounters>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
     /// Returns the repository of this [`RepositoryBackends`].
     #[must_use]
     pub fn repository(&self) -> Arc<dyn WriteBackend> {
@@ -529,4 +752,10 @@ impl RepositoryBackends {
     pub fn repo_hot(&self) -> Option<Arc<dyn WriteBackend>> {
         self.repo_hot.clone()
     }
+
+    /// Returns the metrics of this [`RepositoryBackends`].
+    #[must_use]
+    pub fn metrics(&self) -> Arc<BackendCounters> {
+        self.metrics.clone()
+    }
 }
@@ -0,0 +1,521 @@
+//! Asynchronous backends
+//!
+//! rustic_core itself works with blocking backends. The traits in this module allow async applications
+//! to use blocking backends from async code using [`BlockingBackend`] and to implement backends natively
+//! using async code which can be used by a [`Repository`](crate::Repository) using [`AsyncBackend`].
+use std::{future::Future, sync::Arc};
+
+use bytes::Bytes;
+use jiff::Timestamp;
+use tokio::{runtime::Handle, task::spawn_blocking};
+
+use crate::{
+    backend::{FileType, ReadBackend, WriteBackend},
+    error::{ErrorKind, RusticError, RusticResult},
+    id::Id,
+};
+
+/// Trait for asynchronous backends that can read.
+///
+/// This is the async equivalent of [`ReadBackend`].
+pub trait AsyncReadBackend: Send + Sync + 'static {
+    /// Returns the location of the backend.
+    fn location(&self) -> String;
+
+    /// Lists all files with their size of the given type.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files to list.
+    ///
+    /// # Errors
+    ///
+    /// * If the files could not be listed.
+    fn list_with_size(
+        &self,
+        tpe: FileType,
+    ) -> impl Future<Output = RusticResult<Vec<(Id, u32)>>> + Send;
+
+    /// Lists all files of the given type.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files to list.
+    ///
+    /// # Errors
+    ///
+    /// * If the files could not be listed.
+    fn list(&self, tpe: FileType) -> impl Future<Output = RusticResult<Vec<Id>>> + Send {
+        async move {
+            Ok(self
+                .list_with_size(tpe)
+                .await?
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect())
+        }
+    }
+
+    /// Returns the time the given file has been modified last, if the backend supports it.
+    ///
+    /// This is the async equivalent of [`ReadBackend::modified`].
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the metadata of the file could not be read.
+    fn modified(
+        &self,
+        tpe: FileType,
+        id: &Id,
+    ) -> impl Future<Output = RusticResult<Option<Timestamp>>> + Send {
+        _ = (tpe, id);
+        async { Ok(None) }
+    }
+
+    /// Reads full data of the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be read.
+    fn read_full(&self, tpe: FileType, id: &Id)
+    -> impl Future<Output = RusticResult<Bytes>> + Send;
+
+    /// Reads partial data of the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file should be cached.
+    /// * `offset` - The offset to read from.
+    /// * `length` - The length to read.
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be read.
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> impl Future<Output = RusticResult<Bytes>> + Send;
+
+    /// Get the warmup path for the given file type and id, see [`ReadBackend::warmup_path`].
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String;
+
+    /// Returns whether the backend can store files of the given type, see
+    /// [`ReadBackend::supports_file_type`].
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files.
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        !tpe.is_extension()
+    }
+}
+
+/// Trait for asynchronous backends that can write.
+///
+/// This is the async equivalent of [`WriteBackend`].
+pub trait AsyncWriteBackend: AsyncReadBackend {
+    /// Creates a new backend.
+    ///
+    /// # Errors
+    ///
+    /// * If the backend could not be created.
+    fn create(&self) -> impl Future<Output = RusticResult<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Writes bytes to the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the data should be cached.
+    /// * `buf` - The data to write.
+    ///
+    /// # Errors
+    ///
+    /// * If the data could not be written.
+    fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+    ) -> impl Future<Output = RusticResult<()>> + Send;
+
+    /// Removes the given file.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the file is cacheable.
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be removed.
+    fn remove(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+    ) -> impl Future<Output = RusticResult<()>> + Send;
+
+    /// Returns whether the backend supports object locks.
+    ///
+    /// This is the async equivalent of [`WriteBackend::supports_object_lock`].
+    fn supports_object_lock(&self) -> bool {
+        false
+    }
+
+    /// Writes bytes to the given file and locks it until the given time.
+    ///
+    /// This is the async equivalent of [`WriteBackend::write_bytes_locked`].
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    /// * `cacheable` - Whether the data should be cached.
+    /// * `buf` - The data to write.
+    /// * `until` - The end of the retention period.
+    ///
+    /// # Errors
+    ///
+    /// * If the backend does not support object locks.
+    /// * If the data could not be written.
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> impl Future<Output = RusticResult<()>> + Send {
+        _ = (cacheable, buf, until);
+        let err = RusticError::new(
+            ErrorKind::Unsupported,
+            "Backend `{location}` does not support locking file `{id}`.",
+        )
+        .attach_context("location", self.location())
+        .attach_context("tpe", tpe.to_string())
+        .attach_context("id", id.to_string());
+        async { Err(err) }
+    }
+
+    /// Returns the end of the retention period of the given file, if it is locked.
+    ///
+    /// This is the async equivalent of [`WriteBackend::locked_until`].
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    ///
+    /// # Errors
+    ///
+    /// * If the retention period could not be determined.
+    fn locked_until(
+        &self,
+        tpe: FileType,
+        id: &Id,
+    ) -> impl Future<Output = RusticResult<Option<Timestamp>>> + Send {
+        _ = (tpe, id);
+        async { Ok(None) }
+    }
+}
+
+/// Run a blocking function on the blocking thread pool of the tokio runtime.
+///
+/// # Errors
+///
+/// * If the blocking task panicked or has been cancelled.
+pub(crate) async fn run_blocking<T, F>(f: F) -> RusticResult<T>
+where
+    F: FnOnce() -> RusticResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_blocking(f).await.map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::Internal,
+            "Blocking task failed to complete.",
+            err,
+        )
+    })?
+}
+
+/// An adapter to use a blocking backend as [`AsyncReadBackend`] or [`AsyncWriteBackend`].
+///
+/// All calls of the blocking backend are run on the blocking thread pool of the tokio runtime.
+#[derive(Debug)]
+pub struct BlockingBackend<B: ?Sized> {
+    /// The blocking backend
+    be: Arc<B>,
+}
+
+impl<B: ?Sized> BlockingBackend<B> {
+    /// Creates a new `BlockingBackend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The blocking backend to use
+    pub const fn new(be: Arc<B>) -> Self {
+        Self { be }
+    }
+}
+
+impl<B: ?Sized> Clone for BlockingBackend<B> {
+    fn clone(&self) -> Self {
+        Self {
+            be: self.be.clone(),
+        }
+    }
+}
+
+impl<B: ReadBackend + ?Sized> AsyncReadBackend for BlockingBackend<B> {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    async fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        let be = self.be.clone();
+        run_blocking(move || be.list_with_size(tpe)).await
+    }
+
+    async fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        let (be, id) = (self.be.clone(), *id);
+        run_blocking(move || be.modified(tpe, &id)).await
+    }
+
+    async fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        let (be, id) = (self.be.clone(), *id);
+        run_blocking(move || be.read_full(tpe, &id)).await
+    }
+
+    async fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        let (be, id) = (self.be.clone(), *id);
+        run_blocking(move || be.read_partial(tpe, &id, cacheable, offset, length)).await
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
+}
+
+impl<B: WriteBackend + ?Sized> AsyncWriteBackend for BlockingBackend<B> {
+    async fn create(&self) -> RusticResult<()> {
+        let be = self.be.clone();
+        run_blocking(move || be.create()).await
+    }
+
+    async fn write_bytes(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+    ) -> RusticResult<()> {
+        let (be, id) = (self.be.clone(), *id);
+        run_blocking(move || be.write_bytes(tpe, &id, cacheable, buf)).await
+    }
+
+    async fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        let (be, id) = (self.be.clone(), *id);
+        run_blocking(move || be.remove(tpe, &id, cacheable)).await
+    }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    async fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        let (be, id) = (self.be.clone(), *id);
+        run_blocking(move || be.write_bytes_locked(tpe, &id, cacheable, buf, until)).await
+    }
+
+    async fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        let (be, id) = (self.be.clone(), *id);
+        run_blocking(move || be.locked_until(tpe, &id)).await
+    }
+}
+
+/// An adapter to use an [`AsyncReadBackend`] or [`AsyncWriteBackend`] as blocking backend, e.g. within
+/// [`RepositoryBackends`](crate::RepositoryBackends).
+///
+/// The futures are run on the given tokio runtime.
+///
+/// # Note
+///
+/// The blocking calls must not be made from within the async context of the runtime. This is ensured
+/// when using the backend with an [`AsyncRepository`](crate::AsyncRepository).
+#[derive(Debug)]
+pub struct AsyncBackend<B> {
+    /// The async backend
+    be: B,
+    /// The handle of the runtime to run the futures on
+    handle: Handle,
+}
+
+impl<B> AsyncBackend<B> {
+    /// Creates a new `AsyncBackend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The async backend to use
+    /// * `handle` - The handle of the runtime to run the futures on
+    pub const fn new(be: B, handle: Handle) -> Self {
+        Self { be, handle }
+    }
+}
+
+impl<B: AsyncReadBackend> ReadBackend for AsyncBackend<B> {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.handle.block_on(self.be.list_with_size(tpe))
+    }
+
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.handle.block_on(self.be.modified(tpe, id))
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.handle.block_on(self.be.list(tpe))
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.handle.block_on(self.be.read_full(tpe, id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.handle
+            .block_on(self.be.read_partial(tpe, id, cacheable, offset, length))
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
+}
+
+impl<B: AsyncWriteBackend> WriteBackend for AsyncBackend<B> {
+    fn create(&self) -> RusticResult<()> {
+        self.handle.block_on(self.be.create())
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        self.handle
+            .block_on(self.be.write_bytes(tpe, id, cacheable, buf))
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.handle.block_on(self.be.remove(tpe, id, cacheable))
+    }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        self.handle
+            .block_on(self.be.write_bytes_locked(tpe, id, cacheable, buf, until))
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.handle.block_on(self.be.locked_until(tpe, id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::runtime::Builder;
+
+    use crate::backend::MockBackend;
+
+    #[test]
+    fn test_blocking_and_async_backend() {
+        let id = Id::random();
+        let mut be = MockBackend::new();
+        _ = be
+            .expect_read_full()
+            .returning(|_, _| Ok(Bytes::from_static(b"data")));
+        _ = be
+            .expect_list_with_size()
+            .returning(move |_| Ok(vec![(id, 4)]));
+
+        let rt = Builder::new_current_thread().build().unwrap();
+        let be = BlockingBackend::new(Arc::new(be));
+        rt.block_on(async {
+            assert_eq!(be.read_full(FileType::Index, &id).await.unwrap(), "data");
+            assert_eq!(be.list(FileType::Index).await.unwrap(), vec![id]);
+        });
+
+        // use the async backend as blocking backend outside of the async context
+        let be = AsyncBackend::new(be, rt.handle().clone());
+        assert_eq!(
+            ReadBackend::read_full(&be, FileType::Index, &id).unwrap(),
+            "data"
+        );
+        assert_eq!(ReadBackend::list(&be, FileType::Index).unwrap(), vec![id]);
+    }
+}
@@ -0,0 +1,481 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use bytesize::ByteSize;
+use jiff::{Timestamp, Zoned, civil::Time};
+
+use crate::{
+    backend::{FileType, ReadBackend, WriteBackend},
+    error::{ErrorKind, RusticError, RusticResult},
+    id::Id,
+};
+
+/// A single entry of a [`BandwidthSchedule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BandwidthScheduleEntry {
+    /// The time of day this entry starts at
+    pub start: Time,
+    /// The bandwidth limit per second; `None` means unlimited
+    pub limit: Option<ByteSize>,
+}
+
+/// A bandwidth limit which may change depending on the time of day.
+///
+/// The schedule is given like the `--bwlimit` option of rclone: A list of `HH:MM,LIMIT` entries separated by
+/// spaces, e.g. `08:00,1MiB 18:00,off`, where each limit applies from the given time until the next entry.
+/// The last entry also applies before the first entry. A single `LIMIT` applies the whole day. Limits are
+/// given per second; use `off` for no limit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BandwidthSchedule {
+    /// The entries, sorted by their start
+    entries: Vec<BandwidthScheduleEntry>,
+}
+
+impl BandwidthSchedule {
+    /// Creates a schedule with the given limit for the whole day.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The bandwidth limit per second; `None` means unlimited
+    #[must_use]
+    pub fn constant(limit: Option<ByteSize>) -> Self {
+        Self {
+            entries: vec![BandwidthScheduleEntry {
+                start: Time::midnight(),
+                limit,
+            }],
+        }
+    }
+
+    /// Returns the entries of the schedule, sorted by their start
+    #[must_use]
+    pub fn entries(&self) -> &[BandwidthScheduleEntry] {
+        &self.entries
+    }
+
+    /// Returns the bandwidth limit per second at the given time of day; `None` means unlimited
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The time of day
+    #[must_use]
+    pub fn limit_at(&self, time: Time) -> Option<ByteSize> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.start <= time)
+            .or_else(|| self.entries.last())
+            .and_then(|entry| entry.limit)
+    }
+}
+
+/// Parse a limit of a bandwidth schedule
+///
+/// # Arguments
+///
+/// * `s` - The limit to parse
+///
+/// # Errors
+///
+/// * If the limit is not `off` and no valid size.
+fn parse_limit(s: &str) -> Result<Option<ByteSize>, Box<RusticError>> {
+    if matches!(s, "off" | "unlimited") {
+        return Ok(None);
+    }
+    let limit = ByteSize::from_str(s).map_err(|err| {
+        RusticError::new(
+            ErrorKind::InvalidInput,
+            "Failed to parse bandwidth limit `{limit}`: {error}. Please use a size per second (e.g. `1MiB`) or `off`.",
+        )
+        .attach_context("limit", s)
+        .attach_context("error", err)
+    })?;
+    Ok((limit.as_u64() > 0).then_some(limit))
+}
+
+impl FromStr for BandwidthSchedule {
+    type Err = Box<RusticError>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = s
+            .split_whitespace()
+            .map(|entry| match entry.split_once(',') {
+                None => Ok(BandwidthScheduleEntry {
+                    start: Time::midnight(),
+                    limit: parse_limit(entry)?,
+                }),
+                Some((start, limit)) => Ok(BandwidthScheduleEntry {
+                    start: start.parse().map_err(|err| {
+                        RusticError::with_source(
+                            ErrorKind::InvalidInput,
+                            "Failed to parse start time `{time}` of bandwidth schedule. Please use `HH:MM`, e.g. `08:00`.",
+                            err,
+                        )
+                        .attach_context("time", start)
+                    })?,
+                    limit: parse_limit(limit)?,
+                }),
+            })
+            .collect::<Result<Vec<_>, Self::Err>>()?;
+
+        entries.sort_by_key(|entry| entry.start);
+        if entries.windows(2).any(|w| w[0].start == w[1].start) {
+            return Err(RusticError::new(
+                ErrorKind::InvalidInput,
+                "Bandwidth schedule `{schedule}` contains multiple entries for the same time.",
+            )
+            .attach_context("schedule", s));
+        }
+        Ok(Self { entries })
+    }
+}
+
+impl fmt::Display for BandwidthSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{},", entry.start.strftime("%H:%M"))?;
+            match entry.limit {
+                Some(limit) => write!(f, "{}", limit.as_u64())?,
+                None => write!(f, "off")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The state of the token bucket
+#[derive(Debug)]
+struct BucketState {
+    /// The schedule to use
+    schedule: BandwidthSchedule,
+    /// The available bytes; negative if more bytes have been transferred than allowed
+    tokens: f64,
+    /// The last time the tokens have been refilled
+    last: Instant,
+}
+
+impl BucketState {
+    /// Account for transferring the given number of bytes at the given time.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time
+    /// * `limit` - The bandwidth limit per second which currently applies; `None` means unlimited
+    /// * `bytes` - The number of bytes to transfer
+    ///
+    /// # Returns
+    ///
+    /// The time to wait until the transfer is allowed
+    fn take(&mut self, now: Instant, limit: Option<ByteSize>, bytes: u64) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+
+        let Some(limit) = limit else {
+            self.tokens = 0.0;
+            return Duration::ZERO;
+        };
+
+        #[allow(clippy::cast_precision_loss)]
+        let rate = limit.as_u64() as f64;
+        // allow bursts of at most one second
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        #[allow(clippy::cast_precision_loss)]
+        let needed = bytes as f64;
+        self.tokens -= needed;
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// A handle to a token bucket limiting the bandwidth of all backend operations of a repository.
+///
+/// The handle can be cloned; all clones share the same limit. Use [`BandwidthLimiter::set_schedule`] to
+/// adjust the limit at runtime.
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+    /// The shared state
+    state: Arc<Mutex<BucketState>>,
+}
+
+impl Default for BandwidthLimiter {
+    fn default() -> Self {
+        Self::new(BandwidthSchedule::default())
+    }
+}
+
+impl BandwidthLimiter {
+    /// Creates a new `BandwidthLimiter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - The schedule to use
+    #[must_use]
+    pub fn new(schedule: BandwidthSchedule) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(BucketState {
+                schedule,
+                tokens: 0.0,
+                last: Instant::now(),
+            })),
+        }
+    }
+
+    /// Returns the schedule currently used
+    #[must_use]
+    pub fn schedule(&self) -> BandwidthSchedule {
+        self.state.lock().unwrap().schedule.clone()
+    }
+
+    /// Use a new schedule; this takes effect for all following transfers.
+    ///
+    /// # Arguments
+    ///
+    /// * `schedule` - The schedule to use
+    pub fn set_schedule(&self, schedule: BandwidthSchedule) {
+        self.state.lock().unwrap().schedule = schedule;
+    }
+
+    /// Use a constant limit, see [`BandwidthSchedule::constant`].
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The bandwidth limit per second; `None` means unlimited
+    pub fn set_limit(&self, limit: Option<ByteSize>) {
+        self.set_schedule(BandwidthSchedule::constant(limit));
+    }
+
+    /// Returns the bandwidth limit per second which currently applies; `None` means unlimited
+    #[must_use]
+    pub fn current_limit(&self) -> Option<ByteSize> {
+        self.state
+            .lock()
+            .unwrap()
+            .schedule
+            .limit_at(Zoned::now().time())
+    }
+
+    /// Account for transferring the given number of bytes and block until the transfer is allowed.
+    ///
+    /// Transfers larger than the limit are allowed, but the following transfers are delayed accordingly.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The number of bytes to transfer
+    pub fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            // only determine the time of day if there is a limit at all
+            let limit = if state.schedule.entries.iter().any(|e| e.limit.is_some()) {
+                state.schedule.limit_at(Zoned::now().time())
+            } else {
+                None
+            };
+            state.take(Instant::now(), limit, bytes)
+        };
+        if !wait.is_zero() {
+            sleep(wait);
+        }
+    }
+}
+
+/// A backend which limits the bandwidth of reading and writing files using a [`BandwidthLimiter`].
+#[derive(Clone, Debug)]
+pub struct BandwidthLimitBackend {
+    /// The backend to use
+    be: Arc<dyn WriteBackend>,
+    /// The limiter to use
+    limiter: BandwidthLimiter,
+}
+
+impl BandwidthLimitBackend {
+    /// Creates a new `BandwidthLimitBackend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to limit
+    /// * `limiter` - The limiter to use; it may be shared with other backends
+    pub fn new(be: Arc<dyn WriteBackend>, limiter: BandwidthLimiter) -> Self {
+        Self { be, limiter }
+    }
+}
+
+impl ReadBackend for BandwidthLimitBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        let data = self.be.read_full(tpe, id)?;
+        self.limiter.acquire(data.len() as u64);
+        Ok(data)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.limiter.acquire(length.into());
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for BandwidthLimitBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        self.limiter.acquire(buf.len() as u64);
+        self.be.write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        self.limiter.acquire(buf.len() as u64);
+        self.be.write_bytes_locked(tpe, id, cacheable, buf, until)
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use jiff::civil::time;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("1MiB", "00:00,1048576")]
+    #[case("18:00,off 08:00,1KiB", "08:00,1024 18:00,off")]
+    #[case(
+        "08:00,512 12:00,unlimited 13:00,1KB",
+        "08:00,512 12:00,off 13:00,1000"
+    )]
+    fn schedule_roundtrip(#[case] input: &str, #[case] expected: &str) {
+        let schedule: BandwidthSchedule = input.parse().unwrap();
+        assert_eq!(schedule.to_string(), expected);
+        assert_eq!(expected.parse::<BandwidthSchedule>().unwrap(), schedule);
+    }
+
+    #[rstest]
+    #[case("1XB")]
+    #[case("25:00,1MiB")]
+    #[case("08:00,1MiB 08:00,off")]
+    fn invalid_schedule(#[case] input: &str) {
+        assert!(input.parse::<BandwidthSchedule>().is_err());
+    }
+
+    #[test]
+    fn schedule_limit_at() {
+        let schedule: BandwidthSchedule = "08:00,1KiB 18:00,off".parse().unwrap();
+        assert_eq!(schedule.limit_at(time(7, 0, 0, 0)), None);
+        assert_eq!(schedule.limit_at(time(8, 0, 0, 0)), Some(ByteSize::kib(1)));
+        assert_eq!(
+            schedule.limit_at(time(17, 59, 0, 0)),
+            Some(ByteSize::kib(1))
+        );
+        assert_eq!(schedule.limit_at(time(18, 0, 0, 0)), None);
+        assert_eq!(
+            BandwidthSchedule::default().limit_at(time(12, 0, 0, 0)),
+            None
+        );
+    }
+
+    #[test]
+    fn bucket_delays_transfers() {
+        let start = Instant::now();
+        let mut state = BucketState {
+            schedule: BandwidthSchedule::default(),
+            tokens: 0.0,
+            last: start,
+        };
+        let limit = Some(ByteSize::kib(100));
+
+        // the bucket starts empty, so each transfer waits for its size
+        assert_eq!(
+            state.take(start, limit, 50 * 1024),
+            Duration::from_millis(500)
+        );
+        assert_eq!(state.take(start, limit, 50 * 1024), Duration::from_secs(1));
+
+        // after waiting, the next transfer only needs to wait for itself
+        let now = start + Duration::from_secs(1);
+        assert_eq!(
+            state.take(now, limit, 10 * 1024),
+            Duration::from_millis(100)
+        );
+
+        // bursts are limited to one second
+        let now = now + Duration::from_secs(10);
+        assert_eq!(state.take(now, limit, 100 * 1024), Duration::ZERO);
+        assert_eq!(
+            state.take(now, limit, 10 * 1024),
+            Duration::from_millis(100)
+        );
+
+        // no limit: no waiting and the debt is discarded
+        assert_eq!(state.take(now, None, 100 * 1024 * 1024), Duration::ZERO);
+        assert_eq!(state.take(now, limit, 0), Duration::ZERO);
+    }
+}
@@ -1,14 +1,22 @@
 use std::{
     collections::HashMap,
     fs::{self, File},
-    io::{self, Read, Seek, SeekFrom, Write},
+    io::{self, ErrorKind as IoErrorKind, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
 };
 
 use bytes::Bytes;
 use dirs::cache_dir;
-use log::{trace, warn};
+use filetime::{FileTime, set_file_mtime};
+use gethostname::gethostname;
+use jiff::Timestamp;
+use log::{debug, trace, warn};
+use serde_derive::{Deserialize, Serialize};
 use walkdir::WalkDir;
 
 use crate::{
@@ -18,6 +26,9 @@ use crate::{
     repofile::configfile::RepositoryId,
 };
 
+/// Name of the cache directory containing the cached pack headers
+const PACK_HEADER_DIRNAME: &str = "pack-headers";
+
 /// Backend that caches data.
 ///
 /// This backend caches data in a directory.
@@ -79,6 +90,10 @@ impl ReadBackend for CachedBackend {
         Ok(list)
     }
 
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
     /// Reads full data of the given file.
     ///
     /// # Arguments
@@ -174,6 +189,10 @@ impl ReadBackend for CachedBackend {
         self.be.needs_warm_up()
     }
 
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
+
     fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
         self.be.warm_up(tpe, id)
     }
@@ -214,7 +233,8 @@ impl WriteBackend for CachedBackend {
 
     /// Removes the given file.
     ///
-    /// If the file is cacheable, it will also be removed from the cache.
+    /// If the file is cacheable, it will also be removed from the cache. For pack files, a cached pack
+    /// header is removed as well.
     ///
     /// # Arguments
     ///
@@ -229,8 +249,43 @@ impl WriteBackend for CachedBackend {
                 err.display_log()
             );
         }
+        if tpe == FileType::Pack
+            && let Err(err) = self.cache.remove_pack_header(id)
+        {
+            warn!(
+                "Error in cache backend removing pack header {id}: {}",
+                err.display_log()
+            );
+        }
         self.be.remove(tpe, id, cacheable)
     }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        if (cacheable || tpe.is_cacheable())
+            && let Err(err) = self.cache.write_bytes(tpe, id, &buf)
+        {
+            warn!(
+                "Error in cache backend writing {tpe:?},{id}: {}",
+                err.display_log()
+            );
+        }
+        self.be.write_bytes_locked(tpe, id, cacheable, buf, until)
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
 }
 
 /// Backend that caches data in a directory.
@@ -238,6 +293,10 @@ impl WriteBackend for CachedBackend {
 pub struct Cache {
     /// The path to the cache.
     path: PathBuf,
+    /// The maximum total size of the cached pack files, if limited
+    size_limit: Option<u64>,
+    /// The current total size of the cached pack files; only tracked if the size is limited
+    pack_size: Arc<AtomicU64>,
 }
 
 impl Cache {
@@ -300,7 +359,113 @@ impl Cache {
             .attach_context("id", id.to_string())
         })?;
 
-        Ok(Self { path })
+        Ok(Self {
+            path,
+            size_limit: None,
+            pack_size: Arc::default(),
+        })
+    }
+
+    /// Limit the total size of the cached pack files and pack headers.
+    ///
+    /// If the limit is exceeded, the least recently used pack files and pack headers are removed from the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `size_limit` - The maximum total size of the cached pack files and pack headers in bytes
+    ///
+    /// # Errors
+    ///
+    /// * If the cached pack files could not be listed.
+    pub fn with_size_limit(mut self, size_limit: u64) -> RusticResult<Self> {
+        self.size_limit = Some(size_limit);
+        self.pack_size = Arc::new(AtomicU64::new(0));
+        self.evict_packs(size_limit)?;
+        Ok(self)
+    }
+
+    /// Remove the least recently used pack files and pack headers until their total size is at most `size`.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The maximum total size of the pack files and pack headers to keep
+    ///
+    /// # Errors
+    ///
+    /// * If the cached pack files could not be listed.
+    fn evict_packs(&self, size: u64) -> RusticResult<()> {
+        let mut packs: Vec<_> = [FileType::Pack.dirname(), PACK_HEADER_DIRNAME]
+            .into_iter()
+            .flat_map(|dirname| WalkDir::new(self.path.join(dirname)))
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| {
+                let meta = entry.metadata().ok()?;
+                let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((used, meta.len(), entry.into_path()))
+            })
+            .collect();
+        let mut total: u64 = packs.iter().map(|(_, size, _)| size).sum();
+
+        if total > size {
+            // remove least recently used packs first
+            packs.sort_unstable();
+            for (_, pack_size, path) in packs {
+                if total <= size {
+                    break;
+                }
+                match fs::remove_file(&path) {
+                    Ok(()) => total -= pack_size,
+                    Err(err) => warn!("Error removing {} from cache: {err}", path.display()),
+                }
+            }
+            debug!("cached packs reduced to {total} bytes");
+        }
+        self.pack_size.store(total, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Mark the given file as recently used, if the cache size is limited.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `id` - The id of the file.
+    fn touch(&self, tpe: FileType, id: &Id) {
+        if self.size_limit.is_some() && tpe == FileType::Pack {
+            // failing to update the time only affects the order of eviction
+            _ = set_file_mtime(self.path(tpe, id), FileTime::now());
+        }
+    }
+
+    /// Account for an added pack file and enforce the size limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the added file.
+    /// * `size` - The size of the added file.
+    fn add_size(&self, tpe: FileType, size: u64) {
+        if tpe == FileType::Pack {
+            self.add_limited_size(size);
+        }
+    }
+
+    /// Account for an added file which is subject to the size limit and enforce the limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the added file.
+    fn add_limited_size(&self, size: u64) {
+        let Some(size_limit) = self.size_limit else {
+            return;
+        };
+        let total = self.pack_size.fetch_add(size, Ordering::Relaxed) + size;
+        if total > size_limit {
+            // evict some more packs than needed, so that we don't need to evict after every write
+            if let Err(err) = self.evict_packs(size_limit / 10 * 9) {
+                warn!("Error evicting packs from cache: {}", err.display_log());
+            }
+        }
     }
 
     /// Returns the path to the location of this [`Cache`].
@@ -437,6 +602,7 @@ impl Cache {
         match fs::read(&path) {
             Ok(data) => {
                 trace!("cache hit!");
+                self.touch(tpe, id);
                 Ok(Some(data.into()))
             }
             Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
@@ -522,6 +688,7 @@ impl Cache {
         })?;
 
         trace!("cache hit!");
+        self.touch(tpe, id);
 
         Ok(Some(vec.into()))
     }
@@ -538,6 +705,29 @@ impl Cache {
     ///
     /// * If the file could not be written.
     pub fn write_bytes(&self, tpe: FileType, id: &Id, buf: &Bytes) -> RusticResult<()> {
+        trace!("cache writing tpe: {:?}, id: {}", &tpe, &id);
+
+        Self::write_file(&self.dir(tpe, id), &self.path(tpe, id), buf).map_err(|err| {
+            err.attach_context("tpe", tpe.to_string())
+                .attach_context("id", id.to_string())
+        })?;
+        self.add_size(tpe, buf.len() as u64);
+
+        Ok(())
+    }
+
+    /// Writes the given data to the given file using a temporary file.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory of the file; it is created if it doesn't exist.
+    /// * `filename` - The path of the file.
+    /// * `buf` - The data to write.
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be written.
+    fn write_file(dir: &Path, filename: &Path, buf: &[u8]) -> RusticResult<()> {
         fn write_local_file(filename: &Path, buf: &[u8]) -> RusticResult<()> {
             let mut file = fs::OpenOptions::new()
                 .create(true)
@@ -564,24 +754,19 @@ impl Cache {
             Ok(())
         }
 
-        trace!("cache writing tpe: {:?}, id: {}", &tpe, &id);
-
-        let dir = self.dir(tpe, id);
-
         // create parent directory if it does not exist
-        fs::create_dir_all(&dir).map_err(|err| {
+        fs::create_dir_all(dir).map_err(|err| {
             RusticError::with_source(
                 ErrorKind::InputOutput,
                 "Failed to create directories at `{path}`",
                 err,
             )
             .attach_context("path", dir.display().to_string())
-            .attach_context("tpe", tpe.to_string())
-            .attach_context("id", id.to_string())
         })?;
 
-        let filename = self.path(tpe, id);
-        let filename_tmp = dir.join(id.to_hex().to_string() + "-tmp-");
+        let mut filename_tmp = filename.as_os_str().to_owned();
+        filename_tmp.push("-tmp-");
+        let filename_tmp = PathBuf::from(filename_tmp);
         match write_local_file(&filename_tmp, buf) {
             Ok(file) => file,
             Err(err) => {
@@ -591,7 +776,7 @@ impl Cache {
             }
         }
         // rename temporary file to real file
-        fs::rename(&filename_tmp, &filename).map_err(|err| {
+        fs::rename(&filename_tmp, filename).map_err(|err| {
             RusticError::with_source(
                 ErrorKind::InputOutput,
                 "Failed to move `{path_tmp}` to `{path}`",
@@ -600,9 +785,7 @@ impl Cache {
             .attach_context("path_tmp", filename_tmp.display().to_string())
             .attach_context("path", filename.display().to_string())
             .ask_report()
-        })?;
-
-        Ok(())
+        })
     }
 
     /// Removes the given file.
@@ -618,6 +801,10 @@ impl Cache {
     pub fn remove(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
         trace!("cache writing tpe: {:?}, id: {}", &tpe, &id);
         let filename = self.path(tpe, id);
+        let size = match (self.size_limit, tpe) {
+            (Some(_), FileType::Pack) => fs::metadata(&filename).map_or(0, |meta| meta.len()),
+            _ => 0,
+        };
         fs::remove_file(&filename).map_err(|err| {
             RusticError::with_source(
                 ErrorKind::InputOutput,
@@ -628,7 +815,410 @@ impl Cache {
             .attach_context("tpe", tpe.to_string())
             .attach_context("id", id.to_string())
         })?;
+        _ = self
+            .pack_size
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_sub(size))
+            });
 
         Ok(())
     }
+
+    /// Returns the path to the cached header of the given pack.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the pack.
+    fn pack_header_path(&self, id: &Id) -> PathBuf {
+        let hex_id = id.to_hex();
+        self.path
+            .join(PACK_HEADER_DIRNAME)
+            .join(&hex_id[0..2])
+            .join(hex_id)
+    }
+
+    /// Reads the cached header of the given pack.
+    ///
+    /// The cached header is the end of the pack file, i.e. the encrypted pack header followed by the
+    /// pack trailer.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the pack.
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be read.
+    pub fn read_pack_header(&self, id: &Id) -> RusticResult<Option<Bytes>> {
+        trace!("cache reading pack header, id: {id}");
+
+        let path = self.pack_header_path(id);
+        match fs::read(&path) {
+            Ok(data) => {
+                trace!("cache hit!");
+                if self.size_limit.is_some() {
+                    // failing to update the time only affects the order of eviction
+                    _ = set_file_mtime(&path, FileTime::now());
+                }
+                Ok(Some(data.into()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(RusticError::with_source(
+                ErrorKind::InputOutput,
+                "Failed to read pack header at `{path}`",
+                err,
+            )
+            .attach_context("path", path.display().to_string())
+            .attach_context("id", id.to_string())),
+        }
+    }
+
+    /// Writes the header of the given pack to the cache.
+    ///
+    /// Cached pack headers are subject to the size limit, see [`Cache::with_size_limit`].
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the pack.
+    /// * `buf` - The end of the pack file, i.e. the encrypted pack header followed by the pack trailer.
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be written.
+    pub fn write_pack_header(&self, id: &Id, buf: &Bytes) -> RusticResult<()> {
+        trace!("cache writing pack header, id: {id}");
+
+        let path = self.pack_header_path(id);
+        let dir = path.parent().unwrap_or(&self.path);
+        Self::write_file(dir, &path, buf)
+            .map_err(|err| err.attach_context("id", id.to_string()))?;
+        self.add_limited_size(buf.len() as u64);
+
+        Ok(())
+    }
+
+    /// Removes the cached header of the given pack, if it exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the pack.
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be removed.
+    pub fn remove_pack_header(&self, id: &Id) -> RusticResult<()> {
+        let path = self.pack_header_path(id);
+        let size = fs::metadata(&path).map_or(0, |meta| meta.len());
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == IoErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(RusticError::with_source(
+                    ErrorKind::InputOutput,
+                    "Failed to remove pack header at `{path}`",
+                    err,
+                )
+                .attach_context("path", path.display().to_string())
+                .attach_context("id", id.to_string()));
+            }
+        }
+        if self.size_limit.is_some() {
+            _ = self
+                .pack_size
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                    Some(total.saturating_sub(size))
+                });
+        }
+
+        Ok(())
+    }
+
+    /// Acquires the lock with the given name in the cache directory.
+    ///
+    /// As the cache directory is specific to the repository, this prevents concurrent operations
+    /// on the same repository from the same host. The lock file is created atomically with the
+    /// information about its owner, so it is never observed empty or partially written.
+    /// An existing lock is only broken if its owner is provably no longer running on this host or if
+    /// it can't be parsed and hasn't been modified for an hour.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the lock.
+    ///
+    /// # Errors
+    ///
+    /// * If the lock is held by another process.
+    /// * If the lock file could not be read, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The [`CacheLock`] which releases the lock when dropped.
+    pub fn lock(&self, name: &str) -> RusticResult<CacheLock> {
+        let path = self.path.join(format!("{name}.lock"));
+        let info = CacheLockInfo {
+            hostname: gethostname().to_string_lossy().into_owned(),
+            pid: std::process::id(),
+            time: Timestamp::now(),
+        };
+        let data = serde_json::to_vec(&info).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::Internal,
+                "Failed to serialize lock information.",
+                err,
+            )
+        })?;
+
+        // write the lock information to a temporary file which is then linked to the lock file.
+        // Linking fails if the lock file exists, so the lock file always contains complete information.
+        let temp_path = path.with_extension(format!("lock.{}.tmp", info.pid));
+        fs::write(&temp_path, &data).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InputOutput,
+                "Failed to write lock file at `{path}`",
+                err,
+            )
+            .attach_context("path", temp_path.display().to_string())
+        })?;
+        let result = Self::link_lock(&path, &temp_path, &info.hostname);
+        if let Err(err) = fs::remove_file(&temp_path) {
+            warn!("failed to remove {}: {err}", temp_path.display());
+        }
+        result
+    }
+
+    /// Links the temporary lock file to the lock file, breaking stale locks.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the lock file.
+    /// * `temp_path` - The path of the temporary file containing the lock information.
+    /// * `hostname` - The name of the current host.
+    ///
+    /// # Errors
+    ///
+    /// * If the lock is held by another process.
+    /// * If the lock file could not be read, written or removed.
+    fn link_lock(path: &Path, temp_path: &Path, hostname: &str) -> RusticResult<CacheLock> {
+        loop {
+            match fs::hard_link(temp_path, path) {
+                Ok(()) => {
+                    debug!("acquired lock {}", path.display());
+                    return Ok(CacheLock {
+                        path: path.to_path_buf(),
+                    });
+                }
+                Err(err) if err.kind() == IoErrorKind::AlreadyExists => {
+                    let data = match fs::read(path) {
+                        Ok(data) => data,
+                        // the lock has been released in the meantime
+                        Err(err) if err.kind() == IoErrorKind::NotFound => continue,
+                        Err(err) => {
+                            return Err(RusticError::with_source(
+                                ErrorKind::InputOutput,
+                                "Failed to read lock file at `{path}`",
+                                err,
+                            )
+                            .attach_context("path", path.display().to_string()));
+                        }
+                    };
+                    match serde_json::from_slice::<CacheLockInfo>(&data) {
+                        Ok(existing) if !existing.is_stale(hostname) => {
+                            return Err(RusticError::new(
+                                ErrorKind::Repository,
+                                "The lock `{path}` is held by process {pid} on host `{hostname}` since {time}. Please wait until this process has finished.",
+                            )
+                            .attach_context("path", path.display().to_string())
+                            .attach_context("pid", existing.pid.to_string())
+                            .attach_context("hostname", existing.hostname)
+                            .attach_context("time", existing.time.to_string()));
+                        }
+                        Err(_) if !Self::is_expired(path) => {
+                            return Err(RusticError::new(
+                                ErrorKind::Repository,
+                                "The lock `{path}` is invalid, but too recent to be removed. If no other process is running, please remove it manually.",
+                            )
+                            .attach_context("path", path.display().to_string()));
+                        }
+                        _ => Self::break_lock(path, &data)?,
+                    }
+                }
+                Err(err) => {
+                    return Err(RusticError::with_source(
+                        ErrorKind::InputOutput,
+                        "Failed to create lock file at `{path}`",
+                        err,
+                    )
+                    .attach_context("path", path.display().to_string()));
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the lock file at `path` has not been modified for `INVALID_LOCK_MAX_AGE`.
+    fn is_expired(path: &Path) -> bool {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age >= INVALID_LOCK_MAX_AGE)
+    }
+
+    /// Removes the stale lock at `path` which contained `data`.
+    ///
+    /// The lock file is first moved away, so that a lock which has been acquired by another process in the
+    /// meantime is detected and restored instead of being removed.
+    ///
+    /// # Errors
+    ///
+    /// * If the lock file could not be moved or read.
+    fn break_lock(path: &Path, data: &[u8]) -> RusticResult<()> {
+        warn!("removing stale lock {}", path.display());
+        let stale_path = path.with_extension(format!("lock.{}.stale", std::process::id()));
+        match fs::rename(path, &stale_path) {
+            Ok(()) => {}
+            // another process may have removed the stale lock in the meantime
+            Err(err) if err.kind() == IoErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(RusticError::with_source(
+                    ErrorKind::InputOutput,
+                    "Failed to remove stale lock file at `{path}`",
+                    err,
+                )
+                .attach_context("path", path.display().to_string()));
+            }
+        }
+        if fs::read(&stale_path).is_ok_and(|moved| moved != data) {
+            // we moved the lock of another process which replaced the stale lock: restore it
+            _ = fs::hard_link(&stale_path, path);
+        }
+        if let Err(err) = fs::remove_file(&stale_path) {
+            warn!("failed to remove {}: {err}", stale_path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Minimum age of an invalid lock file in the cache directory before it is removed
+const INVALID_LOCK_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// The content of a lock file in the cache directory
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheLockInfo {
+    /// The host which holds the lock
+    hostname: String,
+    /// The process id of the process which holds the lock
+    pid: u32,
+    /// The time the lock has been acquired
+    time: Timestamp,
+}
+
+impl CacheLockInfo {
+    /// Returns `true` if the process holding the lock is known to be no longer running.
+    ///
+    /// Locks of other hosts are never considered stale, as their processes can't be checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the current host.
+    fn is_stale(&self, hostname: &str) -> bool {
+        if self.hostname != hostname {
+            return false;
+        }
+        #[cfg(not(windows))]
+        {
+            use nix::{errno::Errno, sys::signal::kill, unistd::Pid};
+
+            let Ok(pid) = i32::try_from(self.pid) else {
+                return true;
+            };
+            matches!(kill(Pid::from_raw(pid), None), Err(Errno::ESRCH))
+        }
+        #[cfg(windows)]
+        {
+            false
+        }
+    }
+}
+
+/// A lock in the cache directory, see [`Cache::lock`].
+///
+/// The lock is released when this is dropped.
+#[derive(Debug)]
+pub struct CacheLock {
+    /// The path of the lock file
+    path: PathBuf,
+}
+
+impl Drop for CacheLock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!("failed to remove lock {}: {err}", self.path.display());
+        } else {
+            debug!("released lock {}", self.path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(RepositoryId::default(), Some(dir.path().to_path_buf()))
+            .unwrap()
+            .with_size_limit(250)
+            .unwrap();
+        let data = Bytes::from(vec![0; 100]);
+        let ids = [Id::random(), Id::random(), Id::random()];
+
+        cache.write_bytes(FileType::Pack, &ids[0], &data).unwrap();
+        set_file_mtime(
+            cache.path(FileType::Pack, &ids[0]),
+            FileTime::from_unix_time(1000, 0),
+        )
+        .unwrap();
+        cache.write_bytes(FileType::Pack, &ids[1], &data).unwrap();
+        // index files are not subject to the size limit
+        cache.write_bytes(FileType::Index, &ids[0], &data).unwrap();
+        cache.write_bytes(FileType::Pack, &ids[2], &data).unwrap();
+
+        // the least recently used pack has been removed
+        assert!(!cache.path(FileType::Pack, &ids[0]).exists());
+        assert!(cache.path(FileType::Pack, &ids[1]).exists());
+        assert!(cache.path(FileType::Pack, &ids[2]).exists());
+        assert!(cache.path(FileType::Index, &ids[0]).exists());
+    }
+
+    #[test]
+    fn test_cache_pack_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(RepositoryId::default(), Some(dir.path().to_path_buf()))
+            .unwrap()
+            .with_size_limit(250)
+            .unwrap();
+        let data = Bytes::from(vec![0; 100]);
+        let ids = [Id::random(), Id::random()];
+
+        assert_eq!(cache.read_pack_header(&ids[0]).unwrap(), None);
+        cache.write_pack_header(&ids[0], &data).unwrap();
+        assert_eq!(cache.read_pack_header(&ids[0]).unwrap(), Some(data.clone()));
+        set_file_mtime(
+            cache.pack_header_path(&ids[0]),
+            FileTime::from_unix_time(1000, 0),
+        )
+        .unwrap();
+
+        // pack headers count towards the size limit
+        cache.write_bytes(FileType::Pack, &ids[1], &data).unwrap();
+        cache.write_pack_header(&ids[1], &data).unwrap();
+        assert_eq!(cache.read_pack_header(&ids[0]).unwrap(), None);
+        assert!(cache.path(FileType::Pack, &ids[1]).exists());
+
+        cache.remove_pack_header(&ids[1]).unwrap();
+        assert_eq!(cache.read_pack_header(&ids[1]).unwrap(), None);
+        // removing a missing pack header is fine
+        cache.remove_pack_header(&ids[1]).unwrap();
+    }
 }
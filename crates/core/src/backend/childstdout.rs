@@ -26,6 +26,8 @@ pub struct ChildStdoutSource {
     process: Mutex<Child>,
     /// the command which is called
     command: CommandInput,
+    /// The expected size of the output, used for progress reporting.
+    size: Option<u64>,
 }
 
 impl ChildStdoutSource {
@@ -50,9 +52,17 @@ impl ChildStdoutSource {
             path,
             process: Mutex::new(process),
             command: cmd.clone(),
+            size: None,
         })
     }
 
+    /// Set the expected size of the output, which is used to show the backup progress.
+    #[must_use]
+    pub fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
+    }
+
     /// Finishes the `ChildSource`
     ///
     /// # Errors
@@ -74,7 +84,7 @@ impl ReadSource for ChildStdoutSource {
     type Iter = Once<RusticResult<ReadSourceEntry<ChildStdout>>>;
 
     fn size(&self) -> RusticResult<Option<u64>> {
-        Ok(None)
+        Ok(self.size)
     }
 
     fn entries(&self) -> Self::Iter {
@@ -1,9 +1,11 @@
-use std::{num::NonZeroU32, sync::Arc};
+use std::{io, num::NonZeroU32, sync::Arc};
 
 use bytes::Bytes;
 use crossbeam_channel::{Receiver, bounded};
+use jiff::Timestamp;
+use log::trace;
 use rayon::{prelude::*, spawn};
-use zstd::stream::{copy_encode, decode_all, encode_all};
+use zstd::stream::{Encoder, decode_all};
 
 pub use zstd::compression_level_range;
 
@@ -14,7 +16,7 @@ use crate::{
     crypto::{CryptoKey, hasher::hash},
     error::{ErrorKind, RusticError, RusticResult},
     id::Id,
-    repofile::{RepoFile, RepoId},
+    repofile::{RepoFile, RepoId, packfile::PackLayout},
 };
 
 /// The maximum compression level allowed by zstd
@@ -23,6 +25,84 @@ pub fn max_compression_level() -> i32 {
     *compression_level_range().end()
 }
 
+/// The range of zstd window logs which can be set for compression.
+///
+/// The upper limit is the maximum window log zstd decoders accept by default.
+pub const ZSTD_WINDOW_LOG_RANGE: std::ops::RangeInclusive<u32> = 10..=27;
+
+/// The default number of pack files which are uploaded concurrently.
+pub const DEFAULT_UPLOAD_CONCURRENCY: usize = 2;
+
+/// Advanced parameters used for zstd compression
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ZstdParams {
+    /// Whether to enable long-distance matching
+    pub long_distance_matching: bool,
+    /// The window log to use; if not set, zstd chooses the window log depending on the compression level
+    pub window_log: Option<u32>,
+    /// Whether to store blobs uncompressed if a sample of them doesn't compress
+    pub skip_incompressible: bool,
+}
+
+impl ZstdParams {
+    /// The size of the prefix of blobs used to estimate whether they are compressible
+    const SAMPLE_SIZE: usize = 64 * 1024;
+
+    /// The minimum size of blobs for which the compressibility is estimated
+    const MIN_SAMPLE_SIZE: usize = 4 * 1024;
+
+    /// The compression level used to compress the sample
+    const SAMPLE_LEVEL: i32 = 1;
+
+    /// Estimate whether the given data is worth compressing.
+    ///
+    /// A prefix of the data is compressed with a fast compression level; the data is considered incompressible
+    /// if the sample shrinks by less than 3%, which is typically the case for already compressed data like
+    /// JPEG images or videos. Small data is always considered compressible.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to check
+    #[must_use]
+    pub fn is_compressible(data: &[u8]) -> bool {
+        if data.len() < Self::MIN_SAMPLE_SIZE {
+            return true;
+        }
+        let sample = &data[..data.len().min(Self::SAMPLE_SIZE)];
+        match zstd::bulk::compress(sample, Self::SAMPLE_LEVEL) {
+            Ok(compressed) => compressed.len() * 100 < sample.len() * 97,
+            // let the real compression handle the error
+            Err(_) => true,
+        }
+    }
+
+    /// Compress the given data and append it to `out`
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data to compress
+    /// * `out` - The buffer to append the compressed data to
+    /// * `level` - The compression level to use
+    ///
+    /// # Errors
+    ///
+    /// * If a parameter is not supported by zstd
+    /// * If the data could not be compressed
+    fn encode(self, mut data: &[u8], out: &mut Vec<u8>, level: i32) -> io::Result<()> {
+        let mut encoder = Encoder::new(out, level)?;
+        if self.long_distance_matching {
+            encoder.long_distance_matching(true)?;
+        }
+        if let Some(window_log) = self.window_log {
+            encoder.window_log(window_log)?;
+        }
+        _ = io::copy(&mut data, &mut encoder)?;
+        _ = encoder.finish()?;
+        Ok(())
+    }
+}
+
 /// A backend that can decrypt data.
 /// This is a trait that is implemented by all backends that can decrypt data.
 /// It is implemented for all backends that implement `DecryptWriteBackend` and `DecryptReadBackend`.
@@ -229,6 +309,29 @@ pub trait DecryptWriteBackend: WriteBackend + Clone + 'static {
     /// The hash of the written data.
     fn hash_write_full(&self, tpe: FileType, data: &[u8]) -> RusticResult<Id>;
 
+    /// Writes the given data to the backend, locks it until the given time and returns the id of the data.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file.
+    /// * `data` - The data to write.
+    /// * `until` - The end of the retention period.
+    ///
+    /// # Errors
+    ///
+    /// * If the data could not be written.
+    /// * If the backend does not support object locks.
+    ///
+    /// # Returns
+    ///
+    /// The hash of the written data.
+    fn hash_write_full_locked(
+        &self,
+        tpe: FileType,
+        data: &[u8],
+        until: Timestamp,
+    ) -> RusticResult<Id>;
+
     /// Process some blob data.
     /// This compresses and encrypts the data as requested
     ///
@@ -259,6 +362,9 @@ pub trait DecryptWriteBackend: WriteBackend + Clone + 'static {
     }
     /// Saves the given file.
     ///
+    /// If the file should be retained until a given time (see [`RepoFile::retain_until`]) and the backend
+    /// supports object locks, the file is locked until this time.
+    ///
     /// # Arguments
     ///
     /// * `file` - The file to save.
@@ -266,6 +372,7 @@ pub trait DecryptWriteBackend: WriteBackend + Clone + 'static {
     /// # Errors
     ///
     /// * If the file could not be serialized to json.
+    /// * If the file could not be written.
     ///
     /// # Returns
     ///
@@ -280,13 +387,21 @@ pub trait DecryptWriteBackend: WriteBackend + Clone + 'static {
             .ask_report()
         })?;
 
-        if F::ENCRYPTED {
-            self.hash_write_full(F::TYPE, &data)
-        } else {
-            let id = hash(&data);
-
-            self.write_bytes(F::TYPE, &id, false, data.into())?;
-            Ok(id)
+        let until = file.retain_until().filter(|_| self.supports_object_lock());
+
+        match (F::ENCRYPTED, until) {
+            (true, None) => self.hash_write_full(F::TYPE, &data),
+            (true, Some(until)) => self.hash_write_full_locked(F::TYPE, &data, until),
+            (false, until) => {
+                let id = hash(&data);
+                match until {
+                    None => self.write_bytes(F::TYPE, &id, false, data.into())?,
+                    Some(until) => {
+                        self.write_bytes_locked(F::TYPE, &id, false, data.into(), until)?;
+                    }
+                }
+                Ok(id)
+            }
         }
     }
 
@@ -372,7 +487,34 @@ pub trait DecryptWriteBackend: WriteBackend + Clone + 'static {
     ///
     /// * `zstd` - The compression level to use for zstd. TODO: What happens if this is None? What are defaults?
     fn set_zstd(&mut self, zstd: Option<i32>);
+
+    /// Sets the advanced parameters to use for zstd compression.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The zstd parameters to use.
+    fn set_zstd_params(&mut self, params: ZstdParams);
+
+    /// Returns the layout to use for writing pack files.
+    fn pack_layout(&self) -> PackLayout;
+
+    /// Sets the layout to use for writing pack files.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - The pack layout to use.
+    fn set_pack_layout(&mut self, layout: PackLayout);
     fn set_extra_verify(&mut self, extra_check: bool);
+
+    /// Returns the maximum number of pack files which are uploaded concurrently.
+    fn upload_concurrency(&self) -> usize;
+
+    /// Sets the maximum number of pack files which are uploaded concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `concurrency` - The number of concurrent uploads; `0` is treated as `1`.
+    fn set_upload_concurrency(&mut self, concurrency: usize);
 }
 
 /// A backend that can decrypt data.
@@ -388,8 +530,14 @@ pub struct DecryptBackend<C: CryptoKey> {
     key: C,
     /// The compression level to use for zstd.
     zstd: Option<i32>,
+    /// The advanced parameters to use for zstd compression.
+    zstd_params: ZstdParams,
+    /// The layout to use for writing pack files.
+    pack_layout: PackLayout,
     /// Whether to do an extra verification by decompressing and decrypting the data
     extra_verify: bool,
+    /// The maximum number of pack files which are uploaded concurrently
+    upload_concurrency: usize,
 }
 
 impl<C: CryptoKey> DecryptBackend<C> {
@@ -413,7 +561,10 @@ impl<C: CryptoKey> DecryptBackend<C> {
             key,
             // zstd and extra_verify are directly set, where needed.
             zstd: None,
+            zstd_params: ZstdParams::default(),
+            pack_layout: PackLayout::default(),
             extra_verify: false,
+            upload_concurrency: DEFAULT_UPLOAD_CONCURRENCY,
         }
     }
 
@@ -443,14 +594,16 @@ impl<C: CryptoKey> DecryptBackend<C> {
         let data_encrypted = match self.zstd {
             Some(level) => {
                 let mut out = vec![2_u8];
-                copy_encode(data, &mut out, level).map_err(|err| {
-                    RusticError::with_source(
-                        ErrorKind::Internal,
-                        "Compressing and appending data failed. The data may be corrupted.",
-                        err,
-                    )
-                    .attach_context("compression_level", level.to_string())
-                })?;
+                self.zstd_params
+                    .encode(data, &mut out, level)
+                    .map_err(|err| {
+                        RusticError::with_source(
+                            ErrorKind::Internal,
+                            "Compressing and appending data failed. The data may be corrupted.",
+                            err,
+                        )
+                        .attach_context("compression_level", level.to_string())
+                    })?;
 
                 self.key().encrypt_data(&out)?
             }
@@ -486,21 +639,31 @@ impl<C: CryptoKey> DecryptBackend<C> {
             .ask_report()
         })?;
 
-        let (data_encrypted, uncompressed_length) = match self.zstd {
+        // incompressible data is stored uncompressed if requested
+        let zstd = self
+            .zstd
+            .filter(|_| !self.zstd_params.skip_incompressible || ZstdParams::is_compressible(data));
+        if zstd.is_none() && self.zstd.is_some() {
+            trace!("storing incompressible data uncompressed");
+        }
+
+        let (data_encrypted, uncompressed_length) = match zstd {
             None => (self.key.encrypt_data(data)?, None),
             // compress if requested
-            Some(level) => (
-                self.key
-                    .encrypt_data(&encode_all(data, level).map_err(|err| {
+            Some(level) => {
+                let mut out = Vec::new();
+                self.zstd_params
+                    .encode(data, &mut out, level)
+                    .map_err(|err| {
                         RusticError::with_source(
                             ErrorKind::Internal,
                             "Failed to encode zstd compressed data. The data may be corrupted.",
                             err,
                         )
                         .attach_context("compression_level", level.to_string())
-                    })?)?,
-                NonZeroU32::new(data_len),
-            ),
+                    })?;
+                (self.key.encrypt_data(&out)?, NonZeroU32::new(data_len))
+            }
         };
         Ok((data_encrypted, data_len, uncompressed_length))
     }
@@ -563,6 +726,22 @@ impl<C: CryptoKey> DecryptWriteBackend for DecryptBackend<C> {
         Ok(id)
     }
 
+    fn hash_write_full_locked(
+        &self,
+        tpe: FileType,
+        data: &[u8],
+        until: Timestamp,
+    ) -> RusticResult<Id> {
+        let data_encrypted = self.encrypt_file(data)?;
+
+        self.very_file(&data_encrypted, data)?;
+
+        let id = hash(&data_encrypted);
+
+        self.write_bytes_locked(tpe, &id, false, data_encrypted.into(), until)?;
+        Ok(id)
+    }
+
     fn process_data(&self, data: &[u8]) -> RusticResult<(Vec<u8>, u32, Option<NonZeroU32>)> {
         let (data_encrypted, data_len, uncompressed_length) = self.encrypt_data(data)?;
 
@@ -580,6 +759,29 @@ impl<C: CryptoKey> DecryptWriteBackend for DecryptBackend<C> {
         self.zstd = zstd;
     }
 
+    /// Sets the advanced parameters to use for zstd compression.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The zstd parameters to use.
+    fn set_zstd_params(&mut self, params: ZstdParams) {
+        self.zstd_params = params;
+    }
+
+    /// Returns the layout to use for writing pack files.
+    fn pack_layout(&self) -> PackLayout {
+        self.pack_layout
+    }
+
+    /// Sets the layout to use for writing pack files.
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - The pack layout to use.
+    fn set_pack_layout(&mut self, layout: PackLayout) {
+        self.pack_layout = layout;
+    }
+
     /// Sets `extra_check`, i.e. whether to do an extra check after compressing/encrypting
     ///
     /// # Arguments
@@ -588,6 +790,20 @@ impl<C: CryptoKey> DecryptWriteBackend for DecryptBackend<C> {
     fn set_extra_verify(&mut self, extra_verify: bool) {
         self.extra_verify = extra_verify;
     }
+
+    /// Returns the maximum number of pack files which are uploaded concurrently.
+    fn upload_concurrency(&self) -> usize {
+        self.upload_concurrency
+    }
+
+    /// Sets the maximum number of pack files which are uploaded concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `concurrency` - The number of concurrent uploads; `0` is treated as `1`.
+    fn set_upload_concurrency(&mut self, concurrency: usize) {
+        self.upload_concurrency = concurrency.max(1);
+    }
 }
 
 impl<C: CryptoKey> DecryptReadBackend for DecryptBackend<C> {
@@ -643,6 +859,10 @@ impl<C: CryptoKey> ReadBackend for DecryptBackend<C> {
         self.be.list_with_size(tpe)
     }
 
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
     fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
         self.be.read_full(tpe, id)
     }
@@ -667,6 +887,10 @@ impl<C: CryptoKey> ReadBackend for DecryptBackend<C> {
         // Delegate to the underlying backend
         self.be.needs_warm_up()
     }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
 }
 
 impl<C: CryptoKey> WriteBackend for DecryptBackend<C> {
@@ -681,12 +905,32 @@ impl<C: CryptoKey> WriteBackend for DecryptBackend<C> {
     fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
         self.be.remove(tpe, id, cacheable)
     }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        self.be.write_bytes_locked(tpe, id, cacheable, buf, until)
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{backend::MockBackend, crypto::aespoly1305::Key};
     use anyhow::Result;
+    use rand::Rng;
 
     use super::*;
 
@@ -731,6 +975,50 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn encrypt_data_with_zstd_params() -> Result<()> {
+        let (mut be, _) = init();
+        be.set_extra_verify(true);
+        be.set_zstd_params(ZstdParams {
+            long_distance_matching: true,
+            window_log: Some(20),
+            skip_incompressible: false,
+        });
+        let data = b"{some redundant data} ".repeat(1000);
+        let (data_encrypted, _, ul) = be.encrypt_data(&data)?;
+        assert!(ul.is_some());
+        assert!(data_encrypted.len() < data.len());
+        be.very_data(&data_encrypted, ul, &data)?;
+
+        let data_encrypted = be.encrypt_file(&data)?;
+        assert_eq!(be.decrypt_file(&data_encrypted)?, data);
+        Ok(())
+    }
+
+    #[test]
+    fn encrypt_data_skip_incompressible() -> Result<()> {
+        let (mut be, _) = init();
+        be.set_zstd_params(ZstdParams {
+            skip_incompressible: true,
+            ..Default::default()
+        });
+
+        let text = b"{some redundant text} ".repeat(1000);
+        let (_, _, ul) = be.encrypt_data(&text)?;
+        assert!(ul.is_some());
+
+        let mut random = vec![0; 100_000];
+        rand::rng().fill_bytes(&mut random);
+        assert!(!ZstdParams::is_compressible(&random));
+        let (_, _, ul) = be.encrypt_data(&random)?;
+        assert!(ul.is_none());
+
+        // small data is always compressed
+        let (_, _, ul) = be.encrypt_data(&random[..100])?;
+        assert!(ul.is_some());
+        Ok(())
+    }
+
     #[test]
     fn verify_encrypt_data_ok() -> Result<()> {
         let (mut be, data) = init();
@@ -1,13 +1,15 @@
 use bytes::Bytes;
+use jiff::Timestamp;
 use zstd::decode_all;
 
 use crate::{
     backend::{
         FileType, ReadBackend, WriteBackend,
-        decrypt::{DecryptFullBackend, DecryptReadBackend, DecryptWriteBackend},
+        decrypt::{DecryptFullBackend, DecryptReadBackend, DecryptWriteBackend, ZstdParams},
     },
     error::{ErrorKind, RusticError, RusticResult},
     id::Id,
+    repofile::packfile::PackLayout,
 };
 
 /// A backend implementation that does not actually write to the backend.
@@ -89,6 +91,10 @@ impl<BE: DecryptFullBackend> ReadBackend for DryRunBackend<BE> {
         self.be.list_with_size(tpe)
     }
 
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
     fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
         self.be.read_full(tpe, id)
     }
@@ -113,6 +119,10 @@ impl<BE: DecryptFullBackend> ReadBackend for DryRunBackend<BE> {
         // Delegate to the underlying backend
         self.be.needs_warm_up()
     }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
 }
 
 impl<BE: DecryptFullBackend> DecryptWriteBackend for DryRunBackend<BE> {
@@ -130,6 +140,19 @@ impl<BE: DecryptFullBackend> DecryptWriteBackend for DryRunBackend<BE> {
         }
     }
 
+    fn hash_write_full_locked(
+        &self,
+        tpe: FileType,
+        data: &[u8],
+        until: Timestamp,
+    ) -> RusticResult<Id> {
+        if self.dry_run {
+            Ok(Id::default())
+        } else {
+            self.be.hash_write_full_locked(tpe, data, until)
+        }
+    }
+
     fn process_data(
         &self,
         data: &[u8],
@@ -143,11 +166,37 @@ impl<BE: DecryptFullBackend> DecryptWriteBackend for DryRunBackend<BE> {
         }
     }
 
+    fn set_zstd_params(&mut self, params: ZstdParams) {
+        if !self.dry_run {
+            self.be.set_zstd_params(params);
+        }
+    }
+
+    fn pack_layout(&self) -> PackLayout {
+        self.be.pack_layout()
+    }
+
+    fn set_pack_layout(&mut self, layout: PackLayout) {
+        if !self.dry_run {
+            self.be.set_pack_layout(layout);
+        }
+    }
+
     fn set_extra_verify(&mut self, extra_check: bool) {
         if !self.dry_run {
             self.be.set_extra_verify(extra_check);
         }
     }
+
+    fn upload_concurrency(&self) -> usize {
+        self.be.upload_concurrency()
+    }
+
+    fn set_upload_concurrency(&mut self, concurrency: usize) {
+        if !self.dry_run {
+            self.be.set_upload_concurrency(concurrency);
+        }
+    }
 }
 
 impl<BE: DecryptFullBackend> WriteBackend for DryRunBackend<BE> {
@@ -174,4 +223,27 @@ impl<BE: DecryptFullBackend> WriteBackend for DryRunBackend<BE> {
             self.be.remove(tpe, id, cacheable)
         }
     }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        if self.dry_run {
+            Ok(())
+        } else {
+            self.be.write_bytes_locked(tpe, id, cacheable, buf, until)
+        }
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
 }
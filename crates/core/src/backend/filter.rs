@@ -0,0 +1,185 @@
+use std::{ffi::OsString, path::Path};
+
+use ignore::{WalkBuilder, overrides::Override};
+use log::warn;
+
+use crate::{Excludes, backend::ignore::LocalSourceFilterOptions, error::RusticResult};
+
+/// A [`BackupFilter`] decides which entries of a local source are backed up.
+///
+/// It combines the glob rules of [`Excludes`] with the [`LocalSourceFilterOptions`] and is shared by
+/// the `backup` command and [`Repository::diff_with_local_filtered`](crate::Repository::diff_with_local_filtered),
+/// so that a diff with a local directory reports exactly what a backup would save.
+///
+/// The rules are applied with the following precedence:
+///
+/// 1. Directories crossing a filesystem boundary (`one_file_system`), containing one of the
+///    `exclude_if_present` marker files or having one of the `exclude_if_xattr` attributes are excluded
+///    including their contents.
+/// 2. Globs are matched in the given order and the last matching glob wins: A glob starting with `!`
+///    excludes, all other globs include. If include globs are given, files not matched by any glob are
+///    excluded. Case-insensitive globs (`iglobs`) are matched after all case-sensitive globs.
+/// 3. Files larger than `exclude_larger_than` are excluded.
+/// 4. Ignore files (`git_ignore` and `custom_ignorefiles`) are applied to entries not matched by any glob.
+#[derive(Debug, Clone)]
+pub struct BackupFilter {
+    /// The compiled globs
+    overrides: Override,
+    /// The filter options
+    filter_opts: LocalSourceFilterOptions,
+}
+
+impl BackupFilter {
+    /// Creates a new `BackupFilter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `excludes` - The glob rules to use
+    /// * `filter_opts` - The filter options to use
+    ///
+    /// # Errors
+    ///
+    /// * If a glob pattern is invalid.
+    /// * If a glob file could not be read.
+    pub fn new(excludes: &Excludes, filter_opts: &LocalSourceFilterOptions) -> RusticResult<Self> {
+        Ok(Self {
+            overrides: excludes.as_override()?,
+            filter_opts: filter_opts.clone(),
+        })
+    }
+
+    /// Whether the given path is excluded by the glob rules or by a marker file.
+    ///
+    /// Ignore files, size limits and filesystem boundaries are only checked while walking the local
+    /// source and are not respected here.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to check
+    /// * `is_dir` - Whether the path is a directory
+    #[must_use]
+    pub fn is_excluded(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dir
+            && self
+                .filter_opts
+                .exclude_if_present
+                .iter()
+                .any(|file| path.join(file).exists())
+        {
+            return true;
+        }
+        self.overrides.matched(path, is_dir).is_ignore()
+    }
+
+    /// Apply the filter to a walk of a local source
+    ///
+    /// # Arguments
+    ///
+    /// * `walk_builder` - The builder of the walk to filter
+    pub(crate) fn apply(&self, walk_builder: &mut WalkBuilder) {
+        let filter_opts = &self.filter_opts;
+        for file in &filter_opts.custom_ignorefiles {
+            _ = walk_builder.add_custom_ignore_filename(file);
+        }
+
+        _ = walk_builder
+            .follow_links(filter_opts.follow_symlinks)
+            .max_depth(filter_opts.max_depth)
+            .hidden(false)
+            .ignore(false)
+            .git_ignore(filter_opts.git_ignore)
+            .git_exclude(filter_opts.git_ignore)
+            .require_git(!filter_opts.no_require_git)
+            .sort_by_file_path(Path::cmp)
+            .same_file_system(filter_opts.one_file_system)
+            .max_filesize(filter_opts.exclude_larger_than.map(|s| s.as_u64()))
+            .overrides(self.overrides.clone());
+
+        let exclude_if_present = filter_opts.exclude_if_present.clone();
+        let exclude_if_xattr: Vec<OsString> = filter_opts
+            .exclude_if_xattr
+            .iter()
+            .map(OsString::from)
+            .collect();
+
+        if !exclude_if_xattr.is_empty() {
+            #[cfg(any(windows, target_os = "openbsd"))]
+            warn!("exclude-if-xattr is not supported on this platform");
+            #[cfg(not(any(windows, target_os = "openbsd")))]
+            if !xattr::SUPPORTED_PLATFORM {
+                warn!("exclude-if-xattr is not supported on this platform");
+            }
+        }
+
+        let needs_entry_filter = !exclude_if_present.is_empty() || !exclude_if_xattr.is_empty();
+
+        if needs_entry_filter {
+            _ = walk_builder.filter_entry(move |entry| {
+                // exclude-if-present: skip directories containing a marker file
+                if !exclude_if_present.is_empty()
+                    && let Some(tpe) = entry.file_type()
+                    && tpe.is_dir()
+                    && exclude_if_present
+                        .iter()
+                        .any(|file| entry.path().join(file).exists())
+                {
+                    return false;
+                }
+
+                // exclude-if-xattr: skip entries that have a matching xattr
+                #[cfg(not(any(windows, target_os = "openbsd")))]
+                if xattr::SUPPORTED_PLATFORM && !exclude_if_xattr.is_empty() {
+                    match xattr::list(entry.path()) {
+                        Ok(mut attrs) => {
+                            if attrs.any(|attr| exclude_if_xattr.contains(&attr)) {
+                                return false;
+                            }
+                        }
+                        Err(err) => {
+                            warn!(
+                                "Error reading xattrs for {}, not excluding: {err}",
+                                entry.path().display()
+                            );
+                        }
+                    }
+                }
+
+                true
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_precedence() {
+        let excludes = Excludes::default().globs(vec![
+            "!*.log".to_string(),
+            "*.log".to_string(),
+            "!debug.log".to_string(),
+        ]);
+        let filter = BackupFilter::new(&excludes, &LocalSourceFilterOptions::default()).unwrap();
+        assert!(!filter.is_excluded(Path::new("app.log"), false));
+        assert!(filter.is_excluded(Path::new("debug.log"), false));
+        // with an include glob, other files are excluded
+        assert!(filter.is_excluded(Path::new("app.txt"), false));
+
+        let filter =
+            BackupFilter::new(&Excludes::default(), &LocalSourceFilterOptions::default()).unwrap();
+        assert!(!filter.is_excluded(Path::new("app.txt"), false));
+    }
+
+    #[test]
+    fn test_exclude_if_present() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".nobackup"), "").unwrap();
+        let filter_opts =
+            LocalSourceFilterOptions::default().exclude_if_present(vec![".nobackup".to_string()]);
+        let filter = BackupFilter::new(&Excludes::default(), &filter_opts).unwrap();
+        assert!(filter.is_excluded(dir.path(), true));
+        assert!(!filter.is_excluded(&dir.path().join(".nobackup"), false));
+    }
+}
@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use bytes::Bytes;
+use jiff::Timestamp;
 
 use crate::{
     backend::{FileType, ReadBackend, WriteBackend},
@@ -49,6 +50,10 @@ impl ReadBackend for HotColdBackend {
         self.be.list_with_size(tpe)
     }
 
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
     fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
         self.be_hot.read_full(tpe, id)
     }
@@ -72,6 +77,10 @@ impl ReadBackend for HotColdBackend {
         self.be.needs_warm_up()
     }
 
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe) && self.be_hot.supports_file_type(tpe)
+    }
+
     fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
         self.be.warm_up(tpe, id)
     }
@@ -102,4 +111,27 @@ impl WriteBackend for HotColdBackend {
         }
         Ok(())
     }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        // only the cold backend holds the complete repository and needs to lock files
+        if tpe != FileType::Config && (cacheable || tpe != FileType::Pack) {
+            self.be_hot.write_bytes(tpe, id, cacheable, buf.clone())?;
+        }
+        self.be.write_bytes_locked(tpe, id, cacheable, buf, until)
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
 }
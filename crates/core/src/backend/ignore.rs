@@ -2,7 +2,6 @@ pub mod mapper;
 pub use mapper::LocalSourceSaveOptions;
 
 use std::{
-    ffi::OsString,
     fs::File,
     path::{Path, PathBuf},
 };
@@ -18,7 +17,7 @@ use std::num::TryFromIntError;
 
 use crate::{
     Excludes,
-    backend::{ReadSource, ReadSourceEntry, ReadSourceOpen},
+    backend::{ReadSource, ReadSourceEntry, ReadSourceOpen, filter::BackupFilter},
     error::{ErrorKind, RusticError, RusticResult},
 };
 
@@ -107,6 +106,17 @@ pub struct LocalSourceFilterOptions {
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
     pub exclude_larger_than: Option<ByteSize>,
+
+    /// Follow symbolic links and back up their targets instead of the links.
+    /// Links pointing to one of their parent directories are not followed to avoid endless loops.
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
+    pub follow_symlinks: bool,
+
+    /// Maximum depth of directories to descend into, e.g. to limit deeply nested symlink farms
+    #[cfg_attr(feature = "clap", clap(long, value_name = "DEPTH"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub max_depth: Option<usize>,
 }
 
 impl LocalSource {
@@ -126,93 +136,42 @@ impl LocalSource {
     ///
     /// * If the a glob pattern could not be added to the override builder.
     /// * If a glob file could not be read.
-    #[allow(clippy::too_many_lines)]
     pub fn new(
         save_opts: LocalSourceSaveOptions,
         excludes: &Excludes,
         filter_opts: &LocalSourceFilterOptions,
         backup_paths: &[impl AsRef<Path>],
     ) -> RusticResult<Self> {
+        let filter = BackupFilter::new(excludes, filter_opts)?;
+        Ok(Self::with_filter(save_opts, &filter, backup_paths))
+    }
+
+    /// Create a local source from [`LocalSourceSaveOptions`], a [`BackupFilter`] and backup path(s).
+    ///
+    /// # Arguments
+    ///
+    /// * `save_opts` - The [`LocalSourceSaveOptions`] to use.
+    /// * `filter` - The [`BackupFilter`] to use.
+    /// * `backup_paths` - The backup path(s) to use.
+    ///
+    /// # Returns
+    ///
+    /// The created local source.
+    pub fn with_filter(
+        save_opts: LocalSourceSaveOptions,
+        filter: &BackupFilter,
+        backup_paths: &[impl AsRef<Path>],
+    ) -> Self {
         let mut walk_builder = WalkBuilder::new(&backup_paths[0]);
 
         for path in &backup_paths[1..] {
             _ = walk_builder.add(path);
         }
-
-        let overrides = excludes.as_override()?;
-
-        for file in &filter_opts.custom_ignorefiles {
-            _ = walk_builder.add_custom_ignore_filename(file);
-        }
-
-        _ = walk_builder
-            .follow_links(false)
-            .hidden(false)
-            .ignore(false)
-            .git_ignore(filter_opts.git_ignore)
-            .git_exclude(filter_opts.git_ignore)
-            .require_git(!filter_opts.no_require_git)
-            .sort_by_file_path(Path::cmp)
-            .same_file_system(filter_opts.one_file_system)
-            .max_filesize(filter_opts.exclude_larger_than.map(|s| s.as_u64()))
-            .overrides(overrides);
-
-        let exclude_if_present = filter_opts.exclude_if_present.clone();
-        let exclude_if_xattr: Vec<OsString> = filter_opts
-            .exclude_if_xattr
-            .iter()
-            .map(OsString::from)
-            .collect();
-
-        if !exclude_if_xattr.is_empty() {
-            #[cfg(any(windows, target_os = "openbsd"))]
-            warn!("exclude-if-xattr is not supported on this platform");
-            #[cfg(not(any(windows, target_os = "openbsd")))]
-            if !xattr::SUPPORTED_PLATFORM {
-                warn!("exclude-if-xattr is not supported on this platform");
-            }
-        }
-
-        let needs_entry_filter = !exclude_if_present.is_empty() || !exclude_if_xattr.is_empty();
-
-        if needs_entry_filter {
-            _ = walk_builder.filter_entry(move |entry| {
-                // exclude-if-present: skip directories containing a marker file
-                if !exclude_if_present.is_empty()
-                    && let Some(tpe) = entry.file_type()
-                    && tpe.is_dir()
-                    && exclude_if_present
-                        .iter()
-                        .any(|file| entry.path().join(file).exists())
-                {
-                    return false;
-                }
-
-                // exclude-if-xattr: skip entries that have a matching xattr
-                #[cfg(not(any(windows, target_os = "openbsd")))]
-                if xattr::SUPPORTED_PLATFORM && !exclude_if_xattr.is_empty() {
-                    match xattr::list(entry.path()) {
-                        Ok(mut attrs) => {
-                            if attrs.any(|attr| exclude_if_xattr.contains(&attr)) {
-                                return false;
-                            }
-                        }
-                        Err(err) => {
-                            warn!(
-                                "Error reading xattrs for {}, not excluding: {err}",
-                                entry.path().display()
-                            );
-                        }
-                    }
-                }
-
-                true
-            });
-        }
+        filter.apply(&mut walk_builder);
 
         let builder = walk_builder;
 
-        Ok(Self { builder, save_opts })
+        Self { builder, save_opts }
     }
 }
 
@@ -306,6 +265,14 @@ impl Iterator for LocalSourceWalker {
         .map(|e| {
             self.save_opts
                 .map_entry(e.map_err(|err| {
+                    if let Some((child, ancestor)) = symlink_loop(&err) {
+                        return RusticError::new(
+                            ErrorKind::InputOutput,
+                            "Not following symlink `{path}` which points to its parent directory `{ancestor}`.",
+                        )
+                        .attach_context("path", child.display().to_string())
+                        .attach_context("ancestor", ancestor.display().to_string());
+                    }
                     RusticError::with_source(
                         ErrorKind::Internal,
                         "Failed to get next entry from walk iterator.",
@@ -324,3 +291,18 @@ impl Iterator for LocalSourceWalker {
         })
     }
 }
+
+/// Returns the symlink and the ancestor it points to if the error is a loop detected when following symlinks
+///
+/// # Arguments
+///
+/// * `err` - The error returned by the walk iterator
+fn symlink_loop(err: &ignore::Error) -> Option<(&Path, &Path)> {
+    match err {
+        ignore::Error::Loop { ancestor, child } => Some((child, ancestor)),
+        ignore::Error::WithPath { err, .. }
+        | ignore::Error::WithDepth { err, .. }
+        | ignore::Error::WithLineNumber { err, .. } => symlink_loop(err),
+        _ => None,
+    }
+}
@@ -112,6 +112,7 @@ impl LocalSourceSaveOptions {
         };
         let extended_attributes = self.set_xattrs.unwrap_or_default().map_or_else(xattr);
         let (mode, inode, links) = Self::nix_infos(&m);
+        let sparse = Self::sparse(&m);
 
         let meta = Metadata {
             mode,
@@ -127,6 +128,7 @@ impl LocalSourceSaveOptions {
             size,
             links,
             extended_attributes,
+            sparse,
         };
 
         let node = self.to_node(&entry, &m, meta)?;
@@ -191,6 +193,11 @@ impl LocalSourceSaveOptions {
         (Some(mode), inode, links)
     }
 
+    fn sparse(m: &std::fs::Metadata) -> bool {
+        // `blocks` is given in units of 512 bytes
+        m.is_file() && m.blocks().saturating_mul(512) < m.len()
+    }
+
     /// List [`ExtendedAttribute`] for a [`Node`] located at `path`
     ///
     /// # Argument
@@ -267,6 +274,10 @@ impl LocalSourceSaveOptions {
         (None, 0, 0)
     }
 
+    fn sparse(_m: &std::fs::Metadata) -> bool {
+        false
+    }
+
     fn xattrs(_path: &Path) -> IgnoreResult<Vec<ExtendedAttribute>> {
         Ok(Vec::new())
     }
@@ -607,6 +607,47 @@ impl LocalDestination {
         Ok(())
     }
 
+    /// Create `item` (relative to the base path) as a sparse file with the given length
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item to create
+    /// * `size` - The size of the file
+    ///
+    /// # Errors
+    ///
+    /// * If the file does not have a parent.
+    /// * If the directory could not be created.
+    /// * If the file could not be opened.
+    /// * If the length of the file could not be set.
+    ///
+    /// # Notes
+    ///
+    /// If the file exists, its contents are discarded. The file is then extended to the
+    /// given length without writing any data, so that filesystems supporting sparse files
+    /// don't allocate any blocks for it.
+    pub(crate) fn create_sparse(
+        &self,
+        item: impl AsRef<Path>,
+        size: u64,
+    ) -> LocalDestinationResult<()> {
+        let filename = self.path(item);
+        let dir = filename
+            .parent()
+            .ok_or_else(|| LocalDestinationErrorKind::FileDoesNotHaveParent(filename.clone()))?;
+        fs::create_dir_all(dir).map_err(LocalDestinationErrorKind::DirectoryCreationFailed)?;
+
+        OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(filename)
+            .map_err(LocalDestinationErrorKind::OpeningFileFailed)?
+            .set_len(size)
+            .map_err(LocalDestinationErrorKind::SettingFileLengthFailed)?;
+        Ok(())
+    }
+
     #[cfg(windows)]
     // TODO: Windows support
     /// Create a special file (relative to the base path)
@@ -843,7 +884,12 @@ impl LocalDestination {
     ///
     /// * If the new hardlink does not have a parent directory.
     /// * If the directory could not be created.
+    /// * If an existing file could not be removed.
     /// * If the hardlink could not be created.
+    ///
+    /// # Notes
+    ///
+    /// If `item` already exists, it is replaced by the hardlink.
     pub(crate) fn hard_link(
         &self,
         source_item: impl AsRef<Path>,
@@ -855,6 +901,9 @@ impl LocalDestination {
             .parent()
             .ok_or_else(|| LocalDestinationErrorKind::FileDoesNotHaveParent(filename.clone()))?;
         fs::create_dir_all(dir).map_err(LocalDestinationErrorKind::DirectoryCreationFailed)?;
+        if fs::symlink_metadata(&filename).is_ok() {
+            self.remove_file(&filename)?;
+        }
         fs::hard_link(&source_path, &filename).map_err(|err| {
             LocalDestinationErrorKind::HardLinkingFailed {
                 source_path,
@@ -864,4 +913,46 @@ impl LocalDestination {
         })?;
         Ok(())
     }
+
+    #[cfg(not(windows))]
+    /// Check if `item` is already a hardlink to `source_item`, both relative to the base path.
+    ///
+    /// # Arguments
+    ///
+    /// * `source_item` - The file the hardlink should point to
+    /// * `item` - The path to check
+    ///
+    /// # Returns
+    ///
+    /// `true` if both paths exist and refer to the same inode on the same device.
+    pub(crate) fn is_hard_link(
+        &self,
+        source_item: impl AsRef<Path>,
+        item: impl AsRef<Path>,
+    ) -> bool {
+        use std::os::unix::fs::MetadataExt;
+
+        match (
+            fs::symlink_metadata(self.path(source_item)),
+            fs::symlink_metadata(self.path(item)),
+        ) {
+            (Ok(source), Ok(meta)) => source.dev() == meta.dev() && source.ino() == meta.ino(),
+            _ => false,
+        }
+    }
+
+    #[cfg(windows)]
+    /// Check if `item` is already a hardlink to `source_item`, both relative to the base path.
+    ///
+    /// # Note
+    ///
+    /// This is always `false` on windows.
+    #[allow(clippy::unused_self)]
+    pub(crate) fn is_hard_link(
+        &self,
+        _source_item: impl AsRef<Path>,
+        _item: impl AsRef<Path>,
+    ) -> bool {
+        false
+    }
 }
@@ -0,0 +1,269 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    vec,
+};
+
+use ignore::WalkBuilder;
+use log::warn;
+
+use crate::{
+    backend::{
+        ReadSource, ReadSourceEntry,
+        ignore::{LocalSourceSaveOptions, OpenFile},
+    },
+    error::{ErrorKind, RusticError, RusticResult},
+};
+
+/// An entry of a backup manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ManifestEntry {
+    /// The path to back up
+    pub path: PathBuf,
+    /// The expected size of the file, if known
+    pub size: Option<u64>,
+}
+
+/// A [`ManifestSource`] is a source which backs up exactly the paths given in a manifest.
+///
+/// In contrast to a [`LocalSource`](crate::LocalSource), directories are not walked; only the listed
+/// paths are backed up and missing parent directories are added to the snapshot automatically. This
+/// allows to integrate with change-tracking systems which already know the files to back up.
+///
+/// Exclude and filter options are not applied to the listed paths.
+#[derive(Debug, Clone)]
+pub struct ManifestSource {
+    /// The entries to back up, sorted by path
+    entries: Vec<ManifestEntry>,
+    /// The save options to use.
+    save_opts: LocalSourceSaveOptions,
+}
+
+impl ManifestSource {
+    /// Create a manifest source from the given entries.
+    ///
+    /// # Arguments
+    ///
+    /// * `save_opts` - The [`LocalSourceSaveOptions`] to use.
+    /// * `entries` - The entries to back up; duplicate paths are only backed up once.
+    pub fn new(save_opts: LocalSourceSaveOptions, mut entries: Vec<ManifestEntry>) -> Self {
+        // the archiver needs the entries in the order of a directory walk
+        entries.sort_by(|e1, e2| e1.path.cmp(&e2.path));
+        entries.dedup_by(|e1, e2| e1.path == e2.path);
+        Self { entries, save_opts }
+    }
+
+    /// Create a manifest source reading the manifest from a reader.
+    ///
+    /// The manifest contains one path per line, optionally followed by a tab and the expected size of the
+    /// file. Empty lines and lines starting with `#` are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `save_opts` - The [`LocalSourceSaveOptions`] to use.
+    /// * `reader` - The reader to read the manifest from.
+    ///
+    /// # Errors
+    ///
+    /// * If the manifest could not be read.
+    /// * If an expected size is not a valid number.
+    pub fn from_reader(save_opts: LocalSourceSaveOptions, reader: impl Read) -> RusticResult<Self> {
+        let mut entries = Vec::new();
+        for (number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line.map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::InputOutput,
+                    "Failed to read line {line} of the manifest.",
+                    err,
+                )
+                .attach_context("line", (number + 1).to_string())
+            })?;
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (path, size) = match line.rsplit_once('\t') {
+                Some((path, size)) => {
+                    let size = size.trim().parse().map_err(|err| {
+                        RusticError::with_source(
+                            ErrorKind::InvalidInput,
+                            "Invalid size `{size}` in line {line} of the manifest. Please use `PATH<TAB>SIZE`.",
+                            err,
+                        )
+                        .attach_context("size", size)
+                        .attach_context("line", (number + 1).to_string())
+                    })?;
+                    (path, Some(size))
+                }
+                None => (line.as_str(), None),
+            };
+            entries.push(ManifestEntry {
+                path: PathBuf::from(path),
+                size,
+            });
+        }
+        Ok(Self::new(save_opts, entries))
+    }
+
+    /// Create a manifest source reading the manifest from a file, see [`ManifestSource::from_reader`].
+    ///
+    /// # Arguments
+    ///
+    /// * `save_opts` - The [`LocalSourceSaveOptions`] to use.
+    /// * `path` - The path of the manifest file.
+    ///
+    /// # Errors
+    ///
+    /// * If the manifest file could not be opened or read.
+    /// * If an expected size is not a valid number.
+    pub fn from_file(
+        save_opts: LocalSourceSaveOptions,
+        path: impl AsRef<Path>,
+    ) -> RusticResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InputOutput,
+                "Failed to open manifest file `{path}`. Please make sure the file exists and is readable.",
+                err,
+            )
+            .attach_context("path", path.display().to_string())
+        })?;
+        Self::from_reader(save_opts, file)
+    }
+
+    /// The paths contained in the manifest, sorted
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+}
+
+impl ReadSource for ManifestSource {
+    type Open = OpenFile;
+    type Iter = ManifestSourceIter;
+
+    /// Get the size of the manifest source.
+    ///
+    /// Expected sizes given in the manifest are used; other files are queried.
+    ///
+    /// # Returns
+    ///
+    /// The total size of the listed files.
+    fn size(&self) -> RusticResult<Option<u64>> {
+        let size = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                entry.size.or_else(|| {
+                    std::fs::symlink_metadata(&entry.path)
+                        .ok()
+                        .filter(std::fs::Metadata::is_file)
+                        .map(|m| m.len())
+                })
+            })
+            .sum();
+        Ok(Some(size))
+    }
+
+    /// Iterate over the entries of the manifest source.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the entries of the manifest source.
+    fn entries(&self) -> Self::Iter {
+        ManifestSourceIter {
+            entries: self.entries.clone().into_iter(),
+            save_opts: self.save_opts,
+        }
+    }
+}
+
+/// Iterator over the entries of a [`ManifestSource`]
+#[derive(Debug)]
+pub struct ManifestSourceIter {
+    /// The remaining entries
+    entries: vec::IntoIter<ManifestEntry>,
+    /// The save options to use.
+    save_opts: LocalSourceSaveOptions,
+}
+
+impl Iterator for ManifestSourceIter {
+    type Item = RusticResult<ReadSourceEntry<OpenFile>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ManifestEntry { path, size } = self.entries.next()?;
+        // a walk with depth 0 only yields the path itself
+        let entry = WalkBuilder::new(&path)
+            .standard_filters(false)
+            .max_depth(Some(0))
+            .build()
+            .next()?
+            .map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::InputOutput,
+                    "Failed to read `{path}` listed in the manifest.",
+                    err,
+                )
+                .attach_context("path", path.display().to_string())
+            })
+            .and_then(|entry| {
+                self.save_opts.map_entry(entry).map_err(|err| {
+                    RusticError::with_source(
+                        ErrorKind::Internal,
+                        "Failed to map Directory entry to ReadSourceEntry.",
+                        err,
+                    )
+                    .ask_report()
+                })
+            })
+            .inspect(|entry| {
+                if let Some(size) = size
+                    && entry.node.is_file()
+                    && entry.node.meta.size != size
+                {
+                    warn!(
+                        "{}: size {} differs from the size {size} given in the manifest.",
+                        path.display(),
+                        entry.node.meta.size
+                    );
+                }
+            });
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_reader() {
+        let manifest = "b/file\t42\n# comment\n\na/file\nb/file\t42\n";
+        let source =
+            ManifestSource::from_reader(LocalSourceSaveOptions::default(), manifest.as_bytes())
+                .unwrap();
+        assert_eq!(
+            source.entries,
+            vec![
+                ManifestEntry {
+                    path: "a/file".into(),
+                    size: None
+                },
+                ManifestEntry {
+                    path: "b/file".into(),
+                    size: Some(42)
+                },
+            ]
+        );
+
+        let manifest = "a/file\tsize\n";
+        assert!(
+            ManifestSource::from_reader(LocalSourceSaveOptions::default(), manifest.as_bytes())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::{io::Cursor, path::PathBuf, vec};
+
+use bytes::Bytes;
+
+use crate::{
+    backend::{
+        ReadSource, ReadSourceEntry,
+        node::{Metadata, NodeType},
+    },
+    error::RusticResult,
+};
+
+/// A [`MemorySource`] is a source of files whose contents are held in memory.
+///
+/// This allows to back up generated data, e.g. database dumps or exports from other systems, directly
+/// into a snapshot without staging it to disk.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySource {
+    /// The files, sorted by path
+    files: Vec<(PathBuf, Bytes, Metadata)>,
+}
+
+impl MemorySource {
+    /// Creates a new empty `MemorySource`.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file to this [`MemorySource`]; an already added file with the same path is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file
+    /// * `data` - The contents of the file
+    /// * `meta` - The metadata of the file; the size is set from `data`
+    ///
+    /// # Returns
+    ///
+    /// The modified [`MemorySource`]
+    #[must_use]
+    pub fn add_file(
+        mut self,
+        path: impl Into<PathBuf>,
+        data: impl Into<Bytes>,
+        mut meta: Metadata,
+    ) -> Self {
+        let (path, data) = (path.into(), data.into());
+        meta.size = data.len() as u64;
+        match self.files.binary_search_by(|(p, _, _)| p.cmp(&path)) {
+            Ok(idx) => self.files[idx] = (path, data, meta),
+            Err(idx) => self.files.insert(idx, (path, data, meta)),
+        }
+        self
+    }
+}
+
+impl ReadSource for MemorySource {
+    type Open = Cursor<Bytes>;
+    type Iter = vec::IntoIter<RusticResult<ReadSourceEntry<Cursor<Bytes>>>>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        Ok(Some(self.files.iter().map(|(_, _, meta)| meta.size).sum()))
+    }
+
+    fn entries(&self) -> Self::Iter {
+        self.files
+            .iter()
+            .map(|(path, data, meta)| {
+                let open = Some(Cursor::new(data.clone()));
+                ReadSourceEntry::new(path.clone(), NodeType::File, meta.clone(), open)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
@@ -0,0 +1,488 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use enum_map::{Enum, EnumMap};
+use jiff::Timestamp;
+
+use crate::{
+    backend::{FileType, ReadBackend, WriteBackend},
+    error::RusticResult,
+    id::Id,
+};
+
+/// The kind of an operation issued to a backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Enum, derive_more::Display)]
+#[non_exhaustive]
+pub enum BackendOperation {
+    /// Listing files
+    #[display("list")]
+    List,
+    /// Querying the metadata of a file
+    #[display("stat")]
+    Stat,
+    /// Reading a complete or partial file
+    #[display("read")]
+    Read,
+    /// Writing a file
+    #[display("write")]
+    Write,
+    /// Removing a file
+    #[display("remove")]
+    Remove,
+    /// Warming up a file
+    #[display("warm-up")]
+    WarmUp,
+    /// Creating the backend
+    #[display("create")]
+    Create,
+}
+
+/// Instrumentation hooks which are called for all operations issued to a backend.
+///
+/// Use a [`MetricsBackend`] to call the hooks for all operations of a backend and a
+/// [`RetryBackend`](crate::RetryBackend) to report retries. Implementations are called concurrently from
+/// many threads and should be fast, e.g. only update counters.
+pub trait BackendMetrics: Debug + Send + Sync {
+    /// Record a finished operation.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The kind of the operation
+    /// * `tpe` - The type of the file(s)
+    /// * `bytes` - The number of read or written bytes
+    /// * `latency` - The time the operation took
+    /// * `success` - Whether the operation succeeded
+    fn record_operation(
+        &self,
+        op: BackendOperation,
+        tpe: FileType,
+        bytes: u64,
+        latency: Duration,
+        success: bool,
+    );
+
+    /// Record a successful read of a complete or partial file.
+    ///
+    /// This is called in addition to [`BackendMetrics::record_operation`] and allows to record which files are read.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file
+    /// * `id` - The id of the file
+    /// * `full` - Whether the complete file has been read
+    /// * `bytes` - The number of read bytes
+    fn record_read(&self, _tpe: FileType, _id: &Id, _full: bool, _bytes: u64) {}
+
+    /// Record that a failed operation is retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The kind of the operation
+    fn record_retry(&self, _op: BackendOperation) {}
+}
+
+/// Metrics of one kind of backend operation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct OperationStats {
+    /// Number of requests
+    pub requests: u64,
+    /// Number of failed requests
+    pub errors: u64,
+    /// Number of retries
+    pub retries: u64,
+    /// Total number of read or written bytes
+    pub bytes: u64,
+    /// Sum of the latencies of all requests
+    pub total_latency: Duration,
+    /// Maximum latency of a request
+    pub max_latency: Duration,
+}
+
+impl OperationStats {
+    /// Returns the mean latency of a request
+    #[must_use]
+    pub fn mean_latency(&self) -> Duration {
+        u32::try_from(self.requests)
+            .ok()
+            .and_then(|requests| self.total_latency.checked_div(requests))
+            .unwrap_or_default()
+    }
+}
+
+/// The metrics collected by [`BackendCounters`]
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct BackendMetricsSnapshot {
+    /// The metrics per kind of operation
+    pub operations: EnumMap<BackendOperation, OperationStats>,
+    /// The metrics per file type
+    pub file_types: EnumMap<FileType, OperationStats>,
+    /// The time the metrics have been collected
+    pub elapsed: Duration,
+}
+
+impl BackendMetricsSnapshot {
+    /// Returns the number of bytes read from the backend
+    #[must_use]
+    pub fn bytes_read(&self) -> u64 {
+        self.operations[BackendOperation::Read].bytes
+    }
+
+    /// Returns the number of bytes written to the backend
+    #[must_use]
+    pub fn bytes_written(&self) -> u64 {
+        self.operations[BackendOperation::Write].bytes
+    }
+
+    /// Returns the average throughput of the given operation in bytes per second
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The kind of the operation
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn throughput(&self, op: BackendOperation) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.operations[op].bytes as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The collected metrics
+#[derive(Debug)]
+struct CounterState {
+    /// The metrics per kind of operation
+    operations: EnumMap<BackendOperation, OperationStats>,
+    /// The metrics per file type
+    file_types: EnumMap<FileType, OperationStats>,
+    /// The start of the collection
+    start: Instant,
+}
+
+impl Default for CounterState {
+    fn default() -> Self {
+        Self {
+            operations: EnumMap::default(),
+            file_types: EnumMap::default(),
+            start: Instant::now(),
+        }
+    }
+}
+
+/// [`BackendMetrics`] collecting counters which can be read using [`BackendCounters::snapshot`].
+///
+/// Every [`Repository`](crate::Repository) collects these metrics, see
+/// [`Repository::backend_metrics`](crate::Repository::backend_metrics).
+#[derive(Debug, Default)]
+pub struct BackendCounters {
+    /// The collected metrics
+    state: Mutex<CounterState>,
+}
+
+impl BackendCounters {
+    /// Returns the metrics collected so far
+    #[must_use]
+    pub fn snapshot(&self) -> BackendMetricsSnapshot {
+        let state = self.state.lock().unwrap();
+        BackendMetricsSnapshot {
+            operations: state.operations,
+            file_types: state.file_types,
+            elapsed: state.start.elapsed(),
+        }
+    }
+
+    /// Discard all metrics collected so far
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = CounterState::default();
+    }
+}
+
+impl BackendMetrics for BackendCounters {
+    fn record_operation(
+        &self,
+        op: BackendOperation,
+        tpe: FileType,
+        bytes: u64,
+        latency: Duration,
+        success: bool,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        for stats in [&mut state.operations[op], &mut state.file_types[tpe]] {
+            stats.requests += 1;
+            if !success {
+                stats.errors += 1;
+            }
+            stats.bytes += bytes;
+            stats.total_latency += latency;
+            stats.max_latency = stats.max_latency.max(latency);
+        }
+    }
+
+    fn record_retry(&self, op: BackendOperation) {
+        self.state.lock().unwrap().operations[op].retries += 1;
+    }
+}
+
+/// A backend which calls the given [`BackendMetrics`] for all operations issued to the underlying backend.
+#[derive(Clone, Debug)]
+pub struct MetricsBackend {
+    /// The backend to use
+    be: Arc<dyn WriteBackend>,
+    /// The metrics to record the operations in
+    metrics: Vec<Arc<dyn BackendMetrics>>,
+}
+
+impl MetricsBackend {
+    /// Creates a new `MetricsBackend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to instrument
+    /// * `metrics` - The metrics to record the operations in
+    pub fn new(be: Arc<dyn WriteBackend>, metrics: Arc<dyn BackendMetrics>) -> Self {
+        Self {
+            be,
+            metrics: vec![metrics],
+        }
+    }
+
+    /// Additionally record the operations in the given metrics.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - The additional metrics to record the operations in
+    #[must_use]
+    pub fn add_metrics(mut self, metrics: Arc<dyn BackendMetrics>) -> Self {
+        self.metrics.push(metrics);
+        self
+    }
+
+    /// Run the operation and record it
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The kind of the operation
+    /// * `tpe` - The type of the file(s)
+    /// * `bytes` - Returns the number of read or written bytes of the result
+    /// * `f` - The operation to run
+    fn record<T>(
+        &self,
+        op: BackendOperation,
+        tpe: FileType,
+        bytes: impl FnOnce(&T) -> usize,
+        f: impl FnOnce() -> RusticResult<T>,
+    ) -> RusticResult<T> {
+        let start = Instant::now();
+        let result = f();
+        let bytes = result.as_ref().map_or(0, bytes) as u64;
+        let latency = start.elapsed();
+        for metrics in &self.metrics {
+            metrics.record_operation(op, tpe, bytes, latency, result.is_ok());
+        }
+        result
+    }
+
+    /// Run the read operation and record it
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file
+    /// * `id` - The id of the file
+    /// * `full` - Whether the complete file is read
+    /// * `f` - The read operation to run
+    fn record_read(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        full: bool,
+        f: impl FnOnce() -> RusticResult<Bytes>,
+    ) -> RusticResult<Bytes> {
+        let result = self.record(BackendOperation::Read, tpe, Bytes::len, f);
+        if let Ok(data) = &result {
+            for metrics in &self.metrics {
+                metrics.record_read(tpe, id, full, data.len() as u64);
+            }
+        }
+        result
+    }
+}
+
+impl ReadBackend for MetricsBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.record(
+            BackendOperation::List,
+            tpe,
+            |_| 0,
+            || self.be.list_with_size(tpe),
+        )
+    }
+
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.record(
+            BackendOperation::Stat,
+            tpe,
+            |_| 0,
+            || self.be.modified(tpe, id),
+        )
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.record(BackendOperation::List, tpe, |_| 0, || self.be.list(tpe))
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.record_read(tpe, id, true, || self.be.read_full(tpe, id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.record_read(tpe, id, false, || {
+            self.be.read_partial(tpe, id, cacheable, offset, length)
+        })
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.record(
+            BackendOperation::WarmUp,
+            tpe,
+            |_| 0,
+            || self.be.warm_up(tpe, id),
+        )
+    }
+}
+
+impl WriteBackend for MetricsBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.record(
+            BackendOperation::Create,
+            FileType::Config,
+            |_| 0,
+            || self.be.create(),
+        )
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        let len = buf.len();
+        self.record(
+            BackendOperation::Write,
+            tpe,
+            |_| len,
+            || self.be.write_bytes(tpe, id, cacheable, buf),
+        )
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.record(
+            BackendOperation::Remove,
+            tpe,
+            |_| 0,
+            || self.be.remove(tpe, id, cacheable),
+        )
+    }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        let len = buf.len();
+        self.record(
+            BackendOperation::Write,
+            tpe,
+            |_| len,
+            || self.be.write_bytes_locked(tpe, id, cacheable, buf, until),
+        )
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        backend::MockBackend,
+        error::{ErrorKind, RusticError},
+    };
+
+    #[test]
+    fn test_metrics_backend() {
+        let mut be = MockBackend::new();
+        _ = be
+            .expect_read_full()
+            .returning(|_, _| Ok(Bytes::from_static(b"data")));
+        _ = be.expect_write_bytes().returning(|_, _, _, _| Ok(()));
+        _ = be
+            .expect_remove()
+            .returning(|_, _, _| Err(RusticError::new(ErrorKind::Backend, "remove failed")));
+        _ = be.expect_modified().returning(|_, _| Ok(None));
+
+        let counters = Arc::new(BackendCounters::default());
+        let be = MetricsBackend::new(Arc::new(be), counters.clone());
+        _ = be.read_full(FileType::Pack, &Id::random()).unwrap();
+        _ = be.read_full(FileType::Index, &Id::random()).unwrap();
+        be.write_bytes(
+            FileType::Pack,
+            &Id::random(),
+            false,
+            Bytes::from_static(b"abc"),
+        )
+        .unwrap();
+        assert!(be.remove(FileType::Pack, &Id::random(), false).is_err());
+        counters.record_retry(BackendOperation::Remove);
+        assert_eq!(be.modified(FileType::Pack, &Id::random()).unwrap(), None);
+
+        let snapshot = counters.snapshot();
+        let read = snapshot.operations[BackendOperation::Read];
+        assert_eq!((read.requests, read.bytes, read.errors), (2, 8, 0));
+        assert_eq!(snapshot.bytes_written(), 3);
+        let remove = snapshot.operations[BackendOperation::Remove];
+        assert_eq!((remove.requests, remove.errors, remove.retries), (1, 1, 1));
+        assert_eq!(snapshot.operations[BackendOperation::Stat].requests, 1);
+        assert_eq!(snapshot.operations[BackendOperation::List].requests, 0);
+        assert_eq!(snapshot.file_types[FileType::Pack].requests, 4);
+
+        counters.reset();
+        assert_eq!(counters.snapshot().bytes_read(), 0);
+    }
+}
@@ -0,0 +1,446 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+use bytes::Bytes;
+use crossbeam_channel::{Sender, bounded};
+use jiff::Timestamp;
+use log::{debug, warn};
+
+use crate::{
+    backend::{FileType, ReadBackend, WriteBackend},
+    error::{ErrorKind, RusticError, RusticResult},
+    id::Id,
+};
+
+/// How writes are replicated to the mirror of a [`MirrorBackend`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum MirrorMode {
+    /// Replicate each write and remove before returning; errors of the mirror are returned
+    #[default]
+    Sync,
+    /// Replicate writes and removes in a background thread. At most `queue_size` operations are queued,
+    /// further operations block until the queue has space. Errors of the mirror are only recorded in the
+    /// [`MirrorStatus`].
+    Async {
+        /// The maximum number of queued operations
+        queue_size: usize,
+    },
+}
+
+/// An operation replicated to the mirror
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MirrorOperation {
+    /// Writing a file
+    Write,
+    /// Removing a file
+    Remove,
+}
+
+/// An operation which could not be replicated to the mirror
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct MirrorFailure {
+    /// The failed operation
+    pub operation: MirrorOperation,
+    /// The type of the file
+    pub tpe: FileType,
+    /// The id of the file
+    pub id: Id,
+    /// The error message
+    pub error: String,
+}
+
+/// The replication status of a [`MirrorBackend`]
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct MirrorStatus {
+    /// Number of files written to the mirror
+    pub written: u64,
+    /// Number of files removed from the mirror
+    pub removed: u64,
+    /// Number of operations which have not yet been replicated
+    pub pending: u64,
+    /// The operations which could not be replicated
+    pub failures: Vec<MirrorFailure>,
+}
+
+impl MirrorStatus {
+    /// Returns `true` if all operations have been replicated successfully
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        self.pending == 0 && self.failures.is_empty()
+    }
+}
+
+/// Differences between the files of the primary backend and the mirror
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MirrorConsistency {
+    /// Files which are missing in the mirror
+    pub missing: Vec<Id>,
+    /// Files which only exist in the mirror
+    pub extra: Vec<Id>,
+    /// Files which have a different size in the mirror
+    pub size_mismatch: Vec<Id>,
+}
+
+impl MirrorConsistency {
+    /// Returns `true` if the mirror contains exactly the files of the primary backend
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.size_mismatch.is_empty()
+    }
+}
+
+/// An operation to replicate
+#[derive(Debug)]
+enum MirrorOp {
+    /// Write a file
+    Write {
+        tpe: FileType,
+        id: Id,
+        cacheable: bool,
+        buf: Bytes,
+    },
+    /// Remove a file
+    Remove {
+        tpe: FileType,
+        id: Id,
+        cacheable: bool,
+    },
+}
+
+impl MirrorOp {
+    /// Apply the operation to the mirror
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The mirror backend
+    fn apply(self, be: &dyn WriteBackend) -> (MirrorOperation, FileType, Id, RusticResult<()>) {
+        match self {
+            Self::Write {
+                tpe,
+                id,
+                cacheable,
+                buf,
+            } => (
+                MirrorOperation::Write,
+                tpe,
+                id,
+                be.write_bytes(tpe, &id, cacheable, buf),
+            ),
+            Self::Remove { tpe, id, cacheable } => (
+                MirrorOperation::Remove,
+                tpe,
+                id,
+                be.remove(tpe, &id, cacheable),
+            ),
+        }
+    }
+}
+
+/// The replication state shared with the background thread
+#[derive(Debug, Default)]
+struct MirrorState {
+    /// The current status
+    status: Mutex<MirrorStatus>,
+    /// Notified when the number of pending operations has decreased
+    progress: Condvar,
+}
+
+impl MirrorState {
+    /// Register a new pending operation
+    fn begin(&self) {
+        self.status.lock().unwrap().pending += 1;
+    }
+
+    /// Record the result of an operation
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation
+    /// * `tpe` - The type of the file
+    /// * `id` - The id of the file
+    /// * `result` - The result of the operation
+    fn finish(&self, operation: MirrorOperation, tpe: FileType, id: Id, result: &RusticResult<()>) {
+        let mut status = self.status.lock().unwrap();
+        status.pending -= 1;
+        match result {
+            Ok(()) => match operation {
+                MirrorOperation::Write => status.written += 1,
+                MirrorOperation::Remove => status.removed += 1,
+            },
+            Err(err) => {
+                warn!("mirroring {operation:?} of {tpe:?} {id} failed: {err}");
+                status.failures.push(MirrorFailure {
+                    operation,
+                    tpe,
+                    id,
+                    error: err.to_string(),
+                });
+            }
+        }
+        drop(status);
+        self.progress.notify_all();
+    }
+}
+
+/// A backend which replicates all writes and removes to a mirror backend.
+///
+/// All reads are served by the primary backend. Depending on the [`MirrorMode`], the mirror is updated
+/// synchronously or by a background thread; [`MirrorBackend::status`] reports whether all operations have
+/// been replicated and [`MirrorBackend::compare`] compares the files of both backends.
+#[derive(Clone, Debug)]
+pub struct MirrorBackend {
+    /// The primary backend
+    be: Arc<dyn WriteBackend>,
+    /// The mirror backend
+    mirror: Arc<dyn WriteBackend>,
+    /// The queue of the background thread, if running in async mode
+    queue: Option<Sender<MirrorOp>>,
+    /// The replication state
+    state: Arc<MirrorState>,
+}
+
+impl MirrorBackend {
+    /// Creates a new `MirrorBackend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The primary backend
+    /// * `mirror` - The backend to replicate to
+    /// * `mode` - How to replicate to the mirror
+    pub fn new(be: Arc<dyn WriteBackend>, mirror: Arc<dyn WriteBackend>, mode: MirrorMode) -> Self {
+        let state = Arc::new(MirrorState::default());
+        let queue = match mode {
+            MirrorMode::Sync => None,
+            MirrorMode::Async { queue_size } => {
+                let (sender, receiver) = bounded::<MirrorOp>(queue_size);
+                let mirror = mirror.clone();
+                let state = state.clone();
+                // the thread ends when all senders, i.e. all clones of this backend, are dropped
+                _ = thread::spawn(move || {
+                    for op in receiver {
+                        let (operation, tpe, id, result) = op.apply(mirror.as_ref());
+                        state.finish(operation, tpe, id, &result);
+                    }
+                    debug!("mirror thread finished");
+                });
+                Some(sender)
+            }
+        };
+        Self {
+            be,
+            mirror,
+            queue,
+            state,
+        }
+    }
+
+    /// Returns the current replication status
+    #[must_use]
+    pub fn status(&self) -> MirrorStatus {
+        self.state.status.lock().unwrap().clone()
+    }
+
+    /// Wait until all queued operations have been replicated
+    ///
+    /// # Returns
+    ///
+    /// The replication status after all operations have been processed
+    #[must_use]
+    pub fn flush(&self) -> MirrorStatus {
+        let mut status = self.state.status.lock().unwrap();
+        while status.pending > 0 {
+            status = self.state.progress.wait(status).unwrap();
+        }
+        status.clone()
+    }
+
+    /// Compare the files of the given type in the primary backend and the mirror
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files to compare
+    ///
+    /// # Errors
+    ///
+    /// * If the files of one of the backends could not be listed.
+    pub fn compare(&self, tpe: FileType) -> RusticResult<MirrorConsistency> {
+        let primary: BTreeMap<_, _> = self.be.list_with_size(tpe)?.into_iter().collect();
+        let mut mirror: BTreeMap<_, _> = self.mirror.list_with_size(tpe)?.into_iter().collect();
+
+        let mut consistency = MirrorConsistency::default();
+        for (id, size) in primary {
+            match mirror.remove(&id) {
+                None => consistency.missing.push(id),
+                Some(mirror_size) if mirror_size != size => consistency.size_mismatch.push(id),
+                Some(_) => {}
+            }
+        }
+        consistency.extra = mirror.into_keys().collect();
+        Ok(consistency)
+    }
+
+    /// Replicate an operation to the mirror
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The operation to replicate
+    ///
+    /// # Errors
+    ///
+    /// * If the operation failed in sync mode.
+    fn replicate(&self, op: MirrorOp) -> RusticResult<()> {
+        self.state.begin();
+        if let Some(queue) = &self.queue {
+            if let Err(err) = queue.send(op) {
+                // the thread is not running anymore; this only happens if it has panicked
+                let (operation, tpe, id) = match err.into_inner() {
+                    MirrorOp::Write { tpe, id, .. } => (MirrorOperation::Write, tpe, id),
+                    MirrorOp::Remove { tpe, id, .. } => (MirrorOperation::Remove, tpe, id),
+                };
+                let err =
+                    RusticError::new(ErrorKind::Internal, "The mirror thread is not running.");
+                self.state.finish(operation, tpe, id, &Err(err));
+            }
+            return Ok(());
+        }
+
+        let (operation, tpe, id, result) = op.apply(self.mirror.as_ref());
+        self.state.finish(operation, tpe, id, &result);
+        result.map_err(|err| {
+            err.prepend_guidance_line("Replicating to the mirror backend `{mirror}` failed.")
+                .attach_context("mirror", self.mirror.location())
+        })
+    }
+}
+
+impl ReadBackend for MirrorBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.be.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe) && self.mirror.supports_file_type(tpe)
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+}
+
+impl WriteBackend for MirrorBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()?;
+        self.mirror.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        self.be.write_bytes(tpe, id, cacheable, buf.clone())?;
+        self.replicate(MirrorOp::Write {
+            tpe,
+            id: *id,
+            cacheable,
+            buf,
+        })
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)?;
+        self.replicate(MirrorOp::Remove {
+            tpe,
+            id: *id,
+            cacheable,
+        })
+    }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        // mirrors don't need to support object locks, so only the primary backend locks the file
+        self.be
+            .write_bytes_locked(tpe, id, cacheable, buf.clone(), until)?;
+        self.replicate(MirrorOp::Write {
+            tpe,
+            id: *id,
+            cacheable,
+            buf,
+        })
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::MockBackend;
+
+    #[test]
+    fn test_compare() {
+        let (id1, id2, id3) = (Id::random(), Id::random(), Id::random());
+        let mut be = MockBackend::new();
+        _ = be
+            .expect_list_with_size()
+            .returning(move |_| Ok(vec![(id1, 1), (id2, 2)]));
+        let mut mirror = MockBackend::new();
+        _ = mirror
+            .expect_list_with_size()
+            .returning(move |_| Ok(vec![(id2, 3), (id3, 3)]));
+
+        let be = MirrorBackend::new(Arc::new(be), Arc::new(mirror), MirrorMode::Sync);
+        let consistency = be.compare(FileType::Pack).unwrap();
+        assert_eq!(consistency.missing, vec![id1]);
+        assert_eq!(consistency.size_mismatch, vec![id2]);
+        assert_eq!(consistency.extra, vec![id3]);
+        assert!(!consistency.is_consistent());
+    }
+}
@@ -273,6 +273,13 @@ pub struct Metadata {
     /// Extended attributes of the node
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub extended_attributes: Vec<ExtendedAttribute>,
+    /// Whether the file was stored sparse on the source filesystem
+    ///
+    /// # Note
+    ///
+    /// This is a rustic extension and is used as a hint to restore the file as sparse file.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub sparse: bool,
 }
 
 pub(crate) fn is_default<T: Default + PartialEq>(t: &T) -> bool {
@@ -352,6 +359,15 @@ impl Node {
     pub fn name(&self) -> Cow<'_, OsStr> {
         unescape_filename(&self.name).unwrap_or_else(|_| Cow::Borrowed(OsStr::new(&self.name)))
     }
+
+    /// Set the node name, handling name escaping
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The new name of the node
+    pub fn set_name(&mut self, name: &OsStr) {
+        self.name = escape_filename(name);
+    }
 }
 
 /// An ordering function returning the latest node by mtime
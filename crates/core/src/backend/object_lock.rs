@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use derive_setters::Setters;
+use jiff::{SignedDuration, Timestamp, Zoned};
+
+use crate::{
+    backend::{FileType, ReadBackend, WriteBackend},
+    commands::forget::KeepOptions,
+    error::{ErrorKind, RusticError, RusticResult},
+    id::Id,
+};
+
+/// Policy which determines how long newly written files are locked by an [`ObjectLockBackend`].
+///
+/// Only pack and snapshot files are locked. Index files are rewritten by `prune`, even if the contained
+/// packs are kept, and keys and the config file need to be changeable.
+///
+/// Snapshots which are marked to be deleted after a given time (see
+/// [`DeleteOption::After`](crate::repofile::DeleteOption::After)) are locked at least until this time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+pub struct ObjectLockPolicy {
+    /// The retention period of newly written snapshot files; zero disables locking snapshots
+    pub snapshot_retention: SignedDuration,
+    /// The retention period of newly written pack files; zero disables locking packs
+    ///
+    /// This should not be shorter than the snapshot retention, as the packs hold the data of the snapshots.
+    pub pack_retention: SignedDuration,
+}
+
+impl ObjectLockPolicy {
+    /// Creates a policy locking newly written pack and snapshot files for the given period.
+    ///
+    /// # Arguments
+    ///
+    /// * `retention` - The retention period of newly written pack and snapshot files
+    #[must_use]
+    pub const fn new(retention: SignedDuration) -> Self {
+        Self {
+            snapshot_retention: retention,
+            pack_retention: retention,
+        }
+    }
+
+    /// Creates a policy from the keep options used by `forget`.
+    ///
+    /// A new snapshot is kept by `forget` at least for the longest `keep-within` period, so packs and
+    /// snapshots are locked for this period, but at least for `min_retention`.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - The keep options
+    /// * `min_retention` - The minimum retention period
+    #[must_use]
+    pub fn from_keep_options(keep: &KeepOptions, min_retention: SignedDuration) -> Self {
+        let now = Zoned::now();
+        let retention = [
+            keep.keep_within,
+            keep.keep_within_minutely,
+            keep.keep_within_hourly,
+            keep.keep_within_daily,
+            keep.keep_within_weekly,
+            keep.keep_within_monthly,
+            keep.keep_within_quarter_yearly,
+            keep.keep_within_half_yearly,
+            keep.keep_within_yearly,
+        ]
+        .into_iter()
+        .flatten()
+        .map(|within| now.saturating_add(within).duration_since(&now))
+        .fold(min_retention, SignedDuration::max);
+        Self::new(retention)
+    }
+
+    /// Returns the retention period of newly written files of the given type, if they are locked.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file
+    #[must_use]
+    pub fn retention(&self, tpe: FileType) -> Option<SignedDuration> {
+        let retention = match tpe {
+            FileType::Pack => self.pack_retention,
+            FileType::Snapshot => self.snapshot_retention,
+            _ => return None,
+        };
+        retention.is_positive().then_some(retention)
+    }
+
+    /// Returns the end of the retention period of a file of the given type written now, if it is locked.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file
+    ///
+    /// # Errors
+    ///
+    /// * If the time overflows.
+    pub fn retain_until(&self, tpe: FileType) -> RusticResult<Option<Timestamp>> {
+        self.retention(tpe)
+            .map(|retention| {
+                Timestamp::now().checked_add(retention).map_err(|err| {
+                    RusticError::with_source(
+                        ErrorKind::InvalidInput,
+                        "The retention period `{retention}` is too large.",
+                        err,
+                    )
+                    .attach_context("retention", retention.to_string())
+                    .attach_context("type", tpe.to_string())
+                })
+            })
+            .transpose()
+    }
+}
+
+/// A backend which locks newly written pack and snapshot files as given by an [`ObjectLockPolicy`].
+///
+/// This only works if the underlying backend supports object locks, e.g. S3 buckets with object lock
+/// enabled. Locked files cannot be removed (or overwritten) before the retention period expired, which
+/// protects the repository against ransomware or compromised credentials. The retention period is set
+/// when writing the file, see [`WriteBackend::write_bytes_locked`].
+#[derive(Clone, Debug)]
+pub struct ObjectLockBackend {
+    /// The backend to use
+    be: Arc<dyn WriteBackend>,
+    /// The policy to determine the retention period of newly written files
+    policy: ObjectLockPolicy,
+}
+
+impl ObjectLockBackend {
+    /// Creates a new `ObjectLockBackend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use
+    /// * `policy` - The policy to determine the retention period of newly written files
+    ///
+    /// # Errors
+    ///
+    /// * If the backend doesn't support object locks.
+    /// * If the policy doesn't lock any files.
+    pub fn new(be: Arc<dyn WriteBackend>, policy: ObjectLockPolicy) -> RusticResult<Self> {
+        if !be.supports_object_lock() {
+            return Err(RusticError::new(
+                ErrorKind::Unsupported,
+                "Backend `{location}` does not support object locks, but a retention period is given.",
+            )
+            .attach_context("location", be.location()));
+        }
+        if policy.retention(FileType::Pack).is_none()
+            && policy.retention(FileType::Snapshot).is_none()
+        {
+            return Err(RusticError::new(
+                ErrorKind::InvalidInput,
+                "The retention periods of packs `{pack_retention}` and snapshots `{snapshot_retention}` must be positive.",
+            )
+            .attach_context("pack_retention", policy.pack_retention.to_string())
+            .attach_context("snapshot_retention", policy.snapshot_retention.to_string()));
+        }
+        Ok(Self { be, policy })
+    }
+}
+
+impl ReadBackend for ObjectLockBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.be.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.be.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.be.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for ObjectLockBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        match self.policy.retain_until(tpe)? {
+            Some(until) => self.be.write_bytes_locked(tpe, id, cacheable, buf, until),
+            None => self.be.write_bytes(tpe, id, cacheable, buf),
+        }
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        // never lock shorter than given by the policy
+        let until = self
+            .policy
+            .retain_until(tpe)?
+            .map_or(until, |policy_until| policy_until.max(until));
+        self.be.write_bytes_locked(tpe, id, cacheable, buf, until)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+
+    fn supports_object_lock(&self) -> bool {
+        true
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::MockBackend;
+
+    #[test]
+    fn test_object_lock() {
+        let mut be = MockBackend::new();
+        _ = be.expect_supports_object_lock().return_const(true);
+        _ = be
+            .expect_write_bytes()
+            .times(1)
+            .withf(|tpe, _, _, _| *tpe == FileType::Index)
+            .returning(|_, _, _, _| Ok(()));
+        _ = be
+            .expect_write_bytes_locked()
+            .times(2)
+            .withf(|tpe, _, _, _, until| {
+                let hours = if *tpe == FileType::Pack { 47 } else { 23 };
+                *tpe != FileType::Index
+                    && *until > Timestamp::now() + SignedDuration::from_hours(hours)
+            })
+            .returning(|_, _, _, _, _| Ok(()));
+
+        let policy = ObjectLockPolicy::new(SignedDuration::from_hours(24))
+            .pack_retention(SignedDuration::from_hours(48));
+        let be = ObjectLockBackend::new(Arc::new(be), policy).unwrap();
+        for tpe in [FileType::Pack, FileType::Index, FileType::Snapshot] {
+            be.write_bytes(tpe, &Id::random(), false, Bytes::from_static(b"data"))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_object_lock_extends_retention() {
+        let later = Timestamp::now() + SignedDuration::from_hours(100);
+        let mut be = MockBackend::new();
+        _ = be.expect_supports_object_lock().return_const(true);
+        _ = be
+            .expect_write_bytes_locked()
+            .times(1)
+            .withf(move |_, _, _, _, until| *until == later)
+            .returning(|_, _, _, _, _| Ok(()));
+
+        let policy = ObjectLockPolicy::new(SignedDuration::from_hours(24));
+        let be = ObjectLockBackend::new(Arc::new(be), policy).unwrap();
+        be.write_bytes_locked(
+            FileType::Snapshot,
+            &Id::random(),
+            false,
+            Bytes::from_static(b"data"),
+            later,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_object_lock_unsupported() {
+        let mut be = MockBackend::new();
+        _ = be.expect_supports_object_lock().return_const(false);
+        _ = be.expect_location().return_const("test".to_string());
+        let policy = ObjectLockPolicy::new(SignedDuration::from_hours(24));
+        assert!(ObjectLockBackend::new(Arc::new(be), policy).is_err());
+    }
+}
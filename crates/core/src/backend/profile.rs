@@ -0,0 +1,199 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use enum_map::EnumMap;
+
+use crate::{
+    backend::{
+        FileType,
+        metrics::{BackendMetrics, BackendOperation},
+    },
+    id::Id,
+};
+
+/// Number of read requests and read bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReadStats {
+    /// Number of requests reading a complete file
+    pub full_reads: u64,
+    /// Number of requests reading a part of a file
+    pub partial_reads: u64,
+    /// Total number of read bytes
+    pub bytes: u64,
+}
+
+impl ReadStats {
+    /// Returns the total number of read requests
+    #[must_use]
+    pub const fn requests(&self) -> u64 {
+        self.full_reads + self.partial_reads
+    }
+
+    fn add(&mut self, full: bool, bytes: u64) {
+        if full {
+            self.full_reads += 1;
+        } else {
+            self.partial_reads += 1;
+        }
+        self.bytes += bytes;
+    }
+}
+
+/// A pack file which has been read by more than one request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RepeatedPackRead {
+    /// The id of the pack
+    pub id: Id,
+    /// Number of read requests for this pack
+    pub reads: u64,
+    /// Total number of bytes read from this pack
+    pub bytes: u64,
+}
+
+/// The reads issued to the backend, see [`ReadProfiler`]
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ReadProfile {
+    /// The reads per file type
+    pub file_types: EnumMap<FileType, ReadStats>,
+    /// Number of distinct packs which have been read
+    pub packs_read: u64,
+    /// Packs which have been read by more than one request, sorted by number of reads (descending)
+    pub repeated_packs: Vec<RepeatedPackRead>,
+}
+
+impl ReadProfile {
+    /// Returns the reads summed over all file types
+    #[must_use]
+    pub fn total(&self) -> ReadStats {
+        self.file_types
+            .values()
+            .fold(ReadStats::default(), |total, stats| ReadStats {
+                full_reads: total.full_reads + stats.full_reads,
+                partial_reads: total.partial_reads + stats.partial_reads,
+                bytes: total.bytes + stats.bytes,
+            })
+    }
+}
+
+/// The recorded reads
+#[derive(Debug, Default)]
+struct ProfileState {
+    /// The reads per file type
+    file_types: EnumMap<FileType, ReadStats>,
+    /// Number of read requests and read bytes per pack
+    packs: HashMap<Id, (u64, u64)>,
+}
+
+/// [`BackendMetrics`] recording all read requests issued to a backend.
+///
+/// Add it to a [`MetricsBackend`](super::metrics::MetricsBackend) to record the reads of a backend. Use [`ReadProfiler::profile`] to get the
+/// number of requests and bytes per [`FileType`] and the packs which have been read multiple times. This
+/// helps to choose a cache size and to find operations which suffer from bad packing locality.
+#[derive(Debug, Default)]
+pub struct ReadProfiler {
+    /// The recorded reads
+    state: Mutex<ProfileState>,
+}
+
+impl ReadProfiler {
+    /// Returns the reads recorded so far
+    #[must_use]
+    pub fn profile(&self) -> ReadProfile {
+        let state = self.state.lock().unwrap();
+        let mut repeated_packs: Vec<_> = state
+            .packs
+            .iter()
+            .filter(|(_, (reads, _))| *reads > 1)
+            .map(|(id, (reads, bytes))| RepeatedPackRead {
+                id: *id,
+                reads: *reads,
+                bytes: *bytes,
+            })
+            .collect();
+        repeated_packs.sort_unstable_by(|p1, p2| p2.reads.cmp(&p1.reads).then(p1.id.cmp(&p2.id)));
+
+        ReadProfile {
+            file_types: state.file_types,
+            packs_read: state.packs.len() as u64,
+            repeated_packs,
+        }
+    }
+
+    /// Discard all reads recorded so far, e.g. to start profiling a new command
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = ProfileState::default();
+    }
+}
+
+impl BackendMetrics for ReadProfiler {
+    fn record_operation(
+        &self,
+        _op: BackendOperation,
+        _tpe: FileType,
+        _bytes: u64,
+        _latency: Duration,
+        _success: bool,
+    ) {
+    }
+
+    fn record_read(&self, tpe: FileType, id: &Id, full: bool, bytes: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.file_types[tpe].add(full, bytes);
+        if tpe == FileType::Pack {
+            let (reads, read_bytes) = state.packs.entry(*id).or_default();
+            *reads += 1;
+            *read_bytes += bytes;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    use crate::backend::{MockBackend, ReadBackend, metrics::MetricsBackend};
+
+    #[test]
+    fn test_profile() {
+        let (id1, id2) = (Id::random(), Id::random());
+        let mut be = MockBackend::new();
+        _ = be
+            .expect_read_full()
+            .returning(|_, _| Ok(Bytes::from_static(b"index")));
+        _ = be
+            .expect_read_partial()
+            .returning(|_, _, _, _, length| Ok(vec![0; length as usize].into()));
+
+        let profiler = Arc::new(ReadProfiler::default());
+        let be = MetricsBackend::new(Arc::new(be), profiler.clone());
+        _ = be.read_full(FileType::Index, &id1).unwrap();
+        _ = be.read_partial(FileType::Pack, &id1, false, 0, 10).unwrap();
+        _ = be.read_partial(FileType::Pack, &id1, false, 20, 5).unwrap();
+        _ = be.read_partial(FileType::Pack, &id2, false, 0, 7).unwrap();
+
+        let profile = profiler.profile();
+        assert_eq!(profile.file_types[FileType::Index].full_reads, 1);
+        assert_eq!(profile.file_types[FileType::Index].bytes, 5);
+        assert_eq!(profile.file_types[FileType::Pack].partial_reads, 3);
+        assert_eq!(profile.file_types[FileType::Pack].bytes, 22);
+        assert_eq!(profile.total().requests(), 4);
+        assert_eq!(profile.packs_read, 2);
+        assert_eq!(
+            profile.repeated_packs,
+            vec![RepeatedPackRead {
+                id: id1,
+                reads: 2,
+                bytes: 15
+            }]
+        );
+
+        profiler.reset();
+        assert_eq!(profiler.profile().total(), ReadStats::default());
+    }
+}
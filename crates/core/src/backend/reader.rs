@@ -0,0 +1,79 @@
+use std::{
+    io::Read,
+    iter::{Once, once},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crate::{
+    backend::{ReadSource, ReadSourceEntry},
+    error::{ErrorKind, RusticError, RusticResult},
+};
+
+/// The `ReaderSource` is a `ReadSource` backing up the data of an arbitrary reader as a single file.
+///
+/// This allows to back up streams like the output of `pg_dump`. As the reader can only be read once,
+/// the file is only contained in the first call of [`ReadSource::entries`].
+#[derive(Debug)]
+pub struct ReaderSource<R> {
+    /// The path of the file within the snapshot.
+    path: PathBuf,
+    /// The expected size of the data, used for progress reporting.
+    size: Option<u64>,
+    /// The reader
+    ///
+    /// # Note
+    ///
+    /// This is in a Mutex as we want to take out the reader in the `entries` method - but this method
+    /// only gets a reference of self.
+    reader: Mutex<Option<R>>,
+}
+
+impl<R> ReaderSource<R> {
+    /// Creates a new `ReaderSource`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file within the snapshot
+    /// * `reader` - The reader to back up
+    pub fn new(path: PathBuf, reader: R) -> Self {
+        Self {
+            path,
+            size: None,
+            reader: Mutex::new(Some(reader)),
+        }
+    }
+
+    /// Set the expected size of the data, which is used to show the backup progress.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The expected size in bytes
+    #[must_use]
+    pub fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
+    }
+}
+
+impl<R: Read + Send + 'static> ReadSource for ReaderSource<R> {
+    type Open = R;
+    type Iter = Once<RusticResult<ReadSourceEntry<R>>>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        Ok(self.size)
+    }
+
+    fn entries(&self) -> Self::Iter {
+        let open = self.reader.lock().unwrap().take();
+        once(
+            ReadSourceEntry::from_path(self.path.clone(), open).map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::Backend,
+                    "Failed to create ReadSourceEntry from reader",
+                    err,
+                )
+            }),
+        )
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{
+    backend::{ReadSource, ReadSourceEntry},
+    error::RusticResult,
+    repofile::snapshotfile::PathRemap,
+};
+
+/// The `RemapSource` is a `ReadSource` recording the entries of another source under different paths.
+///
+/// The path of each entry is mapped using the remap table, see [`PathRemap`]. If the root of a remap is
+/// renamed, the name of its node is changed accordingly.
+///
+/// # Note
+///
+/// The entries of the source must still be sorted after remapping, i.e. the backup paths need to be
+/// given in the order of their targets, no target may contain another one and no remap source may be
+/// nested within another remap source or backup path.
+#[derive(Debug)]
+pub struct RemapSource<R> {
+    /// The source to read from
+    src: R,
+    /// The remap table
+    remaps: Vec<PathRemap>,
+}
+
+impl<R> RemapSource<R> {
+    /// Creates a new `RemapSource`.
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - The source to read from
+    /// * `remaps` - The remap table to use
+    pub fn new(src: R, remaps: Vec<PathRemap>) -> Self {
+        Self { src, remaps }
+    }
+}
+
+impl<R: ReadSource> ReadSource for RemapSource<R> {
+    type Open = R::Open;
+    type Iter = RemapSourceIter<R::Iter>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        self.src.size()
+    }
+
+    fn entries(&self) -> Self::Iter {
+        RemapSourceIter {
+            iter: self.src.entries(),
+            remaps: self.remaps.clone(),
+        }
+    }
+}
+
+/// Iterator over the entries of a [`RemapSource`]
+#[derive(Debug)]
+pub struct RemapSourceIter<I> {
+    /// The entries of the original source
+    iter: I,
+    /// The remap table
+    remaps: Vec<PathRemap>,
+}
+
+impl<I, O> Iterator for RemapSourceIter<I>
+where
+    I: Iterator<Item = RusticResult<ReadSourceEntry<O>>>,
+{
+    type Item = RusticResult<ReadSourceEntry<O>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.iter.next()?.map(|mut entry| {
+            let path = PathRemap::map(&self.remaps, &entry.path);
+            if let Some(name) = path.file_name()
+                && name != &*entry.node.name()
+            {
+                entry.node.set_name(name);
+            }
+            entry.path = path;
+            entry
+        }))
+    }
+}
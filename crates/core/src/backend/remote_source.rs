@@ -0,0 +1,328 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use derive_setters::Setters;
+use ignore::overrides::Override;
+use jiff::Timestamp;
+use log::warn;
+
+use crate::{
+    Excludes,
+    backend::{
+        ReadSource, ReadSourceEntry, ReadSourceOpen,
+        node::{ExtendedAttribute, Metadata, Node, NodeType},
+    },
+    error::{ErrorKind, RusticError, RusticResult},
+};
+
+/// The kind of an entry of a [`SourceFileSystem`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SourceEntryKind {
+    /// A regular file
+    #[default]
+    File,
+    /// A directory
+    Dir,
+    /// A symlink
+    Symlink {
+        /// The target of the symlink
+        target: PathBuf,
+    },
+}
+
+/// Metadata of an entry of a [`SourceFileSystem`]
+///
+/// Fields which are not available from the client (e.g. user names over SFTP) should be left empty.
+#[derive(Debug, Clone, Default, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+pub struct SourceMetadata {
+    /// The kind of the entry
+    pub kind: SourceEntryKind,
+    /// The size of the entry; ignored for directories
+    pub size: u64,
+    /// Unix mode
+    pub mode: Option<u32>,
+    /// Modification time
+    pub mtime: Option<Timestamp>,
+    /// Access time
+    pub atime: Option<Timestamp>,
+    /// Change time
+    pub ctime: Option<Timestamp>,
+    /// Unix uid
+    pub uid: Option<u32>,
+    /// Unix gid
+    pub gid: Option<u32>,
+    /// User name
+    pub user: Option<String>,
+    /// Group name
+    pub group: Option<String>,
+    /// Extended attributes
+    pub extended_attributes: Vec<ExtendedAttribute>,
+}
+
+impl SourceMetadata {
+    /// Returns `true` if the entry is a directory
+    #[must_use]
+    pub fn is_dir(&self) -> bool {
+        self.kind == SourceEntryKind::Dir
+    }
+
+    /// Create the [`Node`] for an entry with this metadata
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the entry
+    #[must_use]
+    pub fn to_node(self, name: &OsStr) -> Node {
+        let (node_type, size) = match self.kind {
+            SourceEntryKind::File => (NodeType::File, self.size),
+            SourceEntryKind::Dir => (NodeType::Dir, 0),
+            SourceEntryKind::Symlink { target } => (NodeType::from_link(&target), self.size),
+        };
+        let meta = Metadata {
+            mode: self.mode,
+            mtime: self.mtime,
+            atime: self.atime,
+            ctime: self.ctime,
+            uid: self.uid,
+            gid: self.gid,
+            user: self.user,
+            group: self.group,
+            size,
+            extended_attributes: self.extended_attributes,
+            ..Default::default()
+        };
+        Node::new_node(name, node_type, meta)
+    }
+}
+
+/// A file system which can be used as a backup source, e.g. a SFTP server or a SMB share.
+///
+/// This is the client surface needed by [`RemoteSource`]; the implementation is responsible for
+/// the connection handling.
+pub trait SourceFileSystem: Send + Sync + 'static {
+    /// The reader returned when opening a file
+    type Reader: Read + Send + 'static;
+
+    /// List the entries of a directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the directory
+    ///
+    /// # Errors
+    ///
+    /// * If the directory could not be listed.
+    ///
+    /// # Returns
+    ///
+    /// The names (not the full paths) and metadata of all entries in the directory, excluding `.` and `..`.
+    fn list(&self, path: &Path) -> RusticResult<Vec<(OsString, SourceMetadata)>>;
+
+    /// Get the metadata of an entry without following symlinks.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the entry
+    ///
+    /// # Errors
+    ///
+    /// * If the metadata could not be read.
+    fn metadata(&self, path: &Path) -> RusticResult<SourceMetadata>;
+
+    /// Open a file for reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be opened.
+    fn open_read(&self, path: &Path) -> RusticResult<Self::Reader>;
+}
+
+/// A [`RemoteSource`] is a source on a [`SourceFileSystem`] which is used to be read from (i.e. to backup it).
+#[derive(Debug)]
+pub struct RemoteSource<F> {
+    /// The file system to read from
+    fs: Arc<F>,
+    /// The excludes to apply
+    overrides: Override,
+    /// The backup path(s)
+    paths: Vec<PathBuf>,
+}
+
+impl<F: SourceFileSystem> RemoteSource<F> {
+    /// Create a remote source from a [`SourceFileSystem`] and backup path(s).
+    ///
+    /// # Arguments
+    ///
+    /// * `fs` - The file system to read from
+    /// * `excludes` - The [`Excludes`] to apply
+    /// * `backup_paths` - The backup path(s) to use.
+    ///
+    /// # Errors
+    ///
+    /// * If the a glob pattern could not be added to the override builder.
+    /// * If a glob file could not be read.
+    pub fn new(
+        fs: Arc<F>,
+        excludes: &Excludes,
+        backup_paths: &[impl AsRef<Path>],
+    ) -> RusticResult<Self> {
+        Ok(Self {
+            fs,
+            overrides: excludes.as_override()?,
+            paths: backup_paths
+                .iter()
+                .map(|p| p.as_ref().to_path_buf())
+                .collect(),
+        })
+    }
+}
+
+/// Describes an open file from a [`RemoteSource`].
+#[derive(Debug)]
+pub struct RemoteOpenFile<F> {
+    /// The file system to read from
+    fs: Arc<F>,
+    /// The path of the file
+    path: PathBuf,
+}
+
+impl<F: SourceFileSystem> ReadSourceOpen for RemoteOpenFile<F> {
+    type Reader = F::Reader;
+
+    /// Open the file from the [`SourceFileSystem`].
+    ///
+    /// # Errors
+    ///
+    /// * If the file could not be opened.
+    fn open(self) -> RusticResult<Self::Reader> {
+        self.fs.open_read(&self.path)
+    }
+}
+
+impl<F: SourceFileSystem> ReadSource for RemoteSource<F> {
+    type Open = RemoteOpenFile<F>;
+    type Iter = RemoteSourceWalker<F>;
+
+    /// Get the size of the remote source by walking it.
+    ///
+    /// # Returns
+    ///
+    /// The size of the remote source.
+    fn size(&self) -> RusticResult<Option<u64>> {
+        let mut size = 0;
+        for entry in self.entries() {
+            match entry {
+                Ok(entry) if !entry.node.is_dir() => size += entry.node.meta.size,
+                Ok(_) => {}
+                Err(err) => warn!("ignoring error {}", err.display_log()),
+            }
+        }
+        Ok(Some(size))
+    }
+
+    /// Iterate over the entries of the remote source.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the entries of the remote source, sorted by path within each directory.
+    fn entries(&self) -> Self::Iter {
+        RemoteSourceWalker {
+            fs: self.fs.clone(),
+            overrides: self.overrides.clone(),
+            stack: self
+                .paths
+                .iter()
+                .rev()
+                .cloned()
+                .map(Pending::Root)
+                .collect(),
+        }
+    }
+}
+
+/// An entry still to be processed by the [`RemoteSourceWalker`]
+#[derive(Debug)]
+enum Pending {
+    /// A backup path whose metadata has not yet been read
+    Root(PathBuf),
+    /// An entry of a listed directory
+    Entry(PathBuf, SourceMetadata),
+    /// An error which occurred when listing a directory
+    Error(Box<RusticError>),
+}
+
+/// Walks a [`RemoteSource`] depth-first
+#[derive(Debug)]
+pub struct RemoteSourceWalker<F> {
+    /// The file system to read from
+    fs: Arc<F>,
+    /// The excludes to apply
+    overrides: Override,
+    /// The entries still to be processed, in reverse order
+    stack: Vec<Pending>,
+}
+
+impl<F: SourceFileSystem> Iterator for RemoteSourceWalker<F> {
+    type Item = RusticResult<ReadSourceEntry<RemoteOpenFile<F>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (path, meta, is_root) = match self.stack.pop()? {
+                Pending::Root(path) => match self.fs.metadata(&path) {
+                    Ok(meta) => (path, meta, true),
+                    Err(err) => return Some(Err(err)),
+                },
+                Pending::Entry(path, meta) => (path, meta, false),
+                Pending::Error(err) => return Some(Err(err)),
+            };
+
+            if !is_root && self.overrides.matched(&path, meta.is_dir()).is_ignore() {
+                continue;
+            }
+
+            if meta.is_dir() {
+                match self.fs.list(&path) {
+                    Ok(mut entries) => {
+                        entries.sort_by(|(n1, _), (n2, _)| n1.cmp(n2));
+                        self.stack.extend(
+                            entries
+                                .into_iter()
+                                .rev()
+                                .map(|(name, meta)| Pending::Entry(path.join(name), meta)),
+                        );
+                    }
+                    Err(err) => self.stack.push(Pending::Error(err)),
+                }
+                // ignore root dirs, like the local source does
+                if is_root {
+                    continue;
+                }
+            }
+
+            let Some(name) = path.file_name() else {
+                return Some(Err(RusticError::new(
+                    ErrorKind::InvalidInput,
+                    "The path `{path}` has no file name.",
+                )
+                .attach_context("path", path.display().to_string())));
+            };
+            let node = meta.to_node(name);
+            let open = node.is_file().then(|| RemoteOpenFile {
+                fs: self.fs.clone(),
+                path: path.clone(),
+            });
+            return Some(Ok(ReadSourceEntry { path, node, open }));
+        }
+    }
+}
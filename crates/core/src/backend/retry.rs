@@ -0,0 +1,383 @@
+use std::{
+    sync::{Arc, mpsc},
+    thread,
+    time::Duration,
+};
+
+use backon::{BlockingRetryable, ExponentialBuilder};
+use bytes::Bytes;
+use derive_setters::Setters;
+use jiff::Timestamp;
+use log::warn;
+
+use crate::{
+    backend::{
+        FileType, ReadBackend, WriteBackend,
+        metrics::{BackendMetrics, BackendOperation},
+    },
+    error::{ErrorKind, RusticError, RusticResult, Status},
+    id::Id,
+};
+
+pub(super) mod constants {
+    use std::time::Duration;
+
+    /// The default maximum number of retries
+    pub(super) const DEFAULT_MAX_RETRIES: usize = 5;
+    /// The default delay before the first retry
+    pub(super) const DEFAULT_MIN_DELAY: Duration = Duration::from_secs(1);
+    /// The default maximum delay between two retries
+    pub(super) const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(60);
+    /// The default factor the delay is increased by after each retry
+    pub(super) const DEFAULT_FACTOR: f32 = 2.0;
+}
+
+#[derive(Debug, Clone, Copy, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for the [`RetryBackend`]
+pub struct RetryOptions {
+    /// Maximum number of retries of a failed operation; `0` disables retrying
+    pub max_retries: usize,
+
+    /// Delay before the first retry
+    pub min_delay: Duration,
+
+    /// Maximum delay between two retries
+    pub max_delay: Duration,
+
+    /// Factor the delay is increased by after each retry
+    pub factor: f32,
+
+    /// Randomize the delays, so that parallel operations don't retry at the same time
+    pub jitter: bool,
+
+    /// Timeout for a single attempt of an operation. An attempt exceeding the timeout is considered failed
+    /// and retried; note that the timed out operation itself is not aborted.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: constants::DEFAULT_MAX_RETRIES,
+            min_delay: constants::DEFAULT_MIN_DELAY,
+            max_delay: constants::DEFAULT_MAX_DELAY,
+            factor: constants::DEFAULT_FACTOR,
+            jitter: true,
+            timeout: None,
+        }
+    }
+}
+
+impl RetryOptions {
+    /// Get the exponential backoff defined by these options
+    fn backoff(&self) -> ExponentialBuilder {
+        let backoff = ExponentialBuilder::default()
+            .with_min_delay(self.min_delay)
+            .with_max_delay(self.max_delay)
+            .with_factor(self.factor)
+            .with_max_times(self.max_retries);
+        if self.jitter {
+            backoff.with_jitter()
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Classification of errors which is used if no other classification is given: All errors are retried,
+/// except errors marked as [`Status::Permanent`].
+fn is_retryable(err: &RusticError) -> bool {
+    !err.is_permanent()
+}
+
+/// A backend which retries failed operations of the underlying backend.
+///
+/// Failed operations are retried with an exponential backoff, see [`RetryOptions`]. By default, all errors
+/// are retried except errors marked as [`Status::Permanent`]; use [`RetryBackend::with_retryable`] to
+/// change which errors are retried. This prevents flaky network backends from aborting long running
+/// operations like `prune` or `restore`.
+#[derive(Debug)]
+pub struct RetryBackend<B: ?Sized> {
+    /// The backend to use
+    be: Arc<B>,
+    /// The retry options
+    opts: RetryOptions,
+    /// Decides whether an error is retried
+    retryable: fn(&RusticError) -> bool,
+    /// The metrics to report retries to
+    metrics: Option<Arc<dyn BackendMetrics>>,
+}
+
+impl<B: ?Sized> Clone for RetryBackend<B> {
+    fn clone(&self) -> Self {
+        Self {
+            be: self.be.clone(),
+            opts: self.opts,
+            retryable: self.retryable,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<B: ReadBackend + ?Sized> RetryBackend<B> {
+    /// Creates a new `RetryBackend`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to retry operations of
+    /// * `opts` - The retry options
+    pub fn new(be: Arc<B>, opts: RetryOptions) -> Self {
+        Self {
+            be,
+            opts,
+            retryable: is_retryable,
+            metrics: None,
+        }
+    }
+
+    /// Set the function deciding whether an error is retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `retryable` - Returns `true` if the operation should be retried after the given error
+    #[must_use]
+    pub fn with_retryable(mut self, retryable: fn(&RusticError) -> bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Report all retries to the given metrics, see [`BackendMetrics::record_retry`].
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - The metrics to report retries to
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<dyn BackendMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Run a single attempt of the operation, respecting the timeout
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation, used in error messages
+    /// * `op` - The operation to run
+    ///
+    /// # Errors
+    ///
+    /// * If the operation failed or timed out.
+    fn attempt<T: Send + 'static>(
+        &self,
+        operation: BackendOperation,
+        op: &Arc<dyn Fn(&B) -> RusticResult<T> + Send + Sync>,
+    ) -> RusticResult<T> {
+        let Some(timeout) = self.opts.timeout else {
+            return op(&self.be);
+        };
+
+        // run the operation in an own thread, so that we can stop waiting for it
+        let (tx, rx) = mpsc::sync_channel(1);
+        let (be, op) = (self.be.clone(), op.clone());
+        _ = thread::spawn(move || {
+            // the receiver may have gone after a timeout
+            _ = tx.send(op(&be));
+        });
+
+        rx.recv_timeout(timeout).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::Backend,
+                "Backend operation `{operation}` did not finish within `{timeout}`.",
+                err,
+            )
+            .attach_context("operation", operation.to_string())
+            .attach_context("timeout", format!("{timeout:?}"))
+            .attach_status(Status::Temporary)
+        })?
+    }
+
+    /// Run the operation, retrying it if it fails
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation, used in log messages and metrics
+    /// * `op` - The operation to run
+    ///
+    /// # Errors
+    ///
+    /// * If the operation failed with an error which is not retried or if all retries failed.
+    fn call<T: Send + 'static>(
+        &self,
+        operation: BackendOperation,
+        op: impl Fn(&B) -> RusticResult<T> + Send + Sync + 'static,
+    ) -> RusticResult<T> {
+        let op: Arc<dyn Fn(&B) -> RusticResult<T> + Send + Sync> = Arc::new(op);
+        (|| self.attempt(operation, &op))
+            .retry(self.opts.backoff())
+            .when(|err| (self.retryable)(err))
+            .notify(|err, duration| {
+                warn!(
+                    "{operation} failed, retrying in {duration:?}: {}",
+                    err.display_log()
+                );
+                if let Some(metrics) = &self.metrics {
+                    metrics.record_retry(operation);
+                }
+            })
+            .call()
+    }
+}
+
+impl<B: ReadBackend + ?Sized> ReadBackend for RetryBackend<B> {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.call(BackendOperation::List, move |be| be.list_with_size(tpe))
+    }
+
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        let id = *id;
+        self.call(BackendOperation::Stat, move |be| be.modified(tpe, &id))
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.call(BackendOperation::List, move |be| be.list(tpe))
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        let id = *id;
+        self.call(BackendOperation::Read, move |be| be.read_full(tpe, &id))
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        let id = *id;
+        self.call(BackendOperation::Read, move |be| {
+            be.read_partial(tpe, &id, cacheable, offset, length)
+        })
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.be.needs_warm_up()
+    }
+
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        let id = *id;
+        self.call(BackendOperation::WarmUp, move |be| be.warm_up(tpe, &id))
+    }
+}
+
+impl<B: WriteBackend + ?Sized> WriteBackend for RetryBackend<B> {
+    fn create(&self) -> RusticResult<()> {
+        self.call(BackendOperation::Create, WriteBackend::create)
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        let id = *id;
+        self.call(BackendOperation::Write, move |be| {
+            be.write_bytes(tpe, &id, cacheable, buf.clone())
+        })
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        let id = *id;
+        self.call(BackendOperation::Remove, move |be| {
+            be.remove(tpe, &id, cacheable)
+        })
+    }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        let id = *id;
+        self.call(BackendOperation::Write, move |be| {
+            be.write_bytes_locked(tpe, &id, cacheable, buf.clone(), until)
+        })
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        let id = *id;
+        self.call(BackendOperation::Read, move |be| be.locked_until(tpe, &id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    use crate::backend::MockBackend;
+
+    fn opts() -> RetryOptions {
+        RetryOptions::default()
+            .max_retries(3_usize)
+            .min_delay(Duration::from_millis(1))
+            .jitter(false)
+    }
+
+    #[test]
+    fn test_retry() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut be = MockBackend::new();
+        let counter = calls.clone();
+        _ = be.expect_read_full().returning(move |_, _| {
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(RusticError::new(ErrorKind::Backend, "temporary error"))
+            } else {
+                Ok(Bytes::from_static(b"data"))
+            }
+        });
+        _ = be.expect_remove().returning(|_, _, _| {
+            Err(RusticError::new(ErrorKind::Backend, "permanent error")
+                .attach_status(Status::Permanent))
+        });
+
+        let be = RetryBackend::new(Arc::new(be), opts());
+        assert_eq!(be.read_full(FileType::Pack, &Id::random()).unwrap(), "data");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // permanent errors are not retried
+        assert!(be.remove(FileType::Pack, &Id::random(), false).is_err());
+    }
+
+    #[test]
+    fn test_retry_timeout() {
+        let mut be = MockBackend::new();
+        _ = be.expect_read_full().returning(|_, _| {
+            thread::sleep(Duration::from_millis(100));
+            Ok(Bytes::new())
+        });
+
+        let be = RetryBackend::new(Arc::new(be), opts().timeout(Duration::from_millis(10)));
+        let err = be.read_full(FileType::Pack, &Id::random()).unwrap_err();
+        assert!(!err.is_permanent());
+    }
+}
@@ -14,13 +14,22 @@ use crate::{
 pub struct StdinSource {
     /// The path of the stdin entry.
     path: PathBuf,
+    /// The expected size of the data, used for progress reporting.
+    size: Option<u64>,
 }
 
 impl StdinSource {
     /// Creates a new `StdinSource`.
     #[must_use]
     pub const fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self { path, size: None }
+    }
+
+    /// Set the expected size of the data, which is used to show the backup progress.
+    #[must_use]
+    pub const fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
     }
 }
 
@@ -32,7 +41,7 @@ impl ReadSource for StdinSource {
 
     /// Returns the size of the source.
     fn size(&self) -> RusticResult<Option<u64>> {
-        Ok(None)
+        Ok(self.size)
     }
 
     /// Returns an iterator over the source.
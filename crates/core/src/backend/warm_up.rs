@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use bytes::Bytes;
+use jiff::Timestamp;
 
 use crate::{
     backend::{FileType, ReadBackend, WriteBackend},
@@ -35,6 +36,10 @@ impl ReadBackend for WarmUpAccessBackend {
         self.be.list_with_size(tpe)
     }
 
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
     fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
         self.be.read_full(tpe, id)
     }
@@ -54,6 +59,10 @@ impl ReadBackend for WarmUpAccessBackend {
         true
     }
 
+    fn supports_file_type(&self, tpe: FileType) -> bool {
+        self.be.supports_file_type(tpe)
+    }
+
     fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
         // warm up files by accessing them - error is ignored as we expect this to error out!
         _ = self.be.read_partial(tpe, id, false, 0, 1);
@@ -79,4 +88,23 @@ impl WriteBackend for WarmUpAccessBackend {
         // First remove cold file
         self.be.remove(tpe, id, cacheable)
     }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        self.be.write_bytes_locked(tpe, id, cacheable, buf, until)
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
 }
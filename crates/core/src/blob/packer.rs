@@ -1,6 +1,9 @@
 use std::{
     num::NonZeroU32,
-    sync::{Arc, RwLock},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
     thread::scope,
     time::{Duration, SystemTime},
 };
@@ -25,7 +28,7 @@ use crate::{
     repofile::{
         configfile::ConfigFile,
         indexfile::IndexPack,
-        packfile::{PackHeaderLength, PackHeaderRef, PackId},
+        packfile::{PackHeaderRef, PackId, PackLayout},
         snapshotfile::SnapshotSummary,
     },
 };
@@ -65,6 +68,37 @@ pub(super) mod constants {
     pub(super) const MAX_AGE: Duration = Duration::from_secs(300);
 }
 
+/// Checks the pack size parameters of a blob type
+///
+/// # Arguments
+///
+/// * `size` - The default size of a pack file.
+/// * `size_limit` - The size limit of a pack file.
+///
+/// # Errors
+///
+/// * If the pack size exceeds the maximum size of a pack.
+/// * If the size limit is smaller than the pack size.
+pub(crate) fn check_packsize_params(size: u32, size_limit: u32) -> RusticResult<()> {
+    if size > constants::MAX_SIZE {
+        return Err(RusticError::new(
+            ErrorKind::InvalidInput,
+            "Pack size `{size}` exceeds the maximum pack size of `{max_size}`.",
+        )
+        .attach_context("size", size.to_string())
+        .attach_context("max_size", constants::MAX_SIZE.to_string()));
+    }
+    if size_limit < size {
+        return Err(RusticError::new(
+            ErrorKind::InvalidInput,
+            "Pack size limit `{size_limit}` must be larger or equal than the pack size `{size}`.",
+        )
+        .attach_context("size", size.to_string())
+        .attach_context("size_limit", size_limit.to_string()));
+    }
+    Ok(())
+}
+
 /// The pack sizer is responsible for computing the size of the pack file.
 #[derive(Debug, Clone, Copy)]
 pub struct PackSizer {
@@ -168,6 +202,20 @@ impl PackSizer {
             < u64::from(target_size) * u64::from(self.min_packsize_tolerate_percent)
     }
 
+    /// Evaluates whether a pack with the given size and number of blobs is too small
+    ///
+    /// Packs which contain the maximum number of blobs can't get larger by repacking them and are
+    /// therefore never too small.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the pack
+    /// * `blob_count` - The number of blobs in the pack
+    #[must_use]
+    pub fn is_pack_too_small(&self, size: u32, blob_count: usize) -> bool {
+        blob_count < constants::MAX_COUNT as usize && self.is_too_small(size)
+    }
+
     /// Evaluates whether the given size is too large
     ///
     /// # Arguments
@@ -447,14 +495,15 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
     /// * `config` - The config file.
     /// * `total_size` - The total size of the pack file.
     fn new(be: BE, blob_type: BlobType, indexer: SharedIndexer<BE>, pack_sizer: PackSizer) -> Self {
+        let concurrency = be.upload_concurrency();
         let file_writer = Some(Actor::new(
             FileWriterHandle {
                 be: be.clone(),
                 indexer,
                 cacheable: blob_type.is_cacheable(),
             },
-            1,
-            1,
+            concurrency,
+            concurrency,
         ));
 
         Self {
@@ -551,6 +600,19 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
 
         let size_limit = self.pack_sizer.pack_size();
 
+        // align the blob start, if required by the pack layout
+        let padding = self.be.pack_layout().padding(self.size);
+        if padding > 0 {
+            _ = self.write_data(&vec![0; padding as usize]).map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::Internal,
+                    "Failed to write padding to packfile for blob `{id}`.",
+                    err,
+                )
+                .attach_context("id", id.to_string())
+            })?;
+        }
+
         let offset = self.size;
 
         let len = self.write_data(data).map_err(|err| {
@@ -629,9 +691,11 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
             .attach_context("length", headerlen.to_string())
         })?;
 
-        // convert header length to binary representation
-        let binary_repr = PackHeaderLength::from_u32(headerlen)
-            .to_binary()
+        // convert header length (or the footer, depending on the pack layout) to binary representation
+        let binary_repr = self
+            .be
+            .pack_layout()
+            .trailer(headerlen, self.count)
             .map_err(|err| {
                 RusticError::with_source(
                     ErrorKind::Internal,
@@ -671,6 +735,11 @@ impl<BE: DecryptWriteBackend> RawPacker<BE> {
 
         self.write_header()?;
 
+        // the pack size can only be computed from the blobs for the restic layout
+        if self.be.pack_layout() != PackLayout::Restic {
+            self.index.size = Some(self.size);
+        }
+
         // write file to backend
         let index = std::mem::take(&mut self.index);
         let file = std::mem::replace(&mut self.file, BytesMut::new());
@@ -725,7 +794,11 @@ impl<BE: DecryptWriteBackend> FileWriterHandle<BE> {
     }
 }
 
-// TODO: add documentation
+/// The `Actor` uploads finished pack files and adds them to the index.
+///
+/// Pack files are sent to the actor using a bounded queue, so that packing blobs is decoupled from the
+/// (potentially slow) upload to the backend. Multiple uploads are run concurrently to saturate backends
+/// with a high latency.
 pub(crate) struct Actor {
     /// The sender to send blobs to the raw packer.
     sender: Sender<(Bytes, IndexPack)>,
@@ -743,31 +816,50 @@ impl Actor {
     /// # Arguments
     ///
     /// * `fwh` - The file writer handle.
-    /// * `queue_len` - The length of the queue.
-    /// * `par` - The number of parallel threads.
+    /// * `queue_len` - The number of pack files which may wait for being uploaded.
+    /// * `par` - The number of concurrent uploads.
     fn new<BE: DecryptWriteBackend>(
         fwh: FileWriterHandle<BE>,
         queue_len: usize,
-        _par: usize,
+        par: usize,
     ) -> Self {
-        let (tx, rx) = bounded(queue_len);
+        let (tx, rx) = bounded::<(Bytes, IndexPack)>(queue_len);
         let (finish_tx, finish_rx) = bounded::<RusticResult<()>>(0);
 
         let _join_handle = std::thread::spawn(move || {
-            scope(|scope| {
-                let status = rx
+            // stop all uploads once an upload failed; this makes sending new pack files fail early
+            let failed = AtomicBool::new(false);
+            let status = scope(|scope| {
+                let uploaders: Vec<_> = (0..par.max(1))
+                    .map(|_| {
+                        let (rx, fwh, failed) = (rx.clone(), &fwh, &failed);
+                        scope.spawn(move || {
+                            rx.into_iter()
+                                .take_while(|_| !failed.load(Ordering::Relaxed))
+                                .try_for_each(|(file, index)| {
+                                    let id = PackId::from(hash(&file));
+                                    fwh.index(fwh.process((file, id, index))?)
+                                })
+                                .inspect_err(|_| failed.store(true, Ordering::Relaxed))
+                        })
+                    })
+                    .collect();
+                drop(rx);
+
+                uploaders
                     .into_iter()
-                    .readahead_scoped(scope)
-                    .map(|(file, index): (Bytes, IndexPack)| {
-                        let id = hash(&file);
-                        (file, PackId::from(id), index)
+                    .map(|uploader| {
+                        uploader.join().map_err(|_| {
+                            RusticError::new(
+                                ErrorKind::Internal,
+                                "Pack uploader thread panicked. Data may be lost.",
+                            )
+                            .ask_report()
+                        })?
                     })
-                    .readahead_scoped(scope)
-                    .map(|load| fwh.process(load))
-                    .readahead_scoped(scope)
-                    .try_for_each(|index| fwh.index(index?));
-                _ = finish_tx.send(status);
+                    .collect::<RusticResult<()>>()
             });
+            _ = finish_tx.send(status);
         });
 
         Self {
@@ -1028,4 +1120,26 @@ mod tests {
 
         assert_ron_snapshot!(output);
     }
+
+    #[test]
+    fn pack_too_small() {
+        let config = ConfigFile {
+            datapack_size: Some(128 * constants::MB),
+            datapack_growfactor: Some(0),
+            ..Default::default()
+        };
+        let pack_sizer = PackSizer::from_config(&config, BlobType::Data, 0);
+        assert_eq!(pack_sizer.pack_size(), 128 * constants::MB);
+        assert!(pack_sizer.is_pack_too_small(32 * constants::MB, 100));
+        assert!(!pack_sizer.is_pack_too_small(64 * constants::MB, 100));
+        // packs with the maximum number of blobs can't get larger
+        assert!(!pack_sizer.is_pack_too_small(32 * constants::MB, 10_000));
+    }
+
+    #[test]
+    fn invalid_packsize_params() {
+        assert!(check_packsize_params(128 * constants::MB, u32::MAX).is_ok());
+        assert!(check_packsize_params(128 * constants::MB, 64 * constants::MB).is_err());
+        assert!(check_packsize_params(u32::MAX, u32::MAX).is_err());
+    }
 }
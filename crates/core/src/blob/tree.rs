@@ -1,3 +1,4 @@
+pub mod diff;
 pub mod excludes;
 pub mod modify;
 pub mod rewrite;
@@ -475,6 +476,10 @@ pub struct TreeStreamerOptions {
     /// recursively list the dir
     #[cfg_attr(feature = "clap", clap(long))]
     pub recursive: bool,
+
+    /// only list entries up to the given depth when listing recursively; depth 1 means only the direct content of the dir
+    #[cfg_attr(feature = "clap", clap(long, value_name = "DEPTH"))]
+    pub max_depth: Option<usize>,
 }
 
 impl Default for TreeStreamerOptions {
@@ -482,6 +487,7 @@ impl Default for TreeStreamerOptions {
         Self {
             excludes: Excludes::default(),
             recursive: true,
+            max_depth: None,
         }
     }
 }
@@ -507,6 +513,8 @@ where
     overrides: Option<Override>,
     /// Whether to stream recursively
     recursive: bool,
+    /// The maximum depth to stream
+    max_depth: Option<usize>,
 }
 
 impl<'a, BE, I> NodeStreamer<'a, BE, I>
@@ -522,6 +530,7 @@ where
     /// * `node` - The node to start from.
     /// * `overrides` - The glob overrides.
     /// * `recursive` - Whether to stream recursively.
+    /// * `max_depth` - The maximum depth to stream, if any.
     ///
     /// # Errors
     ///
@@ -533,6 +542,7 @@ where
         node: &Node,
         overrides: Option<Override>,
         recursive: bool,
+        max_depth: Option<usize>,
     ) -> RusticResult<Self> {
         let inner = if node.is_dir() {
             Tree::from_backend(&be, index, node.subtree.unwrap())?
@@ -549,6 +559,7 @@ where
             index,
             overrides,
             recursive,
+            max_depth,
         })
     }
 
@@ -572,7 +583,14 @@ where
         opts: &TreeStreamerOptions,
     ) -> RusticResult<Self> {
         let overrides = opts.excludes.as_override()?;
-        Self::new_streamer(be, index, node, Some(overrides), opts.recursive)
+        Self::new_streamer(
+            be,
+            index,
+            node,
+            Some(overrides),
+            opts.recursive,
+            opts.max_depth,
+        )
     }
 }
 
@@ -589,7 +607,12 @@ where
             match self.inner.next() {
                 Some(node) => {
                     let path = self.path.join(node.name());
+                    // the content of the subtree would be at depth `open_iterators.len() + 2`
+                    let descend = self
+                        .max_depth
+                        .is_none_or(|depth| self.open_iterators.len() + 2 <= depth);
                     if self.recursive
+                        && descend
                         && let Some(id) = node.subtree
                     {
                         self.path.push(node.name());
@@ -665,6 +688,28 @@ impl TreeStreamerOnce {
         index: &I,
         ids: Vec<TreeId>,
         p: Progress,
+    ) -> RusticResult<Self> {
+        Self::new_with_visited(be, index, ids, BTreeSet::new(), p)
+    }
+
+    /// Creates a new `TreeStreamerOnce` which doesn't visit the given trees (including their subtrees).
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to read from.
+    /// * `ids` - The IDs of the trees to visit.
+    /// * `visited` - The IDs of the trees to treat as already visited.
+    /// * `p` - The progress indicator.
+    ///
+    /// # Errors
+    ///
+    /// * If sending the message fails.
+    pub(crate) fn new_with_visited<BE: DecryptReadBackend, I: ReadGlobalIndex>(
+        be: &BE,
+        index: &I,
+        ids: Vec<TreeId>,
+        visited: BTreeSet<TreeId>,
+        p: Progress,
     ) -> RusticResult<Self> {
         p.set_length(ids.len() as u64);
 
@@ -687,7 +732,7 @@ impl TreeStreamerOnce {
 
         let counter = vec![0; ids.len()];
         let mut streamer = Self {
-            visited: BTreeSet::new(),
+            visited,
             queue_in: Some(in_tx),
             queue_out: out_rx,
             p,
@@ -717,6 +762,11 @@ impl TreeStreamerOnce {
         Ok(streamer)
     }
 
+    /// Returns the IDs of all visited trees, including the trees initially treated as visited.
+    pub(crate) fn into_visited(self) -> BTreeSet<TreeId> {
+        self.visited
+    }
+
     /// Adds a tree ID to the queue.
     ///
     /// # Arguments
@@ -830,11 +880,12 @@ pub(crate) fn merge_trees(
     save: &impl Fn(Tree) -> RusticResult<(TreeId, u64)>,
     summary: &mut SnapshotSummary,
 ) -> RusticResult<TreeId> {
-    // We store nodes with the index of the tree in an Binary Heap where we sort only by node name
+    // We store nodes with the index of the tree in an Binary Heap where we sort by node name and then by
+    // the index of the tree, so that nodes with identical names are merged in the order of the given trees
     struct SortedNode(Node, usize);
     impl PartialEq for SortedNode {
         fn eq(&self, other: &Self) -> bool {
-            self.0.name == other.0.name
+            self.0.name == other.0.name && self.1 == other.1
         }
     }
     impl PartialOrd for SortedNode {
@@ -845,7 +896,11 @@ pub(crate) fn merge_trees(
     impl Eq for SortedNode {}
     impl Ord for SortedNode {
         fn cmp(&self, other: &Self) -> Ordering {
-            self.0.name.cmp(&other.0.name).reverse()
+            self.0
+                .name
+                .cmp(&other.0.name)
+                .then(self.1.cmp(&other.1))
+                .reverse()
         }
     }
 
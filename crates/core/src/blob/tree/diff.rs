@@ -0,0 +1,387 @@
+use std::{
+    cmp::Ordering,
+    iter::Peekable,
+    path::{Path, PathBuf},
+};
+
+use serde_derive::Serialize;
+
+use crate::{
+    DataId, RusticResult, TreeId,
+    backend::{
+        ReadSourceEntry, ReadSourceOpen,
+        decrypt::DecryptReadBackend,
+        ignore::{LocalSourceWalker, OpenFile},
+        node::Metadata,
+    },
+    blob::tree::NodeStreamer,
+    chunker::ChunkIter,
+    crypto::hasher::hash,
+    index::ReadGlobalIndex,
+    repofile::{ConfigFile, Node, Tree, snapshotfile::ChangeDetection},
+};
+
+/// The kind of change of a [`DiffEntry`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum DiffChange {
+    /// The entry only exists in the second tree
+    Added,
+    /// The entry only exists in the first tree
+    Removed,
+    /// The type or the contents of the entry changed
+    Modified,
+    /// Only the metadata (e.g. permissions or mtime) of the entry changed
+    MetadataOnly,
+}
+
+/// A difference between two trees
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct DiffEntry {
+    /// The path of the entry
+    pub path: PathBuf,
+    /// The kind of change
+    pub change: DiffChange,
+}
+
+/// The nodes of a directory in both trees which are compared
+type DiffFrame = (
+    PathBuf,
+    Peekable<std::vec::IntoIter<Node>>,
+    Peekable<std::vec::IntoIter<Node>>,
+);
+
+/// [`TreeDiffStreamer`] walks two trees in parallel and streams all differences in-order.
+///
+/// Subtrees with identical ids are skipped without reading them.
+#[derive(Debug)]
+pub struct TreeDiffStreamer<'a, BE, I> {
+    /// The backend to read from
+    be: BE,
+    /// The index
+    index: &'a I,
+    /// The directories which are currently compared
+    stack: Vec<DiffFrame>,
+}
+
+impl<'a, BE, I> TreeDiffStreamer<'a, BE, I>
+where
+    BE: DecryptReadBackend,
+    I: ReadGlobalIndex,
+{
+    /// Creates a new `TreeDiffStreamer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to read from.
+    /// * `index` - The index to use.
+    /// * `tree1` - The id of the first (old) tree.
+    /// * `tree2` - The id of the second (new) tree.
+    ///
+    /// # Errors
+    ///
+    /// * If one of the trees could not be read.
+    pub fn new(be: BE, index: &'a I, tree1: TreeId, tree2: TreeId) -> RusticResult<Self> {
+        let mut streamer = Self {
+            be,
+            index,
+            stack: Vec::new(),
+        };
+        if tree1 != tree2 {
+            streamer.push(PathBuf::new(), Some(tree1), Some(tree2))?;
+        }
+        Ok(streamer)
+    }
+
+    /// Start comparing the given subtrees; a missing subtree is treated as empty directory.
+    fn push(
+        &mut self,
+        path: PathBuf,
+        tree1: Option<TreeId>,
+        tree2: Option<TreeId>,
+    ) -> RusticResult<()> {
+        let nodes = |tree: Option<TreeId>| -> RusticResult<_> {
+            Ok(match tree {
+                Some(id) => Tree::from_backend(&self.be, self.index, id)?.nodes,
+                None => Vec::new(),
+            }
+            .into_iter()
+            .peekable())
+        };
+        let frame = (path, nodes(tree1)?, nodes(tree2)?);
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    /// Compare two nodes with the same name and start comparing their subtrees, if needed.
+    fn compare(
+        &mut self,
+        path: PathBuf,
+        node1: &Node,
+        node2: &Node,
+    ) -> RusticResult<Option<DiffEntry>> {
+        let change = if node1.node_type != node2.node_type || node1.content != node2.content {
+            Some(DiffChange::Modified)
+        } else if same_metadata(&node1.meta, &node2.meta) {
+            None
+        } else {
+            Some(DiffChange::MetadataOnly)
+        };
+
+        if node1.subtree != node2.subtree {
+            self.push(path.clone(), node1.subtree, node2.subtree)?;
+        }
+        Ok(change.map(|change| DiffEntry { path, change }))
+    }
+
+    /// Process the next node of the current directory
+    ///
+    /// # Returns
+    ///
+    /// `None` if the stack is empty, `Some(None)` if a node was processed without a difference.
+    fn step(&mut self) -> Option<RusticResult<Option<DiffEntry>>> {
+        let (path, nodes1, nodes2) = self.stack.last_mut()?;
+        let order = match (nodes1.peek(), nodes2.peek()) {
+            (None, None) => None,
+            (Some(_), None) => Some(Ordering::Less),
+            (None, Some(_)) => Some(Ordering::Greater),
+            (Some(node1), Some(node2)) => Some(node1.name.cmp(&node2.name)),
+        };
+        let Some(order) = order else {
+            // directory finished
+            _ = self.stack.pop();
+            return Some(Ok(None));
+        };
+
+        let (change, node1, node2) = match order {
+            Ordering::Less => (DiffChange::Removed, nodes1.next(), None),
+            Ordering::Greater => (DiffChange::Added, None, nodes2.next()),
+            Ordering::Equal => (DiffChange::Modified, nodes1.next(), nodes2.next()),
+        };
+        let path = path.join(node1.as_ref().or(node2.as_ref())?.name());
+
+        let result = match (node1, node2) {
+            (Some(node1), Some(node2)) => self.compare(path, &node1, &node2),
+            (node1, node2) => {
+                let subtrees = (
+                    node1.and_then(|node| node.subtree),
+                    node2.and_then(|node| node.subtree),
+                );
+                let pushed = if subtrees == (None, None) {
+                    Ok(())
+                } else {
+                    self.push(path.clone(), subtrees.0, subtrees.1)
+                };
+                pushed.map(|()| Some(DiffEntry { path, change }))
+            }
+        };
+        Some(result)
+    }
+}
+
+impl<BE, I> Iterator for TreeDiffStreamer<'_, BE, I>
+where
+    BE: DecryptReadBackend,
+    I: ReadGlobalIndex,
+{
+    type Item = RusticResult<DiffEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.step()? {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+/// Compare metadata, ignoring the access time
+fn same_metadata(meta1: &Metadata, meta2: &Metadata) -> bool {
+    Metadata {
+        atime: None,
+        ..meta1.clone()
+    } == Metadata {
+        atime: None,
+        ..meta2.clone()
+    }
+}
+
+/// Returns the path of a local entry relative to the local directory
+fn relative<'p>(root: &Path, path: &'p Path) -> &'p Path {
+    path.strip_prefix(root).unwrap_or(path)
+}
+
+/// Compare the permissions and ownership of two nodes
+fn same_permissions(meta1: &Metadata, meta2: &Metadata) -> bool {
+    meta1.mode == meta2.mode
+        && meta1.uid == meta2.uid
+        && meta1.gid == meta2.gid
+        && meta1.user == meta2.user
+        && meta1.group == meta2.group
+}
+
+/// [`LocalDiffStreamer`] compares a tree with a local directory and streams all differences in-order.
+///
+/// Entries only present in the tree are reported as [`DiffChange::Removed`], entries only present locally
+/// as [`DiffChange::Added`]. Whether a file is modified is decided using the given [`ChangeDetection`];
+/// with [`ChangeDetection::Content`], the local file is chunked and its blob ids are compared with the
+/// contents of the tree.
+pub(crate) struct LocalDiffStreamer<'a, BE, I>
+where
+    BE: DecryptReadBackend,
+    I: ReadGlobalIndex,
+{
+    /// The nodes of the tree
+    nodes: Peekable<NodeStreamer<'a, BE, I>>,
+    /// The entries of the local directory
+    entries: Peekable<LocalSourceWalker>,
+    /// The local directory
+    root: PathBuf,
+    /// The config file of the repository, needed to chunk local files
+    config: &'a ConfigFile,
+    /// How to detect changed files
+    change_detection: ChangeDetection,
+}
+
+impl<'a, BE, I> LocalDiffStreamer<'a, BE, I>
+where
+    BE: DecryptReadBackend,
+    I: ReadGlobalIndex,
+{
+    /// Creates a new `LocalDiffStreamer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `nodes` - The nodes of the tree to compare, with paths relative to the tree.
+    /// * `entries` - The entries of the local directory.
+    /// * `root` - The local directory.
+    /// * `config` - The config file of the repository.
+    /// * `change_detection` - How to detect changed files.
+    pub(crate) fn new(
+        nodes: NodeStreamer<'a, BE, I>,
+        entries: LocalSourceWalker,
+        root: PathBuf,
+        config: &'a ConfigFile,
+        change_detection: ChangeDetection,
+    ) -> Self {
+        Self {
+            nodes: nodes.peekable(),
+            entries: entries.peekable(),
+            root,
+            config,
+            change_detection,
+        }
+    }
+
+    /// Compare a node of the tree with the local entry of the same path
+    fn compare(
+        &self,
+        node: &Node,
+        entry: ReadSourceEntry<OpenFile>,
+    ) -> RusticResult<Option<DiffChange>> {
+        let (meta, local) = (&node.meta, &entry.node.meta);
+        if node.node_type != entry.node.node_type {
+            return Ok(Some(DiffChange::Modified));
+        }
+
+        let mut same_meta = same_permissions(meta, local);
+        if node.is_file() {
+            let modified = if self.change_detection == ChangeDetection::Content {
+                same_meta &= meta.mtime == local.mtime;
+                meta.size != local.size || self.content_changed(node, entry.open)?
+            } else {
+                let check_ctime = self.change_detection >= ChangeDetection::Ctime;
+                let check_inode = self.change_detection >= ChangeDetection::Inode;
+                meta.size != local.size
+                    || meta.mtime != local.mtime
+                    || (check_ctime && meta.ctime.zip(local.ctime).is_some_and(|(x, y)| x != y))
+                    || (check_inode
+                        && meta.inode != 0
+                        && local.inode != 0
+                        && meta.inode != local.inode)
+            };
+            if modified {
+                return Ok(Some(DiffChange::Modified));
+            }
+        }
+        Ok((!same_meta).then_some(DiffChange::MetadataOnly))
+    }
+
+    /// Chunk the local file and compare the resulting blob ids with the contents of the node
+    fn content_changed(&self, node: &Node, open: Option<OpenFile>) -> RusticResult<bool> {
+        let Some(open) = open else {
+            return Ok(true);
+        };
+        let size_hint = usize::try_from(node.meta.size).unwrap_or(usize::MAX);
+        let mut content = node.content.iter().flatten();
+        for chunk in ChunkIter::from_config(self.config, open.open()?, size_hint)? {
+            let id = DataId::from(hash(&chunk?));
+            if content.next() != Some(&id) {
+                return Ok(true);
+            }
+        }
+        Ok(content.next().is_some())
+    }
+
+    /// Process the next node or local entry
+    ///
+    /// # Returns
+    ///
+    /// `None` if both sides are finished, `Some(None)` if an entry was processed without a difference.
+    fn step(&mut self) -> Option<RusticResult<Option<DiffEntry>>> {
+        let order = match (self.nodes.peek(), self.entries.peek()) {
+            (None, None) => return None,
+            (Some(Err(_)), _) => return self.nodes.next().map(|item| item.map(|_| None)),
+            (_, Some(Err(_))) => return self.entries.next().map(|item| item.map(|_| None)),
+            (Some(Ok(_)), None) => Ordering::Less,
+            (None, Some(Ok(_))) => Ordering::Greater,
+            (Some(Ok((path, _))), Some(Ok(entry))) => {
+                path.as_path().cmp(relative(&self.root, &entry.path))
+            }
+        };
+
+        let result = match order {
+            Ordering::Less => self.nodes.next()?.map(|(path, _)| {
+                Some(DiffEntry {
+                    path,
+                    change: DiffChange::Removed,
+                })
+            }),
+            Ordering::Greater => self.entries.next()?.map(|entry| {
+                Some(DiffEntry {
+                    path: relative(&self.root, &entry.path).to_path_buf(),
+                    change: DiffChange::Added,
+                })
+            }),
+            Ordering::Equal => match (self.nodes.next()?, self.entries.next()?) {
+                (Ok((path, node)), Ok(entry)) => self
+                    .compare(&node, entry)
+                    .map(|change| change.map(|change| DiffEntry { path, change })),
+                (Err(err), _) | (_, Err(err)) => Err(err),
+            },
+        };
+        Some(result)
+    }
+}
+
+impl<BE, I> Iterator for LocalDiffStreamer<'_, BE, I>
+where
+    BE: DecryptReadBackend,
+    I: ReadGlobalIndex,
+{
+    type Item = RusticResult<DiffEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.step()? {
+                Ok(Some(entry)) => return Some(Ok(entry)),
+                Ok(None) => {}
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use super::TreeId;
 use crate::{
@@ -81,6 +84,8 @@ pub struct TreeModifier<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex> {
     indexer: SharedIndexer<BE>,
     packer: Packer<BE>,
     dry_run: bool,
+    /// size of the (serialized) trees which have been (or would have been in dry-run mode) saved
+    saved_bytes: AtomicU64,
 }
 
 impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> TreeModifier<'a, BE, I> {
@@ -96,6 +101,7 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> TreeModifier<'a, BE, I> {
             indexer,
             packer,
             dry_run,
+            saved_bytes: AtomicU64::new(0),
         })
     }
 
@@ -168,14 +174,29 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> TreeModifier<'a, BE, I> {
         Ok(new_id.map_or_else(|| ModifierChange::Unchanged, ModifierChange::Changed))
     }
 
+    /// The indexer used for the saved trees
+    pub fn indexer(&self) -> &SharedIndexer<BE> {
+        &self.indexer
+    }
+
+    /// The size of the (serialized) trees which have been (or would have been in dry-run mode) saved
+    pub fn saved_bytes(&self) -> u64 {
+        self.saved_bytes.load(Ordering::Relaxed)
+    }
+
     pub fn save_tree(&self, new_tree: &Tree) -> RusticResult<TreeId> {
         let (chunk, new_id) = new_tree.serialize().map_err(|err| {
             RusticError::with_source(ErrorKind::Internal, "Failed to serialize tree.", err)
                 .ask_report()
         })?;
 
-        if !self.index.has_tree(&new_id) && !self.dry_run {
-            self.packer.add(chunk.into(), BlobId::from(*new_id))?;
+        if !self.index.has_tree(&new_id) {
+            _ = self
+                .saved_bytes
+                .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+            if !self.dry_run {
+                self.packer.add(chunk.into(), BlobId::from(*new_id))?;
+            }
         }
         Ok(new_id)
     }
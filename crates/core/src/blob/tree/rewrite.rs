@@ -2,16 +2,19 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     fmt::Debug,
     ops::AddAssign,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use derive_more::Add;
 use derive_setters::Setters;
-use ignore::{Match, overrides::Override};
+use ignore::{
+    Match,
+    overrides::{Override, OverrideBuilder},
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    RusticResult, TreeId,
+    ErrorKind, RusticError, RusticResult, TreeId,
     backend::{
         decrypt::{DecryptFullBackend, DecryptWriteBackend},
         node::modification::NodeModification,
@@ -36,6 +39,11 @@ pub struct RewriteTreesOptions {
     #[cfg_attr(feature = "clap", clap(flatten))]
     pub excludes: Excludes,
 
+    /// Glob pattern of paths to remove from the trees (can be specified multiple times)
+    #[cfg_attr(feature = "clap", clap(long, value_name = "GLOB"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::vec::overwrite_empty))]
+    pub remove: Vec<String>,
+
     /// Node modifications
     #[cfg_attr(feature = "clap", clap(flatten))]
     pub node_modification: NodeModification,
@@ -49,6 +57,7 @@ pub struct RewriteTreesOptions {
 #[derive(Debug)]
 pub struct RewriteVisitor {
     overrides: Override,
+    remove: Override,
     node_modification: NodeModification,
     all_trees: bool,
     changed: BTreeMap<(PathBuf, TreeId), TreeId>,
@@ -60,6 +69,7 @@ impl RewriteVisitor {
     pub fn new(opts: &RewriteTreesOptions) -> RusticResult<Self> {
         Ok(Self {
             overrides: opts.excludes.as_override()?,
+            remove: remove_override(&opts.remove)?,
             node_modification: opts.node_modification.clone(),
             all_trees: opts.all_trees,
             changed: BTreeMap::new(),
@@ -67,6 +77,37 @@ impl RewriteVisitor {
             summary: BTreeMap::new(),
         })
     }
+
+    /// Determines if the node at the given path is removed by the excludes or the paths to remove
+    fn is_removed(&self, path: &Path, is_dir: bool) -> bool {
+        matches!(self.overrides.matched(path, is_dir), Match::Ignore(_))
+            || matches!(self.remove.matched(path, is_dir), Match::Whitelist(_))
+    }
+}
+
+/// Build the matcher for the glob patterns of paths to remove.
+///
+/// In contrast to [`Excludes`], the patterns are plain gitignore patterns: A path matching any of
+/// them is removed.
+fn remove_override(globs: &[String]) -> RusticResult<Override> {
+    let mut override_builder = OverrideBuilder::new("");
+    for glob in globs {
+        _ = override_builder.add(glob).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InvalidInput,
+                "Invalid glob pattern `{glob}` of paths to remove.",
+                err,
+            )
+            .attach_context("glob", glob)
+        })?;
+    }
+    override_builder.build().map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::InvalidInput,
+            "Failed to build matcher for the paths to remove.",
+            err,
+        )
+    })
 }
 
 impl Visitor for RewriteVisitor {
@@ -82,7 +123,7 @@ impl Visitor for RewriteVisitor {
 
     fn process_node(&mut self, path: &PathBuf, mut node: Node, id: TreeId) -> NodeAction {
         self.summary.entry(id).or_default().update(&node);
-        if let Match::Ignore(_) = self.overrides.matched(path, node.is_dir()) {
+        if self.is_removed(path, node.is_dir()) {
             NodeAction::Removed
         } else {
             let changed = self.node_modification.modify_node(&mut node) | self.all_trees;
@@ -140,7 +181,7 @@ impl<'a, BE: DecryptFullBackend, I: ReadGlobalIndex> Rewriter<'a, BE, I> {
     }
 
     pub fn rewrite_tree(&mut self, path: PathBuf, id: TreeId) -> RusticResult<ModifierChange> {
-        if let Match::Ignore(_) = self.visitor.overrides.matched(&path, true) {
+        if self.visitor.is_removed(&path, true) {
             Ok(ModifierChange::Removed)
         } else {
             self.modifier.modify_tree(path, id, &mut self.visitor)
@@ -0,0 +1,116 @@
+use std::{
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::error::{ErrorKind, RusticError, RusticResult};
+
+/// A token to cooperatively cancel long-running operations like backup, restore, copy or prune.
+///
+/// Clones of a token share their state, so one clone can be given to the [`Repository`](crate::Repository)
+/// while another one is used to cancel, e.g. from a signal handler or another thread. Cancelled
+/// operations stop at the next safe point, write their pending packs and index files and return an
+/// error of kind [`ErrorKind::Cancelled`], see [`RusticError::is_cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a new, not cancelled `CancellationToken`
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all operations using this token or a clone of it
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Check if the token has been cancelled
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Return an error if the token has been cancelled
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation which is cancelled, used in the error message
+    ///
+    /// # Errors
+    ///
+    /// * If the token has been cancelled.
+    pub(crate) fn check(&self, operation: &str) -> RusticResult<()> {
+        if self.is_cancelled() {
+            return Err(cancelled(operation));
+        }
+        Ok(())
+    }
+}
+
+/// The error returned by cancelled operations
+///
+/// # Arguments
+///
+/// * `operation` - The operation which has been cancelled
+pub(crate) fn cancelled(operation: &str) -> Box<RusticError> {
+    RusticError::new(
+        ErrorKind::Cancelled,
+        "The operation `{operation}` has been cancelled.",
+    )
+    .attach_context("operation", operation.to_string())
+}
+
+/// A handle to pause and resume long-running operations like backup or restore.
+///
+/// Clones of a handle share their state. Paused operations wait at the next safe point, e.g. before
+/// processing the next file or pack, until they are resumed or cancelled. All state of the operation is kept
+/// while it is paused.
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle(Arc<(Mutex<bool>, Condvar)>);
+
+impl PauseHandle {
+    /// Create a new, not paused `PauseHandle`
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pause all operations using this handle or a clone of it
+    pub fn pause(&self) {
+        *self.0.0.lock().unwrap() = true;
+    }
+
+    /// Resume all operations using this handle or a clone of it
+    pub fn resume(&self) {
+        *self.0.0.lock().unwrap() = false;
+        self.0.1.notify_all();
+    }
+
+    /// Check if the handle is paused
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        *self.0.0.lock().unwrap()
+    }
+
+    /// Block while the handle is paused and the operation is not cancelled
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - The token to cancel the operation
+    pub(crate) fn wait(&self, cancel: &CancellationToken) {
+        let (paused, resumed) = &*self.0;
+        let mut paused = paused.lock().unwrap();
+        // the cancellation token doesn't notify, so check it regularly
+        while *paused && !cancel.is_cancelled() {
+            paused = resumed
+                .wait_timeout(paused, Duration::from_millis(100))
+                .unwrap()
+                .0;
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use std::io::Read;
 
+pub(crate) mod fastcdc;
 mod fixed_size;
 pub mod rabin;
 
+use fastcdc::ChunkIter as FastCdcChunkIter;
 use fixed_size::ChunkIter as FixedSizeChunkIter;
 use rabin::ChunkIter as RabinChunkIter;
 use rustic_cdc::Rabin64;
@@ -15,6 +17,7 @@ use crate::{
 /// `ChunkIter` is an iterator that chunks data.
 pub(crate) enum ChunkIter<R: Read + Send> {
     Rabin(Box<RabinChunkIter<R>>),
+    FastCdc(Box<FastCdcChunkIter<R>>),
     FixedSize(FixedSizeChunkIter<R>),
 }
 
@@ -37,6 +40,14 @@ impl<R: Read + Send> ChunkIter<R> {
                     size_hint,
                 )?))
             }
+            Chunker::FastCdc => Self::FastCdc(Box::new(FastCdcChunkIter::new(
+                config.poly()?,
+                config.chunk_size(),
+                config.chunk_min_size(),
+                config.chunk_max_size(),
+                reader,
+                size_hint,
+            )?)),
             Chunker::FixedSize => Self::FixedSize(FixedSizeChunkIter::new(
                 config.chunk_size(),
                 reader,
@@ -53,6 +64,7 @@ impl<R: Read + Send> Iterator for ChunkIter<R> {
     fn next(&mut self) -> Option<Self::Item> {
         match self {
             Self::Rabin(rabin) => rabin.next(),
+            Self::FastCdc(fastcdc) => fastcdc.next(),
             Self::FixedSize(fixed_size) => fixed_size.next(),
         }
     }
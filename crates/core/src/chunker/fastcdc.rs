@@ -0,0 +1,273 @@
+use std::io::Read;
+
+use crate::error::{ErrorKind, RusticError, RusticResult};
+
+pub(crate) fn check_fastcdc_params(
+    chunk_size: usize,
+    chunk_min_size: usize,
+    chunk_max_size: usize,
+) -> RusticResult<()> {
+    if chunk_size < 64 {
+        return Err(RusticError::new(
+            ErrorKind::Unsupported,
+            "Chunk size must be at least 64 bytes for the fastcdc chunker. chunk size = {chunk_size}.",
+        )
+        .attach_context("chunk_size", chunk_size.to_string()));
+    }
+    if chunk_min_size > chunk_size {
+        return Err(RusticError::new(
+            ErrorKind::Unsupported,
+            "Chunk min size must be smaller or equal than the chunk size.",
+        ));
+    }
+    if chunk_max_size < chunk_size {
+        return Err(RusticError::new(
+            ErrorKind::Unsupported,
+            "Chunk max size must be larger or equal than the chunk size.",
+        ));
+    }
+    Ok(())
+}
+
+/// Create the table of random values used by the gear hash.
+///
+/// The table is derived from a per-repository seed, so that chunk boundaries can't be predicted
+/// without knowing the repository config.
+fn gear_table(seed: u64) -> [u64; 256] {
+    // splitmix64
+    let mut state = seed;
+    let mut table = [0; 256];
+    for value in &mut table {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        *value = z ^ (z >> 31);
+    }
+    table
+}
+
+/// A mask selecting the given number of highest bits, which are the best mixed bits of the gear hash.
+const fn mask(bits: u32) -> u64 {
+    if bits == 0 {
+        0
+    } else {
+        u64::MAX << (64 - bits)
+    }
+}
+
+/// `ChunkIter` is an iterator that chunks data using `FastCDC`.
+///
+/// This implements the normalized chunking of "`FastCDC`: a Fast and Efficient Content-Defined
+/// Chunking Approach for Data Deduplication" (Xia et al., 2016): Before reaching the average chunk
+/// size a stricter mask is used to find a cut point, afterwards a looser one. This results in chunk
+/// sizes being more concentrated around the average chunk size.
+pub(crate) struct ChunkIter<R: Read + Send> {
+    /// The data which has been read but not yet been returned.
+    buf: Vec<u8>,
+
+    /// The reader.
+    reader: R,
+
+    /// The random values used by the gear hash.
+    gear: [u64; 256],
+
+    /// The mask used before the average chunk size is reached.
+    mask_small: u64,
+
+    /// The mask used after the average chunk size is reached.
+    mask_large: u64,
+
+    /// The size hint is used to optimize memory allocation; this should be an upper bound on the size.
+    size_hint: usize,
+
+    /// The minimum size of a chunk.
+    min_size: usize,
+
+    /// The average size of a chunk.
+    avg_size: usize,
+
+    /// The maximum size of a chunk.
+    max_size: usize,
+
+    /// If the reader is exhausted.
+    finished: bool,
+}
+
+impl<R: Read + Send> ChunkIter<R> {
+    /// Creates a new `ChunkIter`.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - The seed of the gear hash.
+    /// * `chunk_size` - The average size of a chunk.
+    /// * `chunk_min_size` - The minimum size of a chunk.
+    /// * `chunk_max_size` - The maximum size of a chunk.
+    /// * `reader` - The reader to read from.
+    /// * `size_hint` - The size hint is used to optimize memory allocation; this should be an upper bound on the size.
+    ///
+    /// # Errors
+    ///
+    /// * If the chunk sizes are invalid.
+    pub(crate) fn new(
+        seed: u64,
+        chunk_size: usize,
+        chunk_min_size: usize,
+        chunk_max_size: usize,
+        reader: R,
+        size_hint: usize,
+    ) -> RusticResult<Self> {
+        check_fastcdc_params(chunk_size, chunk_min_size, chunk_max_size)?;
+        let bits = chunk_size.ilog2();
+        Ok(Self {
+            buf: Vec::with_capacity(size_hint.min(chunk_max_size)),
+            reader,
+            gear: gear_table(seed),
+            mask_small: mask(bits + 1),
+            mask_large: mask(bits - 1),
+            size_hint, // size hint is used to optimize memory allocation; this should be an upper bound on the size
+            min_size: chunk_min_size,
+            avg_size: chunk_size,
+            max_size: chunk_max_size,
+            finished: false,
+        })
+    }
+
+    /// Find the cut point of the next chunk within the given data.
+    fn cut_point(&self, data: &[u8]) -> usize {
+        let len = data.len();
+        if len <= self.min_size {
+            return len;
+        }
+        let normal_size = self.avg_size.clamp(self.min_size, len);
+        let max_size = self.max_size.min(len);
+
+        let mut hash = 0_u64;
+        for (i, byte) in data[..max_size].iter().enumerate().skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(self.gear[usize::from(*byte)]);
+            let mask = if i < normal_size {
+                self.mask_small
+            } else {
+                self.mask_large
+            };
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        max_size
+    }
+}
+
+impl<R: Read + Send> Iterator for ChunkIter<R> {
+    type Item = RusticResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // fill the buffer up to the maximum chunk size
+        if !self.finished && self.buf.len() < self.max_size {
+            let missing = self.max_size - self.buf.len();
+            match (&mut self.reader)
+                .take(missing as u64)
+                .read_to_end(&mut self.buf)
+            {
+                Ok(size) => self.finished = size < missing,
+                Err(err) => {
+                    return Some(Err(RusticError::with_source(
+                        ErrorKind::InputOutput,
+                        "Failed to read from reader in iterator",
+                        err,
+                    )));
+                }
+            }
+        }
+
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let cut = self.cut_point(&self.buf);
+        let chunk: Vec<_> = self.buf.drain(..cut).collect();
+        self.size_hint = self.size_hint.saturating_sub(chunk.len()); // size_hint can be too small!
+        Some(Ok(chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prelude::*;
+    use std::io::Cursor;
+
+    /// The average size of a chunk.
+    const SIZE: usize = 64 * 1024;
+    /// The minimum size of a chunk.
+    const MIN_SIZE: usize = 16 * 1024;
+    /// The maximum size of a chunk.
+    const MAX_SIZE: usize = 256 * 1024;
+    /// The seed of the gear hash.
+    const SEED: u64 = 0x003D_A335_8B4D_C173;
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        ChunkIter::new(SEED, SIZE, MIN_SIZE, MAX_SIZE, Cursor::new(data), 0)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    fn random_data(size: usize) -> Vec<u8> {
+        let mut rng = StdRng::seed_from_u64(23);
+        let mut data = vec![0u8; size];
+        rng.fill_bytes(&mut data);
+        data
+    }
+
+    #[test]
+    fn chunk_random() {
+        let data = random_data(8 * 1024 * 1024);
+        let chunks = chunks(&data);
+
+        assert_eq!(chunks.concat(), data);
+        let (last, chunks) = chunks.split_last().unwrap();
+        assert!(last.len() <= MAX_SIZE);
+        assert!(
+            chunks
+                .iter()
+                .all(|chunk| (MIN_SIZE..=MAX_SIZE).contains(&chunk.len()))
+        );
+        // normalized chunking concentrates the chunk sizes around the average size
+        let avg = data.len() / (chunks.len() + 1);
+        assert!((SIZE / 2..SIZE * 2).contains(&avg), "average size {avg}");
+    }
+
+    #[test]
+    fn chunk_shifted() {
+        let data = random_data(4 * 1024 * 1024);
+        let mut shifted = vec![42];
+        shifted.extend_from_slice(&data);
+
+        // after the first cut point, the chunks are identical
+        let chunks1 = chunks(&data);
+        let chunks2 = chunks(&shifted);
+        assert_eq!(chunks1[1..], chunks2[1..]);
+    }
+
+    #[test]
+    fn chunk_empty() {
+        assert!(chunks(&[]).is_empty());
+        let chunker = ChunkIter::new(SEED, SIZE, MIN_SIZE, MAX_SIZE, Cursor::new([]), 100).unwrap();
+        assert_eq!(0, chunker.count());
+    }
+
+    #[test]
+    fn chunk_small() {
+        let data = random_data(MIN_SIZE - 1);
+        assert_eq!(chunks(&data), vec![data]);
+    }
+
+    #[test]
+    fn invalid_params() {
+        assert!(check_fastcdc_params(32, 16, 64).is_err());
+        assert!(check_fastcdc_params(SIZE, SIZE + 1, MAX_SIZE).is_err());
+        assert!(check_fastcdc_params(SIZE, MIN_SIZE, SIZE - 1).is_err());
+        assert!(check_fastcdc_params(SIZE, MIN_SIZE, MAX_SIZE).is_ok());
+    }
+}
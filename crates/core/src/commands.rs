@@ -6,12 +6,25 @@ pub mod check;
 pub mod config;
 pub mod copy;
 pub mod dump;
+pub mod find;
 pub mod forget;
+pub mod index_gc;
 pub mod init;
+pub mod journal;
 pub mod key;
+pub mod maintain;
 pub mod merge;
+pub mod migrate;
+pub mod prewarm;
 pub mod prune;
+pub mod quarantine;
+pub mod recover;
+pub mod references;
+pub mod rekey;
 pub mod repair;
 pub mod repoinfo;
 pub mod restore;
 pub mod rewrite;
+pub mod scrub;
+pub mod stats;
+pub mod summary;
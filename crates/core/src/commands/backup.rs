@@ -1,9 +1,14 @@
 //! `backup` subcommand
+use bytesize::ByteSize;
 use derive_setters::Setters;
 use itertools::Itertools;
-use log::info;
+use log::{info, warn};
 
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use path_dedot::ParseDot;
 use serde_derive::{Deserialize, Serialize};
@@ -11,18 +16,28 @@ use serde_with::{DisplayFromStr, serde_as};
 
 use crate::{
     CommandInput, Excludes, ReadSource,
-    archiver::{Archiver, parent::Parent},
+    archiver::{
+        Archiver,
+        incremental::{IncrementalArchiver, TreeChange, snapshot_path},
+        parent::Parent,
+    },
     backend::{
+        cache::CacheLock,
         childstdout::ChildStdoutSource,
+        decrypt::DecryptWriteBackend,
         dry_run::DryRunBackend,
+        filter::BackupFilter,
         ignore::{LocalSource, LocalSourceFilterOptions, LocalSourceSaveOptions},
+        manifest::{ManifestEntry, ManifestSource},
+        reader::ReaderSource,
+        remap::RemapSource,
         stdin::StdinSource,
     },
     error::{ErrorKind, RusticError, RusticResult},
     repofile::{
         PathList, SnapshotFile,
         snapshotfile::{
-            SnapshotId,
+            ChangeDetection, PathRemap, SnapshotId, SnapshotRoot,
             grouping::{SnapshotGroup, SnapshotGroupCriterion},
         },
     },
@@ -76,9 +91,25 @@ pub struct ParentOptions {
     #[cfg_attr(feature = "clap", clap(long, conflicts_with = "force"))]
     #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
     pub ignore_inode: bool,
+
+    /// Level of checks to decide whether a file is unchanged w.r.t. the parent snapshot [default: inode]
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "LEVEL", conflicts_with = "force")
+    )]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub change_detection: Option<ChangeDetection>,
 }
 
 impl ParentOptions {
+    /// The level of checks to decide whether a file is unchanged.
+    ///
+    /// Note that `ignore_ctime` and `ignore_inode` are applied on top of this level.
+    #[must_use]
+    pub fn change_detection_level(&self) -> ChangeDetection {
+        self.change_detection.unwrap_or_default()
+    }
+
     /// Get parent snapshot.
     ///
     /// # Type Parameters
@@ -107,6 +138,7 @@ impl ParentOptions {
             SnapshotFile::latest(
                 repo.dbe(),
                 |snap| group.matches(snap),
+                repo.snapshot_error_policy(),
                 &repo.progress_counter(""),
             )
             .ok()
@@ -117,6 +149,7 @@ impl ParentOptions {
                 repo.dbe(),
                 &self.parents,
                 |snap| group.matches(snap),
+                repo.snapshot_error_policy(),
                 &repo.progress_counter(""),
             )
             .unwrap_or_default()
@@ -133,6 +166,7 @@ impl ParentOptions {
                 repo.dbe(),
                 repo.index(),
                 parent_trees,
+                self.change_detection_level(),
                 self.ignore_ctime,
                 self.ignore_inode,
             ),
@@ -140,6 +174,7 @@ impl ParentOptions {
     }
 }
 
+#[serde_as]
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
 #[cfg_attr(feature = "merge", derive(conflate::Merge))]
 #[derive(Clone, Default, Debug, Deserialize, Serialize, Setters)]
@@ -161,11 +196,37 @@ pub struct BackupOptions {
     #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
     pub stdin_command: Option<CommandInput>,
 
+    /// Expected size of the data read from stdin, used to show the backup progress
+    #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub stdin_size: Option<ByteSize>,
+
     /// Manually set backup path in snapshot
     #[cfg_attr(feature = "clap", clap(long, value_name = "PATH", value_hint = ValueHint::DirPath))]
     #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
     pub as_path: Option<PathBuf>,
 
+    /// Record a backup path under a different path in the snapshot, e.g. `/var/lib/app=/app-data`
+    /// (can be specified multiple times). Only used when backing up local paths.
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long = "remap",
+            value_name = "SOURCE=TARGET",
+            conflicts_with = "as_path"
+        )
+    )]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::vec::overwrite_empty))]
+    pub remap: Vec<PathRemap>,
+
+    /// Back up exactly the paths listed in the given manifest file instead of walking the backup paths.
+    /// The manifest contains one path per line, optionally followed by a tab and the expected file size.
+    #[cfg_attr(feature = "clap", clap(long, value_name = "FILE", value_hint = ValueHint::FilePath))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub files_from: Option<PathBuf>,
+
     /// Don't scan the backup source for its size - this disables ETA estimation for backup.
     #[cfg_attr(feature = "clap", clap(long))]
     #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
@@ -176,6 +237,18 @@ pub struct BackupOptions {
     #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
     pub dry_run: bool,
 
+    /// Skip writing of snapshot if an identical snapshot (same tree, paths, host and label) has already
+    /// been created on the same day. The existing snapshot is returned instead.
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
+    pub skip_if_identical_today: bool,
+
+    /// Use a lock in the cache directory to prevent concurrent backups to this repository from this host.
+    /// Locks of processes which are no longer running are removed automatically.
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
+    pub host_lock: bool,
+
     #[cfg_attr(feature = "clap", clap(flatten))]
     #[serde(flatten)]
     /// Options how to use a parent snapshot
@@ -197,6 +270,40 @@ pub struct BackupOptions {
     pub ignore_filter_opts: LocalSourceFilterOptions,
 }
 
+impl BackupOptions {
+    /// The [`BackupFilter`] given by the exclude and filter options.
+    ///
+    /// This is the filter used to back up local paths; use it e.g. with
+    /// [`Repository::diff_with_local_filtered`] to see what a backup would save.
+    ///
+    /// # Errors
+    ///
+    /// * If a glob pattern is invalid.
+    /// * If a glob file could not be read.
+    pub fn filter(&self) -> RusticResult<BackupFilter> {
+        BackupFilter::new(&self.excludes, &self.ignore_filter_opts)
+    }
+}
+
+/// Acquire the host lock, if `host_lock` is set in the backup options.
+///
+/// # Errors
+///
+/// * If the repository has no cache.
+/// * If another backup of this repository is running on this host.
+fn host_lock<S>(repo: &Repository<S>, opts: &BackupOptions) -> RusticResult<Option<CacheLock>> {
+    if !opts.host_lock {
+        return Ok(None);
+    }
+    let cache = repo.cache().ok_or_else(|| {
+        RusticError::new(
+            ErrorKind::Configuration,
+            "Using a host lock requires a cache. Please don't disable the cache or don't use the host lock.",
+        )
+    })?;
+    Ok(Some(cache.lock("backup")?))
+}
+
 /// Backup data, create a snapshot.
 ///
 /// # Type Parameters
@@ -210,6 +317,9 @@ pub struct BackupOptions {
 /// * `opts` - The backup options
 /// * `src` - The source to backup
 /// * `snap` - The snapshot with raw information
+/// * `backup_paths` - The paths recorded in the snapshot
+/// * `local_paths` - The local paths the backup paths are read from, in the same order; empty if the
+///   source is not read from the local filesystem
 ///
 /// # Errors
 ///
@@ -218,6 +328,7 @@ pub struct BackupOptions {
 /// * If sending the message to the raw packer fails.
 /// * If the index file could not be serialized.
 /// * If the time is not in the range of `Local::now()`
+/// * If `host_lock` is set and another backup of this repository is running on this host.
 ///
 /// # Returns
 ///
@@ -228,6 +339,7 @@ pub(crate) fn archive<R, S>(
     src: &R,
     mut snap: SnapshotFile,
     backup_paths: &[PathBuf],
+    local_paths: &[PathBuf],
 ) -> RusticResult<SnapshotFile>
 where
     S: IndexedIds,
@@ -235,6 +347,8 @@ where
     <R as ReadSource>::Open: Send,
     <R as ReadSource>::Iter: Send,
 {
+    let _lock = host_lock(repo, opts)?;
+
     let index = repo.index();
 
     let as_path = opts
@@ -273,6 +387,21 @@ where
         )
     })?;
 
+    snap.roots = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let local_path = local_paths.get(i);
+            let mut root = SnapshotRoot::new(path.to_string_lossy());
+            root.source = local_path
+                .filter(|local_path| *local_path != path)
+                .map(|local_path| local_path.to_string_lossy().into_owned());
+            root.device_id = local_path.and_then(|local_path| device_id(local_path));
+            root
+        })
+        .sorted_by(|root1, root2| root1.path.cmp(&root2.path))
+        .collect();
+
     let (parent_ids, parent) = opts.parent_opts.get_parent(repo, &snap);
     if parent_ids.is_empty() {
         info!("using no parent");
@@ -282,6 +411,24 @@ where
         snap.parents = parent_ids;
     }
 
+    let identical_candidates: Vec<_> = if opts.skip_if_identical_today {
+        let date = snap.time.date();
+        SnapshotFile::iter_all_from_backend(
+            repo.dbe(),
+            |sn| {
+                sn.time.date() == date
+                    && sn.hostname == snap.hostname
+                    && sn.label == snap.label
+                    && sn.paths == snap.paths
+            },
+            repo.snapshot_error_policy(),
+            &repo.progress_counter("finding snapshots of today..."),
+        )?
+        .collect()
+    } else {
+        Vec::new()
+    };
+
     let be = DryRunBackend::new(repo.dbe().clone(), opts.dry_run);
     info!("starting to backup {backup_paths:?} ...");
     let archiver = Archiver::new(be, index, repo.config(), parent, snap)?;
@@ -292,8 +439,11 @@ where
         &backup_paths[0],
         as_path.as_ref(),
         opts.parent_opts.skip_if_unchanged,
+        &identical_candidates,
         opts.no_scan,
         &p,
+        repo.cancellation_token(),
+        repo.pause_handle(),
     )
 }
 
@@ -329,28 +479,309 @@ pub(crate) fn backup<S: IndexedIds>(
 ) -> RusticResult<SnapshotFile> {
     let backup_stdin = PathList::from_string("-")?;
 
+    if !opts.remap.is_empty()
+        && (*source == backup_stdin || opts.files_from.is_some() || opts.as_path.is_some())
+    {
+        return Err(RusticError::new(
+            ErrorKind::InvalidInput,
+            "`remap` can only be used when backing up local paths without `files-from` and `as-path`.",
+        ));
+    }
+
     let snap = if *source == backup_stdin {
         let path = PathBuf::from(&opts.stdin_filename);
         let backup_paths = vec![path.clone()];
+        let size = opts.stdin_size.map(|size| size.as_u64());
         if let Some(command) = &opts.stdin_command {
-            let src = ChildStdoutSource::new(command, path)?;
-            let res = archive(repo, opts, &src, snap, &backup_paths)?;
+            let src = ChildStdoutSource::new(command, path)?.with_size(size);
+            let res = archive(repo, opts, &src, snap, &backup_paths, &[])?;
             src.finish()?;
             res
         } else {
-            let src = StdinSource::new(path);
-            archive(repo, opts, &src, snap, &backup_paths)?
+            let src = StdinSource::new(path).with_size(size);
+            archive(repo, opts, &src, snap, &backup_paths, &[])?
         }
-    } else {
+    } else if let Some(manifest) = &opts.files_from {
+        let src = ManifestSource::from_file(opts.ignore_save_opts, manifest)?;
+        // without given backup paths, the snapshot contains the listed paths
+        let backup_path = if source.is_empty() {
+            src.paths()
+        } else {
+            source.paths()
+        };
+        if backup_path.is_empty() {
+            return Err(RusticError::new(
+                ErrorKind::MissingInput,
+                "The manifest `{path}` does not contain any paths to back up.",
+            )
+            .attach_context("path", manifest.display().to_string()));
+        }
+        if opts.as_path.is_some()
+            && let Some(path) = src.paths().iter().find(|p| !p.starts_with(&backup_path[0]))
+        {
+            return Err(RusticError::new(
+                ErrorKind::InvalidInput,
+                "The path `{path}` listed in the manifest is not contained in the backup path `{backup_path}`, which is needed to use `as-path`.",
+            )
+            .attach_context("path", path.display().to_string())
+            .attach_context("backup_path", backup_path[0].display().to_string()));
+        }
+        archive(repo, opts, &src, snap, &backup_path, &backup_path)?
+    } else if opts.remap.is_empty() {
         let backup_path = source.paths();
-        let src = LocalSource::new(
-            opts.ignore_save_opts,
-            &opts.excludes,
-            &opts.ignore_filter_opts,
-            &backup_path,
-        )?;
-        archive(repo, opts, &src, snap, &backup_path)?
+        let src = LocalSource::with_filter(opts.ignore_save_opts, &opts.filter()?, &backup_path);
+        archive(repo, opts, &src, snap, &backup_path, &backup_path)?
+    } else {
+        let (backup_path, snapshot_paths) = remap_backup_paths(source.paths(), &opts.remap)?;
+        let src = LocalSource::with_filter(opts.ignore_save_opts, &opts.filter()?, &backup_path);
+        let src = RemapSource::new(src, opts.remap.clone());
+        archive(repo, opts, &src, snap, &snapshot_paths, &backup_path)?
+    };
+
+    Ok(snap)
+}
+
+/// The device id of the filesystem containing the given path
+#[cfg(not(windows))]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| m.dev())
+}
+
+/// The device id of the filesystem containing the given path
+#[cfg(windows)]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Order the backup paths by the paths they are recorded as in the snapshot.
+///
+/// The entries of the backup source are then walked in the order of the snapshot tree. This requires
+/// that all entries below a backup path are moved together, so remap sources must not be nested within
+/// other remap sources or within backup paths.
+///
+/// # Arguments
+///
+/// * `paths` - The backup paths
+/// * `remaps` - The remap table to use
+///
+/// # Errors
+///
+/// * If a remap source is nested within another remap source or within a backup path.
+/// * If two backup paths are recorded as overlapping paths in the snapshot.
+///
+/// # Returns
+///
+/// The ordered backup paths and the corresponding paths in the snapshot
+fn remap_backup_paths(
+    paths: Vec<PathBuf>,
+    remaps: &[PathRemap],
+) -> RusticResult<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let nested_error = |remap: &PathRemap, outer: &Path| {
+        RusticError::new(
+            ErrorKind::InvalidInput,
+            "The remap source `{source}` is nested within `{outer}`; its entries would be moved out of order. Please only remap paths which are not nested within other remapped or backup paths.",
+        )
+        .attach_context("source", remap.source.display().to_string())
+        .attach_context("outer", outer.display().to_string())
     };
+    for remap in remaps {
+        if let Some(outer) = remaps
+            .iter()
+            .find(|other| other.source != remap.source && remap.source.starts_with(&other.source))
+        {
+            return Err(nested_error(remap, outer.source.as_path()));
+        }
+        if let Some(outer) = paths
+            .iter()
+            .find(|path| **path != remap.source && remap.source.starts_with(path))
+        {
+            return Err(nested_error(remap, outer.as_path()));
+        }
+    }
+
+    let mut paths: Vec<_> = paths
+        .into_iter()
+        .map(|path| {
+            let target = PathRemap::map(remaps, &path);
+            (path, target)
+        })
+        .collect();
+    paths.sort_by(|(_, target1), (_, target2)| target1.cmp(target2));
+
+    for pair in paths.windows(2) {
+        let ((path1, target1), (path2, target2)) = (&pair[0], &pair[1]);
+        if target2.starts_with(target1) {
+            return Err(RusticError::new(
+                ErrorKind::InvalidInput,
+                "The backup paths `{path1}` and `{path2}` would be recorded as overlapping paths `{target1}` and `{target2}` in the snapshot. Please adjust the remaps.",
+            )
+            .attach_context("path1", path1.display().to_string())
+            .attach_context("path2", path2.display().to_string())
+            .attach_context("target1", target1.display().to_string())
+            .attach_context("target2", target2.display().to_string()));
+        }
+    }
+
+    Ok(paths.into_iter().unzip())
+}
+
+/// Backup the data of a reader as a single file, create a snapshot.
+///
+/// # Type Parameters
+///
+/// * `S` - The type of the indexed tree.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `opts` - The backup options; `stdin_size` is used as expected size of the data
+/// * `reader` - The reader to back up
+/// * `path` - The path of the file within the snapshot
+/// * `snap` - The snapshot with raw information
+///
+/// # Errors
+///
+/// * If the reader could not be read.
+/// * If `host_lock` is set and another backup of this repository is running on this host.
+///
+/// # Returns
+///
+/// The snapshot pointing to the backup'ed data.
+pub(crate) fn backup_from_reader<S: IndexedIds>(
+    repo: &Repository<S>,
+    opts: &BackupOptions,
+    reader: impl Read + Send + 'static,
+    path: PathBuf,
+    snap: SnapshotFile,
+) -> RusticResult<SnapshotFile> {
+    let backup_paths = vec![path.clone()];
+    let src = ReaderSource::new(path, reader).with_size(opts.stdin_size.map(|size| size.as_u64()));
+    archive(repo, opts, &src, snap, &backup_paths, &[])
+}
+
+/// A changed path of the backup source, e.g. reported by a file system watcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChangedPath {
+    /// The path has been created or modified.
+    ///
+    /// The path is read again; if it no longer exists, it is treated as removed.
+    Modified(PathBuf),
+    /// The path has been removed, including everything below it.
+    Removed(PathBuf),
+}
+
+impl ChangedPath {
+    /// The path which has been changed
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Modified(path) | Self::Removed(path) => path,
+        }
+    }
+}
+
+/// Backup changed paths incrementally, create a snapshot.
+///
+/// The changes are applied to the tree of the parent snapshot: Only modified files are read and chunked,
+/// all trees which don't contain a change are reused from the parent snapshot. Changes are given with
+/// the paths of the backup source, i.e. as they are contained in the parent snapshot. Later changes of the
+/// same path overwrite earlier ones.
+///
+/// # Type Parameters
+///
+/// * `S` - The type of the indexed tree.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `opts` - The backup options; exclude options and `as_path` are not used.
+/// * `changes` - The changed paths
+/// * `parent` - The parent snapshot to apply the changes to
+/// * `snap` - The snapshot with raw information
+///
+/// # Errors
+///
+/// * If a changed path contains `.` or `..` components.
+/// * If `host_lock` is set and another backup of this repository is running on this host.
+/// * If a tree of the parent snapshot could not be read.
+/// * If the packs, the index or the snapshot could not be saved.
+///
+/// # Returns
+///
+/// The snapshot pointing to the backup'ed data.
+pub(crate) fn backup_incremental<S: IndexedIds>(
+    repo: &Repository<S>,
+    opts: &BackupOptions,
+    changes: impl IntoIterator<Item = ChangedPath>,
+    parent: &SnapshotFile,
+    mut snap: SnapshotFile,
+) -> RusticResult<SnapshotFile> {
+    let _lock = host_lock(repo, opts)?;
+
+    // later changes overwrite earlier changes of the same path
+    let mut removed = BTreeMap::new();
+    for change in changes {
+        let path = change.path().to_path_buf();
+        let is_removed =
+            matches!(change, ChangedPath::Removed(_)) || std::fs::symlink_metadata(&path).is_err();
+        _ = removed.insert(path, is_removed);
+    }
+    info!(
+        "applying {} changed paths to parent snapshot {}",
+        removed.len(),
+        parent.id
+    );
+
+    snap.paths = parent.paths.clone();
+    snap.parent = Some(parent.id);
+    snap.parents = vec![parent.id];
+
+    let be = DryRunBackend::new(repo.dbe().clone(), opts.dry_run);
+    let mut archiver = IncrementalArchiver::new(&be, repo.index(), repo.config())?;
+    let p = repo.progress_bytes("backing up...");
+
+    let modified: Vec<_> = removed
+        .iter()
+        .filter(|(_, is_removed)| !**is_removed)
+        .map(|(path, _)| ManifestEntry {
+            path: path.clone(),
+            size: None,
+        })
+        .collect();
+    let src = ManifestSource::new(opts.ignore_save_opts, modified);
+    if let Ok(Some(size)) = src.size() {
+        p.set_length(size);
+    }
+    let entries: Vec<_> = src
+        .entries()
+        .filter_map(|entry| {
+            entry
+                .inspect_err(|err| warn!("ignoring error: {}", err.display_log()))
+                .ok()
+        })
+        .collect();
+
+    let mut changes: Vec<_> = archiver
+        .archive_entries(entries, &p)
+        .into_iter()
+        .map(|(path, node)| (path, TreeChange::Modified(node)))
+        .collect();
+    for (path, _) in removed.into_iter().filter(|(_, is_removed)| *is_removed) {
+        changes.push((snapshot_path(&path)?, TreeChange::Removed));
+    }
+    changes.sort_by(|(p1, _), (p2, _)| p1.cmp(p2));
+
+    let tree = archiver.update_tree(Some(parent.tree), 0, &changes)?;
+    archiver.finalize(tree, &mut snap)?;
+    repo.cancellation_token().check("backup")?;
+
+    if !opts.parent_opts.skip_if_unchanged || snap.tree != parent.tree {
+        let id = be.save_file(&snap)?;
+        snap.id = id.into();
+    }
 
+    p.finish();
     Ok(snap)
 }
@@ -3,9 +3,10 @@ use std::path::Path;
 use bytes::Bytes;
 
 use crate::{
-    backend::{FileType, FindInBackend, decrypt::DecryptReadBackend},
+    backend::{FileType, FindInBackend, ReadBackend, decrypt::DecryptReadBackend},
     blob::{BlobId, BlobType, tree::Tree},
     error::{ErrorKind, RusticError, RusticResult},
+    id::Id,
     index::ReadIndex,
     repofile::SnapshotFile,
     repository::{IndexedFull, IndexedTree, Open, Repository},
@@ -29,6 +30,7 @@ use crate::{
 /// * If the string is not a valid hexadecimal string
 /// * If no id could be found.
 /// * If the id is not unique.
+/// * If a pack file is given.
 ///
 /// # Returns
 ///
@@ -39,8 +41,47 @@ pub(crate) fn cat_file<S: Open>(
     id: &str,
 ) -> RusticResult<Bytes> {
     let id = repo.dbe().find_id(tpe, id)?;
-    let data = repo.dbe().read_encrypted_full(tpe, &id)?;
-    Ok(data)
+    cat_file_by_id(repo, tpe, &id)
+}
+
+/// Prints the contents of a file given by its full id.
+///
+/// All repository files except pack files are JSON documents; key files are stored unencrypted
+/// and all other files are decrypted.
+///
+/// # Type Parameters
+///
+/// * `S` - The state the repository is in.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to read from.
+/// * `tpe` - The type of the file.
+/// * `id` - The id of the file.
+///
+/// # Errors
+///
+/// * If a pack file is given.
+/// * If the file could not be read or decrypted.
+///
+/// # Returns
+///
+/// The (decrypted) JSON data.
+pub(crate) fn cat_file_by_id<S: Open>(
+    repo: &Repository<S>,
+    tpe: FileType,
+    id: &Id,
+) -> RusticResult<Bytes> {
+    match tpe {
+        FileType::Pack => Err(RusticError::new(
+            ErrorKind::Unsupported,
+            "Pack file `{id}` consists of separately encrypted blobs and cannot be printed. You could try to use `cat_blob` instead.",
+        )
+        .attach_context("id", id.to_string())),
+        // key files are not encrypted
+        FileType::Key => repo.dbe().read_full(tpe, id),
+        _ => repo.dbe().read_encrypted_full(tpe, id),
+    }
 }
 
 // TODO: Add documentation!
@@ -100,6 +141,7 @@ pub(crate) fn cat_tree<S: IndexedTree>(
         repo.dbe(),
         id,
         sn_filter,
+        repo.snapshot_error_policy(),
         &repo.progress_counter("getting snapshot..."),
     )?;
     let node = Tree::node_from_path(repo.dbe(), repo.index(), snap.tree, Path::new(path))?;
@@ -16,6 +16,7 @@ use jiff::Zoned;
 use log::{debug, error, warn};
 use rand::{Rng, prelude::SliceRandom, rng};
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde_derive::Serialize;
 use thiserror::Error;
 use zstd::stream::decode_all;
 
@@ -31,9 +32,7 @@ use crate::{
         binarysorted::{IndexCollector, IndexType},
     },
     progress::Progress,
-    repofile::{
-        IndexFile, IndexPack, PackHeader, PackHeaderLength, PackHeaderRef, packfile::PackId,
-    },
+    repofile::{IndexFile, IndexPack, PackHeader, PackHeaderRef, PackLayout, packfile::PackId},
     repository::{Open, Repository},
 };
 
@@ -49,6 +48,10 @@ pub enum ReadSubsetOption {
     /// Read a random subset of pack files with (approximately) the given size
     Size(u64),
     /// Read a subset of packfiles based on Ids: Using (1,n) .. (n,n) in separate runs will cover all pack files
+    ///
+    /// When parsing, `n` can also be `hourly`, `daily`, `weekly` or `monthly` to select a rotating subset
+    /// depending on the current time, e.g. `daily/month` reads all pack files within one month
+    /// when being run every day.
     IdSubSet((u32, u32)),
 }
 
@@ -195,6 +198,7 @@ pub struct CheckOptions {
     pub read_data: bool,
 
     /// Read only a subset of the data. Allowed values: "all", "n/m" for specific part, "x%" or a size for a random subset.
+    /// Use e.g. "daily/month" to read a rotating part such that all data is read within a month.
     #[cfg_attr(
         feature = "clap",
         clap(long, default_value = "all", requires = "read_data")
@@ -715,7 +719,7 @@ fn check_trees<S: Open>(
 /// # Panics
 ///
 /// * If zstd decompression fails.
-fn check_pack(
+pub(crate) fn check_pack(
     be: &impl DecryptReadBackend,
     index_pack: IndexPack,
     mut data: Bytes,
@@ -741,18 +745,18 @@ fn check_pack(
 
     // check header length
     let header_len = PackHeaderRef::from_index_pack(&index_pack).size();
-    let pack_header_len = PackHeaderLength::from_binary(&data.split_off(data.len() - 4))
-        .map_err(|err| {
-            RusticError::with_source(
-                ErrorKind::Internal,
-                "Error reading pack header length `{length}` for `{pack_id}`",
-                err,
-            )
-            .attach_context("pack_id", id.to_string())
-            .attach_context("length", header_len.to_string())
-            .ask_report()
-        })?
-        .to_u32();
+    let (layout, pack_header_len, _) = PackLayout::from_pack_end(&data).map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::Internal,
+            "Error reading pack header length `{length}` for `{pack_id}`",
+            err,
+        )
+        .attach_context("pack_id", id.to_string())
+        .attach_context("length", header_len.to_string())
+        .ask_report()
+    })?;
+    let trailer_len = layout.trailer_len();
+    _ = data.split_off(data.len() - trailer_len as usize);
     if pack_header_len != header_len {
         collector.add_error(CheckError::PackHeaderLengthMismatch {
             id,
@@ -765,7 +769,7 @@ fn check_pack(
     // check header
     let header = be.decrypt(&data.split_off(data.len() - header_len as usize))?;
 
-    let pack_blobs = PackHeader::from_binary(&header)
+    let pack_blobs = PackHeader::from_binary(&header, layout)
         .map_err(|err| {
             RusticError::with_source(
                 ErrorKind::Internal,
@@ -784,11 +788,15 @@ fn check_pack(
         debug!("index: {blobs:?}");
         return Ok(());
     }
-    p.inc(u64::from(header_len) + 4);
+    p.inc(u64::from(header_len + trailer_len));
 
     // check blobs
+    let mut offset = 0;
     for blob in blobs {
         let blob_id = blob.id;
+        // skip padding between blobs
+        _ = data.split_to((blob.location.offset - offset) as usize);
+        offset = blob.location.offset + blob.location.length;
         let mut blob_data = be.decrypt(&data.split_to(blob.location.length as usize))?;
 
         // TODO: this is identical to backend/decrypt.rs; unify these two parts!
@@ -940,7 +948,8 @@ pub enum CheckError {
     },
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 #[non_exhaustive]
 /// `CheckErrorLevel` describes severity levels of problems identified by check.
 pub enum CheckErrorLevel {
@@ -988,7 +997,7 @@ impl CheckResultsCollector {
         }
     }
 
-    fn add_error(&self, err: CheckError) {
+    pub(crate) fn add_error(&self, err: CheckError) {
         if self.log {
             error!("{err}");
         }
@@ -1134,4 +1143,20 @@ mod tests {
 
         assert!(all_packs.is_empty());
     }
+
+    #[test]
+    fn test_read_subset_daily_month_covers_all() {
+        let test_packs = test_packs(&mut rng());
+        let mut all_packs: BTreeSet<_> = test_packs.iter().map(|pack| pack.id).collect();
+
+        for day in 1..=30 {
+            let now = RusticTime::parse_utc(&format!("2024-11-{day:02}T03:00:00")).unwrap();
+            let subset = ReadSubsetOption::IdSubSet(parse_n_m(&now, "daily", "month").unwrap());
+            for pack in subset.apply(test_packs.clone()) {
+                assert!(all_packs.remove(&pack.id));
+            }
+        }
+
+        assert!(all_packs.is_empty());
+    }
 }
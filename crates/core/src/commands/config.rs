@@ -3,8 +3,9 @@ use bytesize::ByteSize;
 use derive_setters::Setters;
 
 use crate::{
-    backend::decrypt::{DecryptBackend, DecryptWriteBackend},
-    chunker::rabin::check_rabin_params,
+    backend::decrypt::{DecryptBackend, DecryptWriteBackend, ZSTD_WINDOW_LOG_RANGE},
+    blob::{BlobType, packer::check_packsize_params},
+    chunker::{fastcdc::check_fastcdc_params, rabin::check_rabin_params},
     crypto::CryptoKey,
     error::{ErrorKind, RusticError, RusticResult},
     repofile::{ConfigFile, configfile::Chunker},
@@ -52,7 +53,7 @@ pub(crate) fn apply_config<S: Open>(
     if &new_config == repo.config() {
         Ok(false)
     } else {
-        repo.set_config(new_config.clone());
+        repo.set_config(new_config.clone())?;
         save_config(repo, new_config, *repo.dbe().key())?;
         Ok(true)
     }
@@ -126,22 +127,22 @@ pub struct ConfigOptions {
     #[cfg_attr(feature = "clap", clap(long, value_name = "VERSION"))]
     pub set_version: Option<u32>,
 
-    /// Set chunker to use. Allowed chunkers: ``rabin``, ``fixed_size``.
+    /// Set chunker to use. Allowed chunkers: ``rabin``, ``fastcdc``, ``fixed_size``.
     /// Defaults to ``rabin`` if not set.
     #[cfg_attr(feature = "clap", clap(long, value_name = "CHUNKER"))]
     pub set_chunker: Option<Chunker>,
 
-    /// Set the chunk size. For the rabin chunker this is the average chunk size.
+    /// Set the chunk size. For the rabin and fastcdc chunkers this is the average chunk size.
     /// Defaults to `1 MiB` if not set.
     #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
     pub set_chunk_size: Option<ByteSize>,
 
-    /// Set the minimum chunk size. Only used for the rabin chunker.
+    /// Set the minimum chunk size. Only used for the rabin and fastcdc chunkers.
     /// Defaults to `512 kiB` if not set.
     #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
     pub set_chunk_min_size: Option<ByteSize>,
 
-    /// Set the maximum chunk size. Only used for the rabin chunker.
+    /// Set the maximum chunk size. Only used for the rabin and fastcdc chunkers.
     /// Defaults to `8 MiB` if not set.
     #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
     pub set_chunk_max_size: Option<ByteSize>,
@@ -151,6 +152,23 @@ pub struct ConfigOptions {
     #[cfg_attr(feature = "clap", clap(long, value_name = "LEVEL"))]
     pub set_compression: Option<i32>,
 
+    /// Enable zstd long-distance matching. This improves the compression of large, highly redundant files,
+    /// but needs more memory for compression.
+    /// Defaults to `false` if not set.
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub set_compression_long: Option<bool>,
+
+    /// Set the zstd window log, i.e. the maximum distance of matches is `2^LOG` bytes. Allowed values are 10 to 27.
+    /// If not set, zstd chooses the window log depending on the compression level.
+    #[cfg_attr(feature = "clap", clap(long, value_name = "LOG"))]
+    pub set_compression_window_log: Option<u32>,
+
+    /// Store blobs uncompressed if a sample of them doesn't compress. This saves CPU time for already compressed
+    /// data like images or videos.
+    /// Defaults to `false` if not set.
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub set_compression_skip_incompressible: Option<bool>,
+
     /// Set append-only mode.
     /// Note that only append-only commands work once this is set. `forget`, `prune` or `config` won't work any longer.
     #[cfg_attr(feature = "clap", clap(long))]
@@ -209,6 +227,13 @@ pub struct ConfigOptions {
     /// Default: true
     #[cfg_attr(feature = "clap", clap(long))]
     pub set_extra_verify: Option<bool>,
+
+    /// Experimental: Set the alignment of blobs within new pack files. Must be a power of 2 between `512 B`
+    /// and `1 MiB`; `0` disables the alignment.
+    /// Note that repositories containing aligned pack files can't be used by restic.
+    /// Defaults to no alignment if not set.
+    #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    pub set_pack_alignment: Option<ByteSize>,
 }
 
 impl ConfigOptions {
@@ -224,9 +249,11 @@ impl ConfigOptions {
     /// * If the version is lower than the current version
     /// * If compression is set for a v1 repo
     /// * If the compression level is not supported
+    /// * If the compression window log is not supported
     /// * If the size is too large
     /// * If the min packsize tolerate percent is wrong
     /// * If the max packsize tolerate percent is wrong
+    /// * If the pack alignment is invalid
     #[allow(clippy::too_many_lines)]
     pub fn apply(&self, config: &mut ConfigFile) -> RusticResult<()> {
         if let Some(version) = self.set_version {
@@ -281,12 +308,18 @@ impl ConfigOptions {
         }
 
         // validate chunker parameters
-        if matches!(config.chunker(), Chunker::Rabin) {
-            check_rabin_params(
+        match config.chunker() {
+            Chunker::Rabin => check_rabin_params(
+                config.chunk_size(),
+                config.chunk_min_size(),
+                config.chunk_max_size(),
+            )?,
+            Chunker::FastCdc => check_fastcdc_params(
                 config.chunk_size(),
                 config.chunk_min_size(),
                 config.chunk_max_size(),
-            )?;
+            )?,
+            Chunker::FixedSize => {}
         }
 
         if let Some(compression) = self.set_compression {
@@ -310,6 +343,40 @@ impl ConfigOptions {
             config.compression = Some(compression);
         }
 
+        if let Some(long) = self.set_compression_long {
+            if config.version == 1 && long {
+                return Err(RusticError::new(
+                    ErrorKind::Unsupported,
+                    "Long-distance matching is unsupported for v1 repos as they don't support compression.",
+                ));
+            }
+            config.compression_long = Some(long);
+        }
+
+        if let Some(window_log) = self.set_compression_window_log {
+            if config.version == 1 {
+                return Err(RusticError::new(
+                    ErrorKind::Unsupported,
+                    "Compression window log is unsupported for v1 repos as they don't support compression.",
+                ));
+            }
+
+            let range = ZSTD_WINDOW_LOG_RANGE;
+            if !range.contains(&window_log) {
+                return Err(RusticError::new(
+                    ErrorKind::Unsupported,
+                    "Compression window log `{window_log}` is unsupported. Allowed values are `{allowed_window_logs}`.",
+                )
+                .attach_context("window_log", window_log.to_string())
+                .attach_context("allowed_window_logs", format!("{range:?}")));
+            }
+            config.compression_window_log = Some(window_log);
+        }
+
+        if let Some(skip) = self.set_compression_skip_incompressible {
+            config.compression_skip_incompressible = Some(skip);
+        }
+
         if let Some(append_only) = self.set_append_only {
             config.append_only = Some(append_only);
         }
@@ -350,6 +417,22 @@ impl ConfigOptions {
             );
         }
 
+        // validate the pack size parameters of the blob types whose pack size is changed
+        let changed = [
+            (
+                BlobType::Tree,
+                self.set_treepack_size.is_some() || self.set_treepack_size_limit.is_some(),
+            ),
+            (
+                BlobType::Data,
+                self.set_datapack_size.is_some() || self.set_datapack_size_limit.is_some(),
+            ),
+        ];
+        for (blob_type, _) in changed.into_iter().filter(|(_, changed)| *changed) {
+            let (size, _, size_limit) = config.packsize(blob_type);
+            check_packsize_params(size, size_limit)?;
+        }
+
         if let Some(percent) = self.set_min_packsize_tolerate_percent {
             if percent > 100 {
                 return Err(RusticError::new(
@@ -375,6 +458,31 @@ impl ConfigOptions {
 
         config.extra_verify = self.set_extra_verify;
 
+        if let Some(size) = self.set_pack_alignment {
+            let alignment: u32 = size
+                .as_u64()
+                .try_into()
+                .map_err(|err| construct_size_too_large_error(err, size))?;
+            if alignment == 0 {
+                config.pack_alignment = None;
+            } else {
+                if config.version == 1 {
+                    return Err(RusticError::new(
+                        ErrorKind::Unsupported,
+                        "Aligned pack files are unsupported for v1 repos.",
+                    ));
+                }
+                if !alignment.is_power_of_two() || !(512..=1024 * 1024).contains(&alignment) {
+                    return Err(RusticError::new(
+                        ErrorKind::InvalidInput,
+                        "Pack alignment must be a power of 2 between 512 B and 1 MiB. You provided `{alignment}`.",
+                    )
+                    .attach_context("alignment", alignment.to_string()));
+                }
+                config.pack_alignment = Some(alignment);
+            }
+        }
+
         Ok(())
     }
 }
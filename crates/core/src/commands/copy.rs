@@ -1,22 +1,39 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
 
+use bytes::Bytes;
+use bytesize::ByteSize;
+use derive_setters::Setters;
+use ignore::overrides::Override;
 use itertools::Itertools;
+use log::info;
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
 
 use crate::{
-    DataId, Progress, TreeId,
+    BlobId, CancellationToken, DataId, Excludes, Progress, TreeId,
     backend::{
-        decrypt::{DecryptFullBackend, DecryptWriteBackend},
-        node::NodeType,
+        FileType, ReadBackend,
+        decrypt::{DecryptBackend, DecryptFullBackend, DecryptReadBackend, DecryptWriteBackend},
+        node::{Node, NodeType},
     },
     blob::{
         BlobType,
-        packer::{BlobCopier, CopyPackBlobs, PackSizer},
-        tree::TreeStreamerOnce,
+        packer::{BlobCopier, CopyPackBlobs, PackSizer, Packer},
+        tree::{Tree, TreeStreamerOnce, rewrite::Summary},
+    },
+    chunker::ChunkIter,
+    crypto::{aespoly1305::Key, hasher::hash},
+    error::{ErrorKind, RusticError, RusticResult},
+    index::{
+        ReadGlobalIndex, ReadIndex,
+        indexer::{Indexer, SharedIndexer},
     },
-    error::RusticResult,
-    index::{ReadIndex, indexer::Indexer},
-    repofile::SnapshotFile,
+    repofile::{ConfigFile, SnapshotFile, SnapshotId},
     repository::{IndexedFull, IndexedIds, Open, Repository},
 };
 
@@ -30,6 +47,279 @@ pub struct CopySnapshot {
     pub relevant: bool,
 }
 
+#[serde_as]
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[cfg_attr(feature = "merge", derive(conflate::Merge))]
+#[derive(Clone, Debug, Default, Setters, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for the `copy` command
+///
+/// If files are excluded or re-chunked, the trees of the copied snapshots are rewritten in the destination repository.
+pub struct CopyOptions {
+    /// Don't copy files larger than the given size
+    #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub exclude_larger_than: Option<ByteSize>,
+
+    /// Exclude options
+    #[cfg_attr(feature = "clap", clap(flatten))]
+    pub excludes: Excludes,
+
+    /// Re-chunk file contents using the chunker parameters of the destination repository.
+    ///
+    /// This allows deduplication against existing data in the destination repository if the chunker
+    /// parameters differ, but needs to read all file contents. It has no effect if both repositories use
+    /// the same chunker parameters.
+    #[cfg_attr(feature = "clap", clap(long))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
+    pub rechunk: bool,
+}
+
+impl CopyOptions {
+    /// Returns `true` if no files are excluded from copying
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.exclude_larger_than.is_none() && self.excludes.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// The state of a resumable copy.
+///
+/// This state can be saved (e.g. as JSON) while copying and passed to [`Repository::copy_resumable`] again
+/// to resume an interrupted copy without re-reading the trees which have already been transferred.
+pub struct CopyState {
+    /// The trees (including their subtrees and file contents) transferred by each copied source snapshot
+    trees: BTreeMap<SnapshotId, BTreeSet<TreeId>>,
+}
+
+impl CopyState {
+    /// Returns whether the given source snapshot has already been copied
+    ///
+    /// # Arguments
+    ///
+    /// * `sn` - The snapshot to check
+    #[must_use]
+    pub fn is_copied(&self, sn: &SnapshotFile) -> bool {
+        self.trees.contains_key(&sn.id)
+    }
+}
+
+/// Reads the contents of a file from the source repository blob by blob
+pub(crate) struct ContentReader<'a, BE: DecryptReadBackend, I: ReadGlobalIndex> {
+    /// The backend to read from
+    be: &'a BE,
+    /// The index of the source repository
+    index: &'a I,
+    /// The remaining blobs of the file
+    ids: std::slice::Iter<'a, DataId>,
+    /// The not yet read part of the current blob
+    current: Bytes,
+}
+
+impl<'a, BE: DecryptReadBackend, I: ReadGlobalIndex> ContentReader<'a, BE, I> {
+    /// Creates a new `ContentReader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to read from
+    /// * `index` - The index to find the blobs in
+    /// * `content` - The data blobs of the file
+    pub(crate) fn new(be: &'a BE, index: &'a I, content: &'a [DataId]) -> Self {
+        Self {
+            be,
+            index,
+            ids: content.iter(),
+            current: Bytes::new(),
+        }
+    }
+}
+
+impl<BE: DecryptReadBackend, I: ReadGlobalIndex> Read for ContentReader<'_, BE, I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current.is_empty() {
+            let Some(id) = self.ids.next() else {
+                return Ok(0);
+            };
+            let entry = self
+                .index
+                .get_data(id)
+                .ok_or_else(|| io::Error::other(format!("data blob {id} not found in index")))?;
+            self.current = entry.read_data(self.be).map_err(io::Error::other)?;
+        }
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+/// Re-chunks file contents with the chunker parameters of the destination repository
+struct Rechunker<BE: DecryptWriteBackend> {
+    /// The config of the destination repository
+    config: ConfigFile,
+    /// The packer saving the new data blobs into the destination repository
+    packer: Packer<BE>,
+    /// The already re-chunked contents
+    contents: BTreeMap<Vec<DataId>, Vec<DataId>>,
+}
+
+/// Filters the trees of the snapshots to copy according to the [`CopyOptions`]
+struct TreeFilter<'a, BE: DecryptWriteBackend, I: ReadGlobalIndex, J: ReadGlobalIndex> {
+    /// The backend to read the trees from
+    be: &'a BE,
+    /// The index of the source repository
+    index: &'a I,
+    /// The index of the destination repository
+    index_dest: &'a J,
+    /// The exclude globs
+    overrides: Override,
+    /// The maximum size of files to copy
+    exclude_larger_than: Option<u64>,
+    /// The packer saving the modified trees into the destination repository
+    packer: Packer<BE>,
+    /// The already filtered trees by path and id with their new id and summary
+    trees: BTreeMap<(PathBuf, TreeId), (TreeId, Summary)>,
+    /// The modified trees which have been saved
+    new_trees: BTreeSet<TreeId>,
+    /// The unmodified trees which need to be copied
+    tree_ids: BTreeSet<TreeId>,
+    /// The data blobs which need to be copied
+    data_ids: BTreeSet<DataId>,
+    /// The re-chunker, if file contents should be re-chunked
+    rechunker: Option<Rechunker<BE>>,
+}
+
+impl<BE: DecryptFullBackend, I: ReadGlobalIndex, J: ReadGlobalIndex> TreeFilter<'_, BE, I, J> {
+    fn is_excluded(&self, path: &Path, node: &Node) -> bool {
+        self.overrides.matched(path, node.is_dir()).is_ignore()
+            || (node.is_file()
+                && self
+                    .exclude_larger_than
+                    .is_some_and(|size| node.meta.size > size))
+    }
+
+    /// Filter the tree `id` at `path` and return the (maybe new) tree id and the summary of the filtered tree
+    fn filter_tree(
+        &mut self,
+        path: &Path,
+        id: TreeId,
+        p: &Progress,
+    ) -> RusticResult<(TreeId, Summary)> {
+        if let Some(res) = self.trees.get(&(path.to_path_buf(), id)) {
+            return Ok(*res);
+        }
+
+        let tree = Tree::from_backend(self.be, self.index, id)?;
+        p.inc(1);
+
+        let mut changed = false;
+        let mut summary = Summary::default();
+        summary.dirs += 1;
+        let mut new_tree = Tree::new();
+        for mut node in tree {
+            let node_path = path.join(node.name());
+            if self.is_excluded(&node_path, &node) {
+                changed = true;
+                continue;
+            }
+            if node.is_dir()
+                && let Some(subtree) = node.subtree
+            {
+                let (new_subtree, subtree_summary) = self.filter_tree(&node_path, subtree, p)?;
+                changed |= new_subtree != subtree;
+                node.subtree = Some(new_subtree);
+                summary += subtree_summary;
+            } else {
+                if node.is_file()
+                    && let Some(content) = &node.content
+                    && self.rechunker.is_some()
+                {
+                    let new_content = self.rechunk(content, node.meta.size)?;
+                    changed |= &new_content != content;
+                    node.content = Some(new_content);
+                } else if node.is_file() {
+                    self.data_ids.extend(
+                        node.content
+                            .iter()
+                            .flatten()
+                            .filter(|id| !self.index_dest.has_data(id)),
+                    );
+                }
+                summary.update(&node);
+            }
+            new_tree.add(node);
+        }
+
+        let new_id = if changed {
+            let (chunk, new_id) = new_tree.serialize().map_err(|err| {
+                RusticError::with_source(ErrorKind::Internal, "Failed to serialize tree.", err)
+                    .ask_report()
+            })?;
+            if !self.index_dest.has_tree(&new_id) && self.new_trees.insert(new_id) {
+                self.packer.add(chunk.into(), BlobId::from(*new_id))?;
+            }
+            new_id
+        } else {
+            if !self.index_dest.has_tree(&id) {
+                _ = self.tree_ids.insert(id);
+            }
+            id
+        };
+        _ = self
+            .trees
+            .insert((path.to_path_buf(), id), (new_id, summary));
+        Ok((new_id, summary))
+    }
+
+    /// Re-chunk the file `content` and save the new data blobs into the destination repository
+    fn rechunk(&mut self, content: &[DataId], size: u64) -> RusticResult<Vec<DataId>> {
+        let Some(rechunker) = &mut self.rechunker else {
+            return Ok(content.to_vec());
+        };
+        if let Some(new_content) = rechunker.contents.get(content) {
+            return Ok(new_content.clone());
+        }
+
+        let reader = ContentReader::new(self.be, self.index, content);
+        let new_content = ChunkIter::from_config(
+            &rechunker.config,
+            reader,
+            usize::try_from(size).unwrap_or(usize::MAX),
+        )?
+        .map(|chunk| {
+            let chunk = chunk?;
+            let id = DataId::from(hash(&chunk));
+            if !self.index_dest.has_data(&id) {
+                rechunker.packer.add(chunk.into(), BlobId::from(*id))?;
+            }
+            Ok(id)
+        })
+        .collect::<RusticResult<Vec<_>>>()?;
+
+        _ = rechunker
+            .contents
+            .insert(content.to_vec(), new_content.clone());
+        Ok(new_content)
+    }
+
+    /// Filter the tree of the snapshot and adapt the snapshot summary if the tree has changed
+    fn filter_snapshot(&mut self, sn: &mut SnapshotFile, p: &Progress) -> RusticResult<()> {
+        let (tree, summary) = self.filter_tree(Path::new(""), sn.tree, p)?;
+        if tree != sn.tree {
+            sn.tree = tree;
+            let mut snap_summary = sn.summary.take().unwrap_or_default();
+            snap_summary.total_files_processed = summary.files;
+            snap_summary.total_bytes_processed = summary.size;
+            snap_summary.total_dirs_processed = summary.dirs;
+            sn.summary = Some(snap_summary);
+        }
+        Ok(())
+    }
+}
+
 /// Copy the given snapshots to the destination repository.
 ///
 /// # Type Parameters
@@ -44,6 +334,7 @@ pub struct CopySnapshot {
 /// * `repo` - The repository to copy from
 /// * `repo_dest` - The repository to copy to
 /// * `snapshots` - The snapshots to copy
+/// * `opts` - The options to use
 ///
 /// # Errors
 ///
@@ -52,43 +343,103 @@ pub(crate) fn copy<'a, R: IndexedFull, S: IndexedIds>(
     repo: &Repository<R>,
     repo_dest: &Repository<S>,
     snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
+    opts: &CopyOptions,
 ) -> RusticResult<()> {
     let be_dest = repo_dest.dbe();
 
-    let (snap_trees, snaps): (Vec<_>, Vec<_>) = snapshots
+    let mut snaps: Vec<_> = snapshots
         .into_iter()
         .cloned()
-        .map(|sn| (sn.tree, SnapshotFile::clear_ids(sn)))
-        .unzip();
+        .map(SnapshotFile::clear_ids)
+        .collect();
 
     let be = repo.dbe();
     let index = repo.index();
     let index_dest = repo_dest.index();
 
-    let filter_tree = |id: &TreeId| !index_dest.has_tree(id);
-    let filter_data = |id: &DataId| !index_dest.has_data(id);
-    let mut tree_ids: BTreeSet<_> = snap_trees.iter().copied().filter(filter_tree).collect();
-    let mut data_ids = BTreeSet::new();
+    let indexer = Indexer::new(be_dest.clone()).into_shared();
 
     let p = repo_dest.progress_counter("finding needed blobs...");
 
-    let mut tree_streamer = TreeStreamerOnce::new(be, index, snap_trees, p)?;
-    while let Some(item) = tree_streamer.next().transpose()? {
-        let (_, tree) = item;
-        for node in tree.nodes {
-            match node.node_type {
-                NodeType::File => {
-                    data_ids.extend(node.content.into_iter().flatten().filter(filter_data));
-                }
-                NodeType::Dir => {
-                    tree_ids.extend(node.subtree.into_iter().filter(filter_tree));
-                }
-                _ => {} // nothing to do
-            }
-        }
+    let rechunk = opts.rechunk && !repo.config().has_same_chunker(repo_dest.config());
+    if opts.rechunk && !rechunk {
+        info!("both repositories use the same chunker parameters, not re-chunking.");
     }
 
-    let indexer = Indexer::new(be_dest.clone()).into_shared();
+    let (tree_ids, data_ids) = if opts.is_empty() && !rechunk {
+        let (tree_ids, data_ids, _) = find_needed_blobs(
+            be,
+            index,
+            snaps.iter().map(|sn| sn.tree).collect(),
+            BTreeSet::new(),
+            |id| !index_dest.has_tree(id),
+            |id| !index_dest.has_data(id),
+            p,
+        )?;
+        (tree_ids, data_ids)
+    } else {
+        let pack_sizer = PackSizer::from_config(
+            repo_dest.config(),
+            BlobType::Tree,
+            index_dest.total_size(BlobType::Tree),
+        );
+        let mut filter = TreeFilter {
+            be,
+            index,
+            index_dest,
+            overrides: opts.excludes.as_override()?,
+            exclude_larger_than: opts.exclude_larger_than.map(|size| size.as_u64()),
+            packer: Packer::new(be_dest.clone(), BlobType::Tree, indexer.clone(), pack_sizer)?,
+            trees: BTreeMap::new(),
+            new_trees: BTreeSet::new(),
+            tree_ids: BTreeSet::new(),
+            data_ids: BTreeSet::new(),
+            rechunker: None,
+        };
+        if rechunk {
+            let pack_sizer = PackSizer::from_config(
+                repo_dest.config(),
+                BlobType::Data,
+                index_dest.total_size(BlobType::Data),
+            );
+            filter.rechunker = Some(Rechunker {
+                config: repo_dest.config().clone(),
+                packer: Packer::new(be_dest.clone(), BlobType::Data, indexer.clone(), pack_sizer)?,
+                contents: BTreeMap::new(),
+            });
+        }
+        for sn in &mut snaps {
+            filter.filter_snapshot(sn, &p)?;
+        }
+        p.finish();
+        _ = filter.packer.finalize()?;
+        if let Some(rechunker) = filter.rechunker {
+            _ = rechunker.packer.finalize()?;
+        }
+        (filter.tree_ids, filter.data_ids)
+    };
+
+    copy_needed_blobs(repo, repo_dest, &indexer, tree_ids, data_ids)?;
+
+    indexer.write().unwrap().finalize()?;
+    repo.cancellation_token().check("copy")?;
+
+    let p = repo_dest.progress_counter("saving snapshots...");
+    be_dest.save_list(snaps.iter(), p)?;
+    Ok(())
+}
+
+/// Copy the given trees and data blobs from `repo` to `repo_dest`, adding the written packs to `indexer`
+fn copy_needed_blobs<R: IndexedFull, S: IndexedIds>(
+    repo: &Repository<R>,
+    repo_dest: &Repository<S>,
+    indexer: &SharedIndexer<DecryptBackend<Key>>,
+    tree_ids: BTreeSet<TreeId>,
+    data_ids: BTreeSet<DataId>,
+) -> RusticResult<()> {
+    let be = repo.dbe();
+    let be_dest = repo_dest.dbe();
+    let index = repo.index();
 
     let p = repo_dest.progress_bytes("copying data blobs...");
     let pack_sizer = PackSizer::from_config(
@@ -112,7 +463,7 @@ pub(crate) fn copy<'a, R: IndexedFull, S: IndexedIds>(
         })
         .collect();
 
-    copy_blobs(data_blobs, data_repacker, p)?;
+    copy_blobs(data_blobs, data_repacker, p, repo.cancellation_token())?;
 
     let p = repo_dest.progress_bytes("copying tree blobs...");
     let pack_sizer = PackSizer::from_config(
@@ -137,20 +488,295 @@ pub(crate) fn copy<'a, R: IndexedFull, S: IndexedIds>(
         })
         .collect();
 
-    copy_blobs(trees, tree_repacker, p)?;
+    copy_blobs(trees, tree_repacker, p, repo.cancellation_token())?;
+    Ok(())
+}
 
-    indexer.write().unwrap().finalize()?;
+/// Copy the given snapshots to the destination repository one by one, recording the progress in `state`.
+///
+/// After each snapshot, the destination index is written, the snapshot is saved and `save_state` is called.
+/// Snapshots which are already marked as copied in `state` are skipped and trees which have already been
+/// transferred are not read again.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to copy from
+/// * `repo_dest` - The repository to copy to
+/// * `snapshots` - The snapshots to copy
+/// * `state` - The state of the copy; this is updated after each copied snapshot
+/// * `save_state` - Called with the updated state after each copied snapshot
+///
+/// # Errors
+///
+/// * If blobs could not be read from `repo` or written to `repo_dest`.
+/// * If `save_state` fails.
+pub(crate) fn copy_resumable<'a, R: IndexedFull, S: IndexedIds>(
+    repo: &Repository<R>,
+    repo_dest: &Repository<S>,
+    snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
+    state: &mut CopyState,
+    mut save_state: impl FnMut(&CopyState) -> RusticResult<()>,
+) -> RusticResult<()> {
+    let be = repo.dbe();
+    let index = repo.index();
+    let index_dest = repo_dest.index();
 
-    let p = repo_dest.progress_counter("saving snapshots...");
-    be_dest.save_list(snaps.iter(), p)?;
+    let indexer = Indexer::new(repo_dest.dbe().clone()).into_shared();
+    let mut transferred: BTreeSet<_> = state.trees.values().flatten().copied().collect();
+
+    for sn in snapshots {
+        repo.cancellation_token().check("copy")?;
+        if state.is_copied(sn) {
+            info!("snapshot {} has already been copied, skipping.", sn.id);
+            continue;
+        }
+
+        let p = repo_dest.progress_counter("finding needed blobs...");
+        let (tree_ids, data_ids, visited) = find_needed_blobs(
+            be,
+            index,
+            vec![sn.tree],
+            transferred.clone(),
+            |id| !index_dest.has_tree(id),
+            |id| !index_dest.has_data(id),
+            p,
+        )?;
+
+        copy_needed_blobs(repo, repo_dest, &indexer, tree_ids, data_ids)?;
+        {
+            let mut indexer = indexer.write().unwrap();
+            indexer.save()?;
+            indexer.reset();
+        }
+        repo.cancellation_token().check("copy")?;
+        _ = repo_dest.dbe().save_file(&sn.clone().clear_ids())?;
+
+        let new_trees = visited.difference(&transferred).copied().collect();
+        transferred = visited;
+        _ = state.trees.insert(sn.id, new_trees);
+        save_state(state)?;
+    }
+    Ok(())
+}
+
+/// Copy the given snapshots to several destination repositories, reading each blob only once.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to copy from
+/// * `repos_dest` - The repositories to copy to
+/// * `snapshots` - The snapshots to copy
+///
+/// # Errors
+///
+/// * If blobs could not be read from `repo` or written to one of `repos_dest`.
+pub(crate) fn copy_to_many<'a, R: IndexedFull, S: IndexedIds>(
+    repo: &Repository<R>,
+    repos_dest: &[&Repository<S>],
+    snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
+) -> RusticResult<()> {
+    let snaps: Vec<_> = snapshots
+        .into_iter()
+        .cloned()
+        .map(SnapshotFile::clear_ids)
+        .collect();
+
+    let be = repo.dbe();
+    let index = repo.index();
+    let indexes_dest: Vec<_> = repos_dest.iter().map(|repo| repo.index()).collect();
+
+    let p = repo.progress_counter("finding needed blobs...");
+    let (tree_ids, data_ids, _) = find_needed_blobs(
+        be,
+        index,
+        snaps.iter().map(|sn| sn.tree).collect(),
+        BTreeSet::new(),
+        |id| indexes_dest.iter().any(|index| !index.has_tree(id)),
+        |id| indexes_dest.iter().any(|index| !index.has_data(id)),
+        p,
+    )?;
+
+    let data_blobs: Vec<_> = data_ids
+        .into_iter()
+        .filter_map(|id| {
+            index
+                .get_data(&id)
+                .map(|entry| CopyPackBlobs::from_index_entry(entry, id.into()))
+        })
+        .collect();
+    let tree_blobs: Vec<_> = tree_ids
+        .into_iter()
+        .filter_map(|id| {
+            index
+                .get_tree(&id)
+                .map(|entry| CopyPackBlobs::from_index_entry(entry, id.into()))
+        })
+        .collect();
+
+    let indexers: Vec<_> = repos_dest
+        .iter()
+        .map(|repo| Indexer::new(repo.dbe().clone()).into_shared())
+        .collect();
+
+    for (blob_type, blobs) in [(BlobType::Data, data_blobs), (BlobType::Tree, tree_blobs)] {
+        let packers = repos_dest
+            .iter()
+            .zip(&indexers)
+            .map(|(repo_dest, indexer)| {
+                let pack_sizer = PackSizer::from_config(
+                    repo_dest.config(),
+                    blob_type,
+                    repo_dest.index().total_size(blob_type),
+                );
+                Packer::new(
+                    repo_dest.dbe().clone(),
+                    blob_type,
+                    indexer.clone(),
+                    pack_sizer,
+                )
+            })
+            .collect::<RusticResult<Vec<_>>>()?;
+
+        let p = repo.progress_bytes(match blob_type {
+            BlobType::Data => "copying data blobs...",
+            BlobType::Tree => "copying tree blobs...",
+        });
+        copy_blobs_to_many(
+            be,
+            blob_type,
+            blobs,
+            &packers,
+            |i, id| !indexes_dest[i].has(blob_type, id),
+            &p,
+            repo.cancellation_token(),
+        )?;
+        for packer in packers {
+            _ = packer.finalize()?;
+        }
+    }
+
+    for indexer in &indexers {
+        indexer.write().unwrap().finalize()?;
+    }
+    repo.cancellation_token().check("copy")?;
+
+    for repo_dest in repos_dest {
+        let p = repo_dest.progress_counter("saving snapshots...");
+        repo_dest.dbe().save_list(snaps.iter(), p)?;
+    }
+    Ok(())
+}
+
+/// Find the trees and data blobs needed by the given trees
+///
+/// Only blobs accepted by `filter_tree` or `filter_data` are returned; subtrees are always traversed
+/// unless they are contained in `visited`.
+///
+/// # Returns
+///
+/// The needed trees, the needed data blobs and all visited trees including the given `visited` trees
+fn find_needed_blobs<BE: DecryptReadBackend>(
+    be: &BE,
+    index: &impl ReadGlobalIndex,
+    snap_trees: Vec<TreeId>,
+    visited: BTreeSet<TreeId>,
+    filter_tree: impl Fn(&TreeId) -> bool,
+    filter_data: impl Fn(&DataId) -> bool,
+    p: Progress,
+) -> RusticResult<(BTreeSet<TreeId>, BTreeSet<DataId>, BTreeSet<TreeId>)> {
+    let mut tree_ids: BTreeSet<_> = snap_trees
+        .iter()
+        .filter(|id| !visited.contains(id))
+        .copied()
+        .filter(&filter_tree)
+        .collect();
+    let mut data_ids = BTreeSet::new();
+
+    let mut tree_streamer = TreeStreamerOnce::new_with_visited(be, index, snap_trees, visited, p)?;
+    while let Some(item) = tree_streamer.next().transpose()? {
+        let (_, tree) = item;
+        for node in tree.nodes {
+            match node.node_type {
+                NodeType::File => {
+                    data_ids.extend(node.content.into_iter().flatten().filter(&filter_data));
+                }
+                NodeType::Dir => {
+                    tree_ids.extend(node.subtree.into_iter().filter(&filter_tree));
+                }
+                _ => {} // nothing to do
+            }
+        }
+    }
+    Ok((tree_ids, data_ids, tree_streamer.into_visited()))
+}
+
+/// Copy blobs to several packers, reading and decrypting each blob only once.
+///
+/// `needs(i, id)` determines whether the blob `id` is needed by the `i`-th packer.
+fn copy_blobs_to_many<BE: DecryptFullBackend>(
+    be: &BE,
+    blob_type: BlobType,
+    mut blobs: Vec<CopyPackBlobs>,
+    packers: &[Packer<BE>],
+    needs: impl Fn(usize, &BlobId) -> bool + Sync,
+    p: &Progress,
+    cancel: &CancellationToken,
+) -> RusticResult<()> {
+    blobs.sort_unstable();
+    let blobs: Vec<_> = blobs
+        .into_iter()
+        .coalesce(CopyPackBlobs::coalesce)
+        .collect();
+
+    let length = blobs
+        .iter()
+        .map(|blob| u64::from(blob.locations.length()))
+        .sum();
+    p.set_length(length);
+
+    blobs
+        .into_par_iter()
+        .try_for_each(|pack_blobs| -> RusticResult<_> {
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+            let offset = pack_blobs.locations.offset;
+            let read_data = be.read_partial(
+                FileType::Pack,
+                &pack_blobs.pack_id,
+                blob_type.is_cacheable(),
+                offset,
+                pack_blobs.locations.length,
+            )?;
+
+            for (blob, blob_id) in pack_blobs.locations.blobs {
+                let start = usize::try_from(blob.offset - offset)
+                    .expect("convert from u32 to usize should not fail!");
+                let end = usize::try_from(blob.offset + blob.length - offset)
+                    .expect("convert from u32 to usize should not fail!");
+                let data = be.read_encrypted_from_partial(
+                    &read_data[start..end],
+                    blob.uncompressed_length,
+                )?;
+                for (i, packer) in packers.iter().enumerate() {
+                    if needs(i, &blob_id) {
+                        packer.add(data.clone(), blob_id)?;
+                    }
+                }
+                p.inc(blob.length.into());
+            }
+            Ok(())
+        })?;
+    p.finish();
     Ok(())
 }
 
 #[allow(clippy::needless_pass_by_value)]
-fn copy_blobs<BE: DecryptFullBackend>(
+pub(crate) fn copy_blobs<BE: DecryptFullBackend>(
     mut blobs: Vec<CopyPackBlobs>,
     copier: BlobCopier<BE>,
     p: Progress,
+    cancel: &CancellationToken,
 ) -> RusticResult<()> {
     blobs.sort_unstable();
     let blobs: Vec<_> = blobs
@@ -166,7 +792,13 @@ fn copy_blobs<BE: DecryptFullBackend>(
 
     blobs
         .into_par_iter()
-        .try_for_each(|blobs| -> RusticResult<_> { copier.copy(blobs, &p) })?;
+        .try_for_each(|blobs| -> RusticResult<_> {
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+            copier.copy(blobs, &p)
+        })?;
+    // also finalize if cancelled, so that all copied blobs are indexed
     _ = copier.finalize()?;
     p.finish();
     Ok(())
@@ -203,8 +835,13 @@ where
 {
     let p = dest_repo.progress_counter("finding relevant snapshots...");
     // save snapshots in destination in BTreeSet, as we want to efficiently search within to filter out already existing snapshots before copying.
-    let snapshots_dest: BTreeSet<_> =
-        SnapshotFile::iter_all_from_backend(dest_repo.dbe(), filter, &p)?.collect();
+    let snapshots_dest: BTreeSet<_> = SnapshotFile::iter_all_from_backend(
+        dest_repo.dbe(),
+        filter,
+        dest_repo.snapshot_error_policy(),
+        &p,
+    )?
+    .collect();
 
     let relevant = snaps
         .iter()
@@ -1,3 +1,5 @@
+pub mod archive;
+
 use std::{io::Write, thread::scope};
 
 use pariter::IteratorExt;
@@ -68,6 +70,77 @@ pub(crate) fn dump<S: IndexedFull>(
     })
 }
 
+/// Restores the contents of a file into an arbitrary writer.
+///
+/// In contrast to [`dump`], this flushes the writer and checks that the number of written
+/// bytes matches the size saved in the node.
+///
+/// # Type Parameters
+///
+/// * `S` - The type of the indexed tree.
+/// * `W` - The type of the writer.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to read from.
+/// * `node` - The node to restore.
+/// * `w` - The writer to write to.
+///
+/// # Errors
+///
+/// * If the node is not a file.
+/// * If a blob cannot be fetched from the backend.
+/// * If writing to or flushing `w` fails.
+/// * If the written size doesn't match the size of the node.
+///
+/// # Returns
+///
+/// The writer after all contents have been written.
+pub(crate) fn restore_to_writer<S: IndexedFull, W: Write>(
+    repo: &Repository<S>,
+    node: &Node,
+    w: W,
+) -> RusticResult<W> {
+    let mut w = CountingWriter { inner: w, count: 0 };
+    dump(repo, node, &mut w)?;
+    w.flush().map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::InputOutput,
+            "Failed to flush data to writer.",
+            err,
+        )
+    })?;
+
+    if w.count != node.meta.size {
+        return Err(RusticError::new(
+            ErrorKind::Verification,
+            "Restored size `{written}` of node `{name}` does not match the expected size `{size}`. The node metadata or the repository may be damaged.",
+        )
+        .attach_context("written", w.count.to_string())
+        .attach_context("name", node.name().to_string_lossy())
+        .attach_context("size", node.meta.size.to_string()));
+    }
+    Ok(w.inner)
+}
+
+/// A writer which counts the bytes written into the inner writer.
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 fn dump_sequential<S: IndexedFull>(
     repo: &Repository<S>,
     content: &[DataId],
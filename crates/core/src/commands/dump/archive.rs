@@ -0,0 +1,496 @@
+//! Export of a subtree of a snapshot as tar or zip archive
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{
+    Compression, CrcReader,
+    write::{DeflateEncoder, GzEncoder},
+};
+use jiff::{Timestamp, tz::TimeZone};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tar::{Builder, EntryType, Header};
+
+use crate::{
+    backend::node::{Node, NodeType},
+    blob::tree::TreeStreamerOptions,
+    commands::{copy::ContentReader, dump::CountingWriter},
+    error::{ErrorKind, RusticError, RusticResult},
+    repository::{IndexedFull, Repository},
+};
+
+/// The file type bits of a regular file, see `inode(7)`
+const S_IFREG: u32 = 0o100_000;
+/// The file type bits of a directory, see `inode(7)`
+const S_IFDIR: u32 = 0o040_000;
+/// The file type bits of a symlink, see `inode(7)`
+const S_IFLNK: u32 = 0o120_000;
+
+/// The format of an archive created by [`Repository::dump_archive`]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ArchiveFormat {
+    /// Uncompressed tar archive
+    #[default]
+    Tar,
+    /// Gzip compressed tar archive
+    TarGz,
+    /// Zip archive with deflate compressed files
+    Zip,
+}
+
+/// Streams the given node and all its contents as archive into a writer.
+///
+/// Files, dirs and symlinks are added with their permissions and modification times; tar archives
+/// additionally contain the owners and fifos. Devices and sockets cannot be added and are skipped.
+///
+/// # Type Parameters
+///
+/// * `S` - The type of the indexed tree.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to read from.
+/// * `node` - The node to dump.
+/// * `format` - The archive format.
+/// * `w` - The writer to write to.
+///
+/// # Errors
+///
+/// * If a tree or a blob cannot be read from the backend.
+/// * If writing to `w` fails.
+/// * If the contents exceed the limits of the zip format (4 GiB, 65535 entries).
+pub(crate) fn dump_archive<S: IndexedFull>(
+    repo: &Repository<S>,
+    node: &Node,
+    format: ArchiveFormat,
+    w: &mut impl Write,
+) -> RusticResult<()> {
+    let entries = repo.ls(node, &TreeStreamerOptions::default())?;
+    match format {
+        ArchiveFormat::Tar => write_tar(repo, entries, w),
+        ArchiveFormat::TarGz => {
+            let mut gz = GzEncoder::new(w, Compression::default());
+            write_tar(repo, entries, &mut gz)?;
+            _ = gz
+                .finish()
+                .map_err(|err| archive_error(Path::new(""), err))?;
+            Ok(())
+        }
+        ArchiveFormat::Zip => write_zip(repo, entries, w),
+    }
+}
+
+/// Creates the error returned if writing an archive entry failed
+fn archive_error(path: &Path, err: io::Error) -> Box<RusticError> {
+    RusticError::with_source(
+        ErrorKind::InputOutput,
+        "Failed to write archive entry `{path}`.",
+        err,
+    )
+    .attach_context("path", path.display().to_string())
+}
+
+/// Returns the permission bits of the node, using defaults if the node doesn't have a mode
+fn permissions(node: &Node) -> u32 {
+    node.meta
+        .mode
+        .map_or(if node.is_dir() { 0o755 } else { 0o644 }, |mode| {
+            mode & 0o777
+        })
+}
+
+/// Writes all entries as tar archive.
+///
+/// # Errors
+///
+/// * If an entry cannot be read or written.
+fn write_tar<S: IndexedFull>(
+    repo: &Repository<S>,
+    entries: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+    w: &mut impl Write,
+) -> RusticResult<()> {
+    let mut builder = Builder::new(w);
+    for entry in entries {
+        let (path, node) = entry?;
+        let mut header = Header::new_gnu();
+        header.set_mode(permissions(&node));
+        if let Some(mtime) = node.meta.mtime {
+            header.set_mtime(u64::try_from(mtime.as_second()).unwrap_or_default());
+        }
+        if let Some(uid) = node.meta.uid {
+            header.set_uid(uid.into());
+        }
+        if let Some(gid) = node.meta.gid {
+            header.set_gid(gid.into());
+        }
+        // names which don't fit into the header are omitted
+        if let Some(user) = &node.meta.user {
+            _ = header.set_username(user);
+        }
+        if let Some(group) = &node.meta.group {
+            _ = header.set_groupname(group);
+        }
+
+        let result = match &node.node_type {
+            NodeType::File => {
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(node.meta.size);
+                let content = node.content.as_deref().unwrap_or_default();
+                let reader = ContentReader::new(repo.dbe(), repo.index(), content);
+                builder.append_data(&mut header, &path, reader)
+            }
+            NodeType::Dir => {
+                header.set_entry_type(EntryType::Directory);
+                header.set_size(0);
+                builder.append_data(&mut header, &path, io::empty())
+            }
+            NodeType::Symlink { .. } => {
+                header.set_entry_type(EntryType::Symlink);
+                header.set_size(0);
+                builder.append_link(&mut header, &path, node.node_type.to_link())
+            }
+            NodeType::Fifo => {
+                header.set_entry_type(EntryType::Fifo);
+                header.set_size(0);
+                builder.append_data(&mut header, &path, io::empty())
+            }
+            NodeType::Dev { .. } | NodeType::Chardev { .. } | NodeType::Socket => {
+                warn!(
+                    "skipping {}: {} is not supported in archives",
+                    path.display(),
+                    node.node_type
+                );
+                continue;
+            }
+        };
+        result.map_err(|err| archive_error(&path, err))?;
+    }
+    _ = builder
+        .into_inner()
+        .map_err(|err| archive_error(Path::new(""), err))?;
+    Ok(())
+}
+
+/// Writes all entries as zip archive.
+///
+/// # Errors
+///
+/// * If an entry cannot be read or written.
+fn write_zip<S: IndexedFull>(
+    repo: &Repository<S>,
+    entries: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+    w: &mut impl Write,
+) -> RusticResult<()> {
+    let mut zip = ZipWriter::new(w);
+    for entry in entries {
+        let (path, node) = entry?;
+        let mut name = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let mode = permissions(&node);
+        let mtime = node.meta.mtime;
+
+        let result = match &node.node_type {
+            NodeType::File => {
+                let content = node.content.as_deref().unwrap_or_default();
+                let reader = ContentReader::new(repo.dbe(), repo.index(), content);
+                zip.add_deflated(&name, S_IFREG | mode, mtime, reader)
+            }
+            NodeType::Dir => {
+                name.push('/');
+                zip.add_stored(&name, S_IFDIR | mode, mtime, &[])
+            }
+            NodeType::Symlink { .. } => {
+                let target = node.node_type.to_link().to_string_lossy();
+                zip.add_stored(&name, S_IFLNK | mode, mtime, target.as_bytes())
+            }
+            _ => {
+                warn!(
+                    "skipping {}: {} is not supported in zip archives",
+                    path.display(),
+                    node.node_type
+                );
+                continue;
+            }
+        };
+        result.map_err(|err| archive_error(&path, err))?;
+    }
+    zip.finish()
+        .map_err(|err| archive_error(Path::new(""), err))
+}
+
+/// An entry of a zip archive, needed to write the central directory
+#[derive(Debug)]
+struct ZipEntry {
+    /// The name of the entry; dirs end with `/`
+    name: Vec<u8>,
+    /// The general purpose flags
+    flags: u16,
+    /// The compression method
+    method: u16,
+    /// The modification time in MS-DOS format
+    time: u16,
+    /// The modification date in MS-DOS format
+    date: u16,
+    /// The modification time as unix timestamp, if representable
+    mtime: Option<i32>,
+    /// The CRC-32 of the uncompressed data
+    crc: u32,
+    /// The size of the compressed data
+    compressed_size: u32,
+    /// The size of the uncompressed data
+    size: u32,
+    /// The unix mode including the file type
+    mode: u32,
+    /// The offset of the local header
+    offset: u32,
+}
+
+impl ZipEntry {
+    /// Flag: the sizes and the CRC-32 are given in a data descriptor after the data
+    const DATA_DESCRIPTOR: u16 = 1 << 3;
+    /// Flag: the name is UTF-8 encoded
+    const UTF8: u16 = 1 << 11;
+    /// Compression method: stored
+    const STORED: u16 = 0;
+    /// Compression method: deflate
+    const DEFLATED: u16 = 8;
+    /// Version needed to extract: 2.0 (deflate and dirs)
+    const VERSION: u16 = 20;
+
+    /// Returns the "extended timestamp" extra field containing the unix mtime
+    fn extra_field(&self) -> Vec<u8> {
+        let Some(mtime) = self.mtime else {
+            return Vec::new();
+        };
+        let mut extra = Vec::with_capacity(9);
+        extra.extend_from_slice(&0x5455_u16.to_le_bytes());
+        extra.extend_from_slice(&5_u16.to_le_bytes());
+        extra.push(1);
+        extra.extend_from_slice(&mtime.to_le_bytes());
+        extra
+    }
+
+    /// Returns the local file header
+    fn local_header(&self) -> Vec<u8> {
+        let extra = self.extra_field();
+        let mut header = Vec::with_capacity(30 + self.name.len() + extra.len());
+        header.extend_from_slice(&0x0403_4b50_u32.to_le_bytes());
+        for value in [Self::VERSION, self.flags, self.method, self.time, self.date] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [self.crc, self.compressed_size, self.size] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        header.extend_from_slice(&len_u16(self.name.len()).to_le_bytes());
+        header.extend_from_slice(&len_u16(extra.len()).to_le_bytes());
+        header.extend_from_slice(&self.name);
+        header.extend_from_slice(&extra);
+        header
+    }
+
+    /// Returns the central directory header
+    fn central_header(&self) -> Vec<u8> {
+        let extra = self.extra_field();
+        let mut attributes = self.mode << 16;
+        if self.mode & S_IFDIR == S_IFDIR {
+            // MS-DOS directory attribute
+            attributes |= 0x10;
+        }
+        let mut header = Vec::with_capacity(46 + self.name.len() + extra.len());
+        header.extend_from_slice(&0x0201_4b50_u32.to_le_bytes());
+        // version made by: unix
+        header.extend_from_slice(&((3 << 8) | Self::VERSION).to_le_bytes());
+        for value in [Self::VERSION, self.flags, self.method, self.time, self.date] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [self.crc, self.compressed_size, self.size] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        // name length, extra length, comment length, disk number, internal attributes
+        for value in [len_u16(self.name.len()), len_u16(extra.len()), 0, 0, 0] {
+            header.extend_from_slice(&value.to_le_bytes());
+        }
+        header.extend_from_slice(&attributes.to_le_bytes());
+        header.extend_from_slice(&self.offset.to_le_bytes());
+        header.extend_from_slice(&self.name);
+        header.extend_from_slice(&extra);
+        header
+    }
+}
+
+/// Converts a length which is known to be small into `u16`
+fn len_u16(len: usize) -> u16 {
+    u16::try_from(len).unwrap_or(u16::MAX)
+}
+
+/// Converts a size or offset into `u32`, failing if it exceeds the limits of the zip format
+///
+/// # Errors
+///
+/// * If the value doesn't fit into `u32`; zip64 is not supported.
+fn zip_u32(value: u64) -> io::Result<u32> {
+    u32::try_from(value)
+        .map_err(|_| io::Error::other("archive exceeds 4 GiB which is not supported for zip"))
+}
+
+/// Converts a timestamp into MS-DOS time and date in the local time zone
+///
+/// Times before 1980 or after 2107 are clamped to the supported range.
+fn dos_time(mtime: Option<Timestamp>) -> (u16, u16) {
+    let Some(dt) = mtime.map(|t| t.to_zoned(TimeZone::system()).datetime()) else {
+        return (0, (1 << 5) | 1);
+    };
+    match dt.year() {
+        ..1980 => (0, (1 << 5) | 1),
+        2108.. => (0xbf7d, 0xff9f),
+        year => {
+            let date = ((year - 1980).unsigned_abs() << 9)
+                | (u16::from(dt.month().unsigned_abs()) << 5)
+                | u16::from(dt.day().unsigned_abs());
+            let time = (u16::from(dt.hour().unsigned_abs()) << 11)
+                | (u16::from(dt.minute().unsigned_abs()) << 5)
+                | u16::from(dt.second().unsigned_abs() / 2);
+            (time, date)
+        }
+    }
+}
+
+/// A minimal streaming zip writer which doesn't need to seek in the output
+struct ZipWriter<W> {
+    /// The writer to write to
+    w: CountingWriter<W>,
+    /// The already written entries
+    entries: Vec<ZipEntry>,
+}
+
+impl<W: Write> ZipWriter<W> {
+    /// Creates a new `ZipWriter`.
+    fn new(w: W) -> Self {
+        Self {
+            w: CountingWriter { inner: w, count: 0 },
+            entries: Vec::new(),
+        }
+    }
+
+    /// Creates a new entry starting at the current position
+    ///
+    /// # Errors
+    ///
+    /// * If the archive is too large.
+    fn entry(&self, name: &str, mode: u32, mtime: Option<Timestamp>) -> io::Result<ZipEntry> {
+        if self.entries.len() >= usize::from(u16::MAX) {
+            return Err(io::Error::other(
+                "archive exceeds 65535 entries which is not supported for zip",
+            ));
+        }
+        let (time, date) = dos_time(mtime);
+        Ok(ZipEntry {
+            name: name.as_bytes().to_vec(),
+            flags: ZipEntry::UTF8,
+            method: ZipEntry::STORED,
+            time,
+            date,
+            mtime: mtime.and_then(|t| i32::try_from(t.as_second()).ok()),
+            crc: 0,
+            compressed_size: 0,
+            size: 0,
+            mode,
+            offset: zip_u32(self.w.count)?,
+        })
+    }
+
+    /// Adds an entry with the given uncompressed data.
+    ///
+    /// # Errors
+    ///
+    /// * If writing fails or the archive is too large.
+    fn add_stored(
+        &mut self,
+        name: &str,
+        mode: u32,
+        mtime: Option<Timestamp>,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let mut entry = self.entry(name, mode, mtime)?;
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        entry.crc = crc.sum();
+        entry.size = zip_u32(data.len() as u64)?;
+        entry.compressed_size = entry.size;
+        self.w.write_all(&entry.local_header())?;
+        self.w.write_all(data)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Adds an entry with the data read from `reader`, compressed using deflate.
+    ///
+    /// # Errors
+    ///
+    /// * If reading or writing fails or the archive is too large.
+    fn add_deflated(
+        &mut self,
+        name: &str,
+        mode: u32,
+        mtime: Option<Timestamp>,
+        reader: impl Read,
+    ) -> io::Result<()> {
+        let mut entry = self.entry(name, mode, mtime)?;
+        entry.flags |= ZipEntry::DATA_DESCRIPTOR;
+        entry.method = ZipEntry::DEFLATED;
+        self.w.write_all(&entry.local_header())?;
+
+        let start = self.w.count;
+        let mut reader = CrcReader::new(reader);
+        let mut encoder = DeflateEncoder::new(&mut self.w, Compression::default());
+        let size = io::copy(&mut reader, &mut encoder)?;
+        _ = encoder.finish()?;
+        entry.crc = reader.crc().sum();
+        entry.size = zip_u32(size)?;
+        entry.compressed_size = zip_u32(self.w.count - start)?;
+
+        // data descriptor
+        let mut descriptor = Vec::with_capacity(16);
+        for value in [0x0807_4b50, entry.crc, entry.compressed_size, entry.size] {
+            descriptor.extend_from_slice(&value.to_le_bytes());
+        }
+        self.w.write_all(&descriptor)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and flushes the writer.
+    ///
+    /// # Errors
+    ///
+    /// * If writing fails or the archive is too large.
+    fn finish(mut self) -> io::Result<()> {
+        let start = self.w.count;
+        for entry in &self.entries {
+            self.w.write_all(&entry.central_header())?;
+        }
+        let size = zip_u32(self.w.count - start)?;
+        let offset = zip_u32(start)?;
+        let count = len_u16(self.entries.len());
+
+        // end of central directory record
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&0x0605_4b50_u32.to_le_bytes());
+        for value in [0, 0, count, count] {
+            end.extend_from_slice(&value.to_le_bytes());
+        }
+        end.extend_from_slice(&size.to_le_bytes());
+        end.extend_from_slice(&offset.to_le_bytes());
+        end.extend_from_slice(&0_u16.to_le_bytes());
+        self.w.write_all(&end)?;
+        self.w.flush()
+    }
+}
@@ -0,0 +1,90 @@
+//! `find` subcommand: search for files across many snapshots
+use std::path::{Path, PathBuf};
+
+use ignore::{Match, overrides::OverrideBuilder};
+use serde_derive::Serialize;
+
+use crate::{
+    blob::tree::FindMatches,
+    error::{ErrorKind, RusticError, RusticResult},
+    repofile::{Node, SnapshotFile, snapshotfile::SnapshotId},
+    repository::{IndexedTree, Repository},
+};
+
+/// A node found by [`Repository::find`]
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct FoundNode {
+    /// The snapshot containing the node
+    pub snapshot: SnapshotId,
+    /// The path of the node within the snapshot
+    pub path: PathBuf,
+    /// The found node
+    pub node: Node,
+}
+
+/// Search all snapshots matching the filter for nodes matching the glob pattern.
+///
+/// The pattern uses the gitignore syntax: A pattern without `/` matches the file name at any level,
+/// e.g. `*.txt`, while a pattern containing `/` matches the path within the snapshot. Subtrees shared
+/// by multiple snapshots are only read and matched once.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to search
+/// * `pattern` - The glob pattern to search for
+/// * `filter` - The filter to select the snapshots to search
+///
+/// # Errors
+///
+/// * If the glob pattern is invalid.
+/// * If the snapshots or the trees could not be read.
+///
+/// # Returns
+///
+/// All found nodes, ordered by snapshot.
+pub(crate) fn find<S: IndexedTree>(
+    repo: &Repository<S>,
+    pattern: &str,
+    filter: impl FnMut(&SnapshotFile) -> bool,
+) -> RusticResult<Vec<FoundNode>> {
+    let mut override_builder = OverrideBuilder::new("");
+    _ = override_builder.add(pattern).map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::InvalidInput,
+            "Invalid glob pattern `{glob}`.",
+            err,
+        )
+        .attach_context("glob", pattern)
+    })?;
+    let overrides = override_builder.build().map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::InvalidInput,
+            "Failed to build matcher for glob pattern `{glob}`.",
+            err,
+        )
+        .attach_context("glob", pattern)
+    })?;
+
+    let snapshots = repo.get_matching_snapshots(filter)?;
+    let match_func = |path: &Path, node: &Node| {
+        matches!(overrides.matched(path, node.is_dir()), Match::Whitelist(_))
+    };
+    let FindMatches {
+        paths,
+        nodes,
+        matches,
+    } = repo.find_matching_nodes(snapshots.iter().map(|sn| sn.tree), &match_func)?;
+
+    Ok(snapshots
+        .iter()
+        .zip(matches)
+        .flat_map(|(sn, matches)| {
+            matches.into_iter().map(|(path_idx, node_idx)| FoundNode {
+                snapshot: sn.id,
+                path: paths[path_idx].clone(),
+                node: nodes[node_idx].clone(),
+            })
+        })
+        .collect())
+}
@@ -1,19 +1,29 @@
 //! `forget` subcommand
 
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytesize::ByteSize;
 use derive_setters::Setters;
 use jiff::{Span, Zoned};
+use log::info;
 use serde_derive::{Deserialize, Serialize};
 use serde_with::{DisplayFromStr, serde_as, skip_serializing_none};
 
 use crate::{
+    backend::decrypt::DecryptReadBackend,
+    blob::{
+        BlobId,
+        tree::{Tree, TreeId},
+    },
     error::{ErrorKind, RusticError, RusticResult},
     repofile::{
-        SnapshotFile, StringList,
+        IndexFile, SnapshotFile, StringList,
         snapshotfile::{
             SnapshotId,
-            grouping::{Group, Grouped, SnapshotGroup},
+            grouping::{Group, Grouped, SnapshotGroup, SnapshotGroupCriterion},
         },
     },
+    repository::{IndexedTree, Open, Repository},
 };
 
 type CheckFunction = fn(&SnapshotFile, &SnapshotFile) -> bool;
@@ -58,6 +68,28 @@ impl ForgetGroups {
         g: Grouped<SnapshotFile>,
         keep: &KeepOptions,
         now: &Zoned,
+    ) -> RusticResult<Self> {
+        Self::from_grouped_snapshots_with_tags(g, keep, &RetentionTags::default(), now)
+    }
+
+    /// Determine Snapshots-to-forget from grouped snapshots, additionally keeping snapshots which are
+    /// protected by their tags
+    ///
+    /// # Arguments
+    ///
+    /// * `g` - The grouped snapshots.
+    /// * `keep` - The retention options
+    /// * `retention_tags` - The retention classes assigned by tags
+    /// * `now` - Time to be used to evaluate the retention options; typically now
+    ///
+    /// # Errors
+    ///
+    /// * If keep options are not valid
+    pub fn from_grouped_snapshots_with_tags(
+        g: Grouped<SnapshotFile>,
+        keep: &KeepOptions,
+        retention_tags: &RetentionTags,
+        now: &Zoned,
     ) -> RusticResult<Self> {
         let groups = g
             .groups
@@ -65,7 +97,7 @@ impl ForgetGroups {
             .map(|group| -> RusticResult<_> {
                 Ok(Group {
                     group_key: group.group_key,
-                    items: keep.apply(group.items, now)?,
+                    items: keep.apply_with_tags(group.items, retention_tags, now)?,
                 })
             })
             .collect::<RusticResult<_>>()?;
@@ -81,10 +113,27 @@ impl ForgetGroups {
     /// * `now` - Time to be used to evaluate the delete option; typically now
     #[must_use]
     pub fn from_snapshots(snapshots: Vec<SnapshotFile>, now: &Zoned) -> Self {
+        Self::from_snapshots_with_tags(snapshots, &RetentionTags::default(), now)
+    }
+
+    /// Determine Snapshots-to-forget from a list of snapshots by only evaluating delete option in
+    /// [`SnapshotFile`] and the retention classes assigned by tags
+    ///
+    /// # Arguments
+    ///
+    /// * `g` - The grouped snapshots.
+    /// * `retention_tags` - The retention classes assigned by tags
+    /// * `now` - Time to be used to evaluate the delete option; typically now
+    #[must_use]
+    pub fn from_snapshots_with_tags(
+        snapshots: Vec<SnapshotFile>,
+        retention_tags: &RetentionTags,
+        now: &Zoned,
+    ) -> Self {
         let snapshots = snapshots
             .into_iter()
             .map(|sn| {
-                let keep = sn.must_keep(now);
+                let keep = retention_tags.must_keep(&sn, now);
                 ForgetSnapshot {
                     snapshot: sn,
                     keep,
@@ -111,6 +160,329 @@ impl ForgetGroups {
     }
 }
 
+/// Get the snapshots of the repository grouped by `group_by` together with the information which of them to forget.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `keep` - The retention options
+/// * `group_by` - The criterion to group the snapshots by
+/// * `filter` - The filter to apply to the snapshots
+///
+/// # Errors
+///
+/// * If the snapshots could not be read.
+/// * If keep options are not valid
+pub(crate) fn get_forget_snapshots<S: Open>(
+    repo: &Repository<S>,
+    keep: &KeepOptions,
+    group_by: SnapshotGroupCriterion,
+    filter: impl FnMut(&SnapshotFile) -> bool,
+) -> RusticResult<ForgetGroups> {
+    let now = Zoned::now();
+    let groups = Grouped::from_items(repo.get_matching_snapshots(filter)?, group_by);
+    ForgetGroups::from_grouped_snapshots_with_tags(groups, keep, repo.retention_tags(), &now)
+}
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Clone, Debug, Default, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for the `forget` command
+pub struct ForgetOptions {
+    /// The retention options
+    #[cfg_attr(
+        feature = "clap",
+        clap(flatten, next_help_heading = "Retention options")
+    )]
+    pub keep: KeepOptions,
+
+    /// Additionally forget the oldest snapshots until the projected repository size after pruning is at most SIZE.
+    /// Protected snapshots and the latest snapshot of each group are never forgotten because of this option.
+    #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    pub max_repo_size: Option<ByteSize>,
+}
+
+/// Get the snapshots to forget like [`get_forget_snapshots`], additionally respecting [`ForgetOptions::max_repo_size`].
+///
+/// If only `max_repo_size` is given, all snapshots are kept unless they need to be forgotten to reach the size.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `opts` - The forget options
+/// * `group_by` - The criterion to group the snapshots by
+/// * `filter` - The filter to apply to the snapshots
+///
+/// # Errors
+///
+/// * If the snapshots, the index or the trees could not be read.
+/// * If keep options are not valid
+pub(crate) fn get_forget_snapshots_with_options<S: IndexedTree>(
+    repo: &Repository<S>,
+    opts: &ForgetOptions,
+    group_by: SnapshotGroupCriterion,
+    filter: impl FnMut(&SnapshotFile) -> bool,
+) -> RusticResult<ForgetGroups> {
+    let Some(max_size) = opts.max_repo_size else {
+        return get_forget_snapshots(repo, &opts.keep, group_by, filter);
+    };
+
+    let keep = if opts.keep.is_valid() {
+        opts.keep.clone()
+    } else {
+        opts.keep.clone().keep_last(-1)
+    };
+    let mut groups = get_forget_snapshots(repo, &keep, group_by, filter)?;
+    apply_max_repo_size(repo, &mut groups, max_size.as_u64())?;
+    Ok(groups)
+}
+
+/// Additionally forget the oldest kept snapshots until the projected repository size after pruning is at most `max_size`
+///
+/// The projected size is the size of all blobs which are used by the snapshots not being forgotten,
+/// including the snapshots not matching the filter; the overhead of pack headers is not taken into account.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `groups` - The snapshots to forget as determined by the keep options
+/// * `max_size` - The maximum repository size
+///
+/// # Errors
+///
+/// * If the snapshots, the index or the trees could not be read.
+fn apply_max_repo_size<S: IndexedTree>(
+    repo: &Repository<S>,
+    groups: &mut ForgetGroups,
+    max_size: u64,
+) -> RusticResult<()> {
+    let p = repo.progress_counter("reading index...");
+    let mut sizes = BTreeMap::new();
+    let mut repo_size = 0;
+    for index in repo.dbe().stream_all::<IndexFile>(&p)? {
+        let index = index?.1;
+        for pack in index.packs {
+            repo_size += u64::from(pack.pack_size());
+            for blob in pack.blobs {
+                _ = sizes.insert(blob.id, u64::from(blob.location.length));
+            }
+        }
+        for pack in index.packs_to_delete {
+            repo_size += u64::from(pack.pack_size());
+        }
+    }
+    p.finish();
+    if repo_size <= max_size {
+        return Ok(());
+    }
+
+    // all snapshots which are not forgotten keep their blobs, also those not contained in `groups`
+    let forgotten: BTreeSet<_> = groups
+        .0
+        .iter()
+        .flat_map(|group| &group.items)
+        .filter(|sn| !sn.keep)
+        .map(|sn| sn.snapshot.id)
+        .collect();
+    let trees: Vec<_> = repo
+        .get_all_snapshots()?
+        .into_iter()
+        .filter(|sn| !forgotten.contains(&sn.id))
+        .map(|sn| sn.tree)
+        .collect();
+    let mut refs = BlobRefs::from_trees(repo, &trees)?;
+    let mut projected: u64 = refs.counts.keys().filter_map(|id| sizes.get(id)).sum();
+
+    // candidates are all kept snapshots which are not protected, except the latest of each group
+    let now = Zoned::now();
+    let retention_tags = repo.retention_tags();
+    let mut candidates = Vec::new();
+    for (group_idx, group) in groups.0.iter().enumerate() {
+        let latest = group
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, sn)| sn.keep)
+            .max_by_key(|(_, sn)| &sn.snapshot)
+            .map(|(idx, _)| idx);
+        for (idx, sn) in group.items.iter().enumerate() {
+            if sn.keep && Some(idx) != latest && !retention_tags.must_keep(&sn.snapshot, &now) {
+                candidates.push((sn.snapshot.time.clone(), group_idx, idx));
+            }
+        }
+    }
+    candidates.sort_unstable();
+
+    for (_, group_idx, idx) in candidates {
+        if projected <= max_size {
+            break;
+        }
+        let sn = &mut groups.0[group_idx].items[idx];
+        sn.keep = false;
+        sn.reasons = vec!["max-repo-size".to_string()];
+        refs.release(sn.snapshot.tree, |id| {
+            projected -= sizes.get(id).copied().unwrap_or_default();
+        });
+    }
+
+    info!(
+        "projected repository size after pruning: {}",
+        ByteSize(projected).display().iec()
+    );
+    Ok(())
+}
+
+/// Reference counts of all blobs used by a set of snapshot trees
+///
+/// Each tree is read only once. A blob is counted once per tree containing it and once per snapshot
+/// using it as root tree, so that releasing a snapshot only needs to follow the trees which are no
+/// longer referenced.
+#[derive(Debug, Default)]
+struct BlobRefs {
+    /// The number of references to each blob
+    counts: BTreeMap<BlobId, usize>,
+    /// The blobs directly referenced by each tree
+    children: BTreeMap<BlobId, Vec<BlobId>>,
+}
+
+impl BlobRefs {
+    /// Count the blob references of the given snapshot trees
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to use
+    /// * `trees` - The root trees of the snapshots
+    ///
+    /// # Errors
+    ///
+    /// * If a tree could not be read.
+    fn from_trees<S: IndexedTree>(repo: &Repository<S>, trees: &[TreeId]) -> RusticResult<Self> {
+        let p = repo.progress_counter("finding used blobs...");
+        let mut refs = Self::default();
+        let mut pending = Vec::new();
+        for tree in trees {
+            if refs.add_ref(BlobId::from(**tree)) {
+                pending.push(*tree);
+            }
+        }
+        while let Some(id) = pending.pop() {
+            let tree = Tree::from_backend(repo.dbe(), repo.index(), id)?;
+            let mut children = BTreeSet::new();
+            for node in tree.nodes {
+                if let Some(subtree) = node.subtree
+                    && children.insert(BlobId::from(*subtree))
+                    && refs.add_ref(BlobId::from(*subtree))
+                {
+                    pending.push(subtree);
+                }
+                for data in node.content.iter().flatten() {
+                    if children.insert(BlobId::from(**data)) {
+                        _ = refs.add_ref(BlobId::from(**data));
+                    }
+                }
+            }
+            _ = refs
+                .children
+                .insert(BlobId::from(*id), children.into_iter().collect());
+            p.inc(1);
+        }
+        p.finish();
+        Ok(refs)
+    }
+
+    /// Add a reference to the given blob
+    ///
+    /// # Returns
+    ///
+    /// `true` if this is the first reference to the blob
+    fn add_ref(&mut self, id: BlobId) -> bool {
+        let count = self.counts.entry(id).or_default();
+        *count += 1;
+        *count == 1
+    }
+
+    /// Release the reference of a snapshot to its root tree
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The root tree of the snapshot
+    /// * `freed` - Called for each blob which is no longer referenced
+    fn release(&mut self, tree: TreeId, mut freed: impl FnMut(&BlobId)) {
+        let mut pending = vec![BlobId::from(*tree)];
+        while let Some(id) = pending.pop() {
+            let Some(count) = self.counts.get_mut(&id) else {
+                continue;
+            };
+            *count -= 1;
+            if *count == 0 {
+                _ = self.counts.remove(&id);
+                freed(&id);
+                if let Some(children) = self.children.remove(&id) {
+                    pending.extend(children);
+                }
+            }
+        }
+    }
+}
+
+/// A retention class which can be assigned to snapshots by tagging them, see [`RetentionTags`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum RetentionClass {
+    /// Never remove the snapshot; equivalent to [`DeleteOption::Never`](crate::repofile::DeleteOption::Never)
+    Never,
+}
+
+/// Mapping of policy tags (e.g. `retention:legal-hold`) to [`RetentionClass`]es.
+///
+/// Snapshots carrying a mapped tag are treated by `forget` as if their delete option was set
+/// accordingly. This allows to apply retention classes by tagging instead of modifying the
+/// delete option of each snapshot.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct RetentionTags(pub BTreeMap<String, RetentionClass>);
+
+impl RetentionTags {
+    /// Returns whether no tag is mapped
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the retention class a snapshot is assigned to by its tags, if any
+    ///
+    /// # Arguments
+    ///
+    /// * `sn` - The snapshot to check
+    #[must_use]
+    pub fn class(&self, sn: &SnapshotFile) -> Option<RetentionClass> {
+        sn.tags.iter().find_map(|tag| self.0.get(tag)).copied()
+    }
+
+    /// Returns whether a snapshot must be kept now, either by its delete option or by its tags
+    ///
+    /// # Arguments
+    ///
+    /// * `sn` - The snapshot to check
+    /// * `now` - The current time
+    #[must_use]
+    pub fn must_keep(&self, sn: &SnapshotFile, now: &Zoned) -> bool {
+        sn.must_keep(now) || self.class(sn) == Some(RetentionClass::Never)
+    }
+}
+
+#[cfg(feature = "merge")]
+impl conflate::Merge for RetentionTags {
+    /// Add all mappings of `other` for tags which are not yet mapped
+    fn merge(&mut self, other: Self) {
+        for (tag, class) in other.0 {
+            self.0.entry(tag).or_insert(class);
+        }
+    }
+}
+
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
 #[cfg_attr(feature = "merge", derive(conflate::Merge))]
 #[skip_serializing_none]
@@ -568,8 +940,34 @@ impl KeepOptions {
     /// The list of snapshots with the attribute `keep` set to `true` if the snapshot should be kept and
     /// `reasons` set to the list of reasons why the snapshot should be kept
     pub fn apply(
+        &self,
+        snapshots: Vec<SnapshotFile>,
+        now: &Zoned,
+    ) -> RusticResult<Vec<ForgetSnapshot>> {
+        self.apply_with_tags(snapshots, &RetentionTags::default(), now)
+    }
+
+    /// Apply the `[KeepOptions]` like [`KeepOptions::apply`], additionally keeping snapshots which are
+    /// protected by their tags
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshots` - The list of snapshots to apply the options to
+    /// * `retention_tags` - The retention classes assigned by tags
+    /// * `now` - The current time
+    ///
+    /// # Errors
+    ///
+    /// * If keep options are not valid
+    ///
+    /// # Returns
+    ///
+    /// The list of snapshots with the attribute `keep` set to `true` if the snapshot should be kept and
+    /// `reasons` set to the list of reasons why the snapshot should be kept
+    pub fn apply_with_tags(
         &self,
         mut snapshots: Vec<SnapshotFile>,
+        retention_tags: &RetentionTags,
         now: &Zoned,
     ) -> RusticResult<Vec<ForgetSnapshot>> {
         if !self.is_valid() {
@@ -595,6 +993,8 @@ impl KeepOptions {
             let (keep, reasons) = {
                 if sn.must_keep(now) {
                     (true, vec!["snapshot"])
+                } else if retention_tags.class(&sn) == Some(RetentionClass::Never) {
+                    (true, vec!["retention tag"])
                 } else if sn.must_delete(now) {
                     (false, vec!["snapshot"])
                 } else if self.delete_unchanged
@@ -912,4 +1312,31 @@ mod tests {
         });
         Ok(())
     }
+
+    #[rstest]
+    fn test_apply_retention_tags(test_snapshots: Vec<SnapshotFile>) -> Result<()> {
+        let now = parse_time("2016-01-18 12:02:03")?;
+        let retention_tags: RetentionTags =
+            serde_json::from_str(r#"{"bar": "never", "retention:legal-hold": "never"}"#)?;
+        assert_eq!(retention_tags.0.len(), 2);
+
+        let options = KeepOptions::default().keep_last(1);
+        let result = options.apply_with_tags(test_snapshots, &retention_tags, &now)?;
+        for sn in &result {
+            if sn.snapshot.tags.contains("bar") {
+                assert!(sn.keep);
+                assert_eq!(sn.reasons, vec!["retention tag"]);
+            }
+        }
+        // latest snapshot, 3 snapshots tagged with "bar" and the snapshots with delete option
+        assert_eq!(result.iter().filter(|sn| sn.keep).count(), 6);
+
+        let forget = ForgetGroups::from_snapshots_with_tags(
+            result.into_iter().map(|sn| sn.snapshot).collect(),
+            &retention_tags,
+            &now,
+        );
+        assert_eq!(forget.0[0].items.iter().filter(|sn| sn.keep).count(), 5);
+        Ok(())
+    }
 }
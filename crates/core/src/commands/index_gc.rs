@@ -0,0 +1,156 @@
+//! Garbage collection of the index
+use std::collections::BTreeSet;
+
+use derive_setters::Setters;
+use jiff::{Span, Zoned};
+use log::{debug, info};
+use serde_derive::Serialize;
+
+use crate::{
+    backend::{
+        FileType, ReadBackend, WriteBackend,
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+    },
+    error::{ErrorKind, RusticError, RusticResult},
+    repofile::{IndexFile, JournalFile, packfile::PackId},
+    repository::{Open, Repository},
+};
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Debug, Clone, Copy, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for the garbage collection of the index
+pub struct IndexGcOptions {
+    /// Only remove entries of packs which have been marked for deletion at least this long ago
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "DURATION", default_value = "0d")
+    )]
+    pub older_than: Span,
+}
+
+impl Default for IndexGcOptions {
+    fn default() -> Self {
+        Self {
+            older_than: Span::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+#[non_exhaustive]
+/// Statistics about the garbage collection of the index
+pub struct IndexGcStats {
+    /// Number of removed `packs_to_delete` entries whose packs no longer exist
+    pub stale_packs: u64,
+    /// Number of index files which have been rewritten without the stale entries
+    pub index_files_rewritten: u64,
+    /// Number of index files which have been removed as they only contained stale entries
+    pub index_files_removed: u64,
+    /// Number of deletion journals which have been removed as none of their packs is marked for deletion anymore
+    pub journals_removed: u64,
+}
+
+/// Remove `packs_to_delete` entries of packs which no longer exist from the index files.
+///
+/// Deletion journals which don't record any pack marked for deletion anymore are removed as well.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to work on
+/// * `opts` - The options to use
+/// * `dry_run` - Whether to actually modify the index or just compute what would be done
+///
+/// # Errors
+///
+/// * If the repository is in append-only mode.
+/// * If the packs could not be listed.
+/// * If the index files or deletion journals could not be read, written or removed.
+///
+/// # Returns
+///
+/// The [`IndexGcStats`] of the (would-be) changes
+pub(crate) fn gc_index<S: Open>(
+    repo: &Repository<S>,
+    opts: IndexGcOptions,
+    dry_run: bool,
+) -> RusticResult<IndexGcStats> {
+    if repo.config().append_only == Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::AppendOnly,
+            "Rewriting the index is not allowed in append-only repositories. Please disable append-only mode first, if you know what you are doing. Aborting.",
+        ));
+    }
+
+    let be = repo.dbe();
+    let limit = Zoned::now().saturating_sub(opts.older_than).timestamp();
+
+    let p = repo.progress_spinner("listing packs...");
+    let packs: BTreeSet<_> = be
+        .list(FileType::Pack)?
+        .into_iter()
+        .map(PackId::from)
+        .collect();
+    p.finish();
+
+    let mut stats = IndexGcStats::default();
+    let mut marked = BTreeSet::new();
+    let p = repo.progress_counter("reading index...");
+    for index in be.stream_all::<IndexFile>(&p)? {
+        let (index_id, mut index) = index?;
+        let count = index.packs_to_delete.len();
+        index
+            .packs_to_delete
+            .retain(|pack| packs.contains(&pack.id) || pack.time.is_some_and(|time| time > limit));
+        marked.extend(index.packs_to_delete.iter().map(|pack| pack.id));
+        let stale = count - index.packs_to_delete.len();
+        if stale == 0 {
+            continue;
+        }
+
+        debug!("index file {index_id}: {stale} stale packs to delete");
+        stats.stale_packs += stale as u64;
+        let keep_file = !index.packs.is_empty() || !index.packs_to_delete.is_empty();
+        if keep_file {
+            stats.index_files_rewritten += 1;
+        } else {
+            stats.index_files_removed += 1;
+        }
+
+        if !dry_run {
+            if keep_file {
+                _ = be.save_file(&index)?;
+            }
+            be.remove(FileType::Index, &index_id, true)?;
+        }
+    }
+    p.finish();
+
+    if be.supports_file_type(FileType::Journal) {
+        let p = repo.progress_counter("reading deletion journals...");
+        for journal in be.stream_all::<JournalFile>(&p)? {
+            let (journal_id, journal) = journal?;
+            if journal.packs.iter().any(|id| marked.contains(id)) {
+                continue;
+            }
+            debug!("deletion journal {journal_id} is completed");
+            stats.journals_removed += 1;
+            if !dry_run {
+                be.remove(FileType::Journal, &journal_id, false)?;
+            }
+        }
+        p.finish();
+    }
+
+    info!(
+        "{} {} stale packs to delete: {} index files rewritten, {} index files removed, {} deletion journals removed",
+        if dry_run { "would remove" } else { "removed" },
+        stats.stale_packs,
+        stats.index_files_rewritten,
+        stats.index_files_removed,
+        stats.journals_removed
+    );
+
+    Ok(stats)
+}
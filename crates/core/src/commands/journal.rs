@@ -0,0 +1,201 @@
+//! Audit and cancel pending deletions recorded in deletion journals
+use std::collections::{BTreeMap, BTreeSet};
+
+use jiff::Timestamp;
+use log::{debug, info, warn};
+
+use crate::{
+    backend::{
+        FileType, ReadBackend, WriteBackend,
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+    },
+    error::{ErrorKind, RusticError, RusticResult},
+    repofile::{IndexFile, JournalFile, JournalId, packfile::PackId},
+    repository::{Open, Repository},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// A pack which is marked for deletion
+pub struct PendingDeletion {
+    /// The id of the pack
+    pub id: PackId,
+    /// The size of the pack
+    pub size: u32,
+    /// The time the pack has been marked for deletion
+    pub time: Option<Timestamp>,
+    /// The latest deletion journal recording the mark, if any
+    pub journal: Option<JournalId>,
+}
+
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+/// The result of auditing the pending deletions of a repository
+pub struct DeletionAudit {
+    /// The packs which are marked for deletion
+    pub pending: Vec<PendingDeletion>,
+    /// The deletion journals of the repository
+    pub journals: BTreeMap<JournalId, JournalFile>,
+}
+
+impl DeletionAudit {
+    /// Returns the pending deletions which are not recorded in any deletion journal, e.g. as they have
+    /// been marked by another program
+    pub fn unjournaled(&self) -> impl Iterator<Item = &PendingDeletion> {
+        self.pending.iter().filter(|pd| pd.journal.is_none())
+    }
+
+    /// Returns the deletion journals which don't record any pending deletion anymore
+    #[must_use]
+    pub fn completed_journals(&self) -> Vec<JournalId> {
+        let active: BTreeSet<_> = self.pending.iter().filter_map(|pd| pd.journal).collect();
+        self.journals
+            .keys()
+            .filter(|id| !active.contains(id))
+            .copied()
+            .collect()
+    }
+}
+
+/// Audit the pending deletions of the repository.
+///
+/// This lists all packs which are marked for deletion in the index and matches them with the deletion
+/// journals written by `prune`.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to audit
+///
+/// # Errors
+///
+/// * If the index files or deletion journals could not be read.
+pub(crate) fn audit_deletions<S: Open>(repo: &Repository<S>) -> RusticResult<DeletionAudit> {
+    let be = repo.dbe();
+
+    let p = repo.progress_counter("reading index...");
+    let mut pending = BTreeMap::new();
+    for index in be.stream_all::<IndexFile>(&p)? {
+        for pack in index?.1.packs_to_delete {
+            _ = pending.insert(
+                pack.id,
+                PendingDeletion {
+                    id: pack.id,
+                    size: pack.pack_size(),
+                    time: pack.time,
+                    journal: None,
+                },
+            );
+        }
+    }
+    p.finish();
+
+    // backends which don't support deletion journals can't contain any
+    let journals: BTreeMap<_, _> = if be.supports_file_type(FileType::Journal) {
+        let p = repo.progress_counter("reading deletion journals...");
+        let journals = be
+            .stream_all::<JournalFile>(&p)?
+            .into_iter()
+            .collect::<RusticResult<_>>()?;
+        p.finish();
+        journals
+    } else {
+        BTreeMap::new()
+    };
+
+    // sort by time such that the latest journal recording a pack wins
+    let mut sorted: Vec<_> = journals.iter().collect();
+    sorted.sort_by_key(|(_, journal)| journal.time);
+    for (id, journal) in sorted {
+        for pack in &journal.packs {
+            if let Some(pd) = pending.get_mut(pack) {
+                pd.journal = Some(*id);
+            }
+        }
+    }
+
+    Ok(DeletionAudit {
+        pending: pending.into_values().collect(),
+        journals,
+    })
+}
+
+/// Cancel pending deletions by moving the given packs from the `packs_to_delete` back to the `packs`
+/// of the index.
+///
+/// This is only possible as long as the packs still exist, i.e. before a `prune` run has removed them
+/// after `keep_delete` has expired.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `ids` - The packs to un-mark
+///
+/// # Errors
+///
+/// * If the repository is in append-only mode.
+/// * If the packs could not be listed.
+/// * If the index files could not be read, written or removed.
+///
+/// # Returns
+///
+/// The packs which have been un-marked
+pub(crate) fn cancel_deletions<S: Open>(
+    repo: &Repository<S>,
+    ids: &[PackId],
+) -> RusticResult<Vec<PackId>> {
+    if repo.config().append_only == Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::AppendOnly,
+            "Rewriting the index is not allowed in append-only repositories. Please disable append-only mode first, if you know what you are doing. Aborting.",
+        ));
+    }
+
+    let be = repo.dbe();
+
+    let p = repo.progress_spinner("listing packs...");
+    let existing: BTreeSet<_> = be
+        .list(FileType::Pack)?
+        .into_iter()
+        .map(PackId::from)
+        .collect();
+    p.finish();
+
+    let mut to_cancel: BTreeSet<_> = ids.iter().copied().collect();
+    for id in to_cancel.difference(&existing) {
+        warn!("pack {id} no longer exists and cannot be recovered.");
+    }
+    to_cancel.retain(|id| existing.contains(id));
+
+    let now = Timestamp::now();
+    let mut cancelled = Vec::new();
+    let p = repo.progress_counter("reading index...");
+    for index in be.stream_all::<IndexFile>(&p)? {
+        let (index_id, mut index) = index?;
+        let (recover, keep): (Vec<_>, Vec<_>) = index
+            .packs_to_delete
+            .into_iter()
+            .partition(|pack| to_cancel.remove(&pack.id));
+        if recover.is_empty() {
+            continue;
+        }
+
+        debug!("index file {index_id}: recovering {} packs", recover.len());
+        index.packs_to_delete = keep;
+        for mut pack in recover {
+            cancelled.push(pack.id);
+            // the time of a pack in `packs` is its creation time; use the current time as for recovered packs in `prune`
+            pack.time = Some(now);
+            index.packs.push(pack);
+        }
+        _ = be.save_file(&index)?;
+        be.remove(FileType::Index, &index_id, true)?;
+    }
+    p.finish();
+
+    for id in &to_cancel {
+        warn!("pack {id} is not marked for deletion.");
+    }
+    info!("un-marked {} packs", cancelled.len());
+
+    Ok(cancelled)
+}
@@ -1,8 +1,14 @@
 //! `key` subcommand
 use derive_setters::Setters;
+use jiff::Zoned;
+use scrypt::Params;
+use serde_derive::Serialize;
 
 use crate::{
-    backend::{FileType, WriteBackend, decrypt::DecryptWriteBackend},
+    backend::{
+        FileType, ReadBackend, WriteBackend,
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+    },
     crypto::{aespoly1305::Key, hasher::hash},
     error::{ErrorKind, RusticError, RusticResult},
     repofile::{KeyFile, KeyId},
@@ -26,6 +32,46 @@ pub struct KeyOptions {
     /// Add 'created' date in public key information
     #[cfg_attr(feature = "clap", clap(long))]
     pub with_created: bool,
+
+    /// Cost parameter of the key derivation (scrypt) as log2(N); increases memory usage and run time [default: 17]
+    #[cfg_attr(feature = "clap", clap(long, value_name = "LOG_N"))]
+    pub kdf_log_n: Option<u8>,
+
+    /// Block size parameter r of the key derivation (scrypt); increases memory usage [default: 8]
+    #[cfg_attr(feature = "clap", clap(long, value_name = "R"))]
+    pub kdf_r: Option<u32>,
+
+    /// Parallelization parameter p of the key derivation (scrypt); increases run time [default: 1]
+    #[cfg_attr(feature = "clap", clap(long, value_name = "P"))]
+    pub kdf_p: Option<u32>,
+}
+
+impl KeyOptions {
+    /// Get the parameters of the key derivation function
+    ///
+    /// Parameters which are not set use the recommended defaults.
+    ///
+    /// # Errors
+    ///
+    /// * If the parameters are invalid.
+    pub(crate) fn kdf_params(&self) -> RusticResult<Params> {
+        let default = Params::RECOMMENDED;
+        let (log_n, r, p) = (
+            self.kdf_log_n.unwrap_or(default.log_n()),
+            self.kdf_r.unwrap_or(default.r()),
+            self.kdf_p.unwrap_or(default.p()),
+        );
+        Params::new(log_n, r, p).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InvalidInput,
+                "Invalid key derivation parameters: log_n = `{log_n}`, r = `{r}`, p = `{p}`.",
+                err,
+            )
+            .attach_context("log_n", log_n.to_string())
+            .attach_context("r", r.to_string())
+            .attach_context("p", p.to_string())
+        })
+    }
 }
 
 /// Add the current key to the repository.
@@ -106,7 +152,14 @@ pub(crate) fn add_key_to_repo<S>(
     key: Key,
 ) -> RusticResult<KeyId> {
     let ko = opts.clone();
-    let keyfile = KeyFile::generate(key, &pass, ko.hostname, ko.username, ko.with_created)?;
+    let keyfile = KeyFile::generate_with_params(
+        key,
+        &pass,
+        ko.hostname,
+        ko.username,
+        ko.with_created,
+        opts.kdf_params()?,
+    )?;
 
     let data = serde_json::to_vec(&keyfile).map_err(|err| {
         RusticError::with_source(
@@ -123,3 +176,178 @@ pub(crate) fn add_key_to_repo<S>(
 
     Ok(id)
 }
+
+/// Information about a key of the repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct KeyInfo {
+    /// The id of the key
+    pub id: KeyId,
+    /// Hostname where the key was created
+    pub hostname: Option<String>,
+    /// User which created the key
+    pub username: Option<String>,
+    /// Creation time of the key
+    pub created: Option<Zoned>,
+    /// Whether this key was used to open the repository
+    pub current: bool,
+}
+
+/// List all keys of the repository.
+///
+/// # Type Parameters
+///
+/// * `S` - The state the repository is in
+///
+/// # Arguments
+///
+/// * `repo` - The repository to list the keys of
+///
+/// # Errors
+///
+/// * If the key files could not be read.
+///
+/// # Returns
+///
+/// The information about the keys, sorted by creation time.
+pub(crate) fn list_keys<S: Open>(repo: &Repository<S>) -> RusticResult<Vec<KeyInfo>> {
+    let current = *repo.key_id();
+    let mut keys = repo
+        .dbe()
+        .stream_all::<KeyFile>(&repo.progress_hidden())?
+        .into_iter()
+        .map(|item| {
+            let (id, keyfile) = item?;
+            Ok(KeyInfo {
+                id,
+                hostname: keyfile.hostname,
+                username: keyfile.username,
+                created: keyfile.created,
+                current: current == Some(id),
+            })
+        })
+        .collect::<RusticResult<Vec<_>>>()?;
+    keys.sort_unstable_by(|k1, k2| k1.created.cmp(&k2.created).then(k1.id.cmp(&k2.id)));
+    Ok(keys)
+}
+
+/// Remove a key from the repository.
+///
+/// # Type Parameters
+///
+/// * `S` - The state the repository is in
+///
+/// # Arguments
+///
+/// * `repo` - The repository to remove the key from
+/// * `id` - The id of the key to remove
+///
+/// # Errors
+///
+/// * If the key is the currently used key.
+/// * If the key does not exist.
+/// * If the key is the last key of the repository.
+/// * If the key could not be removed.
+pub(crate) fn remove_key<S: Open>(repo: &Repository<S>, id: &KeyId) -> RusticResult<()> {
+    if repo.key_id().as_ref() == Some(id) {
+        return Err(RusticError::new(
+            ErrorKind::Repository,
+            "Cannot remove the currently used key",
+        ));
+    }
+
+    let keys = repo.dbe().list(FileType::Key)?;
+    if !keys.contains(id) {
+        return Err(
+            RusticError::new(ErrorKind::Repository, "Key `{id}` does not exist.")
+                .attach_context("id", id.to_string()),
+        );
+    }
+    if keys.len() <= 1 {
+        return Err(RusticError::new(
+            ErrorKind::Repository,
+            "Cannot remove the last key `{id}`; the repository could not be opened anymore.",
+        )
+        .attach_context("id", id.to_string()));
+    }
+
+    repo.dbe().remove(FileType::Key, id, false)
+}
+
+/// Change the password of the currently used key.
+///
+/// A new key protected by the given password is added and the currently used key is removed.
+///
+/// # Type Parameters
+///
+/// * `S` - The state the repository is in
+///
+/// # Arguments
+///
+/// * `repo` - The repository to change the password of
+/// * `pass` - The new password
+/// * `opts` - The key options to use for the new key
+///
+/// # Errors
+///
+/// * If the new key could not be saved.
+/// * If the old key could not be removed.
+///
+/// # Returns
+///
+/// The id of the new key.
+pub(crate) fn change_password<S: Open>(
+    repo: &mut Repository<S>,
+    pass: &str,
+    opts: &KeyOptions,
+) -> RusticResult<KeyId> {
+    let old_id = *repo.key_id();
+    let id = add_current_key_to_repo(repo, opts, pass)?;
+    repo.set_key_id(Some(id));
+
+    if let Some(old_id) = old_id {
+        repo.dbe().remove(FileType::Key, &old_id, false)?;
+    }
+    Ok(id)
+}
+
+/// Re-create the currently used key using new parameters for the key derivation function.
+///
+/// The password is kept; a new key file is written and the currently used key file is removed.
+///
+/// # Type Parameters
+///
+/// * `S` - The state the repository is in
+///
+/// # Arguments
+///
+/// * `repo` - The repository to upgrade the key of
+/// * `pass` - The password of the currently used key
+/// * `opts` - The key options to use for the new key, including the parameters of the key derivation function
+///
+/// # Errors
+///
+/// * If the repository was not opened using a key file.
+/// * If the password doesn't match the currently used key.
+/// * If the key derivation parameters are invalid.
+/// * If the new key could not be saved or the old key could not be removed.
+///
+/// # Returns
+///
+/// The id of the new key.
+pub(crate) fn rekey_kdf<S: Open>(
+    repo: &mut Repository<S>,
+    pass: &str,
+    opts: &KeyOptions,
+) -> RusticResult<KeyId> {
+    let Some(id) = *repo.key_id() else {
+        return Err(RusticError::new(
+            ErrorKind::Key,
+            "The repository was not opened using a key file, so there is no key to upgrade.",
+        ));
+    };
+    // check the password before replacing the key
+    let keyfile: KeyFile = repo.dbe().get_file(&id)?;
+    _ = keyfile.key_from_password(&pass)?;
+    change_password(repo, pass, opts)
+}
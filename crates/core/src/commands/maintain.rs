@@ -0,0 +1,141 @@
+//! Run the regular repository maintenance in a single call
+use derive_setters::Setters;
+use log::{info, warn};
+
+use crate::{
+    backend::{FileType, ReadBackend},
+    commands::{
+        check::{CheckOptions, CheckResults},
+        forget::KeepOptions,
+        prune::{PruneOptions, PruneStats},
+    },
+    error::RusticResult,
+    repofile::snapshotfile::{SnapshotId, grouping::SnapshotGroupCriterion},
+    repository::{Open, Repository},
+};
+
+#[derive(Debug, Clone, Default, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for [`Repository::maintain`]
+///
+/// Each step is only run if its options are given.
+pub struct MaintenanceOptions {
+    /// Forget snapshots according to this retention policy
+    pub forget: Option<KeepOptions>,
+
+    /// The criterion to group the snapshots by when forgetting snapshots
+    pub group_by: SnapshotGroupCriterion,
+
+    /// Prune the repository using these options
+    pub prune: Option<PruneOptions>,
+
+    /// Check the repository after pruning using these options
+    pub check: Option<CheckOptions>,
+
+    /// Remove files from the local cache which are no longer present in the repository
+    pub cleanup_cache: bool,
+}
+
+#[derive(Debug, Default)]
+#[non_exhaustive]
+/// The combined result of [`Repository::maintain`]
+pub struct MaintenanceReport {
+    /// The snapshots which have been forgotten
+    pub forgotten: Vec<SnapshotId>,
+    /// The statistics of the prune run, if pruning was requested
+    pub prune: Option<PruneStats>,
+    /// The check results, if checking was requested
+    pub check: Option<CheckResults>,
+    /// The number of files removed from the local cache
+    pub cache_files_removed: usize,
+}
+
+/// Run the requested maintenance steps on the repository.
+///
+/// The steps are run in the order forget, prune, cache cleanup and check, such that the
+/// prune run removes the data of the forgotten snapshots and the check verifies the pruned
+/// repository. Findings of the check are returned in the report and don't abort the maintenance.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `opts` - The maintenance options
+///
+/// # Errors
+///
+/// * If the repository is in append-only mode and snapshots should be forgotten or the repository
+///   should be pruned.
+/// * If one of the steps fails; the following steps are not run.
+pub(crate) fn maintain<S: Open>(
+    repo: &Repository<S>,
+    opts: &MaintenanceOptions,
+) -> RusticResult<MaintenanceReport> {
+    let mut report = MaintenanceReport::default();
+
+    if let Some(keep) = &opts.forget {
+        report.forgotten = repo
+            .get_forget_snapshots(keep, opts.group_by, |_| true)?
+            .into_forget_ids();
+        info!("forgetting {} snapshots", report.forgotten.len());
+        if !report.forgotten.is_empty() {
+            repo.delete_snapshots(&report.forgotten)?;
+        }
+    }
+
+    if let Some(prune_opts) = &opts.prune {
+        let plan = repo.prune_plan(prune_opts)?;
+        let stats = plan.stats.clone();
+        repo.prune(prune_opts, plan)?;
+        report.prune = Some(stats);
+    }
+
+    if opts.cleanup_cache {
+        report.cache_files_removed = cleanup_cache(repo)?;
+        info!(
+            "removed {} files from the cache",
+            report.cache_files_removed
+        );
+    }
+
+    if let Some(check_opts) = opts.check {
+        let results = repo.check(check_opts)?;
+        if let Err(err) = results.is_ok() {
+            warn!("maintenance check: {}", err.display_log());
+        }
+        report.check = Some(results);
+    }
+
+    Ok(report)
+}
+
+/// Remove all files from the cache which are no longer present in the repository
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+///
+/// # Errors
+///
+/// * If the files could not be listed.
+///
+/// # Returns
+///
+/// The number of removed cache files; if the repository uses no cache, nothing is removed.
+fn cleanup_cache<S: Open>(repo: &Repository<S>) -> RusticResult<usize> {
+    let Some(cache) = repo.cache() else {
+        return Ok(0);
+    };
+
+    let p = repo.progress_spinner("cleaning up cache...");
+    let mut removed = 0;
+    for tpe in [FileType::Snapshot, FileType::Index, FileType::Pack] {
+        let before = cache.list_with_size(tpe)?.len();
+        let list = repo.be.list_with_size(tpe)?;
+        cache.remove_not_in_list(tpe, &list)?;
+        removed += before.saturating_sub(cache.list_with_size(tpe)?.len());
+    }
+    p.finish();
+
+    Ok(removed)
+}
@@ -3,9 +3,13 @@
 use std::cmp::Ordering;
 
 use jiff::Zoned;
+use serde_derive::{Deserialize, Serialize};
 
 use crate::{
-    backend::{decrypt::DecryptWriteBackend, node::Node},
+    backend::{
+        decrypt::DecryptWriteBackend,
+        node::{Node, last_modified_node},
+    },
     blob::{
         BlobId, BlobType,
         packer::{PackSizer, Packer},
@@ -17,6 +21,67 @@ use crate::{
     repository::{IndexedTree, Repository},
 };
 
+/// How to resolve merge conflicts, i.e. identical paths with differing contents
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum MergeConflictPolicy {
+    /// Use the node with the latest modification time
+    #[default]
+    LatestMtime,
+    /// Use the node of the first given snapshot containing the path
+    FirstSnapshot,
+    /// Use the node of the last given snapshot containing the path
+    LastSnapshot,
+}
+
+/// Merges the snapshots with the given ids into a new snapshot.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to merge into
+/// * `ids` - The ids of the snapshots to merge, in the order used by the conflict policy
+/// * `policy` - How to resolve merge conflicts
+/// * `snap` - The snapshot to merge into
+///
+/// # Errors
+///
+/// * If no snapshot ids are given.
+/// * If the snapshots could not be read or the merged snapshot could not be saved.
+///
+/// # Returns
+///
+/// The merged snapshot
+pub(crate) fn merge_snapshots_from_ids<S: IndexedTree, T: AsRef<str>>(
+    repo: &Repository<S>,
+    ids: &[T],
+    policy: MergeConflictPolicy,
+    snap: SnapshotFile,
+) -> RusticResult<SnapshotFile> {
+    if ids.is_empty() {
+        return Err(RusticError::new(
+            ErrorKind::InvalidInput,
+            "No snapshots given to merge.",
+        ));
+    }
+    let mut snapshots = repo.get_snapshots(ids)?;
+
+    // Nodes with identical names are compared in snapshot order and the last maximal node is chosen.
+    match policy {
+        MergeConflictPolicy::LatestMtime => {
+            merge_snapshots(repo, &snapshots, &last_modified_node, snap)
+        }
+        MergeConflictPolicy::FirstSnapshot => {
+            snapshots.reverse();
+            merge_snapshots(repo, &snapshots, &|_, _| Ordering::Equal, snap)
+        }
+        MergeConflictPolicy::LastSnapshot => {
+            merge_snapshots(repo, &snapshots, &|_, _| Ordering::Equal, snap)
+        }
+    }
+}
+
 /// Merges the given snapshots into a new snapshot.
 ///
 /// # Arguments
@@ -0,0 +1,251 @@
+//! `migrate` subcommand: upgrade a repository to a newer repository version
+use std::time::Duration;
+
+use bytesize::ByteSize;
+use derive_setters::Setters;
+use log::info;
+
+use crate::{
+    backend::decrypt::DecryptReadBackend,
+    commands::{
+        config::{ConfigOptions, apply_config},
+        prune::{PruneOptions, PruneStats},
+    },
+    error::RusticResult,
+    repofile::{IndexFile, PackId},
+    repository::{Open, Repository},
+    util::LimitOption,
+};
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Debug, Clone, Copy, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for the `migrate` command
+pub struct MigrateOptions {
+    /// The repository version to migrate to
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "VERSION", default_value = "2")
+    )]
+    pub to_version: u32,
+
+    /// Define maximum data to repack within one run in % of reposize or as size (e.g. '5b', '2 kB', '3M', '4TiB') or 'unlimited'
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "LIMIT", default_value = "unlimited")
+    )]
+    pub max_repack: LimitOption,
+
+    /// Delete repacked packs immediately instead of marking them.
+    ///
+    /// # Warning
+    ///
+    /// * Only use if you are sure the repository is not accessed by parallel processes!
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub instant_delete: bool,
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        Self {
+            to_version: 2,
+            max_repack: LimitOption::Unlimited,
+            instant_delete: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+/// The work needed to migrate a repository to a repository version
+pub struct MigrationEstimate {
+    /// The current repository version
+    pub from_version: u32,
+    /// The repository version to migrate to
+    pub to_version: u32,
+    /// Number of packs which need to be repacked
+    pub packs: u64,
+    /// Total size of the packs which need to be repacked
+    pub size: u64,
+    /// Total size of all packs in the repository
+    pub total_size: u64,
+}
+
+impl MigrationEstimate {
+    /// Returns whether the migration is finished
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.from_version >= self.to_version && self.packs == 0
+    }
+
+    /// Estimate the time needed to repack the remaining packs.
+    ///
+    /// # Arguments
+    ///
+    /// * `throughput` - The expected number of bytes which can be repacked per second
+    ///
+    /// # Returns
+    ///
+    /// The estimated duration or `None` if the throughput is zero.
+    #[must_use]
+    pub fn estimated_duration(&self, throughput: ByteSize) -> Option<Duration> {
+        self.size
+            .checked_div(throughput.as_u64())
+            .map(Duration::from_secs)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+/// The result of [`Repository::migrate`]
+pub struct MigrationReport {
+    /// The work which was needed before this run
+    pub before: MigrationEstimate,
+    /// Whether the repository version has been changed in this run
+    pub version_changed: bool,
+    /// The statistics of the prune run used to repack the packs, if packs needed to be repacked
+    pub prune: Option<PruneStats>,
+    /// The work which is still needed after this run; run the migration again to continue
+    pub remaining: MigrationEstimate,
+}
+
+/// Estimate the work needed to migrate the repository to the given version.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to migrate
+/// * `to_version` - The repository version to migrate to
+///
+/// # Errors
+///
+/// * If the version is not supported.
+/// * If the version is lower than the current version.
+/// * If the index could not be read.
+pub(crate) fn migration_estimate<S: Open>(
+    repo: &Repository<S>,
+    to_version: u32,
+) -> RusticResult<MigrationEstimate> {
+    Ok(packs_to_migrate(repo, to_version)?.0)
+}
+
+/// Estimate the work needed to migrate the repository and get the packs which need to be repacked.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to migrate
+/// * `to_version` - The repository version to migrate to
+///
+/// # Errors
+///
+/// * If the version is not supported.
+/// * If the version is lower than the current version.
+/// * If the index could not be read.
+fn packs_to_migrate<S: Open>(
+    repo: &Repository<S>,
+    to_version: u32,
+) -> RusticResult<(MigrationEstimate, Vec<PackId>)> {
+    // validate the version
+    ConfigOptions::default()
+        .set_version(to_version)
+        .apply(&mut repo.config().clone())?;
+
+    let mut estimate = MigrationEstimate {
+        from_version: repo.config().version,
+        to_version,
+        ..Default::default()
+    };
+    let mut packs = Vec::new();
+
+    let p = repo.progress_counter("reading index...");
+    for index in repo.dbe().stream_all::<IndexFile>(&p)? {
+        for pack in index?.1.packs {
+            let size = u64::from(pack.pack_size());
+            estimate.total_size += size;
+            // version 2 adds compression, so all packs containing uncompressed blobs need to be repacked
+            let uncompressed = pack
+                .blobs
+                .iter()
+                .any(|blob| blob.location.uncompressed_length.is_none());
+            if to_version >= 2 && uncompressed {
+                estimate.packs += 1;
+                estimate.size += size;
+                packs.push(pack.id);
+            }
+        }
+    }
+    p.finish();
+
+    Ok((estimate, packs))
+}
+
+/// Migrate the repository to a newer repository version.
+///
+/// This sets the new version in the repository config and repacks the packs using the prune machinery,
+/// e.g. to compress the data when migrating from version 1 to version 2. The amount of data repacked
+/// within one run can be limited; running the migration again continues where the last run stopped.
+///
+/// Only the packs which need to be repacked are touched: other packs are kept even if they contain
+/// unused data. Uncompressed packs which are completely unused cannot be repacked and are marked for
+/// deletion instead.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to migrate
+/// * `opts` - The migration options
+///
+/// # Errors
+///
+/// * If the version is not supported.
+/// * If the version is lower than the current version.
+/// * If the repository is in append-only mode.
+/// * If the config could not be saved or the repository could not be pruned.
+pub(crate) fn migrate<S: Open>(
+    repo: &mut Repository<S>,
+    opts: &MigrateOptions,
+) -> RusticResult<MigrationReport> {
+    let (before, packs) = packs_to_migrate(repo, opts.to_version)?;
+    info!(
+        "migrating from version {} to {}: {} packs ({}) of {} need to be repacked",
+        before.from_version,
+        before.to_version,
+        before.packs,
+        ByteSize(before.size).display().iec(),
+        ByteSize(before.total_size).display().iec(),
+    );
+
+    let version_changed = before.from_version < before.to_version
+        && apply_config(repo, &ConfigOptions::default().set_version(opts.to_version))?;
+
+    let prune = if before.packs > 0 {
+        // restrict prune to repacking the uncompressed packs, other unused data is kept
+        let prune_opts = PruneOptions::default()
+            .repack_uncompressed(true)
+            .only_packs(packs)
+            .max_unused(LimitOption::Unlimited)
+            .max_repack(opts.max_repack)
+            .instant_delete(opts.instant_delete);
+        let plan = repo.prune_plan(&prune_opts)?;
+        let stats = plan.stats.clone();
+        repo.prune(&prune_opts, plan)?;
+        Some(stats)
+    } else {
+        None
+    };
+
+    let remaining = migration_estimate(repo, opts.to_version)?;
+    if !remaining.is_done() {
+        info!(
+            "{} packs ({}) still need to be repacked, please run the migration again",
+            remaining.packs,
+            ByteSize(remaining.size).display().iec(),
+        );
+    }
+
+    Ok(MigrationReport {
+        before,
+        version_changed,
+        prune,
+        remaining,
+    })
+}
@@ -0,0 +1,217 @@
+//! Prewarm the cache for upcoming operations
+use std::collections::BTreeSet;
+
+use log::{debug, info, warn};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    FileType, Id, Progress, ReadBackend, Repository, RusticResult, WriteBackend,
+    backend::{cache::Cache, decrypt::DecryptReadBackend},
+    blob::tree::{Tree, TreeId},
+    commands::repair::hotcold::get_tree_packs,
+    index::{GlobalIndex, ReadGlobalIndex},
+    repofile::{SnapshotFile, SnapshotId},
+    repository::Open,
+};
+
+/// The files an upcoming operation will need.
+///
+/// Each hint includes the files of the previous ones, i.e. the index files are always prewarmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PrewarmHint {
+    /// Only the index files, e.g. for `backup`
+    Index,
+    /// The index and snapshot files, e.g. for `snapshots` or `forget`
+    Snapshots,
+    /// The index and snapshot files and the packs containing the trees of the given snapshots, e.g. for
+    /// `restore`, `ls` or `diff`
+    SnapshotTrees(Vec<SnapshotId>),
+    /// The index and snapshot files and all tree packs, e.g. for `check` or `prune`
+    AllTrees,
+}
+
+/// Statistics about prewarming the cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PrewarmStats {
+    /// Number of needed files which were already cached
+    pub cached: u64,
+    /// Number of files downloaded into the cache
+    pub downloaded: u64,
+    /// Total size of the downloaded files
+    pub downloaded_bytes: u64,
+}
+
+/// Downloads files into the cache
+struct Prewarmer<'a> {
+    /// The backend to read from
+    be: &'a dyn WriteBackend,
+    /// The cache to fill
+    cache: &'a Cache,
+    /// The statistics
+    stats: PrewarmStats,
+}
+
+impl Prewarmer<'_> {
+    /// Download the given files into the cache, if they are not already cached.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the files
+    /// * `ids` - The files to download
+    /// * `p` - The progress bar
+    ///
+    /// # Errors
+    ///
+    /// * If a file could not be read or saved to the cache.
+    fn fetch(&mut self, tpe: FileType, ids: Vec<Id>, p: &Progress) -> RusticResult<()> {
+        let (be, cache) = (self.be, self.cache);
+        let (cached, missing): (Vec<_>, Vec<_>) =
+            ids.into_iter().partition(|id| cache.path(tpe, id).exists());
+        self.stats.cached += cached.len() as u64;
+        p.inc(cached.len() as u64);
+        debug!("downloading {} {tpe:?} files into the cache", missing.len());
+
+        let sizes = missing
+            .into_par_iter()
+            .map(|id| {
+                let data = be.read_full(tpe, &id)?;
+                // index and snapshot files are saved to the cache by the cached backend when reading them
+                if tpe == FileType::Pack {
+                    cache.write_bytes(tpe, &id, &data)?;
+                }
+                p.inc(1);
+                Ok(data.len() as u64)
+            })
+            .collect::<RusticResult<Vec<_>>>()?;
+        self.stats.downloaded += sizes.len() as u64;
+        self.stats.downloaded_bytes += sizes.iter().sum::<u64>();
+        Ok(())
+    }
+
+    /// Download all files of the given type into the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to use
+    /// * `tpe` - The type of the files
+    ///
+    /// # Errors
+    ///
+    /// * If the files could not be listed, read or saved to the cache.
+    fn fetch_all<S>(&mut self, repo: &Repository<S>, tpe: FileType) -> RusticResult<()> {
+        let p = repo.progress_counter(&format!("prewarming {tpe:?} files..."));
+        let ids = self.be.list(tpe)?;
+        p.set_length(ids.len() as u64);
+        self.fetch(tpe, ids, &p)?;
+        p.finish();
+        Ok(())
+    }
+
+    /// Download the packs containing the trees of the given snapshots into the cache.
+    ///
+    /// The trees are traversed level by level; the packs of each level are downloaded in parallel
+    /// before the trees are read from the cache to find the next level.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to use
+    /// * `ids` - The snapshots to use
+    ///
+    /// # Errors
+    ///
+    /// * If the index, the snapshots or the trees could not be read.
+    /// * If the packs could not be read or saved to the cache.
+    fn fetch_snapshot_trees<S: Open>(
+        &mut self,
+        repo: &Repository<S>,
+        ids: &[SnapshotId],
+    ) -> RusticResult<()> {
+        let be = repo.dbe();
+        let index = GlobalIndex::only_full_trees(be, &repo.progress_counter("reading index..."))?;
+
+        let mut level = ids
+            .iter()
+            .map(|id| Ok(be.get_file::<SnapshotFile>(id)?.tree))
+            .collect::<RusticResult<Vec<TreeId>>>()?;
+        let mut visited = BTreeSet::new();
+        let mut fetched = BTreeSet::new();
+
+        let p = repo.progress_counter("prewarming tree packs...");
+        while !level.is_empty() {
+            level.retain(|id| visited.insert(*id));
+            let packs: Vec<_> = level
+                .iter()
+                .filter_map(|id| index.get_tree(id))
+                .map(|entry| entry.pack)
+                .filter(|pack| fetched.insert(*pack))
+                .map(|pack| *pack)
+                .collect();
+            p.set_length(fetched.len() as u64);
+            self.fetch(FileType::Pack, packs, &p)?;
+
+            level = level
+                .into_par_iter()
+                .map(|id| Tree::from_backend(be, &index, id))
+                .collect::<RusticResult<Vec<_>>>()?
+                .into_iter()
+                .flat_map(|tree| tree.nodes.into_iter().filter_map(|node| node.subtree))
+                .collect();
+        }
+        p.finish();
+        Ok(())
+    }
+}
+
+/// Download the files needed by an upcoming operation into the cache.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `hint` - The files the operation will need
+///
+/// # Errors
+///
+/// * If the files could not be listed, read or saved to the cache.
+///
+/// # Returns
+///
+/// The [`PrewarmStats`]; if the repository uses no cache, nothing is downloaded.
+pub(crate) fn prewarm_cache<S: Open>(
+    repo: &Repository<S>,
+    hint: &PrewarmHint,
+) -> RusticResult<PrewarmStats> {
+    let Some(cache) = repo.cache() else {
+        warn!("the repository uses no cache, nothing to prewarm.");
+        return Ok(PrewarmStats::default());
+    };
+    let mut prewarmer = Prewarmer {
+        be: repo.be.as_ref(),
+        cache,
+        stats: PrewarmStats::default(),
+    };
+
+    prewarmer.fetch_all(repo, FileType::Index)?;
+    if *hint != PrewarmHint::Index {
+        prewarmer.fetch_all(repo, FileType::Snapshot)?;
+    }
+    match hint {
+        PrewarmHint::Index | PrewarmHint::Snapshots => {}
+        PrewarmHint::SnapshotTrees(ids) => prewarmer.fetch_snapshot_trees(repo, ids)?,
+        PrewarmHint::AllTrees => {
+            let packs: Vec<_> = get_tree_packs(repo)?.into_iter().map(|id| *id).collect();
+            let p = repo.progress_counter("prewarming tree packs...");
+            p.set_length(packs.len() as u64);
+            prewarmer.fetch(FileType::Pack, packs, &p)?;
+            p.finish();
+        }
+    }
+
+    let stats = prewarmer.stats;
+    info!(
+        "prewarmed cache: {} files already cached, {} files ({} bytes) downloaded",
+        stats.cached, stats.downloaded, stats.downloaded_bytes
+    );
+    Ok(stats)
+}
@@ -5,22 +5,20 @@
 use std::{
     cmp::Ordering,
     collections::{BTreeMap, BTreeSet},
-    str::FromStr,
 };
 
-use bytesize::ByteSize;
 use derive_more::Add;
 use derive_setters::Setters;
 use enumset::{EnumSet, EnumSetType};
 use itertools::Itertools;
 use jiff::{Span, Timestamp, Zoned};
-use log::{info, warn};
+use log::{debug, info, warn};
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     backend::{
-        FileType, ReadBackend,
+        FileType, ReadBackend, WriteBackend,
         decrypt::{DecryptReadBackend, DecryptWriteBackend},
         node::NodeType,
     },
@@ -29,18 +27,21 @@ use crate::{
         packer::{BlobCopier, CopyPackBlobs, PackSizer},
         tree::TreeStreamerOnce,
     },
+    commands::rekey::check_no_rekey,
     error::{ErrorKind, RusticError, RusticResult},
     index::{
         GlobalIndex, ReadGlobalIndex, ReadIndex,
         binarysorted::{IndexCollector, IndexType},
         indexer::Indexer,
+        sequence::IndexSequence,
     },
     progress::ProgressBars,
     repofile::{
-        HeaderEntry, IndexBlob, IndexFile, IndexPack, SnapshotFile, SnapshotId, indexfile::IndexId,
-        packfile::PackId,
+        HeaderEntry, IndexBlob, IndexFile, IndexPack, JournalFile, SnapshotFile, SnapshotId,
+        indexfile::IndexId, packfile::PackId,
     },
     repository::{Open, Repository},
+    util::LimitOption,
 };
 
 pub(super) mod constants {
@@ -50,7 +51,8 @@ pub(super) mod constants {
 
 #[allow(clippy::struct_excessive_bools)]
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
-#[derive(Debug, Clone, Setters)]
+#[derive(Debug, Clone, Setters, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
 #[setters(into)]
 #[non_exhaustive]
 /// Options for the `prune` command
@@ -122,6 +124,11 @@ pub struct PruneOptions {
     #[cfg_attr(feature = "clap", clap(long))]
     pub no_resize: bool,
 
+    /// Write a sequence number into the new index files and abort before removing the old index files
+    /// if the index has been rewritten by another process in the meantime (e.g. by a parallel prune)
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub index_sequence: bool,
+
     #[cfg_attr(feature = "clap", clap(skip))]
     /// Ignore these snapshots when looking for data-still-in-use.
     ///
@@ -131,6 +138,10 @@ pub struct PruneOptions {
     /// * If you specify snapshots which are not deleted, running the resulting `PrunePlan`
     ///   will remove data which is used within those snapshots!
     pub ignore_snaps: Vec<SnapshotId>,
+
+    /// Only repack or delete the given packs (can be specified multiple times). All other packs are kept.
+    #[cfg_attr(feature = "clap", clap(long = "only-pack", value_name = "ID"))]
+    pub only_packs: Vec<PackId>,
 }
 
 impl Default for PruneOptions {
@@ -147,7 +158,9 @@ impl Default for PruneOptions {
             repack_all: false,
             repack_cacheable_only: None,
             no_resize: false,
+            index_sequence: false,
             ignore_snaps: Vec::new(),
+            only_packs: Vec::new(),
         }
     }
 }
@@ -180,51 +193,6 @@ impl PruneOptions {
     }
 }
 
-/// Enum to specify a size limit
-#[derive(Clone, Copy, Debug)]
-#[non_exhaustive]
-pub enum LimitOption {
-    /// Size in bytes
-    Size(ByteSize),
-    /// Size in percentage of repository size
-    Percentage(u64),
-    /// No limit
-    Unlimited,
-}
-
-impl FromStr for LimitOption {
-    type Err = Box<RusticError>;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s.chars().last().unwrap_or('0') {
-            '%' => Self::Percentage({
-                let mut copy = s.to_string();
-                _ = copy.pop();
-                copy.parse().map_err(|err| {
-                    RusticError::with_source(
-                        ErrorKind::InvalidInput,
-                        "Failed to parse percentage limit `{limit}`",
-                        err,
-                    )
-                    .attach_context("limit", s)
-                })?
-            }),
-            'd' if s == "unlimited" => Self::Unlimited,
-            _ => {
-                let byte_size = ByteSize::from_str(s).map_err(|err| {
-                    RusticError::with_source(
-                        ErrorKind::InvalidInput,
-                        "Failed to parse size limit `{limit}`",
-                        err,
-                    )
-                    .attach_context("limit", s)
-                })?;
-
-                Self::Size(byte_size)
-            }
-        })
-    }
-}
-
 #[derive(EnumSetType, Debug, PartialOrd, Ord, Serialize, Deserialize)]
 #[enumset(serialize_repr = "list")]
 pub enum PackStatus {
@@ -236,6 +204,7 @@ pub enum PackStatus {
     HasUnusedBlobs,
     HasUsedBlobs,
     Marked,
+    NotSelected,
 }
 
 #[derive(Debug, Clone, Copy, Serialize)]
@@ -254,7 +223,7 @@ pub struct DebugStatsKey {
     pub status: EnumSet<PackStatus>,
 }
 
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct DebugStats(pub BTreeMap<DebugStatsKey, DebugDetailedStats>);
 
 impl DebugStats {
@@ -347,7 +316,7 @@ impl SizeStats {
 }
 
 /// Statistics about a [`PrunePlan`]
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct PruneStats {
     /// Statistics about pack count
     pub packs_to_delete: DeleteStats,
@@ -367,6 +336,8 @@ pub struct PruneStats {
     pub index_files: u64,
     /// Number of index files which will be rebuilt during the prune
     pub index_files_rebuild: u64,
+    /// Number of packs which cannot be removed yet as they are locked by the backend
+    pub packs_locked: u64,
     /// Detailed debug statistics
     pub debug: DebugStats,
 }
@@ -389,6 +360,117 @@ impl PruneStats {
     }
 }
 
+/// How much of a pack is used, see [`PackLayoutReport`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct PackFill {
+    /// The id of the pack
+    pub id: PackId,
+    /// The type of the blobs in the pack, i.e. the type of the first blob
+    pub blob_type: BlobType,
+    /// The size of the pack
+    pub size: u32,
+    /// The number of used blobs in the pack
+    pub used_blobs: u16,
+    /// The number of unused blobs in the pack
+    pub unused_blobs: u16,
+    /// The size of the used blobs in the pack
+    pub used_size: u32,
+    /// The size of the unused blobs in the pack
+    pub unused_size: u32,
+    /// Whether the pack contains blobs of different blob types
+    pub mixed_blob_types: bool,
+}
+
+impl PackFill {
+    /// Create a `PackFill` from a `PrunePack` and its `PackInfo`
+    ///
+    /// # Arguments
+    ///
+    /// * `pack` - The pack
+    /// * `pi` - The information about used and unused blobs of the pack
+    fn new(pack: &PrunePack, pi: &PackInfo) -> Self {
+        Self {
+            id: pack.id,
+            blob_type: pi.blob_type,
+            size: pack.size,
+            used_blobs: pi.used_blobs,
+            unused_blobs: pi.unused_blobs,
+            used_size: pi.used_size,
+            unused_size: pi.unused_size,
+            mixed_blob_types: pack.blobs.iter().any(|blob| blob.tpe != pack.blob_type),
+        }
+    }
+
+    /// Returns the ratio of the used size to the size of all blobs in the pack
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn fill_ratio(&self) -> f64 {
+        let total = u64::from(self.used_size) + u64::from(self.unused_size);
+        if total == 0 {
+            0.0
+        } else {
+            f64::from(self.used_size) / total as f64
+        }
+    }
+
+    /// Returns whether the pack contains both used and unused blobs, i.e. it can only be cleaned up by repacking
+    #[must_use]
+    pub const fn is_partly_used(&self) -> bool {
+        self.used_blobs > 0 && self.unused_blobs > 0
+    }
+}
+
+/// Report about how well the packs of the repository are used, see [`PrunePlan::pack_layout`]
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct PackLayoutReport {
+    /// The fill information of all packs which are not marked for deletion
+    pub packs: Vec<PackFill>,
+}
+
+impl PackLayoutReport {
+    /// Returns the size of the unused blobs in partly used packs, i.e. the size which can only be
+    /// freed by repacking
+    #[must_use]
+    pub fn fragmented_size(&self) -> u64 {
+        self.packs
+            .iter()
+            .filter(|pack| pack.is_partly_used())
+            .map(|pack| u64::from(pack.unused_size))
+            .sum()
+    }
+
+    /// Returns the number of packs containing blobs of different blob types
+    #[must_use]
+    pub fn mixed_packs(&self) -> usize {
+        self.packs
+            .iter()
+            .filter(|pack| pack.mixed_blob_types)
+            .count()
+    }
+
+    /// Returns the fragmentation score, i.e. the fraction of the blob sizes which is unused, but can
+    /// only be freed by repacking.
+    ///
+    /// The score is between `0.0` (no partly used packs) and `1.0`; completely unused packs don't count as
+    /// they can be removed without repacking.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn fragmentation(&self) -> f64 {
+        let total: u64 = self
+            .packs
+            .iter()
+            .map(|pack| u64::from(pack.used_size) + u64::from(pack.unused_size))
+            .sum();
+        if total == 0 {
+            0.0
+        } else {
+            self.fragmented_size() as f64 / total as f64
+        }
+    }
+}
+
 // TODO: add documentation!
 #[derive(Debug)]
 struct PruneIndex {
@@ -587,10 +669,18 @@ pub struct PrunePlan {
     used_ids: BTreeMap<BlobId, u8>,
     /// The ids of the existing packs
     existing_packs: BTreeMap<PackId, u32>,
+    /// The ids of all packs which existed when the plan was created
+    pack_ids: BTreeSet<PackId>,
     /// The packs which should be repacked
     repack_candidates: Vec<(PackInfo, EnumSet<PackStatus>, RepackReason, usize, usize)>,
     /// The index files
     index_files: Vec<PruneIndex>,
+    /// The packs which are locked by the backend and therefore cannot be removed
+    locked_packs: BTreeSet<PackId>,
+    /// The fill information of the packs which are not marked for deletion
+    pack_fills: Vec<PackFill>,
+    /// The sequence information of the read index files
+    index_sequence: IndexSequence,
     /// `prune` statistics
     pub stats: PruneStats,
 }
@@ -608,6 +698,7 @@ impl PrunePlan {
         existing_packs: BTreeMap<PackId, u32>,
         index_files: Vec<(IndexId, IndexFile)>,
     ) -> Self {
+        let index_sequence = IndexSequence::new(index_files.iter().map(|(id, file)| (id, file)));
         let mut processed_packs = BTreeSet::new();
         let mut processed_packs_delete = BTreeSet::new();
         let mut index_files: Vec<_> = index_files
@@ -663,9 +754,13 @@ impl PrunePlan {
         Self {
             time: Zoned::now(),
             used_ids,
+            pack_ids: existing_packs.keys().copied().collect(),
             existing_packs,
             repack_candidates: Vec::new(),
             index_files,
+            locked_packs: BTreeSet::new(),
+            pack_fills: Vec::new(),
+            index_sequence,
             stats: PruneStats::default(),
         }
     }
@@ -737,6 +832,18 @@ impl PrunePlan {
         let mut pruner = Self::new(used_ids, existing_packs, index_files);
         pruner.count_used_blobs();
         pruner.check()?;
+        let only_packs: BTreeSet<_> = opts.only_packs.iter().copied().collect();
+        let index_packs: BTreeSet<_> = pruner
+            .index_files
+            .iter()
+            .flat_map(|index| &index.packs)
+            .map(|pack| pack.id)
+            .collect();
+        for id in only_packs.difference(&index_packs) {
+            if !pruner.existing_packs.contains_key(id) {
+                warn!("pack {id} does not exist and is ignored.");
+            }
+        }
         let repack_cacheable_only = opts
             .repack_cacheable_only
             .unwrap_or_else(|| repo.config().is_hot == Some(true));
@@ -749,6 +856,7 @@ impl PrunePlan {
             repack_cacheable_only,
             opts.repack_uncompressed,
             opts.repack_all,
+            &only_packs,
             &pack_sizer,
         )?;
 
@@ -760,7 +868,12 @@ impl PrunePlan {
             &pack_sizer,
         );
 
-        pruner.check_existing_packs()?;
+        pruner.check_existing_packs(&only_packs)?;
+        if be.supports_object_lock() {
+            let p = repo.progress_spinner("checking object locks of packs...");
+            pruner.check_locked_packs(be, opts.instant_delete)?;
+            p.finish();
+        }
         pruner.filter_index_files(opts.instant_delete);
 
         Ok(pruner)
@@ -812,6 +925,7 @@ impl PrunePlan {
     /// * `repack_cacheable_only` - Whether to only repack cacheable packs
     /// * `repack_uncompressed` - Whether to repack packs containing uncompressed blobs
     /// * `repack_all` - Whether to repack all packs
+    /// * `only_packs` - If not empty, only these packs may be repacked or deleted
     /// * `pack_sizer` - The `PackSizer` for the packs
     ///
     /// # Errors
@@ -819,6 +933,7 @@ impl PrunePlan {
     // TODO: add errors!
     #[allow(clippy::too_many_lines)]
     #[allow(clippy::unnecessary_wraps)]
+    #[allow(clippy::too_many_arguments)]
     fn decide_packs(
         &mut self,
         keep_pack: Span,
@@ -826,26 +941,31 @@ impl PrunePlan {
         repack_cacheable_only: bool,
         repack_uncompressed: bool,
         repack_all: bool,
+        only_packs: &BTreeSet<PackId>,
         pack_sizer: &BlobTypeMap<PackSizer>,
     ) -> RusticResult<()> {
         // first process all marked packs then the unmarked ones:
         // - first processed packs are more likely to have all blobs seen as unused
         // - if marked packs have used blob but these blobs are all present in
         //   unmarked packs, we want to perform the deletion!
-        for mark_case in [true, false] {
+        // For the same reason, selected packs are processed before the packs which are kept anyway.
+        for (mark_case, selected_case) in
+            [(true, true), (true, false), (false, true), (false, false)]
+        {
             for (index_num, index) in self.index_files.iter_mut().enumerate() {
-                for (pack_num, pack) in index
-                    .packs
-                    .iter_mut()
-                    .enumerate()
-                    .filter(|(_, p)| p.delete_mark == mark_case)
-                {
+                for (pack_num, pack) in index.packs.iter_mut().enumerate().filter(|(_, p)| {
+                    p.delete_mark == mark_case
+                        && (only_packs.is_empty() || only_packs.contains(&p.id)) == selected_case
+                }) {
                     let pi = PackInfo::from_pack(pack, &mut self.used_ids);
                     //update used/unused stats
                     self.stats.blobs[pi.blob_type].used += u64::from(pi.used_blobs);
                     self.stats.blobs[pi.blob_type].unused += u64::from(pi.unused_blobs);
                     self.stats.size[pi.blob_type].used += u64::from(pi.used_size);
                     self.stats.size[pi.blob_type].unused += u64::from(pi.unused_size);
+                    if !pack.delete_mark {
+                        self.pack_fills.push(PackFill::new(pack, &pi));
+                    }
                     let mut status = EnumSet::empty();
 
                     // Various checks to determine if packs need to be kept
@@ -854,25 +974,32 @@ impl PrunePlan {
                         _ = status.insert(PackStatus::TooYoung);
                     }
                     let keep_uncacheable = repack_cacheable_only && !pack.blob_type.is_cacheable();
+                    let not_selected = !selected_case;
+                    if not_selected {
+                        _ = status.insert(PackStatus::NotSelected);
+                    }
 
                     let to_compress = repack_uncompressed && !pack.is_compressed();
                     if to_compress {
                         _ = status.insert(PackStatus::NotCompressed);
                     }
-                    let size_mismatch = !pack_sizer[pack.blob_type].size_ok(pack.size);
-                    if pack_sizer[pack.blob_type].is_too_small(pack.size) {
+                    let too_small =
+                        pack_sizer[pack.blob_type].is_pack_too_small(pack.size, pack.blobs.len());
+                    if too_small {
                         _ = status.insert(PackStatus::TooSmall);
                     }
-                    if pack_sizer[pack.blob_type].is_too_large(pack.size) {
+                    let too_large = pack_sizer[pack.blob_type].is_too_large(pack.size);
+                    if too_large {
                         _ = status.insert(PackStatus::TooLarge);
                     }
+                    let size_mismatch = too_small || too_large;
                     match (pack.delete_mark, pi.used_blobs, pi.unused_blobs) {
                         (false, 0, _) => {
                             // unused pack
                             self.stats.packs.unused += 1;
                             _ = status.insert(PackStatus::HasUnusedBlobs);
-                            if too_young {
-                                // keep packs which are too young
+                            if too_young || not_selected {
+                                // keep packs which are too young or not selected
                                 pack.set_todo(PackToDo::Keep, &pi, status, &mut self.stats);
                             } else {
                                 pack.set_todo(PackToDo::MarkDelete, &pi, status, &mut self.stats);
@@ -882,7 +1009,7 @@ impl PrunePlan {
                             // used pack
                             self.stats.packs.used += 1;
                             _ = status.insert(PackStatus::HasUsedBlobs);
-                            if too_young || keep_uncacheable {
+                            if too_young || keep_uncacheable || not_selected {
                                 pack.set_todo(PackToDo::Keep, &pi, status, &mut self.stats);
                             } else if to_compress || repack_all {
                                 self.repack_candidates.push((
@@ -911,8 +1038,8 @@ impl PrunePlan {
                             status
                                 .insert_all(PackStatus::HasUsedBlobs | PackStatus::HasUnusedBlobs);
 
-                            if too_young || keep_uncacheable {
-                                // keep packs which are too young and non-cacheable packs if requested
+                            if too_young || keep_uncacheable || not_selected {
+                                // keep packs which are too young, not selected and non-cacheable packs if requested
                                 pack.set_todo(PackToDo::Keep, &pi, status, &mut self.stats);
                             } else {
                                 // other partly used pack => candidate for repacking
@@ -930,8 +1057,9 @@ impl PrunePlan {
                             match pack.time {
                                 // unneeded and marked pack => check if we can remove it.
                                 Some(local_date_time)
-                                    if self.time.saturating_sub(keep_delete).timestamp()
-                                        >= local_date_time =>
+                                    if selected_case
+                                        && self.time.saturating_sub(keep_delete).timestamp()
+                                            >= local_date_time =>
                                 {
                                     _ = status.insert(PackStatus::TooYoung);
                                     pack.set_todo(PackToDo::Delete, &pi, status, &mut self.stats);
@@ -1053,12 +1181,16 @@ impl PrunePlan {
 
     /// Checks if the existing packs are ok
     ///
+    /// # Arguments
+    ///
+    /// * `only_packs` - If not empty, only these unreferenced packs may be removed
+    ///
     /// # Errors
     ///
     /// * If a pack is undecided
     /// * If the size of a pack does not match
     /// * If a pack does not exist
-    fn check_existing_packs(&mut self) -> RusticResult<()> {
+    fn check_existing_packs(&mut self, only_packs: &BTreeSet<PackId>) -> RusticResult<()> {
         for pack in self.index_files.iter().flat_map(|index| &index.packs) {
             let existing_size = self.existing_packs.remove(&pack.id);
 
@@ -1105,7 +1237,10 @@ impl PrunePlan {
             }
         }
 
-        // all remaining packs in existing_packs are unreferenced packs
+        // all remaining packs in existing_packs are unreferenced packs; keep those which are not selected
+        if !only_packs.is_empty() {
+            self.existing_packs.retain(|id, _| only_packs.contains(id));
+        }
         for size in self.existing_packs.values() {
             self.stats.size_unref += u64::from(*size);
         }
@@ -1114,6 +1249,81 @@ impl PrunePlan {
         Ok(())
     }
 
+    /// Checks which packs to remove are still locked by the backend, see [`WriteBackend::supports_object_lock`].
+    ///
+    /// Locked packs which should be deleted are kept marked for deletion instead. If `instant_delete` is set,
+    /// locked packs are marked for deletion instead of being removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to query the locks from
+    /// * `instant_delete` - Whether to instantly delete unreferenced packs
+    ///
+    /// # Errors
+    ///
+    /// * If the lock of a pack could not be queried
+    fn check_locked_packs(
+        &mut self,
+        be: &impl WriteBackend,
+        instant_delete: bool,
+    ) -> RusticResult<()> {
+        let is_removed = |to_do| match to_do {
+            PackToDo::Delete => true,
+            PackToDo::Repack
+            | PackToDo::MarkDelete
+            | PackToDo::KeepMarked
+            | PackToDo::KeepMarkedAndCorrect => instant_delete,
+            PackToDo::Undecided | PackToDo::Keep | PackToDo::Recover => false,
+        };
+        let mut packs: Vec<_> = self
+            .index_files
+            .iter()
+            .flat_map(|index| &index.packs)
+            .filter(|pack| is_removed(pack.to_do))
+            .map(|pack| pack.id)
+            .collect();
+        if instant_delete {
+            packs.extend(self.existing_packs.keys().copied());
+        }
+
+        let now = Timestamp::now();
+        self.locked_packs = packs
+            .into_par_iter()
+            .map(|id| -> RusticResult<_> {
+                let locked = be
+                    .locked_until(FileType::Pack, &id)?
+                    .is_some_and(|until| until > now);
+                Ok(locked.then_some(id))
+            })
+            .filter_map(Result::transpose)
+            .collect::<RusticResult<_>>()?;
+
+        for pack in self
+            .index_files
+            .iter_mut()
+            .flat_map(|index| &mut index.packs)
+        {
+            if pack.to_do == PackToDo::Delete && self.locked_packs.contains(&pack.id) {
+                // the pack cannot be removed yet => keep it marked for deletion
+                pack.to_do = PackToDo::KeepMarked;
+                self.stats.packs_to_delete.remove -= 1;
+                self.stats.packs_to_delete.keep += 1;
+                self.stats.size_to_delete.remove -= u64::from(pack.size);
+                self.stats.size_to_delete.keep += u64::from(pack.size);
+            }
+        }
+
+        self.stats.packs_locked = self.locked_packs.len() as u64;
+        if !self.locked_packs.is_empty() {
+            warn!(
+                "{} packs are locked by the backend and are kept until their retention period expired.",
+                self.locked_packs.len()
+            );
+        }
+
+        Ok(())
+    }
+
     /// Filter out index files which do not need processing
     ///
     /// # Arguments
@@ -1159,6 +1369,16 @@ impl PrunePlan {
             .collect()
     }
 
+    /// Get the report about how well the packs are used from the [`PrunePlan`].
+    ///
+    /// This can be used to decide whether a prune run with repacking is worthwhile.
+    #[must_use]
+    pub fn pack_layout(&self) -> PackLayoutReport {
+        let mut packs = self.pack_fills.clone();
+        packs.sort_unstable_by_key(|pack| pack.id);
+        PackLayoutReport { packs }
+    }
+
     /// Perform the pruning on the given repository.
     ///
     /// # Arguments
@@ -1201,6 +1421,7 @@ impl PrunePlan {
 /// # Errors
 ///
 /// * If the repository is in append-only mode
+/// * If the operation has been cancelled before modifying the repository
 /// * If a pack has no decision
 ///
 /// # Returns
@@ -1223,38 +1444,47 @@ pub(crate) fn prune_repository<S: Open>(
             "Pruning is not allowed in append-only repositories. Please disable append-only mode first, if you know what you are doing. Aborting.",
         ));
     }
+    check_no_rekey(repo, "prune")?;
     repo.warm_up_wait(prune_plan.repack_packs().into_iter())?;
+    // Once the repository is modified, pruning is not cancelled anymore: Stopping while repacking
+    // could leave packs marked for deletion whose needed blobs are not yet repacked.
+    repo.cancellation_token().check("prune")?;
     let be = repo.dbe();
     let prune_time = prune_plan.time.timestamp();
 
-    let mut indexer = Indexer::new_unindexed(be.clone());
-    // mark unreferenced packs for deletion
-    if !prune_plan.existing_packs.is_empty() {
-        if opts.instant_delete {
-            let p = repo.progress_counter("removing unindexed packs...");
-            let existing_packs: Vec<_> = prune_plan.existing_packs.into_keys().collect();
-            be.delete_list(true, existing_packs.iter(), p)?;
-        } else {
-            let p = repo.progress_counter("marking unneeded unindexed pack files for deletion...");
-            p.set_length(
-                prune_plan
-                    .existing_packs
-                    .len()
-                    .try_into()
-                    .unwrap_or_default(),
-            );
-            for (id, size) in prune_plan.existing_packs {
-                let pack = IndexPack {
-                    id,
-                    size: Some(size),
-                    time: Some(prune_time),
-                    blobs: Vec::new(),
-                };
-                indexer.add_remove(pack)?;
-                p.inc(1);
-            }
-            p.finish();
+    let index_sequence = opts.index_sequence.then_some(&prune_plan.index_sequence);
+    // locked packs cannot be removed, so they are always marked for deletion
+    let locked_packs = &prune_plan.locked_packs;
+    let instant_delete = |id: &PackId| opts.instant_delete && !locked_packs.contains(id);
+    let mut indexer =
+        Indexer::new_unindexed(be.clone()).with_sequence(index_sequence.map(IndexSequence::next));
+    // packs newly marked for deletion, recorded in the deletion journal
+    let mut marked_packs = Vec::new();
+    // remove unreferenced packs or mark them for deletion
+    let (existing_packs_remove, existing_packs_mark): (BTreeMap<_, _>, BTreeMap<_, _>) = prune_plan
+        .existing_packs
+        .into_iter()
+        .partition(|(id, _)| instant_delete(id));
+    let existing_packs_remove: Vec<_> = existing_packs_remove.into_keys().collect();
+    if !existing_packs_remove.is_empty() {
+        let p = repo.progress_counter("removing unindexed packs...");
+        be.delete_list(true, existing_packs_remove.iter(), p)?;
+    }
+    if !existing_packs_mark.is_empty() {
+        let p = repo.progress_counter("marking unneeded unindexed pack files for deletion...");
+        p.set_length(existing_packs_mark.len().try_into().unwrap_or_default());
+        for (id, size) in existing_packs_mark {
+            marked_packs.push(id);
+            let pack = IndexPack {
+                id,
+                size: Some(size),
+                time: Some(prune_time),
+                blobs: Vec::new(),
+            };
+            indexer.add_remove(pack)?;
+            p.inc(1);
         }
+        p.finish();
     }
 
     if prune_plan.index_files.is_empty() {
@@ -1272,6 +1502,9 @@ pub(crate) fn prune_repository<S: Open>(
 
     // remove old index files early if requested
     if !indexes_remove.is_empty() && early_delete_index {
+        if let Some(index_sequence) = index_sequence {
+            index_sequence.check(be, &indexer.saved_ids())?;
+        }
         let p = repo.progress_counter("removing old index files...");
         be.delete_list(true, indexes_remove.iter(), p)?;
     }
@@ -1310,10 +1543,11 @@ pub(crate) fn prune_repository<S: Open>(
                     indexer.add(pack)?;
                 }
                 PackToDo::Repack => {
-                    if opts.instant_delete {
+                    if instant_delete(&pack.id) {
                         delete_pack(&pack);
                     } else {
                         // mark pack for removal
+                        marked_packs.push(pack.id);
                         let pack = pack.clone().into_index_pack_with_time(prune_time);
                         indexer.add_remove(pack)?;
                     }
@@ -1324,16 +1558,17 @@ pub(crate) fn prune_repository<S: Open>(
                     repack_packs.push(pack);
                 }
                 PackToDo::MarkDelete => {
-                    if opts.instant_delete {
+                    if instant_delete(&pack.id) {
                         delete_pack(&pack);
                     } else {
                         // mark pack for removal
+                        marked_packs.push(pack.id);
                         let pack = pack.into_index_pack_with_time(prune_time);
                         indexer.add_remove(pack)?;
                     }
                 }
                 PackToDo::KeepMarked | PackToDo::KeepMarkedAndCorrect => {
-                    if opts.instant_delete {
+                    if instant_delete(&pack.id) {
                         delete_pack(&pack);
                     } else {
                         // keep pack: add to new index; keep the timestamp.
@@ -1353,8 +1588,9 @@ pub(crate) fn prune_repository<S: Open>(
     }
     p.finish();
 
-    if repack_packs.is_empty() {
+    let saved_index_ids = if repack_packs.is_empty() {
         indexer.finalize()?;
+        indexer.saved_ids()
     } else {
         let p = repo.progress_bytes("repacking...");
         p.set_length(prune_plan.stats.size_sum().repack - prune_plan.stats.size_sum().repackrm);
@@ -1381,9 +1617,17 @@ pub(crate) fn prune_repository<S: Open>(
             PackSizer::fixed(PackSizer::from_config(repo.config(), blob_type, size).pack_size())
         });
 
+        // packs containing uncompressed blobs are repacked to compress them, so don't skip incompressible blobs
+        let mut be_dst = be.clone();
+        if opts.repack_uncompressed {
+            let mut zstd_params = repo.config().zstd_params();
+            zstd_params.skip_incompressible = false;
+            be_dst.set_zstd_params(zstd_params);
+        }
+
         let tree_repacker = BlobCopier::new(
             be.clone(),
-            be.clone(),
+            be_dst.clone(),
             BlobType::Tree,
             indexer.clone(),
             pack_sizer[BlobType::Tree],
@@ -1391,7 +1635,7 @@ pub(crate) fn prune_repository<S: Open>(
 
         let data_repacker = BlobCopier::new(
             be.clone(),
-            be.clone(),
+            be_dst,
             BlobType::Data,
             indexer.clone(),
             pack_sizer[BlobType::Data],
@@ -1428,8 +1672,15 @@ pub(crate) fn prune_repository<S: Open>(
             })?;
         _ = tree_repacker.finalize()?;
         _ = data_repacker.finalize()?;
-        indexer.write().unwrap().finalize()?;
+        let indexer = indexer.read().unwrap();
+        indexer.finalize()?;
         p.finish();
+        indexer.saved_ids()
+    };
+
+    // check that no other process rewrote the index before removing anything
+    if let Some(index_sequence) = index_sequence {
+        index_sequence.check(be, &saved_index_ids)?;
     }
 
     // remove old index files first as they may reference pack files which are removed soon.
@@ -1438,6 +1689,17 @@ pub(crate) fn prune_repository<S: Open>(
         be.delete_list(true, indexes_remove.iter(), p)?;
     }
 
+    // deletion journals are only written if the backend supports them
+    if !marked_packs.is_empty() && be.supports_file_type(FileType::Journal) {
+        let journal = JournalFile {
+            time: prune_time,
+            packs: marked_packs,
+            options: opts.clone(),
+        };
+        let id = be.save_file(&journal)?;
+        debug!("saved deletion journal {id}");
+    }
+
     if !data_packs_remove.is_empty() {
         let p = repo.progress_counter("removing old data packs...");
         be.delete_list(false, data_packs_remove.iter(), p)?;
@@ -1448,6 +1710,33 @@ pub(crate) fn prune_repository<S: Open>(
         be.delete_list(true, tree_packs_remove.iter(), p)?;
     }
 
+    // remove deletion journals whose packs are all gone
+    if be.supports_file_type(FileType::Journal) {
+        let removed: BTreeSet<_> = existing_packs_remove
+            .iter()
+            .chain(&data_packs_remove)
+            .chain(&tree_packs_remove)
+            .copied()
+            .collect();
+        let gone = |id: &PackId| !prune_plan.pack_ids.contains(id) || removed.contains(id);
+
+        let p = repo.progress_counter("reading deletion journals...");
+        let mut journals_remove = Vec::new();
+        for journal in be.stream_all::<JournalFile>(&p)? {
+            let (journal_id, journal) = journal?;
+            if journal.packs.iter().all(gone) {
+                debug!("deletion journal {journal_id} is completed");
+                journals_remove.push(journal_id);
+            }
+        }
+        p.finish();
+
+        if !journals_remove.is_empty() {
+            let p = repo.progress_counter("removing completed deletion journals...");
+            be.delete_list(false, journals_remove.iter(), p)?;
+        }
+    }
+
     Ok(())
 }
 
@@ -1609,6 +1898,7 @@ fn find_used_blobs<S>(
 
     let mut tree_streamer = TreeStreamerOnce::new(be, index, snap_trees, p)?;
     while let Some(item) = tree_streamer.next().transpose()? {
+        repo.cancellation_token().check("prune")?;
         let (_, tree) = item;
         for node in tree.nodes {
             match node.node_type {
@@ -0,0 +1,231 @@
+//! Quarantine packs which exist in the backend but are not contained in the index
+use std::collections::BTreeMap;
+
+use derive_setters::Setters;
+use jiff::{Span, Timestamp, Zoned};
+use log::{debug, info};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    backend::{
+        FileType, ReadBackend,
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+    },
+    commands::{prune::PruneOptions, rekey::check_no_rekey},
+    error::{ErrorKind, RusticError, RusticResult},
+    index::indexer::Indexer,
+    repofile::{IndexFile, IndexPack, JournalFile, JournalId, packfile::PackId},
+    repository::{Open, Repository},
+};
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Debug, Clone, Setters, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for quarantining unindexed packs
+pub struct QuarantineOptions {
+    /// Minimum duration (e.g. 10m) packs stay in quarantine. Packs quarantined longer ago are reported as
+    /// expired; they will be removed by a `prune` run using the same `keep-delete`.
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "DURATION", default_value = "23h")
+    )]
+    pub keep_delete: Span,
+
+    /// Minimum age (e.g. 24h) of unindexed packs to quarantine. Younger packs may belong to a backup which
+    /// is still running and are skipped, as are packs whose modification time is not reported by the
+    /// backend, unless this is zero.
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "DURATION", default_value = "24h")
+    )]
+    pub min_age: Span,
+
+    /// Only report the unindexed packs, don't quarantine them
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub dry_run: bool,
+}
+
+impl Default for QuarantineOptions {
+    fn default() -> Self {
+        Self {
+            keep_delete: Span::new().hours(23),
+            min_age: Span::new().hours(24),
+            dry_run: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+/// A pack which is present in the backend but not contained in the index
+pub struct QuarantinedPack {
+    /// The id of the pack
+    pub id: PackId,
+    /// The size of the pack
+    pub size: u32,
+    /// The time the pack has been modified last, if known; only set for unindexed packs found by this run
+    pub modified: Option<Timestamp>,
+    /// The time the pack has been quarantined, i.e. marked for deletion
+    pub time: Option<Timestamp>,
+    /// Whether the pack has been quarantined longer than `keep_delete`, i.e. will be removed by the
+    /// next `prune` run
+    pub expired: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+/// The result of quarantining unindexed packs, see [`Repository::quarantine_unindexed_packs`]
+pub struct QuarantineReport {
+    /// The unindexed packs which have been quarantined by this run (or would have been in dry-run mode)
+    pub quarantined: Vec<QuarantinedPack>,
+    /// The packs which already have been quarantined or marked for deletion before
+    pub pending: Vec<QuarantinedPack>,
+    /// The unindexed packs which have been skipped as they are younger than `min_age` or their age is
+    /// unknown
+    pub too_recent: Vec<QuarantinedPack>,
+    /// The deletion journal recording the newly quarantined packs, if the backend supports journals
+    pub journal: Option<JournalId>,
+}
+
+impl QuarantineReport {
+    /// Returns the total size of the newly quarantined packs
+    #[must_use]
+    pub fn quarantined_size(&self) -> u64 {
+        self.quarantined
+            .iter()
+            .map(|pack| u64::from(pack.size))
+            .sum()
+    }
+
+    /// Returns the packs which will be removed by the next `prune` run
+    pub fn expired(&self) -> impl Iterator<Item = &QuarantinedPack> {
+        self.pending.iter().filter(|pack| pack.expired)
+    }
+}
+
+/// Cross-check the packs in the backend with the index and quarantine all unindexed packs.
+///
+/// Only packs older than `min_age` are quarantined, and the repository is locked exclusively before
+/// reading the index, so packs of running backups holding a shared lock are never quarantined.
+/// Quarantined packs are marked for deletion in a new index file and recorded in a deletion journal, but
+/// are not removed. They can be reviewed using [`Repository::audit_deletions`] and restored using
+/// [`Repository::cancel_deletions`]. A `prune` run only removes them after `keep_delete` has expired and
+/// recovers them if they are referenced by the index in the meantime, e.g. by a backup which has still
+/// been running.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `opts` - The quarantine options
+///
+/// # Errors
+///
+/// * If the repository is in append-only mode.
+/// * If the repository is locked by another process.
+/// * If the packs or their modification times could not be listed.
+/// * If the index files could not be read or written.
+pub(crate) fn quarantine_unindexed_packs<S: Open>(
+    repo: &Repository<S>,
+    opts: &QuarantineOptions,
+) -> RusticResult<QuarantineReport> {
+    if !opts.dry_run && repo.config().append_only == Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::AppendOnly,
+            "Quarantining packs is not allowed in append-only repositories. Please disable append-only mode first, if you know what you are doing. Aborting.",
+        ));
+    }
+    if !opts.dry_run {
+        check_no_rekey(repo, "quarantine")?;
+    }
+
+    let be = repo.dbe();
+    let now = Zoned::now();
+    let expired_before = now.saturating_sub(opts.keep_delete).timestamp();
+    let min_age_zero = opts.min_age.is_zero();
+    let modified_before = now.saturating_sub(opts.min_age).timestamp();
+
+    let p = repo.progress_spinner("listing packs...");
+    let mut unindexed: BTreeMap<_, _> = be
+        .list_with_size(FileType::Pack)?
+        .into_iter()
+        .map(|(id, size)| (PackId::from(id), size))
+        .collect();
+    p.finish();
+
+    let mut report = QuarantineReport::default();
+    let p = repo.progress_counter("reading index...");
+    for index in be.stream_all::<IndexFile>(&p)? {
+        let index = index?.1;
+        for pack in index.packs {
+            _ = unindexed.remove(&pack.id);
+        }
+        for pack in index.packs_to_delete {
+            if unindexed.remove(&pack.id).is_some() {
+                report.pending.push(QuarantinedPack {
+                    id: pack.id,
+                    size: pack.pack_size(),
+                    modified: None,
+                    time: pack.time,
+                    expired: pack.time.is_some_and(|time| time <= expired_before),
+                });
+            }
+        }
+    }
+    p.finish();
+
+    let time = now.timestamp();
+    for (id, size) in unindexed {
+        let modified = be.modified(FileType::Pack, &id)?;
+        let old_enough =
+            min_age_zero || modified.is_some_and(|modified| modified <= modified_before);
+        let pack = QuarantinedPack {
+            id,
+            size,
+            modified,
+            time: old_enough.then_some(time),
+            expired: false,
+        };
+        if old_enough {
+            report.quarantined.push(pack);
+        } else {
+            debug!("skipping recent unindexed pack {id}, modified: {modified:?}");
+            report.too_recent.push(pack);
+        }
+    }
+    info!(
+        "found {} unindexed packs, {} packs already quarantined, {} recent packs skipped",
+        report.quarantined.len(),
+        report.pending.len(),
+        report.too_recent.len()
+    );
+
+    if opts.dry_run || report.quarantined.is_empty() {
+        return Ok(report);
+    }
+
+    let mut indexer = Indexer::new_unindexed(be.clone());
+    for pack in &report.quarantined {
+        indexer.add_remove(IndexPack {
+            id: pack.id,
+            size: Some(pack.size),
+            time: Some(time),
+            blobs: Vec::new(),
+        })?;
+    }
+    indexer.finalize()?;
+
+    if be.supports_file_type(FileType::Journal) {
+        let journal = JournalFile {
+            time,
+            packs: report.quarantined.iter().map(|pack| pack.id).collect(),
+            options: PruneOptions::default().keep_delete(opts.keep_delete),
+        };
+        let id = be.save_file(&journal)?;
+        debug!("saved deletion journal {id}");
+        report.journal = Some(JournalId::from(id));
+    }
+
+    Ok(report)
+}
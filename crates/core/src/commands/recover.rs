@@ -0,0 +1,142 @@
+//! Recover packs which are marked for deletion
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use jiff::Timestamp;
+use log::{debug, info, warn};
+
+use crate::{
+    backend::{
+        FileType, ReadBackend, WriteBackend,
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+    },
+    error::{ErrorKind, RusticError, RusticResult},
+    index::indexer::Indexer,
+    repofile::{IndexFile, IndexId, IndexPack, PackHeader, PackHeaderRef, packfile::PackId},
+    repository::{Open, Repository},
+};
+
+/// Recover packs which are marked for deletion.
+///
+/// The headers of the given packs are re-read and the packs are added to the live index with the
+/// current time. Afterwards, the delete marks are removed from the existing index files. This is the
+/// user-driven counterpart of the recovery done by `prune` and allows to undo the effects of a `prune`
+/// run which marked packs still needed, e.g. because of wrong `ignore_snaps`.
+///
+/// Packs which are not marked for deletion, which have already been removed or whose header cannot be
+/// read are skipped with a warning.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `ids` - The packs to recover
+///
+/// # Errors
+///
+/// * If the repository is in append-only mode.
+/// * If the packs could not be listed.
+/// * If the index files could not be read, written or removed.
+///
+/// # Returns
+///
+/// The packs which have been recovered
+pub(crate) fn recover_packs<S: Open>(
+    repo: &Repository<S>,
+    ids: &[PackId],
+) -> RusticResult<Vec<PackId>> {
+    if repo.config().append_only == Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::AppendOnly,
+            "Recovering packs is not allowed in append-only repositories. Please disable append-only mode first, if you know what you are doing. Aborting.",
+        ));
+    }
+
+    let be = repo.dbe();
+
+    let p = repo.progress_spinner("listing packs...");
+    let existing: HashMap<_, _> = be
+        .list_with_size(FileType::Pack)?
+        .into_iter()
+        .map(|(id, size)| (PackId::from(id), size))
+        .collect();
+    p.finish();
+
+    let mut to_recover: BTreeSet<_> = ids.iter().copied().collect();
+    for id in to_recover.iter().filter(|id| !existing.contains_key(*id)) {
+        warn!("pack {id} no longer exists and cannot be recovered.");
+    }
+    to_recover.retain(|id| existing.contains_key(id));
+
+    // find the marked packs and remember the index files containing them
+    let mut candidates = BTreeMap::new();
+    let mut index_files: Vec<(IndexId, IndexFile)> = Vec::new();
+    let p = repo.progress_counter("reading index...");
+    for index in be.stream_all::<IndexFile>(&p)? {
+        let (index_id, index) = index?;
+        let mut found = false;
+        for pack in &index.packs_to_delete {
+            if to_recover.remove(&pack.id) {
+                let size_hint = PackHeaderRef::from_index_pack(pack).size();
+                _ = candidates.insert(pack.id, (size_hint, existing[&pack.id]));
+                found = true;
+            }
+        }
+        if found {
+            index_files.push((index_id, index));
+        }
+    }
+    p.finish();
+
+    for id in &to_recover {
+        warn!("pack {id} is not marked for deletion.");
+    }
+
+    repo.warm_up_wait(candidates.keys().copied())?;
+
+    let now = Timestamp::now();
+    let mut indexer = Indexer::new_unindexed(be.clone());
+    let mut recovered = BTreeSet::new();
+    let p = repo.progress_counter("reading pack headers");
+    p.set_length(candidates.len() as u64);
+    for (id, (size_hint, pack_size)) in candidates {
+        debug!("reading pack {id}...");
+        match PackHeader::from_file(be, repo.cache(), id, Some(size_hint), pack_size) {
+            Err(err) => {
+                warn!(
+                    "error reading pack {id} (-> keeping delete mark): {}",
+                    err.display_log()
+                );
+            }
+            Ok(header) => {
+                let pack = IndexPack {
+                    id,
+                    blobs: header.into_blobs(),
+                    time: Some(now),
+                    size: Some(pack_size),
+                };
+                indexer.add(pack)?;
+                _ = recovered.insert(id);
+            }
+        }
+        p.inc(1);
+    }
+    indexer.finalize()?;
+    p.finish();
+
+    // the recovered packs are now contained in the new index; remove the delete marks
+    for (index_id, mut index) in index_files {
+        let marked = index.packs_to_delete.len();
+        index
+            .packs_to_delete
+            .retain(|pack| !recovered.contains(&pack.id));
+        if index.packs_to_delete.len() == marked {
+            continue;
+        }
+        if !index.packs.is_empty() || !index.packs_to_delete.is_empty() {
+            _ = be.save_file(&index)?;
+        }
+        be.remove(FileType::Index, &index_id, true)?;
+    }
+    info!("recovered {} packs", recovered.len());
+
+    Ok(recovered.into_iter().collect())
+}
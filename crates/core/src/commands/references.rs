@@ -0,0 +1,231 @@
+//! Reference counts of blobs, e.g. for capacity analytics
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use serde_derive::Serialize;
+
+use crate::{
+    backend::{decrypt::DecryptReadBackend, node::NodeType},
+    blob::{BlobId, BlobType, tree::TreeStreamerOnce},
+    error::RusticResult,
+    progress::Progress,
+    repofile::{IndexFile, PackId, snapshotfile::SnapshotId},
+    repository::{IndexedTree, Repository},
+};
+
+/// The references to a blob contained in the repository, see [`Repository::blob_references`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BlobReferences {
+    /// The id of the blob
+    pub id: BlobId,
+    /// The type of the blob
+    pub tpe: BlobType,
+    /// The pack containing the blob
+    pub pack: PackId,
+    /// The (packed) length of the blob within the pack
+    pub length: u32,
+    /// The length of the blob data, i.e. after decompression
+    pub data_length: u32,
+    /// The number of references to the blob.
+    ///
+    /// Each tree is only counted once, even if it is contained in multiple snapshots. Root trees are
+    /// counted once for each snapshot. A blob which is not referenced can be removed by `prune`.
+    pub reference_count: u64,
+    /// A sample of snapshots referencing the blob.
+    ///
+    /// As trees are only walked once, these are the snapshots which first reached a tree referencing
+    /// the blob.
+    pub snapshots: Vec<SnapshotId>,
+}
+
+/// A path within a snapshot referencing a blob, see [`Repository::find_blob_origin`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BlobPath {
+    /// The snapshot referencing the blob
+    pub snapshot: SnapshotId,
+    /// The path of the file (data blobs) or dir (tree blobs) within the snapshot; empty for the root tree
+    pub path: PathBuf,
+}
+
+/// The origin of a blob, see [`Repository::find_blob_origin`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BlobOrigin {
+    /// The id of the blob
+    pub id: BlobId,
+    /// The type of the blob, or `None` if the blob is not contained in the index
+    pub tpe: Option<BlobType>,
+    /// The packs containing the blob; packs marked for deletion are not included
+    pub packs: Vec<PackId>,
+    /// All snapshots and paths referencing the blob
+    pub paths: Vec<BlobPath>,
+}
+
+/// The references collected while walking the snapshots
+#[derive(Debug, Default)]
+struct References {
+    /// The number of references
+    count: u64,
+    /// The sampled snapshots
+    snapshots: Vec<SnapshotId>,
+}
+
+/// Walk all snapshots once and stream the references of all blobs contained in the index.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `sample_size` - The maximum number of snapshots given per blob
+///
+/// # Errors
+///
+/// * If the snapshots, the trees or the index files could not be read.
+///
+/// # Returns
+///
+/// An iterator over the references of all blobs contained in the index. Blobs contained in multiple
+/// packs are returned once for each pack; blobs in packs marked for deletion are not returned.
+pub(crate) fn blob_references<S: IndexedTree>(
+    repo: &Repository<S>,
+    sample_size: usize,
+) -> RusticResult<impl Iterator<Item = RusticResult<BlobReferences>>> {
+    let mut snapshots = repo.get_all_snapshots()?;
+    // walk the oldest snapshots first
+    snapshots.sort_unstable();
+
+    let mut refs: BTreeMap<BlobId, References> = BTreeMap::new();
+    let mut add = |id: BlobId, snap: SnapshotId| {
+        let refs = refs.entry(id).or_default();
+        refs.count += 1;
+        if refs.snapshots.len() < sample_size && !refs.snapshots.contains(&snap) {
+            refs.snapshots.push(snap);
+        }
+    };
+
+    let p = repo.progress_counter("finding used blobs...");
+    p.set_length(snapshots.len() as u64);
+    let mut visited = BTreeSet::new();
+    for snap in snapshots {
+        add(BlobId::from(*snap.tree), snap.id);
+        let mut tree_streamer = TreeStreamerOnce::new_with_visited(
+            repo.dbe(),
+            repo.index(),
+            vec![snap.tree],
+            visited,
+            Progress::hidden(),
+        )?;
+        while let Some(item) = tree_streamer.next().transpose()? {
+            let (_, tree) = item;
+            for node in tree.nodes {
+                match node.node_type {
+                    NodeType::File => {
+                        for id in node.content.iter().flatten() {
+                            add(BlobId::from(**id), snap.id);
+                        }
+                    }
+                    NodeType::Dir => {
+                        if let Some(subtree) = node.subtree {
+                            add(BlobId::from(*subtree), snap.id);
+                        }
+                    }
+                    _ => {} // nothing to do
+                }
+            }
+        }
+        visited = tree_streamer.into_visited();
+        p.inc(1);
+    }
+    p.finish();
+
+    let p = repo.progress_counter("reading index...");
+    let indexes = repo.dbe().stream_all::<IndexFile>(&p)?;
+    Ok(indexes.into_iter().flat_map(move |index| {
+        let blobs: Vec<_> = match index {
+            Ok((_, index)) => index
+                .packs
+                .into_iter()
+                .flat_map(|pack| {
+                    let id = pack.id;
+                    pack.blobs.into_iter().map(move |blob| (id, blob))
+                })
+                .map(|(pack, blob)| {
+                    let refs = refs.get(&blob.id);
+                    Ok(BlobReferences {
+                        id: blob.id,
+                        tpe: blob.tpe,
+                        pack,
+                        length: blob.location.length,
+                        data_length: blob.location.data_length(),
+                        reference_count: refs.map_or(0, |refs| refs.count),
+                        snapshots: refs.map_or_else(Vec::new, |refs| refs.snapshots.clone()),
+                    })
+                })
+                .collect(),
+            Err(err) => vec![Err(err)],
+        };
+        blobs
+    }))
+}
+
+/// Find the packs containing a blob and the snapshots and paths referencing it.
+///
+/// All index files are read to find the packs, and all snapshots are walked to find the files and dirs
+/// referencing the blob. Trees shared by multiple snapshots are only read once.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `id` - The id of the blob
+///
+/// # Errors
+///
+/// * If the snapshots, the trees or the index files could not be read.
+pub(crate) fn find_blob_origin<S: IndexedTree>(
+    repo: &Repository<S>,
+    id: &BlobId,
+) -> RusticResult<BlobOrigin> {
+    let mut origin = BlobOrigin {
+        id: *id,
+        tpe: None,
+        packs: Vec::new(),
+        paths: Vec::new(),
+    };
+
+    let p = repo.progress_counter("reading index...");
+    for index in repo.dbe().stream_all::<IndexFile>(&p)? {
+        for pack in index?.1.packs {
+            if let Some(blob) = pack.blobs.iter().find(|blob| blob.id == *id) {
+                origin.tpe = Some(blob.tpe);
+                origin.packs.push(pack.id);
+            }
+        }
+    }
+    p.finish();
+
+    let mut snapshots = repo.get_all_snapshots()?;
+    snapshots.sort_unstable();
+    let matches = repo.find_matching_nodes(snapshots.iter().map(|sn| sn.tree), &|_, node| {
+        node.subtree.is_some_and(|tree| *tree == **id)
+            || node.content.iter().flatten().any(|data| **data == **id)
+    })?;
+    for (sn, found) in snapshots.iter().zip(matches.matches) {
+        if *sn.tree == **id {
+            origin.paths.push(BlobPath {
+                snapshot: sn.id,
+                path: PathBuf::new(),
+            });
+        }
+        origin
+            .paths
+            .extend(found.into_iter().map(|(path_idx, _)| BlobPath {
+                snapshot: sn.id,
+                path: matches.paths[path_idx].clone(),
+            }));
+    }
+
+    Ok(origin)
+}
@@ -0,0 +1,800 @@
+//! `rekey` subcommand: re-encrypt a repository using a new master key
+//!
+//! The repository is re-encrypted using the crypto scheme of restic repositories (AES-256-CTR with
+//! Poly1305-AES); only the master key is replaced. Migrating to another crypto scheme is not supported.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use derive_setters::Setters;
+use jiff::Timestamp;
+use log::{debug, info};
+use serde_derive::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
+
+use crate::{
+    CancellationToken,
+    backend::{
+        FileType, ReadBackend, WriteBackend,
+        decrypt::{DecryptBackend, DecryptReadBackend, DecryptWriteBackend},
+    },
+    blob::{
+        BlobLocations, BlobType,
+        packer::{BlobCopier, CopyPackBlobs, PackSizer},
+    },
+    commands::{
+        config::save_config,
+        copy::copy_blobs,
+        key::{KeyOptions, add_key_to_repo},
+    },
+    crypto::{CryptoKey, aespoly1305::Key},
+    error::{ErrorKind, RusticError, RusticResult},
+    id::Id,
+    index::indexer::Indexer,
+    repofile::{
+        IndexFile, IndexPack, KeyId, MasterKey, RepoFile, SnapshotFile, SnapshotId,
+        indexfile::IndexId, keyfile::key_from_backend, packfile::PackId,
+    },
+    repository::{Open, Repository},
+    util::LimitOption,
+};
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Debug, Clone, Copy, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for the `rekey` command
+pub struct RekeyOptions {
+    /// Define maximum data to re-encrypt within one run in % of reposize or as size (e.g. '5b', '2 kB', '3M', '4TiB') or 'unlimited'
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "LIMIT", default_value = "unlimited")
+    )]
+    pub max_repack: LimitOption,
+}
+
+impl Default for RekeyOptions {
+    fn default() -> Self {
+        Self {
+            max_repack: LimitOption::Unlimited,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The state of a running re-encryption of a repository.
+///
+/// Re-encrypting a repository can take a long time. This state can be saved (e.g. as JSON) between
+/// multiple runs of [`Repository::rekey`] to re-encrypt the repository incrementally.
+///
+/// # Note
+///
+/// The state contains the new master key encrypted with the current key of the repository and vice
+/// versa. Treat it like a key file.
+pub struct RekeyState {
+    /// The new master key, encrypted with the current key of the repository
+    #[serde_as(as = "Base64")]
+    key: Vec<u8>,
+    /// The current master key, encrypted with the new key; this allows to continue finishing the
+    /// re-encryption once the repository uses the new key
+    #[serde_as(as = "Base64")]
+    old_key: Vec<u8>,
+    /// The packs which have already been re-encrypted
+    done: BTreeSet<PackId>,
+    /// The index of the newly written, re-encrypted packs
+    packs: Vec<IndexPack>,
+}
+
+impl RekeyState {
+    /// Create a new state with a newly generated master key
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to re-encrypt
+    ///
+    /// # Errors
+    ///
+    /// * If the new key could not be serialized or encrypted.
+    fn new<S: Open>(repo: &Repository<S>) -> RusticResult<Self> {
+        let old_key = *repo.dbe().key();
+        let new_key = Key::new();
+        Ok(Self {
+            key: old_key.encrypt_data(&serialize_key(new_key)?)?,
+            old_key: new_key.encrypt_data(&serialize_key(old_key)?)?,
+            done: BTreeSet::new(),
+            packs: Vec::new(),
+        })
+    }
+
+    /// Get the old and the new key
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to re-encrypt; it may use the old or already the new key
+    ///
+    /// # Errors
+    ///
+    /// * If the state does not belong to the given repository.
+    fn keys<S: Open>(&self, repo: &Repository<S>) -> RusticResult<RekeyKeys> {
+        let current = *repo.dbe().key();
+        if let Ok(data) = current.decrypt_data(&self.key) {
+            return Ok(RekeyKeys {
+                old: current,
+                new: deserialize_key(&data)?,
+                switched: false,
+            });
+        }
+        // finishing the re-encryption may have been interrupted after switching to the new key
+        let data = current.decrypt_data(&self.old_key).map_err(|err| {
+            err.prepend_guidance_line(
+                "The rekey state could not be decrypted. Please check that it belongs to this repository.",
+            )
+        })?;
+        Ok(RekeyKeys {
+            old: deserialize_key(&data)?,
+            new: current,
+            switched: true,
+        })
+    }
+
+    /// The number of packs which have already been re-encrypted
+    #[must_use]
+    pub fn packs_done(&self) -> usize {
+        self.done.len()
+    }
+}
+
+/// The keys of a re-encryption
+#[derive(Debug, Clone, Copy)]
+struct RekeyKeys {
+    /// The key the repository is re-encrypted from
+    old: Key,
+    /// The key the repository is re-encrypted to
+    new: Key,
+    /// Whether the repository already uses the new key
+    switched: bool,
+}
+
+/// Serialize a key to JSON
+fn serialize_key(key: Key) -> RusticResult<Vec<u8>> {
+    serde_json::to_vec(&MasterKey::from_key(key)).map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::Internal,
+            "Failed to serialize the master key to JSON.",
+            err,
+        )
+    })
+}
+
+/// Deserialize a key from JSON
+fn deserialize_key(data: &[u8]) -> RusticResult<Key> {
+    let key: MasterKey = serde_json::from_slice(data).map_err(|err| {
+        RusticError::with_source(
+            ErrorKind::Internal,
+            "Failed to deserialize the master key from JSON.",
+            err,
+        )
+    })?;
+    Ok(key.key())
+}
+
+/// Check that no re-encryption of the repository is in progress.
+///
+/// Use this before running commands which remove or re-index packs which are not contained in the index.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to check
+/// * `operation` - The name of the operation, used in the error message
+///
+/// # Errors
+///
+/// * If a re-encryption is in progress.
+pub(crate) fn check_no_rekey<S: Open>(
+    repo: &Repository<S>,
+    operation: &'static str,
+) -> RusticResult<()> {
+    if repo.config().rekey_in_progress == Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::Repository,
+            "Cannot run `{operation}` while the repository is being re-encrypted, as this would remove the already re-encrypted packs. Please finish the re-encryption first. Aborting.",
+        )
+        .attach_context("operation", operation));
+    }
+    Ok(())
+}
+
+/// Backend used to write re-encrypted packs.
+///
+/// Packs are written to the repository, but all other files (i.e. index files) are kept in memory:
+/// They are encrypted with the new key and must not be visible in the repository before the re-encryption is finished.
+#[derive(Debug)]
+struct RekeyBackend {
+    /// The backend to write packs to
+    be: Arc<dyn WriteBackend>,
+    /// The files kept in memory
+    files: Mutex<BTreeMap<Id, Bytes>>,
+}
+
+impl RekeyBackend {
+    fn new(be: Arc<dyn WriteBackend>) -> Self {
+        Self {
+            be,
+            files: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The ids of the files kept in memory
+    fn ids(&self) -> Vec<Id> {
+        self.files.lock().unwrap().keys().copied().collect()
+    }
+}
+
+impl ReadBackend for RekeyBackend {
+    fn location(&self) -> String {
+        self.be.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.be.list_with_size(tpe)
+    }
+
+    fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.modified(tpe, id)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        if tpe != FileType::Pack
+            && let Some(data) = self.files.lock().unwrap().get(id)
+        {
+            return Ok(data.clone());
+        }
+        self.be.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.be.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn warmup_path(&self, tpe: FileType, id: &Id) -> String {
+        self.be.warmup_path(tpe, id)
+    }
+}
+
+impl WriteBackend for RekeyBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.be.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        if tpe == FileType::Pack {
+            self.be.write_bytes(tpe, id, cacheable, buf)
+        } else {
+            debug!("keeping {tpe} file {id} in memory");
+            _ = self.files.lock().unwrap().insert(*id, buf);
+            Ok(())
+        }
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.be.remove(tpe, id, cacheable)
+    }
+
+    fn supports_object_lock(&self) -> bool {
+        self.be.supports_object_lock()
+    }
+
+    fn write_bytes_locked(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        buf: Bytes,
+        until: Timestamp,
+    ) -> RusticResult<()> {
+        if tpe == FileType::Pack {
+            self.be.write_bytes_locked(tpe, id, cacheable, buf, until)
+        } else {
+            // only packs are written here; all other files are written when finishing the re-encryption
+            debug!("keeping {tpe} file {id} in memory, lock until {until}");
+            _ = self.files.lock().unwrap().insert(*id, buf);
+            Ok(())
+        }
+    }
+
+    fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+        self.be.locked_until(tpe, id)
+    }
+}
+
+/// Create a [`DecryptBackend`] using the new key and the compression settings of the repository
+fn new_dbe<S: Open>(
+    repo: &Repository<S>,
+    be: Arc<dyn WriteBackend>,
+    key: Key,
+) -> RusticResult<DecryptBackend<Key>> {
+    let mut dbe = DecryptBackend::new(be, key);
+    dbe.set_zstd(repo.config().zstd()?);
+    dbe.set_zstd_params(repo.config().zstd_params());
+    dbe.set_pack_layout(repo.config().pack_layout());
+    dbe.set_extra_verify(repo.config().extra_verify());
+    dbe.set_upload_concurrency(repo.dbe().upload_concurrency());
+    Ok(dbe)
+}
+
+/// Read all packs contained in the index of the repository which are not marked for deletion
+fn read_index<S: Open>(repo: &Repository<S>) -> RusticResult<Vec<IndexPack>> {
+    let p = repo.progress_counter("reading index...");
+    let mut packs = BTreeMap::new();
+    for index in repo.dbe().stream_all::<IndexFile>(&p)? {
+        for pack in index?.1.packs {
+            _ = packs.insert(pack.id, pack);
+        }
+    }
+    p.finish();
+    Ok(packs.into_values().collect())
+}
+
+/// Start the re-encryption of the repository.
+///
+/// This generates the new master key and marks the re-encryption as in progress in the config file,
+/// so that commands which would remove the re-encrypted packs refuse to run until it is finished.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to re-encrypt
+///
+/// # Errors
+///
+/// * If the repository is in append-only mode.
+/// * If the new key could not be serialized or encrypted.
+/// * If the config file could not be written.
+///
+/// # Returns
+///
+/// The state of the re-encryption
+pub(crate) fn start_rekey<S: Open>(repo: &mut Repository<S>) -> RusticResult<RekeyState> {
+    if repo.config().append_only == Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::Repository,
+            "Repository is in append-only mode and cannot be re-encrypted. Aborting.",
+        ));
+    }
+    let state = RekeyState::new(repo)?;
+    if repo.config().rekey_in_progress != Some(true) {
+        let mut config = repo.config().clone();
+        config.rekey_in_progress = Some(true);
+        repo.set_config(config.clone())?;
+        save_config(repo, config, *repo.dbe().key())?;
+    }
+    Ok(state)
+}
+
+/// Re-encrypt packs of the repository using the new key of the given [`RekeyState`].
+///
+/// # Arguments
+///
+/// * `repo` - The repository to re-encrypt
+/// * `state` - The state of the re-encryption; this is updated with the re-encrypted packs
+/// * `opts` - The options to use
+///
+/// # Errors
+///
+/// * If the operation has been cancelled before starting the re-encryption.
+/// * If the re-encryption has not been started or is already being finished.
+/// * If the state does not belong to the repository.
+/// * If the index could not be read.
+/// * If blobs could not be re-encrypted.
+///
+/// # Returns
+///
+/// The number of packs which still need to be re-encrypted.
+pub(crate) fn rekey<S: Open>(
+    repo: &Repository<S>,
+    state: &mut RekeyState,
+    opts: &RekeyOptions,
+) -> RusticResult<usize> {
+    repo.cancellation_token().check("rekey")?;
+    if repo.config().rekey_in_progress != Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::Repository,
+            "No re-encryption of the repository has been started. Please start the re-encryption first.",
+        ));
+    }
+    let keys = state.keys(repo)?;
+    if keys.switched {
+        return Err(RusticError::new(
+            ErrorKind::Repository,
+            "The repository already uses the new key. Please finish the re-encryption.",
+        ));
+    }
+    let packs = read_index(repo)?;
+
+    let total_size = packs.iter().map(|pack| u64::from(pack.pack_size())).sum();
+    let limit = opts.max_repack.to_size(total_size).unwrap_or(u64::MAX);
+
+    let mut size = 0;
+    let (selected, remaining): (Vec<_>, Vec<_>) = packs
+        .into_iter()
+        .filter(|pack| !state.done.contains(&pack.id))
+        .partition(|pack| {
+            if size < limit {
+                size += u64::from(pack.pack_size());
+                true
+            } else {
+                false
+            }
+        });
+    info!(
+        "re-encrypting {} packs, {} packs remaining afterwards",
+        selected.len(),
+        remaining.len()
+    );
+
+    let rekey_be = Arc::new(RekeyBackend::new(repo.be.clone()));
+    let be = repo.dbe();
+    let be_new = new_dbe(repo, rekey_be.clone(), keys.new)?;
+    let indexer = Indexer::new_unindexed(be_new.clone()).into_shared();
+
+    for blob_type in [BlobType::Tree, BlobType::Data] {
+        let packs: Vec<_> = selected
+            .iter()
+            .filter(|pack| pack.blob_type() == blob_type)
+            .collect();
+        let current_size = packs.iter().map(|pack| u64::from(pack.pack_size())).sum();
+        let blobs = packs
+            .iter()
+            .flat_map(|pack| {
+                pack.blobs.iter().map(|blob| CopyPackBlobs {
+                    pack_id: pack.id,
+                    locations: BlobLocations::from_blob_location(blob.location, blob.id),
+                })
+            })
+            .collect();
+        let pack_sizer = PackSizer::from_config(repo.config(), blob_type, current_size);
+        let copier = BlobCopier::new(
+            be.clone(),
+            be_new.clone(),
+            blob_type,
+            indexer.clone(),
+            pack_sizer,
+        )?;
+        let p = match blob_type {
+            BlobType::Tree => repo.progress_bytes("re-encrypting tree blobs..."),
+            BlobType::Data => repo.progress_bytes("re-encrypting data blobs..."),
+        };
+        // a started batch is always completed, as the state only records completely re-encrypted packs
+        copy_blobs(blobs, copier, p, &CancellationToken::new())?;
+    }
+    indexer.write().unwrap().finalize()?;
+
+    for id in rekey_be.ids() {
+        let index: IndexFile = be_new.get_file(&IndexId::from(id))?;
+        state.packs.extend(index.packs);
+    }
+    state.done.extend(selected.iter().map(|pack| pack.id));
+
+    Ok(remaining.len())
+}
+
+/// Files of one type, split by the key they are encrypted with
+struct KeyedFiles<F: RepoFile> {
+    /// The files encrypted with the old key
+    old: Vec<(F::Id, F)>,
+    /// The files encrypted with the new key
+    new: Vec<(F::Id, F)>,
+}
+
+impl<F: RepoFile> KeyedFiles<F> {
+    /// Read all files of the given type using the old or the new key
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to read from
+    /// * `be_old` - The backend using the old key
+    /// * `be_new` - The backend using the new key
+    /// * `prefix` - The prefix of the progress bar
+    ///
+    /// # Errors
+    ///
+    /// * If a file could be decrypted using neither the old nor the new key.
+    fn read<S: Open>(
+        repo: &Repository<S>,
+        be_old: &DecryptBackend<Key>,
+        be_new: &DecryptBackend<Key>,
+        prefix: &str,
+    ) -> RusticResult<Self> {
+        let mut files = Self {
+            old: Vec::new(),
+            new: Vec::new(),
+        };
+        let p = repo.progress_counter(prefix);
+        let ids = repo.be.list(F::TYPE)?;
+        p.set_length(ids.len() as u64);
+        for id in ids.into_iter().map(F::Id::from) {
+            match be_old.get_file::<F>(&id) {
+                Ok(file) => files.old.push((id, file)),
+                Err(err) => match be_new.get_file::<F>(&id) {
+                    Ok(file) => files.new.push((id, file)),
+                    Err(_) => return Err(err),
+                },
+            }
+            p.inc(1);
+        }
+        p.finish();
+        Ok(files)
+    }
+}
+
+/// Find the key file of the new key fitting the given password, or add one.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to re-encrypt
+/// * `state` - The state of the re-encryption
+/// * `keys` - The keys of the re-encryption
+/// * `pass` - The password to protect the new key with
+/// * `opts` - The options for the new key file
+///
+/// # Errors
+///
+/// * If the key files could not be listed or the new key file could not be written.
+fn new_key_file<S: Open>(
+    repo: &Repository<S>,
+    state: &RekeyState,
+    keys: RekeyKeys,
+    pass: &str,
+    opts: &KeyOptions,
+) -> RusticResult<KeyId> {
+    for id in repo.list::<KeyId>()? {
+        // only the new key can decrypt the old key saved in the state
+        if let Ok(key) = key_from_backend(&repo.be, &id, &pass)
+            && key.decrypt_data(&state.old_key).is_ok()
+        {
+            return Ok(id);
+        }
+    }
+    add_key_to_repo(repo, opts, pass, keys.new)
+}
+
+/// Finish the re-encryption of the repository.
+///
+/// This adds a new key file and writes the index, snapshots and config using the new key. Then all files
+/// which are encrypted using the old key are removed, including all old key files.
+///
+/// If finishing is interrupted, it can be run again using the same state: Files which have already
+/// been written using the new key are not written again. Once the config has been written, the repository
+/// can only be opened using the new password.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to re-encrypt
+/// * `state` - The state of the re-encryption
+/// * `pass` - The password to protect the new key with
+/// * `opts` - The options for the new key file
+///
+/// # Errors
+///
+/// * If the repository is in append-only mode.
+/// * If not all packs have been re-encrypted.
+/// * If re-encrypted packs are missing in the repository.
+/// * If files could not be read, written or removed.
+///
+/// # Returns
+///
+/// The id of the new key file.
+pub(crate) fn finish_rekey<S: Open>(
+    repo: &Repository<S>,
+    state: &RekeyState,
+    pass: &str,
+    opts: &KeyOptions,
+) -> RusticResult<KeyId> {
+    if repo.config().append_only == Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::Repository,
+            "Repository is in append-only mode and cannot be re-encrypted. Aborting.",
+        ));
+    }
+    let keys = state.keys(repo)?;
+    let be_old = new_dbe(repo, repo.be.clone(), keys.old)?;
+    let be_new = new_dbe(repo, repo.be.clone(), keys.new)?;
+
+    let index = KeyedFiles::<IndexFile>::read(repo, &be_old, &be_new, "reading index...")?;
+    let snaps = KeyedFiles::<SnapshotFile>::read(repo, &be_old, &be_new, "reading snapshots...")?;
+    let existing: BTreeSet<PackId> = repo.list()?.collect();
+
+    if !keys.switched {
+        let missing = index
+            .old
+            .iter()
+            .flat_map(|(_, index)| &index.packs)
+            .filter(|pack| !state.done.contains(&pack.id))
+            .count();
+        if missing > 0 {
+            return Err(RusticError::new(
+                ErrorKind::Repository,
+                "{count} packs have not been re-encrypted yet. Please run rekey again before finishing it.",
+            )
+            .attach_context("count", missing.to_string()));
+        }
+        if let Some(pack) = state.packs.iter().find(|pack| !existing.contains(&pack.id)) {
+            return Err(RusticError::new(
+                ErrorKind::Repository,
+                "The re-encrypted pack `{id}` is missing in the repository. Please start rekeying again.",
+            )
+            .attach_context("id", pack.id.to_string()));
+        }
+
+        // add the new key first, so the new password can be used as soon as the config is written
+        _ = new_key_file(repo, state, keys, pass, opts)?;
+
+        // write the index and snapshots using the new key, skipping files of an interrupted run
+        let indexed: BTreeSet<_> = index
+            .new
+            .iter()
+            .flat_map(|(_, index)| index.packs.iter().map(|pack| pack.id))
+            .collect();
+        let mut indexer = Indexer::new_unindexed(be_new.clone());
+        for pack in state
+            .packs
+            .iter()
+            .filter(|pack| !indexed.contains(&pack.id))
+        {
+            indexer.add(pack.clone())?;
+        }
+        indexer.finalize()?;
+
+        // re-encrypted snapshots keep the `original` of the old snapshot
+        let mut written: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (id, snap) in &snaps.new {
+            written
+                .entry((snap.original.unwrap_or(*id), snap.tree))
+                .or_default()
+                .push(*id);
+        }
+        let p = repo.progress_counter("re-encrypting snapshots...");
+        let mut old_snaps: Vec<_> = snaps
+            .old
+            .iter()
+            .map(|(id, snap)| {
+                let mut snap = snap.clone();
+                snap.id = *id;
+                _ = snap.original.get_or_insert(*id);
+                snap
+            })
+            .collect();
+        old_snaps.sort_unstable();
+        p.set_length(old_snaps.len() as u64);
+        let mut snap_ids = BTreeMap::new();
+        for mut snap in old_snaps {
+            let old_id = snap.id;
+            let key = (snap.original.unwrap_or(old_id), snap.tree);
+            let new_id = if let Some(new_id) = written.get_mut(&key).and_then(Vec::pop) {
+                new_id
+            } else {
+                snap.id = SnapshotId::default();
+                for parent in snap.parent.iter_mut().chain(&mut snap.parents) {
+                    if let Some(id) = snap_ids.get(&*parent) {
+                        *parent = *id;
+                    }
+                }
+                SnapshotId::from(be_new.save_file(&snap)?)
+            };
+            debug!("re-encrypted snapshot {old_id} as {new_id}");
+            _ = snap_ids.insert(old_id, new_id);
+            p.inc(1);
+        }
+        p.finish();
+
+        // switch the repository to the new key
+        let mut config = repo.config().clone();
+        config.rekey_in_progress = None;
+        save_config(repo, config, keys.new)?;
+    }
+
+    // remove all files encrypted with the old key; the old index files are removed last, so that
+    // an interrupted run still knows the old packs
+    let key_id = new_key_file(repo, state, keys, pass, opts)?;
+    let old_keys: Vec<KeyId> = repo.list()?.filter(|id| id != &key_id).collect();
+    for id in &old_keys {
+        repo.be.remove(FileType::Key, id, false)?;
+    }
+    let old_packs: Vec<_> = index
+        .old
+        .iter()
+        .flat_map(|(_, index)| index.packs.iter().chain(&index.packs_to_delete))
+        .filter(|pack| existing.contains(&pack.id))
+        .collect();
+    let p = repo.progress_counter("removing old packs...");
+    p.set_length(old_packs.len() as u64);
+    for pack in old_packs {
+        repo.be
+            .remove(FileType::Pack, &pack.id, pack.blob_type().is_cacheable())?;
+        p.inc(1);
+    }
+    p.finish();
+    let snap_ids: Vec<_> = snaps.old.iter().map(|(id, _)| *id).collect();
+    be_old.delete_list(
+        true,
+        snap_ids.iter(),
+        repo.progress_counter("removing old snapshots..."),
+    )?;
+    let index_ids: Vec<_> = index.old.iter().map(|(id, _)| *id).collect();
+    be_old.delete_list(
+        true,
+        index_ids.iter(),
+        repo.progress_counter("removing old index files..."),
+    )?;
+
+    Ok(key_id)
+}
+
+/// Re-encrypt the whole repository, saving the progress after each re-encrypted batch of packs.
+///
+/// This re-encrypts packs in batches limited by `opts` until all packs are re-encrypted and finishes the
+/// re-encryption. After each batch, `save_state` is called with the updated [`RekeyState`]; passing the last
+/// saved state again resumes the re-encryption, also if finishing it has been interrupted.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to re-encrypt
+/// * `state` - The state of the re-encryption, see [`start_rekey`]
+/// * `opts` - The options limiting the size of each batch
+/// * `pass` - The password to protect the new key with
+/// * `key_opts` - The options for the new key file
+/// * `save_state` - Called to persist the state after each batch
+///
+/// # Errors
+///
+/// * If the repository is in append-only mode.
+/// * If the state does not belong to the repository.
+/// * If a batch didn't re-encrypt any pack.
+/// * If blobs could not be re-encrypted.
+/// * If `save_state` fails.
+/// * If files could not be read, written or removed.
+///
+/// # Returns
+///
+/// The id of the new key file.
+pub(crate) fn reencrypt<S: Open>(
+    repo: &Repository<S>,
+    state: &mut RekeyState,
+    opts: &RekeyOptions,
+    pass: &str,
+    key_opts: &KeyOptions,
+    mut save_state: impl FnMut(&RekeyState) -> RusticResult<()>,
+) -> RusticResult<KeyId> {
+    // all packs have already been re-encrypted if finishing has been interrupted after switching the key
+    if !state.keys(repo)?.switched {
+        loop {
+            let done = state.packs_done();
+            let remaining = rekey(repo, state, opts)?;
+            save_state(state)?;
+            debug!(
+                "re-encrypted {} packs, {remaining} remaining",
+                state.packs_done()
+            );
+            if remaining == 0 {
+                break;
+            }
+            if state.packs_done() == done {
+                return Err(RusticError::new(
+                    ErrorKind::InvalidInput,
+                    "No pack has been re-encrypted, {remaining} packs remaining. Please increase the size limit.",
+                )
+                .attach_context("remaining", remaining.to_string()));
+            }
+        }
+    }
+    finish_rekey(repo, state, pass, key_opts)
+}
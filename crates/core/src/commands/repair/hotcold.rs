@@ -11,10 +11,12 @@ use crate::{
     repository::{Open, warm_up::warm_up_wait},
 };
 
-/// Repairs a hot/cold repository by copying missing files (except pack files) over from one to the other part.
+/// Repairs a hot/cold repository by copying missing files (except pack and lock files) over from one to the other part.
 pub(crate) fn repair_hotcold<S>(repo: &Repository<S>, dry_run: bool) -> RusticResult<()> {
     for file_type in ALL_FILE_TYPES {
-        if file_type != FileType::Pack {
+        if !matches!(file_type, FileType::Pack | FileType::Lock)
+            && repo.be.supports_file_type(file_type)
+        {
             correct_missing_files(repo, file_type, |_| true, dry_run)?;
         }
     }
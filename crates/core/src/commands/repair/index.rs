@@ -9,9 +9,10 @@ use crate::{
         FileType, ReadBackend, WriteBackend,
         decrypt::{DecryptReadBackend, DecryptWriteBackend},
     },
+    commands::rekey::check_no_rekey,
     error::{ErrorKind, RusticError, RusticResult},
     index::{GlobalIndex, binarysorted::IndexCollector, indexer::Indexer},
-    repofile::{IndexFile, IndexPack, PackHeader, PackHeaderRef, packfile::PackId},
+    repofile::{IndexFile, PackHeader, PackHeaderRef, packfile::PackId},
     repository::{Open, Repository},
 };
 
@@ -24,6 +25,13 @@ pub struct RepairIndexOptions {
     /// Read all data packs, i.e. completely re-create the index
     #[cfg_attr(feature = "clap", clap(long))]
     pub read_all: bool,
+
+    /// Only adopt orphaned packs, i.e. packs which are not contained in any index file.
+    ///
+    /// The headers of these packs are read and added to the index; existing index files are not checked or modified.
+    /// This makes data from partially-failed backups reachable again.
+    #[cfg_attr(feature = "clap", clap(long, conflicts_with = "read_all"))]
+    pub adopt_orphans: bool,
 }
 
 /// Runs the `repair index` command
@@ -48,6 +56,9 @@ pub(crate) fn repair_index<S: Open>(
             "Repairing the index is not allowed in append-only repositories. Please disable append-only mode first, if you know what you are doing. Aborting.",
         ));
     }
+    if !dry_run {
+        check_no_rekey(repo, "repair index")?;
+    }
 
     let be = repo.dbe();
     let mut checker = PackChecker::new(repo)?;
@@ -55,6 +66,10 @@ pub(crate) fn repair_index<S: Open>(
     let p = repo.progress_counter("reading index...");
     for index in be.stream_all::<IndexFile>(&p)? {
         let (index_id, index) = index?;
+        if opts.adopt_orphans {
+            checker.mark_indexed(&index);
+            continue;
+        }
         let (new_index, changed) = checker.check_pack(index, opts.read_all);
         match (changed, dry_run) {
             (true, true) => info!("would have modified index file {index_id}"),
@@ -70,6 +85,9 @@ pub(crate) fn repair_index<S: Open>(
     p.finish();
 
     let pack_read_header = checker.into_pack_to_read();
+    if opts.adopt_orphans {
+        info!("found {} orphaned packs", pack_read_header.len());
+    }
     repo.warm_up_wait(pack_read_header.iter().map(|(id, _, _)| *id))?;
 
     let indexer = Indexer::new(be.clone()).into_shared();
@@ -85,7 +103,7 @@ pub(crate) fn repair_index<S: Open>(
     })?);
     for (id, size_hint, packsize) in pack_read_header {
         debug!("reading pack {id}...");
-        match PackHeader::from_file(be, id, size_hint, packsize) {
+        match PackHeader::from_file(be, repo.cache(), id, size_hint, packsize) {
             Err(err) => {
                 warn!(
                     "error reading pack {id} (-> removing from index): {}",
@@ -93,11 +111,7 @@ pub(crate) fn repair_index<S: Open>(
                 );
             }
             Ok(header) => {
-                let pack = IndexPack {
-                    blobs: header.into_blobs(),
-                    id,
-                    ..Default::default()
-                };
+                let pack = header.into_index_pack(id, packsize);
                 if !dry_run {
                     // write pack file to index - without the delete mark
                     indexer.write().unwrap().add_with(pack, false)?;
@@ -170,6 +184,13 @@ impl PackChecker {
         (new_index, changed)
     }
 
+    /// Mark all packs contained in `indexfile` as indexed without checking them
+    fn mark_indexed(&mut self, indexfile: &IndexFile) {
+        for pack in indexfile.packs.iter().chain(&indexfile.packs_to_delete) {
+            _ = self.packs.remove(&pack.id);
+        }
+    }
+
     fn into_pack_to_read(mut self) -> Vec<(PackId, Option<u32>, u32)> {
         // add packs which are listed but not contained in the index
         self.packs_to_read
@@ -209,11 +230,8 @@ pub(crate) fn index_checked_from_collector<S: Open>(
         .into_iter()
         .map(|(id, size_hint, packsize)| {
             debug!("reading pack {id}...");
-            let pack = IndexPack {
-                id,
-                blobs: PackHeader::from_file(be, id, size_hint, packsize)?.into_blobs(),
-                ..Default::default()
-            };
+            let pack = PackHeader::from_file(be, repo.cache(), id, size_hint, packsize)?
+                .into_index_pack(id, packsize);
             p.inc(1);
             Ok(pack)
         })
@@ -1,22 +1,32 @@
 //! `repair snapshots` subcommand
 use derive_setters::Setters;
 use log::{info, warn};
+use serde_derive::Serialize;
 
 use std::{
     collections::{BTreeMap, BTreeSet},
     path::PathBuf,
+    sync::Arc,
 };
 
 use crate::{
-    backend::{decrypt::DecryptWriteBackend, node::NodeType},
+    backend::{
+        decrypt::{DecryptBackend, DecryptWriteBackend},
+        node::NodeType,
+    },
     blob::tree::modify::{
         ModifierAction, ModifierChange, NodeAction, TreeAction, TreeModifier, Visitor,
     },
-    blob::tree::{Tree, TreeId},
+    blob::{
+        BlobId, BlobType,
+        packer::{PackSizer, Packer},
+        tree::{Tree, TreeId},
+    },
+    crypto::aespoly1305::Key,
     error::{ErrorKind, RusticError, RusticResult},
     index::ReadGlobalIndex,
     repofile::{Node, SnapshotFile, StringList, snapshotfile::SnapshotId},
-    repository::{IndexedFull, Repository},
+    repository::{IndexedFull, IndexedFullStatus, Repository},
 };
 
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
@@ -46,6 +56,11 @@ pub struct RepairSnapshotsOptions {
         clap(long, value_name = "TAG[,TAG,..]", default_value = "repaired")
     )]
     pub tag: Vec<StringList>,
+
+    /// Repository to fetch missing data blobs from; found blobs are saved in the repaired repository
+    /// instead of marking the files as repaired
+    #[cfg_attr(feature = "clap", clap(skip))]
+    pub fallback_repo: Option<Arc<Repository<IndexedFullStatus>>>,
 }
 
 impl Default for RepairSnapshotsOptions {
@@ -54,27 +69,98 @@ impl Default for RepairSnapshotsOptions {
             delete: true,
             suffix: ".repaired".to_string(),
             tag: vec![StringList(BTreeSet::from(["repaired".to_string()]))],
+            fallback_repo: None,
         }
     }
 }
 
+/// Report of the `repair snapshots` command, i.e. what has been (or would have been in dry-run mode) changed
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct RepairReport {
+    /// Number of trees which have been repaired
+    pub trees_repaired: u64,
+    /// Number of files which have been truncated as their contents are missing
+    pub files_truncated: u64,
+    /// Number of missing data blobs which have been taken from the fallback repository
+    pub blobs_substituted: u64,
+    /// Snapshots which have been replaced by a repaired snapshot
+    pub snapshots_modified: Vec<SnapshotId>,
+    /// Snapshots which are removed; this is empty if the `delete` option is not set
+    pub snapshots_to_delete: Vec<SnapshotId>,
+    /// Uncompressed size of the repaired trees and substituted data blobs saved in the repository
+    pub bytes_repacked: u64,
+}
+
 pub(crate) struct RepairState<'a, I: ReadGlobalIndex> {
     opts: &'a RepairSnapshotsOptions,
     index: &'a I,
     changed: BTreeMap<TreeId, TreeId>,
     unchanged: BTreeSet<TreeId>,
     delete: Vec<SnapshotId>,
+    files_truncated: u64,
+    /// packer for data blobs taken from the fallback repository; `None` in dry-run mode
+    packer: Option<Packer<DecryptBackend<Key>>>,
+    /// data blobs taken from the fallback repository with their data length
+    substituted: BTreeMap<BlobId, u64>,
 }
 
 impl<'a, I: ReadGlobalIndex> RepairState<'a, I> {
-    fn new(opts: &'a RepairSnapshotsOptions, index: &'a I) -> Self {
+    fn new(
+        opts: &'a RepairSnapshotsOptions,
+        index: &'a I,
+        packer: Option<Packer<DecryptBackend<Key>>>,
+    ) -> Self {
         Self {
             opts,
             index,
             changed: BTreeMap::new(),
             unchanged: BTreeSet::new(),
             delete: Vec::new(),
+            files_truncated: 0,
+            packer,
+            substituted: BTreeMap::new(),
+        }
+    }
+
+    /// Try to take a missing data blob from the fallback repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the missing data blob
+    ///
+    /// # Returns
+    ///
+    /// The data length of the blob, if it is (or would be in dry-run mode) saved in the repository.
+    fn substitute(&mut self, id: &BlobId) -> Option<u64> {
+        if let Some(length) = self.substituted.get(id) {
+            return Some(*length);
         }
+        let fallback = self.opts.fallback_repo.as_ref()?;
+        let length = if let Some(packer) = &self.packer {
+            let res = fallback
+                .index()
+                .blob_from_backend(fallback.dbe(), BlobType::Data, id)
+                .and_then(|data| {
+                    let length = data.len() as u64;
+                    packer.add(data, *id)?;
+                    Ok(length)
+                });
+            match res {
+                Ok(length) => length,
+                Err(err) => {
+                    warn!(
+                        "blob {id}: could not be taken from fallback repository: {}",
+                        err.display_log()
+                    );
+                    return None;
+                }
+            }
+        } else {
+            u64::from(fallback.index().get_id(BlobType::Data, id)?.data_length())
+        };
+        _ = self.substituted.insert(*id, length);
+        Some(length)
     }
 }
 
@@ -102,21 +188,30 @@ impl<I: ReadGlobalIndex> Visitor for RepairState<'_, I> {
         match node.node_type {
             NodeType::File => {
                 let mut file_changed = false;
+                let mut substituted = 0;
                 let mut new_content = Vec::new();
                 let mut new_size = 0;
                 for blob in node.content.take().unwrap() {
-                    self.index.get_data(&blob).map_or_else(
-                        || {
-                            file_changed = true;
-                        },
-                        |ie| {
-                            new_content.push(blob);
-                            new_size += u64::from(ie.data_length());
-                        },
+                    if let Some(ie) = self.index.get_data(&blob) {
+                        new_content.push(blob);
+                        new_size += u64::from(ie.data_length());
+                    } else if let Some(length) = self.substitute(&BlobId::from(*blob)) {
+                        substituted += 1;
+                        new_content.push(blob);
+                        new_size += length;
+                    } else {
+                        file_changed = true;
+                    }
+                }
+                if substituted > 0 {
+                    info!(
+                        "file {}: took {substituted} missing blobs from fallback repository",
+                        node.name
                     );
                 }
                 if file_changed {
                     warn!("file {}: contents are missing", node.name);
+                    self.files_truncated += 1;
                     node.name += &self.opts.suffix;
                 } else if new_size != node.meta.size {
                     info!("file {}: corrected file size", node.name);
@@ -157,12 +252,16 @@ impl<I: ReadGlobalIndex> Visitor for RepairState<'_, I> {
 /// * `opts` - The repair options to use
 /// * `snapshots` - The snapshots to repair
 /// * `dry_run` - Whether to actually modify the repository or just print what would be done
+///
+/// # Returns
+///
+/// The [`RepairReport`] of the (would-be) changes
 pub(crate) fn repair_snapshots<S: IndexedFull>(
     repo: &Repository<S>,
     opts: &RepairSnapshotsOptions,
     snapshots: Vec<SnapshotFile>,
     dry_run: bool,
-) -> RusticResult<()> {
+) -> RusticResult<RepairReport> {
     let be = repo.dbe();
     let config_file = repo.config();
 
@@ -173,8 +272,24 @@ pub(crate) fn repair_snapshots<S: IndexedFull>(
         ));
     }
 
-    let mut state = RepairState::new(opts, repo.index());
     let modifier = TreeModifier::new(be, repo.index(), config_file, dry_run)?;
+    let packer = if opts.fallback_repo.is_some() && !dry_run {
+        let pack_sizer = PackSizer::from_config(
+            config_file,
+            BlobType::Data,
+            repo.index().total_size(BlobType::Data),
+        );
+        Some(Packer::new(
+            be.clone(),
+            BlobType::Data,
+            modifier.indexer().clone(),
+            pack_sizer,
+        )?)
+    } else {
+        None
+    };
+    let mut state = RepairState::new(opts, repo.index(), packer);
+    let mut snapshots_modified = Vec::new();
 
     for mut snap in snapshots {
         let snap_id = snap.id;
@@ -210,10 +325,15 @@ pub(crate) fn repair_snapshots<S: IndexedFull>(
                     let new_id = be.save_file(&snap)?;
                     info!("saved modified snapshot as {new_id}.");
                 }
+                snapshots_modified.push(snap_id);
                 state.delete.push(snap_id);
             }
         }
     }
+    if let Some(packer) = state.packer.take() {
+        _ = packer.finalize()?;
+    }
+    let bytes_repacked = modifier.saved_bytes() + state.substituted.values().sum::<u64>();
     modifier.finalize()?;
 
     if opts.delete {
@@ -228,5 +348,16 @@ pub(crate) fn repair_snapshots<S: IndexedFull>(
         }
     }
 
-    Ok(())
+    Ok(RepairReport {
+        trees_repaired: state.changed.len() as u64,
+        files_truncated: state.files_truncated,
+        blobs_substituted: state.substituted.len() as u64,
+        snapshots_modified,
+        snapshots_to_delete: if opts.delete {
+            state.delete
+        } else {
+            Vec::new()
+        },
+        bytes_repacked,
+    })
 }
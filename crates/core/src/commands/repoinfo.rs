@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+
+use jiff::Timestamp;
 use serde_derive::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
@@ -6,7 +9,10 @@ use crate::{
     blob::{BlobType, BlobTypeMap},
     error::RusticResult,
     index::IndexEntry,
-    repofile::indexfile::{IndexFile, IndexPack},
+    repofile::{
+        indexfile::{IndexBlob, IndexFile, IndexPack},
+        packfile::PackId,
+    },
     repository::{Open, Repository},
 };
 
@@ -93,6 +99,77 @@ impl PackInfo {
     }
 }
 
+#[skip_serializing_none]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+/// A pack file together with the index information about its blobs
+pub struct PackListEntry {
+    /// The pack id
+    pub id: PackId,
+    /// The blobs contained in the pack
+    pub blobs: Vec<IndexBlob>,
+    /// The pack size
+    pub size: u32,
+    /// The pack creation time or time when the pack was marked for deletion
+    pub time: Option<Timestamp>,
+    /// Whether the pack is marked for deletion
+    pub to_delete: bool,
+}
+
+impl PackListEntry {
+    /// Create a [`PackListEntry`] from an [`IndexPack`]
+    ///
+    /// # Arguments
+    ///
+    /// * `pack` - The [`IndexPack`] to use
+    /// * `to_delete` - Whether the pack is marked for deletion
+    fn from_index_pack(pack: IndexPack, to_delete: bool) -> Self {
+        Self {
+            size: pack.pack_size(),
+            id: pack.id,
+            blobs: pack.blobs,
+            time: pack.time,
+            to_delete,
+        }
+    }
+}
+
+/// Lists all packs contained in the index files of the given repository.
+///
+/// Packs contained in multiple index files are only listed once; if a pack is contained both
+/// normally and marked for deletion, it is listed as not marked for deletion.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to list the packs from.
+///
+/// # Errors
+///
+/// * If the index files could not be read.
+///
+/// # Returns
+///
+/// The packs, ordered by their id
+pub(crate) fn list_packs<S: Open>(repo: &Repository<S>) -> RusticResult<Vec<PackListEntry>> {
+    let mut packs = BTreeMap::new();
+
+    let p = repo.progress_counter("reading index...");
+    for index in repo.dbe().stream_all::<IndexFile>(&p)? {
+        let index = index?.1;
+        for pack in index.packs {
+            _ = packs.insert(pack.id, PackListEntry::from_index_pack(pack, false));
+        }
+        for pack in index.packs_to_delete {
+            _ = packs
+                .entry(pack.id)
+                .or_insert_with(|| PackListEntry::from_index_pack(pack, true));
+        }
+    }
+    p.finish();
+
+    Ok(packs.into_values().collect())
+}
+
 /// Collects the index infos from the given repository.
 ///
 /// # Type Parameters
@@ -188,6 +265,9 @@ pub struct RepoFileInfo {
 pub(crate) fn collect_file_info(be: &impl ReadBackend) -> RusticResult<Vec<RepoFileInfo>> {
     let mut files = Vec::with_capacity(ALL_FILE_TYPES.len());
     for tpe in ALL_FILE_TYPES {
+        if !be.supports_file_type(tpe) {
+            continue;
+        }
         let list = be.list_with_size(tpe)?;
         let count = list.len() as u64;
         let size = list.iter().map(|f| u64::from(f.1)).sum();
@@ -1,14 +1,30 @@
 //! `restore` subcommand
 
+use bytesize::ByteSize;
 use derive_setters::Setters;
 use jiff::Timestamp;
 use log::{debug, error, info, trace, warn};
+use serde_derive::Serialize;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
 use smallvec::SmallVec;
 
-use std::{cmp::Ordering, collections::BTreeMap, path::PathBuf, sync::Mutex};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fmt,
+    fs::File,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
 use itertools::Itertools;
-use rayon::ThreadPoolBuilder;
+use rayon::{
+    ThreadPool, ThreadPoolBuilder,
+    prelude::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator},
+};
 use walkdir::{DirEntry, WalkDir};
 
 use crate::{
@@ -18,15 +34,21 @@ use crate::{
         local_destination::LocalDestination,
         node::{Node, NodeType},
     },
-    blob::{BlobLocation, BlobLocations},
+    blob::{BlobLocation, BlobLocations, DataId},
+    crypto::hasher::{hash, hash_reader},
     error::{ErrorKind, RusticError, RusticResult},
+    id::Id,
+    index::IndexEntry,
     repofile::packfile::PackId,
     repository::{IndexedFull, IndexedTree, Open, Repository},
+    util::RateLimiter,
 };
 
 pub(crate) mod constants {
     /// The maximum number of reader threads to use for restoring.
     pub(crate) const MAX_READER_THREADS_NUM: usize = 20;
+    /// The number of nodes which are read ahead to verify existing files in parallel.
+    pub(crate) const VERIFY_BATCH_SIZE: usize = 1024;
 }
 
 type Filenames = Vec<PathBuf>;
@@ -58,6 +80,88 @@ pub struct RestoreOptions {
     /// Always read and verify existing files (don't trust correct modification time and file size)
     #[cfg_attr(feature = "clap", clap(long))]
     pub verify_existing: bool,
+
+    /// Number of threads used to read and verify existing files [default: verify sequentially]
+    #[cfg_attr(feature = "clap", clap(long, value_name = "N"))]
+    pub verify_threads: Option<usize>,
+
+    /// Restore files as sparse files, i.e. don't write blobs which only contain zeros.
+    ///
+    /// # Note
+    ///
+    /// * Files which have been marked as sparse during backup are always restored as sparse files.
+    /// * Only files which are newly created (or completely rewritten) are restored as sparse files.
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub sparse: bool,
+
+    /// How to handle errors when restoring file contents: "abort", "skip" or "retry:<N>" (retry N times, then skip)
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, value_name = "POLICY", default_value = "abort")
+    )]
+    pub on_error: RestoreErrorPolicy,
+
+    /// After restoring, re-read the restored file contents and verify them against the blob hashes
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub verify_after: bool,
+
+    /// Generate a manifest of all restored entries including content hashes, see [`RestoreReport::manifest`]
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub manifest: bool,
+
+    /// Limit the data downloaded from the repository to the given size per second (e.g. '10 MiB')
+    #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    pub max_download_rate: Option<ByteSize>,
+
+    /// Limit the data written to the destination to the given size per second (e.g. '10 MiB')
+    #[cfg_attr(feature = "clap", clap(long, value_name = "SIZE"))]
+    pub max_write_rate: Option<ByteSize>,
+}
+
+/// Policy how to handle errors when restoring file contents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, SerializeDisplay, DeserializeFromStr)]
+#[non_exhaustive]
+pub enum RestoreErrorPolicy {
+    /// Abort the restore on the first error
+    #[default]
+    Abort,
+    /// Skip files which could not be restored and report them
+    Skip,
+    /// Retry reading the contents the given number of times, then skip the affected files
+    Retry(u32),
+}
+
+impl FromStr for RestoreErrorPolicy {
+    type Err = Box<RusticError>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "abort" => Self::Abort,
+            "skip" => Self::Skip,
+            _ => {
+                let retries = s
+                    .strip_prefix("retry:")
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| {
+                        RusticError::new(
+                            ErrorKind::InvalidInput,
+                            "Invalid error policy `{policy}`. Please use `abort`, `skip` or `retry:<N>`, e.g. `retry:3`.",
+                        )
+                        .attach_context("policy", s)
+                    })?;
+                Self::Retry(retries)
+            }
+        })
+    }
+}
+
+impl fmt::Display for RestoreErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Abort => write!(f, "abort"),
+            Self::Skip => write!(f, "skip"),
+            Self::Retry(n) => write!(f, "retry:{n}"),
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -86,6 +190,163 @@ pub struct RestoreStats {
     pub dirs: FileDirStats,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+/// The action a restore would perform on a destination entry
+pub enum RestoreAction {
+    /// The entry does not exist in the destination and will be created
+    Create,
+    /// The entry exists in the destination and will be modified
+    Modify,
+    /// The entry exists in the destination and has been verified to be identical
+    Verify,
+    /// The entry only exists in the destination and will be deleted
+    Delete,
+    /// The entry exists in the destination and is unchanged (determined by size and modification time).
+    ///
+    /// This is only used in [`RestoreManifestEntry`]; unchanged entries are not listed in a [`RestoreDiff`].
+    Unchanged,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+/// A single entry of a [`RestoreDiff`]
+pub struct RestoreDiffEntry {
+    /// The path of the entry, relative to the restore destination
+    pub path: PathBuf,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+    /// The action which would be performed
+    pub action: RestoreAction,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[non_exhaustive]
+/// Machine-readable list of changes a restore would apply to the destination.
+///
+/// This is only filled when preparing the restore in dry-run mode or if [`RestoreOptions::manifest`] is set.
+/// Files and dirs which are unchanged (determined by size and modification time) are not listed.
+pub struct RestoreDiff {
+    /// The entries which would be changed, sorted by path
+    pub entries: Vec<RestoreDiffEntry>,
+}
+
+impl RestoreDiff {
+    fn push(&mut self, path: PathBuf, is_dir: bool, action: RestoreAction) {
+        self.entries.push(RestoreDiffEntry {
+            path,
+            is_dir,
+            action,
+        });
+    }
+
+    /// Iterate over all entries which have the given action
+    pub fn with_action(&self, action: RestoreAction) -> impl Iterator<Item = &RestoreDiffEntry> {
+        self.entries.iter().filter(move |e| e.action == action)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+/// A file which could not be restored
+pub struct RestoreFailure {
+    /// The path of the file, relative to the restore destination
+    pub path: PathBuf,
+    /// The error which occurred
+    pub error: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[non_exhaustive]
+/// Report about a finished restore
+pub struct RestoreReport {
+    /// Files whose contents could not be (completely) restored
+    ///
+    /// This is only filled if errors are not aborting the restore, see [`RestoreOptions::on_error`].
+    pub failed_files: Vec<RestoreFailure>,
+    /// The result of verifying the restored files
+    ///
+    /// This is only filled if [`RestoreOptions::verify_after`] is set.
+    pub verification: Option<RestoreVerification>,
+    /// All restored entries, sorted by path
+    ///
+    /// This is only filled if [`RestoreOptions::manifest`] is set.
+    pub manifest: Vec<RestoreManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+/// A single restored entry, see [`RestoreReport::manifest`]
+pub struct RestoreManifestEntry {
+    /// The path of the entry, relative to the restore destination
+    pub path: PathBuf,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+    /// The size of the entry
+    pub size: u64,
+    /// Unix mode of the entry
+    pub mode: Option<u32>,
+    /// Modification time of the entry
+    pub mtime: Option<Timestamp>,
+    /// SHA-256 hash of the restored file contents; `None` for non-files and files which could not be restored
+    pub content_hash: Option<Id>,
+    /// The action taken; special files like symlinks are always (re-)created
+    pub action: RestoreAction,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[non_exhaustive]
+/// Summary of the verification of restored file contents, see [`RestoreOptions::verify_after`]
+pub struct RestoreVerification {
+    /// Number of verified files
+    pub files: u64,
+    /// Number of verified blobs
+    pub blobs: u64,
+    /// Number of verified bytes
+    pub bytes: u64,
+    /// Files whose restored contents don't match the snapshot, sorted by path
+    pub mismatched_files: Vec<PathBuf>,
+}
+
+impl RestoreVerification {
+    /// Returns `true` if all verified files match the snapshot
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.mismatched_files.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+#[non_exhaustive]
+/// Report about a verify-only restore, see [`Repository::verify_restore`]
+pub struct VerifyRestoreReport {
+    /// Number of verified files
+    pub files: u64,
+    /// Number of verified blobs; blobs contained in multiple files are only verified once
+    pub blobs: u64,
+    /// Number of verified bytes, i.e. the total size of the file contents a restore would write
+    pub bytes: u64,
+    /// Number of partial pack reads
+    pub pack_reads: u64,
+    /// Number of bytes read from the backend
+    pub read_bytes: u64,
+    /// The time needed to read the trees and to read and verify all file contents
+    pub duration: Duration,
+    /// Files whose contents could not be read or don't match the snapshot
+    ///
+    /// This is only filled if errors are not aborting the verification, see [`RestoreErrorPolicy`].
+    pub failed_files: Vec<RestoreFailure>,
+}
+
+impl VerifyRestoreReport {
+    /// Returns `true` if all file contents could be read and verified
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.failed_files.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct HardlinkKey {
     device_id: u64,
@@ -110,28 +371,303 @@ struct HardlinkKey {
 /// # Errors
 ///
 /// * If the restore failed.
+///
+/// # Returns
+///
+/// The [`RestoreReport`] listing the files which could not be restored.
 pub(crate) fn restore_repository<S: IndexedTree>(
     file_infos: RestorePlan,
     repo: &Repository<S>,
     opts: RestoreOptions,
     node_streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
     dest: &LocalDestination,
-) -> RusticResult<()> {
+) -> RusticResult<RestoreReport> {
     repo.warm_up_wait(file_infos.to_packs().into_iter())?;
-    restore_contents(
+    let to_verify = opts.verify_after.then(|| file_infos.blobs_by_file());
+    let actions: BTreeMap<_, _> = file_infos
+        .diff
+        .entries
+        .iter()
+        .map(|entry| (entry.path.clone(), entry.action))
+        .collect();
+    let mut report = restore_contents(
         repo,
         dest,
         &file_infos.names,
         file_infos.file_lengths,
+        &file_infos.sparse,
         file_infos.r,
         file_infos.restore_size,
+        &opts,
     )?;
 
+    if let Some(to_verify) = to_verify {
+        let verification = verify_contents(repo, dest, &file_infos.names, to_verify);
+        if !verification.is_ok() {
+            warn!(
+                "{} restored files do not match the snapshot!",
+                verification.mismatched_files.len()
+            );
+        }
+        report.verification = Some(verification);
+    }
+
+    let mut manifest = Vec::new();
+    // indices of the files within the manifest
+    let mut files = Vec::new();
+    let node_streamer = node_streamer.inspect(|item| {
+        if opts.manifest
+            && let Ok((path, node)) = item
+        {
+            if node.is_file() {
+                files.push(manifest.len());
+            }
+            let action = actions.get(path).copied().unwrap_or(match node.node_type {
+                NodeType::File | NodeType::Dir => RestoreAction::Unchanged,
+                _ => RestoreAction::Create,
+            });
+            manifest.push(RestoreManifestEntry {
+                path: path.clone(),
+                is_dir: node.is_dir(),
+                size: node.meta.size,
+                mode: node.meta.mode,
+                mtime: node.meta.mtime,
+                content_hash: None,
+                action,
+            });
+        }
+    });
+
     let p = repo.progress_spinner("setting metadata...");
     restore_metadata(node_streamer, &file_infos.hardlink_candidates, opts, dest)?;
     p.finish();
 
-    Ok(())
+    if opts.manifest {
+        let failed: BTreeSet<_> = report
+            .failed_files
+            .iter()
+            .map(|failure| failure.path.clone())
+            .collect();
+        let p = repo.progress_bytes("hashing restored files...");
+        p.set_length(files.iter().map(|&idx| manifest[idx].size).sum());
+        for idx in files {
+            let entry = &mut manifest[idx];
+            if !failed.contains(&entry.path) {
+                entry.content_hash = File::open(dest.path(&entry.path))
+                    .and_then(hash_reader)
+                    .inspect_err(|err| warn!("error hashing {}: {err}", entry.path.display()))
+                    .ok();
+            }
+            p.inc(entry.size);
+        }
+        p.finish();
+        report.manifest = manifest;
+    }
+
+    Ok(report)
+}
+
+/// Re-read the restored files from `dest` and check the contained blobs against their ids
+///
+/// # Arguments
+///
+/// * `repo` - The repository used for the restore
+/// * `dest` - The destination the files have been restored to
+/// * `filenames` - The names of the restored files
+/// * `to_verify` - The blobs to verify, as `(start, length, id)` per file index
+///
+/// # Returns
+///
+/// The [`RestoreVerification`] summary
+fn verify_contents<S: Open>(
+    repo: &Repository<S>,
+    dest: &LocalDestination,
+    filenames: &Filenames,
+    to_verify: BTreeMap<usize, Vec<(u64, u64, DataId)>>,
+) -> RestoreVerification {
+    let p = repo.progress_bytes("verifying restored files...");
+    p.set_length(
+        to_verify
+            .values()
+            .flatten()
+            .map(|(_, length, _)| length)
+            .sum(),
+    );
+
+    let mut verification = RestoreVerification::default();
+    for (file_idx, blobs) in to_verify {
+        let path = &filenames[file_idx];
+        let mut matches = true;
+        for (start, length, id) in blobs {
+            match dest.read_at(path, start, length) {
+                Ok(data) => matches &= DataId::from(hash(&data)) == id,
+                Err(err) => {
+                    warn!("error reading {}: {err}", path.display());
+                    matches = false;
+                }
+            }
+            verification.blobs += 1;
+            verification.bytes += length;
+            p.inc(length);
+        }
+        verification.files += 1;
+        if !matches {
+            debug!("file {} does not match the snapshot", path.display());
+            verification.mismatched_files.push(path.clone());
+        }
+    }
+    p.finish();
+
+    verification.mismatched_files.sort_unstable();
+    verification
+}
+
+/// Perform all backend reads of a restore and verify the read contents without writing anything.
+///
+/// This is a disaster-recovery drill: all needed pack contents are read, decrypted and verified against
+/// their blob ids, but no destination is needed and the read data is discarded.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `node_streamer` - The nodes which would be restored
+/// * `on_error` - How to handle errors while reading or verifying contents
+///
+/// # Errors
+///
+/// * If a tree or index entry could not be read.
+/// * If the thread pool could not be created.
+/// * If reading or verifying contents failed and `on_error` is [`RestoreErrorPolicy::Abort`].
+///
+/// # Returns
+///
+/// The [`VerifyRestoreReport`] with timing and integrity results
+pub(crate) fn verify_restore<S: IndexedFull>(
+    repo: &Repository<S>,
+    node_streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+    on_error: RestoreErrorPolicy,
+) -> RusticResult<VerifyRestoreReport> {
+    let start = Instant::now();
+    let mut report = VerifyRestoreReport::default();
+
+    let p = repo.progress_spinner("collecting file information...");
+    let mut plan = RestorePlan::default();
+    for item in node_streamer {
+        let (path, node) = item?;
+        if node.is_file() {
+            plan.add_file_range(repo, &node, path, 0..node.meta.size)?;
+            report.files += 1;
+        }
+    }
+    p.finish();
+
+    repo.warm_up_wait(plan.to_packs().into_iter())?;
+
+    let packs: Vec<_> = plan
+        .r
+        .into_iter()
+        .map(|((pack_id, bl), fls)| {
+            // all locations of the same blob share the blob id
+            let blob_id = fls[0].blob_id;
+            let file_idxs: SmallVec<[usize; 1]> = fls.iter().map(|fl| fl.file_idx).collect();
+            (
+                pack_id,
+                BlobLocations::from_blob_location(bl, (blob_id, file_idxs)),
+            )
+        })
+        // optimize reading from backend by reading many blobs in a row
+        .coalesce(|(pack1, bl1), (pack2, bl2)| {
+            if pack1 == pack2 {
+                bl1.coalesce(bl2)
+                    .map(|bl| (pack1, bl))
+                    .map_err(|(bl1, bl2)| ((pack1, bl1), (pack2, bl2)))
+            } else {
+                Err(((pack1, bl1), (pack2, bl2)))
+            }
+        })
+        .collect();
+
+    let be = repo.dbe();
+    let errors = ErrorCollector::new(on_error);
+    let p = repo.progress_bytes("verifying file contents...");
+    p.set_length(plan.restore_size);
+
+    let pool = reader_pool(constants::MAX_READER_THREADS_NUM)?;
+    let (pack_reads, read_bytes, blobs) = pool.install(|| {
+        packs
+            .into_par_iter()
+            .map(|(pack_id, locations)| {
+                if errors.aborted() {
+                    return (0, 0, 0);
+                }
+                let read_data = errors.retry(|| {
+                    be.read_partial(
+                        FileType::Pack,
+                        &pack_id,
+                        false,
+                        locations.offset,
+                        locations.length,
+                    )
+                });
+                let read_data = match read_data {
+                    Ok(data) => data,
+                    Err(err) => {
+                        let file_idxs = locations
+                            .blobs
+                            .iter()
+                            .flat_map(|(_, (_, file_idxs))| file_idxs.iter().copied());
+                        errors.add(file_idxs, err);
+                        return (0, 0, 0);
+                    }
+                };
+
+                let mut blobs = 0;
+                for (bl, (blob_id, file_idxs)) in locations.blobs {
+                    let start = usize::try_from(bl.offset - locations.offset)
+                        .expect("convert from u32 to usize should not fail!");
+                    let end = usize::try_from(bl.offset + bl.length - locations.offset)
+                        .expect("convert from u32 to usize should not fail!");
+                    let data = &read_data[start..end];
+                    match be.read_encrypted_from_partial(data, bl.uncompressed_length) {
+                        Ok(data) if DataId::from(hash(&data)) == blob_id => blobs += 1,
+                        Ok(_) => {
+                            let err = RusticError::new(
+                                ErrorKind::Verification,
+                                "The contents of blob `{blob_id}` in pack `{pack_id}` do not match the blob id. The pack may be damaged.",
+                            )
+                            .attach_context("blob_id", blob_id.to_string())
+                            .attach_context("pack_id", pack_id.to_string());
+                            errors.add(file_idxs.iter().copied(), err);
+                        }
+                        Err(err) => errors.add(file_idxs.iter().copied(), err),
+                    }
+                    let size: u64 = bl.data_length().into();
+                    p.inc(size * file_idxs.len() as u64);
+                }
+                (1, u64::from(locations.length), blobs)
+            })
+            .reduce(|| (0, 0, 0), |a, b| (a.0 + b.0, a.1 + b.1, a.2 + b.2))
+    });
+    p.finish();
+
+    report.failed_files = errors.finish(&plan.names)?.failed_files;
+    report.blobs = blobs;
+    report.bytes = plan.restore_size;
+    report.pack_reads = pack_reads;
+    report.read_bytes = read_bytes;
+    report.duration = start.elapsed();
+
+    info!(
+        "verified {} files ({} bytes) in {:.1?}: read {} bytes from {} pack reads.",
+        report.files, report.bytes, report.duration, report.read_bytes, report.pack_reads
+    );
+    if !report.is_ok() {
+        warn!(
+            "{} files could not be read or do not match the snapshot!",
+            report.failed_files.len()
+        );
+    }
+    Ok(report)
 }
 
 /// Collect restore information, scan existing files, create needed dirs and remove superfluous files
@@ -147,6 +683,7 @@ pub(crate) fn restore_repository<S: IndexedTree>(
 /// * `node_streamer` - The node streamer to use.
 /// * `dest` - The destination to restore to.
 /// * `dry_run` - If true, don't actually restore anything, but only print out what would be done.
+///   The changes which would be done are additionally collected in [`RestorePlan::diff`].
 ///
 /// # Errors
 ///
@@ -156,15 +693,30 @@ pub(crate) fn restore_repository<S: IndexedTree>(
 pub(crate) fn collect_and_prepare<S: IndexedFull>(
     repo: &Repository<S>,
     opts: RestoreOptions,
-    mut node_streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+    node_streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
     dest: &LocalDestination,
     dry_run: bool,
 ) -> RusticResult<RestorePlan> {
     let p = repo.progress_spinner("collecting file information...");
     let dest_path = dest.path("");
 
+    let mut node_streamer = VerifyAhead {
+        nodes: node_streamer,
+        pool: opts
+            .verify_threads
+            .filter(|threads| *threads > 1)
+            .map(reader_pool)
+            .transpose()?,
+        repo,
+        dest,
+        ignore_mtime: opts.verify_existing,
+        hardlinks: BTreeSet::new(),
+        buffer: VecDeque::new(),
+    };
+
     let mut stats = RestoreStats::default();
     let mut restore_infos = RestorePlan::default();
+    let mut diff = RestoreDiff::default();
     let mut additional_existing = false;
 
     let next_entry = |walker: &mut walkdir::IntoIter| -> Option<DirEntry> {
@@ -197,12 +749,22 @@ pub(crate) fn collect_and_prepare<S: IndexedFull>(
                         "would have removed the additional dir: {}",
                         entry.path().display()
                     );
+                    let path = entry
+                        .path()
+                        .strip_prefix(&dest_path)
+                        .unwrap_or_else(|_| entry.path());
+                    diff.push(path.to_path_buf(), true, RestoreAction::Delete);
                 }
                 (true, true, false) => {
                     info!(
                         "would have removed the additional file: {}",
                         entry.path().display()
                     );
+                    let path = entry
+                        .path()
+                        .strip_prefix(&dest_path)
+                        .unwrap_or_else(|_| entry.path());
+                    diff.push(path.to_path_buf(), false, RestoreAction::Delete);
                 }
                 (true, false, true) => {
                     if let Err(err) = dest.remove_dir(entry.path()) {
@@ -226,7 +788,13 @@ pub(crate) fn collect_and_prepare<S: IndexedFull>(
             Ok(next_entry(walker))
         };
 
-    let mut process_node = |path: &PathBuf, node: &Node, exists: bool| -> RusticResult<_> {
+    // the changes are also needed to generate the manifest of a restore
+    let collect_diff = dry_run || opts.manifest;
+    let mut process_node = |path: &PathBuf,
+                            node: &Node,
+                            check: Option<FileCheck>,
+                            exists: bool|
+     -> RusticResult<_> {
         match node.node_type {
             NodeType::Dir => {
                 if exists {
@@ -235,6 +803,11 @@ pub(crate) fn collect_and_prepare<S: IndexedFull>(
                 } else {
                     stats.dirs.restore += 1;
                     debug!("to restore: {}", path.display());
+                    if collect_diff {
+                        restore_infos
+                            .diff
+                            .push(path.clone(), true, RestoreAction::Create);
+                    }
                     if !dry_run {
                         dest.create_dir(path)
                             .map_err(|err| {
@@ -255,13 +828,44 @@ pub(crate) fn collect_and_prepare<S: IndexedFull>(
                             trace!("Adding hardlink candidate {}", path.display());
                             _ = entry.insert(path.clone());
                         }
-                        std::collections::btree_map::Entry::Occupied(_) => return Ok(()), // this is a hardlink to an existing candidate, will be processed later while setting metadata
+                        std::collections::btree_map::Entry::Occupied(entry) => {
+                            // this is a hardlink to an existing candidate, will be processed later while setting metadata
+                            if exists && dest.is_hard_link(entry.get(), path) {
+                                stats.files.unchanged += 1;
+                                trace!("identical hardlink: {}", path.display());
+                            } else if exists {
+                                stats.files.modify += 1;
+                                debug!("to re-link: {}", path.display());
+                                if collect_diff {
+                                    restore_infos.diff.push(
+                                        path.clone(),
+                                        false,
+                                        RestoreAction::Modify,
+                                    );
+                                }
+                            } else {
+                                stats.files.restore += 1;
+                                debug!("to link: {}", path.display());
+                                if collect_diff {
+                                    restore_infos.diff.push(
+                                        path.clone(),
+                                        false,
+                                        RestoreAction::Create,
+                                    );
+                                }
+                            }
+                            return Ok(());
+                        }
                     }
                 }
-                // collect blobs needed for restoring
+                // collect blobs needed for restoring; the existing file may already be checked in advance
+                let check = match check {
+                    Some(check) => check,
+                    None => check_file(dest, node, path, repo, opts.verify_existing)?,
+                };
                 match (
                     exists,
-                    restore_infos.add_file(dest, node, path.clone(), repo, opts.verify_existing)?,
+                    restore_infos.add_file(path.clone(), check, opts.sparse || node.meta.sparse),
                 ) {
                     // Note that exists = false and Existing or Verified can happen if the file is changed between scanning the dir
                     // and calling add_file. So we don't care about exists but trust add_file here.
@@ -272,16 +876,31 @@ pub(crate) fn collect_and_prepare<S: IndexedFull>(
                     (_, AddFileResult::Verified) => {
                         stats.files.verified += 1;
                         trace!("verified identical file: {}", path.display());
+                        if collect_diff {
+                            restore_infos
+                                .diff
+                                .push(path.clone(), false, RestoreAction::Verify);
+                        }
                     }
                     // TODO: The differentiation between files to modify and files to create could be done only by add_file
                     // Currently, add_file never returns Modify, but always New, so we differentiate based on exists
                     (true, AddFileResult::Modify) => {
                         stats.files.modify += 1;
                         debug!("to modify: {}", path.display());
+                        if collect_diff {
+                            restore_infos
+                                .diff
+                                .push(path.clone(), false, RestoreAction::Modify);
+                        }
                     }
                     (false, AddFileResult::Modify) => {
                         stats.files.restore += 1;
                         debug!("to restore: {}", path.display());
+                        if collect_diff {
+                            restore_infos
+                                .diff
+                                .push(path.clone(), false, RestoreAction::Create);
+                        }
                     }
                 }
             }
@@ -300,13 +919,13 @@ pub(crate) fn collect_and_prepare<S: IndexedFull>(
     let mut next_node = node_streamer.next().transpose()?;
 
     loop {
-        match (&next_dst, &next_node) {
+        match (&next_dst, &mut next_node) {
             (None, None) => break,
 
             (Some(destination), None) => {
                 next_dst = process_existing(&mut walker, destination)?;
             }
-            (Some(destination), Some((path, node))) => {
+            (Some(destination), Some((path, node, check))) => {
                 match destination.path().cmp(&dest.path(path)) {
                     Ordering::Less => {
                         next_dst = process_existing(&mut walker, destination)?;
@@ -322,17 +941,17 @@ pub(crate) fn collect_and_prepare<S: IndexedFull>(
                         } else {
                             next_dst = next_entry(&mut walker);
                         }
-                        process_node(path, node, true)?;
+                        process_node(path, node, check.take(), true)?;
                         next_node = node_streamer.next().transpose()?;
                     }
                     Ordering::Greater => {
-                        process_node(path, node, false)?;
+                        process_node(path, node, check.take(), false)?;
                         next_node = node_streamer.next().transpose()?;
                     }
                 }
             }
-            (None, Some((path, node))) => {
-                process_node(path, node, false)?;
+            (None, Some((path, node, check))) => {
+                process_node(path, node, check.take(), false)?;
                 next_node = node_streamer.next().transpose()?;
             }
         }
@@ -343,6 +962,11 @@ pub(crate) fn collect_and_prepare<S: IndexedFull>(
     }
 
     restore_infos.stats = stats;
+    // deletions are collected separately as `process_existing` and `process_node` are both borrowing mutably;
+    // they come first so that a type change lists the deletion before the creation.
+    diff.entries.append(&mut restore_infos.diff.entries);
+    diff.entries.sort_by(|e1, e2| e1.path.cmp(&e2.path));
+    restore_infos.diff = diff;
     p.finish();
 
     Ok(restore_infos)
@@ -371,6 +995,7 @@ fn restore_metadata(
         if let Some(key) = hardlink_key(&node)
             && let Some(canonical) = hardlink_candidates.get(&key)
             && canonical != &path
+            && !dest.is_hard_link(canonical, &path)
         {
             debug!(
                 "restoring hardlink {} -> {}",
@@ -494,6 +1119,112 @@ impl PackInfo {
     }
 }
 
+/// Collects errors which occur while restoring file contents according to a [`RestoreErrorPolicy`]
+struct ErrorCollector {
+    /// The error policy to use
+    policy: RestoreErrorPolicy,
+    /// The error which aborts the restore
+    abort: Mutex<Option<Box<RusticError>>>,
+    /// The first error which occurred for a file, indexed by file index
+    failed: Mutex<BTreeMap<usize, String>>,
+}
+
+impl ErrorCollector {
+    fn new(policy: RestoreErrorPolicy) -> Self {
+        Self {
+            policy,
+            abort: Mutex::new(None),
+            failed: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Whether the restore has been aborted
+    fn aborted(&self) -> bool {
+        self.abort.lock().unwrap().is_some()
+    }
+
+    /// Run `f`, retrying it if the policy allows it
+    fn retry<T>(&self, mut f: impl FnMut() -> RusticResult<T>) -> RusticResult<T> {
+        let retries = match self.policy {
+            RestoreErrorPolicy::Retry(n) => n,
+            _ => 0,
+        };
+        let mut res = f();
+        for i in 1..=retries {
+            match res {
+                Ok(_) => break,
+                Err(err) => {
+                    warn!("retry {i}/{retries} after error: {}", err.display_log());
+                    res = f();
+                }
+            }
+        }
+        res
+    }
+
+    /// Add an error which occurred for the files with the given indices
+    fn add(&self, file_idxs: impl IntoIterator<Item = usize>, err: Box<RusticError>) {
+        if self.policy == RestoreErrorPolicy::Abort {
+            _ = self.abort.lock().unwrap().get_or_insert(err);
+            return;
+        }
+        let message = err.display_log();
+        error!("restore: {message}");
+        let mut failed = self.failed.lock().unwrap();
+        for file_idx in file_idxs {
+            _ = failed.entry(file_idx).or_insert_with(|| message.clone());
+        }
+    }
+
+    /// Finish collecting errors and create the [`RestoreReport`]
+    ///
+    /// # Errors
+    ///
+    /// * If the restore has been aborted
+    fn finish(self, filenames: &Filenames) -> RusticResult<RestoreReport> {
+        if let Some(err) = self.abort.into_inner().unwrap() {
+            return Err(err);
+        }
+        let failed_files = self
+            .failed
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|(file_idx, error)| RestoreFailure {
+                path: filenames[file_idx].clone(),
+                error,
+            })
+            .collect();
+        Ok(RestoreReport {
+            failed_files,
+            ..Default::default()
+        })
+    }
+}
+
+/// Create a thread pool used for reading file contents, either from the backend or from the destination
+///
+/// # Arguments
+///
+/// * `threads` - The number of threads to use
+///
+/// # Errors
+///
+/// * If the thread pool could not be created.
+fn reader_pool(threads: usize) -> RusticResult<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::Internal,
+                "Failed to create the thread pool with `{num_threads}` threads. Please try again.",
+                err,
+            )
+            .attach_context("num_threads", threads.to_string())
+        })
+}
+
 /// [`restore_contents`] restores all files contents as described by `file_infos`
 /// using the [`DecryptReadBackend`] `be` and writing them into the [`LocalDestination`] `dest`.
 ///
@@ -506,36 +1237,64 @@ impl PackInfo {
 ///
 /// * `repo` - The repository to restore.
 /// * `dest` - The destination to restore to.
-/// * `file_infos` - The restore information.
+/// * `filenames` - The names of the files to restore.
+/// * `file_lengths` - The lengths of the files to restore.
+/// * `sparse` - Whether the files should be restored as sparse files.
+/// * `restore_info` - The restore information.
+/// * `restore_size` - The total size to restore.
+/// * `opts` - The restore options; `on_error` and the rate limits are used.
 ///
 /// # Errors
 ///
-/// * If the length of a file could not be set.
-/// * If the restore failed.
-#[allow(clippy::too_many_lines)]
+/// * If the thread pool could not be created.
+/// * If the restore failed and `on_error` is [`RestoreErrorPolicy::Abort`].
+///
+/// # Returns
+///
+/// The files which could not be restored.
+#[allow(clippy::too_many_lines, clippy::too_many_arguments)]
 fn restore_contents<S: Open>(
     repo: &Repository<S>,
     dest: &LocalDestination,
     filenames: &Filenames,
-    file_lengths: Vec<u64>,
+    mut file_lengths: Vec<u64>,
+    sparse: &[bool],
     restore_info: RestoreInfo,
     restore_size: u64,
-) -> RusticResult<()> {
+    opts: &RestoreOptions,
+) -> RusticResult<RestoreReport> {
     let be = repo.dbe();
+    let errors = ErrorCollector::new(opts.on_error);
+    let download_limiter = &RateLimiter::new(opts.max_download_rate);
+    let write_limiter = &RateLimiter::new(opts.max_write_rate);
+
+    let set_length_error = |path: &PathBuf, err| {
+        RusticError::with_source(
+            ErrorKind::InputOutput,
+            "Failed to set the length of the file `{path}`. Please check the path and try again.",
+            err,
+        )
+        .attach_context("path", path.display().to_string())
+    };
 
-    // first create needed empty files, as they are not created later.
-    for (i, size) in file_lengths.iter().enumerate() {
-        if *size == 0 {
-            let path = &filenames[i];
-            dest.set_length(path, *size).map_err(|err| {
-                RusticError::with_source(
-                    ErrorKind::InputOutput,
-                    "Failed to set the length of the file `{path}`. Please check the path and try again.",
-                    err,
-                )
-                .attach_context("path", path.display().to_string())
-            })?;
+    // first create needed empty and sparse files, as they are not (completely) written later.
+    for (i, size) in file_lengths.iter_mut().enumerate() {
+        let path = &filenames[i];
+        let res = if sparse[i] {
+            dest.create_sparse(path, *size)
+        } else if *size == 0 {
+            dest.set_length(path, *size)
+        } else {
+            continue;
+        };
+        if let Err(err) = res {
+            errors.add([i], set_length_error(path, err));
+            if errors.aborted() {
+                return errors.finish(filenames);
+            }
         }
+        // file is allocated, so we don't need to allocate it again
+        *size = 0;
     }
 
     let sizes = &Mutex::new(file_lengths);
@@ -567,21 +1326,12 @@ fn restore_contents<S: Open>(
         .coalesce(PackInfo::coalesce)
         .collect();
 
-    let threads = constants::MAX_READER_THREADS_NUM;
-
-    let pool = ThreadPoolBuilder::new()
-        .num_threads(threads)
-        .build()
-        .map_err(|err| {
-            RusticError::with_source(
-                ErrorKind::Internal,
-                "Failed to create the thread pool with `{num_threads}` threads. Please try again.",
-                err,
-            )
-            .attach_context("num_threads", threads.to_string())
-        })?;
+    let pool = reader_pool(constants::MAX_READER_THREADS_NUM)?;
+    let cancel = repo.cancellation_token();
+    let pause = repo.pause_handle();
 
     pool.in_place_scope(|s| {
+        let errors = &errors;
         for PackInfo {
             pack_id,
             from_file,
@@ -596,18 +1346,41 @@ fn restore_contents<S: Open>(
             let p = &p;
 
             if !blobs.is_empty() {
-                // TODO: error handling!
                 s.spawn(move |s1| {
-                    let read_data = match &from_file {
+                    pause.wait(cancel);
+                    if errors.aborted() || cancel.is_cancelled() {
+                        return;
+                    }
+                    let read_data = errors.retry(|| match &from_file {
                         Some((file_idx, offset_file, length_file)) => {
                             // read from existing file
-                            dest.read_at(&filenames[*file_idx], *offset_file, (*length_file).into())
-                                .unwrap()
+                            let path = &filenames[*file_idx];
+                            dest.read_at(path, *offset_file, (*length_file).into())
+                                .map_err(|err| {
+                                    RusticError::with_source(
+                                        ErrorKind::InputOutput,
+                                        "Failed to read from the file `{path}`. Please check the path and try again.",
+                                        err,
+                                    )
+                                    .attach_context("path", path.display().to_string())
+                                })
                         }
                         None => {
                             // read needed part of the pack
+                            if let Some(limiter) = download_limiter {
+                                limiter.acquire(length.into());
+                            }
                             be.read_partial(FileType::Pack, &pack_id, false, offset, length)
-                                .unwrap()
+                        }
+                    });
+                    let read_data = match read_data {
+                        Ok(data) => data,
+                        Err(err) => {
+                            let file_idxs = blobs
+                                .iter()
+                                .flat_map(|(_, name_dests)| name_dests.iter().map(|(i, _)| *i));
+                            errors.add(file_idxs, err);
+                            return;
                         }
                     };
 
@@ -621,25 +1394,58 @@ fn restore_contents<S: Open>(
                                 .expect("convert from u32 to usize should not fail!");
                             let end = usize::try_from(bl.offset + bl.length - offset)
                                 .expect("convert from u32 to usize should not fail!");
-                            be.read_encrypted_from_partial(
+                            match be.read_encrypted_from_partial(
                                 &read_data[start..end],
                                 bl.uncompressed_length,
-                            )
-                            .unwrap()
+                            ) {
+                                Ok(data) => data,
+                                Err(err) => {
+                                    errors.add(name_dests.iter().map(|(i, _)| *i), err);
+                                    continue;
+                                }
+                            }
                         };
+                        let is_zero = data.iter().all(|b| *b == 0);
                         for (file_idx, start) in name_dests {
+                            if is_zero && sparse[file_idx] {
+                                // sparse file: leave the hole instead of writing zeros
+                                p.inc(size);
+                                continue;
+                            }
                             let data = data.clone();
                             s1.spawn(move |_| {
+                                pause.wait(cancel);
+                                if errors.aborted() || cancel.is_cancelled() {
+                                    return;
+                                }
                                 let path = &filenames[file_idx];
                                 // Allocate file if it is not yet allocated
                                 let mut sizes_guard = sizes.lock().unwrap();
                                 let filesize = sizes_guard[file_idx];
                                 if filesize > 0 {
-                                    dest.set_length(path, filesize).unwrap();
+                                    if let Err(err) = dest.set_length(path, filesize) {
+                                        drop(sizes_guard);
+                                        errors.add([file_idx], set_length_error(path, err));
+                                        return;
+                                    }
                                     sizes_guard[file_idx] = 0;
                                 }
                                 drop(sizes_guard);
-                                dest.write_at(path, start, &data).unwrap();
+                                if let Some(limiter) = write_limiter {
+                                    limiter.acquire(size);
+                                }
+                                if let Err(err) = dest.write_at(path, start, &data) {
+                                    errors.add(
+                                        [file_idx],
+                                        RusticError::with_source(
+                                            ErrorKind::InputOutput,
+                                            "Failed to write to the file `{path}`. Please check the path and try again.",
+                                            err,
+                                        )
+                                        .attach_context("path", path.display().to_string()),
+                                    );
+                                    return;
+                                }
                                 p.inc(size);
                             });
                         }
@@ -650,8 +1456,10 @@ fn restore_contents<S: Open>(
     });
 
     p.finish();
+    // restoring again continues with the files which have not been completely restored
+    cancel.check("restore")?;
 
-    Ok(())
+    errors.finish(filenames)
 }
 
 /// Information about what will be restored.
@@ -667,6 +1475,8 @@ pub struct RestorePlan {
     names: Filenames,
     /// The length of the files to restore
     file_lengths: Vec<u64>,
+    /// Whether the files to restore should be restored as sparse files
+    sparse: Vec<bool>,
     /// The restore information
     r: RestoreInfo,
     /// candidates for hardlinks
@@ -677,6 +1487,9 @@ pub struct RestorePlan {
     pub matched_size: u64,
     /// Statistics about the restore.
     pub stats: RestoreStats,
+    /// The changes the restore would apply to the destination; only filled in dry-run mode or if
+    /// [`RestoreOptions::manifest`] is set.
+    pub diff: RestoreDiff,
 }
 
 /// [`FileLocation`] contains information about a file within a blob
@@ -686,6 +1499,8 @@ struct FileLocation {
     file_idx: usize,
     /// The start of the file within the blob
     file_start: u64,
+    /// The id of the blob
+    blob_id: DataId,
     /// Whether the file matches the blob
     ///
     /// This indicates that the file exists and these contents are already correct.
@@ -700,98 +1515,215 @@ enum AddFileResult {
     Modify,
 }
 
+/// [`FileCheck`] is the result of checking an existing file against the contents of a [`Node`]
+enum FileCheck {
+    /// The file exists and is accepted without reading it; contains the size of the matched content
+    Existing(u64),
+    /// The contents of the node must be compared blob-wise
+    Blobs {
+        /// Whether a file with matching size exists and could be opened
+        open: bool,
+        /// The blobs of the node with their index entries and whether they match the existing file
+        blobs: Vec<(DataId, IndexEntry, bool)>,
+    },
+}
+
+/// Check the existing file `name` against the contents of `file` using `index` to get blob information.
+///
+/// # Type Parameters
+///
+/// * `S` - The type of the indexed tree.
+///
+/// # Arguments
+///
+/// * `dest` - The destination to restore to.
+/// * `file` - The file to check.
+/// * `name` - The name of the file.
+/// * `repo` - The repository to restore.
+/// * `ignore_mtime` - If true, ignore the modification time of the file.
+///
+/// # Errors
+///
+/// * If the metadata of the existing file could not be read.
+/// * If a blob of the file is not contained in the index.
+fn check_file<S: IndexedFull>(
+    dest: &LocalDestination,
+    file: &Node,
+    name: &Path,
+    repo: &Repository<S>,
+    ignore_mtime: bool,
+) -> RusticResult<FileCheck> {
+    let mut open_file = dest.get_matching_file(name, file.meta.size);
+
+    // Empty files which exists with correct size should always return Ok(Existing)!
+    if file.meta.size == 0
+        && let Some(meta) = open_file
+            .as_ref()
+            .map(std::fs::File::metadata)
+            .transpose()
+            .map_err(|err|
+                RusticError::with_source(
+                    ErrorKind::InputOutput,
+                    "Failed to get the metadata of the file `{path}`. Please check the path and try again.",
+                    err
+                )
+                .attach_context("path", name.display().to_string())
+            )?
+            && meta.len() == 0 {
+                // Empty file exists
+                return Ok(FileCheck::Existing(0));
+            }
+
+    if !ignore_mtime
+        && let Some(meta) = open_file
+            .as_ref()
+            .map(std::fs::File::metadata)
+            .transpose()
+            .map_err(|err|
+                RusticError::with_source(
+                    ErrorKind::InputOutput,
+                    "Failed to get the metadata of the file `{path}`. Please check the path and try again.",
+                    err
+                )
+                .attach_context("path", name.display().to_string())
+            )?
+        {
+            // TODO: This is the same logic as in backend/ignore.rs => consolidate!
+            let mtime = meta
+                .modified()
+                .ok()
+                .and_then(|t| Timestamp::try_from(t).ok());
+            if meta.len() == file.meta.size && mtime == file.meta.mtime {
+                // File exists with fitting mtime => we suspect this file is ok!
+                debug!("file {} exists with suitable size and mtime, accepting it!",name.display());
+                return Ok(FileCheck::Existing(file.meta.size));
+            }
+        }
+
+    let blobs = file
+        .content
+        .iter()
+        .flatten()
+        .map(|id| -> RusticResult<_> {
+            let ie = repo.get_index_entry(id)?;
+            let length: u64 = ie.location.data_length().into();
+            let matches = open_file
+                .as_mut()
+                .is_some_and(|file| id.blob_matches_reader(length, file));
+            Ok((*id, ie, matches))
+        })
+        .collect::<RusticResult<_>>()?;
+
+    Ok(FileCheck::Blobs {
+        open: open_file.is_some(),
+        blobs,
+    })
+}
+
+/// [`VerifyAhead`] wraps the node streamer and checks existing files in advance.
+///
+/// If a thread pool is given, up to [`constants::VERIFY_BATCH_SIZE`] nodes are read ahead and the
+/// existing files are checked in parallel. Otherwise, nodes are passed through unchecked and files
+/// are checked sequentially when they are added to the [`RestorePlan`].
+struct VerifyAhead<'a, S, I> {
+    /// The wrapped node streamer
+    nodes: I,
+    /// The thread pool used for checking files
+    pool: Option<ThreadPool>,
+    /// The repository to restore
+    repo: &'a Repository<S>,
+    /// The destination to restore to
+    dest: &'a LocalDestination,
+    /// If true, ignore the modification time of the files
+    ignore_mtime: bool,
+    /// Hardlinks which are already checked; only the first node of a hardlink is restored
+    hardlinks: BTreeSet<HardlinkKey>,
+    /// The nodes which are read ahead
+    buffer: VecDeque<RusticResult<(PathBuf, Node, Option<FileCheck>)>>,
+}
+
+impl<S, I> Iterator for VerifyAhead<'_, S, I>
+where
+    S: IndexedFull,
+    I: Iterator<Item = RusticResult<(PathBuf, Node)>>,
+{
+    type Item = RusticResult<(PathBuf, Node, Option<FileCheck>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Some(pool) = &self.pool else {
+            return self
+                .nodes
+                .next()
+                .map(|item| item.map(|(path, node)| (path, node, None)));
+        };
+
+        if self.buffer.is_empty() {
+            let items: Vec<_> = self
+                .nodes
+                .by_ref()
+                .take(constants::VERIFY_BATCH_SIZE)
+                .collect();
+            let to_check: Vec<_> = items
+                .iter()
+                .map(|item| {
+                    item.as_ref().is_ok_and(|(_, node)| {
+                        node.is_file()
+                            && hardlink_key(node).is_none_or(|key| self.hardlinks.insert(key))
+                    })
+                })
+                .collect();
+
+            let (repo, dest, ignore_mtime) = (self.repo, self.dest, self.ignore_mtime);
+            let checked: Vec<_> = pool.install(|| {
+                items
+                    .into_par_iter()
+                    .zip(to_check)
+                    .map(|(item, to_check)| -> RusticResult<_> {
+                        let (path, node) = item?;
+                        let check = to_check
+                            .then(|| check_file(dest, &node, &path, repo, ignore_mtime))
+                            .transpose()?;
+                        Ok((path, node, check))
+                    })
+                    .collect()
+            });
+            self.buffer = checked.into();
+        }
+
+        self.buffer.pop_front()
+    }
+}
+
 impl RestorePlan {
-    /// Add the file to [`FileLocation`] using `index` to get blob information.
-    ///
-    /// # Type Parameters
-    ///
-    /// * `P` - The progress bar type.
-    /// * `S` - The type of the indexed tree.
+    /// Add the file to [`FileLocation`] using the result of checking the existing file.
     ///
     /// # Arguments
     ///
-    /// * `dest` - The destination to restore to.
-    /// * `file` - The file to add.
     /// * `name` - The name of the file.
-    /// * `repo` - The repository to restore.
-    /// * `ignore_mtime` - If true, ignore the modification time of the file.
-    ///
-    /// # Errors
-    ///
-    /// * If the file could not be added.
-    fn add_file<S: IndexedFull>(
-        &mut self,
-        dest: &LocalDestination,
-        file: &Node,
-        name: PathBuf,
-        repo: &Repository<S>,
-        ignore_mtime: bool,
-    ) -> RusticResult<AddFileResult> {
-        let mut open_file = dest.get_matching_file(&name, file.meta.size);
-
-        // Empty files which exists with correct size should always return Ok(Existing)!
-        if file.meta.size == 0
-            && let Some(meta) = open_file
-                .as_ref()
-                .map(std::fs::File::metadata)
-                .transpose()
-                .map_err(|err|
-                    RusticError::with_source(
-                        ErrorKind::InputOutput,
-                        "Failed to get the metadata of the file `{path}`. Please check the path and try again.",
-                        err
-                    )
-                    .attach_context("path", name.display().to_string())
-                )?
-                && meta.len() == 0 {
-                    // Empty file exists
-                    return Ok(AddFileResult::Existing);
-                }
-
-        if !ignore_mtime
-            && let Some(meta) = open_file
-                .as_ref()
-                .map(std::fs::File::metadata)
-                .transpose()
-                .map_err(|err|
-                    RusticError::with_source(
-                        ErrorKind::InputOutput,
-                        "Failed to get the metadata of the file `{path}`. Please check the path and try again.",
-                        err
-                    )
-                    .attach_context("path", name.display().to_string())
-                )?
-            {
-                // TODO: This is the same logic as in backend/ignore.rs => consolidate!
-                let mtime = meta
-                    .modified()
-                    .ok()
-                    .and_then(|t| Timestamp::try_from(t).ok());
-                if meta.len() == file.meta.size && mtime == file.meta.mtime {
-                    // File exists with fitting mtime => we suspect this file is ok!
-                    debug!("file {} exists with suitable size and mtime, accepting it!",name.display());
-                    self.matched_size += file.meta.size;
-                    return Ok(AddFileResult::Existing);
-                }
+    /// * `check` - The result of [`check_file`] for the file.
+    /// * `sparse` - If true, restore the file as sparse file if it is completely rewritten.
+    fn add_file(&mut self, name: PathBuf, check: FileCheck, sparse: bool) -> AddFileResult {
+        let (open, blobs) = match check {
+            FileCheck::Existing(matched_size) => {
+                self.matched_size += matched_size;
+                return AddFileResult::Existing;
             }
+            FileCheck::Blobs { open, blobs } => (open, blobs),
+        };
 
         let file_idx = self.names.len();
         self.names.push(name);
         let mut file_pos = 0;
         let mut has_unmatched = false;
-        for id in file.content.iter().flatten() {
-            let ie = repo.get_index_entry(id)?;
+        for (id, ie, matches) in blobs {
             let bl = ie.location;
             let length: u64 = bl.data_length().into();
 
-            let matches = open_file
-                .as_mut()
-                .is_some_and(|file| id.blob_matches_reader(length, file));
-
             let blob_location = self.r.entry((ie.pack, bl)).or_default();
             blob_location.push(FileLocation {
                 file_idx,
                 file_start: file_pos,
+                blob_id: id,
                 matches,
             });
 
@@ -806,14 +1738,92 @@ impl RestorePlan {
         }
 
         self.file_lengths.push(file_pos);
+        // Only files where no content can be taken from an existing file can be restored as sparse files
+        self.sparse.push(sparse && !open);
 
-        if !has_unmatched && open_file.is_some() {
-            Ok(AddFileResult::Verified)
+        if !has_unmatched && open {
+            AddFileResult::Verified
         } else {
-            Ok(AddFileResult::Modify)
+            AddFileResult::Modify
         }
     }
 
+    /// Add a byte range of the file to the restore plan.
+    ///
+    /// Only the blobs overlapping `range` are added, so only those need to be fetched from the backend.
+    /// As contents are restored blob-wise, the restored part of the file may exceed `range` up to the
+    /// boundaries of the first and last overlapping blob. All other contents of an existing destination
+    /// file are left untouched, a non-existing file is created with holes outside of the restored part.
+    ///
+    /// To restore the plan, use [`Repository::restore`], e.g. with an empty node streamer if no
+    /// metadata should be set.
+    ///
+    /// # Type Parameters
+    ///
+    /// * `S` - The type of the indexed tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to restore.
+    /// * `file` - The file to add.
+    /// * `name` - The name of the file, relative to the restore destination.
+    /// * `range` - The byte range of the file to restore.
+    ///
+    /// # Errors
+    ///
+    /// * If `file` is not a file.
+    /// * If a blob of the file is not contained in the index.
+    pub fn add_file_range<S: IndexedFull>(
+        &mut self,
+        repo: &Repository<S>,
+        file: &Node,
+        name: impl Into<PathBuf>,
+        range: Range<u64>,
+    ) -> RusticResult<()> {
+        let name = name.into();
+        if !file.is_file() {
+            return Err(RusticError::new(
+                ErrorKind::InvalidInput,
+                "Cannot restore a byte range of `{path}` as it is not a file.",
+            )
+            .attach_context("path", name.display().to_string()));
+        }
+
+        let mut file_pos = 0;
+        let mut blobs = Vec::new();
+        for id in file.content.iter().flatten() {
+            let ie = repo.get_index_entry(id)?;
+            let length: u64 = ie.location.data_length().into();
+            if file_pos < range.end && file_pos + length > range.start {
+                blobs.push((*id, ie, file_pos, length));
+            }
+            file_pos += length;
+        }
+
+        if blobs.is_empty() {
+            return Ok(());
+        }
+
+        let file_idx = self.names.len();
+        self.names.push(name);
+        for (blob_id, ie, file_start, length) in blobs {
+            self.r
+                .entry((ie.pack, ie.location))
+                .or_default()
+                .push(FileLocation {
+                    file_idx,
+                    file_start,
+                    blob_id,
+                    matches: false,
+                });
+            self.restore_size += length;
+        }
+        self.file_lengths.push(file_pos);
+        self.sparse.push(false);
+
+        Ok(())
+    }
+
     /// Get a list of all pack files needed to perform the restore
     ///
     /// This can be used e.g. to warm-up those pack files before doing the actual restore.
@@ -827,4 +1837,20 @@ impl RestorePlan {
             .dedup()
             .collect()
     }
+
+    /// Get the blobs of all files to restore as `(start, length, id)`, sorted by file index and start
+    fn blobs_by_file(&self) -> BTreeMap<usize, Vec<(u64, u64, DataId)>> {
+        let mut blobs: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for ((_, bl), fls) in &self.r {
+            let length: u64 = bl.data_length().into();
+            for fl in fls {
+                blobs
+                    .entry(fl.file_idx)
+                    .or_default()
+                    .push((fl.file_start, length, fl.blob_id));
+            }
+        }
+        blobs.values_mut().for_each(|b| b.sort_unstable());
+        blobs
+    }
 }
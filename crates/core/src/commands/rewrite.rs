@@ -119,6 +119,11 @@ fn process_snapshots<S: Open>(
     mut snapshots: Vec<SnapshotFile>,
     opts: &RewriteOptions,
 ) -> RusticResult<Vec<SnapshotFile>> {
+    // keep track of the snapshots the rewritten ones originate from
+    for sn in &mut snapshots {
+        _ = sn.original.get_or_insert(sn.id);
+    }
+
     if !snapshots.is_empty() && !opts.dry_run {
         match (&opts.tags_rewritten, opts.forget) {
             (Some(tags), _) => snapshots
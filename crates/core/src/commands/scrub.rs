@@ -0,0 +1,162 @@
+//! Incremental verification of pack files
+use std::collections::BTreeMap;
+
+use bytesize::ByteSize;
+use jiff::Timestamp;
+use log::{debug, info};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    backend::{
+        FileType, ReadBackend, WriteBackend,
+        decrypt::{DecryptReadBackend, DecryptWriteBackend},
+    },
+    commands::check::{CheckError, CheckResults, CheckResultsCollector, check_pack},
+    error::{ErrorKind, RusticError, RusticResult},
+    repofile::{IndexFile, ScrubFile, packfile::PackId},
+    repository::{Open, Repository},
+};
+
+#[derive(Debug)]
+#[non_exhaustive]
+/// Results of a scrub run
+pub struct ScrubResults {
+    /// The packs which have been read and verified in this run, including packs with problems
+    pub packs_verified: Vec<PackId>,
+    /// The total size of the packs verified in this run
+    pub bytes_verified: u64,
+    /// The number of packs which have not yet been verified successfully
+    pub packs_unverified: u64,
+    /// The problems found when verifying the packs
+    pub check_results: CheckResults,
+}
+
+/// Verify the least-recently verified packs up to the given byte budget and record the verification in
+/// the scrub state of the repository.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to scrub
+/// * `budget` - The maximum size of the packs to verify
+///
+/// # Errors
+///
+/// * If the backend cannot store the scrub state, see [`ReadBackend::supports_file_type`].
+/// * If the index or scrub files could not be read.
+/// * If the scrub state could not be saved.
+///
+/// # Returns
+///
+/// The [`ScrubResults`] of this run
+pub(crate) fn scrub<S: Open>(repo: &Repository<S>, budget: ByteSize) -> RusticResult<ScrubResults> {
+    let be = repo.dbe();
+    if !be.supports_file_type(FileType::Scrub) {
+        return Err(RusticError::new(
+            ErrorKind::Unsupported,
+            "Backend `{location}` cannot store the scrub state. Please use `check` with `read-data-subset` instead.",
+        )
+        .attach_context("location", be.location()));
+    }
+
+    let p = repo.progress_counter("reading scrub state...");
+    let mut state = ScrubFile::default();
+    let mut scrub_ids = Vec::new();
+    for scrub_file in be.stream_all::<ScrubFile>(&p)? {
+        let (id, scrub_file) = scrub_file?;
+        scrub_ids.push(id);
+        state.merge(scrub_file);
+    }
+    p.finish();
+
+    let p = repo.progress_counter("reading index...");
+    let mut packs = BTreeMap::new();
+    for index in be.stream_all::<IndexFile>(&p)? {
+        for pack in index?.1.packs {
+            _ = packs.insert(pack.id, pack);
+        }
+    }
+    p.finish();
+
+    // remove packs which are no longer in the index
+    let old_state = state.clone();
+    state.verified.retain(|id, _| packs.contains_key(id));
+
+    let pack_count = packs.len();
+    let mut packs: Vec<_> = packs.into_values().collect();
+    packs.sort_by_key(|pack| (state.last_verified(&pack.id), pack.id));
+
+    // always take at least one pack, so that scrubbing makes progress even if the budget is smaller than a pack
+    let budget = budget.as_u64();
+    let mut bytes_verified = 0;
+    let packs: Vec<_> = packs
+        .into_iter()
+        .take_while(|pack| {
+            let size = u64::from(pack.pack_size());
+            let take = budget > 0 && (bytes_verified == 0 || bytes_verified + size <= budget);
+            if take {
+                bytes_verified += size;
+            }
+            take
+        })
+        .collect();
+    debug!("scrubbing {} packs, {bytes_verified} bytes", packs.len());
+
+    repo.warm_up_wait(packs.iter().map(|pack| pack.id))?;
+
+    let p = repo.progress_bytes("scrubbing packs...");
+    p.set_length(bytes_verified);
+    let results: Vec<_> = packs
+        .into_par_iter()
+        .map(|pack| {
+            let id = pack.id;
+            let collector = CheckResultsCollector::default().log(true);
+            match be.read_full(FileType::Pack, &id) {
+                Err(err) => {
+                    collector.add_error(CheckError::ErrorReadingPack { id, source: err });
+                }
+                Ok(data) => {
+                    if let Err(err) = check_pack(be, pack, data, &p, &collector) {
+                        collector.add_error(CheckError::ErrorCheckingPack { id, source: err });
+                    }
+                }
+            }
+            (id, collector.into_check_results())
+        })
+        .collect();
+    p.finish();
+
+    let now = Timestamp::now();
+    let mut packs_verified = Vec::new();
+    let mut findings = Vec::new();
+    for (id, results) in results {
+        if results.0.is_empty() {
+            _ = state.verified.insert(id, now);
+        }
+        packs_verified.push(id);
+        findings.extend(results.0);
+    }
+    let packs_unverified = (pack_count - state.verified.len()) as u64;
+
+    if state != old_state {
+        _ = be.save_file(&state)?;
+        // in append-only repositories, the old scrub files are kept; they are merged when reading the state.
+        if repo.config().append_only != Some(true) {
+            for id in scrub_ids {
+                be.remove(FileType::Scrub, &id, false)?;
+            }
+        }
+    }
+
+    info!(
+        "verified {} packs ({}), {packs_unverified} packs not yet verified",
+        packs_verified.len(),
+        ByteSize(bytes_verified).display().iec()
+    );
+
+    Ok(ScrubResults {
+        packs_verified,
+        bytes_verified,
+        packs_unverified,
+        check_results: CheckResults(findings),
+    })
+}
@@ -0,0 +1,515 @@
+//! Statistics about the repository, snapshots and their nodes
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use derive_setters::Setters;
+use jiff::Zoned;
+use serde_derive::Serialize;
+use serde_with::serde_as;
+
+use crate::{
+    backend::{
+        decrypt::DecryptReadBackend,
+        node::{Node, NodeType},
+    },
+    blob::{
+        BlobId, BlobType, BlobTypeMap,
+        tree::{TreeId, TreeStreamerOnce, TreeStreamerOptions as LsOptions},
+    },
+    error::RusticResult,
+    index::ReadIndex,
+    repofile::{
+        IndexFile, RusticTime, SnapshotFile,
+        snapshotfile::{
+            SnapshotId,
+            grouping::{Grouped, SnapshotGroup, SnapshotGroupCriterion},
+        },
+    },
+    repository::{IndexedFull, IndexedTree, Open, Repository},
+};
+
+/// A hook which aggregates metadata of the nodes visited while walking a tree.
+///
+/// See [`Repository::aggregate_nodes`]. Implement this trait to build custom analyses on top of the tree
+/// walk, e.g. size distributions or owner statistics.
+pub trait NodeAggregator {
+    /// Visit a node
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the node relative to the walked node
+    /// * `node` - The node
+    fn visit(&mut self, path: &Path, node: &Node);
+}
+
+/// Number of nodes per node type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct NodeTypeCounts {
+    /// Number of regular files
+    pub files: u64,
+    /// Number of directories
+    pub dirs: u64,
+    /// Number of symlinks
+    pub symlinks: u64,
+    /// Number of block devices
+    pub devs: u64,
+    /// Number of character devices
+    pub chardevs: u64,
+    /// Number of fifos
+    pub fifos: u64,
+    /// Number of sockets
+    pub sockets: u64,
+}
+
+impl NodeTypeCounts {
+    /// Returns the total number of nodes
+    #[must_use]
+    pub const fn total(&self) -> u64 {
+        self.files
+            + self.dirs
+            + self.symlinks
+            + self.devs
+            + self.chardevs
+            + self.fifos
+            + self.sockets
+    }
+}
+
+/// Statistics about the nodes of a tree, see [`Repository::node_stats`]
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct NodeStats {
+    /// Number of nodes per node type
+    pub node_types: NodeTypeCounts,
+    /// Total size of all regular files
+    pub file_size: u64,
+    /// Number of files which were stored sparse on the source filesystem
+    pub sparse_files: u64,
+    /// Number of hard link groups, i.e. inodes which are referenced by more than one hard link
+    pub hardlink_groups: u64,
+    /// Number of nodes which are part of a hard link group
+    pub hardlinked_nodes: u64,
+    /// Total size of the hard-linked files, counting each hard link group only once
+    pub hardlinked_size: u64,
+    /// The (device id, inode) of the found hard link groups
+    #[serde(skip)]
+    inodes: BTreeSet<(u64, u64)>,
+}
+
+impl NodeAggregator for NodeStats {
+    fn visit(&mut self, _path: &Path, node: &Node) {
+        let counts = &mut self.node_types;
+        match node.node_type {
+            NodeType::File => counts.files += 1,
+            NodeType::Dir => counts.dirs += 1,
+            NodeType::Symlink { .. } => counts.symlinks += 1,
+            NodeType::Dev { .. } => counts.devs += 1,
+            NodeType::Chardev { .. } => counts.chardevs += 1,
+            NodeType::Fifo => counts.fifos += 1,
+            NodeType::Socket => counts.sockets += 1,
+        }
+
+        if node.is_file() {
+            self.file_size += node.meta.size;
+            if node.meta.sparse {
+                self.sparse_files += 1;
+            }
+        }
+
+        // inodes are only saved on unix; a zero inode means there is no information
+        if !node.is_dir() && node.meta.links > 1 && node.meta.inode != 0 {
+            self.hardlinked_nodes += 1;
+            if self.inodes.insert((node.meta.device_id, node.meta.inode)) {
+                self.hardlink_groups += 1;
+                if node.is_file() {
+                    self.hardlinked_size += node.meta.size;
+                }
+            }
+        }
+    }
+}
+
+/// Walk all nodes within the given node and pass them to the aggregator
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `node` - The node to walk; if it is a directory, all nodes within it are visited recursively
+/// * `aggregator` - The aggregator to pass the nodes to
+///
+/// # Errors
+///
+/// * If a tree could not be read.
+pub(crate) fn aggregate_nodes<S: IndexedTree>(
+    repo: &Repository<S>,
+    node: &Node,
+    aggregator: &mut impl NodeAggregator,
+) -> RusticResult<()> {
+    for item in repo.ls(node, &LsOptions::default())? {
+        let (path, node) = item?;
+        aggregator.visit(&path, &node);
+    }
+    Ok(())
+}
+
+/// Aggregated statistics of a group of snapshots, see [`Repository::snapshot_group_stats`]
+///
+/// The sizes are taken from the [`SnapshotSummary`](crate::repofile::SnapshotSummary) of the snapshots;
+/// snapshots without summary are only counted.
+#[serde_as]
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct SnapshotGroupStats {
+    /// The group
+    pub group: SnapshotGroup,
+    /// Number of snapshots in the group
+    pub count: u64,
+    /// Number of snapshots without summary
+    pub count_without_summary: u64,
+    /// Time of the oldest snapshot
+    #[serde_as(as = "RusticTime")]
+    pub oldest: Zoned,
+    /// Time of the newest snapshot
+    #[serde_as(as = "RusticTime")]
+    pub newest: Zoned,
+    /// Total size of all snapshots, i.e. the sum of the processed bytes
+    pub total_size: u64,
+    /// Size of the newest snapshot
+    pub latest_size: u64,
+    /// Total (uncompressed) size of the data added to the repository by all snapshots
+    pub data_added: u64,
+    /// Size of the data added by the newest snapshot, i.e. since the previous snapshot
+    pub latest_data_added: u64,
+}
+
+impl SnapshotGroupStats {
+    /// Aggregate the statistics of a group of snapshots
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The group
+    /// * `snapshots` - The snapshots of the group; must not be empty
+    fn from_snapshots(group: SnapshotGroup, snapshots: &[SnapshotFile]) -> Self {
+        let oldest = snapshots.iter().min().expect("group is not empty");
+        let newest = snapshots.iter().max().expect("group is not empty");
+        let mut stats = Self {
+            group,
+            count: snapshots.len() as u64,
+            count_without_summary: 0,
+            oldest: oldest.time.clone(),
+            newest: newest.time.clone(),
+            total_size: 0,
+            latest_size: 0,
+            data_added: 0,
+            latest_data_added: 0,
+        };
+        for sn in snapshots {
+            match &sn.summary {
+                Some(summary) => {
+                    stats.total_size += summary.total_bytes_processed;
+                    stats.data_added += summary.data_added;
+                }
+                None => stats.count_without_summary += 1,
+            }
+        }
+        if let Some(summary) = &newest.summary {
+            stats.latest_size = summary.total_bytes_processed;
+            stats.latest_data_added = summary.data_added;
+        }
+        stats
+    }
+}
+
+/// Get aggregated statistics for all snapshot groups
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `criterion` - The criterion to group the snapshots by
+///
+/// # Errors
+///
+/// * If the snapshots could not be read.
+///
+/// # Returns
+///
+/// The statistics of each group, sorted by group
+pub(crate) fn snapshot_group_stats<S: Open>(
+    repo: &Repository<S>,
+    criterion: SnapshotGroupCriterion,
+) -> RusticResult<Vec<SnapshotGroupStats>> {
+    let groups = Grouped::from_items(repo.get_all_snapshots()?, criterion);
+    Ok(groups
+        .groups
+        .into_iter()
+        .map(|group| SnapshotGroupStats::from_snapshots(group.group_key, &group.items))
+        .collect())
+}
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Debug, Clone, Copy, Default, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for [`Repository::stats`]
+pub struct StatsOptions {
+    /// Compute the size of the data which is only referenced by a single snapshot for each snapshot
+    #[cfg_attr(feature = "clap", clap(long))]
+    pub per_snapshot: bool,
+}
+
+/// Statistics about the blobs of one blob type, see [`RepoStats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct BlobStats {
+    /// Number of blobs
+    pub count: u64,
+    /// Total size of the blobs saved in the repository, i.e. after compression and encryption
+    pub size: u64,
+    /// Total raw size of the blobs, i.e. without compression or encryption
+    pub data_size: u64,
+}
+
+impl BlobStats {
+    /// Returns the compression ratio, i.e. the raw size divided by the size saved in the repository
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.size > 0).then(|| self.data_size as f64 / self.size as f64)
+    }
+}
+
+/// The distribution of the pack sizes of one blob type, see [`RepoStats`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct PackSizeDistribution {
+    /// Number of packs
+    pub count: u64,
+    /// Total size of the packs
+    pub total_size: u64,
+    /// Size of the smallest pack, `None` if there is no pack
+    pub min_size: Option<u64>,
+    /// Size of the largest pack, `None` if there is no pack
+    pub max_size: Option<u64>,
+    /// Number of packs per size class; the key is the upper bound of the pack sizes, a power of two
+    pub buckets: BTreeMap<u64, u64>,
+}
+
+impl PackSizeDistribution {
+    /// Add a pack of the given size
+    fn add(&mut self, size: u64) {
+        self.count += 1;
+        self.total_size += size;
+        self.min_size = Some(self.min_size.map_or(size, |min| min.min(size)));
+        self.max_size = Some(self.max_size.map_or(size, |max| max.max(size)));
+        *self.buckets.entry(size.next_power_of_two()).or_default() += 1;
+    }
+}
+
+/// Size statistics of a single snapshot, see [`RepoStats`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct SnapshotSizeStats {
+    /// The id of the snapshot
+    pub id: SnapshotId,
+    /// Total size of all files of the snapshot, i.e. the size of a restore
+    pub restore_size: u64,
+    /// Size saved in the repository of the blobs which are only referenced by this snapshot.
+    ///
+    /// This is the size which would be freed by removing this snapshot and pruning the repository.
+    pub unique_size: u64,
+    /// Raw size of the blobs which are only referenced by this snapshot
+    pub unique_data_size: u64,
+}
+
+/// Repository-wide statistics, see [`Repository::stats`]
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct RepoStats {
+    /// Number of snapshots
+    pub snapshots: u64,
+    /// Total size of all files of all snapshots, i.e. the size of restoring all snapshots
+    pub restore_size: u64,
+    /// Statistics about the blobs per blob type
+    pub blobs: BlobTypeMap<BlobStats>,
+    /// The distribution of the pack sizes per blob type
+    pub packs: BlobTypeMap<PackSizeDistribution>,
+    /// Size statistics per snapshot; only filled if [`StatsOptions::per_snapshot`] is set
+    pub snapshot_sizes: Vec<SnapshotSizeStats>,
+}
+
+impl RepoStats {
+    /// Returns the raw size of all file contents saved in the repository
+    #[must_use]
+    pub fn raw_data_size(&self) -> u64 {
+        self.blobs[BlobType::Data].data_size
+    }
+
+    /// Returns the deduplication ratio, i.e. the restore size divided by the raw size of the saved file contents
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn dedup_ratio(&self) -> Option<f64> {
+        let raw_size = self.raw_data_size();
+        (raw_size > 0).then(|| self.restore_size as f64 / raw_size as f64)
+    }
+}
+
+/// Compute the total size of all files within a tree
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `id` - The id of the tree
+/// * `sizes` - The already computed sizes of trees; identical trees are only read once
+///
+/// # Errors
+///
+/// * If a tree could not be read.
+fn tree_size<S: IndexedTree>(
+    repo: &Repository<S>,
+    id: TreeId,
+    sizes: &mut BTreeMap<TreeId, u64>,
+) -> RusticResult<u64> {
+    if let Some(size) = sizes.get(&id) {
+        return Ok(*size);
+    }
+    let mut size = 0;
+    for node in repo.get_tree(&id)?.nodes {
+        if node.is_file() {
+            size += node.meta.size;
+        } else if let Some(subtree) = node.subtree {
+            size += tree_size(repo, subtree, sizes)?;
+        }
+    }
+    _ = sizes.insert(id, size);
+    Ok(size)
+}
+
+/// Find all blobs which are referenced by the given snapshot
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `snap` - The snapshot
+///
+/// # Errors
+///
+/// * If a tree could not be read.
+fn snapshot_blobs<S: IndexedTree>(
+    repo: &Repository<S>,
+    snap: &SnapshotFile,
+) -> RusticResult<BTreeSet<(BlobType, BlobId)>> {
+    let mut blobs = BTreeSet::from([(BlobType::Tree, BlobId::from(*snap.tree))]);
+    let mut tree_streamer = TreeStreamerOnce::new(
+        repo.dbe(),
+        repo.index(),
+        vec![snap.tree],
+        repo.progress_hidden(),
+    )?;
+    while let Some(item) = tree_streamer.next().transpose()? {
+        let (_, tree) = item;
+        for node in tree.nodes {
+            match node.node_type {
+                NodeType::File => blobs.extend(
+                    node.content
+                        .iter()
+                        .flatten()
+                        .map(|id| (BlobType::Data, BlobId::from(**id))),
+                ),
+                NodeType::Dir => {
+                    blobs.extend(node.subtree.map(|id| (BlobType::Tree, BlobId::from(*id))))
+                }
+                _ => {} // nothing to do
+            }
+        }
+    }
+    Ok(blobs)
+}
+
+/// Compute repository-wide statistics
+///
+/// The blob and pack statistics are taken from the index. If requested, the blobs which are only
+/// referenced by a single snapshot are attributed to this snapshot, like `prune` counts used blobs.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `opts` - The options to use
+///
+/// # Errors
+///
+/// * If the index or the snapshots could not be read.
+/// * If a tree could not be read.
+pub(crate) fn repo_stats<S: IndexedFull>(
+    repo: &Repository<S>,
+    opts: &StatsOptions,
+) -> RusticResult<RepoStats> {
+    let mut stats = RepoStats::default();
+
+    let p = repo.progress_counter("reading index...");
+    for index in repo.dbe().stream_all::<IndexFile>(&p)? {
+        for pack in index?.1.packs {
+            let tpe = pack.blob_type();
+            stats.packs[tpe].add(u64::from(pack.pack_size()));
+            let blob_stats = &mut stats.blobs[tpe];
+            for blob in &pack.blobs {
+                blob_stats.count += 1;
+                blob_stats.size += u64::from(blob.location.length);
+                blob_stats.data_size += u64::from(blob.location.data_length());
+            }
+        }
+    }
+    p.finish();
+
+    let mut snaps = repo.get_all_snapshots()?;
+    snaps.sort_unstable();
+    stats.snapshots = snaps.len() as u64;
+
+    let p = repo.progress_counter("computing restore sizes...");
+    p.set_length(snaps.len() as u64);
+    let mut tree_sizes = BTreeMap::new();
+    let mut snapshot_sizes = Vec::with_capacity(snaps.len());
+    for snap in &snaps {
+        let restore_size = tree_size(repo, snap.tree, &mut tree_sizes)?;
+        stats.restore_size += restore_size;
+        snapshot_sizes.push(SnapshotSizeStats {
+            id: snap.id,
+            restore_size,
+            unique_size: 0,
+            unique_data_size: 0,
+        });
+        p.inc(1);
+    }
+    p.finish();
+
+    if opts.per_snapshot {
+        // the number of referencing snapshots and the last referencing snapshot of each blob
+        let mut refs: BTreeMap<(BlobType, BlobId), (u64, usize)> = BTreeMap::new();
+        let p = repo.progress_counter("finding used blobs...");
+        p.set_length(snaps.len() as u64);
+        for (i, snap) in snaps.iter().enumerate() {
+            for blob in snapshot_blobs(repo, snap)? {
+                let (count, owner) = refs.entry(blob).or_default();
+                *count += 1;
+                *owner = i;
+            }
+            p.inc(1);
+        }
+        p.finish();
+
+        for ((tpe, id), (count, owner)) in refs {
+            if count == 1
+                && let Some(ie) = repo.index().get_id(tpe, &id)
+            {
+                let sizes = &mut snapshot_sizes[owner];
+                sizes.unique_size += u64::from(ie.location.length);
+                sizes.unique_data_size += u64::from(ie.data_length());
+            }
+        }
+        stats.snapshot_sizes = snapshot_sizes;
+    }
+
+    Ok(stats)
+}
@@ -0,0 +1,146 @@
+//! Reconstruct missing snapshot summaries
+use std::collections::BTreeMap;
+
+use log::info;
+
+use crate::{
+    blob::tree::TreeId,
+    error::{ErrorKind, RusticError, RusticResult},
+    index::ReadGlobalIndex,
+    repofile::{SnapshotFile, SnapshotSummary, Tree},
+    repository::{IndexedTree, Repository},
+};
+
+/// The totals of a tree including all its subtrees
+#[derive(Debug, Default, Clone, Copy)]
+struct TreeTotals {
+    /// Number of files
+    files: u64,
+    /// Total size of all files
+    bytes: u64,
+    /// Number of directories
+    dirs: u64,
+    /// Total size of all trees
+    dirsize: u64,
+}
+
+/// Computes [`TreeTotals`] of trees, visiting each tree only once
+struct TotalsCollector<'a, S: IndexedTree> {
+    /// The repository to read the trees from
+    repo: &'a Repository<S>,
+    /// The already computed totals
+    totals: BTreeMap<TreeId, TreeTotals>,
+}
+
+impl<S: IndexedTree> TotalsCollector<'_, S> {
+    /// Get the totals of the given tree
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the tree
+    ///
+    /// # Errors
+    ///
+    /// * If a tree could not be read.
+    fn totals(&mut self, id: TreeId) -> RusticResult<TreeTotals> {
+        if let Some(totals) = self.totals.get(&id) {
+            return Ok(*totals);
+        }
+
+        let tree = Tree::from_backend(self.repo.dbe(), self.repo.index(), id)?;
+        let mut totals = TreeTotals {
+            dirs: 1,
+            dirsize: self
+                .repo
+                .index()
+                .get_tree(&id)
+                .map_or(0, |ie| u64::from(ie.data_length())),
+            ..Default::default()
+        };
+        for node in tree.nodes {
+            if let Some(subtree) = node.subtree {
+                let sub = self.totals(subtree)?;
+                totals.files += sub.files;
+                totals.bytes += sub.bytes;
+                totals.dirs += sub.dirs;
+                totals.dirsize += sub.dirsize;
+            } else if node.is_file() {
+                totals.files += 1;
+                totals.bytes += node.meta.size;
+            }
+        }
+        _ = self.totals.insert(id, totals);
+        Ok(totals)
+    }
+}
+
+/// Reconstruct the summary of snapshots which don't have one and save the modified snapshots.
+///
+/// The file and directory counts and sizes are computed by walking the snapshot trees; each tree is
+/// only read once. Statistics which cannot be reconstructed (e.g. about new or changed files) are left
+/// empty. The original snapshots are replaced by the modified ones, their `original` id is preserved.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `snapshots` - The snapshots to process; snapshots which already have a summary are skipped
+/// * `dry_run` - Don't save the modified snapshots
+///
+/// # Errors
+///
+/// * If the repository is append-only and `dry_run` is not set.
+/// * If a tree could not be read.
+/// * If the snapshots could not be saved or the old snapshots could not be removed.
+///
+/// # Returns
+///
+/// The modified snapshots
+pub(crate) fn backfill_summaries<S: IndexedTree>(
+    repo: &Repository<S>,
+    snapshots: Vec<SnapshotFile>,
+    dry_run: bool,
+) -> RusticResult<Vec<SnapshotFile>> {
+    if !dry_run && repo.config().append_only == Some(true) {
+        return Err(RusticError::new(
+            ErrorKind::AppendOnly,
+            "Replacing snapshots is not allowed in append-only repositories. Please disable append-only mode first, if you know what you are doing. Aborting.",
+        ));
+    }
+    let mut collector = TotalsCollector {
+        repo,
+        totals: BTreeMap::new(),
+    };
+
+    let p = repo.progress_counter("computing snapshot summaries...");
+    let snapshots: Vec<_> = snapshots
+        .into_iter()
+        .filter(|sn| sn.summary.is_none())
+        .collect();
+    p.set_length(snapshots.len() as u64);
+    let snapshots = snapshots
+        .into_iter()
+        .map(|mut sn| {
+            let totals = collector.totals(sn.tree)?;
+            sn.summary = Some(SnapshotSummary {
+                total_files_processed: totals.files,
+                total_bytes_processed: totals.bytes,
+                total_dirs_processed: totals.dirs,
+                total_dirsize_processed: totals.dirsize,
+                backup_start: sn.time.clone(),
+                backup_end: sn.time.clone(),
+                ..Default::default()
+            });
+            p.inc(1);
+            Ok(sn)
+        })
+        .collect::<RusticResult<Vec<_>>>()?;
+    p.finish();
+
+    info!("reconstructed the summary of {} snapshots", snapshots.len());
+    if !dry_run && !snapshots.is_empty() {
+        repo.save_snapshots(snapshots.clone())?;
+        let old_snap_ids: Vec<_> = snapshots.iter().map(|sn| sn.id).collect();
+        repo.delete_snapshots(&old_snap_ids)?;
+    }
+    Ok(snapshots)
+}
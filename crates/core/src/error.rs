@@ -110,6 +110,8 @@ pub enum ErrorKind {
     AppendOnly,
     /// the backend
     Backend,
+    /// a cancelled operation
+    Cancelled,
     /// the configuration
     Configuration,
     /// cryptographic operations
@@ -361,6 +363,22 @@ impl RusticError {
         self.is_code("C002")
     }
 
+    /// Checks if the error is due to another operation running exclusively on the repository or a
+    /// conflicting lock of another process
+    pub fn is_busy(&self) -> bool {
+        self.is_code("R001")
+    }
+
+    /// Checks if the error is due to a cancelled operation, see [`CancellationToken`](crate::CancellationToken)
+    pub fn is_cancelled(&self) -> bool {
+        self.kind == ErrorKind::Cancelled
+    }
+
+    /// Checks if the error has been marked as permanent, i.e. retrying the operation won't help
+    pub fn is_permanent(&self) -> bool {
+        self.status == Some(Status::Permanent)
+    }
+
     /// Creates a new error from a given error.
     pub fn from<T: std::error::Error + Display + Send + Sync + 'static>(
         kind: ErrorKind,
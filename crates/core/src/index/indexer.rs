@@ -1,6 +1,6 @@
 use std::{
     collections::BTreeSet,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, RwLock},
     time::{Duration, SystemTime},
 };
 
@@ -10,7 +10,7 @@ use crate::{
     backend::decrypt::DecryptWriteBackend,
     blob::BlobId,
     error::RusticResult,
-    repofile::indexfile::{IndexFile, IndexPack},
+    repofile::indexfile::{IndexFile, IndexId, IndexPack},
 };
 
 pub(super) mod constants {
@@ -40,6 +40,10 @@ where
     created: SystemTime,
     /// The set of indexed blob ids.
     indexed: Option<BTreeSet<BlobId>>,
+    /// The sequence number to write into the index files.
+    sequence: Option<u64>,
+    /// The ids of the saved index files.
+    saved: Mutex<Vec<IndexId>>,
 }
 
 impl<BE: DecryptWriteBackend> Indexer<BE> {
@@ -59,6 +63,8 @@ impl<BE: DecryptWriteBackend> Indexer<BE> {
             count: 0,
             created: SystemTime::now(),
             indexed: Some(BTreeSet::new()),
+            sequence: None,
+            saved: Mutex::new(Vec::new()),
         }
     }
 
@@ -78,12 +84,29 @@ impl<BE: DecryptWriteBackend> Indexer<BE> {
             count: 0,
             created: SystemTime::now(),
             indexed: None,
+            sequence: None,
+            saved: Mutex::new(Vec::new()),
         }
     }
 
+    /// Sets the sequence number which is written into all index files saved by this `Indexer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - The sequence number to write
+    #[must_use]
+    pub(crate) fn with_sequence(mut self, sequence: Option<u64>) -> Self {
+        self.sequence = sequence;
+        self.file.sequence = sequence;
+        self
+    }
+
     /// Resets the indexer.
     pub fn reset(&mut self) {
-        self.file = IndexFile::default();
+        self.file = IndexFile {
+            sequence: self.sequence,
+            ..Default::default()
+        };
         self.count = 0;
         self.created = SystemTime::now();
     }
@@ -113,11 +136,17 @@ impl<BE: DecryptWriteBackend> Indexer<BE> {
     /// * If the index file could not be serialized.
     pub fn save(&self) -> RusticResult<()> {
         if (self.file.packs.len() + self.file.packs_to_delete.len()) > 0 {
-            _ = self.be.save_file(&self.file)?;
+            let id = self.be.save_file(&self.file)?;
+            self.saved.lock().unwrap().push(IndexId::from(id));
         }
         Ok(())
     }
 
+    /// Returns the ids of all index files saved by this `Indexer`.
+    pub(crate) fn saved_ids(&self) -> Vec<IndexId> {
+        self.saved.lock().unwrap().clone()
+    }
+
     /// Adds a pack to the `Indexer`.
     ///
     /// # Arguments
@@ -0,0 +1,85 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    backend::{FileType, decrypt::DecryptReadBackend},
+    error::{ErrorKind, RusticError, RusticResult},
+    repofile::indexfile::{IndexFile, IndexId},
+};
+
+/// The index files read by an index rewrite (e.g. `prune`), used to detect concurrent index rewrites.
+///
+/// An index rewrite writes its index files with the sequence number [`IndexSequence::next`]. Before it
+/// removes the old index files, [`IndexSequence::check`] verifies that no other process has rewritten the
+/// index in the meantime. This is a cheap optimistic concurrency control which avoids lost updates, e.g.
+/// when two `prune` runs are racing.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IndexSequence {
+    /// The highest sequence number of the read index files
+    sequence: u64,
+    /// The ids of the read index files
+    ids: BTreeSet<IndexId>,
+}
+
+impl IndexSequence {
+    /// Create a new [`IndexSequence`] from the read index files
+    ///
+    /// # Arguments
+    ///
+    /// * `index_files` - The read index files
+    pub(crate) fn new<'a>(
+        index_files: impl IntoIterator<Item = (&'a IndexId, &'a IndexFile)>,
+    ) -> Self {
+        let mut result = Self::default();
+        for (id, file) in index_files {
+            _ = result.ids.insert(*id);
+            result.sequence = result.sequence.max(file.sequence.unwrap_or_default());
+        }
+        result
+    }
+
+    /// Returns the sequence number to use for the rewritten index files
+    pub(crate) const fn next(&self) -> u64 {
+        self.sequence + 1
+    }
+
+    /// Check that the index has not been rewritten by another process
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use
+    /// * `own` - The ids of the index files written by this process
+    ///
+    /// # Errors
+    ///
+    /// * If an index file which has been read is no longer present.
+    /// * If another process wrote an index file with a sequence number at least as high as [`IndexSequence::next`].
+    /// * If the index files could not be listed or read.
+    pub(crate) fn check(&self, be: &impl DecryptReadBackend, own: &[IndexId]) -> RusticResult<()> {
+        let current: BTreeSet<_> = be
+            .list(FileType::Index)?
+            .into_iter()
+            .map(IndexId::from)
+            .collect();
+
+        if let Some(id) = self.ids.difference(&current).next() {
+            return Err(RusticError::new(
+                ErrorKind::Repository,
+                "Index file `{id}` has been removed by another process. The index has been rewritten concurrently; aborting to avoid losing index updates. Please re-run the command.",
+            )
+            .attach_context("id", id.to_string()));
+        }
+
+        for id in current.difference(&self.ids).filter(|id| !own.contains(id)) {
+            let file: IndexFile = be.get_file(id)?;
+            if let Some(sequence) = file.sequence.filter(|seq| *seq >= self.next()) {
+                return Err(RusticError::new(
+                    ErrorKind::Repository,
+                    "Index file `{id}` with sequence number `{sequence}` has been written by another process. The index has been rewritten concurrently; aborting to avoid losing index updates. Please re-run the command.",
+                )
+                .attach_context("id", id.to_string())
+                .attach_context("sequence", sequence.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
@@ -98,8 +98,12 @@ This crate exposes a few features for controlling dependency usage.
   arguments and merging them into one (e.g. `config`). *This feature is disabled
   by default*.
 
-- **webdav** - Enables a dependency on the `dav-server` and `futures` crate.
-  This enables us to run a `WebDAV` server asynchronously on the commandline.
+- **webdav** - Enables a dependency on the `dav-server` and `futures` crate
+  and provides `WebDavFs` to serve a `Vfs` via `WebDAV`. Implies the `async` feature.
+  *This feature is disabled by default*.
+
+- **async** - Enables a dependency on the `tokio` crate and provides async backend
+  traits and the `AsyncRepository` facade for use in async applications.
   *This feature is disabled by default*.
 */
 
@@ -111,16 +115,19 @@ This crate exposes a few features for controlling dependency usage.
 pub(crate) mod archiver;
 pub(crate) mod backend;
 pub(crate) mod blob;
+pub(crate) mod cancel;
 pub(crate) mod chunker;
 pub(crate) mod commands;
 pub(crate) mod crypto;
 pub(crate) mod error;
 pub(crate) mod id;
 pub(crate) mod index;
+pub(crate) mod ndjson;
 pub(crate) mod progress;
 /// Structs which are saved in JSON or binary format in the repository
 pub mod repofile;
 pub(crate) mod repository;
+pub(crate) mod util;
 /// Virtual File System support - allows to act on the repository like on a file system
 pub mod vfs;
 
@@ -128,50 +135,112 @@ pub mod vfs;
 pub use jiff;
 
 // rustic_core Public API
+#[cfg(feature = "async")]
+pub use crate::{
+    backend::async_backend::{AsyncBackend, AsyncReadBackend, AsyncWriteBackend, BlockingBackend},
+    repository::async_repository::AsyncRepository,
+};
 pub use crate::{
     backend::{
         ALL_FILE_TYPES, FileType, ReadBackend, ReadSource, ReadSourceEntry, ReadSourceOpen,
         RepositoryBackends, WriteBackend,
+        bandwidth::{
+            BandwidthLimitBackend, BandwidthLimiter, BandwidthSchedule, BandwidthScheduleEntry,
+        },
         childstdout::ChildStdoutSource,
-        decrypt::{compression_level_range, max_compression_level},
+        decrypt::{
+            ZSTD_WINDOW_LOG_RANGE, ZstdParams, compression_level_range, max_compression_level,
+        },
+        filter::BackupFilter,
         ignore::{LocalSource, LocalSourceFilterOptions, LocalSourceSaveOptions},
         local_destination::LocalDestination,
+        manifest::{ManifestEntry, ManifestSource, ManifestSourceIter},
+        memory::MemorySource,
+        metrics::{
+            BackendCounters, BackendMetrics, BackendMetricsSnapshot, BackendOperation,
+            MetricsBackend, OperationStats,
+        },
+        mirror::{
+            MirrorBackend, MirrorConsistency, MirrorFailure, MirrorMode, MirrorOperation,
+            MirrorStatus,
+        },
         node::{
             last_modified_node,
             modification::{
                 BlockdevOption, DevIdOption, NodeModification, TimeOption, XattrOption,
             },
         },
+        object_lock::{ObjectLockBackend, ObjectLockPolicy},
+        profile::{ReadProfile, ReadProfiler, ReadStats, RepeatedPackRead},
+        reader::ReaderSource,
+        remap::{RemapSource, RemapSourceIter},
+        remote_source::{
+            RemoteOpenFile, RemoteSource, RemoteSourceWalker, SourceEntryKind, SourceFileSystem,
+            SourceMetadata,
+        },
+        retry::{RetryBackend, RetryOptions},
         stdin::StdinSource,
     },
     blob::{
         BlobId, DataId, PackedId,
+        packer::PackSizer,
         tree::{
-            FindMatches, FindNode, TreeId, TreeStreamerOptions as LsOptions, excludes::Excludes,
+            FindMatches, FindNode, TreeId, TreeStreamerOptions as LsOptions,
+            diff::{DiffChange, DiffEntry},
+            excludes::Excludes,
             rewrite::RewriteTreesOptions,
         },
     },
+    cancel::{CancellationToken, PauseHandle},
     commands::{
-        backup::{BackupOptions, ParentOptions},
+        backup::{BackupOptions, ChangedPath, ParentOptions},
         check::{CheckOptions, CheckResults, ReadSubsetOption},
         config::ConfigOptions,
-        copy::CopySnapshot,
-        forget::{ForgetGroup, ForgetGroups, ForgetSnapshot, KeepOptions},
-        key::KeyOptions,
-        prune::{LimitOption, PruneOptions, PrunePlan, PruneStats},
-        repair::{index::RepairIndexOptions, snapshots::RepairSnapshotsOptions},
-        repoinfo::{BlobInfo, IndexInfos, PackInfo, RepoFileInfo, RepoFileInfos},
-        restore::{FileDirStats, RestoreOptions, RestorePlan, RestoreStats},
+        copy::{CopyOptions, CopySnapshot, CopyState},
+        dump::archive::ArchiveFormat,
+        find::FoundNode,
+        forget::{
+            ForgetGroup, ForgetGroups, ForgetOptions, ForgetSnapshot, KeepOptions, RetentionClass,
+            RetentionTags,
+        },
+        index_gc::{IndexGcOptions, IndexGcStats},
+        journal::{DeletionAudit, PendingDeletion},
+        key::{KeyInfo, KeyOptions},
+        maintain::{MaintenanceOptions, MaintenanceReport},
+        merge::MergeConflictPolicy,
+        migrate::{MigrateOptions, MigrationEstimate, MigrationReport},
+        prewarm::{PrewarmHint, PrewarmStats},
+        prune::{PackFill, PackLayoutReport, PruneOptions, PrunePlan, PruneStats},
+        quarantine::{QuarantineOptions, QuarantineReport, QuarantinedPack},
+        references::{BlobOrigin, BlobPath, BlobReferences},
+        rekey::{RekeyOptions, RekeyState},
+        repair::{
+            index::RepairIndexOptions,
+            snapshots::{RepairReport, RepairSnapshotsOptions},
+        },
+        repoinfo::{BlobInfo, IndexInfos, PackInfo, PackListEntry, RepoFileInfo, RepoFileInfos},
+        restore::{
+            FileDirStats, RestoreAction, RestoreDiff, RestoreDiffEntry, RestoreErrorPolicy,
+            RestoreFailure, RestoreManifestEntry, RestoreOptions, RestorePlan, RestoreReport,
+            RestoreStats, RestoreVerification, VerifyRestoreReport,
+        },
         rewrite::RewriteOptions,
+        scrub::ScrubResults,
+        stats::{
+            BlobStats, NodeAggregator, NodeStats, NodeTypeCounts, PackSizeDistribution, RepoStats,
+            SnapshotGroupStats, SnapshotSizeStats, StatsOptions,
+        },
     },
     error::{ErrorKind, RusticError, RusticResult, Severity, Status},
     id::{HexId, Id},
+    ndjson::NdjsonWriter,
     progress::{
-        HiddenProgress, NoProgress, NoProgressBars, Progress, ProgressBars, ProgressType,
-        RusticProgress,
+        HiddenProgress, NoProgress, NoProgressBars, Progress, ProgressBars, ProgressEvent,
+        ProgressEvents, ProgressType, RusticProgress,
     },
     repofile::snapshotfile::{
-        PathList, SnapshotOptions, StringList,
+        ChangeDetection, PathCanonicalization, PathList, PathRemap, SnapshotErrorPolicy,
+        SnapshotOptions, SnapshotPage, SnapshotSort, StringList,
         grouping::{Group, Grouped, SnapshotGroup, SnapshotGroupCriterion},
     },
     repository::{
@@ -179,5 +248,9 @@ pub use crate::{
         IndexedTreesStatus, Open, OpenStatus, Repository, RepositoryOptions,
         command_input::{CommandInput, CommandInputErrorKind},
         credentials::{CredentialOptions, Credentials},
+        lock::{ExclusiveOperation, RepositoryLock},
+        warm_up::{BackendWarmUp, CommandWarmUp, GlacierTier, NoWarmUp, S3GlacierWarmUp, WarmUp},
+        watch::{RepositoryChanges, RepositoryWatcher},
     },
+    util::{DurationOption, LimitOption},
 };
@@ -0,0 +1,290 @@
+//! Streaming output of listings as newline-delimited JSON (ndjson)
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    blob::tree::FindMatches,
+    commands::check::{CheckErrorLevel, CheckResults},
+    error::{ErrorKind, RusticError, RusticResult},
+    repofile::{Node, SnapshotFile, snapshotfile::SnapshotId},
+};
+
+/// An entry of an `ls` listing
+#[derive(Serialize)]
+struct LsEntry<'a> {
+    /// The path of the entry
+    path: &'a Path,
+    /// The node of the entry
+    #[serde(flatten)]
+    node: &'a Node,
+}
+
+/// A match of a `find` search
+#[derive(Serialize)]
+struct FindEntry<'a> {
+    /// The snapshot containing the match
+    snapshot: SnapshotId,
+    /// The path of the match
+    path: &'a Path,
+    /// The matching node
+    node: &'a Node,
+}
+
+/// An issue found by `check`
+#[derive(Serialize)]
+struct CheckIssue<'a> {
+    /// The severity of the issue
+    level: &'a CheckErrorLevel,
+    /// The description of the issue
+    message: String,
+}
+
+/// Writes items as newline-delimited JSON (ndjson), i.e. one JSON value per line.
+///
+/// Items are written as soon as they are given, so large listings can be streamed without
+/// collecting them first. Use a buffered writer (e.g. [`std::io::BufWriter`]) for best performance.
+#[derive(Debug)]
+pub struct NdjsonWriter<W> {
+    /// The writer to write to
+    writer: W,
+    /// The number of written items
+    count: u64,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    /// Create a new [`NdjsonWriter`]
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The writer to write to
+    pub const fn new(writer: W) -> Self {
+        Self { writer, count: 0 }
+    }
+
+    /// Returns the number of items written so far
+    pub const fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Write a single item as one line.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The item to write
+    ///
+    /// # Errors
+    ///
+    /// * If the item could not be serialized or written.
+    pub fn write<T: Serialize + ?Sized>(&mut self, item: &T) -> RusticResult<()> {
+        serde_json::to_writer(&mut self.writer, item).map_err(|err| {
+            RusticError::with_source(ErrorKind::InputOutput, "Failed to write JSON item.", err)
+        })?;
+        self.writer.write_all(b"\n").map_err(|err| {
+            RusticError::with_source(ErrorKind::InputOutput, "Failed to write JSON item.", err)
+        })?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Write all items of an iterator, one per line.
+    ///
+    /// # Arguments
+    ///
+    /// * `items` - The items to write
+    ///
+    /// # Errors
+    ///
+    /// * If an item is an error; the items before are already written.
+    /// * If an item could not be serialized or written.
+    pub fn write_all<T: Serialize>(
+        &mut self,
+        items: impl IntoIterator<Item = RusticResult<T>>,
+    ) -> RusticResult<()> {
+        for item in items {
+            self.write(&item?)?;
+        }
+        Ok(())
+    }
+
+    /// Write a snapshot listing, one snapshot per line.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshots` - The snapshots to write
+    ///
+    /// # Errors
+    ///
+    /// * If a snapshot could not be serialized or written.
+    pub fn write_snapshots<'a>(
+        &mut self,
+        snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
+    ) -> RusticResult<()> {
+        for snap in snapshots {
+            self.write(snap)?;
+        }
+        Ok(())
+    }
+
+    /// Write an `ls` listing, one entry per line containing the path and the flattened node.
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The entries to write, e.g. as returned by [`crate::Repository::ls`]
+    ///
+    /// # Errors
+    ///
+    /// * If an entry is an error; the entries before are already written.
+    /// * If an entry could not be serialized or written.
+    pub fn write_ls(
+        &mut self,
+        entries: impl IntoIterator<Item = RusticResult<(PathBuf, Node)>>,
+    ) -> RusticResult<()> {
+        for entry in entries {
+            let (path, node) = entry?;
+            self.write(&LsEntry {
+                path: &path,
+                node: &node,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Write the matches of a `find` search, one match per line containing the snapshot, path and node.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshots` - The searched snapshots, in the order of the trees given to [`crate::Repository::find_matching_nodes`]
+    /// * `matches` - The found matches
+    ///
+    /// # Errors
+    ///
+    /// * If a match could not be serialized or written.
+    pub fn write_find_matches(
+        &mut self,
+        snapshots: &[SnapshotFile],
+        matches: &FindMatches,
+    ) -> RusticResult<()> {
+        for (snap, snap_matches) in snapshots.iter().zip(&matches.matches) {
+            for (path_idx, node_idx) in snap_matches {
+                self.write(&FindEntry {
+                    snapshot: snap.id,
+                    path: &matches.paths[*path_idx],
+                    node: &matches.nodes[*node_idx],
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the issues found by `check`, one issue per line containing the level and the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `results` - The check results to write
+    ///
+    /// # Errors
+    ///
+    /// * If an issue could not be serialized or written.
+    pub fn write_check_results(&mut self, results: &CheckResults) -> RusticResult<()> {
+        for (level, err) in &results.0 {
+            self.write(&CheckIssue {
+                level,
+                message: err.to_string(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Flush and return the underlying writer
+    ///
+    /// # Errors
+    ///
+    /// * If the writer could not be flushed.
+    pub fn into_inner(mut self) -> RusticResult<W> {
+        self.writer.flush().map_err(|err| {
+            RusticError::with_source(ErrorKind::InputOutput, "Failed to flush JSON output.", err)
+        })?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        backend::node::{Metadata, NodeType},
+        commands::check::CheckError,
+        repofile::packfile::PackId,
+    };
+
+    #[test]
+    fn test_write_ls() {
+        let node = Node::new_node(
+            "file".as_ref(),
+            NodeType::File,
+            Metadata {
+                size: 5,
+                ..Default::default()
+            },
+        );
+        let mut writer = NdjsonWriter::new(Vec::new());
+        writer
+            .write_ls([
+                Ok((PathBuf::from("dir/file"), node.clone())),
+                Ok((PathBuf::from("dir/file2"), node)),
+            ])
+            .unwrap();
+        assert_eq!(writer.count(), 2);
+
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["path"], "dir/file");
+        assert_eq!(lines[0]["name"], "file");
+        assert_eq!(lines[0]["type"], "file");
+        assert_eq!(lines[1]["path"], "dir/file2");
+    }
+
+    #[test]
+    fn test_write_all_stops_at_error() {
+        let mut writer = NdjsonWriter::new(Vec::new());
+        let items = [
+            Ok(1),
+            Err(RusticError::new(ErrorKind::Other, "failed")),
+            Ok(3),
+        ];
+        assert!(writer.write_all(items).is_err());
+        assert_eq!(writer.count(), 1);
+        assert_eq!(writer.into_inner().unwrap(), b"1\n");
+    }
+
+    #[test]
+    fn test_write_check_results() {
+        let results = CheckResults(vec![(
+            CheckErrorLevel::Warn,
+            CheckError::PackTimeNotSet {
+                id: PackId::default(),
+            },
+        )]);
+        let mut writer = NdjsonWriter::new(Vec::new());
+        writer.write_check_results(&results).unwrap();
+
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let issue: serde_json::Value = serde_json::from_str(output.trim_end()).unwrap();
+        assert_eq!(issue["level"], "warn");
+        assert!(
+            issue["message"]
+                .as_str()
+                .unwrap()
+                .contains("No time is set")
+        );
+    }
+}
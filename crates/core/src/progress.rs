@@ -1,6 +1,11 @@
-use std::sync::Arc;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+    mpsc::{Receiver, Sender, channel},
+};
 
 use log::info;
+use serde_derive::Serialize;
 
 /// A progress used to indicate/update the status of something which is being processed
 #[derive(Debug, Clone)]
@@ -90,7 +95,8 @@ pub trait RusticProgress: Send + Sync + 'static + std::fmt::Debug {
 }
 
 /// Type of progress
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ProgressType {
     /// a progress spinner. Note that this progress doesn't get a length and is not advanced, only finished.
     Spinner,
@@ -154,3 +160,152 @@ impl ProgressBars for NoProgressBars {
         Progress::new(NoProgress)
     }
 }
+
+/// A structured progress event emitted by [`ProgressEvents`]
+///
+/// All events of one progress share the same `id`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ProgressEvent {
+    /// A new progress (i.e. a phase of an action) has been started
+    Started {
+        /// The id of the progress
+        id: u64,
+        /// The type of the progress
+        progress_type: ProgressType,
+        /// The prefix of the progress, e.g. "reading index..."
+        prefix: String,
+    },
+    /// The title of the progress has been set
+    Title {
+        /// The id of the progress
+        id: u64,
+        /// The new title
+        title: String,
+    },
+    /// The total number of items or bytes has been set
+    Total {
+        /// The id of the progress
+        id: u64,
+        /// The total number of items or bytes
+        total: u64,
+    },
+    /// The progress has been advanced
+    Advanced {
+        /// The id of the progress
+        id: u64,
+        /// The increment
+        inc: u64,
+        /// The number of items or bytes done so far
+        done: u64,
+    },
+    /// The progress has been finished
+    Finished {
+        /// The id of the progress
+        id: u64,
+        /// The number of items or bytes done
+        done: u64,
+    },
+}
+
+/// Emit typed [`ProgressEvent`]s over a channel instead of displaying progress bars.
+///
+/// This allows GUIs or daemons to render or forward progress information. Events are dropped
+/// if the receiver has been dropped.
+#[derive(Debug, Clone)]
+pub struct ProgressEvents {
+    /// The sender for the events
+    sender: Sender<ProgressEvent>,
+    /// The id of the next started progress
+    next_id: Arc<AtomicU64>,
+}
+
+impl ProgressEvents {
+    /// Create a new `ProgressEvents` together with the receiver of the events
+    #[must_use]
+    pub fn new() -> (Self, Receiver<ProgressEvent>) {
+        let (sender, receiver) = channel();
+        (Self::with_sender(sender), receiver)
+    }
+
+    /// Create a new `ProgressEvents` sending events to the given sender
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - The sender for the events
+    #[must_use]
+    pub fn with_sender(sender: Sender<ProgressEvent>) -> Self {
+        Self {
+            sender,
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl ProgressBars for ProgressEvents {
+    fn progress(&self, progress_type: ProgressType, prefix: &str) -> Progress {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let progress = EventProgress {
+            id,
+            sender: self.sender.clone(),
+            done: AtomicU64::new(0),
+        };
+        progress.send(ProgressEvent::Started {
+            id,
+            progress_type,
+            prefix: prefix.to_string(),
+        });
+        Progress::new(progress)
+    }
+}
+
+/// A progress emitting [`ProgressEvent`]s, see [`ProgressEvents`]
+#[derive(Debug)]
+struct EventProgress {
+    /// The id of this progress
+    id: u64,
+    /// The sender for the events
+    sender: Sender<ProgressEvent>,
+    /// The number of items or bytes done so far
+    done: AtomicU64,
+}
+
+impl EventProgress {
+    /// Send an event, ignoring a dropped receiver
+    fn send(&self, event: ProgressEvent) {
+        _ = self.sender.send(event);
+    }
+}
+
+impl RusticProgress for EventProgress {
+    fn is_hidden(&self) -> bool {
+        false
+    }
+    fn set_length(&self, len: u64) {
+        self.send(ProgressEvent::Total {
+            id: self.id,
+            total: len,
+        });
+    }
+    fn set_title(&self, title: &str) {
+        self.send(ProgressEvent::Title {
+            id: self.id,
+            title: title.to_string(),
+        });
+    }
+    fn inc(&self, inc: u64) {
+        let done = self.done.fetch_add(inc, Ordering::Relaxed) + inc;
+        self.send(ProgressEvent::Advanced {
+            id: self.id,
+            inc,
+            done,
+        });
+    }
+    fn finish(&self) {
+        self.send(ProgressEvent::Finished {
+            id: self.id,
+            done: self.done.load(Ordering::Relaxed),
+        });
+    }
+}
@@ -11,8 +11,11 @@ use serde_with::{DeserializeAs, SerializeAs};
 
 pub(crate) mod configfile;
 pub(crate) mod indexfile;
+pub(crate) mod journalfile;
 pub(crate) mod keyfile;
+pub(crate) mod lockfile;
 pub(crate) mod packfile;
+pub(crate) mod scrubfile;
 pub(crate) mod snapshotfile;
 
 /// Marker trait for repository files which are stored as JSON
@@ -23,6 +26,13 @@ pub trait RepoFile: Serialize + DeserializeOwned + Sized + Send + Sync + 'static
     const ENCRYPTED: bool = true;
     /// The Id type associated with the repository file
     type Id: RepoId;
+
+    /// The time until the file should be protected from removal, if any.
+    ///
+    /// Backends supporting object locks lock the file until this time when saving it.
+    fn retain_until(&self) -> Option<Timestamp> {
+        None
+    }
 }
 
 /// Marker trait for Ids which identify repository files
@@ -154,16 +164,21 @@ pub use {
     crate::{
         backend::{
             ALL_FILE_TYPES, FileType,
-            node::{Metadata, Node, NodeType},
+            node::{ExtendedAttribute, Metadata, Node, NodeType},
         },
         blob::{ALL_BLOB_TYPES, BlobType, tree::Tree},
     },
     configfile::{Chunker, ConfigFile},
     indexfile::{IndexBlob, IndexFile, IndexId, IndexPack},
+    journalfile::{JournalFile, JournalId},
     keyfile::{KeyFile, KeyId, MasterKey},
-    packfile::{HeaderEntry, PackHeader, PackHeaderLength, PackHeaderRef, PackId},
+    lockfile::{LockFile, LockId, STALE_LOCK_TIMEOUT},
+    packfile::{
+        HeaderEntry, PackFooter, PackHeader, PackHeaderLength, PackHeaderRef, PackId, PackLayout,
+    },
+    scrubfile::{ScrubFile, ScrubId},
     snapshotfile::{
-        DeleteOption, PathList, SnapshotFile, SnapshotId, SnapshotModification, SnapshotSummary,
-        StringList,
+        DeleteOption, PathList, SnapshotFile, SnapshotFilter, SnapshotId, SnapshotModification,
+        SnapshotRoot, SnapshotSummary, StringList,
     },
 };
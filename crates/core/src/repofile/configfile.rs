@@ -4,12 +4,12 @@ use serde_derive::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 
 use crate::{
-    backend::FileType,
+    backend::{FileType, decrypt::ZstdParams},
     blob::BlobType,
     define_new_id_struct,
     error::{ErrorKind, RusticError, RusticResult},
     impl_repofile,
-    repofile::RepoFile,
+    repofile::{RepoFile, packfile::PackLayout},
 };
 
 pub(super) mod constants {
@@ -59,20 +59,21 @@ pub struct ConfigFile {
     /// The chunker polynomial used to chunk data
     pub chunker: Option<Chunker>,
 
-    /// The chunker polynomial used to chunk data in case of Rabin content defined chunking
+    /// The chunker polynomial used to chunk data in case of Rabin content defined chunking.
+    /// For `FastCdc` chunking, this is used as seed of the gear hash.
     pub chunker_polynomial: String,
 
-    /// The (average) chunk size. For `FixedSized` chunking, this is the chunk size, for Rabin chunking, this size
-    /// will be reached on average for chunks.
+    /// The (average) chunk size. For `FixedSized` chunking, this is the chunk size, for Rabin and `FastCdc` chunking,
+    /// this size will be reached on average for chunks.
     pub chunk_size: Option<usize>,
 
-    /// The minimum chunk size. For Rabin chunking, this defines the minimum chunk size before chunks are defined by
-    /// the Rabin fingerprint.
+    /// The minimum chunk size. For Rabin and `FastCdc` chunking, this defines the minimum chunk size before chunks are
+    /// defined by the fingerprint.
     /// Has no effect for `FixedSized` chunking.
     pub chunk_min_size: Option<usize>,
 
-    /// The maximum chunk size. For Rabin chunking, this defines the maximum chunk size, i.e. the size when chunks are cut
-    /// even if no cut point has been identified by the Rabin fingerprint.
+    /// The maximum chunk size. For Rabin and `FastCdc` chunking, this defines the maximum chunk size, i.e. the size when
+    /// chunks are cut even if no cut point has been identified by the fingerprint.
     /// Has no effect for `FixedSized` chunking.
     pub chunk_max_size: Option<usize>,
 
@@ -90,6 +91,14 @@ pub struct ConfigFile {
     /// Commands which are not append-only won't run once this is set.
     pub append_only: Option<bool>,
 
+    /// Marker if a re-encryption of the repository using a new master key is in progress.
+    ///
+    /// # Note
+    ///
+    /// The already re-encrypted packs are not contained in the index, so commands which remove or
+    /// re-index unindexed packs won't run once this is set.
+    pub rekey_in_progress: Option<bool>,
+
     /// Compression level
     ///
     /// # Note
@@ -99,6 +108,30 @@ pub struct ConfigFile {
     /// * for repository version 2, use the zstd default compression
     pub compression: Option<i32>,
 
+    /// Enable zstd long-distance matching
+    ///
+    /// # Note
+    ///
+    /// This improves the compression of large blobs with repeated content far apart. It has no effect if
+    /// compression is disabled. If not set, long-distance matching is disabled.
+    pub compression_long: Option<bool>,
+
+    /// Window log used for zstd compression, i.e. the maximum distance of matches is `2^window_log` bytes
+    ///
+    /// # Note
+    ///
+    /// It has no effect if compression is disabled. If not set, zstd chooses the window log depending on the
+    /// compression level.
+    pub compression_window_log: Option<u32>,
+
+    /// Store blobs uncompressed if a sample of them doesn't compress
+    ///
+    /// # Note
+    ///
+    /// This saves CPU time for already compressed data like images or videos. It has no effect if compression is
+    /// disabled. If not set, all blobs are compressed.
+    pub compression_skip_incompressible: Option<bool>,
+
     /// Size of tree packs. This will be enhanced by the `treepack_growfactor` depending on the repository size
     ///
     /// If not set, defaults to 4 MiB
@@ -137,6 +170,14 @@ pub struct ConfigFile {
 
     /// Do an extra verification by decompressing/decrypting all data before uploading to the repository
     pub extra_verify: Option<bool>,
+
+    /// Experimental: Align blob starts within pack files to multiples of this size
+    ///
+    /// # Note
+    ///
+    /// If set, new pack files are written using the aligned [`PackLayout`] which is not compatible with restic.
+    /// If not set, pack files use the restic layout.
+    pub pack_alignment: Option<u32>,
 }
 
 impl ConfigFile {
@@ -192,6 +233,25 @@ impl ConfigFile {
         }
     }
 
+    /// Get the advanced zstd compression parameters
+    #[must_use]
+    pub fn zstd_params(&self) -> ZstdParams {
+        ZstdParams {
+            long_distance_matching: self.compression_long.unwrap_or_default(),
+            window_log: self.compression_window_log,
+            skip_incompressible: self.compression_skip_incompressible.unwrap_or_default(),
+        }
+    }
+
+    /// Get the layout used to write pack files
+    #[must_use]
+    pub fn pack_layout(&self) -> PackLayout {
+        match self.pack_alignment {
+            None | Some(0) => PackLayout::Restic,
+            Some(alignment) => PackLayout::Aligned { alignment },
+        }
+    }
+
     /// Get whether an extra verification (decompressing/decrypting data before writing to the repository) should be performed.
     #[must_use]
     pub fn extra_verify(&self) -> bool {
@@ -274,7 +334,7 @@ impl ConfigFile {
     pub fn has_same_chunker(&self, other: &Self) -> bool {
         match self.chunker() {
             chunker if chunker != other.chunker() => false,
-            Chunker::Rabin => {
+            Chunker::Rabin | Chunker::FastCdc => {
                 self.chunker_polynomial == other.chunker_polynomial
                     && self.chunk_size() == other.chunk_size()
                     && self.chunk_min_size() == other.chunk_min_size()
@@ -291,6 +351,8 @@ pub enum Chunker {
     #[default]
     /// Rabin chunker - a content defined chunker (CDC) based on Rabin fingerprints
     Rabin,
+    /// `FastCDC` chunker - a content defined chunker (CDC) based on a gear hash using normalized chunking
+    FastCdc,
     /// Fixed size chunker - makes chunks of a given fixed size
     FixedSize,
 }
@@ -300,10 +362,11 @@ impl FromStr for Chunker {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "rabin" => Ok(Self::Rabin),
+            "fastcdc" => Ok(Self::FastCdc),
             "fixed_size" => Ok(Self::FixedSize),
             _ => Err(RusticError::new(
                 ErrorKind::InvalidInput,
-                "only ``rabin`` and ``fixed_size`` are valid chunkers",
+                "only ``rabin``, ``fastcdc`` and ``fixed_size`` are valid chunkers",
             )),
         }
     }
@@ -29,6 +29,8 @@ pub struct IndexFile {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     /// Index information about unused packs which are already marked for deletion
     pub packs_to_delete: Vec<IndexPack>,
+    /// The sequence number of the index rewrite which wrote this file; used to detect concurrent index rewrites
+    pub sequence: Option<u64>,
 }
 
 impl RepoFile for IndexFile {
@@ -0,0 +1,23 @@
+use jiff::Timestamp;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{backend::FileType, commands::prune::PruneOptions, impl_repofile, repofile::RepoFile};
+
+use super::packfile::PackId;
+
+impl_repofile!(JournalId, FileType::Journal, JournalFile);
+
+/// Deletion journals record which packs have been marked for deletion by a `prune` run.
+///
+/// They are usually stored in the repository under `/journal/<ID>` and allow to audit pending deletions
+/// and to cancel them before the marked packs are finally removed. As `journal/` is no part of the
+/// restic repository layout, deletion journals are only written if the backend supports them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalFile {
+    /// The time the packs have been marked for deletion
+    pub time: Timestamp,
+    /// The packs which have been marked for deletion
+    pub packs: Vec<PackId>,
+    /// The options of the `prune` run which marked the packs
+    pub options: PruneOptions,
+}
@@ -1,4 +1,5 @@
 use jiff::Zoned;
+use log::debug;
 use rand::{Rng, rng};
 use scrypt::Params;
 use serde_derive::{Deserialize, Serialize};
@@ -202,9 +203,45 @@ impl KeyFile {
         hostname: Option<String>,
         username: Option<String>,
         with_created: bool,
+    ) -> RusticResult<Self> {
+        Self::generate_with_params(
+            key,
+            passwd,
+            hostname,
+            username,
+            with_created,
+            Params::RECOMMENDED,
+        )
+    }
+
+    /// Generate a new [`KeyFile`] from a given key and password using the given `scrypt` parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key to use for encryption
+    /// * `passwd` - The password to use for the key derivation function
+    /// * `hostname` - The hostname to use for the [`KeyFile`]
+    /// * `username` - The username to use for the [`KeyFile`]
+    /// * `with_created` - Whether to set the creation time of the [`KeyFile`] to the current time
+    /// * `params` - The parameters of the key derivation function
+    ///
+    /// # Errors
+    ///
+    /// * If the output length of the key derivation function is invalid
+    /// * If the [`KeyFile`] could not be serialized
+    ///
+    /// # Returns
+    ///
+    /// The generated [`KeyFile`]
+    pub(crate) fn generate_with_params(
+        key: Key,
+        passwd: &impl AsRef<[u8]>,
+        hostname: Option<String>,
+        username: Option<String>,
+        with_created: bool,
+        params: Params,
     ) -> RusticResult<Self> {
         let masterkey = MasterKey::from_key(key);
-        let params = Params::RECOMMENDED;
         let mut salt = vec![0; 64];
         rng().fill_bytes(&mut salt);
 
@@ -393,6 +430,7 @@ pub(crate) fn key_from_backend<B: ReadBackend>(
 /// * `be` - The backend to use
 /// * `passwd` - The password to use
 /// * `hint` - The key hint to use
+/// * `fits` - Keys for which this returns `false` are skipped, unless given as hint
 ///
 /// # Errors
 ///
@@ -405,13 +443,15 @@ pub(crate) fn find_key_in_backend<B: ReadBackend>(
     be: &B,
     passwd: &impl AsRef<[u8]>,
     hint: Option<&KeyId>,
+    fits: impl Fn(&Key) -> bool,
 ) -> RusticResult<(Key, KeyId)> {
     if let Some(id) = hint {
         Ok((key_from_backend(be, id, passwd)?, *id))
     } else {
         for id in be.list(FileType::Key)? {
             match key_from_backend(be, &id.into(), passwd) {
-                Ok(key) => return Ok((key, KeyId(id))),
+                Ok(key) if fits(&key) => return Ok((key, KeyId(id))),
+                Ok(_) => debug!("skipping key {id} which doesn't fit"),
                 Err(err) if err.is_code("C001") => {}
                 Err(err) => return Err(err),
             }
@@ -0,0 +1,150 @@
+use gethostname::gethostname;
+use jiff::{SignedDuration, Timestamp};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{backend::FileType, impl_repofile, repofile::RepoFile};
+
+impl_repofile!(LockId, FileType::Lock, LockFile);
+
+/// Locks which have not been refreshed for this time are considered stale
+pub const STALE_LOCK_TIMEOUT: SignedDuration = SignedDuration::from_mins(30);
+
+/// Lock files announce that a process is working on the repository.
+///
+/// They are usually stored in the repository under `/locks/<ID>` and are compatible with the lock
+/// files written by restic. Exclusive locks are used by operations which remove data (e.g. `prune`),
+/// shared locks by operations which only add or read data (e.g. `backup`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockFile {
+    /// The time the lock has been created or refreshed
+    pub time: Timestamp,
+    /// Whether the lock is exclusive
+    #[serde(default)]
+    pub exclusive: bool,
+    /// The host of the process holding the lock
+    #[serde(default)]
+    pub hostname: String,
+    /// The user running the process holding the lock
+    #[serde(default)]
+    pub username: String,
+    /// The process id of the process holding the lock
+    #[serde(default)]
+    pub pid: u32,
+    /// The user id of the process holding the lock
+    #[serde(default)]
+    pub uid: u32,
+    /// The group id of the process holding the lock
+    #[serde(default)]
+    pub gid: u32,
+}
+
+impl LockFile {
+    /// Create a new [`LockFile`] for the current process
+    ///
+    /// # Arguments
+    ///
+    /// * `exclusive` - Whether the lock is exclusive
+    #[must_use]
+    pub fn new(exclusive: bool) -> Self {
+        #[cfg(not(windows))]
+        let (username, uid, gid) = {
+            use nix::unistd::{User, getgid, getuid};
+
+            let (uid, gid) = (getuid(), getgid());
+            let username = User::from_uid(uid)
+                .ok()
+                .flatten()
+                .map(|user| user.name)
+                .unwrap_or_default();
+            (username, uid.as_raw(), gid.as_raw())
+        };
+        #[cfg(windows)]
+        let (username, uid, gid) = (String::new(), 0, 0);
+
+        Self {
+            time: Timestamp::now(),
+            exclusive,
+            hostname: gethostname().to_string_lossy().into_owned(),
+            username,
+            pid: std::process::id(),
+            uid,
+            gid,
+        }
+    }
+
+    /// Returns `true` if the lock is stale, i.e. it is older than [`STALE_LOCK_TIMEOUT`] or the
+    /// process holding it is known to be no longer running.
+    ///
+    /// Processes can only be checked for locks of the current host.
+    ///
+    /// # Arguments
+    ///
+    /// * `hostname` - The name of the current host
+    /// * `now` - The current time
+    #[must_use]
+    pub fn is_stale(&self, hostname: &str, now: Timestamp) -> bool {
+        if now.duration_since(self.time) > STALE_LOCK_TIMEOUT {
+            return true;
+        }
+        if self.hostname != hostname {
+            return false;
+        }
+        #[cfg(not(windows))]
+        {
+            use nix::{errno::Errno, sys::signal::kill, unistd::Pid};
+
+            let Ok(pid) = i32::try_from(self.pid) else {
+                return true;
+            };
+            matches!(kill(Pid::from_raw(pid), None), Err(Errno::ESRCH))
+        }
+        #[cfg(windows)]
+        {
+            false
+        }
+    }
+
+    /// Returns `true` if this lock conflicts with the given lock, i.e. one of them is exclusive
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The other lock
+    #[must_use]
+    pub const fn conflicts_with(&self, other: &Self) -> bool {
+        self.exclusive || other.exclusive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_stale() {
+        let lock = LockFile::new(false);
+        let now = Timestamp::now();
+        assert!(!lock.is_stale(&lock.hostname, now));
+        assert!(!lock.is_stale("other-host", now));
+        let later = now + STALE_LOCK_TIMEOUT + SignedDuration::from_secs(1);
+        assert!(lock.is_stale("other-host", later));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let (shared, exclusive) = (LockFile::new(false), LockFile::new(true));
+        assert!(!shared.conflicts_with(&shared));
+        assert!(shared.conflicts_with(&exclusive));
+        assert!(exclusive.conflicts_with(&shared));
+        assert!(exclusive.conflicts_with(&exclusive));
+    }
+
+    #[test]
+    fn test_deserialize_restic_lock() {
+        let json = r#"{"time":"2024-01-02T03:04:05.123456789+01:00","exclusive":true,"hostname":"host","username":"user","pid":42,"uid":1000,"gid":1000}"#;
+        let lock: LockFile = serde_json::from_str(json).unwrap();
+        assert!(lock.exclusive);
+        assert_eq!(lock.hostname, "host");
+        assert_eq!(lock.pid, 42);
+        assert_eq!(lock.time, "2024-01-02T02:04:05.123456789Z".parse().unwrap());
+    }
+}
@@ -1,10 +1,11 @@
 use std::num::NonZeroU32;
 
 use binrw::{BinRead, BinWrite, io::Cursor};
-use log::trace;
+use bytes::{Bytes, BytesMut};
+use log::{trace, warn};
 
 use crate::{
-    backend::{FileType, decrypt::DecryptReadBackend},
+    backend::{FileType, cache::Cache, decrypt::DecryptReadBackend},
     blob::{BlobLocation, BlobType},
     error::{ErrorKind, RusticError, RusticResult},
     id::Id,
@@ -20,6 +21,8 @@ pub enum PackFileErrorKind {
     ReadingBinaryRepresentationFailed(binrw::Error),
     /// Failed writing binary representation of the pack header: `{0:?}`
     WritingBinaryRepresentationFailed(binrw::Error),
+    /// Pack footer uses an invalid alignment `{0}`
+    InvalidAlignment(u32),
 }
 
 pub(crate) type PackFileResult<T> = Result<T, PackFileErrorKind>;
@@ -33,6 +36,158 @@ pub(super) mod constants {
     pub(super) const COMP_OVERHEAD: u32 = 32;
     /// The length of the length field within the pack header
     pub(super) const LENGTH_LEN: u32 = 4;
+    /// The length of the footer of packs using the aligned layout
+    pub(super) const FOOTER_LEN: u32 = 16;
+    /// The magic bytes ending packs using the aligned layout
+    pub(super) const FOOTER_MAGIC: [u8; 4] = *b"RPK3";
+}
+
+/// The layout of pack files
+///
+/// # Note
+///
+/// Only [`PackLayout::Restic`] is compatible with restic. The aligned layout is experimental and only
+/// written if enabled in the repository config, see [`ConfigFile::pack_alignment`](crate::repofile::ConfigFile::pack_alignment).
+/// Reading packs detects the layout from the end of the pack file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PackLayout {
+    /// The restic layout: Blobs are stored without gaps, followed by the encrypted pack header and the
+    /// header length.
+    #[default]
+    Restic,
+    /// Experimental aligned layout: Blob starts are aligned to multiples of `alignment` by zero padding.
+    /// The encrypted pack header is followed by a [`PackFooter`] instead of the header length.
+    Aligned {
+        /// The alignment of blob starts in bytes
+        alignment: u32,
+    },
+}
+
+impl PackLayout {
+    /// The padding needed before a blob starting at the given offset
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The position in the pack file after the previous blob
+    #[must_use]
+    pub(crate) const fn padding(self, offset: u32) -> u32 {
+        match self {
+            Self::Restic => 0,
+            Self::Aligned { alignment } => (alignment - offset % alignment) % alignment,
+        }
+    }
+
+    /// The length of the data following the encrypted pack header
+    #[must_use]
+    pub(crate) const fn trailer_len(self) -> u32 {
+        match self {
+            Self::Restic => constants::LENGTH_LEN,
+            Self::Aligned { .. } => constants::FOOTER_LEN,
+        }
+    }
+
+    /// Read the layout and the pack header length from the end of a pack file
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The end of the pack file; this must contain at least the last 16 bytes of the pack file
+    ///   or the complete pack file
+    ///
+    /// # Errors
+    ///
+    /// * If reading the binary representation failed
+    /// * If the footer contains an invalid alignment
+    ///
+    /// # Returns
+    ///
+    /// The layout, the header length and - for the aligned layout - the number of blobs in the pack
+    pub(crate) fn from_pack_end(data: &[u8]) -> PackFileResult<(Self, u32, Option<u32>)> {
+        if data.ends_with(&constants::FOOTER_MAGIC) && data.len() >= constants::FOOTER_LEN as usize
+        {
+            let footer =
+                PackFooter::from_binary(&data[data.len() - constants::FOOTER_LEN as usize..])?;
+            if footer.alignment == 0 {
+                return Err(PackFileErrorKind::InvalidAlignment(footer.alignment));
+            }
+            let layout = Self::Aligned {
+                alignment: footer.alignment,
+            };
+            return Ok((layout, footer.header_len, Some(footer.blob_count)));
+        }
+        let start = data.len().saturating_sub(constants::LENGTH_LEN as usize);
+        let header_len = PackHeaderLength::from_binary(&data[start..])?.to_u32();
+        Ok((Self::Restic, header_len, None))
+    }
+
+    /// Generate the binary representation of the data following the encrypted pack header
+    ///
+    /// # Arguments
+    ///
+    /// * `header_len` - The length of the encrypted pack header
+    /// * `blob_count` - The number of blobs in the pack
+    ///
+    /// # Errors
+    ///
+    /// * If writing the binary representation failed
+    pub(crate) fn trailer(self, header_len: u32, blob_count: u32) -> PackFileResult<Vec<u8>> {
+        match self {
+            Self::Restic => PackHeaderLength::from_u32(header_len).to_binary(),
+            Self::Aligned { alignment } => PackFooter {
+                header_len,
+                blob_count,
+                alignment,
+                magic: constants::FOOTER_MAGIC,
+            }
+            .to_binary(),
+        }
+    }
+}
+
+/// The footer of packs using the aligned [`PackLayout`]
+///
+/// The footer summarizes the pack, so that readers can locate the pack header and know the number of blobs
+/// and their alignment without decrypting the header. It ends with magic bytes which restic interprets
+/// as a (too large) header length, so restic refuses to read these packs.
+#[derive(BinWrite, BinRead, Debug, Clone, Copy)]
+#[brw(little)]
+pub struct PackFooter {
+    /// The length of the encrypted pack header
+    pub header_len: u32,
+    /// The number of blobs in the pack
+    pub blob_count: u32,
+    /// The alignment of blob starts in bytes
+    pub alignment: u32,
+    /// The magic bytes `RPK3`
+    pub magic: [u8; 4],
+}
+
+impl PackFooter {
+    /// Read pack footer from binary representation
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The binary representation of the pack footer
+    ///
+    /// # Errors
+    ///
+    /// * If reading the binary representation failed
+    pub(crate) fn from_binary(data: &[u8]) -> PackFileResult<Self> {
+        let mut reader = Cursor::new(data);
+        Self::read(&mut reader).map_err(PackFileErrorKind::ReadingBinaryRepresentationFailed)
+    }
+
+    /// Generate the binary representation of the pack footer
+    ///
+    /// # Errors
+    ///
+    /// * If writing the binary representation failed
+    pub(crate) fn to_binary(self) -> PackFileResult<Vec<u8>> {
+        let mut writer = Cursor::new(Vec::with_capacity(constants::FOOTER_LEN as usize));
+        self.write(&mut writer)
+            .map_err(PackFileErrorKind::WritingBinaryRepresentationFailed)?;
+        Ok(writer.into_inner())
+    }
 }
 
 /// The length field within the pack header (which is the total length of the pack header)
@@ -221,17 +376,21 @@ impl PackHeader {
     /// # Arguments
     ///
     /// * `pack` - The binary representation of the pack header
+    /// * `layout` - The layout of the pack file
     ///
     /// # Errors
     ///
     /// * If reading the binary representation failed
-    pub(crate) fn from_binary(pack: &[u8]) -> PackFileResult<Self> {
+    pub(crate) fn from_binary(pack: &[u8], layout: PackLayout) -> PackFileResult<Self> {
         let mut reader = Cursor::new(pack);
         let mut offset = 0;
         let mut blobs = Vec::new();
         loop {
             let blob = match HeaderEntry::read(&mut reader) {
-                Ok(entry) => entry.into_blob(offset),
+                Ok(entry) => {
+                    offset += layout.padding(offset);
+                    entry.into_blob(offset)
+                }
                 Err(err) if err.is_eof() => break,
                 Err(err) => return Err(PackFileErrorKind::ReadingBinaryRepresentationFailed(err)),
             };
@@ -243,9 +402,13 @@ impl PackHeader {
 
     /// Read the pack header directly from a packfile using the backend
     ///
+    /// If a cache is given, the pack header is read from the cache if possible and added to the cache
+    /// after it has been read from the backend.
+    ///
     /// # Arguments
     ///
     /// * `be` - The backend to use
+    /// * `cache` - The cache to use, if any
     /// * `id` - The id of the packfile
     /// * `size_hint` - The size hint for the pack header
     /// * `pack_size` - The size of the packfile
@@ -258,53 +421,105 @@ impl PackHeader {
     /// * If the pack size computed from the header does not match the real pack file size
     pub(crate) fn from_file(
         be: &impl DecryptReadBackend,
+        cache: Option<&Cache>,
         id: PackId,
         size_hint: Option<u32>,
         pack_size: u32,
     ) -> RusticResult<Self> {
+        if let Some(cache) = cache {
+            match cache.read_pack_header(&id) {
+                Ok(Some(end)) => match Self::from_file_end(be, id, end, pack_size) {
+                    Ok((header, _)) => return Ok(header),
+                    Err(err) => warn!(
+                        "ignoring invalid cached header of pack {id}: {}",
+                        err.display_log()
+                    ),
+                },
+                Ok(None) => {}
+                Err(err) => warn!(
+                    "Error reading cached header of pack {id}: {}",
+                    err.display_log()
+                ),
+            }
+        }
+
         // guess the header size from size_hint and pack_size
         // If the guess is too small, we have to re-read. If the guess is too large, we have to have read too much
         // but this should normally not matter too much. So we try to overguess here...
         let size_guess = size_hint.unwrap_or(0);
 
-        // read (guessed) header + length field
-        let read_size = size_guess + constants::LENGTH_LEN;
+        // read (guessed) header + footer; the footer of the aligned layout is the largest possible trailer
+        let read_size = (size_guess + constants::FOOTER_LEN).min(pack_size);
         let offset = pack_size - read_size;
-        let mut data = be.read_partial(FileType::Pack, &id, false, offset, read_size)?;
-
-        // get header length from the file
-        let size_real = PackHeaderLength::from_binary(&data.split_off(size_guess as usize))
-            .map_err(|err| {
-                RusticError::with_source(
-                    ErrorKind::Internal,
-                    "Reading pack header length failed",
-                    err,
-                )
-            })?
-            .to_u32();
-        trace!("header size: {size_real}");
-
-        if size_real + constants::LENGTH_LEN > pack_size {
+        let data = be.read_partial(FileType::Pack, &id, false, offset, read_size)?;
+
+        let (header, end) = Self::from_file_end(be, id, data, pack_size)?;
+        if let Some(cache) = cache
+            && let Err(err) = cache.write_pack_header(&id, &end)
+        {
+            warn!("Error caching header of pack {id}: {}", err.display_log());
+        }
+        Ok(header)
+    }
+
+    /// Read the pack header from the end of a packfile
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use for decrypting and for reading the rest of the header if needed
+    /// * `id` - The id of the packfile
+    /// * `data` - The end of the packfile, containing at least the trailer
+    /// * `pack_size` - The size of the packfile
+    ///
+    /// # Errors
+    ///
+    /// * If reading the binary representation failed
+    /// * If the header length is too large
+    /// * If the header length does not match the header contents
+    /// * If the pack size computed from the header does not match the real pack file size
+    ///
+    /// # Returns
+    ///
+    /// The pack header and the end of the packfile consisting of exactly the header and the trailer
+    fn from_file_end(
+        be: &impl DecryptReadBackend,
+        id: PackId,
+        mut data: Bytes,
+        pack_size: u32,
+    ) -> RusticResult<(Self, Bytes)> {
+        // get layout and header length from the file
+        let (layout, size_real, blob_count) = PackLayout::from_pack_end(&data).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::Internal,
+                "Reading pack header length failed",
+                err,
+            )
+        })?;
+        trace!("header size: {size_real}, layout: {layout:?}");
+        let trailer_len = layout.trailer_len();
+        let trailer = data.split_off(data.len() - trailer_len as usize);
+
+        if u64::from(size_real) + u64::from(trailer_len) > u64::from(pack_size) {
             return Err(RusticError::new(
                 ErrorKind::Internal,
                 "Read header length `{size_real}` + `{length}` is larger than `{pack_size}`!",
             )
             .attach_context("size_real", size_real.to_string())
             .attach_context("pack_size", pack_size.to_string())
-            .attach_context("length", constants::LENGTH_LEN.to_string()));
+            .attach_context("length", trailer_len.to_string()));
         }
 
         // now read the header
-        let data = if size_real <= size_guess {
+        let data = if size_real as usize <= data.len() {
             // header was already read
-            data.split_off((size_guess - size_real) as usize)
+            data.split_off(data.len() - size_real as usize)
         } else {
             // size_guess was too small; we have to read again
-            let offset = pack_size - size_real - constants::LENGTH_LEN;
+            let offset = pack_size - size_real - trailer_len;
             be.read_partial(FileType::Pack, &id, false, offset, size_real)?
         };
 
-        let header = Self::from_binary(&be.decrypt(&data)?).map_err(|err| {
+        let header = Self::from_binary(&be.decrypt(&data)?, layout).map_err(|err| {
             RusticError::with_source(ErrorKind::Internal, "Reading pack header failed.", err)
         })?;
 
@@ -317,16 +532,51 @@ impl PackHeader {
             .attach_context("size_computed", header.size().to_string()));
         }
 
-        if header.pack_size() != pack_size {
+        if let Some(blob_count) = blob_count
+            && header.0.len() != blob_count as usize
+        {
+            return Err(RusticError::new(
+                ErrorKind::Internal,
+                "Number of blobs `{blob_count}` in pack footer doesn't match header contents `{blobs}`!",
+            )
+            .attach_context("blob_count", blob_count.to_string())
+            .attach_context("blobs", header.0.len().to_string()));
+        }
+
+        let size_computed = PackHeaderRef(&header.0).pack_size_with_layout(layout);
+        if size_computed != pack_size {
             return Err(RusticError::new(
                 ErrorKind::Internal,
                 "pack size `{size_computed}` computed from header doesn't match real pack file size `{size_real}`!",
             )
             .attach_context("size_real", pack_size.to_string())
-            .attach_context("size_computed", header.pack_size().to_string()));
+            .attach_context("size_computed", size_computed.to_string()));
         }
 
-        Ok(header)
+        let mut end = BytesMut::with_capacity(data.len() + trailer.len());
+        end.extend_from_slice(&data);
+        end.extend_from_slice(&trailer);
+        Ok((header, end.freeze()))
+    }
+
+    /// Convert this [`PackHeader`] into an [`IndexPack`]
+    ///
+    /// The pack size is only saved in the [`IndexPack`] if it can't be computed from the contained blobs,
+    /// i.e. if the pack doesn't use the restic [`PackLayout`].
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the pack
+    /// * `pack_size` - The size of the pack file
+    #[must_use]
+    pub(crate) fn into_index_pack(self, id: PackId, pack_size: u32) -> IndexPack {
+        let size = (self.pack_size() != pack_size).then_some(pack_size);
+        IndexPack {
+            id,
+            blobs: self.0,
+            time: None,
+            size,
+        }
     }
 
     /// Convert this [`PackHeader`] into a [`Vec`] of [`IndexBlob`]s
@@ -374,10 +624,20 @@ impl<'a> PackHeaderRef<'a> {
     /// Calculate the pack size from the contained blobs
     #[must_use]
     pub(crate) fn pack_size(&self) -> u32 {
-        self.0.iter().fold(
-            constants::COMP_OVERHEAD + constants::LENGTH_LEN,
-            |acc, blob| acc + blob.location.length + HeaderEntry::from_blob(blob).length(),
-        )
+        self.pack_size_with_layout(PackLayout::Restic)
+    }
+
+    /// Calculate the pack size from the contained blobs for the given layout
+    ///
+    /// # Arguments
+    ///
+    /// * `layout` - The layout of the pack file
+    #[must_use]
+    pub(crate) fn pack_size_with_layout(&self, layout: PackLayout) -> u32 {
+        let blobs_size = self.0.iter().fold(0, |acc, blob| {
+            acc + layout.padding(acc) + blob.location.length
+        });
+        blobs_size + self.size() + layout.trailer_len()
     }
 
     /// Generate the binary representation of the pack header
@@ -396,3 +656,80 @@ impl<'a> PackHeaderRef<'a> {
         Ok(writer.into_inner())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blobs(lengths: &[u32], layout: PackLayout) -> Vec<IndexBlob> {
+        let mut offset = 0;
+        lengths
+            .iter()
+            .map(|&length| {
+                offset += layout.padding(offset);
+                let blob = IndexBlob {
+                    id: Id::random().into(),
+                    tpe: BlobType::Data,
+                    location: BlobLocation {
+                        offset,
+                        length,
+                        uncompressed_length: NonZeroU32::new(length + 10),
+                    },
+                };
+                offset += length;
+                blob
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_padding() {
+        let layout = PackLayout::Aligned { alignment: 512 };
+        assert_eq!(layout.padding(0), 0);
+        assert_eq!(layout.padding(1), 511);
+        assert_eq!(layout.padding(512), 0);
+        assert_eq!(layout.padding(700), 324);
+        assert_eq!(PackLayout::Restic.padding(700), 0);
+    }
+
+    #[test]
+    fn test_header_roundtrip() {
+        for layout in [PackLayout::Restic, PackLayout::Aligned { alignment: 512 }] {
+            let blobs = blobs(&[100, 1000, 512, 3], layout);
+            let header = PackHeaderRef(&blobs).to_binary().unwrap();
+            let read = PackHeader::from_binary(&header, layout).unwrap();
+            assert_eq!(read.into_blobs(), blobs);
+        }
+    }
+
+    #[test]
+    fn test_pack_size() {
+        let layout = PackLayout::Aligned { alignment: 512 };
+        let blobs = blobs(&[100, 1000], layout);
+        let header = PackHeaderRef(&blobs);
+        assert_eq!(header.pack_size(), 1100 + header.size() + 4);
+        assert_eq!(
+            header.pack_size_with_layout(layout),
+            1512 + header.size() + 16
+        );
+    }
+
+    #[test]
+    fn test_trailer_roundtrip() {
+        let mut pack = vec![42; 20];
+        pack.extend(PackLayout::Restic.trailer(123, 2).unwrap());
+        assert_eq!(
+            PackLayout::from_pack_end(&pack).unwrap(),
+            (PackLayout::Restic, 123, None)
+        );
+
+        let layout = PackLayout::Aligned { alignment: 4096 };
+        let mut pack = vec![42; 20];
+        pack.extend(layout.trailer(123, 2).unwrap());
+        assert_eq!(pack.len(), 36);
+        assert_eq!(
+            PackLayout::from_pack_end(&pack).unwrap(),
+            (layout, 123, Some(2))
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+
+use jiff::Timestamp;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{backend::FileType, impl_repofile, repofile::RepoFile};
+
+use super::packfile::PackId;
+
+impl_repofile!(ScrubId, FileType::Scrub, ScrubFile);
+
+/// Scrub files record when the pack files of the repository have been verified the last time.
+///
+/// They are usually stored in the repository under `/scrub/<ID>`. If there are multiple scrub files,
+/// they are merged using the latest verification time of each pack. As `scrub/` is no part of the
+/// restic repository layout, scrub files can only be stored if the backend supports them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScrubFile {
+    /// The time of the last successful verification of each pack
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub verified: BTreeMap<PackId, Timestamp>,
+}
+
+impl ScrubFile {
+    /// Merge another scrub file into this one, keeping the latest verification time of each pack
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The scrub file to merge
+    pub fn merge(&mut self, other: Self) {
+        for (id, time) in other.verified {
+            _ = self
+                .verified
+                .entry(id)
+                .and_modify(|t| *t = (*t).max(time))
+                .or_insert(time);
+        }
+    }
+
+    /// Returns the time of the last successful verification of the given pack
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the pack
+    #[must_use]
+    pub fn last_verified(&self, id: &PackId) -> Option<Timestamp> {
+        self.verified.get(id).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Id;
+
+    #[test]
+    fn test_merge_keeps_latest() {
+        let (id1, id2) = (PackId::from(Id::random()), PackId::from(Id::random()));
+        let (t1, t2) = (Timestamp::UNIX_EPOCH, Timestamp::MAX);
+
+        let mut scrub = ScrubFile::default();
+        _ = scrub.verified.insert(id1, t2);
+        _ = scrub.verified.insert(id2, t1);
+        let mut other = ScrubFile::default();
+        _ = other.verified.insert(id1, t1);
+        _ = other.verified.insert(id2, t2);
+
+        scrub.merge(other);
+        assert_eq!(scrub.last_verified(&id1), Some(t2));
+        assert_eq!(scrub.last_verified(&id2), Some(t2));
+    }
+}
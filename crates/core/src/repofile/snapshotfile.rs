@@ -1,11 +1,13 @@
+mod filter;
 pub mod grouping;
 mod modification;
 
+pub use filter::SnapshotFilter;
 pub use modification::SnapshotModification;
 
 use std::{
     cmp::Ordering,
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, BinaryHeap},
     fmt::{self, Display},
     ops::Index,
     path::{Path, PathBuf},
@@ -16,8 +18,8 @@ use derive_setters::Setters;
 use dunce::canonicalize;
 use gethostname::gethostname;
 use itertools::Itertools;
-use jiff::{Span, Unit, Zoned};
-use log::{info, warn};
+use jiff::{Span, Timestamp, Unit, Zoned};
+use log::{error, info, warn};
 use path_dedot::ParseDot;
 use serde::{Deserialize, Serialize};
 use serde_with::{DisplayFromStr, serde_as, skip_serializing_none};
@@ -29,9 +31,10 @@ use crate::{
     Id,
     backend::{FileType, FindInBackend, decrypt::DecryptReadBackend},
     blob::tree::TreeId,
+    crypto::hasher::hash,
     error::{ErrorKind, RusticError, RusticResult},
     id::{FindUniqueMultiple, FindUniqueResults, constants::HEX_LEN},
-    impl_repofile,
+    impl_repoid,
     progress::Progress,
     repofile::{RepoFile, RusticTime},
 };
@@ -122,6 +125,11 @@ pub struct SnapshotOptions {
     #[cfg_attr(feature = "clap", clap(long))]
     #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
     pub command: Option<String>,
+
+    /// Key-value metadata to attach to the snapshot, see [`SnapshotFile::metadata`]
+    #[cfg_attr(feature = "clap", clap(skip))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::btreemap::append_or_ignore))]
+    pub metadata: BTreeMap<String, String>,
 }
 
 impl SnapshotOptions {
@@ -150,6 +158,34 @@ impl SnapshotOptions {
         Ok(self)
     }
 
+    /// Add metadata given as `KEY=VALUE` to this [`SnapshotOptions`]
+    ///
+    /// # Arguments
+    ///
+    /// * `key_value` - The metadata entry to add
+    ///
+    /// # Errors
+    ///
+    /// * If the entry is not of the form `KEY=VALUE` or the key is empty
+    ///
+    /// # Returns
+    ///
+    /// The modified [`SnapshotOptions`]
+    pub fn add_metadata(mut self, key_value: &str) -> RusticResult<Self> {
+        let (key, value) = key_value
+            .split_once('=')
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| {
+                RusticError::new(
+                    ErrorKind::InvalidInput,
+                    "Invalid metadata entry `{entry}`. Please use the form `KEY=VALUE`, e.g. `job-id=42`.",
+                )
+                .attach_context("entry", key_value)
+            })?;
+        _ = self.metadata.insert(key.to_string(), value.to_string());
+        Ok(self)
+    }
+
     /// Create a new [`SnapshotFile`] using this `SnapshotOption`s
     ///
     /// # Errors
@@ -164,6 +200,111 @@ impl SnapshotOptions {
     }
 }
 
+/// The level of checks used to decide whether a file is unchanged w.r.t. the parent snapshot.
+///
+/// The levels are ordered; each level includes the checks of the previous ones.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum ChangeDetection {
+    /// Compare file size and mtime
+    SizeMtime,
+    /// Additionally compare ctime
+    Ctime,
+    /// Additionally compare the inode number
+    #[default]
+    Inode,
+    /// Always read the file contents; all files contained in the parent are reported as changed
+    Content,
+}
+
+/// How to handle snapshot files which cannot be read or parsed when iterating over all snapshots.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum SnapshotErrorPolicy {
+    /// Warn about each unreadable snapshot and skip it
+    #[default]
+    Skip,
+    /// Skip unreadable snapshots and report the ids of all of them at the end
+    Report,
+    /// Fail if any snapshot cannot be read
+    Fail,
+}
+
+/// The order of snapshots when listing them page by page, see [`SnapshotPage`]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum SnapshotSort {
+    /// Sort by time, newest snapshot first
+    #[default]
+    NewestFirst,
+    /// Sort by time, oldest snapshot first
+    OldestFirst,
+    /// Sort by snapshot id.
+    ///
+    /// This only needs to read the snapshot files of the requested page.
+    Id,
+}
+
+impl SnapshotSort {
+    /// Compare two snapshots according to this sort order; snapshots with identical time are ordered by id
+    ///
+    /// # Arguments
+    ///
+    /// * `sn1` - The first snapshot
+    /// * `sn2` - The second snapshot
+    #[must_use]
+    pub fn compare(self, sn1: &SnapshotFile, sn2: &SnapshotFile) -> Ordering {
+        match self {
+            Self::NewestFirst => sn2.time.cmp(&sn1.time).then(sn1.id.cmp(&sn2.id)),
+            Self::OldestFirst => sn1.time.cmp(&sn2.time).then(sn1.id.cmp(&sn2.id)),
+            Self::Id => sn1.id.cmp(&sn2.id),
+        }
+    }
+}
+
+/// A page of snapshots, see [`Repository::list_snapshots_paged`](crate::Repository::list_snapshots_paged)
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SnapshotPage {
+    /// The snapshots of this page in the requested order
+    pub snapshots: Vec<SnapshotFile>,
+    /// The total number of snapshot files in the repository
+    pub total: usize,
+    /// The offset of the next page; `None` if this is the last page
+    pub next_offset: Option<usize>,
+    /// The ids of the snapshots which could not be read while building this page
+    pub unreadable: Vec<SnapshotId>,
+}
+
+/// A snapshot ranked by a [`SnapshotSort`], used to keep the best snapshots in a [`BinaryHeap`]
+struct RankedSnapshot(SnapshotFile, SnapshotSort);
+
+impl PartialEq for RankedSnapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RankedSnapshot {}
+
+impl PartialOrd for RankedSnapshot {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RankedSnapshot {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.1.compare(&self.0, &other.0)
+    }
+}
+
 /// Summary information about a snapshot.
 ///
 /// This is an extended version of the summaryOutput structure of restic in
@@ -247,6 +388,10 @@ pub struct SnapshotSummary {
 
     /// Total duration that the rustic command ran in seconds
     pub total_duration: f64,
+
+    /// The level of checks which has been used to detect unchanged files w.r.t. the parent snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_detection: Option<ChangeDetection>,
 }
 
 impl Default for SnapshotSummary {
@@ -275,6 +420,7 @@ impl Default for SnapshotSummary {
             backup_end: Zoned::now(),
             backup_duration: Default::default(),
             total_duration: Default::default(),
+            change_detection: None,
         }
     }
 }
@@ -324,7 +470,64 @@ impl DeleteOption {
     }
 }
 
-impl_repofile!(SnapshotId, FileType::Snapshot, SnapshotFile);
+/// Metadata of a single backup path of a [`SnapshotFile`]
+///
+/// Snapshots created by older versions don't contain this information, see [`SnapshotFile::roots`].
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct SnapshotRoot {
+    /// The path as contained in the snapshot paths
+    pub path: String,
+    /// The local path the data has been read from, if it differs from `path`, e.g. when using
+    /// `as-path` or `remap`
+    pub source: Option<String>,
+    /// The device id of the filesystem the path has been read from
+    pub device_id: Option<u64>,
+    /// Total processed files below this path
+    pub files_processed: u64,
+    /// Total size of all processed files below this path
+    pub bytes_processed: u64,
+}
+
+impl SnapshotRoot {
+    /// Create a new `SnapshotRoot` without metadata
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path as contained in the snapshot paths
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the given path within the snapshot is contained in this root
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path within the snapshot
+    #[must_use]
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        path.as_ref().starts_with(&self.path)
+    }
+}
+
+impl_repoid!(SnapshotId, FileType::Snapshot);
+
+impl RepoFile for SnapshotFile {
+    const TYPE: FileType = FileType::Snapshot;
+    type Id = SnapshotId;
+
+    fn retain_until(&self) -> Option<Timestamp> {
+        match &self.delete {
+            DeleteOption::After(time) => Some(time.timestamp()),
+            _ => None,
+        }
+    }
+}
 
 #[serde_as]
 #[skip_serializing_none]
@@ -396,6 +599,14 @@ pub struct SnapshotFile {
     /// A description of what is contained in this snapshot
     pub description: Option<String>,
 
+    /// Machine-readable key-value metadata, e.g. a job id or ticket number of an orchestration tool
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+
+    /// Metadata of the backup paths, in the order of `paths`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<SnapshotRoot>,
+
     /// The snapshot Id (not stored within the JSON)
     #[serde(default, skip_serializing_if = "Id::is_null")]
     pub id: SnapshotId,
@@ -424,6 +635,8 @@ impl Default for SnapshotFile {
             delete: DeleteOption::default(),
             summary: Option::default(),
             description: Option::default(),
+            metadata: BTreeMap::default(),
+            roots: Vec::default(),
             id: SnapshotId::default(),
         }
     }
@@ -582,6 +795,7 @@ impl SnapshotFile {
                 ..Default::default()
             }),
             description: opts.description.clone(),
+            metadata: opts.metadata.clone(),
             ..Default::default()
         };
 
@@ -633,6 +847,7 @@ impl SnapshotFile {
     /// * `be` - The backend to use
     /// * `string` - The (part of the) id of the snapshot
     /// * `predicate` - A predicate to filter the snapshots
+    /// * `policy` - How to handle unreadable snapshots when looking for the latest snapshot
     /// * `p` - A progress bar to use
     ///
     /// # Errors
@@ -641,14 +856,16 @@ impl SnapshotFile {
     /// * If no id could be found.
     /// * If the id is not unique.
     /// * If the `latest` syntax is "detected" but inexact
+    /// * If a snapshot cannot be read and `policy` is [`SnapshotErrorPolicy::Fail`]
     pub(crate) fn from_str<B: DecryptReadBackend>(
         be: &B,
         string: &str,
         predicate: impl FnMut(&Self) -> bool + Send + Sync,
+        policy: SnapshotErrorPolicy,
         p: &Progress,
     ) -> RusticResult<Self> {
         match string.parse()? {
-            SnapshotRequest::Latest(n) => Self::latest_n(be, predicate, p, n),
+            SnapshotRequest::Latest(n) => Self::latest_n(be, predicate, policy, p, n),
             SnapshotRequest::StartsWith(id) => Self::from_id(be, &id),
             SnapshotRequest::Id(id) => Self::from_backend(be, &id),
         }
@@ -663,6 +880,7 @@ impl SnapshotFile {
     /// * `be` - The backend to use
     /// * `string` - The (part of the) id of the snapshot
     /// * `predicate` - A predicate to filter the snapshots
+    /// * `policy` - How to handle unreadable snapshots when looking for the latest snapshots
     /// * `p` - A progress bar to use
     ///
     /// # Errors
@@ -671,10 +889,12 @@ impl SnapshotFile {
     /// * If no id could be found.
     /// * If the id is not unique.
     /// * If the `latest` syntax is "detected" but inexact
+    /// * If a snapshot cannot be read and `policy` is [`SnapshotErrorPolicy::Fail`]
     pub(crate) fn from_strs<B: DecryptReadBackend, S: AsRef<str>>(
         be: &B,
         strings: &[S],
         predicate: impl FnMut(&Self) -> bool + Send + Sync,
+        policy: SnapshotErrorPolicy,
         p: &Progress,
     ) -> RusticResult<Vec<Self>> {
         let requests = SnapshotRequests::from_strs(strings)?;
@@ -710,7 +930,7 @@ impl SnapshotFile {
                 let mut ids_starts_with = FindUniqueResults::new(&requests.starts_with);
 
                 // search for id names while iterating snapshots to get latest ones
-                let iter = Self::iter_all_from_backend(be, predicate, p)?.inspect(|sn| {
+                let iter = Self::iter_all_from_backend(be, predicate, policy, p)?.inspect(|sn| {
                     if let Some(idx) = ids.get(&sn.id) {
                         vec_ids[*idx] = sn.clone();
                     }
@@ -733,17 +953,20 @@ impl SnapshotFile {
     ///
     /// * `be` - The backend to use
     /// * `predicate` - A predicate to filter the snapshots
+    /// * `policy` - How to handle unreadable snapshots
     /// * `p` - A progress bar to use
     ///
     /// # Errors
     ///
     /// * If no snapshots are found
+    /// * If a snapshot cannot be read and `policy` is [`SnapshotErrorPolicy::Fail`]
     pub(crate) fn latest<B: DecryptReadBackend>(
         be: &B,
         predicate: impl FnMut(&Self) -> bool + Send + Sync,
+        policy: SnapshotErrorPolicy,
         p: &Progress,
     ) -> RusticResult<Self> {
-        Self::latest_n(be, predicate, p, 0)
+        Self::latest_n(be, predicate, policy, p, 0)
     }
 
     fn latest_n_from_iter(
@@ -777,15 +1000,18 @@ impl SnapshotFile {
     ///
     /// * `be` - The backend to use
     /// * `predicate` - A predicate to filter the snapshots
+    /// * `policy` - How to handle unreadable snapshots
     /// * `p` - A progress bar to use
     /// * `n` - The n-latest index to go back for snapshot
     ///
     /// # Errors
     ///
     /// * If no snapshots are found
+    /// * If a snapshot cannot be read and `policy` is [`SnapshotErrorPolicy::Fail`]
     pub(crate) fn latest_n<B: DecryptReadBackend>(
         be: &B,
         predicate: impl FnMut(&Self) -> bool + Send + Sync,
+        policy: SnapshotErrorPolicy,
         p: &Progress,
         n: usize,
     ) -> RusticResult<Self> {
@@ -795,7 +1021,7 @@ impl SnapshotFile {
             p.set_title("getting latest~N snapshot...");
         }
         let mut snapshots =
-            Self::latest_n_from_iter(n, Self::iter_all_from_backend(be, predicate, p)?)?;
+            Self::latest_n_from_iter(n, Self::iter_all_from_backend(be, predicate, policy, p)?)?;
 
         p.finish();
         Ok(snapshots.pop().unwrap()) // we want the latest element if we found n+1 snapshots
@@ -876,23 +1102,193 @@ impl SnapshotFile {
             .collect())
     }
 
-    // TODO: add documentation!
+    /// Read all snapshots from the backend, handling unreadable snapshot files according to `policy`
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use
+    /// * `policy` - How to handle unreadable snapshots
+    /// * `p` - A progress bar to use
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be listed.
+    /// * If a snapshot cannot be read and `policy` is [`SnapshotErrorPolicy::Fail`]
+    ///
+    /// # Returns
+    ///
+    /// The readable snapshots and the ids of the unreadable snapshots
+    pub(crate) fn all_from_backend<B: DecryptReadBackend>(
+        be: &B,
+        policy: SnapshotErrorPolicy,
+        p: &Progress,
+    ) -> RusticResult<(Vec<Self>, Vec<SnapshotId>)> {
+        let ids: Vec<_> = be
+            .list(FileType::Snapshot)?
+            .into_iter()
+            .map(SnapshotId::from)
+            .collect();
+
+        let mut snaps = Vec::new();
+        let mut first_error = None;
+        for item in be.stream_list::<Self>(ids.clone(), p)? {
+            match item {
+                Ok(snap) => snaps.push(Self::set_id(snap)),
+                Err(err) => {
+                    warn!("Error reading snapshot: {err}");
+                    _ = first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        let read: BTreeSet<_> = snaps.iter().map(|snap| snap.id).collect();
+        let unreadable: Vec<_> = ids.into_iter().filter(|id| !read.contains(id)).collect();
+        Self::handle_unreadable(policy, first_error, &unreadable)?;
+        Ok((snaps, unreadable))
+    }
+
+    /// Handle unreadable snapshot files according to `policy`
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - How to handle unreadable snapshots
+    /// * `first_error` - The first error which occurred when reading the snapshots
+    /// * `unreadable` - The ids of the unreadable snapshots
+    ///
+    /// # Errors
+    ///
+    /// * If a snapshot could not be read and `policy` is [`SnapshotErrorPolicy::Fail`]
+    fn handle_unreadable(
+        policy: SnapshotErrorPolicy,
+        first_error: Option<Box<RusticError>>,
+        unreadable: &[SnapshotId],
+    ) -> RusticResult<()> {
+        if let Some(err) = first_error {
+            match policy {
+                SnapshotErrorPolicy::Skip => {}
+                SnapshotErrorPolicy::Report => error!(
+                    "{} snapshot(s) could not be read and are ignored: {}",
+                    unreadable.len(),
+                    unreadable.iter().join(", ")
+                ),
+                SnapshotErrorPolicy::Fail => {
+                    return Err(err
+                        .prepend_guidance_line(
+                            "{count} snapshot(s) could not be read: {ids}. Please check these snapshot files, e.g. using `check`.",
+                        )
+                        .attach_context("count", unreadable.len().to_string())
+                        .attach_context("ids", unreadable.iter().join(", ")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a page of snapshots from the backend
+    ///
+    /// Only the snapshots up to the end of the requested page are kept in memory. For
+    /// [`SnapshotSort::Id`], only the snapshot files of the requested page are read.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use
+    /// * `offset` - The number of snapshots to skip
+    /// * `limit` - The maximum number of snapshots to return
+    /// * `sort` - The order of the snapshots
+    /// * `policy` - How to handle unreadable snapshots
+    /// * `p` - A progress bar to use
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be listed.
+    /// * If a snapshot cannot be read and `policy` is [`SnapshotErrorPolicy::Fail`]
+    pub(crate) fn page_from_backend<B: DecryptReadBackend>(
+        be: &B,
+        offset: usize,
+        limit: usize,
+        sort: SnapshotSort,
+        policy: SnapshotErrorPolicy,
+        p: &Progress,
+    ) -> RusticResult<SnapshotPage> {
+        let mut ids: Vec<_> = be
+            .list(FileType::Snapshot)?
+            .into_iter()
+            .map(SnapshotId::from)
+            .collect();
+        let total = ids.len();
+
+        // the number of snapshots to keep and to skip of the snapshots read
+        let (ids, keep, skip) = if sort == SnapshotSort::Id {
+            ids.sort_unstable();
+            let page = ids.into_iter().skip(offset).take(limit).collect();
+            (page, limit, 0)
+        } else {
+            (ids, offset.saturating_add(limit), offset)
+        };
+
+        // the top of the heap is the last of the kept snapshots
+        let mut heap = BinaryHeap::new();
+        let mut read = BTreeSet::new();
+        let mut first_error = None;
+        for item in be.stream_list::<Self>(ids.clone(), p)? {
+            match item {
+                Ok(snap) => {
+                    let snap = Self::set_id(snap);
+                    _ = read.insert(snap.id);
+                    heap.push(RankedSnapshot(snap, sort));
+                    if heap.len() > keep {
+                        _ = heap.pop();
+                    }
+                }
+                Err(err) => {
+                    warn!("Error reading snapshot: {err}");
+                    _ = first_error.get_or_insert(err);
+                }
+            }
+        }
+        let unreadable: Vec<_> = ids.into_iter().filter(|id| !read.contains(id)).collect();
+        Self::handle_unreadable(policy, first_error, &unreadable)?;
+
+        let snapshots: Vec<_> = heap
+            .into_sorted_vec()
+            .into_iter()
+            .skip(skip)
+            .map(|ranked| ranked.0)
+            .collect();
+        let end = offset.saturating_add(limit);
+        Ok(SnapshotPage {
+            snapshots,
+            total,
+            next_offset: (end < total).then_some(end),
+            unreadable,
+        })
+    }
+
+    /// Iterate over all snapshots from the backend which match the given `filter`
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend to use
+    /// * `filter` - The filter to use
+    /// * `policy` - How to handle unreadable snapshots
+    /// * `p` - A progress bar to use
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be listed.
+    /// * If a snapshot cannot be read and `policy` is [`SnapshotErrorPolicy::Fail`]
     pub(crate) fn iter_all_from_backend<B, F>(
         be: &B,
         filter: F,
+        policy: SnapshotErrorPolicy,
         p: &Progress,
     ) -> RusticResult<impl Iterator<Item = Self>>
     where
         B: DecryptReadBackend,
         F: FnMut(&Self) -> bool,
     {
-        Ok(be
-            .stream_all::<Self>(p)?
-            .into_iter()
-            .map(|item| item.inspect_err(|err| warn!("Error reading snapshot: {err}")))
-            .filter_map(Result::ok)
-            .map(Self::set_id)
-            .filter(filter))
+        let (snaps, _) = Self::all_from_backend(be, policy, p)?;
+        Ok(snaps.into_iter().filter(filter))
     }
 
     // TODO: add documentation!
@@ -1011,6 +1407,44 @@ impl SnapshotFile {
         sn
     }
 
+    /// Digest identifying what has been backed up, i.e. the tree, the paths, the host and the label.
+    ///
+    /// Two snapshots with the same digest contain identical backups of the same source.
+    #[must_use]
+    pub fn content_digest(&self) -> Id {
+        let data = serde_json::to_vec(&(&self.tree, &self.paths, &self.hostname, &self.label))
+            .expect("serializing tree id and strings should not fail");
+        hash(&data)
+    }
+
+    /// The backup paths of this snapshot together with their metadata.
+    ///
+    /// For snapshots without stored metadata, e.g. created by older versions or by restic, the roots
+    /// are derived from `paths`.
+    #[must_use]
+    pub fn roots(&self) -> Vec<SnapshotRoot> {
+        if self.roots.is_empty() {
+            self.paths.iter().map(SnapshotRoot::new).collect()
+        } else {
+            self.roots.clone()
+        }
+    }
+
+    /// The backup path containing the given path within the snapshot, if any.
+    ///
+    /// This allows to scope e.g. a restore or diff to a single backup path of a multi-path snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path within the snapshot
+    #[must_use]
+    pub fn root_of(&self, path: impl AsRef<Path>) -> Option<SnapshotRoot> {
+        self.roots()
+            .into_iter()
+            .filter(|root| root.contains(&path))
+            .max_by_key(|root| root.path.len())
+    }
+
     /// Convenience method to get parent snapshots which are stored in the `parent` or `parents` field.
     #[must_use]
     pub fn get_parents(&self) -> &[SnapshotId] {
@@ -1179,6 +1613,21 @@ impl<'str> IntoIterator for &'str StringList {
     }
 }
 
+/// Policy how paths of a [`PathList`] are canonicalized when sanitizing them.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum PathCanonicalization {
+    /// Canonicalize all paths (resolving symlinks) if any of the paths is absolute
+    #[default]
+    IfAbsolute,
+    /// Never canonicalize, i.e. keep (symlinked) paths as given
+    Never,
+    /// Make all paths absolute without resolving symlinks
+    Absolutize,
+}
+
 /// `PathList` is a rustic-internal list of `PathBuf`s. It is used in the [`crate::Repository::backup`] command.
 #[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct PathList(Vec<PathBuf>);
@@ -1243,20 +1692,54 @@ impl PathList {
 
     /// Sanitize paths: Parse dots, absolutize if needed and merge paths.
     ///
+    /// This canonicalizes all paths if any path is absolute, see [`PathCanonicalization::IfAbsolute`].
+    ///
+    /// # Errors
+    ///
+    /// * If removing dots from path failed
+    /// * If canonicalizing path failed
+    pub fn sanitize(self) -> SnapshotFileResult<Self> {
+        self.sanitize_with(PathCanonicalization::default())
+    }
+
+    /// Sanitize paths: Parse dots, canonicalize or absolutize according to the given policy and merge paths.
+    ///
+    /// # Arguments
+    ///
+    /// * `canonicalization` - How to canonicalize the paths
+    ///
     /// # Errors
     ///
     /// * If removing dots from path failed
     /// * If canonicalizing path failed
-    pub fn sanitize(mut self) -> SnapshotFileResult<Self> {
+    pub fn sanitize_with(
+        mut self,
+        canonicalization: PathCanonicalization,
+    ) -> SnapshotFileResult<Self> {
         for path in &mut self.0 {
             *path = sanitize_dot(path)?;
         }
-        if self.0.iter().any(|p| p.is_absolute()) {
-            self.0 = self
-                .0
-                .into_iter()
-                .map(|p| canonicalize(p).map_err(SnapshotFileErrorKind::CanonicalizingPathFailed))
-                .collect::<Result<_, _>>()?;
+        match canonicalization {
+            PathCanonicalization::IfAbsolute if self.0.iter().any(|p| p.is_absolute()) => {
+                self.0 = self
+                    .0
+                    .into_iter()
+                    .map(|p| {
+                        canonicalize(p).map_err(SnapshotFileErrorKind::CanonicalizingPathFailed)
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            PathCanonicalization::Absolutize => {
+                self.0 = self
+                    .0
+                    .into_iter()
+                    .map(|p| {
+                        std::path::absolute(p)
+                            .map_err(SnapshotFileErrorKind::CanonicalizingPathFailed)
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            PathCanonicalization::IfAbsolute | PathCanonicalization::Never => {}
         }
         Ok(self.merge())
     }
@@ -1281,6 +1764,103 @@ impl PathList {
 
         Self(paths)
     }
+
+    /// Map the paths to the paths they are recorded as in the snapshot, see [`PathRemap`].
+    ///
+    /// Paths not matched by any remap are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `remaps` - The remap table to use
+    #[must_use]
+    pub fn remap(&self, remaps: &[PathRemap]) -> Self {
+        Self(self.0.iter().map(|p| PathRemap::map(remaps, p)).collect())
+    }
+}
+
+/// A `PathRemap` records a backup path under a different path in the snapshot.
+///
+/// E.g. `/var/lib/app=/app-data` backs up `/var/lib/app` but records it as `/app-data` in the
+/// snapshot tree. This allows to compose a snapshot from different mounts.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub struct PathRemap {
+    /// The path to back up
+    pub source: PathBuf,
+    /// The path recorded in the snapshot
+    pub target: PathBuf,
+}
+
+impl PathRemap {
+    /// Create a new `PathRemap`
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The path to back up
+    /// * `target` - The path recorded in the snapshot
+    pub fn new(source: impl Into<PathBuf>, target: impl Into<PathBuf>) -> Self {
+        Self {
+            source: source.into(),
+            target: target.into(),
+        }
+    }
+
+    /// Map the given path, if it is contained in the source path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to map
+    #[must_use]
+    pub fn apply(&self, path: &Path) -> Option<PathBuf> {
+        let rest = path.strip_prefix(&self.source).ok()?;
+        Some(if rest.as_os_str().is_empty() {
+            self.target.clone()
+        } else {
+            self.target.join(rest)
+        })
+    }
+
+    /// Map the given path using the remap with the longest matching source path.
+    ///
+    /// # Arguments
+    ///
+    /// * `remaps` - The remap table to use
+    /// * `path` - The path to map
+    ///
+    /// # Returns
+    ///
+    /// The mapped path or the unchanged path, if no remap matches.
+    #[must_use]
+    pub fn map(remaps: &[Self], path: &Path) -> PathBuf {
+        remaps
+            .iter()
+            .filter(|remap| path.starts_with(&remap.source))
+            .max_by_key(|remap| remap.source.components().count())
+            .and_then(|remap| remap.apply(path))
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+}
+
+impl FromStr for PathRemap {
+    type Err = Box<RusticError>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((source, target)) if !source.is_empty() && !target.is_empty() => {
+                Ok(Self::new(source, target))
+            }
+            _ => Err(RusticError::new(
+                ErrorKind::InvalidInput,
+                "Invalid path remap \"{input}\". Expected \"SOURCE=TARGET\", e.g. \"/var/lib/app=/app-data\".",
+            )
+            .attach_context("input", s)),
+        }
+    }
+}
+
+impl Display for PathRemap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.source.display(), self.target.display())
+    }
 }
 
 // helper function to sanitize paths containing dots
@@ -1335,6 +1915,24 @@ mod tests {
         assert_eq!(expected, sanitize_dot(path).unwrap());
     }
 
+    #[test]
+    fn sanitize_with_policies() -> Result<()> {
+        let cwd = std::env::current_dir()?;
+
+        let paths = PathList::from_iter(["test", "./test/sub"]);
+        let never = paths.clone().sanitize_with(PathCanonicalization::Never)?;
+        assert_eq!(never, PathList::from_iter(["test"]));
+
+        let absolute = paths.sanitize_with(PathCanonicalization::Absolutize)?;
+        assert_eq!(absolute, PathList::from_iter([cwd.join("test")]));
+
+        // absolutizing doesn't need existing paths, i.e. doesn't resolve symlinks
+        let absolute = PathList::from_iter(["does/not/exist"])
+            .sanitize_with(PathCanonicalization::Absolutize)?;
+        assert_eq!(absolute, PathList::from_iter([cwd.join("does/not/exist")]));
+        Ok(())
+    }
+
     #[rstest]
     #[case("abc", vec!["abc".to_string()])]
     #[case("abc,def", vec!["abc".to_string(), "def".to_string()])]
@@ -1359,6 +1957,55 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_metadata() -> Result<()> {
+        // empty metadata is not serialized
+        let snap = SnapshotFile::from_options(&SnapshotOptions::default())?;
+        assert!(!serde_json::to_string(&snap)?.contains("metadata"));
+
+        let opts = SnapshotOptions::default()
+            .add_metadata("job-id=42")?
+            .add_metadata("ticket=OPS-1=2")?;
+        let snap = SnapshotFile::from_options(&opts)?;
+        let snap: SnapshotFile = serde_json::from_str(&serde_json::to_string(&snap)?)?;
+        assert_eq!(
+            snap.metadata,
+            BTreeMap::from([
+                ("job-id".to_string(), "42".to_string()),
+                ("ticket".to_string(), "OPS-1=2".to_string()),
+            ])
+        );
+
+        assert!(SnapshotOptions::default().add_metadata("job-id").is_err());
+        assert!(SnapshotOptions::default().add_metadata("=42").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_roots() -> Result<()> {
+        // snapshots without roots derive them from the paths
+        let mut snap = SnapshotFile {
+            paths: StringList::from_str("/home,/srv")?,
+            ..Default::default()
+        };
+        assert!(!serde_json::to_string(&snap)?.contains("roots"));
+        assert_eq!(
+            snap.roots(),
+            vec![SnapshotRoot::new("/home"), SnapshotRoot::new("/srv")]
+        );
+
+        let mut root = SnapshotRoot::new("/srv");
+        root.source = Some("/mnt/srv".to_string());
+        root.device_id = Some(42);
+        root.bytes_processed = 100;
+        snap.roots = vec![SnapshotRoot::new("/home"), root.clone()];
+        let snap: SnapshotFile = serde_json::from_str(&serde_json::to_string(&snap)?)?;
+        assert_eq!(snap.root_of("/srv/www/index.html"), Some(root));
+        assert_eq!(snap.root_of("/srv"), snap.roots.get(1).cloned());
+        assert_eq!(snap.root_of("/srv2"), None);
+        Ok(())
+    }
+
     #[rstest]
     #[case(vec![], "")]
     #[case(vec!["test"], "test")]
@@ -1369,6 +2016,32 @@ mod tests {
         assert_eq!(expected, &result);
     }
 
+    #[rstest]
+    #[case("/var/lib/app", "/app-data")]
+    #[case("/var/lib/app/db", "/app-data/db")]
+    #[case("/var/lib/app/cache", "/cache")]
+    #[case("/var/lib/application", "/var/lib/application")]
+    #[case("/home", "/home")]
+    fn test_path_remap(#[case] input: &str, #[case] expected: &str) -> Result<()> {
+        let remaps: [PathRemap; 2] = [
+            "/var/lib/app=/app-data".parse()?,
+            "/var/lib/app/cache=/cache".parse()?,
+        ];
+        assert_eq!(
+            PathRemap::map(&remaps, Path::new(input)),
+            Path::new(expected)
+        );
+        Ok(())
+    }
+
+    #[rstest]
+    #[case("/var/lib/app")]
+    #[case("=/app-data")]
+    #[case("/var/lib/app=")]
+    fn test_path_remap_fails(#[case] input: &str) {
+        assert!(input.parse::<PathRemap>().is_err());
+    }
+
     fn fake_snapshot_file_with_id_time(
         id_time_vec: Vec<(Id, Zoned)>,
         key: &Key,
@@ -1442,19 +2115,24 @@ mod tests {
     fn test_snapshot_file_latest() {
         let p = Progress::new(NoProgress);
         let (be, [id1, id2, id3]) = setup_mock_backend();
-        let latest = SnapshotFile::latest(&be, |_sn| true, &p).unwrap();
+        let latest =
+            SnapshotFile::latest(&be, |_sn| true, SnapshotErrorPolicy::default(), &p).unwrap();
         assert_eq!(latest.id, SnapshotId(id3));
 
-        let latest_n0 = SnapshotFile::latest_n(&be, |_sn| true, &p, 0).unwrap();
+        let latest_n0 =
+            SnapshotFile::latest_n(&be, |_sn| true, SnapshotErrorPolicy::default(), &p, 0).unwrap();
         assert_eq!(latest_n0, latest);
 
-        let latest_n1 = SnapshotFile::latest_n(&be, |_sn| true, &p, 1).unwrap();
+        let latest_n1 =
+            SnapshotFile::latest_n(&be, |_sn| true, SnapshotErrorPolicy::default(), &p, 1).unwrap();
         assert_eq!(latest_n1.id, SnapshotId(id2));
 
-        let latest_n2 = SnapshotFile::latest_n(&be, |_sn| true, &p, 2).unwrap();
+        let latest_n2 =
+            SnapshotFile::latest_n(&be, |_sn| true, SnapshotErrorPolicy::default(), &p, 2).unwrap();
         assert_eq!(latest_n2.id, SnapshotId(id1));
 
-        let latest_n3 = SnapshotFile::latest_n(&be, |_sn| true, &p, 3);
+        let latest_n3 =
+            SnapshotFile::latest_n(&be, |_sn| true, SnapshotErrorPolicy::default(), &p, 3);
         let latest_n3_err = latest_n3.unwrap_err().to_string();
         let expected = "No snapshots found for latest~3.";
         assert!(
@@ -1468,31 +2146,68 @@ mod tests {
         let p = Progress::new(NoProgress);
         let (be, [id1, id2, id3]) = setup_mock_backend();
 
-        let latest = SnapshotFile::from_str(&be, "latest", |_sn| true, &p).unwrap();
+        let latest = SnapshotFile::from_str(
+            &be,
+            "latest",
+            |_sn| true,
+            SnapshotErrorPolicy::default(),
+            &p,
+        )
+        .unwrap();
         assert_eq!(latest.id, SnapshotId(id3));
 
-        let latest_n0 = SnapshotFile::from_str(&be, "latest~0", |_sn| true, &p).unwrap();
+        let latest_n0 = SnapshotFile::from_str(
+            &be,
+            "latest~0",
+            |_sn| true,
+            SnapshotErrorPolicy::default(),
+            &p,
+        )
+        .unwrap();
         assert_eq!(latest_n0, latest);
 
         let snap_id3 = SnapshotFile::from_str(
             &be,
             "0031223344556677001122334455667700112233445566770000000000000003",
             |_sn| true,
+            SnapshotErrorPolicy::default(),
             &p,
         )
         .unwrap();
         assert_eq!(latest, snap_id3);
 
-        let snap_id3 = SnapshotFile::from_str(&be, "003", |_sn| true, &p).unwrap();
+        let snap_id3 =
+            SnapshotFile::from_str(&be, "003", |_sn| true, SnapshotErrorPolicy::default(), &p)
+                .unwrap();
         assert_eq!(latest, snap_id3);
 
-        let latest_n1 = SnapshotFile::from_str(&be, "latest~1", |_sn| true, &p).unwrap();
+        let latest_n1 = SnapshotFile::from_str(
+            &be,
+            "latest~1",
+            |_sn| true,
+            SnapshotErrorPolicy::default(),
+            &p,
+        )
+        .unwrap();
         assert_eq!(latest_n1.id, SnapshotId(id2));
 
-        let latest_n2 = SnapshotFile::from_str(&be, "latest~2", |_sn| true, &p).unwrap();
+        let latest_n2 = SnapshotFile::from_str(
+            &be,
+            "latest~2",
+            |_sn| true,
+            SnapshotErrorPolicy::default(),
+            &p,
+        )
+        .unwrap();
         assert_eq!(latest_n2.id, SnapshotId(id1));
 
-        let latest_n3 = SnapshotFile::from_str(&be, "latest~3", |_sn| true, &p);
+        let latest_n3 = SnapshotFile::from_str(
+            &be,
+            "latest~3",
+            |_sn| true,
+            SnapshotErrorPolicy::default(),
+            &p,
+        );
         let latest_n3_err = latest_n3.unwrap_err().to_string();
         let expected = "No snapshots found for latest~3.";
         assert!(
@@ -1500,9 +2215,15 @@ mod tests {
             "Err is: {latest_n3_err}\n\nShould contain: {expected}",
         );
 
-        let latest_syntax_err = SnapshotFile::from_str(&be, "laztet~1", |_sn| true, &p)
-            .unwrap_err()
-            .to_string();
+        let latest_syntax_err = SnapshotFile::from_str(
+            &be,
+            "laztet~1",
+            |_sn| true,
+            SnapshotErrorPolicy::default(),
+            &p,
+        )
+        .unwrap_err()
+        .to_string();
         let expected = "No suitable id found for `laztet~1`.";
         assert!(
             latest_syntax_err.contains(expected),
@@ -1524,6 +2245,7 @@ mod tests {
                 "0031223344556677001122334455667700112233445566770000000000000003",
             ],
             |_sn| true,
+            SnapshotErrorPolicy::default(),
             &p,
         )
         .unwrap();
@@ -1539,6 +2261,7 @@ mod tests {
                 "001",
             ],
             |_sn| true,
+            SnapshotErrorPolicy::default(),
             &p,
         )
         .unwrap();
@@ -1546,12 +2269,25 @@ mod tests {
         assert_eq!(ids, vec![id2, id2, id1]);
 
         // typical "last two" request
-        let snaps = SnapshotFile::from_strs(&be, &["latest", "latest~1"], |_sn| true, &p).unwrap();
+        let snaps = SnapshotFile::from_strs(
+            &be,
+            &["latest", "latest~1"],
+            |_sn| true,
+            SnapshotErrorPolicy::default(),
+            &p,
+        )
+        .unwrap();
         let ids: Vec<_> = snaps.iter().map(|sn| *sn.id).collect();
         assert_eq!(ids, vec![id3, id2]);
 
         // not enough latest snapshots
-        let latest_n3 = SnapshotFile::from_strs(&be, &["003", "latest~3"], |_sn| true, &p);
+        let latest_n3 = SnapshotFile::from_strs(
+            &be,
+            &["003", "latest~3"],
+            |_sn| true,
+            SnapshotErrorPolicy::default(),
+            &p,
+        );
         let latest_n3_err = latest_n3.unwrap_err().to_string();
         let expected = "No snapshots found for latest~3.";
         assert!(
@@ -1567,6 +2303,7 @@ mod tests {
                 "001",
             ],
             |_sn| true,
+            SnapshotErrorPolicy::default(),
             &p,
         )
         .unwrap();
@@ -1582,6 +2319,7 @@ mod tests {
                 "0031223344556677001122334455667700112233445566770000000000000003",
             ],
             |_sn| true,
+            SnapshotErrorPolicy::default(),
             &p,
         )
         .unwrap();
@@ -0,0 +1,534 @@
+use std::{
+    fmt,
+    iter::Peekable,
+    str::{CharIndices, FromStr},
+    vec::IntoIter,
+};
+
+use jiff::Zoned;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+use crate::{
+    ErrorKind, RusticError, RusticResult, StringList,
+    repofile::{RusticTime, SnapshotFile},
+};
+
+/// A snapshot field which can be used in a [`SnapshotFilter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// The hostname (`host` or `hostname`)
+    Hostname,
+    /// The label
+    Label,
+    /// The user name (`user` or `username`)
+    Username,
+    /// The description
+    Description,
+    /// The program version
+    ProgramVersion,
+    /// The tags
+    Tags,
+    /// The backup paths
+    Paths,
+    /// The backup time
+    Time,
+    /// The snapshot id
+    Id,
+}
+
+impl Field {
+    /// Parse a field name
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "host" | "hostname" => Self::Hostname,
+            "label" => Self::Label,
+            "user" | "username" => Self::Username,
+            "description" => Self::Description,
+            "program-version" => Self::ProgramVersion,
+            "tags" => Self::Tags,
+            "paths" => Self::Paths,
+            "time" => Self::Time,
+            "id" => Self::Id,
+            _ => return None,
+        })
+    }
+}
+
+/// A comparison operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    /// `==`
+    Eq,
+    /// `!=`
+    Ne,
+    /// `<`
+    Lt,
+    /// `<=`
+    Le,
+    /// `>`
+    Gt,
+    /// `>=`
+    Ge,
+    /// `contains`
+    Contains,
+}
+
+/// The value a field is compared to
+#[derive(Debug, Clone)]
+enum Value {
+    /// A string value
+    Text(String),
+    /// A list of strings, given as comma-separated string
+    List(StringList),
+    /// A time
+    Time(Zoned),
+}
+
+/// A parsed filter expression
+#[derive(Debug, Clone)]
+enum Expr {
+    /// Both expressions must match
+    And(Box<Self>, Box<Self>),
+    /// One of the expressions must match
+    Or(Box<Self>, Box<Self>),
+    /// The expression must not match
+    Not(Box<Self>),
+    /// A comparison of a snapshot field
+    Compare(Field, Op, Value),
+}
+
+impl Expr {
+    /// Evaluate the expression for the given snapshot
+    fn matches(&self, sn: &SnapshotFile) -> bool {
+        match self {
+            Self::And(e1, e2) => e1.matches(sn) && e2.matches(sn),
+            Self::Or(e1, e2) => e1.matches(sn) || e2.matches(sn),
+            Self::Not(e) => !e.matches(sn),
+            Self::Compare(field, op, value) => match (field, value) {
+                (Field::Time, Value::Time(time)) => {
+                    let ord = sn.time.timestamp().cmp(&time.timestamp());
+                    match op {
+                        Op::Eq => ord.is_eq(),
+                        Op::Ne => ord.is_ne(),
+                        Op::Lt => ord.is_lt(),
+                        Op::Le => ord.is_le(),
+                        Op::Gt => ord.is_gt(),
+                        Op::Ge => ord.is_ge(),
+                        Op::Contains => false,
+                    }
+                }
+                (Field::Tags | Field::Paths, Value::List(list)) => {
+                    let field = if *field == Field::Tags {
+                        &sn.tags
+                    } else {
+                        &sn.paths
+                    };
+                    match op {
+                        Op::Eq => field == list,
+                        Op::Ne => field != list,
+                        Op::Contains => field.contains_all(list),
+                        _ => false,
+                    }
+                }
+                (Field::Id, Value::Text(prefix)) => {
+                    let id = sn.id.to_hex();
+                    match op {
+                        Op::Eq => id.starts_with(prefix.as_str()),
+                        Op::Ne => !id.starts_with(prefix.as_str()),
+                        _ => false,
+                    }
+                }
+                (field, Value::Text(value)) => {
+                    let text = match field {
+                        Field::Hostname => sn.hostname.as_str(),
+                        Field::Label => sn.label.as_str(),
+                        Field::Username => sn.username.as_str(),
+                        Field::Description => sn.description.as_deref().unwrap_or_default(),
+                        Field::ProgramVersion => sn.program_version.as_str(),
+                        _ => return false,
+                    };
+                    match op {
+                        Op::Eq => text == value,
+                        Op::Ne => text != value,
+                        Op::Contains => text.contains(value.as_str()),
+                        _ => false,
+                    }
+                }
+                _ => false,
+            },
+        }
+    }
+}
+
+/// A token of a filter expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// `(`
+    Open,
+    /// `)`
+    Close,
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// `!`
+    Not,
+    /// A comparison operator
+    Op(Op),
+    /// A bare word, i.e. a field name, the `contains` operator or an unquoted value
+    Word(String),
+    /// A quoted string
+    Quoted(String),
+}
+
+/// Create the error for an invalid filter expression
+///
+/// # Arguments
+///
+/// * `filter` - The filter expression
+/// * `guidance` - What is wrong
+fn invalid_filter(filter: &str, guidance: &'static str) -> Box<RusticError> {
+    RusticError::new(ErrorKind::InvalidInput, guidance)
+        .append_guidance_line("Filter expression: `{filter}`")
+        .attach_context("filter", filter)
+}
+
+/// Split a filter expression into tokens
+///
+/// # Arguments
+///
+/// * `s` - The filter expression
+///
+/// # Errors
+///
+/// * If a quoted string is not terminated or an unknown character is found.
+fn tokenize(s: &str) -> RusticResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<CharIndices<'_>> = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if next_is(&mut chars, '&') => Token::And,
+            '|' if next_is(&mut chars, '|') => Token::Or,
+            '=' if next_is(&mut chars, '=') => Token::Op(Op::Eq),
+            '!' if next_is(&mut chars, '=') => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if next_is(&mut chars, '=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if next_is(&mut chars, '=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '\'' | '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => {
+                            if let Some((_, c)) = chars.next() {
+                                value.push(c);
+                            }
+                        }
+                        Some((_, q)) if q == c => break,
+                        Some((_, c)) => value.push(c),
+                        None => {
+                            return Err(invalid_filter(
+                                s,
+                                "The filter contains an unterminated quoted string.",
+                            ));
+                        }
+                    }
+                }
+                Token::Quoted(value)
+            }
+            c if is_word_char(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+                    end = i + c.len_utf8();
+                }
+                Token::Word(s[start..end].to_string())
+            }
+            _ => {
+                return Err(invalid_filter(
+                    s,
+                    "The filter contains the unexpected character `{char}`. Please use `&&`, `||`, `!`, parentheses, comparison operators and quotes for values containing special characters.",
+                )
+                .attach_context("char", c.to_string()));
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Consume the next character if it is the expected one
+fn next_is(chars: &mut Peekable<CharIndices<'_>>, expected: char) -> bool {
+    chars.next_if(|(_, c)| *c == expected).is_some()
+}
+
+/// Returns whether the character can be part of an unquoted word
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !"()&|!=<>'\"".contains(c)
+}
+
+/// A recursive descent parser for filter expressions
+struct Parser<'a> {
+    /// The filter expression, used for error messages
+    filter: &'a str,
+    /// The remaining tokens
+    tokens: Peekable<IntoIter<Token>>,
+}
+
+impl Parser<'_> {
+    /// Parse `and ("||" and)*`
+    fn parse_or(&mut self) -> RusticResult<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parse `unary ("&&" unary)*`
+    fn parse_and(&mut self) -> RusticResult<Expr> {
+        let mut expr = self.parse_unary()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parse `"!" unary | "(" or ")" | comparison`
+    fn parse_unary(&mut self) -> RusticResult<Expr> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Open) => {
+                let expr = self.parse_or()?;
+                if self.tokens.next() != Some(Token::Close) {
+                    return Err(invalid_filter(
+                        self.filter,
+                        "The filter contains an unclosed parenthesis.",
+                    ));
+                }
+                Ok(expr)
+            }
+            Some(Token::Word(name)) => self.parse_comparison(&name),
+            _ => Err(invalid_filter(
+                self.filter,
+                "The filter is incomplete or misses a field name. Please use comparisons like `host == 'name'`.",
+            )),
+        }
+    }
+
+    /// Parse `field op value`, the field name has already been read
+    fn parse_comparison(&mut self, name: &str) -> RusticResult<Expr> {
+        let field = Field::parse(name).ok_or_else(|| {
+            invalid_filter(
+                self.filter,
+                "Unknown field `{field}`. Please use one of `host`, `label`, `user`, `description`, `program-version`, `tags`, `paths`, `time` or `id`.",
+            )
+            .attach_context("field", name)
+        })?;
+        let op = match self.tokens.next() {
+            Some(Token::Op(op)) => op,
+            Some(Token::Word(word)) if word == "contains" => Op::Contains,
+            _ => {
+                return Err(invalid_filter(
+                    self.filter,
+                    "Missing comparison operator after `{field}`. Please use `==`, `!=`, `<`, `<=`, `>`, `>=` or `contains`.",
+                )
+                .attach_context("field", name));
+            }
+        };
+        let value = match self.tokens.next() {
+            Some(Token::Word(value) | Token::Quoted(value)) => value,
+            _ => {
+                return Err(invalid_filter(
+                    self.filter,
+                    "Missing value after `{field}`. Please quote values containing whitespace or special characters.",
+                )
+                .attach_context("field", name));
+            }
+        };
+
+        let allowed = match field {
+            Field::Time => op != Op::Contains,
+            Field::Id => matches!(op, Op::Eq | Op::Ne),
+            _ => matches!(op, Op::Eq | Op::Ne | Op::Contains),
+        };
+        if !allowed {
+            return Err(invalid_filter(
+                self.filter,
+                "The operator used for `{field}` is not supported. Only `time` can be compared using `<`, `<=`, `>` and `>=`; `contains` cannot be used for `time` and `id`.",
+            )
+            .attach_context("field", name));
+        }
+
+        let value = match field {
+            Field::Time => Value::Time(RusticTime::parse_system(&value).map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::InvalidInput,
+                    "Failed to parse the time `{time}` in the filter. Please use a time like `2024-01-01` or `2024-01-01 12:00:00`.",
+                    err,
+                )
+                .attach_context("time", value.clone())
+            })?),
+            Field::Tags | Field::Paths => Value::List(StringList::from_str(&value).map_err(
+                |err| {
+                    RusticError::with_source(
+                        ErrorKind::InvalidInput,
+                        "Failed to parse the list `{list}` in the filter.",
+                        err,
+                    )
+                    .attach_context("list", value.clone())
+                },
+            )?),
+            _ => Value::Text(value),
+        };
+        Ok(Expr::Compare(field, op, value))
+    }
+}
+
+/// A filter for snapshots given as expression.
+///
+/// Filters are parsed from strings like `host == 'web1' && tags contains 'prod' && time > 2024-01-01` and can
+/// be used wherever snapshots are selected by a predicate, see [`SnapshotFilter::as_predicate`].
+///
+/// # Syntax
+///
+/// * Comparisons have the form `field operator value`. Values containing whitespace or special characters
+///   must be quoted using `'` or `"`.
+/// * The fields `host`, `label`, `user`, `description` and `program-version` support `==`, `!=` and
+///   `contains` (substring match).
+/// * The fields `tags` and `paths` support `==` and `!=` (comparing the whole list) and `contains`; values
+///   are comma-separated lists, `contains` matches if all given elements are contained.
+/// * The field `time` supports `==`, `!=`, `<`, `<=`, `>` and `>=`; values are times like `2024-01-01` or
+///   `"2024-01-01 12:00:00"`, using the local timezone if none is given.
+/// * The field `id` supports `==` and `!=`; the value may be an id prefix.
+/// * Comparisons can be combined using `&&`, `||`, `!` and parentheses; `&&` binds stronger than `||`.
+#[derive(Debug, Clone, SerializeDisplay, DeserializeFromStr)]
+pub struct SnapshotFilter {
+    /// The parsed expression
+    expr: Expr,
+    /// The filter as given
+    source: String,
+}
+
+impl SnapshotFilter {
+    /// Returns whether the given snapshot matches the filter
+    ///
+    /// # Arguments
+    ///
+    /// * `sn` - The snapshot to check
+    #[must_use]
+    pub fn matches(&self, sn: &SnapshotFile) -> bool {
+        self.expr.matches(sn)
+    }
+
+    /// Returns the filter as a predicate which can be used for methods like
+    /// [`Repository::get_matching_snapshots`](crate::Repository::get_matching_snapshots)
+    pub fn as_predicate(&self) -> impl FnMut(&SnapshotFile) -> bool + Send + Sync + '_ {
+        |sn| self.matches(sn)
+    }
+}
+
+impl FromStr for SnapshotFilter {
+    type Err = Box<RusticError>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            filter: s,
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let expr = parser.parse_or()?;
+        if parser.tokens.peek().is_some() {
+            return Err(invalid_filter(
+                s,
+                "The filter contains unexpected content after a complete expression. Please combine comparisons using `&&` or `||`.",
+            ));
+        }
+        Ok(Self {
+            expr,
+            source: s.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for SnapshotFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl PartialEq for SnapshotFilter {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for SnapshotFilter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    fn snapshot() -> SnapshotFile {
+        SnapshotFile {
+            hostname: "web1".to_string(),
+            label: "daily".to_string(),
+            tags: StringList::from_str("prod,db").unwrap(),
+            paths: StringList::from_str("/srv").unwrap(),
+            time: RusticTime::parse_system("2024-06-01 12:00:00").unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case("host == 'web1'", true)]
+    #[case("host == web2", false)]
+    #[case("hostname contains web", true)]
+    #[case("host != 'web1'", false)]
+    #[case("tags contains prod", true)]
+    #[case("tags contains 'prod,db'", true)]
+    #[case("tags contains test", false)]
+    #[case("tags == 'db,prod'", true)]
+    #[case("paths == /srv", true)]
+    #[case("time > 2024-01-01", true)]
+    #[case("time < 2024-01-01", false)]
+    #[case("time >= '2024-06-01 12:00:00'", true)]
+    #[case("host == 'web1' && tags contains 'prod' && time > 2024-01-01", true)]
+    #[case("host == 'web2' || label == daily", true)]
+    #[case("!(host == 'web2' || label == daily)", false)]
+    #[case("host == 'web2' || label == daily && tags contains test", false)]
+    #[case("(host == 'web2' || label == daily) && !tags contains test", true)]
+    fn test_filter(#[case] filter: &str, #[case] expected: bool) {
+        let filter = SnapshotFilter::from_str(filter).unwrap();
+        assert_eq!(filter.matches(&snapshot()), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("host")]
+    #[case("host ==")]
+    #[case("hots == web1")]
+    #[case("host < web1")]
+    #[case("time contains 2024")]
+    #[case("time > yesterday-ish")]
+    #[case("host == 'web1")]
+    #[case("(host == web1")]
+    #[case("host == web1 label == daily")]
+    #[case("host = web1")]
+    fn test_invalid_filter(#[case] filter: &str) {
+        assert!(SnapshotFilter::from_str(filter).is_err());
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        let filter = SnapshotFilter::from_str(" host == 'web1' ").unwrap();
+        assert_eq!(filter.to_string(), "host == 'web1'");
+        assert_eq!(
+            SnapshotFilter::from_str(&filter.to_string()).unwrap(),
+            filter
+        );
+    }
+}
@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
 
 use cached::macros::cached;
 use derive_setters::Setters;
@@ -8,7 +11,7 @@ use serde_with::{DisplayFromStr, serde_as};
 
 use crate::{
     ErrorKind, RusticError, RusticResult, StringList,
-    repofile::{DeleteOption, RusticTime, SnapshotFile},
+    repofile::{DeleteOption, RusticTime, SnapshotFile, snapshotfile::PathRemap},
 };
 
 /// Modification(s) to apply to a snapshot
@@ -35,6 +38,33 @@ pub struct SnapshotModification {
     #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
     pub set_hostname: Option<String>,
 
+    /// Shift the backup time by the given duration (e.g. "-1h" or "2h 30m")
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long,
+            value_name = "DURATION",
+            allow_hyphen_values = true,
+            conflicts_with = "set_time"
+        )
+    )]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub shift_time: Option<Span>,
+
+    /// Normalize the host name: Use lowercase and remove the domain part
+    #[cfg_attr(feature = "clap", clap(long, conflicts_with = "set_hostname"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
+    pub normalize_hostname: bool,
+
+    /// Rewrite the prefix of the snapshot paths (can be specified multiple times).
+    ///
+    /// Note that this only changes the recorded paths, not the contents of the snapshot tree.
+    #[cfg_attr(feature = "clap", clap(long, value_name = "SOURCE=TARGET"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::vec::overwrite_empty))]
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub remap_paths: Vec<PathRemap>,
+
     /// Tags to add (can be specified multiple times)
     #[cfg_attr(feature = "clap", clap(long, value_name = "TAG[,TAG,..]"))]
     #[cfg_attr(feature = "merge", merge(strategy = conflate::vec::overwrite_empty))]
@@ -141,10 +171,56 @@ impl SnapshotModification {
         changed |= set_check(&mut sn.description, &description);
         changed |= set_check(&mut sn.time, &self.set_time);
         changed |= set_check(&mut sn.hostname, &self.set_hostname);
+
+        if let Some(span) = self.shift_time {
+            let time = sn.time.checked_add(span).map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::InvalidInput,
+                    "Failed to shift snapshot time `{time}` by `{span}`.",
+                    err,
+                )
+                .attach_context("time", sn.time.to_string())
+                .attach_context("span", span.to_string())
+            })?;
+            changed |= set_check(&mut sn.time, &Some(time));
+        }
+
+        if self.normalize_hostname {
+            let hostname = normalized_hostname(&sn.hostname);
+            changed |= set_check(&mut sn.hostname, &Some(hostname));
+        }
+
+        if !self.remap_paths.is_empty() {
+            let remap = |path: &str| {
+                PathRemap::map(&self.remap_paths, Path::new(path))
+                    .to_string_lossy()
+                    .to_string()
+            };
+            let paths = sn.paths.iter().map(|path| remap(path)).collect();
+            changed |= set_check(&mut sn.paths.0, &Some(paths));
+            for root in &mut sn.roots {
+                let path = remap(&root.path);
+                changed |= set_check(&mut root.path, &Some(path));
+            }
+        }
+
         Ok(changed)
     }
 }
 
+/// Normalize the host name, i.e. use lowercase and remove the domain part
+///
+/// IP addresses are kept as they are.
+fn normalized_hostname(hostname: &str) -> String {
+    if hostname.parse::<IpAddr>().is_ok() {
+        return hostname.to_string();
+    }
+    hostname
+        .split_once('.')
+        .map_or(hostname, |(host, _)| host)
+        .to_lowercase()
+}
+
 #[allow(clippy::ref_option)]
 fn set_check<T: PartialEq + Clone>(a: &mut T, b: &Option<T>) -> bool {
     if let Some(b) = b
@@ -1,75 +1,125 @@
+#[cfg(feature = "async")]
+pub(crate) mod async_repository;
 pub(crate) mod command_input;
 pub(crate) mod credentials;
+pub(crate) mod lock;
 pub(crate) mod status;
 pub(crate) mod warm_up;
+pub(crate) mod watch;
 
 pub use status::*;
 
 use std::{
     cmp::Ordering,
-    io::Write,
+    io::{Read, Write},
+    ops::ControlFlow,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use bytes::Bytes;
+use bytesize::ByteSize;
 use derive_setters::Setters;
-use jiff::SignedDuration;
+use jiff::{SignedDuration, Timestamp};
 use log::info;
 use serde_with::{DisplayFromStr, serde_as};
 
 use crate::{
-    ReadSource, RepositoryBackends, RusticError,
+    CancellationToken, PauseHandle, ReadSource, RepositoryBackends, RusticError,
     backend::{
         FileType, FindInBackend, ReadBackend, WriteBackend,
+        bandwidth::{BandwidthLimitBackend, BandwidthLimiter, BandwidthSchedule},
         cache::{Cache, CachedBackend},
         decrypt::{DecryptBackend, DecryptReadBackend, DecryptWriteBackend},
+        filter::BackupFilter,
         hotcold::HotColdBackend,
+        ignore::{LocalSource, LocalSourceFilterOptions, LocalSourceSaveOptions},
         local_destination::LocalDestination,
+        metrics::{BackendCounters, BackendMetricsSnapshot, MetricsBackend},
         node::Node,
+        object_lock::{ObjectLockBackend, ObjectLockPolicy},
+        profile::{ReadProfile, ReadProfiler},
         warm_up::WarmUpAccessBackend,
     },
     blob::{
         BlobId, BlobType, PackedId,
         tree::{
             FindMatches, FindNode, NodeStreamer, TreeId, TreeStreamerOptions as LsOptions,
+            diff::{DiffEntry, LocalDiffStreamer, TreeDiffStreamer},
+            excludes::Excludes,
             rewrite::RewriteTreesOptions,
         },
     },
     commands::{
         self,
-        backup::BackupOptions,
+        backup::{BackupOptions, ChangedPath},
         check::{CheckOptions, CheckResults, check_repository},
         config::{ConfigOptions, save_config_hot},
-        copy::CopySnapshot,
-        key::{KeyOptions, add_current_key_to_repo},
-        prune::{PruneOptions, PrunePlan, prune_repository},
+        copy::{CopyOptions, CopySnapshot, CopyState},
+        dump::archive::ArchiveFormat,
+        find::FoundNode,
+        forget::{ForgetGroups, ForgetOptions, KeepOptions, RetentionTags},
+        index_gc::{IndexGcOptions, IndexGcStats, gc_index},
+        journal::{DeletionAudit, audit_deletions, cancel_deletions},
+        key::{
+            KeyInfo, KeyOptions, add_current_key_to_repo, change_password, list_keys, rekey_kdf,
+            remove_key,
+        },
+        maintain::{MaintenanceOptions, MaintenanceReport, maintain},
+        merge::MergeConflictPolicy,
+        migrate::{
+            MigrateOptions, MigrationEstimate, MigrationReport, migrate, migration_estimate,
+        },
+        prewarm::{PrewarmHint, PrewarmStats, prewarm_cache},
+        prune::{PackLayoutReport, PruneOptions, PrunePlan, prune_repository},
+        quarantine::{QuarantineOptions, QuarantineReport, quarantine_unindexed_packs},
+        recover::recover_packs,
+        references::{BlobOrigin, BlobReferences},
+        rekey::{RekeyOptions, RekeyState, finish_rekey, reencrypt, rekey, start_rekey},
         repair::{
             hotcold::{repair_hotcold, repair_hotcold_packs},
             index::{RepairIndexOptions, index_checked_from_collector, repair_index},
-            snapshots::{RepairSnapshotsOptions, repair_snapshots},
+            snapshots::{RepairReport, RepairSnapshotsOptions, repair_snapshots},
+        },
+        repoinfo::{IndexInfos, PackListEntry, RepoFileInfos},
+        restore::{
+            RestoreErrorPolicy, RestoreOptions, RestorePlan, RestoreReport, VerifyRestoreReport,
+            collect_and_prepare, restore_repository, verify_restore,
         },
-        repoinfo::{IndexInfos, RepoFileInfos},
-        restore::{RestoreOptions, RestorePlan, collect_and_prepare, restore_repository},
         rewrite::{RewriteOptions, rewrite_snapshots, rewrite_snapshots_and_trees},
+        scrub::{ScrubResults, scrub},
+        stats::{
+            NodeAggregator, NodeStats, RepoStats, SnapshotGroupStats, StatsOptions,
+            aggregate_nodes, repo_stats, snapshot_group_stats,
+        },
+        summary::backfill_summaries,
     },
     crypto::aespoly1305::Key,
     error::{ErrorKind, RusticResult},
+    id::Id,
     index::{
         GlobalIndex, IndexEntry, ReadGlobalIndex, ReadIndex,
         binarysorted::{IndexCollector, IndexType},
     },
     progress::{HiddenProgress, NoProgressBars, Progress, ProgressBars, ProgressType},
     repofile::{
-        ConfigFile, KeyId, PathList, RepoFile, RepoId, SnapshotFile, SnapshotSummary, Tree,
+        ConfigFile, KeyId, LockFile, LockId, PathList, RepoFile, RepoId, SnapshotFile,
+        SnapshotSummary, Tree,
         configfile::ConfigId,
         keyfile::{MasterKey, find_key_in_backend},
-        snapshotfile::SnapshotId,
+        packfile::PackId,
+        snapshotfile::{
+            ChangeDetection, SnapshotErrorPolicy, SnapshotId, SnapshotPage, SnapshotSort,
+            grouping::SnapshotGroupCriterion,
+        },
     },
     repository::{
         command_input::CommandInput,
         credentials::Credentials,
-        warm_up::{warm_up, warm_up_wait},
+        lock::{self, ExclusiveOperation, RepositoryLock},
+        warm_up::{CommandWarmUp, WarmUp, warm_up, warm_up_wait},
+        watch::{RepositoryChanges, RepositoryWatcher},
     },
     vfs::OpenFile,
 };
@@ -113,6 +163,16 @@ pub struct RepositoryOptions {
     #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
     pub cache_dir: Option<PathBuf>,
 
+    /// Maximum total size (e.g. 10GiB) of pack files and pack headers in the cache. If exceeded, the least
+    /// recently used ones are removed from the cache. Default: unlimited
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, global = true, value_name = "SIZE", conflicts_with = "no_cache")
+    )]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub cache_size_limit: Option<ByteSize>,
+
     /// Warm up needed data pack files by only requesting them without processing
     #[cfg_attr(feature = "clap", clap(long, global = true))]
     #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
@@ -144,6 +204,69 @@ pub struct RepositoryOptions {
     #[cfg_attr(feature = "clap", clap(long, global = true))]
     #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
     pub warm_up_batch: Option<usize>,
+
+    /// Number of warm-up command batches run concurrently [default: 1]
+    #[cfg_attr(feature = "clap", clap(long, global = true))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub warm_up_concurrency: Option<usize>,
+
+    /// Check if a warmed up pack is available by running the command with %id replaced by pack id.
+    /// The command must exit successfully if the pack is available; it is polled until all packs are available.
+    #[cfg_attr(feature = "clap", clap(long, global = true))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub warm_up_status_command: Option<CommandInput>,
+
+    /// Duration (e.g. 5m) between two checks if warmed up packs are available [default: 1m]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[cfg_attr(feature = "clap", clap(long, global = true, value_name = "DURATION"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub warm_up_poll_interval: Option<SignedDuration>,
+
+    /// Limit the bandwidth used for reading and writing files, e.g. `1MiB` or a schedule like
+    /// `08:00,1MiB 18:00,off`. Limits are given per second [default: off]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[cfg_attr(feature = "clap", clap(long, global = true, value_name = "SCHEDULE"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub bandwidth_limit: Option<BandwidthSchedule>,
+
+    /// Duration (e.g. 2160h) to lock newly written pack and snapshot files for. Needs a backend
+    /// supporting object locks, e.g. S3 with object lock enabled [default: off]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[cfg_attr(feature = "clap", clap(long, global = true, value_name = "DURATION"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub object_lock_retention: Option<SignedDuration>,
+
+    /// Duration (e.g. 4320h) to lock newly written pack files for; should not be shorter than the
+    /// retention of snapshots [default: object-lock-retention]
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[cfg_attr(feature = "clap", clap(long, global = true, value_name = "DURATION"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub object_lock_pack_retention: Option<SignedDuration>,
+
+    /// Number of pack files which are uploaded concurrently. Increase this to saturate backends with a
+    /// high latency [default: 2]
+    #[cfg_attr(feature = "clap", clap(long, global = true, value_name = "NUMBER"))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub upload_concurrency: Option<usize>,
+
+    /// How to handle snapshot files which cannot be read or parsed [default: skip]
+    #[cfg_attr(
+        feature = "clap",
+        clap(long, global = true, value_name = "POLICY", value_enum)
+    )]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::option::overwrite_none))]
+    pub snapshot_errors: Option<SnapshotErrorPolicy>,
+
+    /// Record all read requests issued to the backend, see [`Repository::read_profile`]
+    #[cfg_attr(feature = "clap", clap(long, global = true))]
+    #[cfg_attr(feature = "merge", merge(strategy = conflate::bool::overwrite_false))]
+    pub profile_reads: bool,
+
+    /// Map policy tags to retention classes, e.g. `"retention:legal-hold" = "never"`.
+    ///
+    /// Snapshots with a mapped tag are treated by `forget` like snapshots with the corresponding delete option.
+    #[cfg_attr(feature = "clap", clap(skip))]
+    pub retention_tags: RetentionTags,
 }
 
 #[derive(Debug, Clone)]
@@ -170,12 +293,30 @@ pub struct Repository<S> {
     /// The Backend to use for cold files
     pub(crate) be_cold: Arc<dyn WriteBackend>,
 
+    /// The recorded reads, if reads are profiled
+    profiler: Option<Arc<ReadProfiler>>,
+
+    /// The metrics of the backend operations
+    metrics: Arc<BackendCounters>,
+
+    /// The limiter of the bandwidth used by the backend
+    bandwidth: BandwidthLimiter,
+
+    /// The strategy to warm up files
+    warm_up_strategy: Arc<dyn WarmUp>,
+
     /// The options used for this repository
     opts: RepositoryOptions,
 
     /// The progress bar to use
     pb: Arc<dyn ProgressBars>,
 
+    /// The token to cancel long-running operations
+    cancel: CancellationToken,
+
+    /// The handle to pause long-running operations
+    pause: PauseHandle,
+
     /// The status
     status: S,
 }
@@ -226,11 +367,30 @@ impl Repository<()> {
             let _ = warm_up.uses_plural_placeholders()?;
 
             info!(
-                "using warm-up command {warm_up} with batch size {}",
-                opts.warm_up_batch.unwrap_or(1)
+                "using warm-up command {warm_up} with batch size {} and concurrency {}",
+                opts.warm_up_batch.unwrap_or(1),
+                opts.warm_up_concurrency.unwrap_or(1)
             );
         }
 
+        if let Some(status) = &opts.warm_up_status_command
+            && status.uses_plural_placeholders()?
+        {
+            return Err(RusticError::new(
+                ErrorKind::InvalidInput,
+                "The warm-up status command `{command}` is run once per pack and only supports %id and %path.",
+            )
+            .attach_context("command", status.to_string()));
+        }
+
+        let warm_up_strategy = Arc::new(CommandWarmUp {
+            command: opts.warm_up_command.clone(),
+            wait_command: opts.warm_up_wait_command.clone(),
+            status_command: opts.warm_up_status_command.clone(),
+            batch_size: opts.warm_up_batch,
+            concurrency: opts.warm_up_concurrency,
+        });
+
         let be_cold = be.clone();
 
         if opts.warm_up {
@@ -244,13 +404,39 @@ impl Repository<()> {
             name.push_str(&be_hot.location());
         }
 
+        if opts.object_lock_retention.is_some() || opts.object_lock_pack_retention.is_some() {
+            let retention = opts.object_lock_retention.unwrap_or(SignedDuration::ZERO);
+            let policy = ObjectLockPolicy::new(retention)
+                .pack_retention(opts.object_lock_pack_retention.unwrap_or(retention));
+            be = Arc::new(ObjectLockBackend::new(be, policy)?);
+        }
+
+        let metrics = backends.metrics();
+        let profiler = opts
+            .profile_reads
+            .then(|| Arc::new(ReadProfiler::default()));
+        let mut metrics_be = MetricsBackend::new(be, metrics.clone());
+        if let Some(profiler) = &profiler {
+            metrics_be = metrics_be.add_metrics(profiler.clone());
+        }
+        be = Arc::new(metrics_be);
+
+        let bandwidth = BandwidthLimiter::new(opts.bandwidth_limit.clone().unwrap_or_default());
+        be = Arc::new(BandwidthLimitBackend::new(be, bandwidth.clone()));
+
         Ok(Self {
             name,
             be,
             be_hot,
             be_cold,
+            profiler,
+            metrics,
+            bandwidth,
+            warm_up_strategy,
             opts: opts.clone(),
             pb: Arc::new(pb),
+            cancel: CancellationToken::default(),
+            pause: PauseHandle::default(),
             status: (),
         })
     }
@@ -289,6 +475,105 @@ impl<S> Repository<S> {
         self.pb.progress(ProgressType::Bytes, prefix)
     }
 
+    /// Get the retention classes assigned by tags, see [`RepositoryOptions::retention_tags`]
+    pub fn retention_tags(&self) -> &RetentionTags {
+        &self.opts.retention_tags
+    }
+
+    /// Get the policy for unreadable snapshots, see [`RepositoryOptions::snapshot_errors`]
+    pub fn snapshot_error_policy(&self) -> SnapshotErrorPolicy {
+        self.opts.snapshot_errors.unwrap_or_default()
+    }
+
+    /// Get the reads issued to the backend so far, see [`RepositoryOptions::profile_reads`]
+    ///
+    /// Reads served by the cache are not contained.
+    ///
+    /// # Returns
+    ///
+    /// The [`ReadProfile`] or `None` if reads are not profiled
+    pub fn read_profile(&self) -> Option<ReadProfile> {
+        self.profiler.as_ref().map(|profiler| profiler.profile())
+    }
+
+    /// Discard the reads recorded so far, e.g. to profile the next command separately
+    pub fn reset_read_profile(&self) {
+        if let Some(profiler) = &self.profiler {
+            profiler.reset();
+        }
+    }
+
+    /// Get the metrics of all operations issued to the backend so far, e.g. to monitor the throughput
+    /// of long running operations.
+    ///
+    /// Operations served by the cache are not contained.
+    #[must_use]
+    pub fn backend_metrics(&self) -> BackendMetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Discard the backend metrics collected so far, e.g. to monitor the next command separately
+    pub fn reset_backend_metrics(&self) {
+        self.metrics.reset();
+    }
+
+    /// Get the handle of the bandwidth limiter, e.g. to adjust the limit at runtime
+    ///
+    /// The limiter is initialized with [`RepositoryOptions::bandwidth_limit`] and shared by all reads and
+    /// writes of the backend.
+    #[must_use]
+    pub fn bandwidth_limiter(&self) -> &BandwidthLimiter {
+        &self.bandwidth
+    }
+
+    /// Use the given strategy to warm up files, e.g. an [`S3GlacierWarmUp`](crate::S3GlacierWarmUp).
+    ///
+    /// This replaces the strategy given by the warm-up commands of the [`RepositoryOptions`].
+    /// The [`RepositoryOptions::warm_up_wait`] and [`RepositoryOptions::warm_up_poll_interval`] are still used.
+    ///
+    /// # Arguments
+    ///
+    /// * `warm_up` - The warm-up strategy to use
+    #[must_use]
+    pub fn with_warm_up(mut self, warm_up: Arc<dyn WarmUp>) -> Self {
+        self.warm_up_strategy = warm_up;
+        self
+    }
+
+    /// Use the given token to cancel long-running operations like backup, restore, copy or prune.
+    ///
+    /// # Arguments
+    ///
+    /// * `cancel` - The cancellation token; keep a clone to cancel the operations
+    #[must_use]
+    pub fn with_cancellation_token(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Get the token to cancel long-running operations of this repository
+    #[must_use]
+    pub fn cancellation_token(&self) -> &CancellationToken {
+        &self.cancel
+    }
+
+    /// Use the given handle to pause and resume long-running operations like backup or restore.
+    ///
+    /// # Arguments
+    ///
+    /// * `pause` - The pause handle; keep a clone to pause and resume the operations
+    #[must_use]
+    pub fn with_pause_handle(mut self, pause: PauseHandle) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Get the handle to pause and resume long-running operations of this repository
+    #[must_use]
+    pub fn pause_handle(&self) -> &PauseHandle {
+        &self.pause
+    }
+
     /// Returns the Id of the config file
     ///
     /// # Errors
@@ -411,13 +696,24 @@ impl<S> Repository<S> {
             }
         }
 
+        let be = if use_hot {
+            self.be.clone()
+        } else {
+            // warm-up config file
+            self.warm_up_wait(std::iter::once(config_id))?;
+            self.be_cold.clone()
+        };
+
         let (key, key_id) = match credentials {
             Credentials::Password(password) => {
-                let (key, key_id) = if use_hot {
-                    find_key_in_backend(&self.be, &password, None)?
-                } else {
-                    find_key_in_backend(&self.be_cold, &password, None)?
+                // while a re-encryption is being finished, keys of the old and new master key may
+                // fit the password; only use a key which can decrypt the config
+                let fits_config = |key: &Key| {
+                    DecryptBackend::new(be.clone(), *key)
+                        .get_file::<ConfigFile>(&config_id)
+                        .is_ok()
                 };
+                let (key, key_id) = find_key_in_backend(&be, &password, None, fits_config)?;
                 info!("repository {}: password is correct.", self.name);
                 (key, Some(key_id))
             }
@@ -425,13 +721,6 @@ impl<S> Repository<S> {
         };
 
         // Initialize a new repository with given credentials and options.
-        let be = if use_hot {
-            self.be.clone()
-        } else {
-            // warm-up config file
-            self.warm_up_wait(std::iter::once(config_id))?;
-            self.be_cold.clone()
-        };
         let dbe = DecryptBackend::new(be, key);
         let mut config: ConfigFile = dbe.get_file(&config_id)?;
         if !use_hot && self.be_hot.is_some() {
@@ -549,6 +838,10 @@ impl<S> Repository<S> {
         let cache = (!self.opts.no_cache)
             .then(|| Cache::new(config.id, self.opts.cache_dir.clone()).ok())
             .flatten();
+        let cache = match (cache, self.opts.cache_size_limit) {
+            (Some(cache), Some(limit)) => Some(cache.with_size_limit(limit.as_u64())?),
+            (cache, _) => cache,
+        };
 
         if let Some(cache) = &cache {
             self.be = CachedBackend::new_cache(self.be.clone(), cache.clone());
@@ -559,7 +852,12 @@ impl<S> Repository<S> {
 
         let mut dbe = DecryptBackend::new(self.be.clone(), key);
         dbe.set_zstd(config.zstd()?);
+        dbe.set_zstd_params(config.zstd_params());
+        dbe.set_pack_layout(config.pack_layout());
         dbe.set_extra_verify(config.extra_verify());
+        if let Some(concurrency) = self.opts.upload_concurrency {
+            dbe.set_upload_concurrency(concurrency);
+        }
 
         let open = OpenStatus {
             cache,
@@ -573,8 +871,14 @@ impl<S> Repository<S> {
             be: self.be,
             be_hot: self.be_hot,
             be_cold: self.be_cold,
+            profiler: self.profiler,
+            metrics: self.metrics,
+            bandwidth: self.bandwidth,
+            warm_up_strategy: self.warm_up_strategy,
             opts: self.opts,
             pb: self.pb,
+            cancel: self.cancel,
+            pause: self.pause,
             status: open,
         })
     }
@@ -633,6 +937,8 @@ impl<S> Repository<S> {
 
     /// Warm up the given pack files and wait the configured waiting time.
     ///
+    /// If the warm-up strategy supports status polling, this waits until all files are available.
+    ///
     /// # Arguments
     ///
     /// * `ids` - The ids to warm up
@@ -641,6 +947,7 @@ impl<S> Repository<S> {
     ///
     /// * If the command could not be parsed.
     /// * If the thread pool could not be created.
+    /// * If the status of a file could not be determined.
     pub(crate) fn warm_up_wait<I: RepoId>(
         &self,
         ids: impl ExactSizeIterator<Item = I> + Clone,
@@ -677,10 +984,29 @@ impl<S: Open> Repository<S> {
     /// * If the string is not a valid hexadecimal string
     /// * If no id could be found.
     /// * If the id is not unique.
+    /// * If a pack file is given.
     pub fn cat_file(&self, tpe: FileType, id: &str) -> RusticResult<Bytes> {
         commands::cat::cat_file(self, tpe, id)
     }
 
+    /// Get the content of the repository file given by its full id and [`FileType`]
+    ///
+    /// All repository files except pack files are JSON documents. Key files are returned as stored,
+    /// all other files are decrypted. This allows to inspect the repository internals, e.g. for debugging.
+    ///
+    /// # Arguments
+    ///
+    /// * `tpe` - The type of the file to get
+    /// * `id` - The id of the file to get
+    ///
+    /// # Errors
+    ///
+    /// * If a pack file is given; use [`Repository::cat_blob`] to get the contained blobs.
+    /// * If the file could not be read or decrypted.
+    pub fn cat_file_by_id(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        commands::cat::cat_file_by_id(self, tpe, id)
+    }
+
     /// Add a new key to the repository
     ///
     /// # Arguments
@@ -695,6 +1021,80 @@ impl<S: Open> Repository<S> {
         add_current_key_to_repo(self, opts, pass)
     }
 
+    /// List all keys of the repository
+    ///
+    /// # Errors
+    ///
+    /// * If the key files could not be read.
+    ///
+    /// # Returns
+    ///
+    /// The information about all keys, sorted by creation time.
+    pub fn list_keys(&self) -> RusticResult<Vec<KeyInfo>> {
+        list_keys(self)
+    }
+
+    /// Remove the key with the given id
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the key to remove
+    ///
+    /// # Errors
+    ///
+    /// * If the key is the currently used key.
+    /// * If the key does not exist.
+    /// * If the key is the last key of the repository.
+    /// * If the key could not be removed.
+    pub fn remove_key(&self, id: &KeyId) -> RusticResult<()> {
+        remove_key(self, id)
+    }
+
+    /// Change the password used to open the repository
+    ///
+    /// This adds a new key with the given password and removes the key which was used to open the repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `pass` - The new password
+    /// * `opts` - The options to use for the new key
+    ///
+    /// # Errors
+    ///
+    /// * If the new key could not be saved.
+    /// * If the old key could not be removed.
+    ///
+    /// # Returns
+    ///
+    /// The id of the new key.
+    pub fn change_password(&mut self, pass: &str, opts: &KeyOptions) -> RusticResult<KeyId> {
+        change_password(self, pass, opts)
+    }
+
+    /// Upgrade the key used to open the repository to new parameters of the key derivation function
+    ///
+    /// This adds a new key with the same password, using the key derivation parameters given in `opts`,
+    /// and removes the key which was used to open the repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `pass` - The password of the key used to open the repository
+    /// * `opts` - The options to use for the new key
+    ///
+    /// # Errors
+    ///
+    /// * If the repository was not opened using a key file.
+    /// * If the password doesn't match the key used to open the repository.
+    /// * If the key derivation parameters are invalid.
+    /// * If the new key could not be saved or the old key could not be removed.
+    ///
+    /// # Returns
+    ///
+    /// The id of the new key.
+    pub fn rekey_kdf(&mut self, pass: &str, opts: &KeyOptions) -> RusticResult<KeyId> {
+        rekey_kdf(self, pass, opts)
+    }
+
     /// Update the repository config by applying the given [`ConfigOptions`]
     ///
     /// # Arguments
@@ -715,14 +1115,59 @@ impl<S: Open> Repository<S> {
         commands::config::apply_config(self, opts)
     }
 
+    /// Estimate the work needed to migrate the repository to the given repository version
+    ///
+    /// # Arguments
+    ///
+    /// * `to_version` - The repository version to migrate to
+    ///
+    /// # Errors
+    ///
+    /// * If the version is not supported.
+    /// * If the version is lower than the current version.
+    /// * If the index could not be read.
+    pub fn migration_estimate(&self, to_version: u32) -> RusticResult<MigrationEstimate> {
+        migration_estimate(self, to_version)
+    }
+
+    /// Migrate the repository to a newer repository version
+    ///
+    /// This sets the new version in the config and repacks the packs as needed, e.g. to compress all
+    /// data when migrating from version 1 to version 2. Use [`MigrateOptions::max_repack`] to limit the
+    /// data repacked within one run and run the migration again to continue.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The migration options
+    ///
+    /// # Errors
+    ///
+    /// * If the version is not supported.
+    /// * If the version is lower than the current version.
+    /// * If the repository is in append-only mode.
+    /// * If the config could not be saved or the repository could not be pruned.
+    pub fn migrate(&mut self, opts: &MigrateOptions) -> RusticResult<MigrationReport> {
+        migrate(self, opts)
+    }
+
     /// Get the repository configuration
     pub fn config(&self) -> &ConfigFile {
         &self.status.open_status().config
     }
 
-    /// Set the repository configuration
-    pub(crate) fn set_config(&mut self, config: ConfigFile) {
-        self.status.open_status_mut().config = config;
+    /// Set the repository configuration and use its compression and pack settings from now on
+    ///
+    /// # Errors
+    ///
+    /// * If the version of the config is not supported.
+    pub(crate) fn set_config(&mut self, config: ConfigFile) -> RusticResult<()> {
+        let status = self.status.open_status_mut();
+        status.dbe.set_zstd(config.zstd()?);
+        status.dbe.set_zstd_params(config.zstd_params());
+        status.dbe.set_pack_layout(config.pack_layout());
+        status.dbe.set_extra_verify(config.extra_verify());
+        status.config = config;
+        Ok(())
     }
 
     // TODO: add documentation!
@@ -739,6 +1184,11 @@ impl<S: Open> Repository<S> {
         &self.status.open_status().key_id
     }
 
+    /// Set the [`KeyId`] of the key used to open the repository
+    pub(crate) fn set_key_id(&mut self, key_id: Option<KeyId>) {
+        self.status.open_status_mut().key_id = key_id;
+    }
+
     /// Get the [`MasterKey`] used to open the repository
     pub fn key(&self) -> MasterKey {
         MasterKey::from_key(*self.status.open_status().dbe.key())
@@ -763,14 +1213,9 @@ impl<S: Open> Repository<S> {
     /// # Errors
     ///
     /// * If the key could not be removed.
+    #[deprecated(since = "0.12.0", note = "Use `Repository::remove_key()` instead.")]
     pub fn delete_key(&self, id: &KeyId) -> RusticResult<()> {
-        if self.key_id().as_ref() == Some(id) {
-            return Err(RusticError::new(
-                ErrorKind::Repository,
-                "Cannot remove the currently used key",
-            ));
-        }
-        self.dbe().remove(FileType::Key, id, false)
+        self.remove_key(id)
     }
 
     /// Get a single snapshot
@@ -796,7 +1241,8 @@ impl<S: Open> Repository<S> {
         filter: impl FnMut(&SnapshotFile) -> bool + Send + Sync,
     ) -> RusticResult<SnapshotFile> {
         let p = self.progress_counter("getting snapshot...");
-        let snap = SnapshotFile::from_str(self.dbe(), id, filter, &p)?;
+        let snap =
+            SnapshotFile::from_str(self.dbe(), id, filter, self.snapshot_error_policy(), &p)?;
         p.finish();
         Ok(snap)
     }
@@ -825,7 +1271,8 @@ impl<S: Open> Repository<S> {
         filter: impl FnMut(&SnapshotFile) -> bool + Send + Sync,
     ) -> RusticResult<Vec<SnapshotFile>> {
         let p = self.progress_counter("getting snapshots...");
-        let snaps = SnapshotFile::from_strs(self.dbe(), ids, filter, &p)?;
+        let snaps =
+            SnapshotFile::from_strs(self.dbe(), ids, filter, self.snapshot_error_policy(), &p)?;
         p.finish();
         Ok(snaps)
     }
@@ -883,6 +1330,59 @@ impl<S: Open> Repository<S> {
         self.get_matching_snapshots(|_| true)
     }
 
+    /// Get a page of the snapshots of the repository in the given order
+    ///
+    /// In contrast to [`Repository::get_all_snapshots`], only the snapshots up to the end of the requested
+    /// page are kept in memory. With [`SnapshotSort::Id`], only the snapshot files of the requested page
+    /// are read, which makes paging through repositories with many snapshots cheap.
+    ///
+    /// Unreadable snapshot files are handled according to [`RepositoryOptions::snapshot_errors`].
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - The number of snapshots to skip
+    /// * `limit` - The maximum number of snapshots to return
+    /// * `sort` - The order of the snapshots
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be listed.
+    /// * If a snapshot could not be read and the snapshot error policy is [`SnapshotErrorPolicy::Fail`].
+    pub fn list_snapshots_paged(
+        &self,
+        offset: usize,
+        limit: usize,
+        sort: SnapshotSort,
+    ) -> RusticResult<SnapshotPage> {
+        let p = self.progress_counter("reading snapshots...");
+        let page = SnapshotFile::page_from_backend(
+            self.dbe(),
+            offset,
+            limit,
+            sort,
+            self.snapshot_error_policy(),
+            &p,
+        )?;
+        p.finish();
+        Ok(page)
+    }
+
+    /// Get the ids of all snapshot files which cannot be read or parsed
+    ///
+    /// Depending on [`RepositoryOptions::snapshot_errors`], such snapshots are skipped when looking for the
+    /// latest snapshots.
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be listed.
+    pub fn unreadable_snapshots(&self) -> RusticResult<Vec<SnapshotId>> {
+        let p = self.progress_counter("reading snapshots...");
+        let (_, unreadable) =
+            SnapshotFile::all_from_backend(self.dbe(), SnapshotErrorPolicy::Skip, &p)?;
+        p.finish();
+        Ok(unreadable)
+    }
+
     /// Update existing snapshots to all from the repository
     ///
     /// # Arguments
@@ -966,6 +1466,54 @@ impl<S: Open> Repository<S> {
         commands::copy::relevant_snapshots(snaps, self, filter)
     }
 
+    /// Get the snapshots grouped by `group_by` together with the information which of them to forget
+    ///
+    /// Snapshots which must be kept by their delete option or a retention tag are never forgotten.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep` - The retention options
+    /// * `group_by` - The criterion to group the snapshots by
+    /// * `filter` - The filter to apply to the snapshots
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be read.
+    /// * If keep options are not valid
+    ///
+    /// # Returns
+    ///
+    /// The [`ForgetGroups`] containing all matching snapshots; use [`ForgetGroups::into_forget_ids`] to get
+    /// the ids of the snapshots to remove.
+    pub fn get_forget_snapshots(
+        &self,
+        keep: &KeepOptions,
+        group_by: SnapshotGroupCriterion,
+        filter: impl FnMut(&SnapshotFile) -> bool,
+    ) -> RusticResult<ForgetGroups> {
+        commands::forget::get_forget_snapshots(self, keep, group_by, filter)
+    }
+
+    /// Get aggregated statistics for each snapshot group, e.g. for dashboards
+    ///
+    /// # Arguments
+    ///
+    /// * `criterion` - The criterion to group the snapshots by
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be read.
+    ///
+    /// # Returns
+    ///
+    /// The [`SnapshotGroupStats`] of each group, sorted by group
+    pub fn snapshot_group_stats(
+        &self,
+        criterion: SnapshotGroupCriterion,
+    ) -> RusticResult<Vec<SnapshotGroupStats>> {
+        snapshot_group_stats(self, criterion)
+    }
+
     // TODO: Maybe only offer a method to remove &[Snapshotfile] and check if they must be kept.
     // See e.g. the merge command of the CLI
     /// Remove the given snapshots from the repository
@@ -976,7 +1524,9 @@ impl<S: Open> Repository<S> {
     ///
     /// # Errors
     ///
-    // TODO: Document errors
+    /// * If the repository is in append-only mode.
+    /// * If some snapshots are still locked by the backend, see [`WriteBackend::supports_object_lock`].
+    // TODO: Document remaining errors
     ///
     /// # Panics
     ///
@@ -988,6 +1538,24 @@ impl<S: Open> Repository<S> {
                 "Repository is in append-only mode and snapshots cannot be deleted from it. Aborting.",
             ));
         }
+        if self.dbe().supports_object_lock() {
+            let now = Timestamp::now();
+            let mut locked = Vec::new();
+            for id in ids {
+                if let Some(until) = self.dbe().locked_until(FileType::Snapshot, id)?
+                    && until > now
+                {
+                    locked.push(format!("{id} (until {until})"));
+                }
+            }
+            if !locked.is_empty() {
+                return Err(RusticError::new(
+                    ErrorKind::Repository,
+                    "Snapshots `{snapshots}` are locked by the backend and cannot be deleted before their retention period expired. Aborting.",
+                )
+                .attach_context("snapshots", locked.join(", ")));
+            }
+        }
         let p = self.progress_counter("removing snapshots...");
         self.dbe().delete_list(true, ids.iter(), p)?;
         Ok(())
@@ -1054,7 +1622,115 @@ impl<S: Open> Repository<S> {
         check_repository(self, opts, trees)
     }
 
-    /// Get the plan about what should be pruned and/or repacked.
+    /// Incrementally verify the pack files of the repository.
+    ///
+    /// This reads and verifies the least-recently verified packs (packs which have never been verified first)
+    /// up to the given byte budget. The times of successful verifications are saved in a scrub file in the
+    /// repository, so that regularly running `scrub` eventually verifies all packs and then starts over with
+    /// the oldest verified ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - The maximum size of the packs to verify; at least one pack is verified if this is not zero
+    ///
+    /// # Errors
+    ///
+    /// * If the backend cannot store the scrub state, see [`ReadBackend::supports_file_type`].
+    /// * If the index or scrub files could not be read.
+    /// * If the scrub state could not be saved.
+    ///
+    /// # Returns
+    ///
+    /// The [`ScrubResults`] containing the verified packs and the found problems
+    pub fn scrub(&self, budget: ByteSize) -> RusticResult<ScrubResults> {
+        scrub(self, budget)
+    }
+
+    /// Acquire an exclusive lock on the repository.
+    ///
+    /// Locks are cooperative: they are stored as lock files in the repository and only respected by
+    /// clients which check them. An exclusive lock should be held by operations removing data (e.g. `prune`)
+    /// and fails if any other non-stale lock exists.
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is locked by another process.
+    /// * If the lock files could not be listed, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The [`RepositoryLock`] which removes the lock file when dropped
+    pub fn lock_exclusive(&self) -> RusticResult<RepositoryLock> {
+        lock::lock(self, true)
+    }
+
+    /// Acquire a shared lock on the repository.
+    ///
+    /// A shared lock should be held by operations adding or reading data (e.g. `backup`) and fails if a
+    /// non-stale exclusive lock exists. See [`Repository::lock_exclusive`].
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is exclusively locked by another process.
+    /// * If the lock files could not be listed, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The [`RepositoryLock`] which removes the lock file when dropped
+    pub fn lock_shared(&self) -> RusticResult<RepositoryLock> {
+        lock::lock(self, false)
+    }
+
+    /// Start an exclusive operation on the repository.
+    ///
+    /// `prune`, `repair index`, `repair snapshots`, `recover_packs`, `cancel_deletions`,
+    /// `quarantine_unindexed_packs`, `rekey`, `finish_rekey` and `reencrypt` are exclusive operations: Only one
+    /// of them can run on a repository within this process at a time and the repository is locked exclusively
+    /// while they are running, see [`Repository::lock_exclusive`]. Use this to run custom operations
+    /// exclusively as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The name of the operation, used in error messages
+    ///
+    /// # Errors
+    ///
+    /// * If another exclusive operation is running on the repository or another process holds a lock on the
+    ///   repository, see [`RusticError::is_busy`].
+    /// * If the lock files could not be listed, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The [`ExclusiveOperation`] which ends the operation and releases the lock when dropped
+    pub fn exclusive_operation(&self, operation: &'static str) -> RusticResult<ExclusiveOperation> {
+        lock::exclusive_operation(self, operation)
+    }
+
+    /// List the locks of the repository, including stale locks.
+    ///
+    /// # Errors
+    ///
+    /// * If the lock files could not be listed.
+    pub fn list_locks(&self) -> RusticResult<Vec<(LockId, LockFile)>> {
+        lock::list_locks(self)
+    }
+
+    /// Remove stale locks, i.e. locks which have not been refreshed within
+    /// [`STALE_LOCK_TIMEOUT`](crate::repofile::STALE_LOCK_TIMEOUT) or which
+    /// belong to a process of this host which is no longer running.
+    ///
+    /// # Errors
+    ///
+    /// * If the lock files could not be listed or removed.
+    ///
+    /// # Returns
+    ///
+    /// The ids of the removed locks
+    pub fn remove_stale_locks(&self) -> RusticResult<Vec<LockId>> {
+        lock::remove_stale_locks(self)
+    }
+
+    /// Get the plan about what should be pruned and/or repacked.
     ///
     /// # Arguments
     ///
@@ -1071,6 +1747,22 @@ impl<S: Open> Repository<S> {
         PrunePlan::from_prune_options(self, opts)
     }
 
+    /// Get a report about how well the packs of the repository are used
+    ///
+    /// The report contains the fill ratio and blob type mixing of each pack and an overall fragmentation
+    /// score which helps to decide whether running `prune` with repacking is worthwhile.
+    ///
+    /// # Errors
+    ///
+    /// * If the index or the snapshots could not be read.
+    ///
+    /// # Returns
+    ///
+    /// The [`PackLayoutReport`]
+    pub fn pack_layout(&self) -> RusticResult<PackLayoutReport> {
+        Ok(self.prune_plan(&PruneOptions::default())?.pack_layout())
+    }
+
     /// Perform the pruning on the repository.
     ///
     /// # Arguments
@@ -1091,9 +1783,164 @@ impl<S: Open> Repository<S> {
     ///
     // TODO: Document panics
     pub fn prune(&self, opts: &PruneOptions, prune_plan: PrunePlan) -> RusticResult<()> {
+        let _guard = lock::exclusive_operation(self, "prune")?;
         prune_repository(self, opts, prune_plan)
     }
 
+    /// Run the regular maintenance of the repository in a single call.
+    ///
+    /// Depending on the given options, this forgets snapshots according to a retention policy,
+    /// prunes the repository, cleans up the local cache and checks the repository - in this order.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The maintenance options
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is in append-only mode and snapshots should be forgotten or the repository
+    ///   should be pruned.
+    /// * If one of the steps fails; the following steps are not run.
+    ///
+    /// # Returns
+    ///
+    /// The [`MaintenanceReport`] combining the results of all steps
+    pub fn maintain(&self, opts: &MaintenanceOptions) -> RusticResult<MaintenanceReport> {
+        maintain(self, opts)
+    }
+
+    /// Start re-encrypting the repository using a newly generated master key.
+    ///
+    /// Use [`Repository::rekey`] to re-encrypt the packs and [`Repository::finish_rekey`] to finish
+    /// the re-encryption, or [`Repository::reencrypt`] to do both. The returned state can be saved to
+    /// continue the re-encryption later.
+    ///
+    /// The re-encryption is marked as in progress in the config file; until it is finished, `prune`,
+    /// `repair index` and `quarantine_unindexed_packs` refuse to run as they would remove the re-encrypted packs.
+    /// Only the master key is replaced; the crypto scheme of the repository stays the same.
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is in append-only mode.
+    /// * If the repository is locked by another process.
+    /// * If the new key could not be serialized or encrypted.
+    /// * If the config file could not be written.
+    ///
+    /// # Returns
+    ///
+    /// The state of the re-encryption
+    pub fn start_rekey(&mut self) -> RusticResult<RekeyState> {
+        let _guard = lock::exclusive_operation(self, "start rekey")?;
+        start_rekey(self)
+    }
+
+    /// Re-encrypt packs of the repository using the new key.
+    ///
+    /// The re-encrypted packs are not yet added to the index, so the repository remains usable with the
+    /// current key.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state of the re-encryption; this is updated with the re-encrypted packs
+    /// * `opts` - The options to use
+    ///
+    /// # Errors
+    ///
+    /// * If the re-encryption has not been started using [`Repository::start_rekey`].
+    /// * If the state does not belong to the repository.
+    /// * If the index could not be read.
+    /// * If blobs could not be re-encrypted.
+    ///
+    /// # Returns
+    ///
+    /// The number of packs which still need to be re-encrypted.
+    pub fn rekey(&self, state: &mut RekeyState, opts: &RekeyOptions) -> RusticResult<usize> {
+        let _guard = lock::exclusive_operation(self, "rekey")?;
+        rekey(self, state, opts)
+    }
+
+    /// Finish re-encrypting the repository.
+    ///
+    /// This adds a new key file, writes the index, snapshots and config using the new key and removes all
+    /// files encrypted using the old key, including all old key files. If this is interrupted, run it again
+    /// using the same state; this also works if the repository has already been opened using the new key.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state of the re-encryption
+    /// * `pass` - The password to protect the new key with
+    /// * `opts` - The options for the new key file
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is in append-only mode.
+    /// * If not all packs have been re-encrypted.
+    /// * If files could not be read, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The id of the new key file.
+    ///
+    /// # Note
+    ///
+    /// Snapshots get new ids. The repository must be opened again to be used with the new key.
+    pub fn finish_rekey(
+        &self,
+        state: &RekeyState,
+        pass: &str,
+        opts: &KeyOptions,
+    ) -> RusticResult<KeyId> {
+        let _guard = lock::exclusive_operation(self, "finish rekey")?;
+        finish_rekey(self, state, pass, opts)
+    }
+
+    /// Re-encrypt the whole repository using the new master key of the given state.
+    ///
+    /// This rotates the master key, e.g. after a password has been leaked: All packs, index files,
+    /// snapshots and the config are rewritten using the new key and all old key files are removed.
+    ///
+    /// The packs are re-encrypted in batches limited by `opts.max_repack`; after each batch, `save_state`
+    /// is called with the updated [`RekeyState`]. Passing the last saved state again resumes an interrupted
+    /// re-encryption. This combines [`Repository::rekey`] and [`Repository::finish_rekey`]; use
+    /// [`Repository::start_rekey`] to get the state of a new re-encryption.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state of the re-encryption
+    /// * `opts` - The options limiting the size of each batch
+    /// * `pass` - The password to protect the new key with
+    /// * `key_opts` - The options for the new key file
+    /// * `save_state` - Called to persist the state after each batch
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is in append-only mode.
+    /// * If the repository is locked by another process.
+    /// * If the state does not belong to the repository.
+    /// * If a batch didn't re-encrypt any pack.
+    /// * If blobs could not be re-encrypted.
+    /// * If `save_state` fails.
+    /// * If files could not be read, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The id of the new key file.
+    ///
+    /// # Note
+    ///
+    /// Snapshots get new ids. The repository must be opened again to be used with the new key.
+    pub fn reencrypt(
+        &self,
+        state: &mut RekeyState,
+        opts: &RekeyOptions,
+        pass: &str,
+        key_opts: &KeyOptions,
+        save_state: impl FnMut(&RekeyState) -> RusticResult<()>,
+    ) -> RusticResult<KeyId> {
+        let _guard = lock::exclusive_operation(self, "reencrypt")?;
+        reencrypt(self, state, opts, pass, key_opts, save_state)
+    }
+
     /// Turn the repository into the `IndexedFull` state by reading and storing the index
     ///
     /// # Errors
@@ -1143,8 +1990,14 @@ impl<S: Open> Repository<S> {
             be: self.be,
             be_hot: self.be_hot,
             be_cold: self.be_cold,
+            profiler: self.profiler,
+            metrics: self.metrics,
+            bandwidth: self.bandwidth,
+            warm_up_strategy: self.warm_up_strategy,
             opts: self.opts,
             pb: self.pb,
+            cancel: self.cancel,
+            pause: self.pause,
             status,
         }
     }
@@ -1202,8 +2055,14 @@ impl<S: Open> Repository<S> {
             be: self.be,
             be_hot: self.be_hot,
             be_cold: self.be_cold,
+            profiler: self.profiler,
+            metrics: self.metrics,
+            bandwidth: self.bandwidth,
+            warm_up_strategy: self.warm_up_strategy,
             opts: self.opts,
             pb: self.pb,
+            cancel: self.cancel,
+            pause: self.pause,
             status,
         }
     }
@@ -1222,6 +2081,20 @@ impl<S: Open> Repository<S> {
         commands::repoinfo::collect_index_infos(self)
     }
 
+    /// List all packs of the repository together with the index information about their blobs.
+    /// This method reads all index files, even if an index is already available in memory.
+    ///
+    /// # Errors
+    ///
+    /// * If the index could not be read.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over all packs contained in the index, ordered by their id.
+    pub fn list_packs(&self) -> RusticResult<impl Iterator<Item = PackListEntry>> {
+        Ok(commands::repoinfo::list_packs(self)?.into_iter())
+    }
+
     /// Read a given [`RepoFile`]
     ///
     /// # Errors
@@ -1294,9 +2167,147 @@ impl<S: Open> Repository<S> {
     ///
     // TODO: Document errors
     pub fn repair_index(&self, opts: &RepairIndexOptions, dry_run: bool) -> RusticResult<()> {
+        let _guard = lock::exclusive_operation(self, "repair index")?;
         repair_index(self, *opts, dry_run)
     }
 
+    /// Remove stale `packs_to_delete` entries from the index.
+    ///
+    /// This removes entries of packs marked for deletion which no longer exist in the backend (e.g. as they have
+    /// already been deleted) and rewrites the affected index files. This keeps the index small without
+    /// running a full `prune`.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The options to use
+    /// * `dry_run` - If true, only compute what would be done
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is in append-only mode.
+    /// * If the packs could not be listed.
+    /// * If the index files could not be read, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The [`IndexGcStats`] of the (would-be) changes
+    pub fn gc_index(&self, opts: &IndexGcOptions, dry_run: bool) -> RusticResult<IndexGcStats> {
+        gc_index(self, *opts, dry_run)
+    }
+
+    /// Audit the pending deletions of the repository.
+    ///
+    /// This lists the packs marked for deletion together with the deletion journals written by `prune`
+    /// which record when and with which options they have been marked.
+    ///
+    /// # Errors
+    ///
+    /// * If the index files or deletion journals could not be read.
+    ///
+    /// # Returns
+    ///
+    /// The [`DeletionAudit`] of the repository
+    pub fn audit_deletions(&self) -> RusticResult<DeletionAudit> {
+        audit_deletions(self)
+    }
+
+    /// Cancel the pending deletion of the given packs.
+    ///
+    /// The packs are un-marked in the index, so they are no longer removed by `prune`. This is only
+    /// possible as long as the packs have not been removed yet, i.e. before `keep_delete` has expired.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The packs to un-mark
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is in append-only mode.
+    /// * If the packs could not be listed.
+    /// * If the index files could not be read, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The packs which have been un-marked
+    pub fn cancel_deletions(&self, ids: &[PackId]) -> RusticResult<Vec<PackId>> {
+        let _guard = lock::exclusive_operation(self, "cancel deletions")?;
+        cancel_deletions(self, ids)
+    }
+
+    /// Recover packs which are marked for deletion.
+    ///
+    /// In contrast to [`Repository::cancel_deletions`], the pack headers are re-read and the packs are
+    /// re-added to the index like packs recovered by `prune`. Use this to undo a `prune` run which
+    /// marked packs which are still needed, e.g. because it was run with wrong `ignore_snaps`.
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The packs to recover
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is in append-only mode.
+    /// * If the packs could not be listed.
+    /// * If the index files could not be read, written or removed.
+    ///
+    /// # Returns
+    ///
+    /// The packs which have been recovered
+    pub fn recover_packs(&self, ids: &[PackId]) -> RusticResult<Vec<PackId>> {
+        let _guard = lock::exclusive_operation(self, "recover packs")?;
+        recover_packs(self, ids)
+    }
+
+    /// Quarantine packs which are present in the backend but not contained in the index.
+    ///
+    /// The unindexed packs older than `min_age` are marked for deletion and recorded in a deletion journal
+    /// instead of being removed; the repository is locked exclusively while doing so. The returned report
+    /// lists them together with the packs already quarantined before, so they can be reviewed before
+    /// `prune` removes them after `keep_delete` has expired. Use [`Repository::cancel_deletions`] or
+    /// [`Repository::recover_packs`] to restore quarantined packs.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The quarantine options
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is in append-only mode.
+    /// * If the repository is locked by another process.
+    /// * If the packs could not be listed.
+    /// * If the index files could not be read or written.
+    ///
+    /// # Returns
+    ///
+    /// The [`QuarantineReport`] listing the quarantined packs
+    pub fn quarantine_unindexed_packs(
+        &self,
+        opts: &QuarantineOptions,
+    ) -> RusticResult<QuarantineReport> {
+        let _guard = lock::exclusive_operation(self, "quarantine")?;
+        quarantine_unindexed_packs(self, opts)
+    }
+
+    /// Download the files needed by an upcoming operation into the local cache.
+    ///
+    /// The files are downloaded in parallel ahead of time which reduces the time the operation itself
+    /// takes on slow connections. Files which are already cached are not downloaded again.
+    ///
+    /// # Arguments
+    ///
+    /// * `hint` - The files the operation will need
+    ///
+    /// # Errors
+    ///
+    /// * If the files could not be listed, read or saved to the cache.
+    ///
+    /// # Returns
+    ///
+    /// The [`PrewarmStats`]; if the repository uses no cache, nothing is downloaded.
+    pub fn prewarm_cache(&self, hint: &PrewarmHint) -> RusticResult<PrewarmStats> {
+        prewarm_cache(self, hint)
+    }
+
     /// Repair hotcold packs
     ///
     /// This compares the pack files in the hot and cold repo part and copies missing ones.
@@ -1331,9 +2342,55 @@ impl<S: Open> Repository<S> {
     ) -> RusticResult<Vec<SnapshotFile>> {
         rewrite_snapshots(self, snapshots, opts)
     }
+
+    /// Rewrite all snapshots matching the filter using snapshot modifications.
+    ///
+    /// This allows to change snapshot metadata like host names, paths or times in bulk.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter to select the snapshots to rewrite
+    /// * `opts` - The rewrite options, including the modification(s) to apply to each snapshot
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be read.
+    /// * If a modification could not be applied.
+    /// * If the rewritten snapshots could not be saved or the original ones could not be removed.
+    ///
+    /// # Returns
+    ///
+    /// The changed snapshots.
+    pub fn rewrite_matching_snapshots(
+        &self,
+        filter: impl FnMut(&SnapshotFile) -> bool,
+        opts: &RewriteOptions,
+    ) -> RusticResult<Vec<SnapshotFile>> {
+        let snapshots = self.get_matching_snapshots(filter)?;
+        rewrite_snapshots(self, snapshots, opts)
+    }
 }
 
 impl<S: IndexedFull> Repository<S> {
+    /// Get repository-wide statistics
+    ///
+    /// This computes the raw data size, the restore size of all snapshots, the deduplication ratio,
+    /// the compression ratio per blob type and the distribution of the pack sizes. If
+    /// [`StatsOptions::per_snapshot`] is set, the size of the data which is only referenced by a single
+    /// snapshot is attributed to this snapshot.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The options to use
+    ///
+    /// # Errors
+    ///
+    /// * If the index or the snapshots could not be read.
+    /// * If a tree could not be read.
+    pub fn stats(&self, opts: &StatsOptions) -> RusticResult<RepoStats> {
+        repo_stats(self, opts)
+    }
+
     /// Get the [`IndexEntry`] of the given blob
     ///
     /// # Arguments
@@ -1472,6 +2529,32 @@ impl<S: IndexedTree> Repository<S> {
         Tree::find_matching_nodes(self.dbe(), self.index(), ids, matches)
     }
 
+    /// Search all snapshots matching the filter for files and dirs matching a glob pattern
+    ///
+    /// This answers "which snapshots contain this file" queries. Subtrees shared by multiple
+    /// snapshots are only read once.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The glob pattern (gitignore syntax); a pattern without `/` matches the file name at any level
+    /// * `filter` - The filter to select the snapshots to search
+    ///
+    /// # Errors
+    ///
+    /// * If the glob pattern is invalid.
+    /// * If the snapshots or the trees could not be read.
+    ///
+    /// # Returns
+    ///
+    /// The found nodes together with their snapshot and path, ordered by snapshot
+    pub fn find(
+        &self,
+        pattern: &str,
+        filter: impl FnMut(&SnapshotFile) -> bool,
+    ) -> RusticResult<Vec<FoundNode>> {
+        commands::find::find(self, pattern, filter)
+    }
+
     /// drop the `Repository` index leaving an `Open` `Repository`
     pub fn drop_index(self) -> Repository<OpenStatus> {
         Repository {
@@ -1479,11 +2562,40 @@ impl<S: IndexedTree> Repository<S> {
             be: self.be,
             be_hot: self.be_hot,
             be_cold: self.be_cold,
+            profiler: self.profiler,
+            metrics: self.metrics,
+            bandwidth: self.bandwidth,
+            warm_up_strategy: self.warm_up_strategy,
             opts: self.opts,
             pb: self.pb,
+            cancel: self.cancel,
+            pause: self.pause,
             status: self.status.into_open_status(),
         }
     }
+
+    /// Periodically re-list index and snapshot files and re-read the index if it has changed.
+    ///
+    /// This is meant for long-running processes serving a repository which should pick up new backups.
+    /// Use [`RepositoryWatcher`] to refresh the repository manually.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - The interval to wait between two checks for changes
+    /// * `hook` - Called with the refreshed repository and the changes whenever changes are detected.
+    ///   Return [`ControlFlow::Break`] to stop watching.
+    ///
+    /// # Errors
+    ///
+    /// * If the index or snapshot files could not be listed.
+    /// * If the index could not be read.
+    pub fn watch_for_changes(
+        &mut self,
+        interval: Duration,
+        hook: impl FnMut(&Self, &RepositoryChanges) -> ControlFlow<()>,
+    ) -> RusticResult<()> {
+        RepositoryWatcher::new(self)?.watch(self, interval, hook)
+    }
 }
 
 impl<S: IndexedTree> Repository<S> {
@@ -1513,7 +2625,7 @@ impl<S: IndexedTree> Repository<S> {
         let (id, path) = snap_path.split_once(':').unwrap_or((snap_path, ""));
 
         let p = &self.progress_counter("getting snapshot...");
-        let snap = SnapshotFile::from_str(self.dbe(), id, filter, p)?;
+        let snap = SnapshotFile::from_str(self.dbe(), id, filter, self.snapshot_error_policy(), p)?;
 
         Tree::node_from_path(self.dbe(), self.index(), snap.tree, Path::new(path))
     }
@@ -1584,6 +2696,275 @@ impl<S: IndexedTree> Repository<S> {
         NodeStreamer::new_with_glob(self.dbe().clone(), self.index(), node, ls_opts)
     }
 
+    /// List the contents of the given `path` within a [`SnapshotFile`]
+    ///
+    /// The nodes are streamed, i.e. subtrees are only read when the iterator reaches them.
+    /// Each returned [`Node`] contains the full metadata (size, mode, owner, times) needed for a long listing.
+    ///
+    /// # Arguments
+    ///
+    /// * `snap` - The snapshot to list
+    /// * `path` - The path within the snapshot to list
+    /// * `ls_opts` - The options to use, e.g. recursion, maximum depth and glob patterns
+    ///
+    /// # Note
+    ///
+    /// The `PathBuf` returned will be relative to the given `path`.
+    ///
+    /// # Errors
+    ///
+    /// * If the path is not found within the snapshot.
+    /// * If the glob patterns are invalid.
+    pub fn ls_snapshot_path(
+        &self,
+        snap: &SnapshotFile,
+        path: &str,
+        ls_opts: &LsOptions,
+    ) -> RusticResult<impl Iterator<Item = RusticResult<(PathBuf, Node)>> + Clone + '_> {
+        let node = self.node_from_snapshot_and_path(snap, path)?;
+        self.ls(&node, ls_opts)
+    }
+
+    /// Walk all nodes within the given [`Node`] and pass them to the given aggregator
+    ///
+    /// This is the tree walk used by [`Repository::node_stats`] and can be used to implement other
+    /// analyses using a custom [`NodeAggregator`].
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node to walk; if it is a directory, all nodes within it are visited recursively
+    /// * `aggregator` - The aggregator to pass the nodes to
+    ///
+    /// # Errors
+    ///
+    /// * If a tree could not be read.
+    pub fn aggregate_nodes(
+        &self,
+        node: &Node,
+        aggregator: &mut impl NodeAggregator,
+    ) -> RusticResult<()> {
+        aggregate_nodes(self, node, aggregator)
+    }
+
+    /// Get statistics about all nodes within the given [`Node`]
+    ///
+    /// This includes counts per node type, hard link groups and sparse files.
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The node to get the statistics for
+    ///
+    /// # Errors
+    ///
+    /// * If a tree could not be read.
+    pub fn node_stats(&self, node: &Node) -> RusticResult<NodeStats> {
+        let mut stats = NodeStats::default();
+        aggregate_nodes(self, node, &mut stats)?;
+        Ok(stats)
+    }
+
+    /// Get statistics about all nodes of the given snapshot, see [`Repository::node_stats`]
+    ///
+    /// # Arguments
+    ///
+    /// * `snap` - The snapshot to get the statistics for
+    ///
+    /// # Errors
+    ///
+    /// * If a tree could not be read.
+    pub fn snapshot_stats(&self, snap: &SnapshotFile) -> RusticResult<NodeStats> {
+        let node = self.node_from_snapshot_and_path(snap, "")?;
+        self.node_stats(&node)
+    }
+
+    /// Stream the references of all blobs contained in the index
+    ///
+    /// All snapshots are walked in a single pass, visiting each tree only once. The result allows
+    /// external tools to do capacity analytics, e.g. to find out which snapshots to remove to free some space.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_size` - The maximum number of referencing snapshots given per blob
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots or the trees could not be read.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over the [`BlobReferences`] of all blobs contained in the index
+    pub fn blob_references(
+        &self,
+        sample_size: usize,
+    ) -> RusticResult<impl Iterator<Item = RusticResult<BlobReferences>>> {
+        commands::references::blob_references(self, sample_size)
+    }
+
+    /// Find the packs containing a blob and the snapshots and paths referencing it
+    ///
+    /// This reverse-maps the index and all trees, e.g. to find out where a damaged or suspicious blob
+    /// comes from.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the blob
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots, the trees or the index files could not be read.
+    pub fn find_blob_origin(&self, id: &BlobId) -> RusticResult<BlobOrigin> {
+        commands::references::find_blob_origin(self, id)
+    }
+
+    /// Compare two snapshots by walking both trees in parallel
+    ///
+    /// # Arguments
+    ///
+    /// * `snap1` - The first (old) snapshot
+    /// * `snap2` - The second (new) snapshot
+    ///
+    /// # Errors
+    ///
+    /// * If the root trees could not be read.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over all differences between the two snapshots in tree order.
+    /// Subtrees which are identical in both snapshots are skipped without being read.
+    pub fn diff(
+        &self,
+        snap1: &SnapshotFile,
+        snap2: &SnapshotFile,
+    ) -> RusticResult<impl Iterator<Item = RusticResult<DiffEntry>> + '_> {
+        TreeDiffStreamer::new(self.dbe().clone(), self.index(), snap1.tree, snap2.tree)
+    }
+
+    /// Compare a snapshot with a local directory, i.e. list what changed since the snapshot was taken
+    ///
+    /// # Arguments
+    ///
+    /// * `snap` - The snapshot to compare
+    /// * `local_path` - The local directory which corresponds to the root of the snapshot
+    /// * `change_detection` - How to detect changed files; [`ChangeDetection::Content`] reads and chunks
+    ///   all local files whose size matches
+    ///
+    /// # Errors
+    ///
+    /// * If the root tree of the snapshot could not be read.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over all differences between the snapshot and the local directory in tree order.
+    /// Entries only present locally are reported as [`DiffChange::Added`](crate::DiffChange::Added).
+    pub fn diff_with_local(
+        &self,
+        snap: &SnapshotFile,
+        local_path: impl AsRef<Path>,
+        change_detection: ChangeDetection,
+    ) -> RusticResult<impl Iterator<Item = RusticResult<DiffEntry>> + '_> {
+        let filter = BackupFilter::new(&Excludes::default(), &LocalSourceFilterOptions::default())?;
+        self.diff_with_local_filtered(snap, local_path, change_detection, &filter)
+    }
+
+    /// Compare a snapshot with a local directory, only considering local entries accepted by `filter`
+    ///
+    /// Use the filter of the backup, see [`BackupOptions::filter`], to compare the snapshot with
+    /// what a backup of the local directory would save.
+    ///
+    /// # Arguments
+    ///
+    /// * `snap` - The snapshot to compare
+    /// * `local_path` - The local directory which corresponds to the root of the snapshot
+    /// * `change_detection` - How to detect changed files, see [`Repository::diff_with_local`]
+    /// * `filter` - The filter to apply to the local directory
+    ///
+    /// # Errors
+    ///
+    /// * If the root tree of the snapshot could not be read.
+    ///
+    /// # Returns
+    ///
+    /// An iterator over all differences between the snapshot and the filtered local directory in tree order.
+    pub fn diff_with_local_filtered(
+        &self,
+        snap: &SnapshotFile,
+        local_path: impl AsRef<Path>,
+        change_detection: ChangeDetection,
+        filter: &BackupFilter,
+    ) -> RusticResult<impl Iterator<Item = RusticResult<DiffEntry>> + '_> {
+        let local_path = local_path.as_ref();
+        let node = self.node_from_path(snap.tree, Path::new(""))?;
+        let nodes = NodeStreamer::new_with_glob(
+            self.dbe().clone(),
+            self.index(),
+            &node,
+            &LsOptions::default(),
+        )?;
+        let local =
+            LocalSource::with_filter(LocalSourceSaveOptions::default(), filter, &[local_path]);
+        Ok(LocalDiffStreamer::new(
+            nodes,
+            local.entries(),
+            local_path.to_path_buf(),
+            self.config(),
+            change_detection,
+        ))
+    }
+
+    /// Get the snapshots grouped by `group_by` together with the information which of them to forget,
+    /// additionally respecting [`ForgetOptions::max_repo_size`]
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The forget options
+    /// * `group_by` - The criterion to group the snapshots by
+    /// * `filter` - The filter to apply to the snapshots
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots, the index or the trees could not be read.
+    /// * If keep options are not valid
+    ///
+    /// # Returns
+    ///
+    /// The [`ForgetGroups`] containing all matching snapshots
+    pub fn get_forget_snapshots_with_options(
+        &self,
+        opts: &ForgetOptions,
+        group_by: SnapshotGroupCriterion,
+        filter: impl FnMut(&SnapshotFile) -> bool,
+    ) -> RusticResult<ForgetGroups> {
+        commands::forget::get_forget_snapshots_with_options(self, opts, group_by, filter)
+    }
+
+    /// Reconstruct the missing summary of snapshots and replace the snapshots by the modified ones.
+    ///
+    /// This is useful for snapshots created by tools which don't write a summary. The file and directory
+    /// counts and sizes are computed by walking the snapshot trees. The `original` id of the snapshots is
+    /// preserved.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshots` - The snapshots to process; snapshots which already have a summary are skipped
+    /// * `dry_run` - Don't save the modified snapshots
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is append-only and `dry_run` is not set.
+    /// * If a tree could not be read.
+    /// * If the snapshots could not be saved or the old snapshots could not be removed.
+    ///
+    /// # Returns
+    ///
+    /// The modified snapshots
+    pub fn backfill_summaries(
+        &self,
+        snapshots: Vec<SnapshotFile>,
+        dry_run: bool,
+    ) -> RusticResult<Vec<SnapshotFile>> {
+        backfill_summaries(self, snapshots, dry_run)
+    }
+
     /// Restore a given [`RestorePlan`] to a local destination
     ///
     /// # Arguments
@@ -1595,14 +2976,19 @@ impl<S: IndexedTree> Repository<S> {
     ///
     /// # Errors
     ///
-    // TODO: Document errors
+    /// * If restoring file contents failed and [`RestoreOptions::on_error`] is `abort`.
+    /// * If restoring the metadata failed.
+    ///
+    /// # Returns
+    ///
+    /// The [`RestoreReport`] listing the files which could not be restored.
     pub fn restore(
         &self,
         restore_infos: RestorePlan,
         opts: &RestoreOptions,
         node_streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
         dest: &LocalDestination,
-    ) -> RusticResult<()> {
+    ) -> RusticResult<RestoreReport> {
         restore_repository(restore_infos, self, *opts, node_streamer, dest)
     }
 
@@ -1659,6 +3045,34 @@ impl<S: IndexedTree> Repository<S> {
     ) -> RusticResult<SnapshotFile> {
         commands::merge::merge_snapshots(self, snaps, cmp, snap)
     }
+
+    /// Merge the snapshots with the given ids.
+    ///
+    /// This method will create needed tree blobs within the repository; trees which already exist are not saved again.
+    /// Merge conflicts (identical filenames which do not match) will be resolved using the given [`MergeConflictPolicy`].
+    ///
+    /// # Arguments
+    ///
+    /// * `ids` - The ids of the snapshots to merge, in the order used by the conflict policy
+    /// * `policy` - How to resolve merge conflicts
+    /// * `snap` - The snapshot to save
+    ///
+    /// # Errors
+    ///
+    /// * If no snapshot ids are given.
+    /// * If the snapshots could not be read or the merged snapshot could not be saved.
+    ///
+    /// # Returns
+    ///
+    /// This method returns the modified and already saved [`SnapshotFile`].
+    pub fn merge_snapshots_from_ids<T: AsRef<str>>(
+        &self,
+        ids: &[T],
+        policy: MergeConflictPolicy,
+        snap: SnapshotFile,
+    ) -> RusticResult<SnapshotFile> {
+        commands::merge::merge_snapshots_from_ids(self, ids, policy, snap)
+    }
 }
 
 impl<S: IndexedIds> Repository<S> {
@@ -1685,6 +3099,7 @@ impl<S: IndexedIds> Repository<S> {
         source: &PathList,
         snap: SnapshotFile,
     ) -> RusticResult<SnapshotFile> {
+        let _lock = lock::ensure_lock(self, false)?;
         commands::backup::backup(self, opts, source, snap)
     }
 
@@ -1718,7 +3133,76 @@ impl<S: IndexedIds> Repository<S> {
         <R as ReadSource>::Open: Send,
         <R as ReadSource>::Iter: Send,
     {
-        commands::backup::archive(self, opts, src, snap, backup_paths)
+        let _lock = lock::ensure_lock(self, false)?;
+        commands::backup::archive(self, opts, src, snap, backup_paths, &[])
+    }
+
+    /// Run a backup of the data of a reader, e.g. the output of a database dump.
+    ///
+    /// The data is saved as a single file at `path` within the snapshot. Use
+    /// [`BackupOptions::stdin_size`] to give the expected size of the data for progress reporting.
+    ///
+    /// You have to give a preflled [`SnapshotFile`] which is modified and saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The options to use
+    /// * `reader` - The reader to backup
+    /// * `path` - The path of the file within the snapshot
+    /// * `snap` - The snapshot to modify and save
+    ///
+    /// # Errors
+    ///
+    /// * If the reader could not be read.
+    /// * If `host_lock` is set and another backup of this repository is running on this host.
+    ///
+    /// # Returns
+    ///
+    /// The saved snapshot.
+    pub fn backup_from_reader(
+        &self,
+        opts: &BackupOptions,
+        reader: impl Read + Send + 'static,
+        path: impl Into<PathBuf>,
+        snap: SnapshotFile,
+    ) -> RusticResult<SnapshotFile> {
+        let _lock = lock::ensure_lock(self, false)?;
+        commands::backup::backup_from_reader(self, opts, reader, path.into(), snap)
+    }
+
+    /// Run an incremental backup by applying changed paths to the tree of a parent snapshot.
+    ///
+    /// Only modified files are read and chunked and all trees which don't contain a change are reused,
+    /// so no scan of the backup source is needed. This allows to continuously backup changes reported
+    /// e.g. by a file system watcher.
+    ///
+    /// You have to give a preflled [`SnapshotFile`] which is modified and saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The options to use; exclude options and `as_path` are not used
+    /// * `changes` - The changed paths, given as paths of the backup source
+    /// * `parent` - The parent snapshot to apply the changes to
+    /// * `snap` - The snapshot to modify and save
+    ///
+    /// # Errors
+    ///
+    /// * If a changed path contains `.` or `..` components.
+    /// * If a tree of the parent snapshot could not be read.
+    /// * If `host_lock` is set and another backup of this repository is running on this host.
+    ///
+    /// # Returns
+    ///
+    /// The saved snapshot.
+    pub fn backup_incremental(
+        &self,
+        opts: &BackupOptions,
+        changes: impl IntoIterator<Item = ChangedPath>,
+        parent: &SnapshotFile,
+        snap: SnapshotFile,
+    ) -> RusticResult<SnapshotFile> {
+        let _lock = lock::ensure_lock(self, false)?;
+        commands::backup::backup_incremental(self, opts, changes, parent, snap)
     }
 }
 
@@ -1749,8 +3233,14 @@ impl<S: IndexedFull> Repository<S> {
             be: self.be,
             be_hot: self.be_hot,
             be_cold: self.be_cold,
+            profiler: self.profiler,
+            metrics: self.metrics,
+            bandwidth: self.bandwidth,
+            warm_up_strategy: self.warm_up_strategy,
             opts: self.opts,
             pb: self.pb,
+            cancel: self.cancel,
+            pause: self.pause,
             status: self.status.into_indexed_tree(),
         }
     }
@@ -1793,6 +3283,58 @@ impl<S: IndexedFull> Repository<S> {
         commands::dump::dump(self, node, w)
     }
 
+    /// Dump the given `path` within a [`SnapshotFile`] as archive using the given writer.
+    ///
+    /// The archive is streamed, so it can e.g. be sent to a client without restoring to disk first.
+    /// Paths within the archive are relative to the given `path`.
+    ///
+    /// # Arguments
+    ///
+    /// * `snap` - The snapshot to dump
+    /// * `path` - The path within the snapshot to dump
+    /// * `format` - The archive format
+    /// * `w` - The writer to use
+    ///
+    /// # Errors
+    ///
+    /// * If the path is not found within the snapshot.
+    /// * If a tree or a blob cannot be read.
+    /// * If writing to `w` fails.
+    /// * If the contents exceed the limits of the zip format (4 GiB, 65535 entries).
+    pub fn dump_archive(
+        &self,
+        snap: &SnapshotFile,
+        path: &str,
+        format: ArchiveFormat,
+        w: &mut impl Write,
+    ) -> RusticResult<()> {
+        let node = self.node_from_snapshot_and_path(snap, path)?;
+        commands::dump::archive::dump_archive(self, &node, format, w)
+    }
+
+    /// Restore the contents of a file [`Node`] into an arbitrary writer.
+    ///
+    /// This allows to stream the contents of a single file, e.g. to stdout or into another process,
+    /// without needing a [`LocalDestination`].
+    ///
+    /// # Arguments
+    ///
+    /// * `node` - The file node to restore
+    /// * `w` - The writer to use
+    ///
+    /// # Errors
+    ///
+    /// * If the node is not a file.
+    /// * If writing to or flushing the writer fails.
+    /// * If the written size doesn't match the size of the node.
+    ///
+    /// # Returns
+    ///
+    /// The writer after all contents have been written and flushed.
+    pub fn restore_to_writer<W: Write>(&self, node: &Node, w: W) -> RusticResult<W> {
+        commands::dump::restore_to_writer(self, node, w)
+    }
+
     /// Prepare the restore.
     ///
     /// If `dry_run` is set to false, it will also:
@@ -1824,6 +3366,33 @@ impl<S: IndexedFull> Repository<S> {
         collect_and_prepare(self, *opts, node_streamer, dest, dry_run)
     }
 
+    /// Verify that the given nodes can be restored, without writing anything.
+    ///
+    /// All pack contents a restore would need are read, decrypted and verified against their blob ids;
+    /// the read data is discarded. This allows a realistic disaster-recovery drill without needing space
+    /// for a restore destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `node_streamer` - The nodes to verify, e.g. from [`Repository::ls`]
+    /// * `on_error` - How to handle errors while reading or verifying contents
+    ///
+    /// # Errors
+    ///
+    /// * If a tree or index entry could not be read.
+    /// * If reading or verifying contents failed and `on_error` is [`RestoreErrorPolicy::Abort`].
+    ///
+    /// # Returns
+    ///
+    /// The [`VerifyRestoreReport`] with timing and integrity results.
+    pub fn verify_restore(
+        &self,
+        node_streamer: impl Iterator<Item = RusticResult<(PathBuf, Node)>>,
+        on_error: RestoreErrorPolicy,
+    ) -> RusticResult<VerifyRestoreReport> {
+        verify_restore(self, node_streamer, on_error)
+    }
+
     /// Copy the given `snapshots` to `repo_dest`.
     ///
     /// # Type Parameters
@@ -1851,7 +3420,102 @@ impl<S: IndexedFull> Repository<S> {
         repo_dest: &Repository<R>,
         snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
     ) -> RusticResult<()> {
-        commands::copy::copy(self, repo_dest, snapshots)
+        let _locks = (
+            lock::ensure_lock(self, false)?,
+            lock::ensure_lock(repo_dest, false)?,
+        );
+        commands::copy::copy(self, repo_dest, snapshots, &CopyOptions::default())
+    }
+
+    /// Copy the given `snapshots` to `repo_dest` using the given [`CopyOptions`].
+    ///
+    /// Files which are excluded by `opts` are not copied and file contents can be re-chunked;
+    /// in these cases the trees of the copied snapshots are rewritten in the destination repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo_dest` - The destination repository
+    /// * `snapshots` - The snapshots to copy
+    /// * `opts` - The options to use
+    ///
+    /// # Errors
+    ///
+    /// * If the exclude globs could not be parsed.
+    /// * If the chunker parameters of `repo_dest` are invalid.
+    /// * If blobs could not be read from this repository or written to `repo_dest`.
+    pub fn copy_with_options<'a, R: IndexedIds>(
+        &self,
+        repo_dest: &Repository<R>,
+        snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
+        opts: &CopyOptions,
+    ) -> RusticResult<()> {
+        let _locks = (
+            lock::ensure_lock(self, false)?,
+            lock::ensure_lock(repo_dest, false)?,
+        );
+        commands::copy::copy(self, repo_dest, snapshots, opts)
+    }
+
+    /// Copy the given `snapshots` to all of `repos_dest` in one pass.
+    ///
+    /// Each needed blob is read (and decrypted) only once and then saved into all destination
+    /// repositories which don't contain it yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `repos_dest` - The destination repositories
+    /// * `snapshots` - The snapshots to copy
+    ///
+    /// # Errors
+    ///
+    /// * If blobs could not be read from this repository or written to one of `repos_dest`.
+    ///
+    /// # Note
+    ///
+    /// Like [`Repository::copy`], this copies snapshots even if they already exist in a destination repository.
+    pub fn copy_to_many<'a, R: IndexedIds>(
+        &self,
+        repos_dest: &[&Repository<R>],
+        snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
+    ) -> RusticResult<()> {
+        let _lock = lock::ensure_lock(self, false)?;
+        let _dest_locks = repos_dest
+            .iter()
+            .map(|repo_dest| lock::ensure_lock(*repo_dest, false))
+            .collect::<RusticResult<Vec<_>>>()?;
+        commands::copy::copy_to_many(self, repos_dest, snapshots)
+    }
+
+    /// Copy the given `snapshots` to `repo_dest` such that an interrupted copy can be resumed.
+    ///
+    /// The snapshots are copied one by one; after each snapshot the destination index is written, the
+    /// snapshot is saved and `save_state` is called with the updated [`CopyState`]. Passing the last saved
+    /// state again resumes the copy: already copied snapshots are skipped and already transferred trees
+    /// are not read again.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo_dest` - The destination repository
+    /// * `snapshots` - The snapshots to copy
+    /// * `state` - The state of the copy, use [`CopyState::default`] for a new copy
+    /// * `save_state` - Called to persist the state after each copied snapshot
+    ///
+    /// # Errors
+    ///
+    /// * If blobs could not be read from this repository or written to `repo_dest`.
+    /// * If `save_state` fails.
+    pub fn copy_resumable<'a, R: IndexedIds>(
+        &self,
+        repo_dest: &Repository<R>,
+        snapshots: impl IntoIterator<Item = &'a SnapshotFile>,
+        state: &mut CopyState,
+        save_state: impl FnMut(&CopyState) -> RusticResult<()>,
+    ) -> RusticResult<()> {
+        let _locks = (
+            lock::ensure_lock(self, false)?,
+            lock::ensure_lock(repo_dest, false)?,
+        );
+        commands::copy::copy_resumable(self, repo_dest, snapshots, state, save_state)
     }
 
     /// Repair snapshots.
@@ -1871,12 +3535,17 @@ impl<S: IndexedFull> Repository<S> {
     /// # Errors
     ///
     // TODO: Document errors
+    ///
+    /// # Returns
+    ///
+    /// The [`RepairReport`] of the (would-be) changes
     pub fn repair_snapshots(
         &self,
         opts: &RepairSnapshotsOptions,
         snapshots: Vec<SnapshotFile>,
         dry_run: bool,
-    ) -> RusticResult<()> {
+    ) -> RusticResult<RepairReport> {
+        let _guard = lock::exclusive_operation(self, "repair snapshots")?;
         repair_snapshots(self, opts, snapshots, dry_run)
     }
 
@@ -0,0 +1,181 @@
+//! An async facade for [`Repository`]
+use std::sync::Arc;
+
+use crate::{
+    backend::async_backend::run_blocking,
+    commands::{
+        check::{CheckOptions, CheckResults},
+        maintain::{MaintenanceOptions, MaintenanceReport},
+    },
+    error::{ErrorKind, RusticError, RusticResult},
+    repofile::SnapshotFile,
+    repository::{
+        IndexedFullStatus, IndexedIdsStatus, Open, OpenStatus, Repository, credentials::Credentials,
+    },
+};
+
+/// An async facade for a [`Repository`]
+///
+/// All operations are run on the blocking thread pool of the tokio runtime, so that async applications
+/// can use the repository without blocking their executor. Operations not offered by this facade can be
+/// run using [`AsyncRepository::run`].
+///
+/// # Type Parameters
+///
+/// * `S` - The status of the repository
+#[derive(Debug)]
+pub struct AsyncRepository<S> {
+    /// The repository
+    repo: Arc<Repository<S>>,
+}
+
+impl<S> From<Repository<S>> for AsyncRepository<S> {
+    fn from(repo: Repository<S>) -> Self {
+        Self::new(repo)
+    }
+}
+
+impl<S> AsyncRepository<S> {
+    /// Create a new `AsyncRepository` from a [`Repository`]
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to use
+    pub fn new(repo: Repository<S>) -> Self {
+        Self {
+            repo: Arc::new(repo),
+        }
+    }
+
+    /// Get the blocking [`Repository`]
+    #[must_use]
+    pub fn blocking(&self) -> &Repository<S> {
+        &self.repo
+    }
+
+    /// Get the blocking [`Repository`] back
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is still used by an operation, e.g. of a cancelled future.
+    pub fn into_blocking(self) -> RusticResult<Repository<S>> {
+        Arc::try_unwrap(self.repo).map_err(|_| {
+            RusticError::new(
+                ErrorKind::Repository,
+                "The repository is still used by a running operation. Please wait until it finished.",
+            )
+        })
+    }
+}
+
+impl<S: Send + Sync + 'static> AsyncRepository<S> {
+    /// Run a blocking operation on the repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The operation to run
+    ///
+    /// # Errors
+    ///
+    /// * If the operation fails.
+    /// * If the operation panicked.
+    pub async fn run<T, F>(&self, f: F) -> RusticResult<T>
+    where
+        F: FnOnce(&Repository<S>) -> RusticResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let repo = self.repo.clone();
+        run_blocking(move || f(&repo)).await
+    }
+
+    /// Run a blocking operation changing the state of the repository.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The operation to run
+    ///
+    /// # Errors
+    ///
+    /// * If the repository is still used by another operation.
+    /// * If the operation fails.
+    /// * If the operation panicked.
+    pub async fn map<S2, F>(self, f: F) -> RusticResult<AsyncRepository<S2>>
+    where
+        F: FnOnce(Repository<S>) -> RusticResult<Repository<S2>> + Send + 'static,
+        S2: Send + 'static,
+    {
+        let repo = self.into_blocking()?;
+        let repo = run_blocking(move || f(repo)).await?;
+        Ok(AsyncRepository::new(repo))
+    }
+}
+
+impl AsyncRepository<()> {
+    /// Open the repository, see [`Repository::open`].
+    ///
+    /// # Arguments
+    ///
+    /// * `credentials` - The credentials to use
+    ///
+    /// # Errors
+    ///
+    /// * If the repository could not be opened.
+    pub async fn open(self, credentials: Credentials) -> RusticResult<AsyncRepository<OpenStatus>> {
+        self.map(move |repo| repo.open(&credentials)).await
+    }
+}
+
+impl<S: Open + Send + Sync + 'static> AsyncRepository<S> {
+    /// Read the index of the repository, see [`Repository::to_indexed`].
+    ///
+    /// # Errors
+    ///
+    /// * If the index could not be read.
+    pub async fn to_indexed(self) -> RusticResult<AsyncRepository<IndexedFullStatus>> {
+        self.map(Repository::to_indexed).await
+    }
+
+    /// Read the index of the repository without data, see [`Repository::to_indexed_ids`].
+    ///
+    /// # Errors
+    ///
+    /// * If the index could not be read.
+    pub async fn to_indexed_ids(self) -> RusticResult<AsyncRepository<IndexedIdsStatus>> {
+        self.map(Repository::to_indexed_ids).await
+    }
+
+    /// Get all snapshots of the repository, see [`Repository::get_all_snapshots`].
+    ///
+    /// # Errors
+    ///
+    /// * If the snapshots could not be read.
+    pub async fn get_all_snapshots(&self) -> RusticResult<Vec<SnapshotFile>> {
+        self.run(Repository::get_all_snapshots).await
+    }
+
+    /// Check the repository, see [`Repository::check`].
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The options to use
+    ///
+    /// # Errors
+    ///
+    /// * If the repository could not be checked.
+    pub async fn check(&self, opts: CheckOptions) -> RusticResult<CheckResults> {
+        self.run(move |repo| repo.check(opts)).await
+    }
+
+    /// Run the regular maintenance of the repository, see [`Repository::maintain`].
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The maintenance options
+    ///
+    /// # Errors
+    ///
+    /// * If one of the maintenance steps fails.
+    pub async fn maintain(&self, opts: MaintenanceOptions) -> RusticResult<MaintenanceReport> {
+        self.run(move |repo| repo.maintain(&opts)).await
+    }
+}
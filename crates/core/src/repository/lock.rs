@@ -0,0 +1,431 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use jiff::Timestamp;
+use log::{debug, warn};
+
+use crate::{
+    backend::{
+        FileType, ReadBackend, WriteBackend,
+        decrypt::{DecryptBackend, DecryptReadBackend, DecryptWriteBackend},
+    },
+    crypto::aespoly1305::Key,
+    error::{ErrorKind, RusticError, RusticResult},
+    repofile::{LockFile, LockId, configfile::RepositoryId},
+    repository::{Open, Repository},
+};
+
+/// The interval in which held locks are refreshed; this must be well below
+/// [`STALE_LOCK_TIMEOUT`](crate::repofile::STALE_LOCK_TIMEOUT).
+const LOCK_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The lock file of a [`RepositoryLock`], shared with its refresh thread
+#[derive(Debug)]
+struct LockState {
+    /// The backend the lock file is stored in
+    be: DecryptBackend<Key>,
+    /// The id of the lock file; `None` if the lock has already been released
+    id: Option<LockId>,
+    /// The content of the lock file
+    lock: LockFile,
+}
+
+impl LockState {
+    /// Refresh the lock by writing a new lock file and removing the old one.
+    ///
+    /// # Errors
+    ///
+    /// * If the new lock file could not be written or the old one could not be removed.
+    fn refresh(&mut self) -> RusticResult<()> {
+        self.lock.time = Timestamp::now();
+        let id = LockId::from(self.be.save_file(&self.lock)?);
+        if let Some(old) = self.id.replace(id) {
+            self.be.remove(FileType::Lock, &old, false)?;
+        }
+        debug!("refreshed lock {id}");
+        Ok(())
+    }
+
+    /// Release the lock by removing the lock file.
+    ///
+    /// # Errors
+    ///
+    /// * If the lock file could not be removed.
+    fn unlock(&mut self) -> RusticResult<()> {
+        if let Some(id) = self.id.take() {
+            self.be.remove(FileType::Lock, &id, false)?;
+            debug!("released lock {id}");
+        }
+        Ok(())
+    }
+}
+
+/// A lock on the repository, see [`Repository::lock_exclusive`] and [`Repository::lock_shared`].
+///
+/// The lock file is removed when this is dropped. Locks which are not refreshed within
+/// [`STALE_LOCK_TIMEOUT`](crate::repofile::STALE_LOCK_TIMEOUT) are considered stale by other
+/// processes, so the lock is refreshed in the background while it is held.
+#[derive(Debug)]
+pub struct RepositoryLock {
+    /// The lock file
+    state: Arc<Mutex<LockState>>,
+    /// Whether this is an exclusive lock
+    exclusive: bool,
+    /// Stops the refresh thread when dropped
+    stop: Option<Sender<()>>,
+    /// The refresh thread
+    refresher: Option<JoinHandle<()>>,
+}
+
+impl RepositoryLock {
+    /// Create a new `RepositoryLock` and start refreshing it in the background
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The lock file which has already been saved
+    fn new(state: LockState) -> Self {
+        let exclusive = state.lock.exclusive;
+        let state = Arc::new(Mutex::new(state));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let refresh_state = state.clone();
+        let refresher = thread::spawn(move || {
+            // the sender is only dropped (and never used) to stop refreshing
+            while stopped.recv_timeout(LOCK_REFRESH_INTERVAL) == Err(RecvTimeoutError::Timeout) {
+                if let Err(err) = refresh_state.lock().unwrap().refresh() {
+                    warn!("failed to refresh lock: {err}");
+                }
+            }
+        });
+        Self {
+            state,
+            exclusive,
+            stop: Some(stop),
+            refresher: Some(refresher),
+        }
+    }
+
+    /// Returns the id of the current lock file
+    #[must_use]
+    pub fn id(&self) -> Option<LockId> {
+        self.state.lock().unwrap().id
+    }
+
+    /// Returns `true` if this is an exclusive lock
+    #[must_use]
+    pub const fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    /// Refresh the lock by writing a new lock file and removing the old one.
+    ///
+    /// Held locks are refreshed in the background, so this is usually not needed.
+    ///
+    /// # Errors
+    ///
+    /// * If the new lock file could not be written or the old one could not be removed.
+    pub fn refresh(&mut self) -> RusticResult<()> {
+        self.state.lock().unwrap().refresh()
+    }
+
+    /// Stop refreshing the lock in the background
+    fn stop_refresh(&mut self) {
+        drop(self.stop.take());
+        if let Some(refresher) = self.refresher.take()
+            && refresher.join().is_err()
+        {
+            warn!("lock refresh thread panicked");
+        }
+    }
+
+    /// Release the lock by removing the lock file.
+    ///
+    /// # Errors
+    ///
+    /// * If the lock file could not be removed.
+    pub fn unlock(mut self) -> RusticResult<()> {
+        self.stop_refresh();
+        self.state.lock().unwrap().unlock()
+    }
+}
+
+impl Drop for RepositoryLock {
+    fn drop(&mut self) {
+        self.stop_refresh();
+        if let Err(err) = self.state.lock().unwrap().unlock() {
+            warn!("failed to remove lock: {err}");
+        }
+    }
+}
+
+/// List the lock files of the repository.
+///
+/// Lock files which have been removed while listing or which can't be read are skipped.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+///
+/// # Errors
+///
+/// * If the lock files could not be listed.
+pub(crate) fn list_locks<S: Open>(repo: &Repository<S>) -> RusticResult<Vec<(LockId, LockFile)>> {
+    let be = repo.dbe();
+    let locks = be
+        .list(FileType::Lock)?
+        .into_iter()
+        .map(LockId::from)
+        .filter_map(|id| match be.get_file::<LockFile>(&id) {
+            Ok(lock) => Some((id, lock)),
+            Err(err) => {
+                warn!("ignoring lock {id} which could not be read: {err}");
+                None
+            }
+        })
+        .collect();
+    Ok(locks)
+}
+
+/// Find a non-stale lock conflicting with the given lock
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `lock` - The lock to check
+/// * `own` - The id of the lock file of `lock`, if already saved
+///
+/// # Errors
+///
+/// * If the lock files could not be listed.
+fn find_conflicting_lock<S: Open>(
+    repo: &Repository<S>,
+    lock: &LockFile,
+    own: Option<LockId>,
+) -> RusticResult<Option<(LockId, LockFile)>> {
+    let now = Timestamp::now();
+    Ok(list_locks(repo)?.into_iter().find(|(id, other)| {
+        Some(*id) != own && lock.conflicts_with(other) && !other.is_stale(&lock.hostname, now)
+    }))
+}
+
+/// Create the error for a conflicting lock
+///
+/// # Arguments
+///
+/// * `id` - The id of the conflicting lock
+/// * `lock` - The conflicting lock
+fn conflict_error(id: LockId, lock: &LockFile) -> Box<RusticError> {
+    let kind = if lock.exclusive {
+        "an exclusive"
+    } else {
+        "a shared"
+    };
+    RusticError::new(
+        ErrorKind::Repository,
+        "The repository has {kind} lock `{id}` held by process {pid} of user `{username}` on host `{hostname}` since {time}. Please wait until this process has finished or remove the lock if it is no longer valid.",
+    )
+    .attach_context("kind", kind)
+    .attach_context("pid", lock.pid.to_string())
+    .attach_context("username", lock.username.clone())
+    .attach_context("hostname", lock.hostname.clone())
+    .attach_context("time", lock.time.to_string())
+    .attach_context("id", id.to_string())
+    .attach_error_code("R001")
+}
+
+/// Lock the repository.
+///
+/// An exclusive lock conflicts with all other locks, a shared lock only with exclusive locks. Stale
+/// locks are ignored. After saving the lock file, the locks are checked again to detect processes
+/// which are locking the repository at the same time.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to lock
+/// * `exclusive` - Whether to acquire an exclusive lock
+///
+/// # Errors
+///
+/// * If the repository is locked by another process.
+/// * If the lock files could not be listed, written or removed.
+///
+/// # Returns
+///
+/// The [`RepositoryLock`] which removes the lock file when dropped.
+pub(crate) fn lock<S: Open>(repo: &Repository<S>, exclusive: bool) -> RusticResult<RepositoryLock> {
+    let lock = LockFile::new(exclusive);
+    if let Some((id, other)) = find_conflicting_lock(repo, &lock, None)? {
+        return Err(conflict_error(id, &other));
+    }
+
+    let be = repo.dbe().clone();
+    let id = LockId::from(be.save_file(&lock)?);
+    let mut state = LockState {
+        be,
+        id: Some(id),
+        lock,
+    };
+
+    // another process may have locked the repository in the meantime
+    if let Some((other_id, other)) = find_conflicting_lock(repo, &state.lock, state.id)? {
+        if let Err(err) = state.unlock() {
+            warn!("failed to remove lock {id}: {err}");
+        }
+        return Err(conflict_error(other_id, &other));
+    }
+    debug!("acquired lock {id}");
+    Ok(RepositoryLock::new(state))
+}
+
+/// Make sure the repository is locked by this process.
+///
+/// If this process already holds a non-stale exclusive lock, e.g. acquired using
+/// [`Repository::lock_exclusive`], no new lock is acquired.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to lock
+/// * `exclusive` - Whether an exclusive lock is needed
+///
+/// # Errors
+///
+/// * If the repository is locked by another process.
+/// * If the lock files could not be listed, written or removed.
+///
+/// # Returns
+///
+/// The newly acquired [`RepositoryLock`], if any
+pub(crate) fn ensure_lock<S: Open>(
+    repo: &Repository<S>,
+    exclusive: bool,
+) -> RusticResult<Option<RepositoryLock>> {
+    let own = LockFile::new(exclusive);
+    let now = Timestamp::now();
+    if list_locks(repo)?.into_iter().any(|(_, other)| {
+        other.exclusive
+            && other.hostname == own.hostname
+            && other.pid == own.pid
+            && !other.is_stale(&own.hostname, now)
+    }) {
+        return Ok(None);
+    }
+    lock(repo, exclusive).map(Some)
+}
+
+/// Remove all stale locks from the repository.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+///
+/// # Errors
+///
+/// * If the lock files could not be listed or removed.
+///
+/// # Returns
+///
+/// The ids of the removed locks
+pub(crate) fn remove_stale_locks<S: Open>(repo: &Repository<S>) -> RusticResult<Vec<LockId>> {
+    let hostname = LockFile::new(false).hostname;
+    let now = Timestamp::now();
+    let stale: Vec<_> = list_locks(repo)?
+        .into_iter()
+        .filter(|(_, lock)| lock.is_stale(&hostname, now))
+        .map(|(id, _)| id)
+        .collect();
+    for id in &stale {
+        warn!("removing stale lock {id}");
+        repo.dbe().remove(FileType::Lock, id, false)?;
+    }
+    Ok(stale)
+}
+
+/// The exclusive operations running in this process, by repository
+static EXCLUSIVE_OPERATIONS: Mutex<BTreeMap<RepositoryId, &'static str>> =
+    Mutex::new(BTreeMap::new());
+
+/// A guard for an operation which must not run concurrently with other exclusive operations on the same
+/// repository, see [`Repository::exclusive_operation`].
+///
+/// The operation is registered for the current process and the repository is locked exclusively until
+/// this is dropped.
+#[derive(Debug)]
+pub struct ExclusiveOperation {
+    /// The id of the repository
+    repo_id: RepositoryId,
+    /// The name of the operation
+    operation: &'static str,
+    /// The exclusive lock acquired for the operation; `None` if this process already held one
+    lock: Option<RepositoryLock>,
+}
+
+impl ExclusiveOperation {
+    /// Returns the name of the operation
+    #[must_use]
+    pub const fn operation(&self) -> &'static str {
+        self.operation
+    }
+}
+
+impl Drop for ExclusiveOperation {
+    fn drop(&mut self) {
+        // release the lock before another operation of this process may start
+        drop(self.lock.take());
+        _ = EXCLUSIVE_OPERATIONS.lock().unwrap().remove(&self.repo_id);
+        debug!("finished exclusive operation {}", self.operation);
+    }
+}
+
+/// Start an exclusive operation on the repository.
+///
+/// Within this process, only one exclusive operation can run on a repository at a time; repositories are
+/// identified by their config id. Other processes are excluded by an exclusive lock which is held while the
+/// operation is running, so this also fails if another process holds a non-stale lock on the repository.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to use
+/// * `operation` - The name of the operation
+///
+/// # Errors
+///
+/// * If another exclusive operation is running on the repository or another process holds a lock on
+///   the repository. The error can be identified using [`RusticError::is_busy`].
+/// * If the lock files could not be listed, written or removed.
+pub(crate) fn exclusive_operation<S: Open>(
+    repo: &Repository<S>,
+    operation: &'static str,
+) -> RusticResult<ExclusiveOperation> {
+    let repo_id = repo.config().id;
+    {
+        let mut running = EXCLUSIVE_OPERATIONS.lock().unwrap();
+        if let Some(other) = running.get(&repo_id) {
+            return Err(RusticError::new(
+                ErrorKind::Repository,
+                "Cannot run `{operation}` as `{other}` is already running on this repository. Please wait until it has finished.",
+            )
+            .attach_context("operation", operation)
+            .attach_context("other", *other)
+            .attach_error_code("R001"));
+        }
+        _ = running.insert(repo_id, operation);
+    }
+    // dropping the guard unregisters the operation again if we fail below
+    let mut guard = ExclusiveOperation {
+        repo_id,
+        operation,
+        lock: None,
+    };
+
+    guard.lock =
+        ensure_lock(repo, true).map_err(|err| err.attach_context("operation", operation))?;
+
+    debug!("started exclusive operation {operation}");
+    Ok(guard)
+}
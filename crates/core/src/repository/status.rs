@@ -5,6 +5,7 @@ use crate::{
     backend::{cache::Cache, decrypt::DecryptBackend},
     crypto::aespoly1305::Key,
     index::GlobalIndex,
+    progress::Progress,
     repofile::{ConfigFile, KeyId},
 };
 
@@ -22,6 +23,17 @@ pub trait Open {
 pub trait IndexedTree: Open {
     /// Returns the used indexes
     fn index(&self) -> &GlobalIndex;
+
+    /// Re-read the index from the repository and replace the used index
+    ///
+    /// # Arguments
+    ///
+    /// * `p` - The progress to use
+    ///
+    /// # Errors
+    ///
+    /// * If the index could not be read.
+    fn reload_index(&mut self, p: &Progress) -> RusticResult<()>;
 }
 
 /// A repository which is indexed such that all tree blobs are contained in the index
@@ -105,6 +117,10 @@ impl IndexedTree for IndexedTreesStatus {
     fn index(&self) -> &GlobalIndex {
         &self.index
     }
+    fn reload_index(&mut self, p: &Progress) -> RusticResult<()> {
+        self.index = GlobalIndex::only_full_trees(&self.open.dbe, p)?.drop_data();
+        Ok(())
+    }
 }
 
 /// Indexed Tree Status: The repository is open and the index contains tree packs and the ids for data packs.
@@ -132,6 +148,10 @@ impl IndexedTree for IndexedIdsStatus {
     fn index(&self) -> &GlobalIndex {
         &self.index
     }
+    fn reload_index(&mut self, p: &Progress) -> RusticResult<()> {
+        self.index = GlobalIndex::only_full_trees(&self.open.dbe, p)?;
+        Ok(())
+    }
 }
 
 impl IndexedIds for IndexedIdsStatus {
@@ -182,6 +202,10 @@ impl IndexedTree for IndexedFullStatus {
     fn index(&self) -> &GlobalIndex {
         &self.index
     }
+    fn reload_index(&mut self, p: &Progress) -> RusticResult<()> {
+        self.index = GlobalIndex::new(&self.open.dbe, p)?;
+        Ok(())
+    }
 }
 
 impl IndexedIds for IndexedFullStatus {
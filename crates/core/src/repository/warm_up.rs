@@ -1,15 +1,24 @@
-use std::io;
-use std::process::Command;
-use std::thread::sleep;
+use std::{
+    fmt::Debug,
+    io,
+    process::{Command, Output},
+    thread::sleep,
+    time::Duration,
+};
 
 use backon::{BlockingRetryable, ExponentialBuilder};
 
-use itertools::Itertools;
+use derive_more::Display;
+use derive_setters::Setters;
 use log::{debug, error, warn};
-use rayon::ThreadPoolBuilder;
+use rayon::{
+    ThreadPool, ThreadPoolBuilder,
+    iter::{IntoParallelRefIterator, ParallelIterator},
+    slice::ParallelSlice,
+};
 
 use crate::{
-    CommandInput, Id, Progress,
+    CommandInput, Id, Progress, ProgressBars, ProgressType,
     backend::{FileType, ReadBackend},
     error::{ErrorKind, RusticError, RusticResult},
     repository::Repository,
@@ -26,6 +35,9 @@ pub(super) mod constants {
 
     /// Initial delay for exponential backoff for spawning commands.
     pub(crate) const INITIAL_DELAY: Duration = Duration::from_millis(10);
+
+    /// The default interval to poll the availability of warmed up files.
+    pub(super) const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
 }
 
 /// Configuration for retrying executing a command that the operating system reports is busy.
@@ -37,8 +49,449 @@ fn execute_cmd_retry() -> ExponentialBuilder {
         .with_max_times(constants::MAX_RETRIES)
 }
 
+/// A strategy to make files of a cold repository (e.g. archived in S3 Glacier) available for reading.
+///
+/// Before files are read, the repository calls [`WarmUp::warm_up`] to request them and then
+/// [`WarmUp::wait`]. If the strategy [supports status polling](WarmUp::supports_status), the
+/// repository afterwards polls [`WarmUp::is_available`] until all files are available.
+///
+/// Use [`Repository::with_warm_up`] to use a strategy for a repository. By default, the strategy is
+/// derived from the [`RepositoryOptions`](crate::RepositoryOptions).
+pub trait WarmUp: Debug + Send + Sync {
+    /// Request the given files to be made available.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend containing the files
+    /// * `tpe` - The filetype of the ids
+    /// * `ids` - The ids of the files
+    /// * `pb` - The progress bars to use
+    ///
+    /// # Errors
+    ///
+    /// * If the files could not be requested.
+    fn warm_up(
+        &self,
+        be: &dyn ReadBackend,
+        tpe: FileType,
+        ids: &[Id],
+        pb: &dyn ProgressBars,
+    ) -> RusticResult<()>;
+
+    /// Wait for the requested files, e.g. by running a command.
+    ///
+    /// The default implementation doesn't wait.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend containing the files
+    /// * `tpe` - The filetype of the ids
+    /// * `ids` - The ids of the files
+    /// * `pb` - The progress bars to use
+    ///
+    /// # Errors
+    ///
+    /// * If waiting failed.
+    fn wait(
+        &self,
+        _be: &dyn ReadBackend,
+        _tpe: FileType,
+        _ids: &[Id],
+        _pb: &dyn ProgressBars,
+    ) -> RusticResult<()> {
+        Ok(())
+    }
+
+    /// Returns whether the availability of files can be checked using [`WarmUp::is_available`].
+    fn supports_status(&self) -> bool {
+        false
+    }
+
+    /// Check whether the given file is available for reading.
+    ///
+    /// # Arguments
+    ///
+    /// * `be` - The backend containing the file
+    /// * `tpe` - The filetype of the id
+    /// * `id` - The id of the file
+    ///
+    /// # Errors
+    ///
+    /// * If the status could not be determined.
+    fn is_available(&self, _be: &dyn ReadBackend, _tpe: FileType, _id: &Id) -> RusticResult<bool> {
+        Ok(true)
+    }
+}
+
+/// A [`WarmUp`] which does nothing, i.e. all files are assumed to be available.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoWarmUp;
+
+impl WarmUp for NoWarmUp {
+    fn warm_up(
+        &self,
+        _be: &dyn ReadBackend,
+        _tpe: FileType,
+        _ids: &[Id],
+        _pb: &dyn ProgressBars,
+    ) -> RusticResult<()> {
+        Ok(())
+    }
+}
+
+/// A [`WarmUp`] using the warm-up of the backend, see [`ReadBackend::warm_up`].
+///
+/// Nothing is done if the backend doesn't [need a warm-up](ReadBackend::needs_warm_up).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BackendWarmUp;
+
+impl WarmUp for BackendWarmUp {
+    fn warm_up(
+        &self,
+        be: &dyn ReadBackend,
+        tpe: FileType,
+        ids: &[Id],
+        pb: &dyn ProgressBars,
+    ) -> RusticResult<()> {
+        if !be.needs_warm_up() {
+            return Ok(());
+        }
+
+        let p = pb.progress(ProgressType::Counter, &format!("warming up {tpe}(s)..."));
+        p.set_length(ids.len() as u64);
+        warm_up_pool(constants::MAX_READER_THREADS_NUM)?.install(|| {
+            ids.par_iter().for_each(|id| {
+                if let Err(err) = be.warm_up(tpe, id) {
+                    // FIXME: Use error handling
+                    error!("warm-up failed for id {id:?}. {}", err.display_log());
+                }
+                p.inc(1);
+            });
+        });
+        p.finish();
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Setters)]
+#[setters(into, strip_option)]
+#[non_exhaustive]
+/// A [`WarmUp`] running commands to warm up files and to wait for them.
+///
+/// The commands may contain the placeholders `%tpe`, `%id` and `%path` or `%ids` and `%paths`, see
+/// [`RepositoryOptions::warm_up_command`](crate::RepositoryOptions::warm_up_command).
+pub struct CommandWarmUp {
+    /// The command to warm up files. If not given, the warm-up of the backend is used, see [`BackendWarmUp`].
+    pub command: Option<CommandInput>,
+
+    /// The command to wait for the warmed up files.
+    pub wait_command: Option<CommandInput>,
+
+    /// The command to check whether a file is available; it is run once per file and must exit
+    /// successfully if the file is available. Only `%tpe`, `%id` and `%path` are supported.
+    pub status_command: Option<CommandInput>,
+
+    /// Number of ids to process per batch of commands [default: 1]
+    pub batch_size: Option<usize>,
+
+    /// Number of batches of commands to run concurrently [default: 1]
+    pub concurrency: Option<usize>,
+}
+
+impl WarmUp for CommandWarmUp {
+    fn warm_up(
+        &self,
+        be: &dyn ReadBackend,
+        tpe: FileType,
+        ids: &[Id],
+        pb: &dyn ProgressBars,
+    ) -> RusticResult<()> {
+        match &self.command {
+            Some(command) => warm_up_command(
+                tpe,
+                ids,
+                command,
+                &WarmUpType::WarmUp,
+                self.batch_size.unwrap_or(1),
+                self.concurrency.unwrap_or(1),
+                be,
+                pb,
+            ),
+            None => BackendWarmUp.warm_up(be, tpe, ids, pb),
+        }
+    }
+
+    fn wait(
+        &self,
+        be: &dyn ReadBackend,
+        tpe: FileType,
+        ids: &[Id],
+        pb: &dyn ProgressBars,
+    ) -> RusticResult<()> {
+        if let Some(command) = &self.wait_command {
+            warm_up_command(
+                tpe,
+                ids,
+                command,
+                &WarmUpType::Wait,
+                self.batch_size.unwrap_or(1),
+                self.concurrency.unwrap_or(1),
+                be,
+                pb,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn supports_status(&self) -> bool {
+        self.status_command.is_some()
+    }
+
+    fn is_available(&self, be: &dyn ReadBackend, tpe: FileType, id: &Id) -> RusticResult<bool> {
+        let Some(command) = &self.status_command else {
+            return Ok(true);
+        };
+        let args = singular_args(command, tpe, id, &be.warmup_path(tpe, id));
+        let status = (|| Command::new(command.command()).args(&args).status())
+            .retry(execute_cmd_retry())
+            .when(|err| err.kind() == io::ErrorKind::ExecutableFileBusy)
+            .call()
+            .map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::ExternalCommand,
+                    "Error in executing warm-up status command `{command}`.",
+                    err,
+                )
+                .attach_context("command", command.to_string())
+                .attach_context("id", id.to_string())
+            })?;
+        Ok(status.success())
+    }
+}
+
+/// The retrieval tier used to restore objects from S3 Glacier
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display)]
+#[non_exhaustive]
+pub enum GlacierTier {
+    /// Restore within minutes (not available for Deep Archive)
+    Expedited,
+    /// Restore within hours
+    #[default]
+    Standard,
+    /// Restore within hours to days, but at lowest cost
+    Bulk,
+}
+
+#[derive(Debug, Clone, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// A [`WarmUp`] restoring objects archived in S3 Glacier or S3 Glacier Deep Archive.
+///
+/// Restores are requested and their status is polled using the `aws` command line interface, which
+/// must be installed and configured. The object keys are given by [`ReadBackend::warmup_path`].
+pub struct S3GlacierWarmUp {
+    /// The bucket containing the repository
+    pub bucket: String,
+
+    /// Number of days the restored copies are kept available
+    pub days: u32,
+
+    /// The retrieval tier to use
+    pub tier: GlacierTier,
+
+    /// The `aws` command to use
+    pub aws_command: String,
+}
+
+impl S3GlacierWarmUp {
+    /// Creates a new `S3GlacierWarmUp` keeping the restored objects for one day.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The bucket containing the repository
+    pub fn new(bucket: impl Into<String>) -> Self {
+        Self {
+            bucket: bucket.into(),
+            days: 1,
+            tier: GlacierTier::default(),
+            aws_command: "aws".to_string(),
+        }
+    }
+
+    /// Run an `aws s3api` command for the given object
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The s3api operation
+    /// * `key` - The key of the object
+    /// * `args` - Additional arguments
+    ///
+    /// # Errors
+    ///
+    /// * If the command could not be executed.
+    fn s3api(&self, operation: &str, key: &str, args: &[&str]) -> RusticResult<Output> {
+        debug!("calling aws s3api {operation} for {key}...");
+        Command::new(&self.aws_command)
+            .args(["s3api", operation, "--bucket", &self.bucket, "--key", key])
+            .args(args)
+            .output()
+            .map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::ExternalCommand,
+                    "Error in executing `{command} s3api {operation}`.",
+                    err,
+                )
+                .attach_context("command", &self.aws_command)
+                .attach_context("operation", operation)
+                .attach_context("key", key)
+            })
+    }
+}
+
+impl WarmUp for S3GlacierWarmUp {
+    fn warm_up(
+        &self,
+        be: &dyn ReadBackend,
+        tpe: FileType,
+        ids: &[Id],
+        pb: &dyn ProgressBars,
+    ) -> RusticResult<()> {
+        let p = pb.progress(ProgressType::Counter, &format!("restoring {tpe}(s)..."));
+        p.set_length(ids.len() as u64);
+        let request = format!(
+            r#"{{"Days":{},"GlacierJobParameters":{{"Tier":"{}"}}}}"#,
+            self.days, self.tier
+        );
+        warm_up_pool(constants::MAX_READER_THREADS_NUM)?.install(|| {
+            ids.par_iter().try_for_each(|id| {
+                let key = be.warmup_path(tpe, id);
+                let output =
+                    self.s3api("restore-object", &key, &["--restore-request", &request])?;
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                // objects which are already being restored or which are not archived are fine
+                if !output.status.success()
+                    && !stderr.contains("RestoreAlreadyInProgress")
+                    && !stderr.contains("InvalidObjectState")
+                {
+                    return Err(RusticError::new(
+                        ErrorKind::ExternalCommand,
+                        "Restore request for `{key}` failed: {stderr}",
+                    )
+                    .attach_context("key", key)
+                    .attach_context("stderr", stderr.trim().to_string()));
+                }
+                p.inc(1);
+                Ok(())
+            })
+        })?;
+        p.finish();
+        Ok(())
+    }
+
+    fn supports_status(&self) -> bool {
+        true
+    }
+
+    fn is_available(&self, be: &dyn ReadBackend, tpe: FileType, id: &Id) -> RusticResult<bool> {
+        let key = be.warmup_path(tpe, id);
+        let output = self.s3api(
+            "head-object",
+            &key,
+            &["--query", "[StorageClass, Restore]", "--output", "text"],
+        )?;
+        if !output.status.success() {
+            return Err(RusticError::new(
+                ErrorKind::ExternalCommand,
+                "Could not get the status of `{key}`: {stderr}",
+            )
+            .attach_context("key", key)
+            .attach_context(
+                "stderr",
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        Ok(is_restored(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+/// Check if an object is readable given the output of `head-object` querying `[StorageClass, Restore]`
+fn is_restored(status: &str) -> bool {
+    let archived = status
+        .split_whitespace()
+        .next()
+        .is_some_and(|class| matches!(class, "GLACIER" | "DEEP_ARCHIVE"));
+    !archived || status.contains(r#"ongoing-request="false""#)
+}
+
+/// Create the thread pool used to warm up files in parallel
+///
+/// # Arguments
+///
+/// * `threads` - The number of threads to use
+///
+/// # Errors
+///
+/// * If the thread pool could not be created.
+fn warm_up_pool(threads: usize) -> RusticResult<ThreadPool> {
+    ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::Internal,
+                "Failed to create thread pool for warm-up. Please try again.",
+                err,
+            )
+        })
+}
+
+/// Poll the availability of the given files until all are available.
+///
+/// # Arguments
+///
+/// * `warm_up` - The warm-up strategy to check the status with
+/// * `be` - The backend containing the files
+/// * `tpe` - The filetype of the ids
+/// * `ids` - The ids of the files
+/// * `interval` - The time to wait between two polls
+/// * `p` - The progress to advance for each available file
+///
+/// # Errors
+///
+/// * If the status of a file could not be determined.
+fn wait_until_available(
+    warm_up: &dyn WarmUp,
+    be: &dyn ReadBackend,
+    tpe: FileType,
+    mut ids: Vec<Id>,
+    interval: Duration,
+    p: &Progress,
+) -> RusticResult<()> {
+    loop {
+        let mut pending = Vec::new();
+        for id in ids {
+            if warm_up.is_available(be, tpe, &id)? {
+                p.inc(1);
+            } else {
+                pending.push(id);
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+        debug!(
+            "{} {tpe}(s) not yet available, waiting {interval:?}",
+            pending.len()
+        );
+        sleep(interval);
+        ids = pending;
+    }
+}
+
 /// Warm up the repository and wait.
 ///
+/// If the warm-up strategy supports status polling, this returns only after all files are available.
+///
 /// # Arguments
 ///
 /// * `repo` - The repository to warm up.
@@ -49,34 +502,52 @@ fn execute_cmd_retry() -> ExponentialBuilder {
 ///
 /// * If the command could not be parsed.
 /// * If the thread pool could not be created.
+/// * If the status of a file could not be determined.
 pub(crate) fn warm_up_wait<S>(
     repo: &Repository<S>,
     tpe: FileType,
-    ids: impl ExactSizeIterator<Item = Id> + Clone,
+    ids: impl ExactSizeIterator<Item = Id>,
 ) -> RusticResult<()> {
-    if ids.len() > 0 {
-        warm_up(repo, tpe, ids.clone())?;
+    let ids: Vec<_> = ids.collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let strategy = repo.warm_up_strategy.as_ref();
+    let be = &*repo.be;
+
+    warm_up(repo, tpe, ids.iter().copied())?;
+
+    strategy.wait(be, tpe, &ids, repo.pb.as_ref())?;
+
+    if repo.opts.warm_up_wait_command.is_none()
+        && let Some(wait) = repo.opts.warm_up_wait
+    {
+        let p = repo.progress_spinner(&format!("waiting {wait}..."));
+        sleep(
+            wait.try_into()
+                // ignore conversation errors, but print out warning
+                .inspect_err(|err| warn!("cannot wait for warm-up: {err}"))
+                .unwrap_or_default(),
+        );
+        p.finish();
+    }
 
-        if let Some(warm_up_wait_cmd) = &repo.opts.warm_up_wait_command {
-            warm_up_command(
-                tpe,
-                ids,
-                warm_up_wait_cmd,
-                repo,
-                &WarmUpType::Wait,
-                repo.opts.warm_up_batch.unwrap_or(1),
-                &repo.be,
-            )?;
-        } else if let Some(wait) = repo.opts.warm_up_wait {
-            let p = repo.progress_spinner(&format!("waiting {wait}..."));
-            sleep(
-                wait.try_into()
-                    // ignore conversation errors, but print out warning
-                    .inspect_err(|err| warn!("cannot wait for warm-up: {err}"))
-                    .unwrap_or_default(),
-            );
-            p.finish();
-        }
+    if strategy.supports_status() {
+        let interval = repo
+            .opts
+            .warm_up_poll_interval
+            .map_or(Ok(constants::DEFAULT_POLL_INTERVAL), TryInto::try_into)
+            .map_err(|err| {
+                RusticError::with_source(
+                    ErrorKind::InvalidInput,
+                    "Invalid warm-up poll interval. Please use a positive duration.",
+                    err,
+                )
+            })?;
+        let p = repo.progress_counter(&format!("waiting for {tpe}(s) to be available..."));
+        p.set_length(ids.len() as u64);
+        wait_until_available(strategy, be, tpe, ids, interval, &p)?;
+        p.finish();
     }
     Ok(())
 }
@@ -98,20 +569,10 @@ pub(crate) fn warm_up<S>(
     tpe: FileType,
     ids: impl ExactSizeIterator<Item = Id>,
 ) -> RusticResult<()> {
-    if ids.len() > 0 {
-        if let Some(warm_up_cmd) = &repo.opts.warm_up_command {
-            warm_up_command(
-                tpe,
-                ids,
-                warm_up_cmd,
-                repo,
-                &WarmUpType::WarmUp,
-                repo.opts.warm_up_batch.unwrap_or(1),
-                &repo.be,
-            )?;
-        } else if repo.be.needs_warm_up() {
-            warm_up_repo(repo, tpe, ids)?;
-        }
+    let ids: Vec<_> = ids.collect();
+    if !ids.is_empty() {
+        repo.warm_up_strategy
+            .warm_up(&*repo.be, tpe, &ids, repo.pb.as_ref())?;
     }
     Ok(())
 }
@@ -129,47 +590,73 @@ enum WarmUpType {
 /// * `tpe` - The filetype of the ids.
 /// * `ids` - The ids to warm up.
 /// * `command` - The command to execute.
-/// * `pb` - The progress bar to use.
 /// * `ty` - The type of warm-up operation.
 /// * `batch_size` - The number of ids to process in each batch.
+/// * `concurrency` - The number of batches to process concurrently.
 /// * `backend` - The backend to get id paths from.
+/// * `pb` - The progress bars to use.
 ///
 /// # Errors
 ///
 /// * If the command could not be parsed.
-fn warm_up_command<S>(
+fn warm_up_command(
     tpe: FileType,
-    ids: impl ExactSizeIterator<Item = Id>,
+    ids: &[Id],
     command: &CommandInput,
-    repo: &Repository<S>,
     ty: &WarmUpType,
     batch_size: usize,
-    backend: &impl ReadBackend,
+    concurrency: usize,
+    backend: &dyn ReadBackend,
+    pb: &dyn ProgressBars,
 ) -> RusticResult<()> {
     let use_plural = command.uses_plural_placeholders()?;
 
-    let total = ids.len();
-
-    let p = repo.progress_counter(&match ty {
-        WarmUpType::WarmUp => format!("warming up {tpe}(s)..."),
-        WarmUpType::Wait => format!("waiting for {tpe}(s) to be ready..."),
-    });
-    p.set_length(total as u64);
-
-    let chunks = ids.chunks(batch_size);
-    for batch in &chunks {
-        let batch: Vec<_> = batch.collect();
-        if use_plural {
-            warm_up_batch_plural(tpe, &batch, command, ty, backend, &p)?;
-        } else {
-            warm_up_batch_singular(tpe, &batch, command, ty, backend, &p)?;
-        }
-    }
+    let p = pb.progress(
+        ProgressType::Counter,
+        &match ty {
+            WarmUpType::WarmUp => format!("warming up {tpe}(s)..."),
+            WarmUpType::Wait => format!("waiting for {tpe}(s) to be ready..."),
+        },
+    );
+    p.set_length(ids.len() as u64);
+
+    // run at most `concurrency` batches at the same time
+    warm_up_pool(concurrency.max(1))?.install(|| {
+        ids.par_chunks(batch_size).try_for_each(|batch| {
+            if use_plural {
+                warm_up_batch_plural(tpe, batch, command, ty, backend, &p)
+            } else {
+                warm_up_batch_singular(tpe, batch, command, ty, backend, &p)
+            }
+        })
+    })?;
 
     p.finish();
     Ok(())
 }
 
+/// Get the arguments of a command in singular mode with all placeholders replaced.
+///
+/// # Arguments
+///
+/// * `command` - The command
+/// * `tpe` - The filetype of the id.
+/// * `id` - The id.
+/// * `path` - The backend path of the id.
+fn singular_args(command: &CommandInput, tpe: FileType, id: &Id, path: &str) -> Vec<String> {
+    let file_type = tpe.to_string();
+    let id = id.to_hex().to_string();
+    command
+        .args()
+        .iter()
+        .map(|c| {
+            c.replace("%tpe", &file_type)
+                .replace("%id", &id)
+                .replace("%path", path)
+        })
+        .collect()
+}
+
 /// Warm up a batch of ids using singular mode (one command per id).
 ///
 /// # Arguments
@@ -177,7 +664,6 @@ fn warm_up_command<S>(
 /// * `tpe` - The filetype of the ids.
 /// * `batch` - The ids in this batch.
 /// * `command` - The command to execute.
-/// * `pb` - The progress bar to use.
 /// * `ty` - The type of warm-up operation.
 /// * `backend` - The backend to get id paths from.
 /// * `progress` - The progress bar to update.
@@ -190,26 +676,15 @@ fn warm_up_batch_singular(
     batch: &[Id],
     command: &CommandInput,
     ty: &WarmUpType,
-    backend: &impl ReadBackend,
+    backend: &dyn ReadBackend,
     progress: &Progress,
 ) -> RusticResult<()> {
-    let file_type = tpe.to_string();
     let children: Vec<_> = batch
         .iter()
         .map(|id| {
-            let path = backend.warmup_path(tpe, id);
+            let args = singular_args(command, tpe, id, &backend.warmup_path(tpe, id));
             let id = id.to_hex().to_string();
 
-            let args: Vec<_> = command
-                .args()
-                .iter()
-                .map(|c| {
-                    c.replace("%tpe", &file_type)
-                        .replace("%id", &id)
-                        .replace("%path", &path)
-                })
-                .collect();
-
             debug!("spawning {command:?} for id {id:?}...");
 
             let child = (|| Command::new(command.command()).args(&args).spawn())
@@ -286,7 +761,6 @@ fn warm_up_batch_singular(
 /// * `tpe` - The filetype of the ids.
 /// * `batch` - The ids in this batch.
 /// * `command` - The command to execute.
-/// * `pb` - The progress bar to use.
 /// * `ty` - The type of warm-up operation.
 /// * `backend` - The backend to get id paths from.
 /// * `progress` - The progress bar to update.
@@ -299,7 +773,7 @@ fn warm_up_batch_plural(
     batch: &[Id],
     command: &CommandInput,
     ty: &WarmUpType,
-    backend: &impl ReadBackend,
+    backend: &dyn ReadBackend,
     progress: &Progress,
 ) -> RusticResult<()> {
     let file_type = tpe.to_string();
@@ -313,11 +787,7 @@ fn warm_up_batch_plural(
         if use_ids && arg.contains("%ids") {
             args.extend(batch.iter().map(|id| id.to_hex().to_string()));
         } else if use_paths && arg.contains("%paths") {
-            args.extend(
-                batch
-                    .iter()
-                    .map(|id| backend.warmup_path(FileType::Pack, id)),
-            );
+            args.extend(batch.iter().map(|id| backend.warmup_path(tpe, id)));
         } else {
             args.push(arg.replace("%tpe", &file_type));
         }
@@ -360,50 +830,74 @@ fn warm_up_batch_plural(
     Ok(())
 }
 
-/// Warm up the repository.
-///
-/// # Arguments
-///
-/// * `repo` - The repository to warm up.
-/// * `tpe` - The filetype of the ids
-/// * `ids` - The ids to warm up.
-///
-/// # Errors
-///
-/// * If the thread pool could not be created.
-fn warm_up_repo<S>(
-    repo: &Repository<S>,
-    tpe: FileType,
-    ids: impl ExactSizeIterator<Item = Id>,
-) -> RusticResult<()> {
-    let progress_bar = repo.progress_counter("warming up {tpe}(s)...");
-    progress_bar.set_length(ids.len() as u64);
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
-    let pool = ThreadPoolBuilder::new()
-        .num_threads(constants::MAX_READER_THREADS_NUM)
-        .build()
-        .map_err(|err| {
-            RusticError::with_source(
-                ErrorKind::Internal,
-                "Failed to create thread pool for warm-up. Please try again.",
-                err,
-            )
-        })?;
-    let progress_bar_ref = &progress_bar;
-    let backend = &repo.be;
-    pool.in_place_scope(|scope| {
-        for id in ids {
-            scope.spawn(move |_| {
-                if let Err(err) = backend.warm_up(tpe, &id) {
-                    // FIXME: Use error handling
-                    error!("warm-up failed for id {id:?}. {}", err.display_log());
-                }
-                progress_bar_ref.inc(1);
-            });
+    use super::*;
+
+    use crate::{backend::MockBackend, progress::HiddenProgress};
+
+    /// A warm-up whose files become available after the given number of polls
+    #[derive(Debug)]
+    struct DelayedWarmUp {
+        polls: AtomicUsize,
+        available_after: usize,
+    }
+
+    impl WarmUp for DelayedWarmUp {
+        fn warm_up(
+            &self,
+            _be: &dyn ReadBackend,
+            _tpe: FileType,
+            _ids: &[Id],
+            _pb: &dyn ProgressBars,
+        ) -> RusticResult<()> {
+            Ok(())
         }
-    });
 
-    progress_bar_ref.finish();
+        fn supports_status(&self) -> bool {
+            true
+        }
 
-    Ok(())
+        fn is_available(
+            &self,
+            _be: &dyn ReadBackend,
+            _tpe: FileType,
+            _id: &Id,
+        ) -> RusticResult<bool> {
+            Ok(self.polls.fetch_add(1, Ordering::SeqCst) >= self.available_after)
+        }
+    }
+
+    #[test]
+    fn test_wait_until_available() {
+        let warm_up = DelayedWarmUp {
+            polls: AtomicUsize::new(0),
+            available_after: 3,
+        };
+        let ids = vec![Id::random(), Id::random()];
+        wait_until_available(
+            &warm_up,
+            &MockBackend::new(),
+            FileType::Pack,
+            ids,
+            Duration::from_millis(1),
+            &Progress::new(HiddenProgress),
+        )
+        .unwrap();
+        // both files are polled twice, the one still unavailable after the second poll a third time
+        assert_eq!(warm_up.polls.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_is_restored() {
+        assert!(is_restored("None\tNone"));
+        assert!(is_restored("GLACIER_IR\tNone"));
+        assert!(!is_restored("GLACIER\tNone"));
+        assert!(!is_restored(r#"DEEP_ARCHIVE	ongoing-request="true""#));
+        assert!(is_restored(
+            r#"GLACIER	ongoing-request="false", expiry-date="Fri, 23 Dec 2022 00:00:00 GMT""#
+        ));
+    }
 }
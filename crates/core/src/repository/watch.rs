@@ -0,0 +1,133 @@
+use std::{collections::BTreeSet, ops::ControlFlow, thread::sleep, time::Duration};
+
+use log::{debug, info};
+
+use crate::{
+    error::RusticResult,
+    repofile::{IndexId, SnapshotId},
+    repository::{IndexedTree, Open, Repository},
+};
+
+/// Changes of a repository detected by a [`RepositoryWatcher`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RepositoryChanges {
+    /// Whether index files have been added or removed; if so, the index has been re-read
+    pub index_changed: bool,
+    /// Snapshots which have been added to the repository
+    pub added_snapshots: Vec<SnapshotId>,
+    /// Snapshots which have been removed from the repository
+    pub removed_snapshots: Vec<SnapshotId>,
+}
+
+impl RepositoryChanges {
+    /// Returns `true` if no changes have been detected
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        !self.index_changed && self.added_snapshots.is_empty() && self.removed_snapshots.is_empty()
+    }
+}
+
+/// Watches a repository for added or removed index and snapshot files.
+///
+/// This is meant for long-running processes (e.g. serving the repository via VFS/WebDAV) which
+/// need to pick up new backups without being restarted.
+#[derive(Debug, Clone)]
+pub struct RepositoryWatcher {
+    /// The index files known to the watcher
+    index_ids: BTreeSet<IndexId>,
+    /// The snapshot files known to the watcher
+    snapshot_ids: BTreeSet<SnapshotId>,
+}
+
+impl RepositoryWatcher {
+    /// Create a new watcher using the current index and snapshot files of the repository
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to watch
+    ///
+    /// # Errors
+    ///
+    /// * If the index or snapshot files could not be listed.
+    pub fn new<S: Open>(repo: &Repository<S>) -> RusticResult<Self> {
+        Ok(Self {
+            index_ids: repo.list()?.collect(),
+            snapshot_ids: repo.list()?.collect(),
+        })
+    }
+
+    /// Re-list the index and snapshot files of the repository and re-read the index if it has changed
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to refresh
+    ///
+    /// # Errors
+    ///
+    /// * If the index or snapshot files could not be listed.
+    /// * If the index could not be read.
+    ///
+    /// # Returns
+    ///
+    /// The changes since the last refresh (or the creation of the watcher)
+    pub fn refresh<S: IndexedTree>(
+        &mut self,
+        repo: &mut Repository<S>,
+    ) -> RusticResult<RepositoryChanges> {
+        let index_ids: BTreeSet<IndexId> = repo.list()?.collect();
+        let snapshot_ids: BTreeSet<SnapshotId> = repo.list()?.collect();
+
+        let index_changed = index_ids != self.index_ids;
+        if index_changed {
+            info!("index files changed, re-reading index...");
+            let p = repo.progress_counter("");
+            repo.status.reload_index(&p)?;
+        }
+
+        let changes = RepositoryChanges {
+            index_changed,
+            added_snapshots: snapshot_ids
+                .difference(&self.snapshot_ids)
+                .copied()
+                .collect(),
+            removed_snapshots: self
+                .snapshot_ids
+                .difference(&snapshot_ids)
+                .copied()
+                .collect(),
+        };
+        debug!("repository changes: {changes:?}");
+
+        self.index_ids = index_ids;
+        self.snapshot_ids = snapshot_ids;
+        Ok(changes)
+    }
+
+    /// Periodically refresh the repository and call `hook` for every detected change.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to watch
+    /// * `interval` - The interval to wait between two refreshes
+    /// * `hook` - Called with the refreshed repository and the detected changes.
+    ///   Return [`ControlFlow::Break`] to stop watching.
+    ///
+    /// # Errors
+    ///
+    /// * If the repository could not be refreshed.
+    pub fn watch<S: IndexedTree>(
+        &mut self,
+        repo: &mut Repository<S>,
+        interval: Duration,
+        mut hook: impl FnMut(&Repository<S>, &RepositoryChanges) -> ControlFlow<()>,
+    ) -> RusticResult<()> {
+        loop {
+            sleep(interval);
+            let changes = self.refresh(repo)?;
+            if !changes.is_empty() && hook(repo, &changes).is_break() {
+                return Ok(());
+            }
+        }
+    }
+}
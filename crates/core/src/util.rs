@@ -0,0 +1,277 @@
+//! Utility types for parsing options, e.g. sizes and durations.
+//!
+//! These types are used within the options of rustic commands and can be used by frontends
+//! or config files to parse sizes and durations identically to rustic.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::Mutex,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use bytesize::ByteSize;
+use serde_with::{DeserializeFromStr, SerializeDisplay};
+
+use crate::error::{ErrorKind, RusticError};
+
+/// Enum to specify a size limit
+///
+/// It can be parsed from a size (e.g. '5b', '2 kB', '3M', '4TiB'), a percentage (e.g. '10%') or 'unlimited'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, SerializeDisplay, DeserializeFromStr)]
+#[non_exhaustive]
+pub enum LimitOption {
+    /// Size in bytes
+    Size(ByteSize),
+    /// Size in percentage of repository size
+    Percentage(u64),
+    /// No limit
+    Unlimited,
+}
+
+impl LimitOption {
+    /// Get the limit in bytes for the given total size.
+    ///
+    /// # Arguments
+    ///
+    /// * `total` - The total size percentages are relative to
+    ///
+    /// # Returns
+    ///
+    /// The limit in bytes or `None` if unlimited.
+    #[must_use]
+    pub fn to_size(self, total: u64) -> Option<u64> {
+        match self {
+            Self::Size(size) => Some(size.as_u64()),
+            Self::Percentage(p) => Some(p * total / 100),
+            Self::Unlimited => None,
+        }
+    }
+}
+
+impl FromStr for LimitOption {
+    type Err = Box<RusticError>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.chars().last().unwrap_or('0') {
+            '%' => Self::Percentage({
+                let mut copy = s.to_string();
+                _ = copy.pop();
+                let percentage = copy.trim().parse().map_err(|err| {
+                    RusticError::with_source(
+                        ErrorKind::InvalidInput,
+                        "Failed to parse percentage limit `{limit}`. Please use a number between 0 and 100 followed by `%`, e.g. `10%`.",
+                        err,
+                    )
+                    .attach_context("limit", s)
+                })?;
+                if percentage > 100 {
+                    return Err(RusticError::new(
+                        ErrorKind::InvalidInput,
+                        "Percentage limit `{limit}` is larger than 100%. Please use a number between 0 and 100 followed by `%`, e.g. `10%`.",
+                    )
+                    .attach_context("limit", s));
+                }
+                percentage
+            }),
+            'd' if s == "unlimited" => Self::Unlimited,
+            _ => {
+                let byte_size = ByteSize::from_str(s).map_err(|err| {
+                    RusticError::with_source(
+                        ErrorKind::InvalidInput,
+                        "Failed to parse size limit `{limit}`. Please use a size (e.g. `5b`, `2 kB`, `3M`, `4TiB`), a percentage (e.g. `10%`) or `unlimited`.",
+                        err,
+                    )
+                    .attach_context("limit", s)
+                })?;
+
+                Self::Size(byte_size)
+            }
+        })
+    }
+}
+
+impl fmt::Display for LimitOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Size(size) => write!(f, "{}", size.as_u64()),
+            Self::Percentage(p) => write!(f, "{p}%"),
+            Self::Unlimited => write!(f, "unlimited"),
+        }
+    }
+}
+
+/// Enum to specify a duration limit
+///
+/// It can be parsed from a human-readable duration (e.g. '90d', '10m', '1h 30m') or 'unlimited'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, SerializeDisplay, DeserializeFromStr)]
+#[non_exhaustive]
+pub enum DurationOption {
+    /// A fixed duration
+    Duration(Duration),
+    /// No limit
+    Unlimited,
+}
+
+impl DurationOption {
+    /// Get the duration or `None` if unlimited.
+    #[must_use]
+    pub const fn as_duration(self) -> Option<Duration> {
+        match self {
+            Self::Duration(duration) => Some(duration),
+            Self::Unlimited => None,
+        }
+    }
+}
+
+impl From<Duration> for DurationOption {
+    fn from(duration: Duration) -> Self {
+        Self::Duration(duration)
+    }
+}
+
+impl FromStr for DurationOption {
+    type Err = Box<RusticError>;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "unlimited" {
+            return Ok(Self::Unlimited);
+        }
+        let duration = humantime::parse_duration(s).map_err(|err| {
+            RusticError::with_source(
+                ErrorKind::InvalidInput,
+                "Failed to parse duration `{duration}`. Please use a duration (e.g. `90d`, `10m`, `1h 30m`) or `unlimited`.",
+                err,
+            )
+            .attach_context("duration", s)
+        })?;
+        Ok(Self::Duration(duration))
+    }
+}
+
+impl fmt::Display for DurationOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duration(duration) => write!(f, "{}", humantime::format_duration(*duration)),
+            Self::Unlimited => write!(f, "unlimited"),
+        }
+    }
+}
+
+/// A rate limiter shared between threads to limit the throughput to a number of bytes per second.
+///
+/// Each call of [`RateLimiter::acquire`] reserves the time needed to process the given bytes at the
+/// configured rate and blocks until this time slot has started.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// The maximum number of bytes per second
+    rate: u64,
+    /// The time at which the next time slot starts
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Creates a new `RateLimiter`, if a (non-zero) rate is given.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The maximum number of bytes per second
+    pub(crate) fn new(rate: Option<ByteSize>) -> Option<Self> {
+        rate.map(|rate| rate.as_u64())
+            .filter(|rate| *rate > 0)
+            .map(|rate| Self {
+                rate,
+                next: Mutex::new(Instant::now()),
+            })
+    }
+
+    /// The time needed to process the given bytes
+    fn duration(&self, bytes: u64) -> Duration {
+        let nanos = u128::from(bytes) * 1_000_000_000 / u128::from(self.rate);
+        Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    /// Reserve the time to process the given bytes and wait until it may be processed.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The number of bytes to process
+    pub(crate) fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let start = (*next).max(now);
+            *next = start + self.duration(bytes);
+            start - now
+        };
+        if !wait.is_zero() {
+            sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("10%", LimitOption::Percentage(10))]
+    #[case("unlimited", LimitOption::Unlimited)]
+    #[case("2 kB", LimitOption::Size(ByteSize::kb(2)))]
+    #[case("4TiB", LimitOption::Size(ByteSize::tib(4)))]
+    fn limit_option_roundtrip(#[case] input: &str, #[case] expected: LimitOption) {
+        let limit: LimitOption = input.parse().unwrap();
+        assert_eq!(limit, expected);
+        assert_eq!(limit.to_string().parse::<LimitOption>().unwrap(), limit);
+    }
+
+    #[rstest]
+    #[case("101%")]
+    #[case("x%")]
+    #[case("limited")]
+    fn limit_option_invalid(#[case] input: &str) {
+        assert!(input.parse::<LimitOption>().is_err());
+    }
+
+    #[rstest]
+    #[case("90d", DurationOption::Duration(Duration::from_secs(90 * 24 * 60 * 60)))]
+    #[case("1h 30m", DurationOption::Duration(Duration::from_secs(90 * 60)))]
+    #[case("unlimited", DurationOption::Unlimited)]
+    fn duration_option_roundtrip(#[case] input: &str, #[case] expected: DurationOption) {
+        let duration: DurationOption = input.parse().unwrap();
+        assert_eq!(duration, expected);
+        assert_eq!(
+            duration.to_string().parse::<DurationOption>().unwrap(),
+            duration
+        );
+    }
+
+    #[test]
+    fn duration_option_serde() {
+        let duration = DurationOption::Duration(Duration::from_secs(600));
+        let json = serde_json::to_string(&duration).unwrap();
+        assert_eq!(json, "\"10m\"");
+        assert_eq!(
+            serde_json::from_str::<DurationOption>(&json).unwrap(),
+            duration
+        );
+        assert!(serde_json::from_str::<DurationOption>("\"ten minutes\"").is_err());
+    }
+
+    #[test]
+    fn rate_limiter() {
+        assert!(RateLimiter::new(None).is_none());
+        assert!(RateLimiter::new(Some(ByteSize::b(0))).is_none());
+
+        let limiter = RateLimiter::new(Some(ByteSize::kb(100))).unwrap();
+        assert_eq!(limiter.duration(50_000), Duration::from_millis(500));
+
+        let start = Instant::now();
+        // the first call is not delayed, the following ones wait for the reserved time
+        for _ in 0..3 {
+            limiter.acquire(10_000);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
@@ -1,9 +1,13 @@
 mod format;
+mod prefetch;
+#[cfg(feature = "webdav")]
+mod webdavfs;
 
 use std::{
     collections::BTreeMap,
     ffi::{OsStr, OsString},
     path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 use bytes::{Bytes, BytesMut};
@@ -16,9 +20,16 @@ use crate::{
     index::ReadIndex,
     repofile::{BlobType, Metadata, Node, NodeType, SnapshotFile},
     repository::{IndexedFull, Repository},
-    vfs::format::FormattedSnapshot,
+    vfs::{
+        format::FormattedSnapshot,
+        prefetch::{PrefetchPlanner, ReadAhead},
+    },
 };
 
+pub use crate::vfs::prefetch::PrefetchOptions;
+#[cfg(feature = "webdav")]
+pub use crate::vfs::webdavfs::WebDavFs;
+
 /// [`VfsErrorKind`] describes the errors that can be returned from the Virtual File System
 #[derive(thiserror::Error, Debug, displaydoc::Display)]
 pub enum VfsErrorKind {
@@ -449,6 +460,42 @@ impl Vfs {
         };
         Ok(result)
     }
+
+    /// Open the file at the specified path for reading.
+    ///
+    /// Together with [`Vfs::node_from_path`] and [`Vfs::dir_entries_from_path`], this allows to implement
+    /// a filesystem frontend (e.g. FUSE) as a thin adapter: Use [`OpenFile::read_at`] to read the contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to open the file from
+    /// * `path` - The path of the file
+    ///
+    /// # Errors
+    ///
+    /// * If the component name doesn't exist
+    /// * If the path is not a regular file
+    /// * If the index for the needed data blobs cannot be read
+    ///
+    /// # Returns
+    ///
+    /// The [`OpenFile`] at the specified path
+    pub fn open_file<S: IndexedFull>(
+        &self,
+        repo: &Repository<S>,
+        path: &Path,
+    ) -> RusticResult<OpenFile> {
+        let node = self.node_from_path(repo, path)?;
+        if !node.is_file() {
+            return Err(RusticError::new(
+                ErrorKind::Vfs,
+                "Path `{path}` is not a regular file and cannot be opened.",
+            )
+            .attach_context("path", path.display().to_string())
+            .attach_context("node_type", node.node_type.to_string()));
+        }
+        OpenFile::from_node(repo, &node)
+    }
 }
 
 /// `OpenFile` stores all information needed to access the contents of a file node
@@ -457,6 +504,19 @@ pub struct OpenFile {
     // The list of blobs
     content: Vec<DataId>,
     startpoints: ContentStartpoints,
+    // The prefetch state, if prefetching is enabled
+    prefetch: Option<Prefetch>,
+}
+
+/// The state needed to prefetch blobs of an [`OpenFile`]
+#[derive(Debug)]
+struct Prefetch {
+    /// The prefetch options
+    opts: PrefetchOptions,
+    /// The planner deciding which blobs to prefetch
+    planner: Mutex<PrefetchPlanner>,
+    /// The cache the prefetched blobs are read into
+    cache: Arc<ReadAhead>,
 }
 
 impl OpenFile {
@@ -493,9 +553,31 @@ impl OpenFile {
         Ok(Self {
             content,
             startpoints,
+            prefetch: None,
         })
     }
 
+    /// Enable prefetching of blobs when this `OpenFile` is read sequentially.
+    ///
+    /// Once sequential reads are detected, the blobs following the read position are fetched from the
+    /// backend in the background into a read-ahead cache holding at most `max_blobs` blobs beyond the
+    /// current one, so that subsequent reads don't need to wait for the backend. This greatly improves the sequential read throughput of large
+    /// files on high-latency backends.
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The prefetch options to use
+    #[must_use]
+    pub fn with_prefetch(mut self, opts: PrefetchOptions) -> Self {
+        self.prefetch = Some(Prefetch {
+            opts,
+            planner: Mutex::new(PrefetchPlanner::default()),
+            // the blob currently being read plus the read-ahead window
+            cache: Arc::new(ReadAhead::new(opts.max_blobs + 1)),
+        });
+        self
+    }
+
     /// Read the `OpenFile` at the given `offset` from the `repo`.
     ///
     /// # Arguments
@@ -514,6 +596,60 @@ impl OpenFile {
     /// If offset is behind the end of the file, an empty `Bytes` is returned.
     /// If length is too large, the result up to the end of the file is returned.
     pub fn read_at<S: IndexedFull>(
+        &self,
+        repo: &Repository<S>,
+        offset: usize,
+        length: usize,
+    ) -> RusticResult<Bytes> {
+        if let Some(prefetch) = &self.prefetch {
+            self.read_ahead(repo, prefetch, offset, length);
+        }
+        self.read_blobs(repo, offset, length)
+    }
+
+    /// Record a read at the given `offset` and `length` and start reading ahead the planned blobs
+    ///
+    /// Blobs before the ones needed for this read are evicted from the read-ahead cache.
+    fn read_ahead<S: IndexedFull>(
+        &self,
+        repo: &Repository<S>,
+        prefetch: &Prefetch,
+        offset: usize,
+        length: usize,
+    ) {
+        if self.content.is_empty() || length == 0 {
+            return;
+        }
+        let (first_blob, _) = self.startpoints.compute_start(offset);
+        let (last_blob, _) = self
+            .startpoints
+            .compute_start(offset.saturating_add(length - 1));
+        let last_blob = last_blob.min(self.content.len() - 1);
+
+        let plan = match prefetch.planner.lock() {
+            Ok(mut planner) => planner.plan(
+                &prefetch.opts,
+                offset,
+                length,
+                last_blob,
+                self.content.len(),
+            ),
+            Err(_) => return,
+        };
+
+        let window_end = (last_blob + 1).saturating_add(prefetch.opts.max_blobs);
+        prefetch.cache.retain(&(first_blob..window_end));
+
+        for i in plan {
+            let id = self.content[i];
+            if let Some(ie) = repo.index().get_data(&id) {
+                prefetch.cache.schedule(i, id, ie, repo.dbe().clone());
+            }
+        }
+    }
+
+    /// Read the blobs needed for the given `offset` and `length` using the read-ahead and blob caches
+    fn read_blobs<S: IndexedFull>(
         &self,
         repo: &Repository<S>,
         offset: usize,
@@ -525,7 +661,14 @@ impl OpenFile {
 
         // The case of empty node.content is also correctly handled here
         while length > 0 && i < self.content.len() {
-            let data = repo.get_blob_cached(&BlobId::from(self.content[i]), BlobType::Data)?;
+            let data = match self
+                .prefetch
+                .as_ref()
+                .and_then(|prefetch| prefetch.cache.get(i))
+            {
+                Some(data) => data,
+                None => repo.get_blob_cached(&BlobId::from(self.content[i]), BlobType::Data)?,
+            };
 
             if offset > data.len() {
                 // we cannot read behind the blob. This only happens if offset is too large to fit in the last blob
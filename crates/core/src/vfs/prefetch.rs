@@ -0,0 +1,264 @@
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+use bytes::Bytes;
+use derive_setters::Setters;
+use log::debug;
+
+use crate::{
+    backend::decrypt::DecryptBackend, blob::DataId, crypto::aespoly1305::Key, index::IndexEntry,
+};
+
+#[cfg_attr(feature = "clap", derive(clap::Parser))]
+#[derive(Debug, Clone, Copy, Setters)]
+#[setters(into)]
+#[non_exhaustive]
+/// Options for prefetching blobs when reading an [`OpenFile`](super::OpenFile) sequentially
+pub struct PrefetchOptions {
+    /// Maximum number of blobs to fetch ahead of the current read position (0: disable prefetching)
+    #[cfg_attr(
+        feature = "clap",
+        clap(long = "prefetch-blobs", value_name = "N", default_value = "8")
+    )]
+    pub max_blobs: usize,
+
+    /// Number of consecutive sequential reads needed before prefetching starts
+    #[cfg_attr(
+        feature = "clap",
+        clap(long = "prefetch-after", value_name = "N", default_value = "2")
+    )]
+    pub min_sequential_reads: usize,
+}
+
+impl Default for PrefetchOptions {
+    fn default() -> Self {
+        Self {
+            max_blobs: 8,
+            min_sequential_reads: 2,
+        }
+    }
+}
+
+/// Plans which blobs of an open file to prefetch based on the recent reads.
+///
+/// A read is sequential if it starts where the previous read ended. Once enough sequential reads
+/// have been seen, the blobs following the last read blob are planned, keeping a window of at most
+/// `max_blobs` blobs ahead of the read position. Blobs are only planned once as long as the reads stay
+/// sequential; a random access resets the planner.
+#[derive(Debug, Default)]
+pub(crate) struct PrefetchPlanner {
+    /// The offset where the next sequential read would start
+    next_offset: Option<usize>,
+    /// The number of consecutive sequential reads (including the first read of the sequence)
+    sequential_reads: usize,
+    /// Blobs before this index have already been planned
+    planned_until: usize,
+}
+
+impl PrefetchPlanner {
+    /// Record a read and return the indices of the blobs to prefetch
+    ///
+    /// # Arguments
+    ///
+    /// * `opts` - The prefetch options to use
+    /// * `offset` - The offset of the read
+    /// * `length` - The length of the read
+    /// * `last_blob` - The index of the last blob needed for the read
+    /// * `blob_count` - The total number of blobs of the file
+    ///
+    /// # Returns
+    ///
+    /// The range of blob indices to prefetch; this is empty if nothing should be prefetched.
+    pub(crate) fn plan(
+        &mut self,
+        opts: &PrefetchOptions,
+        offset: usize,
+        length: usize,
+        last_blob: usize,
+        blob_count: usize,
+    ) -> Range<usize> {
+        if self.next_offset == Some(offset) {
+            self.sequential_reads += 1;
+        } else {
+            self.sequential_reads = 1;
+            self.planned_until = 0;
+        }
+        self.next_offset = Some(offset.saturating_add(length));
+
+        if opts.max_blobs == 0 || self.sequential_reads < opts.min_sequential_reads {
+            return 0..0;
+        }
+
+        let start = (last_blob + 1).max(self.planned_until);
+        let end = (last_blob + 1)
+            .saturating_add(opts.max_blobs)
+            .min(blob_count);
+        if start >= end {
+            return 0..0;
+        }
+        self.planned_until = end;
+        start..end
+    }
+}
+
+/// The state of a blob in the [`ReadAhead`] cache
+#[derive(Debug)]
+enum ReadAheadBlob {
+    /// The blob is being read in the background
+    Pending,
+    /// The blob has been read
+    Ready(Bytes),
+    /// Reading the blob failed; the reader falls back to reading it directly
+    Failed,
+}
+
+/// A bounded cache of blobs which are read ahead in the background.
+///
+/// Blobs are identified by their index within the content of the open file. Reads consult the cache
+/// first and wait for blobs which are still being fetched instead of fetching them a second time.
+#[derive(Debug)]
+pub(crate) struct ReadAhead {
+    /// The maximum number of blobs held in the cache
+    capacity: usize,
+    /// The cached blobs by their index
+    blobs: Mutex<BTreeMap<usize, ReadAheadBlob>>,
+    /// Notified whenever a pending blob has been read
+    ready: Condvar,
+}
+
+impl ReadAhead {
+    /// Create a new `ReadAhead` cache holding at most `capacity` blobs
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blobs: Mutex::new(BTreeMap::new()),
+            ready: Condvar::new(),
+        }
+    }
+
+    /// Start reading the blob with the given index in the background
+    ///
+    /// Nothing is done if the blob is already cached or if the cache is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the blob within the file content
+    /// * `id` - The id of the blob
+    /// * `ie` - The index entry of the blob
+    /// * `be` - The backend to read the blob from
+    pub(crate) fn schedule(
+        self: &Arc<Self>,
+        index: usize,
+        id: DataId,
+        ie: IndexEntry,
+        be: DecryptBackend<Key>,
+    ) {
+        {
+            let Ok(mut blobs) = self.blobs.lock() else {
+                return;
+            };
+            if blobs.contains_key(&index) || blobs.len() >= self.capacity {
+                return;
+            }
+            _ = blobs.insert(index, ReadAheadBlob::Pending);
+        }
+
+        let cache = self.clone();
+        _ = thread::spawn(move || {
+            let blob = match ie.read_data(&be) {
+                Ok(data) => ReadAheadBlob::Ready(data),
+                Err(err) => {
+                    debug!("reading ahead blob {id} failed: {}", err.display_log());
+                    ReadAheadBlob::Failed
+                }
+            };
+            if let Ok(mut blobs) = cache.blobs.lock() {
+                // the blob may have been evicted in the meantime
+                if let Some(entry) = blobs.get_mut(&index) {
+                    *entry = blob;
+                }
+            }
+            cache.ready.notify_all();
+        });
+    }
+
+    /// Get the blob with the given index, waiting for it if it is still being read
+    ///
+    /// # Returns
+    ///
+    /// The blob data or `None` if the blob is not cached or reading it failed.
+    pub(crate) fn get(&self, index: usize) -> Option<Bytes> {
+        let blobs = self.blobs.lock().ok()?;
+        let blobs = self
+            .ready
+            .wait_while(blobs, |blobs| {
+                matches!(blobs.get(&index), Some(ReadAheadBlob::Pending))
+            })
+            .ok()?;
+        match blobs.get(&index) {
+            Some(ReadAheadBlob::Ready(data)) => Some(data.clone()),
+            _ => None,
+        }
+    }
+
+    /// Remove all blobs outside of the given window
+    ///
+    /// Pending blobs are dropped once they have been read.
+    pub(crate) fn retain(&self, window: &Range<usize>) {
+        if let Ok(mut blobs) = self.blobs.lock() {
+            blobs.retain(|index, _| window.contains(index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_sequential_reads() {
+        let opts = PrefetchOptions::default().max_blobs(3_usize);
+        let mut planner = PrefetchPlanner::default();
+
+        // first read: not yet sequential
+        assert_eq!(planner.plan(&opts, 0, 10, 0, 10), 0..0);
+        // second sequential read: plan a full window
+        assert_eq!(planner.plan(&opts, 10, 10, 1, 10), 2..5);
+        // still within the planned window: only extend it
+        assert_eq!(planner.plan(&opts, 20, 10, 2, 10), 5..6);
+        // same blob again: nothing new to plan
+        assert_eq!(planner.plan(&opts, 30, 5, 2, 10), 0..0);
+        // window is bounded by the number of blobs
+        assert_eq!(planner.plan(&opts, 35, 50, 8, 10), 9..10);
+        assert_eq!(planner.plan(&opts, 85, 15, 9, 10), 0..0);
+    }
+
+    #[test]
+    fn test_plan_random_access_resets() {
+        let opts = PrefetchOptions::default().max_blobs(2_usize);
+        let mut planner = PrefetchPlanner::default();
+
+        assert_eq!(planner.plan(&opts, 0, 10, 0, 10), 0..0);
+        assert_eq!(planner.plan(&opts, 10, 10, 1, 10), 2..4);
+        // random access: no prefetching
+        assert_eq!(planner.plan(&opts, 70, 10, 7, 10), 0..0);
+        // sequential again: plan from the new position
+        assert_eq!(planner.plan(&opts, 80, 10, 8, 10), 9..10);
+        // jumping back: blobs are planned again
+        assert_eq!(planner.plan(&opts, 0, 10, 0, 10), 0..0);
+        assert_eq!(planner.plan(&opts, 10, 10, 1, 10), 2..4);
+    }
+
+    #[test]
+    fn test_plan_disabled() {
+        let opts = PrefetchOptions::default().max_blobs(0_usize);
+        let mut planner = PrefetchPlanner::default();
+        for i in 0..5 {
+            assert_eq!(planner.plan(&opts, i * 10, 10, i, 10), 0..0);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Serve a [`Vfs`] via WebDAV
+//!
+//! [`WebDavFs`] implements the [`DavFileSystem`] trait of the [`dav_server`] crate, so serving snapshots only
+//! needs to create a [`DavHandler`](dav_server::DavHandler) using it and to connect the handler to a HTTP server.
+use std::{
+    fmt::{Debug, Formatter},
+    io::SeekFrom,
+    path::PathBuf,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use bytes::{Buf, Bytes};
+use dav_server::{
+    davpath::DavPath,
+    fs::{
+        DavDirEntry, DavFile, DavFileSystem, DavMetaData, FsError, FsFuture, FsResult, FsStream,
+        OpenOptions, ReadDirMeta,
+    },
+};
+use futures::{FutureExt, stream};
+
+use crate::{
+    backend::async_backend::run_blocking,
+    repofile::Node,
+    repository::{IndexedFull, Repository},
+    vfs::{FilePolicy, OpenFile, Vfs},
+};
+
+/// The state shared by the [`WebDavFs`] and its open files
+struct DavFsInner<S> {
+    /// The repository to read from
+    repo: Repository<S>,
+    /// The virtual file system to serve
+    vfs: Vfs,
+    /// How to handle access to files
+    file_policy: FilePolicy,
+}
+
+/// A read-only [`DavFileSystem`] serving a [`Vfs`]
+///
+/// All repository accesses are run on the blocking thread pool of the tokio runtime.
+///
+/// # Type Parameters
+///
+/// * `S` - The status of the repository
+pub struct WebDavFs<S> {
+    /// The shared state
+    inner: Arc<DavFsInner<S>>,
+}
+
+impl<S> Clone for WebDavFs<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<S> Debug for WebDavFs<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebDavFs")
+            .field("vfs", &self.inner.vfs)
+            .field("file_policy", &self.inner.file_policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: IndexedFull + Send + Sync + 'static> WebDavFs<S> {
+    /// Create a new `WebDavFs`.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to read from
+    /// * `vfs` - The virtual file system to serve
+    /// * `file_policy` - How to handle access to files
+    pub fn new(repo: Repository<S>, vfs: Vfs, file_policy: FilePolicy) -> Self {
+        Self {
+            inner: Arc::new(DavFsInner {
+                repo,
+                vfs,
+                file_policy,
+            }),
+        }
+    }
+
+    /// Get the [`Node`] at the given path
+    ///
+    /// # Errors
+    ///
+    /// * If the path doesn't exist.
+    async fn node(&self, path: &DavPath) -> FsResult<Node> {
+        let inner = self.inner.clone();
+        let path = path.as_pathbuf();
+        run_blocking(move || inner.vfs.node_from_path(&inner.repo, &path))
+            .await
+            .map_err(|_| FsError::NotFound)
+    }
+}
+
+impl<S: IndexedFull + Send + Sync + 'static> DavFileSystem for WebDavFs<S> {
+    fn open<'a>(
+        &'a self,
+        path: &'a DavPath,
+        options: OpenOptions,
+    ) -> FsFuture<'a, Box<dyn DavFile>> {
+        async move {
+            if options.write
+                || options.append
+                || options.truncate
+                || options.create
+                || options.create_new
+            {
+                return Err(FsError::Forbidden);
+            }
+            if matches!(self.inner.file_policy, FilePolicy::Forbidden) {
+                return Err(FsError::Forbidden);
+            }
+
+            let node = self.node(path).await?;
+            if !node.is_file() {
+                return Err(FsError::Forbidden);
+            }
+            let inner = self.inner.clone();
+            let open_node = node.clone();
+            let open = run_blocking(move || inner.repo.open_file(&open_node))
+                .await
+                .map_err(|_| FsError::GeneralFailure)?;
+            let file: Box<dyn DavFile> = Box::new(DavFsFile {
+                node,
+                open: Arc::new(open),
+                inner: self.inner.clone(),
+                seek: 0,
+            });
+            Ok(file)
+        }
+        .boxed()
+    }
+
+    fn read_dir<'a>(
+        &'a self,
+        path: &'a DavPath,
+        _meta: ReadDirMeta,
+    ) -> FsFuture<'a, FsStream<Box<dyn DavDirEntry>>> {
+        async move {
+            let inner = self.inner.clone();
+            let path: PathBuf = path.as_pathbuf();
+            let entries = run_blocking(move || inner.vfs.dir_entries_from_path(&inner.repo, &path))
+                .await
+                .map_err(|_| FsError::NotFound)?;
+            let entries = entries.into_iter().map(|node| {
+                let entry: Box<dyn DavDirEntry> = Box::new(DavFsDirEntry(node));
+                Ok(entry)
+            });
+            let stream: FsStream<Box<dyn DavDirEntry>> = Box::pin(stream::iter(entries));
+            Ok(stream)
+        }
+        .boxed()
+    }
+
+    fn metadata<'a>(&'a self, path: &'a DavPath) -> FsFuture<'a, Box<dyn DavMetaData>> {
+        async move {
+            let meta: Box<dyn DavMetaData> = Box::new(DavFsMetaData(self.node(path).await?));
+            Ok(meta)
+        }
+        .boxed()
+    }
+}
+
+/// The [`DavMetaData`] of a [`Node`]
+#[derive(Clone, Debug)]
+struct DavFsMetaData(Node);
+
+impl DavMetaData for DavFsMetaData {
+    fn len(&self) -> u64 {
+        self.0.meta.size
+    }
+
+    fn modified(&self) -> FsResult<SystemTime> {
+        Ok(self
+            .0
+            .meta
+            .mtime
+            .map_or(SystemTime::UNIX_EPOCH, SystemTime::from))
+    }
+
+    fn is_dir(&self) -> bool {
+        self.0.is_dir()
+    }
+
+    fn is_file(&self) -> bool {
+        self.0.is_file()
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.0.is_symlink()
+    }
+}
+
+/// A [`DavDirEntry`] given by a [`Node`]
+struct DavFsDirEntry(Node);
+
+impl DavDirEntry for DavFsDirEntry {
+    fn name(&self) -> Vec<u8> {
+        self.0.name().as_encoded_bytes().to_vec()
+    }
+
+    fn metadata(&self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        async move {
+            let meta: Box<dyn DavMetaData> = Box::new(DavFsMetaData(self.0.clone()));
+            Ok(meta)
+        }
+        .boxed()
+    }
+}
+
+/// A read-only [`DavFile`] reading the contents of a file [`Node`]
+struct DavFsFile<S> {
+    /// The node of the file
+    node: Node,
+    /// The opened file
+    open: Arc<OpenFile>,
+    /// The shared state
+    inner: Arc<DavFsInner<S>>,
+    /// The current position within the file
+    seek: usize,
+}
+
+impl<S> Debug for DavFsFile<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DavFsFile")
+            .field("node", &self.node)
+            .field("seek", &self.seek)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: IndexedFull + Send + Sync + 'static> DavFile for DavFsFile<S> {
+    fn metadata(&mut self) -> FsFuture<'_, Box<dyn DavMetaData>> {
+        async move {
+            let meta: Box<dyn DavMetaData> = Box::new(DavFsMetaData(self.node.clone()));
+            Ok(meta)
+        }
+        .boxed()
+    }
+
+    fn write_bytes(&mut self, _buf: Bytes) -> FsFuture<'_, ()> {
+        async move { Err(FsError::Forbidden) }.boxed()
+    }
+
+    fn write_buf(&mut self, _buf: Box<dyn Buf + Send>) -> FsFuture<'_, ()> {
+        async move { Err(FsError::Forbidden) }.boxed()
+    }
+
+    fn read_bytes(&mut self, count: usize) -> FsFuture<'_, Bytes> {
+        async move {
+            let (inner, open, seek) = (self.inner.clone(), self.open.clone(), self.seek);
+            let data = run_blocking(move || open.read_at(&inner.repo, seek, count))
+                .await
+                .map_err(|_| FsError::GeneralFailure)?;
+            self.seek += data.len();
+            Ok(data)
+        }
+        .boxed()
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64> {
+        async move {
+            let (base, offset) = match pos {
+                SeekFrom::Start(start) => (start, 0),
+                SeekFrom::Current(delta) => (self.seek as u64, delta),
+                SeekFrom::End(delta) => (self.node.meta.size, delta),
+            };
+            let new_seek = base
+                .checked_add_signed(offset)
+                .ok_or(FsError::GeneralFailure)?;
+            self.seek = usize::try_from(new_seek).map_err(|_| FsError::GeneralFailure)?;
+            Ok(new_seek)
+        }
+        .boxed()
+    }
+
+    fn flush(&mut self) -> FsFuture<'_, ()> {
+        async move { Ok(()) }.boxed()
+    }
+}
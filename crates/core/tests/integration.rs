@@ -28,17 +28,29 @@ mod integration {
     mod check;
     mod chunker;
     mod copy;
+    mod diff;
     mod dump;
     mod find;
     mod hotcold;
     mod key;
+    mod lock;
     mod ls;
+    mod migrate;
+    mod mirror;
+    mod prewarm;
     mod prune;
+    mod rekey;
+    mod repair_index;
     mod repair_snapshots;
+    mod repoinfo;
     mod restore;
     mod rewrite;
+    mod scrub;
     mod snapshots;
     mod vfs;
+    mod watch;
+    #[cfg(feature = "webdav")]
+    mod webdav;
     use super::*;
 }
 
@@ -1,18 +1,28 @@
 use std::{
+    collections::BTreeSet,
+    fs,
     path::{Path, PathBuf},
     str::FromStr,
+    sync::Arc,
 };
 
 use anyhow::Result;
+use bytesize::ByteSize;
+use filetime::{FileTime, set_file_mtime};
 use insta::Settings;
 use pretty_assertions::assert_eq;
 use rstest::rstest;
 
 use rustic_core::{
-    BackupOptions, CommandInput, Grouped, ParentOptions, PathList, SnapshotGroupCriterion,
-    SnapshotOptions, StringList,
-    repofile::{PackId, SnapshotFile},
+    BackupOptions, CancellationToken, ChangeDetection, ChangedPath, CheckOptions, CommandInput,
+    ConfigOptions, Credentials, Grouped, KeyOptions, MemorySource, MergeConflictPolicy,
+    ParentOptions, PathList, PathRemap, PauseHandle, ProgressEvent, ProgressEvents, ProgressType,
+    PruneOptions, Repository, RepositoryBackends, RepositoryOptions, SnapshotGroupCriterion,
+    SnapshotOptions, StatsOptions, StringList,
+    repofile::{BlobType, MasterKey, Metadata, PackId, SnapshotFile},
 };
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
+use tempfile::tempdir;
 
 use super::{
     RepoOpen, TestSource, assert_with_win, insta_node_redaction, insta_snapshotfile_redaction,
@@ -307,3 +317,897 @@ fn test_backup_excludes_xattr_entries(set_up_repo: Result<RepoOpen>) -> Result<(
 
     Ok(())
 }
+
+#[cfg(not(windows))]
+#[rstest]
+fn test_backup_follow_symlinks(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    use std::{ffi::OsStr, os::unix::fs::symlink};
+
+    use rustic_core::{
+        LocalSourceFilterOptions, LsOptions, RusticResult,
+        repofile::{Metadata, Node, NodeType},
+    };
+
+    let tmp = tempdir()?;
+    let base = tmp.path();
+    fs::create_dir(base.join("real"))?;
+    fs::write(base.join("real").join("file"), "content")?;
+    symlink(base.join("real"), base.join("link"))?;
+    // a symlink pointing to its parent directory would result in an endless loop
+    symlink(base, base.join("real").join("loop"))?;
+
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let paths = PathList::from_iter(Some(base.to_path_buf()));
+    let backup = |filter_opts: LocalSourceFilterOptions| -> Result<Vec<(PathBuf, Node)>> {
+        let opts = BackupOptions::default()
+            .as_path(PathBuf::from("test"))
+            .ignore_filter_opts(filter_opts);
+        let snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+        let repo = repo.clone().to_indexed_ids()?;
+        let mut root_node = Node::new_node(OsStr::new(""), NodeType::Dir, Metadata::default());
+        root_node.subtree = Some(snapshot.tree);
+        let mut nodes = repo
+            .ls(&root_node, &LsOptions::default())?
+            .collect::<RusticResult<Vec<_>>>()?;
+        nodes.sort_by(|(p1, _), (p2, _)| p1.cmp(p2));
+        Ok(nodes)
+    };
+
+    // without following symlinks, the links are saved
+    let nodes = backup(LocalSourceFilterOptions::default())?;
+    let paths: Vec<_> = nodes.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(
+        paths,
+        [
+            "test",
+            "test/link",
+            "test/real",
+            "test/real/file",
+            "test/real/loop"
+        ]
+        .map(PathBuf::from)
+    );
+    assert!(nodes[1].1.is_symlink());
+
+    // following symlinks saves the targets, but not the loop
+    let nodes = backup(LocalSourceFilterOptions::default().follow_symlinks(true))?;
+    let paths: Vec<_> = nodes.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(
+        paths,
+        [
+            "test",
+            "test/link",
+            "test/link/file",
+            "test/real",
+            "test/real/file"
+        ]
+        .map(PathBuf::from)
+    );
+    assert!(nodes[1].1.is_dir());
+    assert!(nodes[2].1.is_file());
+
+    // the depth limit stops descending into directories
+    let nodes = backup(
+        LocalSourceFilterOptions::default()
+            .follow_symlinks(true)
+            .max_depth(1_usize),
+    )?;
+    let paths: Vec<_> = nodes.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(paths, ["test", "test/link", "test/real"].map(PathBuf::from));
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+#[rstest]
+fn test_backup_node_stats(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let tmp = tempdir()?;
+    let base = tmp.path();
+    fs::create_dir(base.join("dir"))?;
+    fs::write(base.join("file"), "content")?;
+    fs::hard_link(base.join("file"), base.join("dir").join("hardlink"))?;
+    fs::write(base.join("other"), "other content")?;
+    symlink(base.join("file"), base.join("symlink"))?;
+
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from("test"));
+    let paths = PathList::from_iter(Some(base.to_path_buf()));
+    let snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+
+    let stats = repo.snapshot_stats(&snapshot)?;
+    // the directories "test" and "test/dir"
+    assert_eq!(stats.node_types.dirs, 2);
+    assert_eq!(stats.node_types.files, 3);
+    assert_eq!(stats.node_types.symlinks, 1);
+    assert_eq!(stats.node_types.total(), 6);
+    assert_eq!(stats.file_size, 7 + 7 + 13);
+    assert_eq!(stats.sparse_files, 0);
+    assert_eq!(stats.hardlink_groups, 1);
+    assert_eq!(stats.hardlinked_nodes, 2);
+    assert_eq!(stats.hardlinked_size, 7);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_remote_source(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    use std::{collections::BTreeMap, ffi::OsString, io::Cursor, sync::Arc};
+
+    use rustic_core::{
+        ErrorKind, Excludes, LsOptions, RemoteSource, RusticError, RusticResult, SourceEntryKind,
+        SourceFileSystem, SourceMetadata,
+    };
+
+    /// A file system keeping all entries in memory
+    #[derive(Debug, Default)]
+    struct MemoryFs(BTreeMap<PathBuf, (SourceMetadata, Vec<u8>)>);
+
+    impl MemoryFs {
+        fn add(&mut self, path: &str, kind: SourceEntryKind, content: &[u8]) {
+            let meta = SourceMetadata::default()
+                .kind(kind)
+                .size(u64::try_from(content.len()).unwrap())
+                .mode(Some(0o644));
+            _ = self.0.insert(path.into(), (meta, content.to_vec()));
+        }
+
+        fn get(&self, path: &Path) -> RusticResult<&(SourceMetadata, Vec<u8>)> {
+            self.0.get(path).ok_or_else(|| {
+                RusticError::new(ErrorKind::InputOutput, "`{path}` does not exist.")
+                    .attach_context("path", path.display().to_string())
+            })
+        }
+    }
+
+    impl SourceFileSystem for MemoryFs {
+        type Reader = Cursor<Vec<u8>>;
+
+        fn list(&self, path: &Path) -> RusticResult<Vec<(OsString, SourceMetadata)>> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|(p, _)| p.parent() == Some(path))
+                .map(|(p, (meta, _))| (p.file_name().unwrap().to_os_string(), meta.clone()))
+                .collect())
+        }
+
+        fn metadata(&self, path: &Path) -> RusticResult<SourceMetadata> {
+            Ok(self.get(path)?.0.clone())
+        }
+
+        fn open_read(&self, path: &Path) -> RusticResult<Self::Reader> {
+            Ok(Cursor::new(self.get(path)?.1.clone()))
+        }
+    }
+
+    let mut fs = MemoryFs::default();
+    fs.add("/share", SourceEntryKind::Dir, b"");
+    fs.add("/share/b.txt", SourceEntryKind::File, b"content of b");
+    fs.add("/share/a.tmp", SourceEntryKind::File, b"temporary");
+    fs.add("/share/dir", SourceEntryKind::Dir, b"");
+    fs.add("/share/dir/c.txt", SourceEntryKind::File, b"content of c");
+    fs.add(
+        "/share/link",
+        SourceEntryKind::Symlink {
+            target: "dir/c.txt".into(),
+        },
+        b"",
+    );
+
+    let excludes = Excludes::default().globs(vec!["!*.tmp".to_string()]);
+    let src = RemoteSource::new(Arc::new(fs), &excludes, &["/share"])?;
+
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from("test"));
+    let snapshot = repo.archive(
+        &opts,
+        &src,
+        SnapshotFile::default(),
+        &[PathBuf::from("/share")],
+    )?;
+    // b.txt, dir/c.txt and link
+    assert_eq!(snapshot.summary.unwrap().files_new, 3);
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest:test", |_| true)?;
+    let entries: Vec<_> = repo
+        .ls(&node, &LsOptions::default())?
+        .collect::<RusticResult<_>>()?;
+    let paths: Vec<_> = entries.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(
+        paths,
+        ["b.txt", "dir", "dir/c.txt", "link"].map(PathBuf::from)
+    );
+    assert_eq!(entries[0].1.meta.mode, Some(0o644));
+    assert!(entries[3].1.is_symlink());
+
+    let node = repo.node_from_snapshot_path("latest:test/dir/c.txt", |_| true)?;
+    let mut content = Vec::new();
+    repo.dump(&node, &mut content)?;
+    assert_eq!(content, b"content of c");
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_skip_if_identical_today(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let paths = &source.path_list();
+    let opts = BackupOptions::default()
+        .as_path(PathBuf::from_str("test")?)
+        .skip_if_identical_today(true);
+
+    let first_snapshot = repo.backup(&opts, paths, SnapshotFile::default())?;
+
+    // nothing changed => the existing snapshot is returned
+    let second_snapshot = repo.backup(&opts, paths, SnapshotFile::default())?;
+    assert_eq!(second_snapshot.id, first_snapshot.id);
+    assert_eq!(repo.get_all_snapshots()?.len(), 1);
+
+    // a different label is a different backup
+    let snap = SnapshotOptions::default()
+        .label("other".to_string())
+        .to_snapshot()?;
+    let third_snapshot = repo.backup(&opts, paths, snap)?;
+    assert_ne!(third_snapshot.id, first_snapshot.id);
+    assert_eq!(repo.get_all_snapshots()?.len(), 2);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_host_lock(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let cache_dir = tempdir()?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let options = RepositoryOptions::default().cache_dir(cache_dir.path());
+    let repo = Repository::new(&options, &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+    let lock_file = cache_dir
+        .path()
+        .join(repo.config().id.to_hex())
+        .join("backup.lock");
+
+    let paths = &source.path_list();
+    let opts = BackupOptions::default()
+        .as_path(PathBuf::from_str("test")?)
+        .host_lock(true);
+
+    // the lock is released after each backup
+    _ = repo.backup(&opts, paths, SnapshotFile::default())?;
+    _ = repo.backup(&opts, paths, SnapshotFile::default())?;
+    assert!(!lock_file.exists());
+
+    // a lock of a running backup (which can't be checked) prevents the backup
+    fs::write(
+        &lock_file,
+        r#"{"hostname":"other-host","pid":1,"time":"2024-01-01T00:00:00Z"}"#,
+    )?;
+    assert!(repo.backup(&opts, paths, SnapshotFile::default()).is_err());
+    assert!(lock_file.exists());
+
+    // without the option, the lock is ignored
+    _ = repo.backup(
+        &opts.clone().host_lock(false),
+        paths,
+        SnapshotFile::default(),
+    )?;
+    assert_eq!(repo.get_all_snapshots()?.len(), 3);
+
+    // a recent invalid lock may belong to a running process and is kept
+    fs::write(&lock_file, "invalid")?;
+    assert!(repo.backup(&opts, paths, SnapshotFile::default()).is_err());
+    assert!(lock_file.exists());
+
+    // an expired invalid lock is removed
+    let expired = FileTime::from_unix_time(FileTime::now().unix_seconds() - 2 * 60 * 60, 0);
+    set_file_mtime(&lock_file, expired)?;
+    _ = repo.backup(&opts, paths, SnapshotFile::default())?;
+    assert!(!lock_file.exists());
+    assert_eq!(repo.get_all_snapshots()?.len(), 4);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_upload_concurrency(
+    tar_gz_testdata: Result<TestSource>,
+    #[values(1, 4)] upload_concurrency: usize,
+) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let options = RepositoryOptions::default().upload_concurrency(upload_concurrency);
+    // use small data packs, so that many packs are uploaded
+    let config_opts = ConfigOptions::default()
+        .set_datapack_size(ByteSize::kib(4))
+        .set_datapack_growfactor(0_u32);
+    let repo = Repository::new(&options, &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &config_opts,
+        )?
+        .to_indexed_ids()?;
+
+    _ = repo.backup(
+        &BackupOptions::default(),
+        &source.path_list(),
+        SnapshotFile::default(),
+    )?;
+
+    // all uploaded packs are contained in the index
+    let packs: BTreeSet<PackId> = repo.list()?.collect();
+    assert!(packs.len() > upload_concurrency);
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_change_detection(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let paths = &source.path_list();
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+
+    let first_snapshot = repo.backup(&opts, paths, SnapshotFile::default())?;
+    let summary = first_snapshot.summary.expect("summary should be set");
+    assert_eq!(summary.change_detection, Some(ChangeDetection::Inode));
+    let files = summary.files_new;
+
+    // only comparing metadata, all files are unchanged
+    let opts_metadata = opts
+        .clone()
+        .parent_opts(ParentOptions::default().change_detection(ChangeDetection::SizeMtime));
+    let snapshot = repo.backup(&opts_metadata, paths, SnapshotFile::default())?;
+    let summary = snapshot.summary.expect("summary should be set");
+    assert_eq!(summary.change_detection, Some(ChangeDetection::SizeMtime));
+    assert_eq!(summary.files_unmodified, files);
+    assert_eq!(summary.files_changed, 0);
+
+    // ignoring ctime keeps the level and its inode check
+    let opts_ctime = opts
+        .clone()
+        .parent_opts(ParentOptions::default().ignore_ctime(true));
+    let snapshot = repo.backup(&opts_ctime, paths, SnapshotFile::default())?;
+    let summary = snapshot.summary.expect("summary should be set");
+    assert_eq!(summary.change_detection, Some(ChangeDetection::Inode));
+    assert_eq!(summary.files_unmodified, files);
+
+    // comparing content, all regular files are read again
+    let opts_content =
+        opts.parent_opts(ParentOptions::default().change_detection(ChangeDetection::Content));
+    let snapshot = repo.backup(&opts_content, paths, SnapshotFile::default())?;
+    let summary = snapshot.summary.expect("summary should be set");
+    assert_eq!(summary.change_detection, Some(ChangeDetection::Content));
+    assert!(summary.files_changed > 0);
+    assert_eq!(summary.files_changed + summary.files_unmodified, files);
+    // the content didn't change, so the same tree is produced
+    assert_eq!(snapshot.tree, first_snapshot.tree);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_files_from_manifest(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+
+    let manifest_dir = tempdir()?;
+    let manifest = manifest_dir.path().join("manifest");
+    let file = source.path().join("0/tests/testfile");
+    let size = fs::metadata(&file)?.len();
+    fs::write(
+        &manifest,
+        format!(
+            "{}\t{size}\n{}\n",
+            file.display(),
+            source.path().join("0/tests/testfile-symlink").display()
+        ),
+    )?;
+
+    let opts = BackupOptions::default()
+        .as_path(PathBuf::from_str("test")?)
+        .files_from(manifest);
+    let snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    // only the listed paths and their parent dirs are contained
+    let repo = repo.to_indexed_ids()?;
+    let node = repo.node_from_snapshot_and_path(&snapshot, "")?;
+    let entries: Vec<_> = repo
+        .ls(&node, &rustic_core::LsOptions::default())?
+        .map(|item| item.map(|(path, node)| (path, node.is_dir())))
+        .collect::<rustic_core::RusticResult<_>>()?;
+    let files: Vec<_> = entries
+        .iter()
+        .filter(|(_, is_dir)| !is_dir)
+        .map(|(path, _)| path.clone())
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            PathBuf::from("test/0/tests/testfile"),
+            PathBuf::from("test/0/tests/testfile-symlink"),
+        ]
+    );
+    assert_eq!(entries.len(), 5);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_repo_stats(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let archive = |repo: &Repository<_>, src: &MemorySource| {
+        repo.archive(
+            &BackupOptions::default(),
+            src,
+            SnapshotFile::default(),
+            &[PathBuf::from("dump")],
+        )
+    };
+
+    let content = "first file content";
+    let src = MemorySource::new()
+        .add_file("dump/a", content, Metadata::default())
+        .add_file("dump/b", vec![b'x'; 1000], Metadata::default());
+    let snap1 = archive(&repo, &src)?;
+    // re-read index, so that the blobs are not saved again
+    let repo = repo.to_indexed_ids()?;
+    let snap2 = archive(&repo, &src)?;
+    let repo = repo.to_indexed_ids()?;
+    let src = MemorySource::new()
+        .add_file("dump/a", content, Metadata::default())
+        .add_file("dump/c", "third", Metadata::default());
+    let snap3 = archive(&repo, &src)?;
+
+    let repo = repo.to_indexed()?;
+    let stats = repo.stats(&StatsOptions::default())?;
+    let len = content.len() as u64;
+    assert_eq!(stats.snapshots, 3);
+    assert_eq!(stats.raw_data_size(), len + 1000 + 5);
+    assert_eq!(stats.restore_size, 2 * (len + 1000) + len + 5);
+    assert!(stats.dedup_ratio().unwrap() > 1.0);
+    assert!(stats.snapshot_sizes.is_empty());
+    let packs = &stats.packs[BlobType::Data];
+    assert_eq!(packs.buckets.values().sum::<u64>(), packs.count);
+    assert!(stats.blobs[BlobType::Tree].compression_ratio().is_some());
+
+    let stats = repo.stats(&StatsOptions::default().per_snapshot(true))?;
+    let sizes = |id| {
+        stats
+            .snapshot_sizes
+            .iter()
+            .find(|sizes| sizes.id == id)
+            .unwrap()
+    };
+    // snapshot 1 and 2 share all blobs
+    for id in [snap1.id, snap2.id] {
+        assert_eq!(sizes(id).restore_size, len + 1000);
+        assert_eq!(sizes(id).unique_size, 0);
+    }
+    // snapshot 3 has a new file and new trees
+    assert_eq!(sizes(snap3.id).restore_size, len + 5);
+    assert!(sizes(snap3.id).unique_data_size > 5);
+    assert!(sizes(snap3.id).unique_size > 0);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_archive_memory_source(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+
+    let src = MemorySource::new()
+        .add_file("dump/users.csv", "id,name\n1,alice\n", Metadata::default())
+        .add_file("dump/db.sql", vec![b'x'; 100_000], Metadata::default());
+    let snapshot = repo.archive(
+        &BackupOptions::default(),
+        &src,
+        SnapshotFile::default(),
+        &[PathBuf::from("dump")],
+    )?;
+    let summary = snapshot.summary.expect("summary should be present");
+    assert_eq!(summary.files_new, 2);
+    assert_eq!(summary.total_bytes_processed, 100_016);
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_and_path(&snapshot, "dump/users.csv")?;
+    let mut data = Vec::new();
+    repo.dump(&node, &mut data)?;
+    assert_eq!(data, b"id,name\n1,alice\n");
+
+    Ok(())
+}
+
+#[rstest]
+fn test_merge_snapshots_from_ids(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let archive = |repo: &Repository<_>, src: &MemorySource| {
+        repo.archive(
+            &BackupOptions::default(),
+            src,
+            SnapshotFile::default(),
+            &[PathBuf::from("dump")],
+        )
+    };
+    let meta = |mtime: &str| -> Result<Metadata> {
+        let mut meta = Metadata::default();
+        meta.mtime = Some(mtime.parse()?);
+        Ok(meta)
+    };
+
+    let src = MemorySource::new()
+        .add_file("dump/a", "new", meta("2024-06-01T00:00:00Z")?)
+        .add_file("dump/b", "only in first", meta("2024-01-01T00:00:00Z")?);
+    let snap1 = archive(&repo, &src)?;
+    let repo = repo.to_indexed_ids()?;
+    let src = MemorySource::new()
+        .add_file("dump/a", "older", meta("2024-01-01T00:00:00Z")?)
+        .add_file("dump/c", "only in second", meta("2024-01-01T00:00:00Z")?);
+    let snap2 = archive(&repo, &src)?;
+    let repo = repo.to_indexed_ids()?;
+
+    let ids = [snap1.id.to_string(), snap2.id.to_string()];
+    let size_of_a = |policy| -> Result<u64> {
+        let merged = repo.merge_snapshots_from_ids(&ids, policy, SnapshotFile::default())?;
+        for path in ["dump/b", "dump/c"] {
+            _ = repo.node_from_path(merged.tree, Path::new(path))?;
+        }
+        Ok(repo
+            .node_from_path(merged.tree, Path::new("dump/a"))?
+            .meta
+            .size)
+    };
+    assert_eq!(size_of_a(MergeConflictPolicy::LatestMtime)?, 3);
+    assert_eq!(size_of_a(MergeConflictPolicy::FirstSnapshot)?, 3);
+    assert_eq!(size_of_a(MergeConflictPolicy::LastSnapshot)?, 5);
+
+    let no_ids: [&str; 0] = [];
+    assert!(
+        repo.merge_snapshots_from_ids(
+            &no_ids,
+            MergeConflictPolicy::default(),
+            SnapshotFile::default()
+        )
+        .is_err()
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_progress_events() -> Result<()> {
+    let (pb, events) = ProgressEvents::new();
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new_with_progress(&RepositoryOptions::default(), &be, pb)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+    let src = MemorySource::new().add_file("dump/a", vec![b'x'; 1000], Metadata::default());
+    _ = repo.archive(
+        &BackupOptions::default(),
+        &src,
+        SnapshotFile::default(),
+        &[PathBuf::from("dump")],
+    )?;
+    drop(repo);
+
+    let events: Vec<_> = events.try_iter().collect();
+    let started: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::Started {
+                id, progress_type, ..
+            } => Some((*id, *progress_type)),
+            _ => None,
+        })
+        .collect();
+    assert!(!started.is_empty());
+    // every started progress is finished
+    for (id, _) in &started {
+        assert!(events.iter().any(
+            |event| matches!(event, ProgressEvent::Finished { id: finished, .. } if finished == id)
+        ));
+    }
+    // the archived bytes are reported
+    let (bytes_id, _) = started
+        .iter()
+        .find(|(_, tpe)| *tpe == ProgressType::Bytes)
+        .expect("backup should report bytes");
+    assert!(events.iter().any(
+        |event| matches!(event, ProgressEvent::Finished { id, done: 1000 } if id == bytes_id)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_cancel_operations() -> Result<()> {
+    let cancel = CancellationToken::new();
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .with_cancellation_token(cancel.clone())
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+    let src = MemorySource::new().add_file("dump/a", "content", Metadata::default());
+    let archive = |repo: &Repository<_>| {
+        repo.archive(
+            &BackupOptions::default(),
+            &src,
+            SnapshotFile::default(),
+            &[PathBuf::from("dump")],
+        )
+    };
+    let snap = archive(&repo)?;
+
+    cancel.cancel();
+    assert!(repo.cancellation_token().is_cancelled());
+    let err = archive(&repo).unwrap_err();
+    assert!(err.is_cancelled());
+    assert_eq!(repo.get_all_snapshots()?, vec![snap]);
+
+    let err = repo.prune_plan(&PruneOptions::default()).unwrap_err();
+    assert!(err.is_cancelled());
+
+    // the repository is still consistent
+    let repo = repo.with_cancellation_token(CancellationToken::new());
+    repo.check(CheckOptions::default())?.is_ok()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_pause_backup() -> Result<()> {
+    let pause = PauseHandle::new();
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .with_pause_handle(pause.clone())
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+    let src = MemorySource::new().add_file("dump/a", "content", Metadata::default());
+
+    pause.pause();
+    std::thread::scope(|s| -> Result<()> {
+        let backup = s.spawn(|| {
+            repo.archive(
+                &BackupOptions::default(),
+                &src,
+                SnapshotFile::default(),
+                &[PathBuf::from("dump")],
+            )
+        });
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        assert!(!backup.is_finished());
+        assert!(repo.get_all_snapshots()?.is_empty());
+
+        pause.resume();
+        let snap = backup.join().expect("backup thread should not panic")?;
+        assert_eq!(repo.get_all_snapshots()?, vec![snap]);
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_incremental(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let parent = repo.backup(
+        &BackupOptions::default(),
+        &source.path_list(),
+        SnapshotFile::default(),
+    )?;
+
+    let modified = source.path().join("0/0/9/0");
+    let removed = source.path().join("0/0/9/15");
+    let new_dir = source.path().join("0/new");
+    let new_file = new_dir.join("file");
+    fs::write(&modified, "modified content")?;
+    fs::remove_file(&removed)?;
+    fs::create_dir(&new_dir)?;
+    fs::write(&new_file, "new content")?;
+
+    let repo = repo.to_indexed_ids()?;
+    let changes = [
+        ChangedPath::Modified(modified.clone()),
+        ChangedPath::Removed(removed.clone()),
+        ChangedPath::Modified(new_file.clone()),
+    ];
+    let snapshot = repo.backup_incremental(
+        &BackupOptions::default(),
+        changes,
+        &parent,
+        SnapshotFile::default(),
+    )?;
+    assert_eq!(snapshot.parent, Some(parent.id));
+    assert_eq!(snapshot.paths, parent.paths);
+    let summary = snapshot.summary.clone().expect("summary should be present");
+    assert_eq!(summary.files_new, 1);
+    assert_eq!(summary.files_changed, 1);
+    assert_eq!(summary.dirs_new, 1);
+
+    let repo = repo.to_indexed()?;
+    let path = |p: &Path| p.to_str().unwrap().to_string();
+    let node = repo.node_from_snapshot_and_path(&snapshot, &path(&modified))?;
+    let mut data = Vec::new();
+    repo.dump(&node, &mut data)?;
+    assert_eq!(data, b"modified content");
+    let node = repo.node_from_snapshot_and_path(&snapshot, &path(&new_file))?;
+    let mut data = Vec::new();
+    repo.dump(&node, &mut data)?;
+    assert_eq!(data, b"new content");
+    assert!(
+        repo.node_from_snapshot_and_path(&snapshot, &path(&removed))
+            .is_err()
+    );
+
+    // unchanged subtrees are reused from the parent
+    let unchanged = path(&source.path().join("0/tests"));
+    assert_eq!(
+        repo.node_from_snapshot_and_path(&snapshot, &unchanged)?
+            .subtree,
+        repo.node_from_snapshot_and_path(&parent, &unchanged)?
+            .subtree
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_from_reader(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+
+    let data = b"CREATE TABLE users;\n".repeat(1000);
+    let opts = BackupOptions::default().stdin_size(ByteSize::b(data.len() as u64));
+    let snapshot = repo.backup_from_reader(
+        &opts,
+        std::io::Cursor::new(data.clone()),
+        "dump/db.sql",
+        SnapshotFile::default(),
+    )?;
+    assert_eq!(snapshot.paths.to_string(), "dump/db.sql");
+    let summary = snapshot.summary.expect("summary should be present");
+    assert_eq!(summary.files_new, 1);
+    assert_eq!(summary.total_bytes_processed, data.len() as u64);
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_and_path(&snapshot, "dump/db.sql")?;
+    let mut dumped = Vec::new();
+    repo.dump(&node, &mut dumped)?;
+    assert_eq!(dumped, data);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_with_remap(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+
+    let dir = tempdir()?;
+    let app = dir.path().join("var/lib/app");
+    let home = dir.path().join("home");
+    fs::create_dir_all(&app)?;
+    fs::create_dir_all(&home)?;
+    fs::write(app.join("a.txt"), "app data")?;
+    fs::write(home.join("b.txt"), "home data")?;
+
+    let paths = PathList::from_iter([&app, &home]);
+    let remap = vec![
+        PathRemap::new(&app, "/app-data"),
+        PathRemap::new(&home, "/home"),
+    ];
+    let opts = BackupOptions::default().remap(remap.clone());
+    let snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    assert_eq!(snapshot.paths.to_string(), "/app-data,/home");
+    assert_eq!(paths.remap(&remap).to_string(), "/app-data,/home");
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_and_path(&snapshot, "/app-data")?;
+    assert!(node.is_dir());
+    assert_eq!(node.name, "app-data");
+    for (path, data) in [
+        ("/app-data/a.txt", "app data"),
+        ("/home/b.txt", "home data"),
+    ] {
+        let node = repo.node_from_snapshot_and_path(&snapshot, path)?;
+        let mut dumped = Vec::new();
+        repo.dump(&node, &mut dumped)?;
+        assert_eq!(dumped, data.as_bytes());
+    }
+
+    // targets must not overlap
+    let repo = repo.to_indexed_ids()?;
+    let opts = BackupOptions::default().remap(vec![
+        PathRemap::new(&app, "/data"),
+        PathRemap::new(&home, "/data/home"),
+    ]);
+    assert!(repo.backup(&opts, &paths, SnapshotFile::default()).is_err());
+
+    // nested remap sources would move entries out of order
+    let cache = app.join("cache");
+    fs::create_dir_all(&cache)?;
+    let opts = BackupOptions::default().remap(vec![
+        PathRemap::new(&app, "/app-data"),
+        PathRemap::new(&cache, "/cache"),
+    ]);
+    assert!(repo.backup(&opts, &paths, SnapshotFile::default()).is_err());
+    let opts = BackupOptions::default().remap(vec![PathRemap::new(&cache, "/cache")]);
+    assert!(repo.backup(&opts, &paths, SnapshotFile::default()).is_err());
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backup_roots(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+
+    let dir = tempdir()?;
+    let app = dir.path().join("app");
+    let home = dir.path().join("home");
+    fs::create_dir_all(app.join("db"))?;
+    fs::create_dir_all(&home)?;
+    fs::write(app.join("db/data"), "0123456789")?;
+    fs::write(app.join("config"), "abc")?;
+    fs::write(home.join("notes"), "12345")?;
+
+    let paths = PathList::from_iter([&app, &home]);
+    let snapshot = repo.backup(&BackupOptions::default(), &paths, SnapshotFile::default())?;
+    let roots = snapshot.roots();
+    assert_eq!(roots.len(), 2);
+    assert_eq!(roots[0].path, app.to_string_lossy());
+    assert_eq!(roots[0].source, None);
+    assert_eq!(roots[0].files_processed, 2);
+    assert_eq!(roots[0].bytes_processed, 13);
+    assert_eq!(roots[1].bytes_processed, 5);
+    #[cfg(not(windows))]
+    assert!(roots.iter().all(|root| root.device_id.is_some()));
+
+    // scope to a single root of the snapshot
+    let file = app.join("db/data");
+    let root = snapshot.root_of(&file).unwrap();
+    assert_eq!(root, roots[0]);
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_and_path(&snapshot, &root.path)?;
+    assert!(node.is_dir());
+
+    // with remapping, the local source path is recorded
+    let repo = repo.to_indexed_ids()?;
+    let opts = BackupOptions::default().remap(vec![PathRemap::new(&app, "/app")]);
+    let snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let root = snapshot.root_of("/app/db/data").unwrap();
+    assert_eq!(root.source, Some(app.to_string_lossy().into_owned()));
+    assert_eq!(root.bytes_processed, 13);
+
+    Ok(())
+}
@@ -6,7 +6,7 @@ use insta::{Settings, assert_ron_snapshot};
 use rstest::rstest;
 
 use rustic_core::{
-    BackupOptions, ConfigOptions,
+    BackupOptions, CheckOptions, ConfigOptions,
     repofile::{Chunker, SnapshotFile},
 };
 
@@ -46,3 +46,45 @@ fn test_chunker_params(
 
     Ok(())
 }
+
+#[rstest]
+fn test_fastcdc_chunker(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    // Fixtures
+    let (source, mut repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+
+    let paths = &source.path_list();
+
+    // set fastcdc chunker with given chunk sizes
+    let config_opts = ConfigOptions::default()
+        .set_chunker(Chunker::FastCdc)
+        .set_chunk_size(ByteSize(4096))
+        .set_chunk_min_size(ByteSize(1024))
+        .set_chunk_max_size(ByteSize(16384));
+
+    assert!(repo.apply_config(&config_opts)?);
+    assert_eq!(repo.config().chunker, Some(Chunker::FastCdc));
+    assert_eq!(repo.config().chunk_size, Some(4096));
+
+    // invalid chunk sizes are rejected
+    let invalid_opts = ConfigOptions::default()
+        .set_chunker(Chunker::FastCdc)
+        .set_chunk_min_size(ByteSize(8192));
+    assert!(repo.apply_config(&invalid_opts).is_err());
+
+    let opts = BackupOptions::default();
+    let snapshot = repo.backup(&opts, paths, SnapshotFile::default())?;
+    let summary = snapshot.summary.unwrap();
+    assert!(summary.data_blobs > 0);
+
+    // backing up the same data again doesn't add any data
+    let snapshot = repo.backup(&opts, paths, SnapshotFile::default())?;
+    assert_eq!(snapshot.summary.unwrap().data_added, 0);
+
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    Ok(())
+}
@@ -4,7 +4,11 @@ use anyhow::Result;
 use pretty_assertions::assert_eq;
 use rstest::rstest;
 
-use rustic_core::{BackupOptions, CheckOptions, CopySnapshot, repofile::SnapshotFile};
+use bytesize::ByteSize;
+use rustic_core::{
+    BackupOptions, CheckOptions, CopyOptions, CopySnapshot, CopyState, ErrorKind, Excludes,
+    LsOptions, ParentOptions, PathList, RusticError, RusticResult, repofile::SnapshotFile,
+};
 
 use super::{RepoOpen, TestSource, set_up_repo, tar_gz_testdata};
 
@@ -44,3 +48,159 @@ fn test_copy(tar_gz_testdata: Result<TestSource>, set_up_repo: Result<RepoOpen>)
 
     Ok(())
 }
+
+#[rstest]
+fn test_copy_with_filters(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+    let repo = repo.to_indexed()?;
+
+    let target = super::set_up_repo()?.to_indexed_ids()?;
+    let copy_opts = CopyOptions::default()
+        .exclude_larger_than(Some(ByteSize::kb(16)))
+        .excludes(Excludes::default().globs(vec!["!test/0/tests/empty-file".to_string()]));
+    repo.copy_with_options(&target, Some(&snap), &copy_opts)?;
+    target.check(CheckOptions::default())?.is_ok()?;
+
+    let target = target.to_indexed()?;
+    let node = target.node_from_snapshot_path("latest", |_| true)?;
+    let files: Vec<_> = target
+        .ls(&node, &LsOptions::default())?
+        .collect::<RusticResult<Vec<_>>>()?
+        .into_iter()
+        .filter(|(_, node)| node.is_file())
+        .collect();
+    assert!(files.iter().all(|(_, node)| node.meta.size <= 16_000));
+    assert!(
+        files
+            .iter()
+            .any(|(path, _)| path == &PathBuf::from("test/0/0/9/68"))
+    );
+    assert!(
+        !files
+            .iter()
+            .any(|(path, _)| path == &PathBuf::from("test/0/tests/empty-file"))
+    );
+
+    let copied = target.get_all_snapshots()?;
+    assert_eq!(copied.len(), 1);
+    assert_ne!(copied[0].tree, snap.tree);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_copy_rechunk(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    // create a file which is larger than the maximum chunk size, so it gets chunked differently
+    let source = tempfile::tempdir()?;
+    let mut state: u64 = 42;
+    let data: Vec<u8> = (0..12 * 1024 * 1024)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            state.to_le_bytes()[7]
+        })
+        .collect();
+    std::fs::write(source.path().join("large"), &data)?;
+    let paths = PathList::from_iter(Some(source.path().to_path_buf()));
+
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed()?;
+
+    // a newly initialized repository uses a different chunker polynomial
+    let target = super::set_up_repo()?.to_indexed_ids()?;
+    repo.copy_with_options(&target, Some(&snap), &CopyOptions::default().rechunk(true))?;
+    target.check(CheckOptions::default())?.is_ok()?;
+
+    let target = target.to_indexed()?;
+    let node = target.node_from_snapshot_path("latest:test/large", |_| true)?;
+    let mut content = Vec::new();
+    target.dump(&node, &mut content)?;
+    assert_eq!(content, data);
+
+    // backing up the same data into the target repository doesn't add any data
+    let target = target.to_indexed_ids()?;
+    let opts = opts.parent_opts(ParentOptions::default().force(true));
+    let snap = target.backup(&opts, &paths, SnapshotFile::default())?;
+    assert_eq!(snap.summary.unwrap().data_blobs, 0);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_copy_to_many(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+    let repo = repo.to_indexed()?;
+
+    // the first target already contains the snapshot, the second one is empty
+    let target1 = super::set_up_repo()?.to_indexed_ids()?;
+    repo.copy(&target1, Some(&snap))?;
+    let target1 = target1.to_indexed_ids()?;
+    let target2 = super::set_up_repo()?.to_indexed_ids()?;
+
+    repo.copy_to_many(&[&target1, &target2], Some(&snap))?;
+
+    for target in [&target1, &target2] {
+        target.check(CheckOptions::default())?.is_ok()?;
+        let snaps = target.get_all_snapshots()?;
+        assert!(snaps.iter().all(|sn| sn.tree == snap.tree));
+    }
+    assert_eq!(target1.get_all_snapshots()?.len(), 2);
+    assert_eq!(target2.get_all_snapshots()?.len(), 1);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_copy_resumable(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let paths = &source.path_list();
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap1 = repo.backup(&opts, paths, SnapshotFile::default())?;
+    let snap2 = repo.backup(&opts, paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed()?;
+    let target = super::set_up_repo()?.to_indexed_ids()?;
+
+    // interrupt the copy after the first snapshot
+    let mut state = CopyState::default();
+    let mut saved = None;
+    let res = repo.copy_resumable(&target, [&snap1, &snap2], &mut state, |state| {
+        saved = Some(state.clone());
+        Err(RusticError::new(ErrorKind::Other, "interrupted"))
+    });
+    assert!(res.is_err());
+    let mut state = saved.unwrap();
+    assert!(state.is_copied(&snap1));
+    assert!(!state.is_copied(&snap2));
+
+    // resume the copy
+    let target = target.to_indexed_ids()?;
+    let mut saves = 0;
+    repo.copy_resumable(&target, [&snap1, &snap2], &mut state, |_| {
+        saves += 1;
+        Ok(())
+    })?;
+    assert_eq!(saves, 1);
+    assert!(state.is_copied(&snap2));
+
+    let target = target.to_indexed_ids()?;
+    target.check(CheckOptions::default())?.is_ok()?;
+    assert_eq!(target.get_all_snapshots()?.len(), 2);
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use std::{fs, path::PathBuf, str::FromStr};
+
+use anyhow::Result;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use tempfile::tempdir;
+
+use rustic_core::{
+    BackupOptions, ChangeDetection, DiffChange, Excludes, LocalSourceFilterOptions, PathList,
+    repofile::SnapshotFile,
+};
+
+use super::{RepoOpen, set_up_repo};
+
+#[rstest]
+fn test_diff(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let source = tempdir()?;
+    fs::write(source.path().join("a"), "content a")?;
+    fs::write(source.path().join("b"), "content b")?;
+    fs::create_dir(source.path().join("sub"))?;
+    fs::write(source.path().join("sub").join("c"), "content c")?;
+
+    let paths = PathList::from_iter(Some(source.path()));
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap1 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    fs::write(source.path().join("a"), "changed content a")?;
+    fs::remove_file(source.path().join("b"))?;
+    fs::write(source.path().join("sub").join("d"), "content d")?;
+    let snap2 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.to_indexed_ids()?;
+
+    // identical snapshots have no differences
+    assert_eq!(repo.diff(&snap1, &snap1)?.count(), 0);
+
+    let entries = repo
+        .diff(&snap1, &snap2)?
+        .map(|entry| entry.map(|entry| (entry.path, entry.change)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let change = |path: &str| {
+        entries
+            .iter()
+            .find(|(p, _)| p == &PathBuf::from(path))
+            .map(|(_, change)| *change)
+    };
+    assert_eq!(change("test/a"), Some(DiffChange::Modified));
+    assert_eq!(change("test/b"), Some(DiffChange::Removed));
+    assert_eq!(change("test/sub/d"), Some(DiffChange::Added));
+    assert_eq!(change("test/sub/c"), None);
+
+    // the reverse diff swaps added and removed entries
+    let reverse = repo.diff(&snap2, &snap1)?.collect::<Result<Vec<_>, _>>()?;
+    assert!(
+        reverse
+            .iter()
+            .any(|entry| entry.path == PathBuf::from("test/b") && entry.change == DiffChange::Added)
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn test_diff_with_local(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let root = tempdir()?;
+    let source = root.path().join("test");
+    fs::create_dir_all(source.join("sub"))?;
+    fs::write(source.join("a"), "content a")?;
+    fs::write(source.join("b"), "content b")?;
+    fs::write(source.join("sub").join("c"), "content c")?;
+
+    let paths = PathList::from_iter(Some(source.as_path()));
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+
+    // nothing changed since the backup
+    for change_detection in [ChangeDetection::SizeMtime, ChangeDetection::Content] {
+        let entries = repo
+            .diff_with_local(&snap, root.path(), change_detection)?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(entries, Vec::new());
+    }
+
+    fs::write(source.join("a"), "changed content a")?;
+    fs::remove_file(source.join("b"))?;
+    fs::write(source.join("sub").join("d"), "content d")?;
+
+    for change_detection in [ChangeDetection::SizeMtime, ChangeDetection::Content] {
+        let entries = repo
+            .diff_with_local(&snap, root.path(), change_detection)?
+            .map(|entry| entry.map(|entry| (entry.path, entry.change)))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            entries,
+            vec![
+                (PathBuf::from("test/a"), DiffChange::Modified),
+                (PathBuf::from("test/b"), DiffChange::Removed),
+                (PathBuf::from("test/sub/d"), DiffChange::Added),
+            ]
+        );
+    }
+
+    Ok(())
+}
+
+#[rstest]
+fn test_diff_with_local_filtered(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let root = tempdir()?;
+    let source = root.path().join("test");
+    fs::create_dir_all(source.join("cache"))?;
+    fs::write(source.join("a"), "content a")?;
+    fs::write(source.join("a.tmp"), "temporary")?;
+    fs::write(source.join("cache").join("CACHEDIR.TAG"), "")?;
+    fs::write(source.join("cache").join("c"), "cached")?;
+
+    let paths = PathList::from_iter(Some(source.as_path()));
+    let opts = BackupOptions::default()
+        .as_path(PathBuf::from_str("test")?)
+        .excludes(Excludes::default().globs(vec!["!*.tmp".to_string()]))
+        .ignore_filter_opts(
+            LocalSourceFilterOptions::default()
+                .exclude_if_present(vec!["CACHEDIR.TAG".to_string()]),
+        );
+    let snap = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+
+    // with the filter of the backup, nothing changed
+    let entries = repo
+        .diff_with_local_filtered(
+            &snap,
+            root.path(),
+            ChangeDetection::SizeMtime,
+            &opts.filter()?,
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(entries, Vec::new());
+
+    // without filter, the excluded entries are reported as added
+    let added: Vec<_> = repo
+        .diff_with_local(&snap, root.path(), ChangeDetection::SizeMtime)?
+        .map(|entry| entry.map(|entry| (entry.path, entry.change)))
+        .collect::<Result<_, _>>()?;
+    assert!(added.contains(&(PathBuf::from("test/a.tmp"), DiffChange::Added)));
+    assert!(added.contains(&(PathBuf::from("test/cache/c"), DiffChange::Added)));
+
+    Ok(())
+}
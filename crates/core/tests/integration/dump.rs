@@ -1,13 +1,19 @@
-use std::{fs, io::Write, path::PathBuf, str::FromStr};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::Result;
 use bytesize::ByteSize;
+use flate2::read::{DeflateDecoder, GzDecoder};
 use pretty_assertions::assert_eq;
 use rstest::rstest;
 use tempfile::tempdir;
 
 use rustic_core::{
-    BackupOptions, ConfigOptions, IndexedFullStatus, PathList, Repository,
+    ArchiveFormat, BackupOptions, ConfigOptions, IndexedFullStatus, PathList, Repository,
     repofile::{Chunker, SnapshotFile},
 };
 
@@ -78,3 +84,71 @@ fn test_dump_default_options_match_source(set_up_repo: Result<RepoOpen>) -> Resu
     assert_eq!(out, data);
     Ok(())
 }
+
+#[rstest]
+fn test_restore_to_writer_matches_source(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let data = payload(64 * 1024);
+    let (repo, snapshot_path) = backup_single_file(set_up_repo?, "file.bin", &data)?;
+    let node = repo.node_from_snapshot_path(&snapshot_path, |_| true)?;
+
+    let out = repo.restore_to_writer(&node, Vec::new())?;
+    assert_eq!(out, data);
+
+    // directories can't be restored into a writer
+    let dir = repo.node_from_snapshot_path("latest", |_| true)?;
+    assert!(repo.restore_to_writer(&dir, Vec::new()).is_err());
+    Ok(())
+}
+
+/// Read the content of the file `name` from a tar archive
+fn read_from_tar(archive: impl Read, name: &str) -> Result<Vec<u8>> {
+    for entry in tar::Archive::new(archive).entries()? {
+        let mut entry = entry?;
+        if entry.path()? == Path::new(name) {
+            let mut content = Vec::new();
+            _ = entry.read_to_end(&mut content)?;
+            return Ok(content);
+        }
+    }
+    anyhow::bail!("{name} not found in archive")
+}
+
+#[rstest]
+fn test_dump_archive(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let data = payload(64 * 1024);
+    let (repo, _) = backup_single_file(set_up_repo?, "file.bin", &data)?;
+    let snap = repo.get_all_snapshots()?.remove(0);
+
+    let mut tar = Vec::new();
+    repo.dump_archive(&snap, "", ArchiveFormat::Tar, &mut tar)?;
+    assert_eq!(read_from_tar(&tar[..], "file.bin")?, data);
+
+    let mut tar_gz = Vec::new();
+    repo.dump_archive(&snap, "", ArchiveFormat::TarGz, &mut tar_gz)?;
+    assert_eq!(
+        read_from_tar(GzDecoder::new(&tar_gz[..]), "file.bin")?,
+        data
+    );
+
+    let mut zip = Vec::new();
+    repo.dump_archive(&snap, "", ArchiveFormat::Zip, &mut zip)?;
+    // local file header, followed by the name and the deflated content
+    assert_eq!(&zip[..4], b"PK\x03\x04");
+    let name_len = usize::from(u16::from_le_bytes([zip[26], zip[27]]));
+    let extra_len = usize::from(u16::from_le_bytes([zip[28], zip[29]]));
+    assert_eq!(&zip[30..30 + name_len], b"file.bin");
+    let mut content = Vec::new();
+    _ = DeflateDecoder::new(&zip[30 + name_len + extra_len..]).read_to_end(&mut content)?;
+    assert_eq!(content, data);
+    // end of central directory record containing a single entry
+    let end = &zip[zip.len() - 22..];
+    assert_eq!(&end[..4], b"PK\x05\x06");
+    assert_eq!(u16::from_le_bytes([end[10], end[11]]), 1);
+
+    // non-existing paths give an error
+    assert!(
+        repo.dump_archive(&snap, "not_existing", ArchiveFormat::Tar, &mut Vec::new())
+            .is_err()
+    );
+    Ok(())
+}
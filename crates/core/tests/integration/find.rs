@@ -8,7 +8,7 @@ use globset::Glob;
 use rstest::rstest;
 
 use rustic_core::{
-    BackupOptions, FindMatches, FindNode,
+    BackupOptions, FindMatches, FindNode, SnapshotOptions,
     repofile::{Node, SnapshotFile},
 };
 
@@ -59,3 +59,52 @@ fn test_find(tar_gz_testdata: Result<TestSource>, set_up_repo: Result<RepoOpen>)
     assert_with_win("find-matching-wildcard-existing", (paths, matches));
     Ok(())
 }
+
+#[rstest]
+fn test_find_in_snapshots(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    // Fixtures
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let paths = &source.path_list();
+
+    // back up the same data twice, so that all trees are shared
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap1 = repo.backup(&opts, paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    let snap = SnapshotOptions::default()
+        .label("other".to_string())
+        .to_snapshot()?;
+    let snap2 = repo.backup(&opts, paths, snap)?;
+    let repo = repo.to_indexed_ids()?;
+
+    let found = repo.find("testfile", |_| true)?;
+    let single = repo.find_matching_nodes(vec![snap1.tree], &|path, _| {
+        path.file_name().is_some_and(|f| f == "testfile")
+    })?;
+    assert!(!single.paths.is_empty());
+    assert_eq!(found.len(), 2 * single.paths.len());
+    for id in [snap1.id, snap2.id] {
+        let mut found_paths: Vec<_> = found
+            .iter()
+            .filter(|f| f.snapshot == id)
+            .map(|f| f.path.clone())
+            .collect();
+        found_paths.sort();
+        let mut expected = single.paths.clone();
+        expected.sort();
+        assert_eq!(found_paths, expected);
+    }
+
+    // only search the filtered snapshots
+    let found = repo.find("testfile", |sn| sn.label == "other")?;
+    assert!(found.iter().all(|f| f.snapshot == snap2.id));
+
+    // patterns containing a slash match the path
+    let found = repo.find("test/0/tests/testfile", |_| true)?;
+    assert_eq!(found.len(), 2);
+
+    assert!(repo.find("[", |_| true).is_err());
+    Ok(())
+}
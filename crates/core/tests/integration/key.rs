@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use rustic_core::{
-    Credentials, KeyOptions,
+    Credentials, FileType, KeyOptions,
     repofile::{KeyFile, KeyId},
 };
 
@@ -52,11 +52,25 @@ fn test_key_commands(set_up_repo: Result<RepoOpen>) -> Result<()> {
     assert_eq!(found_keys.len(), 1);
     assert_eq!(&found_keys[0], keyfile2);
 
+    // list the keys with their metadata
+    let key_infos = repo.list_keys()?;
+    assert_eq!(key_infos.len(), 2);
+    let info = key_infos.iter().find(|info| info.id == key_id).unwrap();
+    assert!(info.current);
+    let info2 = key_infos.iter().find(|info| info.id == key_id2).unwrap();
+    assert!(!info2.current);
+    assert_eq!(info2.hostname, Some("my_host".to_string()));
+    assert_eq!(info2.username, Some("my_user".to_string()));
+    assert!(info2.created.is_some());
+
     // try to remove the used repository key - which should fail
-    assert!(repo.delete_key(&key_id).is_err());
+    assert!(repo.remove_key(&key_id).is_err());
 
     // try to remove the added key
-    repo.delete_key(&key_id2)?;
+    repo.remove_key(&key_id2)?;
+
+    // removing it again fails as it doesn't exist anymore
+    assert!(repo.remove_key(&key_id2).is_err());
 
     // we should have just a single key now
     let keys: Vec<KeyId> = repo.list()?.collect();
@@ -64,3 +78,80 @@ fn test_key_commands(set_up_repo: Result<RepoOpen>) -> Result<()> {
 
     Ok(())
 }
+
+#[rstest]
+fn test_remove_last_key(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    // the repository is opened by the master key, so the only key file is not the current key
+    let repo = set_up_repo?;
+    let key_id = repo.add_key("test", &KeyOptions::default())?;
+    assert!(repo.remove_key(&key_id).is_err());
+
+    let keys: Vec<KeyId> = repo.list()?.collect();
+    assert_eq!(&keys, &[key_id]);
+    Ok(())
+}
+
+#[rstest]
+fn test_change_password(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?;
+    let key_id = repo.add_key("test", &KeyOptions::default())?;
+    let mut repo = repo.open(&Credentials::password("test"))?;
+
+    let new_key_id = repo.change_password("new_pass", &KeyOptions::default())?;
+    assert_ne!(key_id, new_key_id);
+    assert_eq!(*repo.key_id(), Some(new_key_id));
+
+    // the old key is removed
+    let keys: Vec<KeyId> = repo.list()?.collect();
+    assert_eq!(&keys, &[new_key_id]);
+
+    // re-open with the new password
+    let repo = repo.open(&Credentials::password("new_pass"))?;
+    assert_eq!(*repo.key_id(), Some(new_key_id));
+    assert!(repo.open(&Credentials::password("test")).is_err());
+    Ok(())
+}
+
+#[rstest]
+fn test_rekey_kdf(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?;
+    let key_id = repo.add_key("test", &KeyOptions::default())?;
+    let mut repo = repo.open(&Credentials::password("test"))?;
+
+    // invalid parameters and wrong passwords are rejected
+    let opts = KeyOptions::default().kdf_log_n(0_u8).kdf_r(0_u32);
+    assert!(repo.rekey_kdf("test", &opts).is_err());
+    let opts = KeyOptions::default()
+        .kdf_log_n(10_u8)
+        .kdf_r(4_u32)
+        .kdf_p(2_u32);
+    assert!(repo.rekey_kdf("wrong", &opts).is_err());
+
+    let new_key_id = repo.rekey_kdf("test", &opts)?;
+    assert_ne!(key_id, new_key_id);
+    let keys: Vec<KeyId> = repo.list()?.collect();
+    assert_eq!(&keys, &[new_key_id]);
+    let keyfile: KeyFile = repo.get_file(&new_key_id)?;
+    assert_eq!((keyfile.n, keyfile.r, keyfile.p), (1024, 4, 2));
+
+    // the password still works
+    let repo = repo.open(&Credentials::password("test"))?;
+    assert_eq!(*repo.key_id(), Some(new_key_id));
+    Ok(())
+}
+
+#[rstest]
+fn test_cat_key_file(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?;
+    let key_id = repo.add_key("test", &KeyOptions::default())?;
+
+    // key files are stored unencrypted and returned as JSON
+    let data = repo.cat_file(FileType::Key, &key_id.to_string())?;
+    let keyfile: KeyFile = serde_json::from_slice(&data)?;
+    assert_eq!(keyfile, repo.get_file::<KeyFile>(&key_id)?);
+    assert_eq!(repo.cat_file_by_id(FileType::Key, &key_id)?, data);
+
+    // pack files are not JSON
+    assert!(repo.cat_file_by_id(FileType::Pack, &key_id).is_err());
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use anyhow::Result;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+
+use rustic_core::{CheckOptions, QuarantineOptions, RepairIndexOptions};
+
+use super::{RepoOpen, set_up_repo};
+
+#[rstest]
+fn test_lock(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?;
+
+    // shared locks don't conflict with each other
+    let shared1 = repo.lock_shared()?;
+    let shared2 = repo.lock_shared()?;
+    assert!(!shared1.is_exclusive());
+    assert_eq!(repo.list_locks()?.len(), 2);
+    assert!(repo.lock_exclusive().is_err());
+    // a failed lock attempt doesn't leave a lock file
+    assert_eq!(repo.list_locks()?.len(), 2);
+
+    // locks are released when dropped or unlocked
+    drop(shared1);
+    shared2.unlock()?;
+    assert!(repo.list_locks()?.is_empty());
+
+    let mut exclusive = repo.lock_exclusive()?;
+    assert!(exclusive.is_exclusive());
+    assert!(repo.lock_shared().is_err());
+    assert!(repo.lock_exclusive().is_err());
+
+    // refreshing replaces the lock file
+    let old_id = exclusive.id();
+    exclusive.refresh()?;
+    assert_ne!(exclusive.id(), old_id);
+    let locks = repo.list_locks()?;
+    assert_eq!(locks.len(), 1);
+    assert_eq!(Some(locks[0].0), exclusive.id());
+
+    // locks of running processes are not stale
+    assert!(repo.remove_stale_locks()?.is_empty());
+
+    drop(exclusive);
+    assert!(repo.list_locks()?.is_empty());
+    _ = repo.lock_shared()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn test_exclusive_operation(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?;
+    let opts = RepairIndexOptions::default();
+
+    let guard = repo.exclusive_operation("test")?;
+    assert_eq!(guard.operation(), "test");
+    // the operation holds an exclusive lock
+    let locks = repo.list_locks()?;
+    assert_eq!(locks.len(), 1);
+    assert!(locks[0].1.exclusive);
+    assert!(repo.lock_shared().unwrap_err().is_busy());
+    assert!(repo.exclusive_operation("other").unwrap_err().is_busy());
+    assert!(repo.repair_index(&opts, true).unwrap_err().is_busy());
+    assert!(
+        repo.quarantine_unindexed_packs(&QuarantineOptions::default().dry_run(true))
+            .unwrap_err()
+            .is_busy()
+    );
+    // non-exclusive operations are not affected
+    _ = repo.check(CheckOptions::default())?;
+    drop(guard);
+    assert!(repo.list_locks()?.is_empty());
+
+    // exclusive locks of this process don't conflict
+    let lock = repo.lock_exclusive()?;
+    repo.repair_index(&opts, true)?;
+    lock.unlock()?;
+    assert!(repo.list_locks()?.is_empty());
+
+    Ok(())
+}
@@ -50,3 +50,50 @@ fn test_ls(
 
     Ok(())
 }
+
+#[rstest]
+fn test_ls_max_depth(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    // Fixtures
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let paths = &source.path_list();
+
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snapshot = repo.backup(&opts, paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+
+    let all: Vec<_> = repo
+        .ls_snapshot_path(&snapshot, "", &LsOptions::default())?
+        .map(|item| item.map(|(path, _)| path))
+        .collect::<RusticResult<_>>()?;
+    let limited: Vec<_> = repo
+        .ls_snapshot_path(&snapshot, "", &LsOptions::default().max_depth(2_usize))?
+        .map(|item| item.map(|(path, _)| path))
+        .collect::<RusticResult<_>>()?;
+
+    let expected: Vec<_> = all
+        .into_iter()
+        .filter(|path| path.components().count() <= 2)
+        .collect();
+    assert!(expected.len() > 1);
+    assert_eq!(limited, expected);
+
+    // listing a path within the snapshot returns paths relative to it
+    let entries: Vec<_> = repo
+        .ls_snapshot_path(&snapshot, "test", &LsOptions::default().max_depth(1_usize))?
+        .map(|item| item.map(|(path, _)| path))
+        .collect::<RusticResult<_>>()?;
+    assert_eq!(
+        entries.len(),
+        limited
+            .iter()
+            .filter(|path| path.starts_with("test"))
+            .count()
+            - 1
+    );
+    assert!(entries.iter().all(|path| path.components().count() == 1));
+
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use bytesize::ByteSize;
+use rstest::rstest;
+
+use rustic_core::{
+    BackupOptions, CheckOptions, ConfigOptions, Credentials, KeyOptions, MigrateOptions,
+    Repository, RepositoryBackends, RepositoryOptions,
+    repofile::{MasterKey, SnapshotFile},
+};
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
+
+use super::{TestSource, tar_gz_testdata};
+
+#[rstest]
+fn test_migrate(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default().set_version(1_u32),
+        )?
+        .to_indexed_ids()?;
+    _ = repo.backup(
+        &BackupOptions::default(),
+        &source.path_list(),
+        SnapshotFile::default(),
+    )?;
+    let mut repo = repo.drop_index();
+
+    // all packs of a v1 repository need to be repacked
+    let estimate = repo.migration_estimate(2)?;
+    assert_eq!((estimate.from_version, estimate.to_version), (1, 2));
+    assert!(estimate.packs > 0);
+    assert_eq!(estimate.size, estimate.total_size);
+    assert!(!estimate.is_done());
+    assert!(estimate.estimated_duration(ByteSize::b(0)).is_none());
+    assert!(estimate.estimated_duration(ByteSize::b(1)).is_some());
+
+    // unsupported versions are rejected
+    assert!(repo.migration_estimate(3).is_err());
+
+    let report = repo.migrate(&MigrateOptions::default().instant_delete(true))?;
+    assert!(report.version_changed);
+    assert!(report.prune.is_some());
+    assert!(report.remaining.is_done());
+    assert_eq!(repo.config().version, 2);
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    // downgrading is not possible and migrating again has nothing to do
+    assert!(repo.migration_estimate(1).is_err());
+    let report = repo.migrate(&MigrateOptions::default())?;
+    assert!(!report.version_changed);
+    assert!(report.prune.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::{path::PathBuf, str::FromStr, sync::Arc};
+
+use anyhow::Result;
+use rstest::rstest;
+
+use rustic_core::{
+    BackupOptions, ConfigOptions, Credentials, FileType, KeyOptions, MirrorBackend, MirrorMode,
+    ReadBackend, Repository, RepositoryBackends, RepositoryOptions, WriteBackend,
+    repofile::SnapshotFile,
+};
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
+
+use super::{TestSource, tar_gz_testdata};
+
+#[rstest]
+#[case(MirrorMode::Sync)]
+#[case(MirrorMode::Async { queue_size: 4 })]
+fn mirror(tar_gz_testdata: Result<TestSource>, #[case] mode: MirrorMode) -> Result<()> {
+    // Fixtures
+    let source = tar_gz_testdata?;
+
+    let be_mirror = Arc::new(InMemoryBackend::new());
+    let be = Arc::new(MirrorBackend::new(
+        Arc::new(InMemoryBackend::new()),
+        be_mirror.clone(),
+        mode,
+    ));
+    let backends = RepositoryBackends::new(be.clone(), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &backends)?;
+    let repo = repo
+        .init(
+            &Credentials::password("test"),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+    repo.delete_snapshots(&[snapshot.id])?;
+
+    let status = be.flush();
+    assert!(status.is_consistent());
+    assert_eq!(status.removed, 1);
+    for tpe in [
+        FileType::Config,
+        FileType::Key,
+        FileType::Snapshot,
+        FileType::Index,
+        FileType::Pack,
+    ] {
+        assert!(be.compare(tpe)?.is_consistent());
+    }
+    assert!(be_mirror.list(FileType::Snapshot)?.is_empty());
+    assert!(!be_mirror.list(FileType::Pack)?.is_empty());
+
+    // files missing in the mirror are reported
+    let id = be.list(FileType::Index)?[0];
+    be_mirror.remove(FileType::Index, &id, true)?;
+    assert_eq!(be.compare(FileType::Index)?.missing, vec![id]);
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+use tempfile::tempdir;
+
+use rustic_core::{
+    BackupOptions, ConfigOptions, Credentials, KeyOptions, PrewarmHint, Repository,
+    RepositoryBackends, RepositoryOptions, repofile::SnapshotFile,
+};
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
+
+use super::{TestSource, tar_gz_testdata};
+
+#[rstest]
+fn test_prewarm_cache(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let creds = Credentials::password("test");
+
+    let repo = Repository::new(&RepositoryOptions::default().no_cache(true), &be)?
+        .init(&creds, &KeyOptions::default(), &ConfigOptions::default())?
+        .to_indexed_ids()?;
+    let snapshot = repo.backup(
+        &BackupOptions::default(),
+        &source.path_list(),
+        SnapshotFile::default(),
+    )?;
+
+    // without cache, nothing is prewarmed
+    let stats = repo.prewarm_cache(&PrewarmHint::AllTrees)?;
+    assert_eq!(stats.downloaded, 0);
+
+    // open the repository with an empty cache
+    let cache_dir = tempdir()?;
+    let opts = RepositoryOptions::default().cache_dir(cache_dir.path().to_path_buf());
+    let repo = Repository::new(&opts, &be)?.open(&creds)?;
+
+    let stats = repo.prewarm_cache(&PrewarmHint::Index)?;
+    assert!(stats.downloaded > 0);
+    let index_files = stats.downloaded;
+
+    let hint = PrewarmHint::SnapshotTrees(vec![snapshot.id]);
+    let stats = repo.prewarm_cache(&hint)?;
+    assert_eq!(stats.cached, index_files);
+    assert!(stats.downloaded > 0);
+
+    // all needed files are cached now
+    let again = repo.prewarm_cache(&hint)?;
+    assert_eq!(again.downloaded, 0);
+    assert_eq!(again.cached, stats.cached + stats.downloaded);
+
+    Ok(())
+}
@@ -1,12 +1,18 @@
 use anyhow::Result;
 use bytesize::ByteSize;
-use jiff::Span;
+use jiff::{SignedDuration, Span, Timestamp, Zoned};
 use rstest::rstest;
 
+use std::{collections::BTreeSet, sync::Arc};
+
 use rustic_core::{
-    BackupOptions, CheckOptions, ConfigOptions, LimitOption, PathList, PruneOptions,
-    repofile::{Chunker, SnapshotFile},
+    BackupOptions, BlobId, CheckOptions, ConfigOptions, Credentials, FileType, Id, IndexGcOptions,
+    KeepOptions, KeyOptions, LimitOption, MaintenanceOptions, PathList, PruneOptions,
+    QuarantineOptions, ReadBackend, Repository, RepositoryBackends, RepositoryOptions,
+    RusticResult, WriteBackend,
+    repofile::{BlobType, Chunker, DeleteOption, IndexId, MasterKey, PackId, SnapshotFile},
 };
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
 
 use super::{RepoOpen, TestSource, set_up_repo, tar_gz_testdata};
 
@@ -80,3 +86,551 @@ fn test_prune(
 
     Ok(())
 }
+
+#[rstest]
+fn test_gc_index(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+
+    let snapshot = repo.backup(
+        &BackupOptions::default(),
+        &source.path_list(),
+        SnapshotFile::default(),
+    )?;
+
+    // remove the only snapshot and mark all packs for deletion
+    let repo = repo.drop_index();
+    repo.delete_snapshots(&[snapshot.id])?;
+    let prune_opts = PruneOptions::default();
+    let plan = repo.prune_plan(&prune_opts)?;
+    repo.prune(&prune_opts, plan)?;
+
+    // all marked packs still exist
+    let gc_opts = IndexGcOptions::default();
+    assert_eq!(repo.gc_index(&gc_opts, false)?.stale_packs, 0);
+
+    // delete the packs without removing them from the index
+    let packs = be.repository().list(FileType::Pack)?;
+    for id in &packs {
+        be.repository().remove(FileType::Pack, id, false)?;
+    }
+
+    let stats = repo.gc_index(&gc_opts, true)?;
+    assert_eq!(stats.stale_packs, packs.len() as u64);
+    assert!(repo.list::<IndexId>()?.next().is_some());
+
+    let stats = repo.gc_index(&gc_opts, false)?;
+    assert_eq!(stats.stale_packs, packs.len() as u64);
+    assert_eq!(stats.index_files_rewritten, 0);
+    assert_eq!(repo.list::<IndexId>()?.count(), 0);
+    assert_eq!(repo.gc_index(&gc_opts, false)?.stale_packs, 0);
+
+    repo.check(CheckOptions::default())?.is_ok()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn test_prune_index_sequence(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default();
+
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+    let snapshot1 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9/2")));
+    _ = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.drop_index();
+    repo.delete_snapshots(&[snapshot1.id])?;
+
+    let prune_opts = PruneOptions::default()
+        .max_unused(LimitOption::Percentage(0))
+        .keep_delete(Span::default())
+        .index_sequence(true);
+
+    // two racing prunes which computed their plan from the same index
+    let plan1 = repo.prune_plan(&prune_opts)?;
+    let plan2 = repo.prune_plan(&prune_opts)?;
+    repo.prune(&prune_opts, plan1)?;
+    // the second prune detects that the index has been rewritten and refuses to remove anything
+    assert!(repo.prune(&prune_opts, plan2).is_err());
+
+    // a fresh prune works
+    let plan = repo.prune_plan(&prune_opts)?;
+    repo.prune(&prune_opts, plan)?;
+    repo.check(CheckOptions::default())?.is_ok()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn test_prune_only_packs(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default();
+
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+    let snapshot1 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9/2")));
+    _ = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.drop_index();
+    repo.delete_snapshots(&[snapshot1.id])?;
+    let packs: Vec<PackId> = repo.list()?.collect();
+
+    let prune_opts = PruneOptions::default()
+        .repack_all(true)
+        .max_repack(LimitOption::Unlimited)
+        .instant_delete(true);
+
+    // without restriction, all used packs are repacked
+    let plan = repo.prune_plan(&prune_opts)?;
+    let repack_packs = plan.repack_packs();
+    assert!(repack_packs.len() > 1);
+
+    // only the selected pack is repacked
+    let selected = repack_packs[0];
+    let prune_opts = prune_opts.only_packs(vec![selected]);
+    let plan = repo.prune_plan(&prune_opts)?;
+    assert_eq!(plan.repack_packs(), vec![selected]);
+    repo.prune(&prune_opts, plan)?;
+
+    let remaining: BTreeSet<PackId> = repo.list()?.collect();
+    assert!(!remaining.contains(&selected));
+    assert!(
+        packs
+            .iter()
+            .filter(|id| **id != selected)
+            .all(|id| remaining.contains(id))
+    );
+    repo.check(CheckOptions::default())?.is_ok()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn test_prune_object_lock(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new_with_object_lock()), None);
+    // packs are locked when they are written
+    let repo_opts =
+        RepositoryOptions::default().object_lock_pack_retention(SignedDuration::from_hours(1));
+    let repo = Repository::new(&repo_opts, &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+    let opts = BackupOptions::default();
+
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+    let snapshot1 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    // the second snapshot is locked until its delete option allows removing it
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9/2")));
+    let snap = SnapshotFile {
+        delete: DeleteOption::After(Zoned::now().checked_add(SignedDuration::from_hours(1))?),
+        ..Default::default()
+    };
+    let snapshot2 = repo.backup(&opts, &paths, snap)?;
+
+    // snapshots are only locked by their delete option
+    let snapshot_lock = |id: &Id| be.repository().locked_until(FileType::Snapshot, id);
+    assert!(snapshot_lock(&*snapshot1.id)?.is_none());
+    assert!(snapshot_lock(&*snapshot2.id)?.is_some());
+
+    let repo = repo.drop_index();
+    assert!(repo.delete_snapshots(&[snapshot2.id]).is_err());
+    repo.delete_snapshots(&[snapshot1.id])?;
+
+    let packs = be.repository().list(FileType::Pack)?;
+    assert!(packs.iter().all(|id| {
+        be.repository()
+            .locked_until(FileType::Pack, id)
+            .is_ok_and(|until| until.is_some())
+    }));
+
+    let prune_opts = PruneOptions::default()
+        .instant_delete(true)
+        .max_unused(LimitOption::Percentage(0));
+    let plan = repo.prune_plan(&prune_opts)?;
+    assert!(plan.stats.packs_locked > 0);
+    repo.prune(&prune_opts, plan)?;
+
+    // locked packs are marked for deletion instead of being removed
+    let remaining: BTreeSet<_> = be.repository().list(FileType::Pack)?.into_iter().collect();
+    assert!(packs.iter().all(|id| remaining.contains(id)));
+    assert!(!repo.audit_deletions()?.pending.is_empty());
+    repo.check(CheckOptions::default())?.is_ok()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn test_prune_deletion_journal(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default();
+
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+    let snapshot1 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9/2")));
+    _ = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.drop_index();
+    repo.delete_snapshots(&[snapshot1.id])?;
+    assert!(repo.audit_deletions()?.pending.is_empty());
+
+    // prune marks packs for deletion and records them in a deletion journal
+    let prune_opts = PruneOptions::default().max_unused(LimitOption::Percentage(0));
+    let plan = repo.prune_plan(&prune_opts)?;
+    repo.prune(&prune_opts, plan)?;
+
+    let audit = repo.audit_deletions()?;
+    assert!(!audit.pending.is_empty());
+    assert_eq!(audit.journals.len(), 1);
+    let (journal_id, journal) = audit.journals.iter().next().unwrap();
+    assert_eq!(journal.options.max_unused, LimitOption::Percentage(0));
+    assert!(
+        audit
+            .pending
+            .iter()
+            .all(|pd| pd.journal == Some(*journal_id))
+    );
+    assert_eq!(audit.unjournaled().count(), 0);
+    assert!(audit.completed_journals().is_empty());
+
+    // cancel all pending deletions
+    let mut ids: Vec<_> = audit.pending.iter().map(|pd| pd.id).collect();
+    let mut cancelled = repo.cancel_deletions(&ids)?;
+    ids.sort_unstable();
+    cancelled.sort_unstable();
+    assert_eq!(cancelled, ids);
+
+    let audit = repo.audit_deletions()?;
+    assert!(audit.pending.is_empty());
+    assert_eq!(audit.completed_journals(), vec![*journal_id]);
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    // the completed journal is removed by the index garbage collection
+    let stats = repo.gc_index(&IndexGcOptions::default(), false)?;
+    assert_eq!(stats.journals_removed, 1);
+    assert!(repo.audit_deletions()?.journals.is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+fn test_recover_packs(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default();
+
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+    let snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    // prune with a deleted snapshot marks all its packs for deletion
+    let repo = repo.drop_index();
+    repo.delete_snapshots(&[snapshot.id])?;
+    let prune_opts = PruneOptions::default();
+    let plan = repo.prune_plan(&prune_opts)?;
+    repo.prune(&prune_opts, plan)?;
+    let pending = repo.audit_deletions()?.pending;
+    assert!(!pending.is_empty());
+
+    // the snapshot is needed after all
+    repo.save_snapshots(vec![snapshot])?;
+
+    let ids: Vec<_> = pending.iter().map(|pd| pd.id).collect();
+    let recovered = repo.recover_packs(&ids)?;
+    assert_eq!(
+        recovered.into_iter().collect::<BTreeSet<_>>(),
+        ids.into_iter().collect()
+    );
+    assert!(repo.audit_deletions()?.pending.is_empty());
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    // recovered packs are not marked anymore and are kept by the next prune
+    let plan = repo.prune_plan(&prune_opts)?;
+    assert_eq!(plan.stats.packs_to_delete.remove, 0);
+    assert_eq!(plan.stats.packs_to_delete.recover, 0);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_blob_references(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default();
+
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+    let snapshot1 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9/2")));
+    let snapshot2 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    // all blobs are referenced
+    let repo = repo.to_indexed_ids()?;
+    let refs: Vec<_> = repo.blob_references(1)?.collect::<RusticResult<_>>()?;
+    assert!(!refs.is_empty());
+    assert!(refs.iter().all(|blob| blob.reference_count > 0));
+    assert!(refs.iter().all(|blob| blob.snapshots.len() == 1));
+    let root = refs
+        .iter()
+        .find(|blob| *blob.id == *snapshot2.tree)
+        .expect("root tree should be contained");
+    assert_eq!(root.snapshots, vec![snapshot2.id]);
+
+    // after removing the first snapshot, its blobs are no longer referenced
+    repo.delete_snapshots(&[snapshot1.id])?;
+    let refs: Vec<_> = repo.blob_references(1)?.collect::<RusticResult<_>>()?;
+    let unused: u64 = refs
+        .iter()
+        .filter(|blob| blob.reference_count == 0)
+        .map(|blob| u64::from(blob.length))
+        .sum();
+    assert!(unused > 0);
+    assert!(
+        refs.iter()
+            .filter(|blob| blob.reference_count > 0)
+            .all(|blob| blob.snapshots == vec![snapshot2.id])
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn test_find_blob_origin(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default();
+
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+    let snapshot1 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9/2")));
+    let snapshot2 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+
+    // the root tree of the second snapshot
+    let origin = repo.find_blob_origin(&BlobId::from(*snapshot2.tree))?;
+    assert_eq!(origin.tpe, Some(BlobType::Tree));
+    assert_eq!(origin.packs.len(), 1);
+    assert!(
+        origin
+            .paths
+            .iter()
+            .any(|path| path.snapshot == snapshot2.id && path.path.as_os_str().is_empty())
+    );
+
+    // a data blob is referenced by files
+    let refs: Vec<_> = repo.blob_references(1)?.collect::<RusticResult<_>>()?;
+    let data = refs
+        .iter()
+        .find(|blob| blob.tpe == BlobType::Data)
+        .expect("data blob should be contained");
+    let origin = repo.find_blob_origin(&data.id)?;
+    assert_eq!(origin.tpe, Some(BlobType::Data));
+    assert_eq!(origin.packs, vec![data.pack]);
+    assert!(!origin.paths.is_empty());
+    assert!(origin.paths.iter().all(|path| {
+        [snapshot1.id, snapshot2.id].contains(&path.snapshot) && path.path.file_name().is_some()
+    }));
+
+    // unknown blobs have no origin
+    let origin = repo.find_blob_origin(&BlobId::from(Id::random()))?;
+    assert_eq!(origin.tpe, None);
+    assert!(origin.packs.is_empty() && origin.paths.is_empty());
+
+    Ok(())
+}
+
+#[rstest]
+fn test_quarantine_unindexed_packs(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let backend = Arc::new(InMemoryBackend::new());
+    let be = RepositoryBackends::new(backend.clone(), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+
+    _ = repo.backup(
+        &BackupOptions::default(),
+        &source.path_list(),
+        SnapshotFile::default(),
+    )?;
+    let repo = repo.drop_index();
+
+    // without unindexed packs, nothing is quarantined
+    let report = repo.quarantine_unindexed_packs(&QuarantineOptions::default())?;
+    assert!(report.quarantined.is_empty());
+    assert!(report.journal.is_none());
+
+    // save a pack which is not contained in the index, e.g. left by an aborted backup
+    let id = Id::random();
+    be.repository()
+        .write_bytes(FileType::Pack, &id, false, vec![0; 100].into())?;
+    let stray = PackId::from(id);
+
+    // a recent pack may belong to a running backup and is skipped
+    let report = repo.quarantine_unindexed_packs(&QuarantineOptions::default())?;
+    assert!(report.quarantined.is_empty());
+    assert_eq!(report.too_recent.len(), 1);
+    assert_eq!(report.too_recent[0].id, stray);
+    assert!(report.journal.is_none());
+    let modified = Timestamp::now().checked_sub(SignedDuration::from_hours(25))?;
+    backend.set_modified(FileType::Pack, &id, modified);
+
+    // a dry-run only reports the pack
+    let opts = QuarantineOptions::default().dry_run(true);
+    let report = repo.quarantine_unindexed_packs(&opts)?;
+    assert_eq!(report.quarantined.len(), 1);
+    assert_eq!(report.quarantined[0].id, stray);
+    assert_eq!(report.quarantined_size(), 100);
+    assert!(report.journal.is_none());
+    assert!(repo.audit_deletions()?.pending.is_empty());
+
+    // quarantine marks the pack for deletion and records it in a deletion journal; an exclusive lock
+    // held by this process doesn't conflict
+    let lock = repo.lock_exclusive()?;
+    let report = repo.quarantine_unindexed_packs(&QuarantineOptions::default())?;
+    assert_eq!(report.quarantined.len(), 1);
+    assert_eq!(report.quarantined[0].modified, Some(modified));
+    lock.unlock()?;
+    let journal = report.journal.unwrap();
+    let audit = repo.audit_deletions()?;
+    assert_eq!(audit.pending.len(), 1);
+    assert_eq!(audit.pending[0].id, stray);
+    assert_eq!(audit.pending[0].journal, Some(journal));
+
+    // the pack is kept until keep_delete has expired
+    let report = repo.quarantine_unindexed_packs(&QuarantineOptions::default())?;
+    assert!(report.quarantined.is_empty());
+    assert_eq!(report.pending.len(), 1);
+    assert_eq!(report.expired().count(), 0);
+    let opts = QuarantineOptions::default().keep_delete(Span::new());
+    let report = repo.quarantine_unindexed_packs(&opts)?;
+    assert_eq!(report.expired().count(), 1);
+
+    // prune removes the expired pack and the deletion journal recording it
+    let prune_opts = PruneOptions::default().keep_delete(Span::new());
+    let plan = repo.prune_plan(&prune_opts)?;
+    repo.prune(&prune_opts, plan)?;
+    assert!(!repo.list::<PackId>()?.any(|id| id == stray));
+    assert!(repo.audit_deletions()?.journals.is_empty());
+    repo.check(CheckOptions::default())?.is_ok()?;
+
+    Ok(())
+}
+
+#[rstest]
+fn test_maintain(tar_gz_testdata: Result<TestSource>, set_up_repo: Result<RepoOpen>) -> Result<()> {
+    // Fixtures
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+
+    // two backups of the same path within one snapshot group
+    let snapshot1 = repo.backup(&BackupOptions::default(), &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    let snapshot2 = repo.backup(&BackupOptions::default(), &paths, SnapshotFile::default())?;
+
+    let opts = MaintenanceOptions::default()
+        .forget(KeepOptions::default().keep_last(1))
+        .prune(PruneOptions::default().instant_delete(true))
+        .check(CheckOptions::default())
+        .cleanup_cache(true);
+    let report = repo.maintain(&opts)?;
+
+    assert_eq!(report.forgotten, vec![snapshot1.id]);
+    let snaps = repo.get_all_snapshots()?;
+    assert_eq!(snaps.len(), 1);
+    assert_eq!(snaps[0].id, snapshot2.id);
+    assert!(report.prune.is_some());
+    report.check.unwrap().is_ok()?;
+
+    // without options, nothing is done
+    let report = repo.maintain(&MaintenanceOptions::default())?;
+    assert!(report.forgotten.is_empty());
+    assert!(report.prune.is_none());
+    assert!(report.check.is_none());
+    assert_eq!(report.cache_files_removed, 0);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_pack_layout(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default();
+
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9")));
+    let snapshot1 = repo.backup(&opts, &paths, SnapshotFile::default())?;
+    let repo = repo.to_indexed_ids()?;
+    let paths = PathList::from_iter(Some(source.0.path().join("0/0/9/2")));
+    let _ = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.drop_index();
+    let report = repo.pack_layout()?;
+    assert!(!report.packs.is_empty());
+    assert_eq!(report.fragmented_size(), 0);
+    assert_eq!(report.mixed_packs(), 0);
+
+    // removing the first snapshot leaves partly used packs
+    repo.delete_snapshots(&[snapshot1.id])?;
+    let report = repo.pack_layout()?;
+    assert!(report.fragmented_size() > 0);
+    assert!(report.fragmentation() > 0.0 && report.fragmentation() < 1.0);
+    assert!(
+        report
+            .packs
+            .iter()
+            .any(|pack| pack.is_partly_used() && pack.fill_ratio() < 1.0)
+    );
+
+    // after repacking, no fragmentation is left
+    let prune_opts = PruneOptions::default()
+        .instant_delete(true)
+        .max_unused(LimitOption::Percentage(0))
+        .max_repack(LimitOption::Unlimited);
+    let plan = repo.prune_plan(&prune_opts)?;
+    repo.prune(&prune_opts, plan)?;
+    let report = repo.pack_layout()?;
+    assert_eq!(report.fragmented_size(), 0);
+    assert!(report.packs.iter().all(|pack| pack.unused_blobs == 0));
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::{path::PathBuf, str::FromStr, sync::Arc};
+
+use anyhow::Result;
+use bytesize::ByteSize;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+
+use rustic_core::{
+    BackupOptions, CheckOptions, ConfigOptions, Credentials, KeyOptions, LimitOption, LsOptions,
+    PruneOptions, RekeyOptions, RekeyState, Repository, RepositoryBackends, RepositoryOptions,
+    repofile::{KeyId, SnapshotFile},
+};
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
+
+use super::{TestSource, tar_gz_testdata};
+
+#[rstest]
+fn test_rekey(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let credentials = Credentials::password("test");
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &credentials,
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+    let old_keys: Vec<KeyId> = repo.list()?.collect();
+    let ls = |repo: &Repository<_>| -> Result<Vec<_>> {
+        let node = repo.node_from_snapshot_path("latest", |_| true)?;
+        Ok(repo
+            .ls(&node, &LsOptions::default())?
+            .map(|item| item.map(|(path, node)| (path, node.content)))
+            .collect::<Result<Vec<_>, _>>()?)
+    };
+    let contents = ls(&repo.to_indexed()?)?;
+
+    let mut repo = Repository::new(&RepositoryOptions::default(), &be)?.open(&credentials)?;
+    let mut state = repo.start_rekey()?;
+
+    // re-encrypt only a part of the packs; the repository stays usable
+    let opts = RekeyOptions::default().max_repack(LimitOption::Size(ByteSize(1)));
+    let remaining = repo.rekey(&mut state, &opts)?;
+    assert!(remaining > 0);
+    assert_eq!(state.packs_done(), 1);
+    assert!(
+        repo.finish_rekey(&state, "new", &KeyOptions::default())
+            .is_err()
+    );
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    // pruning would remove the re-encrypted packs, so it is refused until the re-encryption is finished
+    let other = Repository::new(&RepositoryOptions::default(), &be)?.open(&credentials)?;
+    let prune_opts = PruneOptions::default();
+    assert!(
+        other
+            .prune(&prune_opts, other.prune_plan(&prune_opts)?)
+            .is_err()
+    );
+
+    // the state can be saved and continued later
+    let mut state: RekeyState = serde_json::from_str(&serde_json::to_string(&state)?)?;
+    assert_eq!(repo.rekey(&mut state, &RekeyOptions::default())?, 0);
+    let key_id = repo.finish_rekey(&state, "new", &KeyOptions::default())?;
+
+    // the old password doesn't work anymore, but the new does
+    assert!(
+        Repository::new(&RepositoryOptions::default(), &be)?
+            .open(&credentials)
+            .is_err()
+    );
+    let repo =
+        Repository::new(&RepositoryOptions::default(), &be)?.open(&Credentials::password("new"))?;
+    assert_eq!(repo.list::<KeyId>()?.collect::<Vec<_>>(), vec![key_id]);
+    assert!(!old_keys.contains(&key_id));
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    // finishing again doesn't change anything
+    assert_eq!(
+        repo.finish_rekey(&state, "new", &KeyOptions::default())?,
+        key_id
+    );
+    assert_eq!(repo.list::<KeyId>()?.collect::<Vec<_>>(), vec![key_id]);
+    repo.prune(&prune_opts, repo.prune_plan(&prune_opts)?)?;
+
+    let snaps = repo.get_all_snapshots()?;
+    assert_eq!(snaps.len(), 1);
+    assert_ne!(snaps[0].id, snapshot.id);
+    assert_eq!(snaps[0].tree, snapshot.tree);
+    assert_eq!(ls(&repo.to_indexed()?)?, contents);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_reencrypt(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let mut repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::password("test"),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let mut state = repo.start_rekey()?;
+    // re-encrypt one pack per batch and save the state after each batch
+    let opts = RekeyOptions::default().max_repack(LimitOption::Size(ByteSize(1)));
+    let mut saved = Vec::new();
+    let key_id = repo.reencrypt(&mut state, &opts, "new", &KeyOptions::default(), |state| {
+        saved.push(state.packs_done());
+        Ok(())
+    })?;
+    assert!(saved.len() > 1);
+    assert_eq!(saved, (1..=saved.len()).collect::<Vec<_>>());
+
+    let repo =
+        Repository::new(&RepositoryOptions::default(), &be)?.open(&Credentials::password("new"))?;
+    assert_eq!(repo.list::<KeyId>()?.collect::<Vec<_>>(), vec![key_id]);
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+    let snaps = repo.get_all_snapshots()?;
+    assert_eq!(snaps.len(), 1);
+    assert_eq!(snaps[0].tree, snapshot.tree);
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use anyhow::Result;
+use bytesize::ByteSize;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+
+use rustic_core::{
+    BackupOptions, CheckOptions, ConfigOptions, Credentials, FileType, KeyOptions, ReadBackend,
+    RepairIndexOptions, Repository, RepositoryBackends, RepositoryOptions, WriteBackend,
+    repofile::{IndexId, MasterKey, SnapshotFile},
+};
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
+
+use super::{TestSource, tar_gz_testdata};
+
+#[rstest]
+fn test_repair_index_adopt_orphans(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+    let _ = repo.backup(
+        &BackupOptions::default(),
+        &source.path_list(),
+        SnapshotFile::default(),
+    )?;
+    let repo = repo.drop_index();
+
+    // simulate a backup which failed before writing the index
+    for id in be.repository().list(FileType::Index)? {
+        be.repository().remove(FileType::Index, &id, false)?;
+    }
+    assert!(repo.check(CheckOptions::default())?.is_ok().is_err());
+
+    let opts = RepairIndexOptions::default().adopt_orphans(true);
+    repo.repair_index(&opts, true)?;
+    assert_eq!(repo.list::<IndexId>()?.count(), 0);
+
+    repo.repair_index(&opts, false)?;
+    assert!(repo.list::<IndexId>()?.count() > 0);
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    // all packs are indexed now, so adopting again doesn't change anything
+    let index_files: BTreeSet<IndexId> = repo.list()?.collect();
+    repo.repair_index(&opts, false)?;
+    assert_eq!(
+        repo.list::<IndexId>()?.collect::<BTreeSet<_>>(),
+        index_files
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn test_repair_index_aligned_packs(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default().set_pack_alignment(ByteSize::kib(4)),
+        )?
+        .to_indexed_ids()?;
+    assert_eq!(repo.config().pack_alignment, Some(4096));
+    let _ = repo.backup(
+        &BackupOptions::default(),
+        &source.path_list(),
+        SnapshotFile::default(),
+    )?;
+    let repo = repo.drop_index();
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    // all packs end with the footer of the aligned layout
+    for (id, size) in be.repository().list_with_size(FileType::Pack)? {
+        let end = be
+            .repository()
+            .read_partial(FileType::Pack, &id, false, size - 4, 4)?;
+        assert_eq!(&end[..], b"RPK3");
+    }
+
+    // the pack headers can be read to rebuild the index
+    for id in be.repository().list(FileType::Index)? {
+        be.repository().remove(FileType::Index, &id, false)?;
+    }
+    let opts = RepairIndexOptions::default().adopt_orphans(true);
+    repo.repair_index(&opts, false)?;
+    repo.check(CheckOptions::default().read_data(true))?
+        .is_ok()?;
+
+    Ok(())
+}
@@ -1,13 +1,20 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, path::PathBuf, str::FromStr, sync::Arc};
 
 use anyhow::Result;
 use insta::{Settings, assert_ron_snapshot};
+use pretty_assertions::assert_eq;
 use rstest::rstest;
 use tempfile::tempdir;
 
-use rustic_core::{CheckOptions, LsOptions, RepairSnapshotsOptions, RusticResult};
+use rustic_core::{
+    BackupOptions, CheckOptions, ConfigOptions, Credentials, FileType, KeyOptions, LsOptions,
+    RepairIndexOptions, RepairSnapshotsOptions, Repository, RepositoryBackends, RepositoryOptions,
+    RusticResult, WriteBackend,
+    repofile::{BlobType, IndexFile, MasterKey, SnapshotFile},
+};
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
 
-use crate::{insta_node_redaction, repo_from_fixture};
+use crate::{TestSource, insta_node_redaction, repo_from_fixture, tar_gz_testdata};
 
 #[rstest]
 // #[case("repo-data-missing.tar.gz")]
@@ -26,7 +33,22 @@ fn test_repair_snapshots(#[case] repo_file: &str, insta_node_redaction: Settings
     let opts = RepairSnapshotsOptions::default()
         .delete(true)
         .suffix(".repaired");
-    repo.repair_snapshots(&opts, snapshots, false)?;
+    let dry_run_report = repo.repair_snapshots(&opts, snapshots.clone(), true)?;
+    assert!(dry_run_report.files_truncated > 0);
+    assert!(dry_run_report.trees_repaired > 0);
+    assert!(dry_run_report.bytes_repacked > 0);
+    assert!(!dry_run_report.snapshots_modified.is_empty());
+    assert!(
+        dry_run_report
+            .snapshots_modified
+            .iter()
+            .all(|id| dry_run_report.snapshots_to_delete.contains(id))
+    );
+    // dry-run doesn't modify the repository
+    assert_eq!(repo.get_all_snapshots()?.len(), snapshots.len());
+
+    let report = repo.repair_snapshots(&opts, snapshots, false)?;
+    assert_eq!(report, dry_run_report);
 
     // reread index
     let repo = repo.to_indexed()?;
@@ -45,3 +67,53 @@ fn test_repair_snapshots(#[case] repo_file: &str, insta_node_redaction: Settings
 
     Ok(())
 }
+
+#[rstest]
+fn test_repair_snapshots_with_fallback(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let paths = &source.path_list();
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+
+    let mut repos = Vec::new();
+    for _ in 0..2 {
+        let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+        let repo = Repository::new(&RepositoryOptions::default(), &be)?
+            .init(
+                &Credentials::Masterkey(MasterKey::new()),
+                &KeyOptions::default(),
+                &ConfigOptions::default(),
+            )?
+            .to_indexed_ids()?;
+        let snap = repo.backup(&opts, paths, SnapshotFile::default())?;
+        repos.push((be, repo.drop_index(), snap));
+    }
+    let (_, fallback, _) = repos.pop().unwrap();
+    let (be, repo, snap) = repos.pop().unwrap();
+
+    // remove all data packs and the corresponding index entries
+    for index in repo.stream_files::<IndexFile>()? {
+        let (_, index) = index?;
+        for pack in index.packs {
+            if pack.blobs.iter().all(|blob| blob.tpe == BlobType::Data) {
+                be.repository().remove(FileType::Pack, &pack.id, false)?;
+            }
+        }
+    }
+    repo.repair_index(&RepairIndexOptions::default(), false)?;
+    let check_opts = CheckOptions::default().read_data(true);
+    assert!(repo.check(check_opts)?.is_ok().is_err());
+
+    let opts = RepairSnapshotsOptions::default().fallback_repo(Arc::new(fallback.to_indexed()?));
+    let repo = repo.to_indexed()?;
+    let report = repo.repair_snapshots(&opts, vec![snap.clone()], false)?;
+    assert!(report.blobs_substituted > 0);
+    assert_eq!(report.files_truncated, 0);
+    assert!(report.snapshots_modified.is_empty());
+
+    // all missing blobs have been taken from the fallback repository, so the snapshot is unchanged
+    let repo = repo.to_indexed()?;
+    repo.check(check_opts)?.is_ok()?;
+    assert_eq!(repo.get_all_snapshots()?, vec![snap]);
+
+    Ok(())
+}
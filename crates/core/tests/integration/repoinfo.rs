@@ -0,0 +1,34 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::Result;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+
+use rustic_core::{BackupOptions, repofile::SnapshotFile};
+
+use super::{RepoOpen, TestSource, set_up_repo, tar_gz_testdata};
+
+#[rstest]
+fn test_list_packs(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    _ = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let packs: Vec<_> = repo.list_packs()?.collect();
+    let infos = repo.infos_index()?;
+
+    let pack_count: u64 = infos.packs.iter().map(|info| info.count).sum();
+    let blob_count: u64 = infos.blobs.iter().map(|info| info.count).sum();
+    assert_eq!(packs.len(), usize::try_from(pack_count)?);
+    assert_eq!(
+        packs.iter().map(|pack| pack.blobs.len()).sum::<usize>(),
+        usize::try_from(blob_count)?
+    );
+    assert!(packs.iter().all(|pack| !pack.to_delete && pack.size > 0));
+    assert!(packs.is_sorted_by_key(|pack| pack.id));
+
+    Ok(())
+}
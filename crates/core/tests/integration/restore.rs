@@ -1,16 +1,27 @@
-use std::{fs, path::PathBuf, str::FromStr};
+use std::{
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 #[cfg(not(windows))]
 use std::os::unix::fs::MetadataExt;
 
 use anyhow::Result;
+use bytesize::ByteSize;
 use pretty_assertions::assert_eq;
 use rstest::rstest;
 use tempfile::tempdir;
 
 use rustic_core::{
-    BackupOptions, LocalDestination, LsOptions, RestoreOptions, repofile::SnapshotFile,
+    BackupOptions, ConfigOptions, Credentials, FileType, KeyOptions, LocalDestination, LsOptions,
+    PathList, ReadBackend, Repository, RepositoryBackends, RepositoryOptions, RestoreAction,
+    RestoreErrorPolicy, RestoreOptions, RestorePlan, RusticResult, WriteBackend,
+    repofile::{MasterKey, SnapshotFile},
 };
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
 
 use super::{RepoOpen, TestSource, set_up_repo, tar_gz_testdata};
 
@@ -41,20 +52,529 @@ fn test_restore_preserves_hardlinks(
     )?;
     let restore_opts = RestoreOptions::default();
     let plan = repo.prepare_restore(&restore_opts, ls.clone(), &dest, false)?;
-    repo.restore(plan, &restore_opts, ls, &dest)?;
+    repo.restore(plan, &restore_opts, ls.clone(), &dest)?;
 
     let hardlink = restore_dir.path().join("test/0/tests/testfile-hardlink");
     let linked = restore_dir.path().join("test/0/tests/testfile");
     let symlink = restore_dir.path().join("test/0/tests/testfile-symlink");
 
-    let hardlink_meta = fs::metadata(&hardlink)?;
-    let linked_meta = fs::metadata(&linked)?;
-    assert_eq!(hardlink_meta.dev(), linked_meta.dev());
-    assert_eq!(hardlink_meta.ino(), linked_meta.ino());
-    assert_eq!(hardlink_meta.nlink(), 2);
-    assert_eq!(linked_meta.nlink(), 2);
-    assert_eq!(fs::read_to_string(&hardlink)?, fs::read_to_string(&linked)?);
-    assert_eq!(fs::read_link(&symlink)?, PathBuf::from("testfile"));
+    let check_hardlink = || -> Result<()> {
+        let hardlink_meta = fs::metadata(&hardlink)?;
+        let linked_meta = fs::metadata(&linked)?;
+        assert_eq!(hardlink_meta.dev(), linked_meta.dev());
+        assert_eq!(hardlink_meta.ino(), linked_meta.ino());
+        assert_eq!(hardlink_meta.nlink(), 2);
+        assert_eq!(linked_meta.nlink(), 2);
+        assert_eq!(fs::read_to_string(&hardlink)?, fs::read_to_string(&linked)?);
+        assert_eq!(fs::read_link(&symlink)?, PathBuf::from("testfile"));
+        Ok(())
+    };
+    check_hardlink()?;
+
+    // an existing hardlink is recognized as unchanged
+    let plan = repo.prepare_restore(&restore_opts, ls.clone(), &dest, true)?;
+    assert!(
+        plan.diff
+            .entries
+            .iter()
+            .all(|e| e.path != PathBuf::from("test/0/tests/testfile-hardlink"))
+    );
+
+    // a file replacing the hardlink is re-linked
+    fs::remove_file(&hardlink)?;
+    fs::write(&hardlink, "replaced")?;
+    let plan = repo.prepare_restore(&restore_opts, ls.clone(), &dest, true)?;
+    let modified: Vec<_> = plan
+        .diff
+        .with_action(RestoreAction::Modify)
+        .map(|e| e.path.clone())
+        .collect();
+    assert!(modified.contains(&PathBuf::from("test/0/tests/testfile-hardlink")));
+
+    let plan = repo.prepare_restore(&restore_opts, ls.clone(), &dest, false)?;
+    repo.restore(plan, &restore_opts, ls, &dest)?;
+    check_hardlink()?;
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(not(windows))]
+fn test_restore_dry_run_diff(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let _snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest", |_| true)?;
+    let ls = repo.ls(&node, &LsOptions::default())?;
+
+    let restore_dir = tempdir()?;
+    let dest = LocalDestination::new(
+        restore_dir
+            .path()
+            .to_str()
+            .expect("restore path is valid utf-8"),
+        true,
+        !node.is_dir(),
+    )?;
+
+    // nothing exists, so everything needs to be created
+    let restore_opts = RestoreOptions::default().delete(true);
+    let plan = repo.prepare_restore(&restore_opts, ls.clone(), &dest, true)?;
+    assert!(!plan.diff.entries.is_empty());
+    assert!(
+        plan.diff
+            .entries
+            .iter()
+            .all(|e| e.action == RestoreAction::Create)
+    );
+    assert_eq!(
+        plan.diff.with_action(RestoreAction::Create).count() as u64,
+        plan.stats.files.restore + plan.stats.dirs.restore
+    );
+
+    // restore and add an additional file which would be deleted
+    let plan = repo.prepare_restore(&restore_opts, ls.clone(), &dest, false)?;
+    assert!(plan.diff.entries.is_empty());
+    repo.restore(plan, &restore_opts, ls.clone(), &dest)?;
+    fs::write(restore_dir.path().join("additional"), "additional")?;
+
+    let plan = repo.prepare_restore(&restore_opts, ls, &dest, true)?;
+    // note that existing special files like symlinks are always removed and re-created
+    let deleted: Vec<_> = plan
+        .diff
+        .with_action(RestoreAction::Delete)
+        .map(|e| (e.path.clone(), e.is_dir))
+        .collect();
+    assert_eq!(
+        deleted,
+        vec![
+            (PathBuf::from("additional"), false),
+            (PathBuf::from("test/0/tests/testfile-symlink"), false)
+        ]
+    );
+    assert_eq!(plan.diff.with_action(RestoreAction::Create).count(), 0);
+
+    Ok(())
+}
+
+#[rstest]
+#[cfg(not(windows))]
+fn test_restore_sparse(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+
+    // a file consisting mainly of zeros
+    let source = tempdir()?;
+    let mut content = vec![0_u8; 4 * 1024 * 1024];
+    content.extend_from_slice(b"some non-zero content");
+    fs::write(source.path().join("sparse"), &content)?;
+
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let paths = PathList::from_iter(Some(source.path().to_path_buf()));
+    let _snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest", |_| true)?;
+    let ls = repo.ls(&node, &LsOptions::default())?;
+
+    let restore_dir = tempdir()?;
+    let dest = LocalDestination::new(
+        restore_dir
+            .path()
+            .to_str()
+            .expect("restore path is valid utf-8"),
+        true,
+        !node.is_dir(),
+    )?;
+    let restore_opts = RestoreOptions::default().sparse(true);
+    let plan = repo.prepare_restore(&restore_opts, ls.clone(), &dest, false)?;
+    repo.restore(plan, &restore_opts, ls, &dest)?;
+
+    let restored = restore_dir.path().join("test/sparse");
+    assert_eq!(fs::read(&restored)?, content);
+    // blocks are given in units of 512 bytes
+    assert!(fs::metadata(&restored)?.blocks() * 512 < content.len() as u64);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_restore_file_range(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+
+    // a pseudo-random file which is split into multiple blobs
+    let source = tempdir()?;
+    let mut state = 42_u64;
+    let content: Vec<u8> = (0..8 * 1024 * 1024)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            state.to_be_bytes()[0]
+        })
+        .collect();
+    fs::write(source.path().join("file"), &content)?;
+
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let paths = PathList::from_iter(Some(source.path().to_path_buf()));
+    let _snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest:test/file", |_| true)?;
+    assert!(node.content.as_ref().is_some_and(|c| c.len() > 1));
+
+    let restore_dir = tempdir()?;
+    let dest = LocalDestination::new(
+        restore_dir
+            .path()
+            .to_str()
+            .expect("restore path is valid utf-8"),
+        true,
+        false,
+    )?;
+    let range = 5 * 1024 * 1024..5 * 1024 * 1024 + 100;
+    let mut plan = RestorePlan::default();
+    plan.add_file_range(&repo, &node, "file", range.clone())?;
+    assert!(plan.restore_size < node.meta.size);
+    repo.restore(plan, &RestoreOptions::default(), std::iter::empty(), &dest)?;
+
+    let restored = fs::read(restore_dir.path().join("file"))?;
+    assert_eq!(restored.len(), content.len());
+    let range = usize::try_from(range.start)?..usize::try_from(range.end)?;
+    assert_eq!(restored[range.clone()], content[range]);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_restore_error_policy(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let _snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest", |_| true)?;
+    let nodes = repo
+        .ls(&node, &LsOptions::default())?
+        .collect::<RusticResult<Vec<_>>>()?;
+    let ls = || nodes.iter().cloned().map(Ok);
+
+    // corrupt all pack files, so no content can be restored
+    for (id, size) in be.repository().list_with_size(FileType::Pack)? {
+        be.repository().write_bytes(
+            FileType::Pack,
+            &id,
+            false,
+            vec![0; usize::try_from(size)?].into(),
+        )?;
+    }
+
+    let restore_dir = tempdir()?;
+    let dest = LocalDestination::new(
+        restore_dir
+            .path()
+            .to_str()
+            .expect("restore path is valid utf-8"),
+        true,
+        !node.is_dir(),
+    )?;
+    let non_empty_files = nodes
+        .iter()
+        .filter(|(_, node)| node.is_file() && node.meta.size > 0)
+        .count();
+
+    let restore_opts = RestoreOptions::default();
+    let plan = repo.prepare_restore(&restore_opts, ls(), &dest, false)?;
+    assert!(repo.restore(plan, &restore_opts, ls(), &dest).is_err());
+
+    for policy in ["skip", "retry:1"] {
+        let restore_opts =
+            RestoreOptions::default().on_error(policy.parse::<RestoreErrorPolicy>()?);
+        let plan = repo.prepare_restore(&restore_opts, ls(), &dest, false)?;
+        let report = repo.restore(plan, &restore_opts, ls(), &dest)?;
+        assert_eq!(report.failed_files.len(), non_empty_files);
+        assert!(report.verification.is_none());
+    }
+
+    // verifying detects the files which could not be restored
+    let restore_opts = RestoreOptions::default()
+        .on_error(RestoreErrorPolicy::Skip)
+        .verify_after(true);
+    let plan = repo.prepare_restore(&restore_opts, ls(), &dest, false)?;
+    let report = repo.restore(plan, &restore_opts, ls(), &dest)?;
+    let verification = report.verification.expect("verification should be done");
+    assert!(!verification.is_ok());
+    assert_eq!(verification.mismatched_files.len(), non_empty_files);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_restore_verify_after(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let _snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest", |_| true)?;
+    let ls = || repo.ls(&node, &LsOptions::default());
+
+    let restore_dir = tempdir()?;
+    let dest = LocalDestination::new(
+        restore_dir
+            .path()
+            .to_str()
+            .expect("restore path is valid utf-8"),
+        true,
+        !node.is_dir(),
+    )?;
+
+    let restore_opts = RestoreOptions::default().verify_after(true);
+    let plan = repo.prepare_restore(&restore_opts, ls()?, &dest, false)?;
+    let restore_size = plan.restore_size;
+    let report = repo.restore(plan, &restore_opts, ls()?, &dest)?;
+    assert!(report.failed_files.is_empty());
+    let verification = report.verification.expect("verification should be done");
+    assert!(verification.is_ok());
+    assert!(verification.files > 0);
+    assert_eq!(verification.bytes, restore_size);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_restore_verify_existing_parallel(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let _snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest", |_| true)?;
+    let ls = || repo.ls(&node, &LsOptions::default());
+
+    let restore_dir = tempdir()?;
+    let dest = LocalDestination::new(
+        restore_dir
+            .path()
+            .to_str()
+            .expect("restore path is valid utf-8"),
+        true,
+        !node.is_dir(),
+    )?;
+    let plan = repo.prepare_restore(&RestoreOptions::default(), ls()?, &dest, false)?;
+    let restore_size = plan.restore_size;
+    let _ = repo.restore(plan, &RestoreOptions::default(), ls()?, &dest)?;
+
+    // verifying all existing files must give the same result, regardless of the number of threads
+    let sequential_opts = RestoreOptions::default().verify_existing(true);
+    let sequential = repo.prepare_restore(&sequential_opts, ls()?, &dest, true)?;
+    let parallel_opts = sequential_opts.verify_threads(4);
+    let parallel = repo.prepare_restore(&parallel_opts, ls()?, &dest, true)?;
+
+    for plan in [&sequential, &parallel] {
+        assert_eq!(plan.restore_size, 0);
+        assert_eq!(plan.matched_size, restore_size);
+        assert_eq!(plan.stats.files.restore, 0);
+        assert_eq!(plan.stats.files.modify, 0);
+    }
+    assert!(parallel.stats.files.verified > 0);
+    assert_eq!(
+        parallel.stats.files.verified,
+        sequential.stats.files.verified
+    );
+    assert_eq!(
+        parallel.stats.files.unchanged,
+        sequential.stats.files.unchanged
+    );
+    assert_eq!(parallel.diff.entries, sequential.diff.entries);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_restore_manifest(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let _snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest", |_| true)?;
+    let ls = || repo.ls(&node, &LsOptions::default());
+
+    let restore_dir = tempdir()?;
+    let dest = LocalDestination::new(
+        restore_dir
+            .path()
+            .to_str()
+            .expect("restore path is valid utf-8"),
+        true,
+        !node.is_dir(),
+    )?;
+
+    let restore_opts = RestoreOptions::default().manifest(true);
+    let restore = || -> Result<_> {
+        let plan = repo.prepare_restore(&restore_opts, ls()?, &dest, false)?;
+        Ok(repo.restore(plan, &restore_opts, ls()?, &dest)?.manifest)
+    };
+
+    // restore into an empty dir: all files are created
+    let manifest = restore()?;
+    assert_eq!(manifest.len(), ls()?.count());
+    assert!(
+        manifest
+            .iter()
+            .filter(|e| !e.is_dir)
+            .all(|e| e.action == RestoreAction::Create)
+    );
+    let hashes: Vec<_> = manifest
+        .iter()
+        .filter_map(|e| e.content_hash.map(|hash| (e.path.clone(), hash)))
+        .collect();
+    assert!(!hashes.is_empty());
+
+    // restore again: all files are unchanged and have the same contents
+    let manifest = restore()?;
+    assert!(
+        manifest
+            .iter()
+            .filter(|e| e.content_hash.is_some())
+            .all(|e| e.action == RestoreAction::Unchanged)
+    );
+    let hashes2: Vec<_> = manifest
+        .iter()
+        .filter_map(|e| e.content_hash.map(|hash| (e.path.clone(), hash)))
+        .collect();
+    assert_eq!(hashes, hashes2);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_verify_restore(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::Masterkey(MasterKey::new()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let _snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest", |_| true)?;
+    let nodes = repo
+        .ls(&node, &LsOptions::default())?
+        .collect::<RusticResult<Vec<_>>>()?;
+    let ls = || nodes.iter().cloned().map(Ok);
+    let files = nodes.iter().filter(|(_, node)| node.is_file()).count();
+    let non_empty_files = nodes
+        .iter()
+        .filter(|(_, node)| node.is_file() && node.meta.size > 0)
+        .count();
+
+    let report = repo.verify_restore(ls(), RestoreErrorPolicy::Abort)?;
+    assert!(report.is_ok());
+    assert_eq!(report.files, u64::try_from(files)?);
+    assert!(report.blobs > 0);
+    assert!(report.pack_reads > 0);
+    assert!(report.read_bytes > 0);
+    let file_size: u64 = nodes
+        .iter()
+        .filter(|(_, node)| node.is_file())
+        .map(|(_, node)| node.meta.size)
+        .sum();
+    assert_eq!(report.bytes, file_size);
+
+    // corrupt all pack files, so no content can be verified
+    for (id, size) in be.repository().list_with_size(FileType::Pack)? {
+        be.repository().write_bytes(
+            FileType::Pack,
+            &id,
+            false,
+            vec![0; usize::try_from(size)?].into(),
+        )?;
+    }
+
+    assert!(
+        repo.verify_restore(ls(), RestoreErrorPolicy::Abort)
+            .is_err()
+    );
+    let report = repo.verify_restore(ls(), RestoreErrorPolicy::Skip)?;
+    assert!(!report.is_ok());
+    assert_eq!(report.failed_files.len(), non_empty_files);
+    assert_eq!(report.blobs, 0);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_restore_rate_limit(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+
+    // small files, each saved in its own blob
+    let source = tempdir()?;
+    for i in 0..10_u8 {
+        fs::write(source.path().join(format!("file{i}")), vec![i; 100_000])?;
+    }
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let paths = PathList::from_iter(Some(source.path().to_path_buf()));
+    let _snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_path("latest", |_| true)?;
+    let ls = repo.ls(&node, &LsOptions::default())?;
+
+    let restore_dir = tempdir()?;
+    let dest = LocalDestination::new(
+        restore_dir
+            .path()
+            .to_str()
+            .expect("restore path is valid utf-8"),
+        true,
+        !node.is_dir(),
+    )?;
+    let restore_opts = RestoreOptions::default()
+        .max_download_rate(ByteSize::mb(100))
+        .max_write_rate(ByteSize::kb(500));
+    let plan = repo.prepare_restore(&restore_opts, ls.clone(), &dest, false)?;
+    assert_eq!(plan.restore_size, 1_000_000);
+
+    let start = Instant::now();
+    repo.restore(plan, &restore_opts, ls, &dest)?;
+    // writing 1 MB at 500 kB/s takes at least 1.8s, as only the first write is not delayed
+    assert!(start.elapsed() >= Duration::from_millis(1800));
+
+    for i in 0..10_u8 {
+        let restored = restore_dir.path().join(format!("test/file{i}"));
+        assert_eq!(fs::read(restored)?, vec![i; 100_000]);
+    }
 
     Ok(())
 }
@@ -1,4 +1,9 @@
-use std::{collections::BTreeMap, ffi::OsStr, path::PathBuf, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::Result;
 use insta::Settings;
@@ -7,8 +12,8 @@ use pretty_assertions::assert_eq;
 use rstest::rstest;
 
 use rustic_core::{
-    BackupOptions, Excludes, LsOptions, NodeModification, RewriteOptions, RewriteTreesOptions,
-    RusticResult, StringList,
+    BackupOptions, Excludes, LsOptions, MemorySource, NodeModification, PathRemap, RewriteOptions,
+    RewriteTreesOptions, RusticResult, StringList,
     repofile::{Metadata, Node, SnapshotFile, SnapshotModification},
 };
 
@@ -114,3 +119,115 @@ fn test_rewrite(
 
     Ok(())
 }
+
+#[rstest]
+fn test_rewrite_remove_paths(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let src = MemorySource::new()
+        .add_file("dump/keep", "keep", Metadata::default())
+        .add_file("dump/.aws/credentials", "secret", Metadata::default())
+        .add_file("dump/sub/secret.key", "secret key", Metadata::default());
+    let snapshot = repo.archive(
+        &BackupOptions::default(),
+        &src,
+        SnapshotFile::default(),
+        &[PathBuf::from("dump")],
+    )?;
+
+    let repo = repo.to_indexed()?;
+    let tree_opts =
+        RewriteTreesOptions::default().remove(vec!["/dump/.aws".to_string(), "*.key".to_string()]);
+    let rewritten = repo.rewrite_snapshots_and_trees(
+        vec![snapshot.clone()],
+        &RewriteOptions::default(),
+        &tree_opts,
+    )?;
+    assert_eq!(rewritten.len(), 1);
+    assert_eq!(rewritten[0].original, Some(snapshot.id));
+    assert_eq!(
+        rewritten[0].summary.as_ref().unwrap().total_files_processed,
+        1
+    );
+
+    let repo = repo.to_indexed_ids()?;
+    let new_snap = repo
+        .get_all_snapshots()?
+        .into_iter()
+        .find(|sn| sn.id != snapshot.id)
+        .expect("rewritten snapshot should be saved");
+    assert_eq!(new_snap.original, Some(snapshot.id));
+    assert!(new_snap.tags.contains("rewrite"));
+    for path in ["dump/.aws/credentials", "dump/.aws", "dump/sub/secret.key"] {
+        assert!(repo.node_from_path(new_snap.tree, Path::new(path)).is_err());
+    }
+    _ = repo.node_from_path(new_snap.tree, Path::new("dump/keep"))?;
+    _ = repo.node_from_path(new_snap.tree, Path::new("dump/sub"))?;
+
+    // invalid glob patterns are rejected
+    let tree_opts = RewriteTreesOptions::default().remove(vec!["a{".to_string()]);
+    assert!(
+        repo.rewrite_snapshots_and_trees(vec![snapshot], &RewriteOptions::default(), &tree_opts)
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn test_rewrite_metadata_in_bulk(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let src = MemorySource::new().add_file("dump/a", "a", Metadata::default());
+    let backup = |hostname: &str| {
+        let snap = SnapshotFile {
+            hostname: hostname.to_string(),
+            ..Default::default()
+        };
+        repo.archive(
+            &BackupOptions::default(),
+            &src,
+            snap,
+            &[PathBuf::from("dump")],
+        )
+    };
+    let snap1 = backup("Host.example.com")?;
+    let snap2 = backup("other")?;
+
+    let modification = SnapshotModification::default()
+        .normalize_hostname(true)
+        .shift_time("-1h".parse::<jiff::Span>()?)
+        .remap_paths(vec!["dump=renamed".parse::<PathRemap>()?]);
+    let opts = RewriteOptions::default()
+        .modification(modification)
+        .forget(true);
+
+    let time1 = repo.get_snapshots(&[snap1.id.to_string()])?[0].time.clone();
+
+    // dry-run doesn't change anything
+    let rewritten = repo.rewrite_matching_snapshots(
+        |sn| sn.hostname.starts_with("Host"),
+        &opts.clone().dry_run(true),
+    )?;
+    assert_eq!(rewritten.len(), 1);
+    assert_eq!(repo.get_all_snapshots()?.len(), 2);
+
+    let rewritten = repo.rewrite_matching_snapshots(|sn| sn.hostname.starts_with("Host"), &opts)?;
+    assert_eq!(rewritten.len(), 1);
+
+    let snaps = repo.get_all_snapshots()?;
+    assert_eq!(snaps.len(), 2);
+    let new_snap = snaps
+        .iter()
+        .find(|sn| sn.original == Some(snap1.id) && sn.id != snap1.id)
+        .expect("rewritten snapshot should exist");
+    assert_eq!(new_snap.hostname, "host");
+    assert_eq!(new_snap.paths.iter().collect::<Vec<_>>(), ["renamed"]);
+    assert_eq!(new_snap.roots[0].path, "renamed");
+    assert_eq!(
+        new_snap.time.timestamp(),
+        time1.timestamp() - jiff::SignedDuration::from_hours(1)
+    );
+    // not matching snapshots are unchanged
+    assert!(snaps.iter().any(|sn| sn.id == snap2.id));
+
+    Ok(())
+}
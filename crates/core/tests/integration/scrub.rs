@@ -0,0 +1,51 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::Result;
+use bytesize::ByteSize;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+
+use rustic_core::{
+    BackupOptions,
+    repofile::{ScrubId, SnapshotFile},
+};
+
+use super::{RepoOpen, TestSource, set_up_repo, tar_gz_testdata};
+
+#[rstest]
+fn test_scrub(tar_gz_testdata: Result<TestSource>, set_up_repo: Result<RepoOpen>) -> Result<()> {
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    _ = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    // a small budget verifies exactly one pack
+    let first = repo.scrub(ByteSize::b(1))?;
+    assert!(first.check_results.is_ok().is_ok());
+    assert_eq!(first.packs_verified.len(), 1);
+    assert!(first.packs_unverified > 0);
+    assert_eq!(repo.list::<ScrubId>()?.count(), 1);
+
+    // a large budget verifies the remaining packs (and the already verified one)
+    let all = repo.scrub(ByteSize::gib(1))?;
+    assert!(all.check_results.is_ok().is_ok());
+    assert_eq!(all.packs_unverified, 0);
+    assert_eq!(
+        all.packs_verified.len(),
+        1 + usize::try_from(first.packs_unverified)?
+    );
+    assert_eq!(repo.list::<ScrubId>()?.count(), 1);
+
+    // now, the least-recently verified pack is taken next
+    let next = repo.scrub(ByteSize::b(1))?;
+    let after_next = repo.scrub(ByteSize::b(1))?;
+    assert_eq!(next.packs_verified.len(), 1);
+    assert_eq!(after_next.packs_verified.len(), 1);
+    assert_ne!(next.packs_verified, after_next.packs_verified);
+
+    // a zero budget verifies nothing
+    let nothing = repo.scrub(ByteSize::b(0))?;
+    assert!(nothing.packs_verified.is_empty());
+    assert_eq!(nothing.bytes_verified, 0);
+
+    Ok(())
+}
@@ -1,17 +1,26 @@
 #![allow(missing_docs)]
 
+use std::fs;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use crate::tar_gz_testdata;
 
 use super::set_up_repo;
 use anyhow::Result;
+use bytesize::ByteSize;
 use jiff::Timestamp;
 use jiff::tz::TimeZone;
 use rstest::{fixture, rstest};
-use rustic_core::repofile::SnapshotFile;
-use rustic_core::{BackupOptions, Grouped, IndexedIdsStatus, Repository, SnapshotGroupCriterion};
+use rustic_core::repofile::{MasterKey, SnapshotFile};
+use rustic_core::{
+    BackupOptions, ConfigOptions, Credentials, FileType, ForgetOptions, Grouped, IndexedIdsStatus,
+    KeepOptions, KeyOptions, PathList, Repository, RepositoryBackends, RepositoryOptions,
+    SnapshotErrorPolicy, SnapshotGroupCriterion, SnapshotSort, WriteBackend,
+};
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
+use tempfile::tempdir;
 
 #[fixture]
 #[once]
@@ -65,6 +74,42 @@ fn test_get_snapshot_group_no_ids(
     Ok(())
 }
 
+#[rstest]
+fn test_snapshot_group_stats(
+    repo_and_snapshots: &(Repository<IndexedIdsStatus>, Vec<SnapshotFile>),
+) -> Result<()> {
+    let (repo, snapshots) = repo_and_snapshots;
+
+    let stats = repo.snapshot_group_stats(SnapshotGroupCriterion::default())?;
+    assert_eq!(stats.len(), 1);
+    let stats = &stats[0];
+    assert_eq!(stats.count, 3);
+    assert_eq!(stats.count_without_summary, 0);
+    assert_eq!(stats.oldest, snapshots[0].time);
+    assert_eq!(stats.newest, snapshots[2].time);
+
+    let summaries: Vec<_> = snapshots
+        .iter()
+        .map(|sn| sn.summary.clone().expect("backup creates a summary"))
+        .collect();
+    assert_eq!(
+        stats.total_size,
+        summaries
+            .iter()
+            .map(|s| s.total_bytes_processed)
+            .sum::<u64>()
+    );
+    assert_eq!(stats.latest_size, summaries[2].total_bytes_processed);
+    assert_eq!(
+        stats.data_added,
+        summaries.iter().map(|s| s.data_added).sum::<u64>()
+    );
+    // the later backups of identical data add nothing
+    assert_eq!(stats.latest_data_added, 0);
+
+    Ok(())
+}
+
 #[rstest]
 fn test_get_snapshot_wrong_id(
     repo_and_snapshots: &(Repository<IndexedIdsStatus>, Vec<SnapshotFile>),
@@ -151,3 +196,244 @@ fn test_get_snapshots_from_strs_latest(
     assert_eq!(snap_latest[1], snapshots[1]);
     Ok(())
 }
+
+#[rstest]
+fn test_list_snapshots_paged(
+    repo_and_snapshots: &(Repository<IndexedIdsStatus>, Vec<SnapshotFile>),
+) -> Result<()> {
+    let (repo, snapshots) = repo_and_snapshots;
+
+    let page = repo.list_snapshots_paged(0, 2, SnapshotSort::NewestFirst)?;
+    assert_eq!(page.total, 3);
+    assert_eq!(page.next_offset, Some(2));
+    assert_eq!(
+        page.snapshots,
+        vec![snapshots[2].clone(), snapshots[1].clone()]
+    );
+
+    let page = repo.list_snapshots_paged(2, 2, SnapshotSort::NewestFirst)?;
+    assert_eq!(page.next_offset, None);
+    assert_eq!(page.snapshots, vec![snapshots[0].clone()]);
+
+    let page = repo.list_snapshots_paged(1, 1, SnapshotSort::OldestFirst)?;
+    assert_eq!(page.snapshots, vec![snapshots[1].clone()]);
+
+    let mut ids: Vec<_> = snapshots.iter().map(|sn| sn.id).collect();
+    ids.sort_unstable();
+    let page = repo.list_snapshots_paged(1, 5, SnapshotSort::Id)?;
+    assert_eq!(
+        page.snapshots.iter().map(|sn| sn.id).collect::<Vec<_>>(),
+        ids[1..]
+    );
+    assert!(page.unreadable.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_error_policy() -> Result<()> {
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let key = MasterKey::new();
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &Credentials::Masterkey(key.clone()),
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+
+    let source = tar_gz_testdata()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap1 = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+    let snap2 = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+    assert!(repo.unreadable_snapshots()?.is_empty());
+
+    // corrupt the latest snapshot
+    be.repository().write_bytes(
+        FileType::Snapshot,
+        &snap2.id,
+        false,
+        b"garbage".to_vec().into(),
+    )?;
+    assert_eq!(repo.unreadable_snapshots()?, vec![snap2.id]);
+
+    // by default, unreadable snapshots are skipped
+    assert_eq!(repo.get_snapshot_from_str("latest", |_| true)?.id, snap1.id);
+
+    let repo_opts = RepositoryOptions::default().snapshot_errors(SnapshotErrorPolicy::Report);
+    let repo = Repository::new(&repo_opts, &be)?.open(&Credentials::Masterkey(key.clone()))?;
+    assert_eq!(repo.get_snapshot_from_str("latest", |_| true)?.id, snap1.id);
+
+    let repo_opts = RepositoryOptions::default().snapshot_errors(SnapshotErrorPolicy::Fail);
+    let repo = Repository::new(&repo_opts, &be)?.open(&Credentials::Masterkey(key))?;
+    let err = repo
+        .get_snapshot_from_str("latest", |_| true)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains(&snap2.id.to_string()), "Err is: {err}");
+
+    Ok(())
+}
+
+#[rstest]
+fn test_get_forget_snapshots(
+    repo_and_snapshots: &(Repository<IndexedIdsStatus>, Vec<SnapshotFile>),
+) -> Result<()> {
+    let (repo, snapshots) = repo_and_snapshots;
+
+    let keep = KeepOptions::default().keep_last(1);
+    let groups = repo.get_forget_snapshots(&keep, SnapshotGroupCriterion::default(), |_| true)?;
+    assert_eq!(groups.0.len(), 1);
+    let kept: Vec<_> = groups.0[0]
+        .items
+        .iter()
+        .filter(|sn| sn.keep)
+        .map(|sn| sn.snapshot.id)
+        .collect();
+    assert_eq!(kept, vec![snapshots[2].id]);
+    assert_eq!(groups.into_forget_ids().len(), snapshots.len() - 1);
+
+    // invalid keep options
+    assert!(
+        repo.get_forget_snapshots(
+            &KeepOptions::default(),
+            SnapshotGroupCriterion::default(),
+            |_| true
+        )
+        .is_err()
+    );
+
+    Ok(())
+}
+
+#[rstest]
+fn test_forget_max_repo_size() -> Result<()> {
+    let repo = set_up_repo()?.to_indexed_ids()?;
+    let source = tempdir()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let mut snapshots = Vec::new();
+    for i in 0..3_u8 {
+        fs::write(source.path().join("file"), vec![i; 100_000])?;
+        let paths = PathList::from_iter(Some(source.path()));
+        snapshots.push(repo.backup(&opts, &paths, SnapshotFile::default())?);
+    }
+    let repo = repo.to_indexed_ids()?;
+
+    let forget_ids = |opts: &ForgetOptions| -> Result<Vec<_>> {
+        let mut ids = repo
+            .get_forget_snapshots_with_options(opts, SnapshotGroupCriterion::default(), |_| true)?
+            .into_forget_ids();
+        ids.sort_unstable();
+        Ok(ids)
+    };
+
+    // repository is small enough: nothing to forget
+    let opts = ForgetOptions::default().max_repo_size(ByteSize::gib(1));
+    assert!(forget_ids(&opts)?.is_empty());
+
+    // repository is too large: forget all but the latest snapshot
+    let opts = ForgetOptions::default().max_repo_size(ByteSize::b(1));
+    let mut expected = vec![snapshots[0].id, snapshots[1].id];
+    expected.sort_unstable();
+    assert_eq!(forget_ids(&opts)?, expected);
+
+    // keep options are applied first
+    let opts = opts.keep(KeepOptions::default().keep_last(1));
+    assert_eq!(forget_ids(&opts)?, expected);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_forget_max_repo_size_counts_all_snapshots() -> Result<()> {
+    let repo = set_up_repo()?.to_indexed_ids()?;
+    let source = tempdir()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+
+    // incompressible, deterministic file contents
+    let content = |seed: u64| -> Vec<u8> {
+        let mut state = seed;
+        (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state.to_le_bytes()[0]
+            })
+            .collect()
+    };
+    // the first two snapshots share their content, the last one has different content
+    let mut snapshots = Vec::new();
+    for seed in [1, 1, 2] {
+        fs::write(source.path().join("file"), content(seed))?;
+        let paths = PathList::from_iter(Some(source.path()));
+        snapshots.push(repo.backup(&opts, &paths, SnapshotFile::default())?);
+    }
+    let repo = repo.to_indexed_ids()?;
+
+    // only the first two snapshots match the filter, but the last one still counts towards the size
+    let latest = snapshots[2].id;
+    let opts = ForgetOptions::default().max_repo_size(ByteSize::b(150_000));
+    let ids = repo
+        .get_forget_snapshots_with_options(&opts, SnapshotGroupCriterion::default(), |sn| {
+            sn.id != latest
+        })?
+        .into_forget_ids();
+    assert_eq!(ids, vec![snapshots[0].id]);
+
+    Ok(())
+}
+
+#[rstest]
+fn test_backfill_summaries() -> Result<()> {
+    let repo = set_up_repo()?.to_indexed_ids()?;
+    let source = tar_gz_testdata()?;
+    let paths = &source.path_list();
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snap = repo.backup(&opts, paths, SnapshotFile::default())?;
+    let summary = snap.summary.clone().unwrap();
+
+    // simulate a snapshot written by a tool which doesn't save a summary
+    let mut stripped = snap.clone();
+    stripped.summary = None;
+    repo.save_snapshots(vec![stripped])?;
+    repo.delete_snapshots(&[snap.id])?;
+    let repo = repo.to_indexed_ids()?;
+    let stripped = repo.get_all_snapshots()?;
+    assert_eq!(stripped.len(), 1);
+    assert!(stripped[0].summary.is_none());
+
+    // dry-run doesn't modify the repository
+    let backfilled = repo.backfill_summaries(stripped.clone(), true)?;
+    assert_eq!(backfilled.len(), 1);
+    assert!(repo.get_all_snapshots()?[0].summary.is_none());
+
+    let backfilled = repo.backfill_summaries(stripped.clone(), false)?;
+    let snapshots = repo.get_all_snapshots()?;
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(backfilled[0].original, stripped[0].original);
+    assert_eq!(snapshots[0].original, stripped[0].original);
+    assert_ne!(snapshots[0].id, stripped[0].id);
+    let new_summary = snapshots[0].summary.clone().unwrap();
+    assert_eq!(
+        new_summary.total_files_processed,
+        summary.total_files_processed
+    );
+    assert_eq!(
+        new_summary.total_bytes_processed,
+        summary.total_bytes_processed
+    );
+    assert_eq!(
+        new_summary.total_dirs_processed,
+        summary.total_dirs_processed
+    );
+    assert_eq!(
+        new_summary.total_dirsize_processed,
+        summary.total_dirsize_processed
+    );
+
+    // snapshots with a summary are skipped
+    assert!(repo.backfill_summaries(snapshots, false)?.is_empty());
+
+    Ok(())
+}
@@ -6,7 +6,11 @@ use insta::Settings;
 use pretty_assertions::assert_eq;
 use rstest::rstest;
 
-use rustic_core::{BackupOptions, repofile::SnapshotFile, vfs::Vfs};
+use rustic_core::{
+    BackupOptions, PathList,
+    repofile::SnapshotFile,
+    vfs::{PrefetchOptions, Vfs},
+};
 
 use super::{
     RepoOpen, TestSource, assert_with_win, insta_node_redaction, set_up_repo, tar_gz_testdata,
@@ -51,6 +55,22 @@ fn test_vfs(
     assert_eq!(Bytes::new(), repo.read_file_at(&file, 25, 1)?); // offset beyond file end
     assert_eq!(Bytes::from("test"), repo.read_file_at(&file, 10, 4)?); // read partial content
 
+    // test reading sequentially with prefetching enabled
+    let opts = PrefetchOptions::default().min_sequential_reads(1_usize);
+    let file = repo.open_file(&node)?.with_prefetch(opts);
+    let mut data = Vec::new();
+    for offset in (0..21).step_by(5) {
+        data.extend_from_slice(&repo.read_file_at(&file, offset, 5)?);
+    }
+    assert_eq!(b"This is a test file.\n", data.as_slice());
+
+    // test opening files by path
+    let file = vfs.open_file(&repo, &path)?;
+    assert_eq!(Bytes::from("test"), file.read_at(&repo, 10, 4)?);
+    let dir: PathBuf = ["test", "0", "tests"].iter().collect();
+    assert!(vfs.open_file(&repo, &dir).is_err());
+    assert!(vfs.open_file(&repo, &dir.join("not_existing")).is_err());
+
     // test reading an empty file from the repository
     let path: PathBuf = ["test", "0", "tests", "empty-file"].iter().collect();
     let node = vfs.node_from_path(&repo, &path)?;
@@ -58,3 +78,54 @@ fn test_vfs(
     assert_eq!(Bytes::new(), repo.read_file_at(&file, 0, 0)?); // empty files
     Ok(())
 }
+
+#[rstest]
+fn test_vfs_prefetch_multiple_blobs(set_up_repo: Result<RepoOpen>) -> Result<()> {
+    // create a file which is larger than the maximum chunk size, so it consists of several blobs
+    let tmp = tempfile::tempdir()?;
+    let file_path = tmp.path().join("big");
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    let content: Vec<u8> = (0..10 * 1024 * 1024)
+        .map(|_| {
+            // xorshift to get incompressible, deterministic content
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state.to_le_bytes()[0]
+        })
+        .collect();
+    std::fs::write(&file_path, &content)?;
+
+    let repo = set_up_repo?.to_indexed_ids()?;
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test/big")?);
+    let paths = PathList::from_iter(Some(file_path));
+    let snapshot = repo.backup(&opts, &paths, SnapshotFile::default())?;
+
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_and_path(&snapshot, "test/big")?;
+    assert!(
+        node.content
+            .as_ref()
+            .is_some_and(|content| content.len() > 1)
+    );
+
+    // read sequentially in small chunks, so blobs are read ahead while the previous ones are read
+    let opts = PrefetchOptions::default()
+        .max_blobs(2_usize)
+        .min_sequential_reads(1_usize);
+    let file = repo.open_file(&node)?.with_prefetch(opts);
+    let mut data = Vec::with_capacity(content.len());
+    for offset in (0..content.len()).step_by(100_000) {
+        data.extend_from_slice(&repo.read_file_at(&file, offset, 100_000)?);
+    }
+    assert_eq!(content, data);
+
+    // random access still returns the right content
+    let offset = 3 * 1024 * 1024 + 17;
+    assert_eq!(
+        &content[offset..offset + 1000],
+        &repo.read_file_at(&file, offset, 1000)?[..]
+    );
+    assert_eq!(&content[..10], &repo.read_file_at(&file, 0, 10)?[..]);
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::{ops::ControlFlow, path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+
+use rustic_core::{
+    BackupOptions, ConfigOptions, Credentials, KeyOptions, Repository, RepositoryBackends,
+    RepositoryOptions, RepositoryWatcher,
+    repofile::{MasterKey, SnapshotFile},
+};
+use rustic_testing::backend::in_memory_backend::InMemoryBackend;
+
+use super::{TestSource, tar_gz_testdata};
+
+#[rstest]
+fn test_watch_for_changes(tar_gz_testdata: Result<TestSource>) -> Result<()> {
+    let source = tar_gz_testdata?;
+    let be = RepositoryBackends::new(Arc::new(InMemoryBackend::new()), None);
+    let credentials = Credentials::Masterkey(MasterKey::new());
+    let repo = Repository::new(&RepositoryOptions::default(), &be)?
+        .init(
+            &credentials,
+            &KeyOptions::default(),
+            &ConfigOptions::default(),
+        )?
+        .to_indexed_ids()?;
+
+    // a second repository instance, e.g. used by a server process
+    let mut server = Repository::new(&RepositoryOptions::default(), &be)?
+        .open(&credentials)?
+        .to_indexed()?;
+    let mut watcher = RepositoryWatcher::new(&server)?;
+    assert!(watcher.refresh(&mut server)?.is_empty());
+
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    let snapshot = repo.backup(&opts, &source.path_list(), SnapshotFile::default())?;
+
+    let changes = watcher.refresh(&mut server)?;
+    assert!(changes.index_changed);
+    assert_eq!(changes.added_snapshots, vec![snapshot.id]);
+    assert!(changes.removed_snapshots.is_empty());
+    // the re-read index contains the trees of the new snapshot
+    let node = server.node_from_snapshot_path("latest", |_| true)?;
+    assert!(node.is_dir());
+
+    repo.delete_snapshots(&[snapshot.id])?;
+    let mut removed = Vec::new();
+    watcher.watch(&mut server, Duration::ZERO, |_, changes| {
+        removed.clone_from(&changes.removed_snapshots);
+        ControlFlow::Break(())
+    })?;
+    assert_eq!(removed, vec![snapshot.id]);
+    assert!(watcher.refresh(&mut server)?.is_empty());
+
+    Ok(())
+}
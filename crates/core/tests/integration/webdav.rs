@@ -0,0 +1,87 @@
+use std::{io::SeekFrom, path::PathBuf, str::FromStr};
+
+use anyhow::Result;
+use bytes::Bytes;
+use dav_server::{
+    davpath::DavPath,
+    fs::{DavFileSystem, FsError, OpenOptions, ReadDirMeta},
+};
+use futures::{StreamExt, TryStreamExt};
+use pretty_assertions::assert_eq;
+use rstest::rstest;
+
+use rustic_core::{
+    BackupOptions,
+    repofile::SnapshotFile,
+    vfs::{FilePolicy, Vfs, WebDavFs},
+};
+
+use super::{RepoOpen, TestSource, set_up_repo, tar_gz_testdata};
+
+#[rstest]
+#[tokio::test]
+async fn test_webdav(
+    tar_gz_testdata: Result<TestSource>,
+    set_up_repo: Result<RepoOpen>,
+) -> Result<()> {
+    // Fixtures
+    let (source, repo) = (tar_gz_testdata?, set_up_repo?.to_indexed_ids()?);
+    let paths = &source.path_list();
+
+    // we use as_path to not depend on the actual tempdir
+    let opts = BackupOptions::default().as_path(PathBuf::from_str("test")?);
+    // backup test-data
+    let snapshot = repo.backup(&opts, paths, SnapshotFile::default())?;
+
+    // create WebDavFs
+    let repo = repo.to_indexed()?;
+    let node = repo.node_from_snapshot_and_path(&snapshot, "")?;
+    let vfs = Vfs::from_dir_node(&node);
+    let webdav = WebDavFs::new(repo, vfs, FilePolicy::Read);
+
+    // test reading a directory
+    let dir = DavPath::new("/test/0/tests")?;
+    let names: Vec<_> = webdav
+        .read_dir(&dir, ReadDirMeta::None)
+        .await?
+        .map(|entry| entry.map(|entry| entry.name()))
+        .try_collect()
+        .await?;
+    assert!(names.contains(&b"testfile".to_vec()));
+    assert!(names.contains(&b"empty-file".to_vec()));
+
+    // test reading a file after seeking
+    let path = DavPath::new("/test/0/tests/testfile")?;
+    let read = OpenOptions {
+        read: true,
+        ..OpenOptions::default()
+    };
+    let mut file = webdav.open(&path, read.clone()).await?;
+    assert_eq!(file.metadata().await?.len(), 21);
+    assert_eq!(file.seek(SeekFrom::Start(10)).await?, 10);
+    assert_eq!(Bytes::from("test"), file.read_bytes(4).await?);
+    assert_eq!(file.seek(SeekFrom::Current(-4)).await?, 10);
+    assert_eq!(Bytes::from("test file.\n"), file.read_bytes(4096).await?);
+    assert_eq!(Bytes::new(), file.read_bytes(1).await?); // read at file end
+    assert!(file.write_bytes(Bytes::from("test")).await.is_err());
+
+    // test that writing and opening directories or non-existing files is rejected
+    let write = OpenOptions {
+        write: true,
+        ..OpenOptions::default()
+    };
+    assert!(matches!(
+        webdav.open(&path, write).await,
+        Err(FsError::Forbidden)
+    ));
+    assert!(matches!(
+        webdav.open(&dir, read.clone()).await,
+        Err(FsError::Forbidden)
+    ));
+    let not_existing = DavPath::new("/test/0/tests/not_existing")?;
+    assert!(matches!(
+        webdav.open(&not_existing, read).await,
+        Err(FsError::NotFound)
+    ));
+    Ok(())
+}
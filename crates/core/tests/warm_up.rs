@@ -4,7 +4,7 @@ use std::{
     fs::{self, File},
     io::Read,
     path::Path,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use anyhow::Result;
@@ -12,7 +12,10 @@ use rstest::rstest;
 use serde::{Deserialize, Serialize};
 use tempfile::tempdir;
 
-use rustic_core::{CommandInput, Id, RepositoryBackends, RepositoryOptions, repofile::PackId};
+use rustic_core::{
+    CommandInput, FileType, Id, ProgressBars, ReadBackend, RepositoryBackends, RepositoryOptions,
+    RusticResult, WarmUp, repofile::PackId,
+};
 use rustic_testing::backend::in_memory_backend::InMemoryBackend;
 
 // Test constants
@@ -375,6 +378,43 @@ sleep 0.2
     Ok(())
 }
 
+#[cfg(not(windows))]
+#[rstest]
+#[case(1, 10, 1)] // batch_size=1, num_packs=10, sequential
+#[case(1, 10, 4)] // batch_size=1, num_packs=10, 4 batches at a time
+#[case(3, 10, 2)] // batch_size=3, num_packs=10, 2 batches at a time
+#[case(5, 10, 8)] // more concurrency than batches
+fn test_warm_up_batch_concurrency(
+    #[case] batch_size: usize,
+    #[case] num_packs: usize,
+    #[case] concurrency: usize,
+) -> Result<()> {
+    let log_dir = tempdir()?;
+    let (_script_dir, command) = create_test_script(log_dir.path())?;
+    let command: CommandInput = format!("{} %ids", command.command()).parse()?;
+
+    let be = InMemoryBackend::new();
+    let be = RepositoryBackends::new(Arc::new(be), None);
+    let options = RepositoryOptions::default()
+        .warm_up_command(command)
+        .warm_up_batch(batch_size)
+        .warm_up_concurrency(concurrency);
+    let repo = rustic_core::Repository::new(&options, &be)?;
+    let pack_ids = create_test_ids(num_packs);
+
+    repo.warm_up(pack_ids.iter().copied())?;
+
+    let expected_calls = num_packs.div_ceil(batch_size);
+    let all_args = assert_call_count(
+        log_dir.path(),
+        expected_calls,
+        &format!("Command should be called {expected_calls} times with concurrency {concurrency}"),
+    )?;
+    verify_batch_distribution(&all_args, num_packs, batch_size);
+
+    Ok(())
+}
+
 #[test]
 fn test_warm_up_batch_default_value() {
     let options = RepositoryOptions::default();
@@ -714,3 +754,55 @@ fn test_warm_up_backend_path_mode() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_validation_status_command_requires_singular() -> Result<()> {
+    let be = InMemoryBackend::new();
+    let be = RepositoryBackends::new(Arc::new(be), None);
+
+    let command: CommandInput = "echo %ids".parse()?;
+    let options = RepositoryOptions::default().warm_up_status_command(command);
+    assert!(rustic_core::Repository::new(&options, &be).is_err());
+
+    let command: CommandInput = "echo %path".parse()?;
+    let options = RepositoryOptions::default().warm_up_status_command(command);
+    assert!(rustic_core::Repository::new(&options, &be).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_custom_warm_up() -> Result<()> {
+    /// A warm-up recording the requested ids
+    #[derive(Debug, Default)]
+    struct RecordingWarmUp(Mutex<Vec<Id>>);
+
+    impl WarmUp for RecordingWarmUp {
+        fn warm_up(
+            &self,
+            _be: &dyn ReadBackend,
+            _tpe: FileType,
+            ids: &[Id],
+            _pb: &dyn ProgressBars,
+        ) -> RusticResult<()> {
+            self.0.lock().unwrap().extend_from_slice(ids);
+            Ok(())
+        }
+    }
+
+    let be = InMemoryBackend::new();
+    let be = RepositoryBackends::new(Arc::new(be), None);
+    // the custom warm-up replaces the warm-up command
+    let options =
+        RepositoryOptions::default().warm_up_command("false %id".parse::<CommandInput>()?);
+    let warm_up = Arc::new(RecordingWarmUp::default());
+    let repo = rustic_core::Repository::new(&options, &be)?.with_warm_up(warm_up.clone());
+
+    let pack_ids = create_test_ids(3);
+    repo.warm_up(pack_ids.iter().copied())?;
+
+    let expected: Vec<Id> = pack_ids.iter().map(|id| **id).collect();
+    assert_eq!(*warm_up.0.lock().unwrap(), expected);
+
+    Ok(())
+}
@@ -7,6 +7,7 @@ pub mod in_memory_backend {
 
     use bytes::Bytes;
     use enum_map::EnumMap;
+    use jiff::Timestamp;
 
     use rustic_core::{
         ErrorKind, FileType, Id, ReadBackend, RusticError, RusticResult, WriteBackend,
@@ -18,16 +19,24 @@ pub mod in_memory_backend {
         map: RwLock<EnumMap<FileType, BTreeMap<Id, Bytes>>>,
         is_cold: bool,
         warm: RwLock<EnumMap<FileType, BTreeSet<Id>>>,
+        object_lock: bool,
+        locks: RwLock<EnumMap<FileType, BTreeMap<Id, Timestamp>>>,
+        modified: RwLock<EnumMap<FileType, BTreeMap<Id, Timestamp>>>,
     }
 
     impl Clone for InMemoryBackend {
         fn clone(&self) -> Self {
             let inner_map = self.map.read().unwrap();
             let inner_warm = self.warm.read().unwrap();
+            let inner_locks = self.locks.read().unwrap();
+            let inner_modified = self.modified.read().unwrap();
             Self {
                 map: RwLock::new(EnumMap::from_fn(|tpe| inner_map[tpe].clone())),
                 is_cold: self.is_cold,
                 warm: RwLock::new(EnumMap::from_fn(|tpe| inner_warm[tpe].clone())),
+                object_lock: self.object_lock,
+                locks: RwLock::new(EnumMap::from_fn(|tpe| inner_locks[tpe].clone())),
+                modified: RwLock::new(EnumMap::from_fn(|tpe| inner_modified[tpe].clone())),
             }
         }
     }
@@ -40,6 +49,9 @@ pub mod in_memory_backend {
                 map: RwLock::new(EnumMap::from_fn(|_| BTreeMap::new())),
                 is_cold: false,
                 warm: RwLock::new(EnumMap::from_fn(|_| BTreeSet::new())),
+                object_lock: false,
+                locks: RwLock::new(EnumMap::from_fn(|_| BTreeMap::new())),
+                modified: RwLock::new(EnumMap::from_fn(|_| BTreeMap::new())),
             }
         }
 
@@ -50,8 +62,25 @@ pub mod in_memory_backend {
                 map: RwLock::new(EnumMap::from_fn(|_| BTreeMap::new())),
                 is_cold: true,
                 warm: RwLock::new(EnumMap::from_fn(|_| BTreeSet::new())),
+                object_lock: false,
+                locks: RwLock::new(EnumMap::from_fn(|_| BTreeMap::new())),
+                modified: RwLock::new(EnumMap::from_fn(|_| BTreeMap::new())),
             }
         }
+
+        /// Create a new (empty) `InMemoryBackend` supporting object locks
+        #[must_use]
+        pub fn new_with_object_lock() -> Self {
+            Self {
+                object_lock: true,
+                ..Self::new()
+            }
+        }
+
+        /// Set the modification time of the given file, e.g. to simulate files written in the past
+        pub fn set_modified(&self, tpe: FileType, id: &Id, time: Timestamp) {
+            _ = self.modified.write().unwrap()[tpe].insert(*id, time);
+        }
     }
 
     impl Default for InMemoryBackend {
@@ -77,6 +106,10 @@ pub mod in_memory_backend {
                 .collect())
         }
 
+        fn modified(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+            Ok(self.modified.read().unwrap()[tpe].get(id).copied())
+        }
+
         fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
             if self.is_cold && !self.warm.read().unwrap()[tpe].contains(id) {
                 return Err(RusticError::new(
@@ -142,6 +175,10 @@ pub mod in_memory_backend {
             }
             Ok(())
         }
+
+        fn supports_file_type(&self, _tpe: FileType) -> bool {
+            true
+        }
     }
 
     impl WriteBackend for InMemoryBackend {
@@ -162,18 +199,57 @@ pub mod in_memory_backend {
                         .attach_context("id", id.to_string()),
                 );
             }
+            self.set_modified(tpe, id, Timestamp::now());
 
             Ok(())
         }
 
         fn remove(&self, tpe: FileType, id: &Id, _cacheable: bool) -> RusticResult<()> {
+            if let Some(until) = self.locks.read().unwrap()[tpe].get(id)
+                && *until > Timestamp::now()
+            {
+                return Err(RusticError::new(
+                    ErrorKind::Backend,
+                    "ID `{id}` is locked until `{until}`.",
+                )
+                .attach_context("id", id.to_string())
+                .attach_context("until", until.to_string()));
+            }
             if self.map.write().unwrap()[tpe].remove(id).is_none() {
                 return Err(
                     RusticError::new(ErrorKind::Backend, "ID `{id}` does not exist.")
                         .attach_context("id", id.to_string()),
                 );
             }
+            _ = self.modified.write().unwrap()[tpe].remove(id);
             Ok(())
         }
+
+        fn supports_object_lock(&self) -> bool {
+            self.object_lock
+        }
+
+        fn write_bytes_locked(
+            &self,
+            tpe: FileType,
+            id: &Id,
+            cacheable: bool,
+            buf: Bytes,
+            until: Timestamp,
+        ) -> RusticResult<()> {
+            if !self.object_lock {
+                return Err(RusticError::new(
+                    ErrorKind::Unsupported,
+                    "Object locks are not supported.",
+                ));
+            }
+            self.write_bytes(tpe, id, cacheable, buf)?;
+            _ = self.locks.write().unwrap()[tpe].insert(*id, until);
+            Ok(())
+        }
+
+        fn locked_until(&self, tpe: FileType, id: &Id) -> RusticResult<Option<Timestamp>> {
+            Ok(self.locks.read().unwrap()[tpe].get(id).copied())
+        }
     }
 }
@@ -0,0 +1,82 @@
+//! `backup_source` example: back up data which is not stored in a local filesystem
+use rustic_backend::BackendOptions;
+use rustic_core::{
+    repofile::{Metadata, NodeType},
+    BackupOptions, Credentials, ReadSource, ReadSourceEntry, Repository, RepositoryOptions,
+    RusticResult, SnapshotOptions,
+};
+use simplelog::{Config, LevelFilter, SimpleLogger};
+use std::{error::Error, io::Cursor, path::PathBuf, vec};
+
+/// A source which generates a "database dump" per table
+struct DumpSource {
+    tables: Vec<&'static str>,
+}
+
+impl DumpSource {
+    fn dump(table: &str) -> Vec<u8> {
+        (0..1000)
+            .map(|row| format!("INSERT INTO {table} VALUES ({row});\n"))
+            .collect::<String>()
+            .into_bytes()
+    }
+}
+
+impl ReadSource for DumpSource {
+    // anything implementing `Read + Send + 'static` can be used to read the contents
+    type Open = Cursor<Vec<u8>>;
+    type Iter = vec::IntoIter<RusticResult<ReadSourceEntry<Self::Open>>>;
+
+    fn size(&self) -> RusticResult<Option<u64>> {
+        // the size is only used to display the progress
+        Ok(None)
+    }
+
+    fn entries(&self) -> Self::Iter {
+        // entries must be sorted by path
+        let mut tables = self.tables.clone();
+        tables.sort_unstable();
+        tables
+            .into_iter()
+            .map(|table| {
+                let data = Self::dump(table);
+                let meta = Metadata {
+                    size: data.len() as u64,
+                    ..Default::default()
+                };
+                let path = PathBuf::from("dump").join(format!("{table}.sql"));
+                ReadSourceEntry::new(path, NodeType::File, meta, Some(Cursor::new(data)))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Display info logs
+    let _ = SimpleLogger::init(LevelFilter::Info, Config::default());
+
+    // Initialize Backends
+    let backends = BackendOptions::default()
+        .repository("/tmp/repo")
+        .to_backends()?;
+
+    // Open repository
+    let repo_opts = RepositoryOptions::default();
+    let credentials = Credentials::password("test");
+    let repo = Repository::new(&repo_opts, &backends)?
+        .open(&credentials)?
+        .to_indexed_ids()?;
+
+    let backup_opts = BackupOptions::default();
+    let source = DumpSource {
+        tables: vec!["users", "orders"],
+    };
+    let snap = SnapshotOptions::default().to_snapshot()?;
+
+    // Create snapshot
+    let snap = repo.archive(&backup_opts, &source, snap, &[PathBuf::from("dump")])?;
+
+    println!("successfully created snapshot:\n{snap:#?}");
+    Ok(())
+}